@@ -189,7 +189,7 @@ where
 }
 
 /// Information about a mounted filesystem.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Mount {
     /// Name of the mounted device.
     pub name: String,
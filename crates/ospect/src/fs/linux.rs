@@ -47,10 +47,492 @@ pub fn flags<P>(path: P) -> std::io::Result<u32> where
     if code == 0 {
         Ok(flags as u32)
     } else {
-        Err(std::io::Error::from_raw_os_error(code))
+        Err(std::io::Error::last_os_error())
     }
 }
 
+/// Returns the capacity (in bytes) of the block device opened as the given file.
+///
+/// Block devices (like `/dev/sda`) report zero as their size in the metadata,
+/// so the capacity has to be queried using the `BLKGETSIZE64` ioctl instead.
+///
+/// # Errors
+///
+/// This function will fail if the file is not a block device. Note that opening
+/// a block device usually requires elevated privileges.
+///
+/// # Examples
+///
+/// ```no_run
+/// let file = std::fs::File::open("/dev/sda").unwrap();
+///
+/// let size = ospect::fs::linux::block_device_size(&file).unwrap();
+/// println!("capacity: {size} bytes");
+/// ```
+pub fn block_device_size(file: &std::fs::File) -> std::io::Result<u64> {
+    let mut size = 0;
+
+    // SAFETY: We pass a raw file descriptor that is valid for the lifetime of
+    // the function and a pointer to a 64-bit integer which is what the ioctl
+    // expects.
+    let code = unsafe {
+        use std::os::unix::io::AsRawFd as _;
+        ioctls::blkgetsize64(file.as_raw_fd(), &mut size)
+    };
+
+    if code < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(size)
+}
+
+/// Request code of the `FS_IOC_FIEMAP` ioctl (`_IOWR('f', 11, struct fiemap)`).
+///
+/// See the [kernel documentation] for more details about the ioctl.
+///
+/// [kernel documentation]: https://www.kernel.org/doc/html/latest/filesystems/fiemap.html
+const FS_IOC_FIEMAP: libc::c_ulong = 0xC020660B;
+
+/// Flag of `FS_IOC_FIEMAP` requests to sync the file before mapping it.
+const FIEMAP_FLAG_SYNC: u32 = 0x00000001;
+
+/// Number of extents requested from the kernel in a single `FS_IOC_FIEMAP` call.
+const FIEMAP_BATCH_LEN: usize = 64;
+
+/// Flag of the last extent of a file.
+const FIEMAP_EXTENT_LAST: u32 = 0x00000001;
+/// Flag of extents whose location is not known (yet).
+const FIEMAP_EXTENT_UNKNOWN: u32 = 0x00000002;
+/// Flag of extents that have delayed allocation (implies unknown location).
+const FIEMAP_EXTENT_DELALLOC: u32 = 0x00000004;
+/// Flag of extents whose data is encoded (e.g. compressed or encrypted).
+const FIEMAP_EXTENT_ENCODED: u32 = 0x00000008;
+/// Flag of extents whose data is stored along with the metadata.
+const FIEMAP_EXTENT_DATA_INLINE: u32 = 0x00000200;
+/// Flag of extents that are allocated but not written (read back as zeros).
+const FIEMAP_EXTENT_UNWRITTEN: u32 = 0x00000800;
+/// Flag of extents that are shared with other files (e.g. reflinks).
+const FIEMAP_EXTENT_SHARED: u32 = 0x00002000;
+
+/// Header of the `FS_IOC_FIEMAP` request (`struct fiemap`).
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+}
+
+/// Extent reported by the `FS_IOC_FIEMAP` ioctl (`struct fiemap_extent`).
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+/// `FS_IOC_FIEMAP` request with space for a batch of extents.
+#[repr(C)]
+struct FiemapRequest {
+    header: Fiemap,
+    extents: [FiemapExtent; FIEMAP_BATCH_LEN],
+}
+
+/// Contiguous range of a file mapped to a range on the underlying device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extent {
+    /// Offset (in bytes) of the extent within the file.
+    pub logical_offset: u64,
+    /// Offset (in bytes) of the extent on the underlying device.
+    ///
+    /// This is meaningless if the location of the extent is not known.
+    pub physical_offset: u64,
+    /// Length (in bytes) of the extent.
+    pub length: u64,
+    /// Mask of `FIEMAP_EXTENT_*` flags of the extent.
+    pub flags: u32,
+}
+
+impl Extent {
+
+    /// Returns whether this is the last extent of the file.
+    pub fn is_last(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_LAST != 0
+    }
+
+    /// Returns whether the location of the extent on the device is not known.
+    pub fn is_unknown(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_UNKNOWN != 0
+    }
+
+    /// Returns whether the extent is not allocated on the device yet.
+    pub fn is_delalloc(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_DELALLOC != 0
+    }
+
+    /// Returns whether the data of the extent is encoded on the device.
+    ///
+    /// Reading the device directly does not yield the contents of the file for
+    /// such extents (e.g. because they are compressed).
+    pub fn is_encoded(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_ENCODED != 0
+    }
+
+    /// Returns whether the data of the extent is stored along with metadata.
+    pub fn is_inline(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_DATA_INLINE != 0
+    }
+
+    /// Returns whether the extent is allocated but has not been written to.
+    pub fn is_unwritten(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_UNWRITTEN != 0
+    }
+
+    /// Returns whether the extent is shared with other files.
+    pub fn is_shared(&self) -> bool {
+        self.flags & FIEMAP_EXTENT_SHARED != 0
+    }
+}
+
+/// Returns the block allocation map of the given file.
+///
+/// The map is obtained through the `FS_IOC_FIEMAP` ioctl and consists of the
+/// extents of the file in the order of their offsets within the file. Ranges of
+/// the file not covered by any extent are holes (i.e. they are not allocated).
+///
+/// If `sync` is set, dirty pages of the file are written back before it is
+/// mapped, so that recently written data (which would otherwise have delayed
+/// allocation) has its final location on the device.
+///
+/// # Errors
+///
+/// This function will fail with [`std::io::ErrorKind::Unsupported`] if the
+/// filesystem of the file does not support the ioctl.
+///
+/// # Examples
+///
+/// ```no_run
+/// let file = std::fs::File::open("/tmp/foo").unwrap();
+///
+/// for extent in ospect::fs::linux::extents(&file, false).unwrap() {
+///     println!("{}+{}", extent.logical_offset, extent.length);
+/// }
+/// ```
+pub fn extents(file: &std::fs::File, sync: bool) -> std::io::Result<Vec<Extent>> {
+    use std::os::unix::io::AsRawFd as _;
+
+    let mut extents = Vec::new();
+
+    let mut request = Box::new(FiemapRequest {
+        header: Fiemap::default(),
+        extents: [FiemapExtent::default(); FIEMAP_BATCH_LEN],
+    });
+
+    loop {
+        let start = match extents.last() {
+            Some(Extent { logical_offset, length, .. }) => logical_offset + length,
+            None => 0,
+        };
+
+        request.header = Fiemap {
+            fm_start: start,
+            fm_length: u64::MAX - start,
+            fm_flags: if sync { FIEMAP_FLAG_SYNC } else { 0 },
+            fm_mapped_extents: 0,
+            fm_extent_count: FIEMAP_BATCH_LEN as u32,
+            fm_reserved: 0,
+        };
+
+        // SAFETY: We pass a raw file descriptor that is valid for the lifetime
+        // of the function and a pointer to a `struct fiemap` followed by space
+        // for the number of extents specified in the header, which is what the
+        // ioctl expects.
+        let code = unsafe {
+            libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut *request)
+        };
+        if code < 0 {
+            let error = std::io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EOPNOTSUPP | libc::ENOTTY) => {
+                    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, error))
+                }
+                _ => Err(error),
+            };
+        }
+
+        let count = std::cmp::min(request.header.fm_mapped_extents as usize, FIEMAP_BATCH_LEN);
+        extents.extend(request.extents[..count].iter().map(|extent| Extent {
+            logical_offset: extent.fe_logical,
+            physical_offset: extent.fe_physical,
+            length: extent.fe_length,
+            flags: extent.fe_flags,
+        }));
+
+        // The kernel reports no extents past the end of the map and marks the
+        // last one. Empty extents (which should not happen) could make us loop
+        // forever, so we stop on them as well.
+        match extents.last() {
+            Some(extent) if !extent.is_last() && count > 0 && extent.length > 0 => continue,
+            _ => return Ok(extents),
+        }
+    }
+}
+
+/// Mask of inotify events we watch directories for.
+const INOTIFY_WATCH_MASK: u32 = libc::IN_CREATE | libc::IN_MODIFY | libc::IN_ATTRIB |
+    libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO |
+    libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_ONLYDIR;
+
+/// Size of the buffer events are read into.
+///
+/// The kernel never splits events between reads, so the buffer has to be big
+/// enough to hold at least one event with the longest possible name.
+const INOTIFY_BUF_LEN: usize = 64 * 1024;
+
+/// Watch of a directory for changes of its entries through [inotify][1].
+///
+/// [1]: https://man7.org/linux/man-pages/man7/inotify.7.html
+#[derive(Debug)]
+pub struct DirWatch {
+    /// File descriptor of the inotify instance.
+    fd: std::os::fd::OwnedFd,
+}
+
+/// Change of a directory entry reported by a [`DirWatch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEvent {
+    /// Mask of `IN_*` flags describing the event.
+    pub mask: u32,
+    /// Cookie associating the two halves of a rename (0 for other events).
+    pub cookie: u32,
+    /// Name of the entry the event is about (unless it is about the directory).
+    pub name: Option<OsString>,
+}
+
+impl DirEvent {
+
+    /// Returns whether the entry was created.
+    pub fn is_create(&self) -> bool {
+        self.mask & libc::IN_CREATE != 0
+    }
+
+    /// Returns whether the contents of the entry were modified.
+    pub fn is_modify(&self) -> bool {
+        self.mask & libc::IN_MODIFY != 0
+    }
+
+    /// Returns whether the metadata (e.g. permissions) of the entry changed.
+    pub fn is_attrib(&self) -> bool {
+        self.mask & libc::IN_ATTRIB != 0
+    }
+
+    /// Returns whether the entry was deleted.
+    pub fn is_delete(&self) -> bool {
+        self.mask & libc::IN_DELETE != 0
+    }
+
+    /// Returns whether the entry was renamed to something else.
+    pub fn is_moved_from(&self) -> bool {
+        self.mask & libc::IN_MOVED_FROM != 0
+    }
+
+    /// Returns whether something was renamed to the entry.
+    pub fn is_moved_to(&self) -> bool {
+        self.mask & libc::IN_MOVED_TO != 0
+    }
+
+    /// Returns whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.mask & libc::IN_ISDIR != 0
+    }
+
+    /// Returns whether the kernel event queue overflowed.
+    ///
+    /// An unknown number of events that happened before this one was lost.
+    pub fn is_overflow(&self) -> bool {
+        self.mask & libc::IN_Q_OVERFLOW != 0
+    }
+
+    /// Returns whether the watched directory itself is gone.
+    ///
+    /// This is the case if it was deleted, moved or the filesystem it lives on
+    /// was unmounted. No more events are reported afterwards.
+    pub fn is_watch_gone(&self) -> bool {
+        self.mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_IGNORED | libc::IN_UNMOUNT) != 0
+    }
+}
+
+impl DirWatch {
+
+    /// Starts watching the given directory for changes of its entries.
+    ///
+    /// Only the immediate entries of the directory are watched, changes deeper
+    /// in the tree are not reported.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the path is not a directory or
+    /// the watch cannot be established (e.g. because the per-user limit of
+    /// inotify instances or watches was reached).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tempdir = tempfile::tempdir().unwrap();
+    /// let watch = ospect::fs::linux::DirWatch::new(tempdir.path()).unwrap();
+    ///
+    /// std::fs::write(tempdir.path().join("foo"), b"").unwrap();
+    ///
+    /// let events = watch.read(std::time::Duration::from_secs(1)).unwrap();
+    /// assert!(events.iter().any(|event| event.is_create()));
+    /// ```
+    pub fn new<P>(path: P) -> std::io::Result<DirWatch>
+    where
+        P: AsRef<Path>,
+    {
+        use std::os::fd::FromRawFd as _;
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+
+        // SAFETY: The call has no preconditions, we verify the result below.
+        let fd = unsafe {
+            libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: We verified that the call succeeded, so the descriptor is
+        // valid and nothing else owns it.
+        let fd = unsafe {
+            std::os::fd::OwnedFd::from_raw_fd(fd)
+        };
+
+        use std::os::fd::AsRawFd as _;
+
+        // SAFETY: We pass a valid descriptor of an inotify instance and a valid
+        // null-terminated path, we verify the result below.
+        let wd = unsafe {
+            libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), INOTIFY_WATCH_MASK)
+        };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(DirWatch {
+            fd,
+        })
+    }
+
+    /// Waits for events for at most `timeout` and returns them.
+    ///
+    /// If no events happen within the timeout, an empty list is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if waiting for the events or reading
+    /// them fails.
+    pub fn read(&self, timeout: std::time::Duration) -> std::io::Result<Vec<DirEvent>> {
+        use std::os::fd::AsRawFd as _;
+
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = libc::c_int::try_from(timeout.as_millis())
+            .unwrap_or(libc::c_int::MAX);
+
+        // SAFETY: We pass a pointer to a single valid `pollfd` struct and that
+        // is what we specify as the number of structs, we verify the result
+        // below.
+        let code = unsafe {
+            libc::poll(&mut pollfd, 1, timeout)
+        };
+        if code < 0 {
+            let error = std::io::Error::last_os_error();
+            // Being interrupted by a signal is not a failure, we just report
+            // no events and the caller can wait again.
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(error);
+        }
+        if code == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut buf = vec![0u8; INOTIFY_BUF_LEN];
+
+        // SAFETY: We pass a valid descriptor and a buffer of the specified len,
+        // we verify the result below.
+        let len = unsafe {
+            libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len())
+        };
+        if len < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::WouldBlock {
+                return Ok(Vec::new());
+            }
+            return Err(error);
+        }
+
+        Ok(parse_dir_events(&buf[..len as usize]))
+    }
+}
+
+/// Parses inotify events from the given buffer.
+///
+/// Truncated events at the end of the buffer (which the kernel should never
+/// produce) are ignored.
+fn parse_dir_events(mut buf: &[u8]) -> Vec<DirEvent> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    const HEADER_LEN: usize = std::mem::size_of::<libc::inotify_event>();
+
+    let mut events = Vec::new();
+    while buf.len() >= HEADER_LEN {
+        // SAFETY: We verified that the buffer holds enough bytes for the event
+        // header and read it unaligned, as the buffer has no alignment.
+        let header = unsafe {
+            std::ptr::read_unaligned(buf.as_ptr().cast::<libc::inotify_event>())
+        };
+
+        let Some(name) = buf.get(HEADER_LEN..HEADER_LEN + header.len as usize) else {
+            break;
+        };
+        buf = &buf[HEADER_LEN + header.len as usize..];
+
+        // Names are padded with null bytes up to the alignment boundary and
+        // events about the watched directory itself have no name at all.
+        let name = match name.iter().position(|byte| *byte == 0) {
+            Some(0) => None,
+            Some(len) => Some(OsStr::from_bytes(&name[..len]).to_os_string()),
+            None if name.is_empty() => None,
+            None => Some(OsStr::from_bytes(name).to_os_string()),
+        };
+
+        events.push(DirEvent {
+            mask: header.mask,
+            cookie: header.cookie,
+            name,
+        });
+    }
+
+    events
+}
+
 /// Collects names of all extended attributes for the specified file.
 pub fn ext_attr_names<P>(path: P) -> std::io::Result<Vec<OsString>>
 where
@@ -183,6 +665,138 @@ where
     Ok(buf)
 }
 
+/// Extended file metadata obtained through the `statx` system call.
+///
+/// This is the metadata that is not available through the standard `stat`
+/// call (and thus through [`std::fs::Metadata`]).
+#[derive(Clone, Copy, Debug)]
+pub struct Statx {
+    /// Time at which the file was created (if supported by the filesystem).
+    pub birth_time: Option<std::time::SystemTime>,
+    /// Identifier of the mount on which the file lives (if known).
+    ///
+    /// This corresponds to the first field of `/proc/self/mountinfo` entries.
+    pub mount_id: Option<u64>,
+    /// Mask of `STATX_ATTR_*` attributes set on the file.
+    pub attributes: u64,
+    /// Mask of `STATX_ATTR_*` attributes supported by the filesystem.
+    pub attributes_mask: u64,
+}
+
+impl Statx {
+
+    /// Returns whether the file is compressed by the filesystem (if known).
+    pub fn is_compressed(&self) -> Option<bool> {
+        self.attribute(libc::STATX_ATTR_COMPRESSED)
+    }
+
+    /// Returns whether the file is encrypted by the filesystem (if known).
+    pub fn is_encrypted(&self) -> Option<bool> {
+        self.attribute(libc::STATX_ATTR_ENCRYPTED)
+    }
+
+    /// Returns whether the file is marked as immutable (if known).
+    pub fn is_immutable(&self) -> Option<bool> {
+        self.attribute(libc::STATX_ATTR_IMMUTABLE)
+    }
+
+    /// Returns whether the file can only be appended to (if known).
+    pub fn is_append_only(&self) -> Option<bool> {
+        self.attribute(libc::STATX_ATTR_APPEND)
+    }
+
+    /// Returns the value of the given attribute if it is supported.
+    fn attribute(&self, attribute: libc::c_int) -> Option<bool> {
+        let attribute = attribute as u64;
+
+        if self.attributes_mask & attribute == 0 {
+            return None;
+        }
+
+        Some(self.attributes & attribute != 0)
+    }
+}
+
+/// Collects extended metadata of the specified file using the `statx` call.
+///
+/// In case of a symlink this function returns the metadata of the link itself
+/// and not the file pointed by it.
+///
+/// # Errors
+///
+/// Apart from the usual errors (e.g. the file not existing), this function
+/// fails with `ENOSYS` on kernels older than 4.11 which do not support `statx`.
+/// Callers should fall back to the standard metadata in such cases.
+///
+/// # Examples
+///
+/// ```no_run
+/// let statx = ospect::fs::linux::statx("/tmp/foo").unwrap();
+/// if let Some(birth_time) = statx.birth_time {
+///     println!("created at: {birth_time:?}");
+/// }
+/// ```
+pub fn statx<P>(path: P) -> std::io::Result<Statx>
+where
+    P: AsRef<Path>,
+{
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let c_str_path = CString::new(path.as_ref().as_os_str().as_bytes())
+        // It is not possible to have a null byte in a Linux path.
+        .expect("path with a null character");
+
+    let mut buf = std::mem::MaybeUninit::<libc::statx>::uninit();
+
+    // SAFETY: The correctness of the path is guaranteed by conversion to the
+    // `CString` type above and the buffer is big enough to hold the `statx`
+    // struct. The rest is just a FFI call respecting the spec.
+    let code = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_str_path.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW | libc::AT_STATX_SYNC_AS_STAT,
+            libc::STATX_BASIC_STATS | libc::STATX_BTIME | libc::STATX_MNT_ID,
+            buf.as_mut_ptr(),
+        )
+    };
+    if code < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: The call succeeded, so the buffer has been initialized.
+    let buf = unsafe {
+        buf.assume_init()
+    };
+
+    let birth_time = if buf.stx_mask & libc::STATX_BTIME != 0 {
+        let secs = buf.stx_btime.tv_sec;
+        let nanos = buf.stx_btime.tv_nsec;
+
+        let duration = std::time::Duration::new(secs.unsigned_abs(), nanos);
+        if secs >= 0 {
+            std::time::UNIX_EPOCH.checked_add(duration)
+        } else {
+            std::time::UNIX_EPOCH.checked_sub(duration)
+        }
+    } else {
+        None
+    };
+
+    let mount_id = if buf.stx_mask & libc::STATX_MNT_ID != 0 {
+        Some(buf.stx_mnt_id)
+    } else {
+        None
+    };
+
+    Ok(Statx {
+        birth_time,
+        mount_id,
+        attributes: buf.stx_attributes,
+        attributes_mask: buf.stx_attributes_mask,
+    })
+}
+
 /// Returns an iterator over mounted filesystems information.
 pub fn mounts() -> std::io::Result<impl Iterator<Item = std::io::Result<Mount>>> {
     // We try to parse `/proc/mounts`, but if it does not exist we fallback to
@@ -198,6 +812,16 @@ pub fn mounts() -> std::io::Result<impl Iterator<Item = std::io::Result<Mount>>>
     Ok(Mounts::new(file))
 }
 
+/// Returns an iterator over filesystems mounted in the namespace of a process.
+///
+/// Processes in different mount namespaces (e.g. in containers) can see entirely
+/// different mounts, so this is the way to inspect mounts that are not visible
+/// to the current process.
+pub fn process_mounts(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<Mount>>> {
+    let file = std::fs::File::open(format!("/proc/{pid}/mounts"))?;
+    Ok(Mounts::new(file))
+}
+
 /// An iterator over mounted filesystems information.
 struct Mounts<R: std::io::Read> {
     /// An mtab-like file to parse for mount information.
@@ -298,6 +922,107 @@ pub(crate) mod tests {
         assert_eq!(flags & FS_NOATIME_FL as u32, FS_NOATIME_FL as u32);
     }
 
+    #[test]
+    fn statx_non_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        assert!(statx(tempdir.path().join("foo")).is_err());
+    }
+
+    #[test]
+    fn statx_file() {
+        let tempfile = tempfile::NamedTempFile::new().unwrap();
+
+        let statx = match statx(tempfile.path()) {
+            Ok(statx) => statx,
+            // The kernel is too old to support `statx`, nothing to test.
+            Err(error) if error.raw_os_error() == Some(libc::ENOSYS) => return,
+            Err(error) => panic!("unexpected error: {error}"),
+        };
+
+        // Mount identifiers are supported since Linux 5.8.
+        assert!(statx.mount_id.is_some());
+
+        // The standard library uses `statx` to obtain creation time as well, so
+        // if it is able to get it, we should be too (and get the same value).
+        let metadata = std::fs::symlink_metadata(tempfile.path()).unwrap();
+        assert_eq!(statx.birth_time, metadata.created().ok());
+
+        // A freshly created file has none of these attributes set (but whether
+        // we know that depends on the filesystem).
+        assert_ne!(statx.is_immutable(), Some(true));
+        assert_ne!(statx.is_append_only(), Some(true));
+        assert_ne!(statx.is_compressed(), Some(true));
+    }
+
+    #[test]
+    fn extents_empty() {
+        let tempfile = tempfile::tempfile().unwrap();
+
+        let extents = match extents(&tempfile, false) {
+            Ok(extents) => extents,
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => return,
+            Err(error) => panic!("unexpected error: {error}"),
+        };
+        assert!(extents.is_empty());
+    }
+
+    #[test]
+    fn extents_sync() {
+        use std::io::Write as _;
+
+        let mut tempfile = tempfile::tempfile().unwrap();
+        tempfile.write_all(&vec![0xf0; 1024 * 1024]).unwrap();
+
+        let extents = match extents(&tempfile, true) {
+            Ok(extents) => extents,
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => return,
+            Err(error) => panic!("unexpected error: {error}"),
+        };
+
+        assert!(!extents.is_empty());
+        assert!(extents.last().unwrap().is_last());
+        // The file has been synced, so all the extents have known locations.
+        assert!(extents.iter().all(|extent| !extent.is_delalloc()));
+
+        let len = extents.iter().map(|extent| extent.length).sum::<u64>();
+        assert!(len >= 1024 * 1024);
+    }
+
+    #[test]
+    fn block_device_size_regular_file() {
+        let tempfile = tempfile::tempfile().unwrap();
+
+        assert!(block_device_size(&tempfile).is_err());
+    }
+
+    #[test]
+    fn block_device_size_sysfs() {
+        // We check against all block devices that we are able to open, so the
+        // test does nothing if there are none or we do not have privileges.
+        let entries = match std::fs::read_dir("/sys/block") {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries {
+            let name = entry.unwrap().file_name();
+
+            let file = match File::open(Path::new("/dev").join(&name)) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+
+            // Sizes in sysfs are expressed in 512-byte sectors regardless of
+            // the actual sector size of the device.
+            let sysfs_size = std::fs::read_to_string({
+                Path::new("/sys/block").join(&name).join("size")
+            }).unwrap().trim().parse::<u64>().unwrap() * 512;
+
+            assert_eq!(block_device_size(&file).unwrap(), sysfs_size);
+        }
+    }
+
     #[test]
     fn ext_attr_names_none() {
         let tempfile = tempfile::NamedTempFile::new().unwrap();
@@ -392,6 +1117,16 @@ pub(crate) mod tests {
         };
     }
 
+    #[test]
+    fn process_mounts_self() {
+        let mounts = process_mounts(std::process::id())
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert!(mounts.iter().any(|mount| mount.path == std::path::Path::new("/")));
+    }
+
     #[test]
     fn mounts_empty_mtab() {
         const MTAB: &'static str = "\
@@ -435,4 +1170,106 @@ proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
 
         assert!(mounts.next().is_none());
     }
+
+    #[test]
+    fn dir_watch_events() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let watch = DirWatch::new(tempdir.path())
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foo")
+            .unwrap();
+        std::fs::remove_file(tempdir.path().join("foo"))
+            .unwrap();
+
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            let batch = watch.read(std::time::Duration::from_secs(5))
+                .unwrap();
+            assert!(!batch.is_empty());
+
+            events.extend(batch);
+        }
+
+        assert!(events[0].is_create());
+        assert_eq!(events[0].name.as_deref(), Some(OsStr::new("foo")));
+        assert!(events[1..].iter().any(DirEvent::is_modify));
+        assert!(events.last().unwrap().is_delete());
+    }
+
+    #[test]
+    fn dir_watch_timeout() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let watch = DirWatch::new(tempdir.path())
+            .unwrap();
+
+        let events = watch.read(std::time::Duration::from_millis(10))
+            .unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn dir_watch_not_dir() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        assert!(DirWatch::new(tempfile.path()).is_err());
+    }
+
+    #[test]
+    fn parse_dir_events_names() {
+        let mut buf = Vec::new();
+        push_raw_event(&mut buf, libc::IN_CREATE, 0, b"foo\0\0\0\0\0");
+        push_raw_event(&mut buf, libc::IN_MOVED_FROM | libc::IN_ISDIR, 42, b"barbaz\0\0");
+        push_raw_event(&mut buf, libc::IN_DELETE_SELF, 0, b"");
+
+        let events = parse_dir_events(&buf);
+        assert_eq!(events.len(), 3);
+
+        assert!(events[0].is_create());
+        assert_eq!(events[0].name.as_deref(), Some(OsStr::new("foo")));
+
+        assert!(events[1].is_moved_from());
+        assert!(events[1].is_dir());
+        assert_eq!(events[1].cookie, 42);
+        assert_eq!(events[1].name.as_deref(), Some(OsStr::new("barbaz")));
+
+        assert!(events[2].is_watch_gone());
+        assert_eq!(events[2].name, None);
+    }
+
+    #[test]
+    fn parse_dir_events_overflow() {
+        let mut buf = Vec::new();
+        push_raw_event(&mut buf, libc::IN_Q_OVERFLOW, 0, b"");
+
+        let events = parse_dir_events(&buf);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_overflow());
+    }
+
+    #[test]
+    fn parse_dir_events_truncated() {
+        let mut buf = Vec::new();
+        push_raw_event(&mut buf, libc::IN_CREATE, 0, b"foo\0");
+        push_raw_event(&mut buf, libc::IN_CREATE, 0, b"bar\0");
+        buf.truncate(buf.len() - 2);
+
+        let events = parse_dir_events(&buf);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name.as_deref(), Some(OsStr::new("foo")));
+    }
+
+    /// Appends an event in the format used by the kernel to the given buffer.
+    fn push_raw_event(buf: &mut Vec<u8>, mask: u32, cookie: u32, name: &[u8]) {
+        buf.extend_from_slice(&1i32.to_ne_bytes());
+        buf.extend_from_slice(&mask.to_ne_bytes());
+        buf.extend_from_slice(&cookie.to_ne_bytes());
+        buf.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+        buf.extend_from_slice(name);
+    }
 }
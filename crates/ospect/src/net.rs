@@ -122,6 +122,38 @@ pub fn interfaces() -> std::io::Result<impl Iterator<Item = Interface>> {
     self::sys::interfaces()
 }
 
+/// DNS resolver configuration of the system.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DnsConfig {
+    /// Addresses of the configured name servers (in the order of preference).
+    pub servers: Vec<std::net::IpAddr>,
+    /// Domains to search when resolving unqualified names.
+    pub search_domains: Vec<String>,
+}
+
+/// Collects DNS resolver configuration of the system.
+///
+/// Note that on systems where the resolver is not configured at all (e.g. the
+/// configuration file is missing), an empty configuration is returned rather
+/// than an error.
+///
+/// # Errors
+///
+/// This function will fail if the configuration exists but there was some
+/// issue when reading it (e.g. insufficient permissions).
+///
+/// # Examples
+///
+/// ```
+/// let config = ospect::net::dns_config().unwrap();
+/// for server in config.servers {
+///     println!("nameserver {}", server);
+/// }
+/// ```
+pub fn dns_config() -> std::io::Result<DnsConfig> {
+    self::sys::dns_config()
+}
+
 /// A list of possible states of the TCP connection.
 ///
 /// [1]: https://www.ietf.org/rfc/rfc793.txt
@@ -162,6 +162,18 @@ pub fn interfaces() -> std::io::Result<impl Iterator<Item = Interface>> {
     Ok(ifaces.into_iter())
 }
 
+/// Collects DNS resolver configuration of the system.
+///
+/// A system agnostic [`dns_config`] function is available in the parent module
+/// and should be the preferred choice in general.
+///
+/// The configuration is read from the `/etc/resolv.conf` file.
+///
+/// [`dns_config`]: super::dns_config
+pub fn dns_config() -> std::io::Result<DnsConfig> {
+    crate::net::unix::dns_config()
+}
+
 /// Returns an iterator over IPv4 TCP connections for the specified process.
 pub fn tcp_v4_connections(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<TcpConnectionV4>>> {
     self::conn::tcp_v4(pid)
@@ -202,6 +214,157 @@ pub fn all_udp_v6_connections() -> std::io::Result<impl Iterator<Item = std::io:
     crate::net::unix::all_udp_v6_connections()
 }
 
+/// Returns an iterator over raw IPv4 and IPv6 sockets for the specified process.
+pub fn raw_connections(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<RawConnection>>> {
+    let v4 = self::conn::raw_v4(pid)?;
+    let v6 = self::conn::raw_v6(pid)?;
+
+    Ok(v4.chain(v6))
+}
+
+/// Returns an iterator over Unix domain sockets for the specified process.
+pub fn unix_sockets(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<UnixSocket>>> {
+    self::conn::unix(pid)
+}
+
+/// Returns an iterator over raw IPv4 and IPv6 sockets of all processes.
+pub fn all_raw_connections() -> std::io::Result<impl Iterator<Item = std::io::Result<RawConnection>>> {
+    let pids = crate::proc::ids()?;
+    Ok(pids.flat_map(|pid| raw_connections(pid?)).flatten())
+}
+
+/// Returns an iterator over Unix domain sockets of all processes.
+pub fn all_unix_sockets() -> std::io::Result<impl Iterator<Item = std::io::Result<UnixSocket>>> {
+    let pids = crate::proc::ids()?;
+    Ok(pids.flat_map(|pid| unix_sockets(pid?)).flatten())
+}
+
+/// Information about a raw (either IPv4 or IPv6) socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RawConnection {
+    /// A local address of the socket.
+    local_addr: std::net::IpAddr,
+    /// A remote address of the socket (unspecified if it is not connected).
+    remote_addr: std::net::IpAddr,
+    /// A number of the IP protocol the socket was opened for.
+    protocol: u8,
+    /// An identifier of the process that owns the socket.
+    pid: u32,
+}
+
+impl RawConnection {
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> std::net::IpAddr {
+        self.local_addr
+    }
+
+    /// Returns the remote address of the socket.
+    ///
+    /// For sockets that are not connected, this is an unspecified address.
+    pub fn remote_addr(&self) -> std::net::IpAddr {
+        self.remote_addr
+    }
+
+    /// Returns the number of the IP protocol the socket was opened for.
+    ///
+    /// See the [IANA registry][1] for the list of known protocol numbers.
+    ///
+    /// [1]: https://www.iana.org/assignments/protocol-numbers/protocol-numbers.xhtml
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Returns the identifier of the process that owns the socket.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
+
+/// Type of a Unix domain socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnixSocketType {
+    /// A connection-oriented socket (`SOCK_STREAM`).
+    Stream,
+    /// A connectionless socket (`SOCK_DGRAM`).
+    Datagram,
+    /// A connection-oriented socket preserving message boundaries
+    /// (`SOCK_SEQPACKET`).
+    SeqPacket,
+}
+
+/// State of a Unix domain socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnixSocketState {
+    /// The socket is not connected.
+    Unconnected,
+    /// The socket is in the process of connecting.
+    Connecting,
+    /// The socket is connected.
+    Connected,
+    /// The socket is in the process of disconnecting.
+    Disconnecting,
+    /// The socket is listening for incoming connections.
+    Listening,
+}
+
+/// Address a Unix domain socket is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UnixSocketAddr {
+    /// A path in the filesystem.
+    Pathname(std::path::PathBuf),
+    /// A name in the abstract namespace (without the leading null byte).
+    ///
+    /// Note that the system reports null bytes within the name as `@`, so
+    /// they are indistinguishable from actual `@` characters.
+    Abstract(Vec<u8>),
+}
+
+/// Information about a Unix domain socket.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnixSocket {
+    /// An address the socket is bound to (if any).
+    addr: Option<UnixSocketAddr>,
+    /// A type of the socket.
+    socket_type: UnixSocketType,
+    /// A state of the socket.
+    state: UnixSocketState,
+    /// An inode number of the socket.
+    inode: u64,
+    /// An identifier of the process that owns the socket.
+    pid: u32,
+}
+
+impl UnixSocket {
+
+    /// Returns the address the socket is bound to.
+    ///
+    /// Unbound sockets (e.g. the client side of a connection) have no address.
+    pub fn addr(&self) -> Option<&UnixSocketAddr> {
+        self.addr.as_ref()
+    }
+
+    /// Returns the type of the socket.
+    pub fn socket_type(&self) -> UnixSocketType {
+        self.socket_type
+    }
+
+    /// Returns the state of the socket.
+    pub fn state(&self) -> UnixSocketState {
+        self.state
+    }
+
+    /// Returns the inode number of the socket.
+    pub fn inode(&self) -> u64 {
+        self.inode
+    }
+
+    /// Returns the identifier of the process that owns the socket.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -228,4 +391,24 @@ mod tests {
             loopback.mac_addr(), Some(&MacAddr::from([0, 0, 0, 0, 0, 0]))
         };
     }
+
+    #[test]
+    fn unix_sockets_listener() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("socket");
+
+        let _listener = std::os::unix::net::UnixListener::bind(&path)
+            .unwrap();
+
+        let socket = unix_sockets(std::process::id())
+            .unwrap()
+            .filter_map(Result::ok)
+            .find(|socket| socket.addr() == Some(&UnixSocketAddr::Pathname(path.clone())))
+            .unwrap();
+
+        assert_eq!(socket.socket_type(), UnixSocketType::Stream);
+        assert_eq!(socket.state(), UnixSocketState::Listening);
+        assert_eq!(socket.pid(), std::process::id());
+    }
 }
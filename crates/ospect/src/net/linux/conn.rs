@@ -4,6 +4,7 @@
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
 
 use crate::net::*;
+use crate::net::linux::{RawConnection, UnixSocket, UnixSocketAddr, UnixSocketState, UnixSocketType};
 
 /// Returns an iterator over IPv4 TCP connections for the specified process.
 pub fn tcp_v4(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<TcpConnectionV4>>> {
@@ -41,6 +42,30 @@ pub fn udp_v6(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<
     }.map(|conn| Ok(UdpConnectionV6::from_inner(conn?))))
 }
 
+/// Returns an iterator over raw IPv4 sockets for the specified process.
+pub fn raw_v4(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<RawConnection>>> {
+    let path = format!("/proc/{pid}/net/raw");
+    Ok(Connections::new(path, parse_raw_v4_connection)?.map(move |conn| {
+        Ok(RawConnection { pid, ..conn? })
+    }))
+}
+
+/// Returns an iterator over raw IPv6 sockets for the specified process.
+pub fn raw_v6(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<RawConnection>>> {
+    let path = format!("/proc/{pid}/net/raw6");
+    Ok(Connections::new(path, parse_raw_v6_connection)?.map(move |conn| {
+        Ok(RawConnection { pid, ..conn? })
+    }))
+}
+
+/// Returns an iterator over Unix domain sockets for the specified process.
+pub fn unix(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<UnixSocket>>> {
+    let path = format!("/proc/{pid}/net/unix");
+    Ok(Connections::new(path, parse_unix_socket)?.map(move |socket| {
+        Ok(UnixSocket { pid, ..socket? })
+    }))
+}
+
 // TODO(rust-lang/rust#63063): Simplify as an alias to `impl`.
 /// Iterator over UDP connections of a particular process.
 ///
@@ -254,6 +279,115 @@ where
     })
 }
 
+/// Parses a raw IPv4 socket information in the procfs format.
+fn parse_raw_v4_connection(string: &str) -> Result<RawConnection, ParseConnectionError> {
+    parse_raw_connection(string, parse_socket_addr_v4)
+}
+
+/// Parses a raw IPv6 socket information in the procfs format.
+fn parse_raw_v6_connection(string: &str) -> Result<RawConnection, ParseConnectionError> {
+    parse_raw_connection(string, parse_socket_addr_v6)
+}
+
+/// Parses a raw socket information in the procfs format.
+fn parse_raw_connection<A>(
+    string: &str,
+    parse_socket_addr: fn(&str) -> Result<A, ParseSocketAddrError>,
+) -> Result<RawConnection, ParseConnectionError>
+where
+    A: Into<std::net::SocketAddr>,
+{
+    // Raw sockets use the same format as TCP ones, except that the "port" of
+    // the local address is the number of the protocol the socket was opened
+    // for [1].
+    //
+    // [1]: https://github.com/torvalds/linux/blob/v6.6/net/ipv4/raw.c#L1026-L1042
+    let conn = parse_tcp_connection(string, parse_socket_addr)?;
+
+    let local_addr: std::net::SocketAddr = conn.local_addr.into();
+    let remote_addr: std::net::SocketAddr = conn.remote_addr.into();
+
+    let protocol = u8::try_from(local_addr.port())
+        .map_err(|_| ParseConnectionError::InvalidFormat)?;
+
+    Ok(RawConnection {
+        local_addr: local_addr.ip(),
+        remote_addr: remote_addr.ip(),
+        protocol,
+        pid: 0, // Set at the iterator level where PID is available.
+    })
+}
+
+/// Parses a Unix domain socket information in the procfs format.
+fn parse_unix_socket(string: &str) -> Result<UnixSocket, ParseConnectionError> {
+    // https://github.com/torvalds/linux/blob/v6.6/net/unix/af_unix.c#L3285-L3330
+    const __SO_ACCEPTCON: u32 = 1 << 16;
+
+    /// Splits the next space-separated (and possibly padded) field.
+    fn next_field(string: &str) -> Result<(&str, &str), ParseConnectionError> {
+        let string = string.trim_start_matches(' ');
+        if string.is_empty() {
+            return Err(ParseConnectionError::InvalidFormat);
+        }
+
+        Ok(match string.split_once(' ') {
+            Some((field, rest)) => (field, rest),
+            None => (string, ""),
+        })
+    }
+
+    // `Num` column (address of the kernel socket object) and `RefCount` and
+    // `Protocol` ones, we don't care about them but expect them to be there.
+    let (_, rest) = next_field(string)?;
+    let (_, rest) = next_field(rest)?;
+    let (_, rest) = next_field(rest)?;
+
+    let (flags_str, rest) = next_field(rest)?;
+    let flags = u32::from_str_radix(flags_str, 16)
+        .map_err(|_| ParseConnectionError::InvalidFormat)?;
+
+    let (type_str, rest) = next_field(rest)?;
+    let socket_type = match u16::from_str_radix(type_str, 16) {
+        Ok(0x0001) => UnixSocketType::Stream,
+        Ok(0x0002) => UnixSocketType::Datagram,
+        Ok(0x0005) => UnixSocketType::SeqPacket,
+        _ => return Err(ParseConnectionError::InvalidFormat),
+    };
+
+    let (state_str, rest) = next_field(rest)?;
+    let state = match u8::from_str_radix(state_str, 16) {
+        _ if flags & __SO_ACCEPTCON != 0 => UnixSocketState::Listening,
+        Ok(0x01) => UnixSocketState::Unconnected,
+        Ok(0x02) => UnixSocketState::Connecting,
+        Ok(0x03) => UnixSocketState::Connected,
+        Ok(0x04) => UnixSocketState::Disconnecting,
+        _ => return Err(ParseConnectionError::InvalidFormat),
+    };
+
+    let (inode_str, path) = next_field(rest)?;
+    let inode = inode_str.parse::<u64>()
+        .map_err(|_| ParseConnectionError::InvalidFormat)?;
+
+    // The path is separated from the inode by exactly one space and is printed
+    // verbatim, so it can contain spaces on its own. Abstract names start with
+    // the `@` character (standing in for the leading null byte).
+    let addr = if path.is_empty() {
+        None
+    } else if let Some(name) = path.strip_prefix('@') {
+        Some(UnixSocketAddr::Abstract(name.as_bytes().to_vec()))
+    } else {
+        Some(UnixSocketAddr::Pathname(std::path::PathBuf::from(path)))
+    };
+
+    Ok(UnixSocket {
+        addr,
+        socket_type,
+        state,
+        inode,
+        pid: 0, // Set at the iterator level where PID is available.
+    })
+}
+
 /// Parses an IPv4 socket address in the procfs format.
 fn parse_socket_addr_v4(string: &str) -> Result<std::net::SocketAddrV4, ParseSocketAddrError> {
     let mut parts = string.split(':');
@@ -777,4 +911,52 @@ mod tests {
 
         assert_eq!(error, ParseTcpStateError::UnknownState);
     }
+
+    #[test]
+    fn parse_raw_v4_connection_icmp() {
+        let conn = parse_raw_v4_connection(" 133: 00000000:0001 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 91796 2 00000000b0893ffd 0")
+            .unwrap();
+
+        assert_eq!(conn.local_addr(), std::net::Ipv4Addr::UNSPECIFIED);
+        assert_eq!(conn.remote_addr(), std::net::Ipv4Addr::UNSPECIFIED);
+        assert_eq!(conn.protocol(), 1);
+    }
+
+    #[test]
+    fn parse_unix_socket_pathname() {
+        let socket = parse_unix_socket("0000000000000000: 00000002 00000000 00010000 0001 01   979 /run/foo bar.sock")
+            .unwrap();
+
+        assert_eq!(socket.addr(), Some(&UnixSocketAddr::Pathname("/run/foo bar.sock".into())));
+        assert_eq!(socket.socket_type(), UnixSocketType::Stream);
+        assert_eq!(socket.state(), UnixSocketState::Listening);
+        assert_eq!(socket.inode(), 979);
+    }
+
+    #[test]
+    fn parse_unix_socket_abstract() {
+        let socket = parse_unix_socket("0000000000000000: 00000002 00000000 00000000 0002 01 91795 @foo")
+            .unwrap();
+
+        assert_eq!(socket.addr(), Some(&UnixSocketAddr::Abstract(b"foo".to_vec())));
+        assert_eq!(socket.socket_type(), UnixSocketType::Datagram);
+        assert_eq!(socket.state(), UnixSocketState::Unconnected);
+    }
+
+    #[test]
+    fn parse_unix_socket_unbound() {
+        let socket = parse_unix_socket("0000000000000000: 00000003 00000000 00000000 0001 03 91754")
+            .unwrap();
+
+        assert_eq!(socket.addr(), None);
+        assert_eq!(socket.state(), UnixSocketState::Connected);
+    }
+
+    #[test]
+    fn parse_unix_socket_unknown_type() {
+        let error = parse_unix_socket("0000000000000000: 00000002 00000000 00000000 0004 01 91795")
+            .unwrap_err();
+
+        assert_eq!(error, ParseConnectionError::InvalidFormat);
+    }
 }
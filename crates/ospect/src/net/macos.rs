@@ -185,6 +185,18 @@ pub fn interfaces() -> std::io::Result<impl Iterator<Item = Interface>> {
     Ok(ifaces.into_iter())
 }
 
+/// Collects DNS resolver configuration of the system.
+///
+/// A system agnostic [`dns_config`] function is available in the parent module
+/// and should be the preferred choice in general.
+///
+/// The configuration is read from the `/etc/resolv.conf` file.
+///
+/// [`dns_config`]: super::dns_config
+pub fn dns_config() -> std::io::Result<DnsConfig> {
+    crate::net::unix::dns_config()
+}
+
 /// Returns an iterator over IPv4 TCP connections for the specified process.
 pub fn tcp_v4_connections(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<TcpConnectionV4>>> {
     conn::tcp_v4(pid)
@@ -23,3 +23,136 @@ pub fn all_udp_v6_connections() -> std::io::Result<impl Iterator<Item = std::io:
     let pids = crate::proc::ids()?;
     Ok(pids.flat_map(|pid| crate::net::udp_v6_connections(pid?)).flatten())
 }
+
+/// Path to the resolver configuration file.
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Collects DNS resolver configuration from the `/etc/resolv.conf` file.
+///
+/// A missing file is not considered an error: in such case the resolver falls
+/// back to defaults and so an empty configuration is returned.
+pub fn dns_config() -> std::io::Result<DnsConfig> {
+    let file = match std::fs::File::open(RESOLV_CONF_PATH) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(DnsConfig::default());
+        }
+        Err(error) => return Err(error),
+    };
+
+    parse_resolv_conf(std::io::BufReader::new(file))
+}
+
+/// Parses DNS resolver configuration in the `resolv.conf` format [1].
+///
+/// Comments, unknown options and malformed name server addresses are ignored.
+///
+/// [1]: https://man7.org/linux/man-pages/man5/resolv.conf.5.html
+pub fn parse_resolv_conf<R>(reader: R) -> std::io::Result<DnsConfig>
+where
+    R: std::io::BufRead,
+{
+    let mut config = DnsConfig::default();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        // Technically, comments should start in the first column but resolvers
+        // are generally lenient about it, so we are too.
+        let line = match line.find(['#', ';']) {
+            Some(idx) => &line[..idx],
+            None => &line[..],
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => {
+                let Some(server) = words.next() else {
+                    continue;
+                };
+
+                // IPv6 addresses can have a zone index (e.g. `fe80::1%eth0`)
+                // which is not supported by the standard parser.
+                let server = match server.split_once('%') {
+                    Some((server, _)) => server,
+                    None => server,
+                };
+
+                match server.parse() {
+                    Ok(server) => config.servers.push(server),
+                    Err(_) => continue,
+                }
+            }
+            // `domain` and `search` are mutually exclusive and the last one of
+            // them wins.
+            Some("domain") | Some("search") => {
+                config.search_domains = words.map(String::from).collect();
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_resolv_conf_empty() {
+        let config = parse_resolv_conf(&b""[..])
+            .unwrap();
+
+        assert_eq!(config, DnsConfig::default());
+    }
+
+    #[test]
+    fn parse_resolv_conf_commented_out() {
+        let config = parse_resolv_conf(&b"\
+# nameserver 8.8.8.8
+; search example.com
+"[..]).unwrap();
+
+        assert_eq!(config, DnsConfig::default());
+    }
+
+    #[test]
+    fn parse_resolv_conf_sample() {
+        let config = parse_resolv_conf(&b"\
+# Generated by NetworkManager.
+search corp.example.com example.com
+nameserver 192.168.1.1
+nameserver 2001:4860:4860::8888 # Secondary.
+; nameserver 10.0.0.1
+nameserver fe80::1%eth0
+nameserver foo
+options edns0 trust-ad
+"[..]).unwrap();
+
+        use std::net::{Ipv4Addr, Ipv6Addr};
+
+        assert_eq!(config.servers, vec! {
+            std::net::IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)),
+            std::net::IpAddr::from(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888)),
+            std::net::IpAddr::from(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+        });
+        assert_eq!(config.search_domains, vec! {
+            String::from("corp.example.com"),
+            String::from("example.com"),
+        });
+    }
+
+    #[test]
+    fn parse_resolv_conf_last_search_wins() {
+        let config = parse_resolv_conf(&b"\
+search foo.example.com
+domain bar.example.com
+"[..]).unwrap();
+
+        assert_eq!(config.search_domains, vec! {
+            String::from("bar.example.com"),
+        });
+    }
+}
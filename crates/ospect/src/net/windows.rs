@@ -208,6 +208,172 @@ pub fn interfaces() -> std::io::Result<impl Iterator<Item = Interface>> {
     Ok(ifaces.into_values())
 }
 
+/// Collects DNS resolver configuration of the system.
+///
+/// A system agnostic [`dns_config`] function is available in the parent module
+/// and should be the preferred choice in general.
+///
+/// This function is a wrapper around [`GetAdaptersAddresses`][1] Windows call
+/// and collects name servers and DNS suffixes of all operational adapters.
+///
+/// [1]: https://learn.microsoft.com/en-us/windows/win32/api/iphlpapi/nf-iphlpapi-getadaptersaddresses
+///
+/// [`dns_config`]: super::dns_config
+pub fn dns_config() -> std::io::Result<DnsConfig> {
+    use windows_sys::Win32::NetworkManagement::IpHelper::*;
+
+    // We are interested only in the DNS information, so we skip all the other
+    // kinds of addresses.
+    let flags = GAA_FLAG_SKIP_UNICAST | GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST;
+
+    let mut buf_size = u32::try_from(DEFAULT_BUF_SIZE)
+        .expect("default buffer size too big");
+
+    let mut buf = Vec::<u8>::with_capacity(buf_size as usize);
+
+    // SAFETY: We call the function as described in the official docs [1]. In
+    // case the allocated buffer is too small, we handle this case below.
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows/win32/api/iphlpapi/nf-iphlpapi-getadaptersaddresses#parameters
+    let mut code = unsafe {
+        GetAdaptersAddresses(
+            windows_sys::Win32::Networking::WinSock::AF_UNSPEC as u32,
+            flags,
+            std::ptr::null_mut(),
+            buf.as_mut_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>(),
+            &mut buf_size,
+        )
+    };
+
+    if code == windows_sys::Win32::Foundation::ERROR_BUFFER_OVERFLOW {
+        buf = Vec::<u8>::with_capacity(buf_size as usize);
+
+        // SAFETY: We call the function the same as above but with larger result
+        // buffer (see comments in the `interfaces` function).
+        code = unsafe {
+            GetAdaptersAddresses(
+                windows_sys::Win32::Networking::WinSock::AF_UNSPEC as u32,
+                flags,
+                std::ptr::null_mut(),
+                buf.as_mut_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>(),
+                &mut buf_size,
+            )
+        };
+    }
+
+    if code != windows_sys::Win32::Foundation::NO_ERROR {
+        let code = i32::try_from(code)
+            .expect("invalid error code");
+
+        return Err(std::io::Error::from_raw_os_error(code));
+    }
+
+    let mut config = DnsConfig::default();
+
+    let mut addr_iter = buf.as_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>();
+    // SAFETY: We validate that the `GetAdaptersAddresses` call above did not
+    // fail. Thus, the buffer was filled with valid data and now we can iterate
+    // over the list using the `Next` pointers [1].
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows/win32/api/iptypes/ns-iptypes-ip_adapter_addresses_lh
+    while let Some(addr) = unsafe { addr_iter.as_ref() } {
+        addr_iter = addr.Next;
+
+        // Servers of adapters that are down are not used for resolution.
+        if addr.OperStatus != windows_sys::Win32::NetworkManagement::Ndis::IfOperStatusUp {
+            continue;
+        }
+
+        let mut server_iter = addr.FirstDnsServerAddress;
+        // SAFETY: We simply iterate on a null-terminated linked list built by
+        // the system [1].
+        //
+        // [1]: https://learn.microsoft.com/en-us/windows/win32/api/iptypes/ns-iptypes-ip_adapter_addresses_lh
+        while let Some(server) = unsafe { server_iter.as_ref() } {
+            server_iter = server.Next;
+
+            // SAFETY: The address is built by the operating system and so it
+            // points to a valid socket address structure.
+            let server = match unsafe { sock_addr_ip_addr(&server.Address) } {
+                Some(server) => server,
+                None => continue,
+            };
+
+            if !config.servers.contains(&server) {
+                config.servers.push(server);
+            }
+        }
+
+        // SAFETY: `DnsSuffix` is a null-terminated wide string [1] (possibly
+        // empty but never null).
+        //
+        // [1]: https://learn.microsoft.com/en-us/windows/win32/api/iptypes/ns-iptypes-ip_adapter_addresses_lh
+        let suffix = unsafe {
+            let len = (0..).take_while(|&i| *addr.DnsSuffix.add(i) != 0).count();
+            std::slice::from_raw_parts(addr.DnsSuffix, len)
+        };
+        push_search_domain(&mut config, suffix);
+
+        let mut suffix_iter = addr.FirstDnsSuffix;
+        // SAFETY: Same as with the name server list above, this is a null-ter-
+        // minated linked list built by the system.
+        while let Some(suffix) = unsafe { suffix_iter.as_ref() } {
+            suffix_iter = suffix.Next;
+
+            let len = suffix.String.iter()
+                .take_while(|&&unit| unit != 0)
+                .count();
+            push_search_domain(&mut config, &suffix.String[..len]);
+        }
+    }
+
+    Ok(config)
+}
+
+/// Adds the given wide-string DNS suffix to the search domains (if not empty).
+fn push_search_domain(config: &mut DnsConfig, suffix: &[u16]) {
+    if suffix.is_empty() {
+        return;
+    }
+
+    let suffix = String::from_utf16_lossy(suffix);
+    if !config.search_domains.contains(&suffix) {
+        config.search_domains.push(suffix);
+    }
+}
+
+/// Converts the given socket address to an IP address (if it is IPv4 or IPv6).
+///
+/// # Safety
+///
+/// The `lpSockaddr` pointer of the socket address must point to a valid socket
+/// address structure.
+unsafe fn sock_addr_ip_addr(
+    sock_addr: &windows_sys::Win32::Networking::WinSock::SOCKET_ADDRESS,
+) -> Option<std::net::IpAddr> {
+    use windows_sys::Win32::Networking::WinSock::*;
+
+    match (*sock_addr.lpSockaddr).sa_family {
+        AF_INET => {
+            let sock_addr = *(sock_addr.lpSockaddr as *const SOCKADDR_IN);
+            let octets = sock_addr.sin_addr.S_un.S_un_b;
+
+            Some(std::net::Ipv4Addr::from([
+                octets.s_b1,
+                octets.s_b2,
+                octets.s_b3,
+                octets.s_b4,
+            ]).into())
+        }
+        AF_INET6 => {
+            let sock_addr = *(sock_addr.lpSockaddr as *const SOCKADDR_IN6);
+
+            Some(std::net::Ipv6Addr::from(sock_addr.sin6_addr.u.Byte).into())
+        }
+        _ => None,
+    }
+}
+
 /// Returns an iterator over IPv4 TCP connections for the specified process.
 pub fn tcp_v4_connections(pid: u32) -> std::io::Result<impl Iterator<Item = std::io::Result<TcpConnectionV4>>> {
     let iter = all_tcp_v4_connections()?
@@ -290,4 +456,9 @@ mod tests {
 
         assert!(ifaces.next().is_some());
     }
+
+    #[test]
+    fn dns_config_ok() {
+        assert!(super::dns_config().is_ok());
+    }
 }
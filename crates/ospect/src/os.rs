@@ -4,7 +4,7 @@
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
 
 #[cfg(target_os = "linux")]
-mod linux;
+pub mod linux;
 
 #[cfg(target_os = "macos")]
 mod macos;
@@ -79,6 +79,104 @@ pub fn version() -> std::io::Result<String> {
     self::sys::version()
 }
 
+/// Normalized information about the release of the operating system.
+///
+/// Fields follow the semantics of the corresponding variables of the Linux
+/// [`os-release`][1] file regardless of the system. Fields that could not be
+/// determined are `None`.
+///
+/// [1]: https://www.freedesktop.org/software/systemd/man/latest/os-release.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Release {
+    /// Identifier of the system (e.g. `debian`, `macos` or `windows`).
+    pub id: Option<String>,
+    /// Name of the system (e.g. `Debian GNU/Linux` or `Windows 10 Pro`).
+    pub name: Option<String>,
+    /// Version of the system (e.g. `12`, `14.2.1` or `22H2`).
+    pub version_id: Option<String>,
+    /// Name of the system suitable for presentation to the user.
+    pub pretty_name: Option<String>,
+}
+
+impl Release {
+
+    /// Checks whether nothing is known about the release.
+    pub fn is_unknown(&self) -> bool {
+        *self == Release::default()
+    }
+}
+
+/// Returns normalized information about the release of the operating system.
+///
+/// The exact behaviour is system specific:
+///
+///   * On Linux it parses the [`os-release`][1] file.
+///   * On macOS it parses the `SystemVersion.plist` file.
+///   * On Windows it reads the `CurrentVersion` key of the Windows Registry.
+///
+/// Unlike most of the functions of this module, it never fails: if the release
+/// information is not available, an unknown release (see [`Release::is_unknown`])
+/// is returned.
+///
+/// [1]: https://www.freedesktop.org/software/systemd/man/latest/os-release.html
+pub fn release() -> Release {
+    self::sys::release()
+}
+
+/// Parses the release information from the macOS `SystemVersion.plist` data.
+///
+/// Only the string values of the `ProductName` and `ProductVersion` keys are
+/// considered, everything else is ignored.
+#[cfg(any(target_os = "macos", test))]
+fn parse_macos_system_version(contents: &str) -> Release {
+    let mut name = None;
+    let mut version = None;
+
+    let mut rest = contents;
+    while let Some((_, after_key)) = rest.split_once("<key>") {
+        let (key, after_key) = match after_key.split_once("</key>") {
+            Some(key) => key,
+            None => break,
+        };
+        rest = after_key;
+
+        let value = match after_key.trim_start().strip_prefix("<string>") {
+            Some(value) => match value.split_once("</string>") {
+                Some((value, _)) => value,
+                None => break,
+            },
+            None => continue,
+        };
+
+        let value = value.trim()
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&");
+        if value.is_empty() {
+            continue;
+        }
+
+        match key.trim() {
+            "ProductName" => name = Some(value),
+            "ProductVersion" => version = Some(value),
+            _ => continue,
+        }
+    }
+
+    let pretty_name = match (&name, &version) {
+        (Some(name), Some(version)) => Some(format!("{name} {version}")),
+        (Some(name), None) => Some(name.clone()),
+        (None, _) => None,
+    };
+
+    Release {
+        id: Some(String::from("macos")),
+        name,
+        version_id: version,
+        pretty_name,
+    }
+}
+
 /// Returns the CPU architecture of the currently running operating system.
 ///
 /// No assumptions on the specific format of this string should be made. Even
@@ -113,6 +211,189 @@ pub fn fqdn() -> std::io::Result<std::ffi::OsString> {
     self::sys::fqdn()
 }
 
+/// Unique identifier of the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MachineId {
+    /// Value of the identifier.
+    pub value: String,
+    /// Source from which the identifier was obtained.
+    pub source: MachineIdSource,
+}
+
+/// Source from which a [`MachineId`] can be obtained.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MachineIdSource {
+    /// UUID of the product from the DMI (SMBIOS) tables (Linux-only).
+    DmiProductUuid,
+    /// The systemd (or D-Bus) `machine-id` file (Linux-only).
+    MachineIdFile,
+    /// The `MachineGuid` value of the Windows Registry (Windows-only).
+    MachineGuid,
+    /// The `IOPlatformUUID` of the hardware (macOS-only).
+    PlatformUuid,
+}
+
+/// Returns the unique identifier of the currently running machine.
+///
+/// The exact behaviour is system specific:
+///
+///   * On Linux it uses the DMI product UUID (which is tied to the hardware and
+///     thus survives reinstalls) and falls back to the [`machine-id`][1] file.
+///   * On macOS it uses the platform UUID (through [`gethostuuid`][2]).
+///   * On Windows it uses the `MachineGuid` value of the Windows Registry.
+///
+/// [1]: https://www.freedesktop.org/software/systemd/man/latest/machine-id.html
+/// [2]: https://developer.apple.com/documentation/kernel/1515464-gethostuuid
+///
+/// # Errors
+///
+/// This function will return an error if none of the sources are available or
+/// there was some issue when trying to query data from the system.
+pub fn machine_id() -> std::io::Result<MachineId> {
+    self::sys::machine_id()
+}
+
+/// Returns the name of the timezone the system is configured with.
+///
+/// On Linux and macOS this is the name from the IANA timezone database [1]
+/// (e.g. `Europe/Zurich`) and on Windows the name of the timezone key of the
+/// Windows Registry (e.g. `W. Europe Standard Time`). If no timezone is
+/// configured or its name cannot be determined, `None` is returned.
+///
+/// The exact behaviour is system specific:
+///
+///   * On Linux it resolves the `/etc/localtime` symlink and falls back to the
+///     `/etc/timezone` file.
+///   * On macOS it resolves the `/etc/localtime` symlink.
+///   * On Windows it uses the [`GetDynamicTimeZoneInformation`][2] call.
+///
+/// [1]: https://www.iana.org/time-zones
+/// [2]: https://learn.microsoft.com/en-us/windows/win32/api/timezoneapi/nf-timezoneapi-getdynamictimezoneinformation
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query data from the system.
+pub fn timezone() -> std::io::Result<Option<String>> {
+    self::sys::timezone()
+}
+
+/// Returns the offset of the local time from UTC currently in effect.
+///
+/// The offset is in seconds and is positive east of UTC (e.g. `3600` for the
+/// Central European Time). It takes daylight saving time into account.
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query data from the system.
+pub fn utc_offset() -> std::io::Result<i32> {
+    self::sys::utc_offset()
+}
+
+/// Information about a local user account.
+#[derive(Debug)]
+pub struct User {
+    /// Name of the user account.
+    pub name: std::ffi::OsString,
+    /// Path to the home directory of the user.
+    pub home: std::path::PathBuf,
+}
+
+/// Returns an iterator over local user accounts available on the system.
+///
+/// The exact behaviour is system specific:
+///
+///   * On Linux and macOS it uses the [`getpwent`][1] call (and thus respects
+///     the system user database configuration).
+///   * On Windows it lists user profile folders at the system drive.
+///
+/// [1]: https://man7.org/linux/man-pages/man3/getpwent.3.html
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query data from the system.
+pub fn users() -> std::io::Result<impl Iterator<Item = User>> {
+    self::sys::users()
+}
+
+/// Returns the user and group identifiers of the user with the given name.
+///
+/// The lookup goes through the [`getpwnam_r`][1] call, so it respects the
+/// system user database configuration (e.g. `nsswitch.conf` on Linux). The
+/// group is the primary group of the user. If there is no user with the given
+/// name, `None` is returned.
+///
+/// This function is available only on Unix-like systems.
+///
+/// [1]: https://man7.org/linux/man-pages/man3/getpwnam_r.3.html
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query the user database.
+///
+/// # Examples
+///
+/// ```
+/// let ids = ospect::os::user_ids(std::ffi::OsStr::new("root"))
+///     .unwrap();
+///
+/// assert_eq!(ids.map(|(uid, _)| uid), Some(0));
+/// ```
+#[cfg(target_family = "unix")]
+pub fn user_ids(name: &std::ffi::OsStr) -> std::io::Result<Option<(u32, u32)>> {
+    self::unix::user_ids(name)
+}
+
+/// Returns the name of the user with the given identifier.
+///
+/// The lookup goes through the [`getpwuid_r`][1] call, so it respects the
+/// system user database configuration (e.g. `nsswitch.conf` on Linux). If
+/// there is no user with the given identifier, `None` is returned.
+///
+/// This function is available only on Unix-like systems.
+///
+/// [1]: https://man7.org/linux/man-pages/man3/getpwuid_r.3.html
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query the user database.
+///
+/// # Examples
+///
+/// ```
+/// let name = ospect::os::user_name(0)
+///     .unwrap();
+///
+/// assert_eq!(name.as_deref(), Some(std::ffi::OsStr::new("root")));
+/// ```
+#[cfg(target_family = "unix")]
+pub fn user_name(uid: u32) -> std::io::Result<Option<std::ffi::OsString>> {
+    self::unix::user_name(uid)
+}
+
+/// Returns the name of the group with the given identifier.
+///
+/// The lookup goes through the [`getgrgid_r`][1] call, so it respects the
+/// system group database configuration (e.g. `nsswitch.conf` on Linux). If
+/// there is no group with the given identifier, `None` is returned.
+///
+/// This function is available only on Unix-like systems.
+///
+/// [1]: https://man7.org/linux/man-pages/man3/getgrgid_r.3.html
+///
+/// # Errors
+///
+/// This function will return an error in case there was some issue when trying
+/// to query the group database.
+#[cfg(target_family = "unix")]
+pub fn group_name(gid: u32) -> std::io::Result<Option<std::ffi::OsString>> {
+    self::unix::group_name(gid)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -134,6 +415,57 @@ mod tests {
         assert_eq!(arch().unwrap(), "x86_64");
     }
 
+    #[test]
+    fn release_known() {
+        assert!(!release().is_unknown());
+    }
+
+    #[test]
+    fn parse_macos_system_version_sonoma() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>BuildID</key>
+	<string>3A9F6E2C-8B1D-11EE-A7B2-4E0A3C5D1F27</string>
+	<key>ProductBuildVersion</key>
+	<string>23C71</string>
+	<key>ProductCopyright</key>
+	<string>1983-2023 Apple Inc.</string>
+	<key>ProductName</key>
+	<string>macOS</string>
+	<key>ProductUserVisibleVersion</key>
+	<string>14.2.1</string>
+	<key>ProductVersion</key>
+	<string>14.2.1</string>
+	<key>iOSSupportVersion</key>
+	<string>17.2</string>
+</dict>
+</plist>
+"#;
+
+        let release = parse_macos_system_version(contents);
+        assert_eq!(release.id.as_deref(), Some("macos"));
+        assert_eq!(release.name.as_deref(), Some("macOS"));
+        assert_eq!(release.version_id.as_deref(), Some("14.2.1"));
+        assert_eq!(release.pretty_name.as_deref(), Some("macOS 14.2.1"));
+    }
+
+    #[test]
+    fn parse_macos_system_version_missing_and_malformed() {
+        let contents = r#"<plist version="1.0">
+<dict>
+	<key>ProductName</key>
+	<integer>14</integer>
+	<key>ProductVersion</key>
+	<string>10.15"#;
+
+        let release = parse_macos_system_version(contents);
+        assert_eq!(release.name, None);
+        assert_eq!(release.version_id, None);
+        assert_eq!(release.pretty_name, None);
+    }
+
     #[test]
     fn hostname_not_empty() {
         assert!(!hostname().unwrap().is_empty());
@@ -145,4 +477,78 @@ mod tests {
     fn fqdn_not_empty() {
         assert!(!fqdn().unwrap().is_empty());
     }
+
+    #[test]
+    fn machine_id_stable() {
+        let machine_id = machine_id().unwrap();
+        assert!(!machine_id.value.is_empty());
+        assert_eq!(self::machine_id().unwrap(), machine_id);
+    }
+
+    #[test]
+    fn timezone_ok() {
+        assert!(timezone().is_ok());
+    }
+
+    #[test]
+    fn utc_offset_in_range() {
+        let utc_offset = utc_offset()
+            .unwrap();
+
+        // Offsets of all the real-world timezones are within ±14 hours.
+        assert!(utc_offset.abs() <= 14 * 60 * 60);
+    }
+
+    #[test]
+    fn users_not_empty() {
+        assert!(users().unwrap().next().is_some());
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn user_ids_root() {
+        let ids = user_ids(std::ffi::OsStr::new("root"))
+            .unwrap();
+
+        assert_eq!(ids.map(|(uid, _)| uid), Some(0));
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn user_ids_unknown() {
+        assert!(user_ids(std::ffi::OsStr::new("rrg-nonexistent-user")).unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn user_name_root() {
+        let name = user_name(0)
+            .unwrap();
+
+        assert_eq!(name.as_deref(), Some(std::ffi::OsStr::new("root")));
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn user_name_unknown() {
+        // Identifier `-2` is reserved as _nobody_ on some systems, so we take
+        // one that is unlikely to be allocated anywhere.
+        assert!(user_name(u32::MAX - 1337).unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn group_name_root() {
+        let name = group_name(0)
+            .unwrap();
+
+        // On macOS the group with identifier 0 is called `wheel`.
+        assert!(name.is_some());
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn group_name_unknown() {
+        assert!(group_name(u32::MAX - 1337).unwrap().is_none());
+    }
 }
@@ -34,3 +34,294 @@ pub fn hostname() -> std::io::Result<std::ffi::OsString> {
 pub fn fqdn() -> std::io::Result<std::ffi::OsString> {
     crate::os::unix::fqdn()
 }
+
+/// Returns the name of the timezone the system is configured with.
+pub fn timezone() -> std::io::Result<Option<String>> {
+    if let Some(timezone) = crate::os::unix::timezone()? {
+        return Ok(Some(timezone));
+    }
+
+    // Debian-based distributions (and container images derived from them) may
+    // have `/etc/localtime` copied rather than symlinked but they record the
+    // name in a separate file.
+    match std::fs::read_to_string("/etc/timezone") {
+        Ok(timezone) if !timezone.trim().is_empty() => {
+            Ok(Some(String::from(timezone.trim())))
+        }
+        Ok(_) => Ok(None),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the offset of the local time from UTC (in seconds) now in effect.
+pub fn utc_offset() -> std::io::Result<i32> {
+    crate::os::unix::utc_offset()
+}
+
+/// Returns an iterator over local user accounts available on the system.
+pub fn users() -> std::io::Result<impl Iterator<Item = crate::os::User>> {
+    crate::os::unix::users()
+}
+
+/// Returns the unique identifier of the currently running machine.
+pub fn machine_id() -> std::io::Result<crate::os::MachineId> {
+    use crate::os::MachineIdSource::*;
+
+    // Paths to files with machine identifiers, ordered from the most stable.
+    const PATHS: &[(&str, crate::os::MachineIdSource)] = &[
+        ("/sys/class/dmi/id/product_uuid", DmiProductUuid),
+        ("/etc/machine-id", MachineIdFile),
+        ("/var/lib/dbus/machine-id", MachineIdFile),
+    ];
+
+    for (path, source) in PATHS {
+        let value = match std::fs::read_to_string(path) {
+            Ok(value) => value.trim().to_lowercase(),
+            Err(_) => continue,
+        };
+
+        if !is_valid_machine_id(&value) {
+            continue;
+        }
+
+        return Ok(crate::os::MachineId {
+            value,
+            source: *source,
+        });
+    }
+
+    Err(std::io::ErrorKind::NotFound.into())
+}
+
+/// Information about the Linux distribution.
+///
+/// Fields correspond to the variables of the [`os-release`][1] file. Variables
+/// that are not specified (or are empty) are `None`.
+///
+/// [1]: https://www.freedesktop.org/software/systemd/man/latest/os-release.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OsRelease {
+    /// Name of the distribution (e.g. `Debian GNU/Linux`).
+    pub name: Option<String>,
+    /// Identifier of the distribution (e.g. `debian`).
+    pub id: Option<String>,
+    /// Version of the distribution (e.g. `12`).
+    pub version_id: Option<String>,
+    /// Name of the distribution suitable for presentation to the user.
+    pub pretty_name: Option<String>,
+}
+
+/// Returns information about the Linux distribution of the system.
+///
+/// The information is read from the `/etc/os-release` file or from the
+/// `/usr/lib/os-release` file if the former does not exist.
+///
+/// # Errors
+///
+/// This function will return an error if none of the files can be read.
+pub fn os_release() -> std::io::Result<OsRelease> {
+    let file = match std::fs::File::open("/etc/os-release") {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::File::open("/usr/lib/os-release")?
+        }
+        Err(error) => return Err(error),
+    };
+
+    parse_os_release(std::io::BufReader::new(file))
+}
+
+/// Returns normalized information about the release of the operating system.
+pub fn release() -> crate::os::Release {
+    match os_release() {
+        Ok(os_release) => os_release.into(),
+        Err(_) => crate::os::Release::default(),
+    }
+}
+
+impl From<OsRelease> for crate::os::Release {
+
+    fn from(os_release: OsRelease) -> crate::os::Release {
+        crate::os::Release {
+            id: os_release.id,
+            name: os_release.name,
+            version_id: os_release.version_id,
+            pretty_name: os_release.pretty_name,
+        }
+    }
+}
+
+/// Parses information about the Linux distribution in the `os-release` format.
+///
+/// Lines that are not variable assignments and unknown variables are ignored.
+///
+/// # Errors
+///
+/// This function will return an error if reading from the given reader fails.
+pub fn parse_os_release<R>(reader: R) -> std::io::Result<OsRelease>
+where
+    R: std::io::BufRead,
+{
+    let mut os_release = OsRelease::default();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key, unquote_os_release_value(value)),
+            None => continue,
+        };
+
+        if value.is_empty() {
+            continue;
+        }
+
+        let field = match key {
+            "NAME" => &mut os_release.name,
+            "ID" => &mut os_release.id,
+            "VERSION_ID" => &mut os_release.version_id,
+            "PRETTY_NAME" => &mut os_release.pretty_name,
+            _ => continue,
+        };
+        *field = Some(value);
+    }
+
+    Ok(os_release)
+}
+
+/// Strips quotes (and escapes) from a value of an `os-release` variable.
+///
+/// Values follow the shell syntax and can be enclosed in single or double
+/// quotes. Within double quotes, `\\`, `\"`, `\$` and `` \` `` are escape
+/// sequences.
+fn unquote_os_release_value(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return String::from(&value[1..value.len() - 1]);
+    }
+
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut result = String::new();
+
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(char) = chars.next() {
+            if char == '\\' {
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+            } else {
+                result.push(char);
+            }
+        }
+
+        return result;
+    }
+
+    String::from(value)
+}
+
+/// Verifies whether the given machine identifier value is usable.
+///
+/// Empty files or placeholder values (like the systemd `uninitialized` marker
+/// or the all-zero and all-ones UUIDs used by some firmware) are not usable.
+fn is_valid_machine_id(value: &str) -> bool {
+    let digits = value.chars()
+        .filter(|char| *char != '-')
+        .collect::<String>();
+
+    if digits.is_empty() || !digits.chars().all(|char| char.is_ascii_hexdigit()) {
+        return false;
+    }
+
+    !digits.chars().all(|char| char == '0') && !digits.chars().all(|char| char == 'f')
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn is_valid_machine_id_ok() {
+        assert!(is_valid_machine_id("4c4c4544-0042-3510-8051-b4c04f4e4d32"));
+        assert!(is_valid_machine_id("b08dfa6083e7567a1921a715000001fb"));
+    }
+
+    #[test]
+    fn is_valid_machine_id_placeholder() {
+        assert!(!is_valid_machine_id(""));
+        assert!(!is_valid_machine_id("uninitialized"));
+        assert!(!is_valid_machine_id("00000000-0000-0000-0000-000000000000"));
+        assert!(!is_valid_machine_id("ffffffff-ffff-ffff-ffff-ffffffffffff"));
+    }
+
+    #[test]
+    fn os_release_ok() {
+        // Virtually all modern distributions ship this file, but we do not want
+        // the test to fail on obscure setups.
+        if !std::path::Path::new("/etc/os-release").exists() {
+            return;
+        }
+
+        let os_release = os_release()
+            .unwrap();
+
+        assert!(os_release.name.is_some() || os_release.id.is_some());
+    }
+
+    #[test]
+    fn parse_os_release_debian() {
+        let contents = b"\
+PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"
+NAME=\"Debian GNU/Linux\"
+VERSION_ID=\"12\"
+VERSION=\"12 (bookworm)\"
+VERSION_CODENAME=bookworm
+ID=debian
+HOME_URL=\"https://www.debian.org/\"
+";
+
+        let os_release = parse_os_release(&contents[..])
+            .unwrap();
+
+        assert_eq!(os_release, OsRelease {
+            name: Some(String::from("Debian GNU/Linux")),
+            id: Some(String::from("debian")),
+            version_id: Some(String::from("12")),
+            pretty_name: Some(String::from("Debian GNU/Linux 12 (bookworm)")),
+        });
+    }
+
+    #[test]
+    fn parse_os_release_missing_and_empty() {
+        let contents = b"\
+# Rolling release without a version.
+NAME='Arch Linux'
+ID=arch
+VERSION_ID=
+";
+
+        let os_release = parse_os_release(&contents[..])
+            .unwrap();
+
+        assert_eq!(os_release.name.as_deref(), Some("Arch Linux"));
+        assert_eq!(os_release.id.as_deref(), Some("arch"));
+        assert_eq!(os_release.version_id, None);
+        assert_eq!(os_release.pretty_name, None);
+    }
+
+    #[test]
+    fn parse_os_release_escapes() {
+        let contents = br#"NAME="Foo \"Bar\" \$Linux\\""#;
+
+        let os_release = parse_os_release(&contents[..])
+            .unwrap();
+
+        assert_eq!(os_release.name.as_deref(), Some(r#"Foo "Bar" $Linux\"#));
+    }
+}
@@ -20,6 +20,15 @@ pub fn version() -> std::io::Result<String> {
     crate::os::unix::version()
 }
 
+/// Returns normalized information about the release of the operating system.
+pub fn release() -> crate::os::Release {
+    let path = "/System/Library/CoreServices/SystemVersion.plist";
+    match std::fs::read_to_string(path) {
+        Ok(contents) => crate::os::parse_macos_system_version(&contents),
+        Err(_) => crate::os::Release::default(),
+    }
+}
+
 /// Returns the CPU architecture of the currently running operating system.
 pub fn arch() -> std::io::Result<String> {
     crate::os::unix::arch()
@@ -34,3 +43,50 @@ pub fn hostname() -> std::io::Result<std::ffi::OsString> {
 pub fn fqdn() -> std::io::Result<std::ffi::OsString> {
     crate::os::unix::fqdn()
 }
+
+/// Returns the name of the timezone the system is configured with.
+pub fn timezone() -> std::io::Result<Option<String>> {
+    crate::os::unix::timezone()
+}
+
+/// Returns the offset of the local time from UTC (in seconds) now in effect.
+pub fn utc_offset() -> std::io::Result<i32> {
+    crate::os::unix::utc_offset()
+}
+
+/// Returns an iterator over local user accounts available on the system.
+pub fn users() -> std::io::Result<impl Iterator<Item = crate::os::User>> {
+    crate::os::unix::users()
+}
+
+/// Returns the unique identifier of the currently running machine.
+pub fn machine_id() -> std::io::Result<crate::os::MachineId> {
+    let mut uuid = [0u8; 16];
+    let timeout = libc::timespec {
+        tv_sec: 5,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: We pass a buffer of 16 bytes (as required by the `uuid_t` type)
+    // and a valid pointer to the timeout. The result is verified below.
+    let code = unsafe {
+        libc::gethostuuid(uuid.as_mut_ptr(), &timeout)
+    };
+    if code != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let value = format! {
+        "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        uuid[0], uuid[1], uuid[2], uuid[3],
+        uuid[4], uuid[5],
+        uuid[6], uuid[7],
+        uuid[8], uuid[9],
+        uuid[10], uuid[11], uuid[12], uuid[13], uuid[14], uuid[15],
+    };
+
+    Ok(crate::os::MachineId {
+        value,
+        source: crate::os::MachineIdSource::PlatformUuid,
+    })
+}
@@ -117,6 +117,254 @@ pub fn fqdn() -> std::io::Result<std::ffi::OsString> {
     Ok(fqdn)
 }
 
+/// Returns an iterator over local user accounts available on the system.
+pub fn users() -> std::io::Result<impl Iterator<Item = crate::os::User>> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    // `getpwent` iterates over a process-global cursor, so we need to make
+    // sure that no two threads walk over the user database at the same time.
+    static GETPWENT_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _guard = GETPWENT_LOCK.lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    let mut users = Vec::new();
+
+    // SAFETY: Rewinding the user database has no preconditions.
+    unsafe {
+        libc::setpwent();
+    }
+
+    loop {
+        // SAFETY: The call has no preconditions. The returned pointer is valid
+        // only until the next call and we copy everything we need from it.
+        let entry = unsafe {
+            libc::getpwent()
+        };
+        if entry.is_null() {
+            break;
+        }
+
+        // SAFETY: We verified that the entry is not null and `pw_name` and
+        // `pw_dir` are guaranteed to be null-terminated strings.
+        let (name, home) = unsafe {
+            let entry = &*entry;
+            if entry.pw_name.is_null() || entry.pw_dir.is_null() {
+                continue;
+            }
+
+            (
+                std::ffi::CStr::from_ptr(entry.pw_name),
+                std::ffi::CStr::from_ptr(entry.pw_dir),
+            )
+        };
+
+        users.push(crate::os::User {
+            name: std::ffi::OsStr::from_bytes(name.to_bytes()).to_os_string(),
+            home: std::ffi::OsStr::from_bytes(home.to_bytes()).into(),
+        });
+    }
+
+    // SAFETY: Closing the user database has no preconditions.
+    unsafe {
+        libc::endpwent();
+    }
+
+    Ok(users.into_iter())
+}
+
+/// Returns the user and group identifiers of the user with the given name.
+pub fn user_ids(name: &std::ffi::OsStr) -> std::io::Result<Option<(u32, u32)>> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    // Entries are usually small, but the buffer has to be grown if the call
+    // reports it as too small. The maximum just guards against bogus entries.
+    const INITIAL_BUF_LEN: usize = 1024;
+    const MAX_BUF_LEN: usize = 1024 * 1024;
+
+    let name = std::ffi::CString::new(name.as_bytes())
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+
+    // SAFETY: `passwd` is a plain C struct for which all-zeros is a valid value
+    // (it is overwritten by the call anyway).
+    let mut entry = unsafe {
+        std::mem::zeroed::<libc::passwd>()
+    };
+
+    let mut buf = vec![0; INITIAL_BUF_LEN];
+    loop {
+        let mut result = std::ptr::null_mut();
+
+        // SAFETY: We pass a valid null-terminated name and valid pointers to
+        // the entry, to the buffer (along with its actual length) and to the
+        // result pointer. We read only the plain identifiers of the entry.
+        let code = unsafe {
+            libc::getpwnam_r(name.as_ptr(), &mut entry, buf.as_mut_ptr(), buf.len(), &mut result)
+        };
+        if code == libc::ERANGE && buf.len() < MAX_BUF_LEN {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if code != 0 {
+            return Err(std::io::Error::from_raw_os_error(code));
+        }
+
+        if result.is_null() {
+            return Ok(None);
+        }
+
+        return Ok(Some((entry.pw_uid, entry.pw_gid)));
+    }
+}
+
+/// Returns the name of the user with the given identifier (if it exists).
+pub fn user_name(uid: u32) -> std::io::Result<Option<std::ffi::OsString>> {
+    // SAFETY: `passwd` is a plain C struct for which all-zeros is a valid value
+    // (it is overwritten by the call anyway).
+    let mut entry = unsafe {
+        std::mem::zeroed::<libc::passwd>()
+    };
+
+    let name = lookup_reentrant(|buf, result: *mut *mut libc::passwd| {
+        // SAFETY: We pass valid pointers to the entry, to the buffer (along with
+        // its actual length) and to the result pointer. The call fills the entry
+        // with pointers into the buffer, which outlives their use below.
+        unsafe {
+            libc::getpwuid_r(uid, &mut entry, buf.as_mut_ptr(), buf.len(), result)
+        }
+    }, |entry: &libc::passwd| entry.pw_name)?;
+
+    Ok(name)
+}
+
+/// Returns the name of the group with the given identifier (if it exists).
+pub fn group_name(gid: u32) -> std::io::Result<Option<std::ffi::OsString>> {
+    // SAFETY: `group` is a plain C struct for which all-zeros is a valid value
+    // (it is overwritten by the call anyway).
+    let mut entry = unsafe {
+        std::mem::zeroed::<libc::group>()
+    };
+
+    let name = lookup_reentrant(|buf, result: *mut *mut libc::group| {
+        // SAFETY: See the comment in `user_name` (the call is analogous).
+        unsafe {
+            libc::getgrgid_r(gid, &mut entry, buf.as_mut_ptr(), buf.len(), result)
+        }
+    }, |entry: &libc::group| entry.gr_name)?;
+
+    Ok(name)
+}
+
+/// Returns the name of the timezone the system is configured with.
+///
+/// The name is taken from the target of the `/etc/localtime` symlink. If the
+/// file is not a symlink (or does not exist), `None` is returned.
+pub fn timezone() -> std::io::Result<Option<String>> {
+    let target = match std::fs::read_link("/etc/localtime") {
+        Ok(target) => target,
+        // `EINVAL` means that the file exists but is not a symlink.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) if error.kind() == std::io::ErrorKind::InvalidInput => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    Ok(timezone_from_path(&target))
+}
+
+/// Extracts the timezone name from a path to a timezone database file.
+///
+/// Timezone database files live in a `zoneinfo` directory (e.g. at
+/// `/usr/share/zoneinfo/Europe/Zurich` on Linux and
+/// `/var/db/timezone/zoneinfo/Europe/Zurich` on macOS) and the timezone name
+/// is the path relative to it.
+pub fn timezone_from_path(path: &std::path::Path) -> Option<String> {
+    let (_, name) = path.to_str()?.rsplit_once("zoneinfo/")?;
+
+    // Some distributions also ship the database variants with (`right`) and
+    // without (`posix`) leap seconds in subdirectories.
+    let name = name.strip_prefix("posix/")
+        .or_else(|| name.strip_prefix("right/"))
+        .unwrap_or(name);
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(String::from(name))
+}
+
+/// Returns the offset of the local time from UTC (in seconds) now in effect.
+pub fn utc_offset() -> std::io::Result<i32> {
+    let mut tm = std::mem::MaybeUninit::uninit();
+
+    // SAFETY: Passing a null pointer to `time` is allowed, the current time is
+    // just returned. We pass a valid pointer to it and to the buffer we have
+    // allocated to `localtime_r` and verify the result below.
+    let result = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, tm.as_mut_ptr())
+    };
+    if result.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: We verified that the call succeeded, so the buffer is initialized.
+    let tm = unsafe {
+        tm.assume_init()
+    };
+
+    Ok(tm.tm_gmtoff as i32)
+}
+
+/// Runs a reentrant user or group database lookup and extracts a name from it.
+///
+/// The lookup is retried with bigger buffers as long as the current one is too
+/// small to hold the entry. `None` is returned if there is no matching entry.
+fn lookup_reentrant<E, L, N>(mut lookup: L, name: N) -> std::io::Result<Option<std::ffi::OsString>>
+where
+    L: FnMut(&mut [libc::c_char], *mut *mut E) -> libc::c_int,
+    N: Fn(&E) -> *const libc::c_char,
+{
+    use std::os::unix::ffi::OsStrExt as _;
+
+    // Entries are small, so this is almost always enough. Entries of groups
+    // with many members can be bigger, in which case we grow the buffer.
+    const INITIAL_BUF_LEN: usize = 1024;
+    const MAX_BUF_LEN: usize = 1024 * 1024;
+
+    let mut buf = vec![0; INITIAL_BUF_LEN];
+    loop {
+        let mut result = std::ptr::null_mut();
+
+        let code = lookup(&mut buf, &mut result);
+        if code == libc::ERANGE && buf.len() < MAX_BUF_LEN {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if code != 0 {
+            return Err(std::io::Error::from_raw_os_error(code));
+        }
+
+        if result.is_null() {
+            return Ok(None);
+        }
+
+        // SAFETY: We verified that the lookup succeeded and found an entry, so
+        // the result points to the initialized entry which points into `buf`.
+        // The name is guaranteed to be null-terminated and we copy it before
+        // the buffer goes away.
+        let name = unsafe {
+            let name = name(&*result);
+            if name.is_null() {
+                return Ok(None);
+            }
+
+            std::ffi::CStr::from_ptr(name)
+        };
+
+        return Ok(Some(std::ffi::OsStr::from_bytes(name.to_bytes()).to_os_string()));
+    }
+}
+
 /// Returns `uname` information of the currently running operating system.
 fn uname() -> std::io::Result<libc::utsname> {
     let mut uname = std::mem::MaybeUninit::uninit();
@@ -138,3 +386,33 @@ fn uname() -> std::io::Result<libc::utsname> {
 
     Ok(uname)
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn timezone_from_path_linux() {
+        let path = std::path::Path::new("/usr/share/zoneinfo/Europe/Zurich");
+        assert_eq!(timezone_from_path(path).as_deref(), Some("Europe/Zurich"));
+    }
+
+    #[test]
+    fn timezone_from_path_macos() {
+        let path = std::path::Path::new("/var/db/timezone/zoneinfo/America/New_York");
+        assert_eq!(timezone_from_path(path).as_deref(), Some("America/New_York"));
+    }
+
+    #[test]
+    fn timezone_from_path_relative_posix() {
+        let path = std::path::Path::new("../usr/share/zoneinfo/posix/Asia/Tokyo");
+        assert_eq!(timezone_from_path(path).as_deref(), Some("Asia/Tokyo"));
+    }
+
+    #[test]
+    fn timezone_from_path_invalid() {
+        assert_eq!(timezone_from_path(std::path::Path::new("/etc/foo")), None);
+        assert_eq!(timezone_from_path(std::path::Path::new("/usr/share/zoneinfo/")), None);
+    }
+}
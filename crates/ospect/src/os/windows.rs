@@ -175,6 +175,95 @@ pub fn version() -> std::io::Result<String> {
     Ok(format!("{major}.{minor}.{build}.{revision}"))
 }
 
+/// Returns normalized information about the release of the operating system.
+pub fn release() -> crate::os::Release {
+    use windows_sys::w;
+
+    let name = current_version_value(w!("ProductName")).ok();
+    // `DisplayVersion` is available only since Windows 10 20H2, older releases
+    // use `ReleaseId` instead.
+    let version_id = current_version_value(w!("DisplayVersion"))
+        .or_else(|_| current_version_value(w!("ReleaseId")))
+        .ok();
+
+    let pretty_name = match (&name, &version_id) {
+        (Some(name), Some(version_id)) => Some(format!("{name} {version_id}")),
+        (Some(name), None) => Some(name.clone()),
+        (None, _) => None,
+    };
+
+    crate::os::Release {
+        id: Some(String::from("windows")),
+        name,
+        version_id,
+        pretty_name,
+    }
+}
+
+/// Reads a string value of the `CurrentVersion` key of the Windows Registry.
+fn current_version_value(name: windows_sys::core::PCWSTR) -> std::io::Result<String> {
+    use windows_sys::{w, Win32::System::Registry::*};
+
+    // We explicitly ask for the 64-bit view of the registry as otherwise 32-bit
+    // builds of the agent would be redirected to `Wow6432Node`.
+    let flags = RRF_RT_REG_SZ | RRF_SUBKEY_WOW6464KEY;
+
+    let mut buf_size = 0u32;
+
+    // SAFETY: We call the function with null buffer to query the size of the
+    // value as described in the docs [1]. The result is verified below.
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows/win32/api/winreg/nf-winreg-reggetvaluew
+    let code = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            name,
+            flags,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut buf_size,
+        )
+    };
+    if code != windows_sys::Win32::Foundation::NO_ERROR {
+        return Err(std::io::Error::from_raw_os_error(code as i32));
+    }
+
+    let mut buf = vec![0u16; buf_size as usize / std::mem::size_of::<u16>()];
+
+    // SAFETY: We pass a buffer of the size returned by the previous call. The
+    // result is verified below.
+    let code = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            name,
+            flags,
+            std::ptr::null_mut(),
+            buf.as_mut_ptr() as *mut std::ffi::c_void,
+            &mut buf_size,
+        )
+    };
+    if code != windows_sys::Win32::Foundation::NO_ERROR {
+        return Err(std::io::Error::from_raw_os_error(code as i32));
+    }
+
+    // The returned size includes the terminating null character.
+    buf.truncate(buf_size as usize / std::mem::size_of::<u16>());
+    while buf.last() == Some(&0) {
+        buf.pop();
+    }
+
+    let value = String::from_utf16(&buf)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    if value.is_empty() {
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+
+    Ok(value)
+}
+
 /// Returns the CPU architecture of the currently running operating system.
 pub fn arch() -> std::io::Result<String> {
     use windows_sys::Win32::System::SystemInformation::*;
@@ -288,3 +377,166 @@ fn computer_name(
     use std::os::windows::ffi::OsStringExt as _;
     Ok(std::ffi::OsString::from_wide(&buf))
 }
+
+/// Returns an iterator over local user accounts available on the system.
+pub fn users() -> std::io::Result<impl Iterator<Item = crate::os::User>> {
+    /// Profile folders that do not correspond to any real user account.
+    const NON_USER_PROFILES: [&str; 4] = [
+        "All Users", "Default", "Default User", "Public",
+    ];
+
+    let system_drive = std::env::var_os("SystemDrive")
+        .unwrap_or_else(|| std::ffi::OsString::from("C:"));
+
+    let mut profiles_path = std::path::PathBuf::from(system_drive);
+    profiles_path.push("\\Users");
+
+    let mut users = Vec::new();
+    for entry in std::fs::read_dir(profiles_path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        if NON_USER_PROFILES.iter().any(|profile| name == *profile) {
+            continue;
+        }
+
+        users.push(crate::os::User {
+            name,
+            home: entry.path(),
+        });
+    }
+
+    Ok(users.into_iter())
+}
+
+/// Returns the unique identifier of the currently running machine.
+pub fn machine_id() -> std::io::Result<crate::os::MachineId> {
+    use windows_sys::{w, Win32::System::Registry::*};
+
+    // We explicitly ask for the 64-bit view of the registry as otherwise 32-bit
+    // builds of the agent would be redirected to `Wow6432Node` where there is
+    // no such value.
+    let flags = RRF_RT_REG_SZ | RRF_SUBKEY_WOW6464KEY;
+
+    let mut buf_size = 0u32;
+
+    // SAFETY: We call the function with null buffer to query the size of the
+    // value as described in the docs [1]. The result is verified below.
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows/win32/api/winreg/nf-winreg-reggetvaluew
+    let code = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Cryptography"),
+            w!("MachineGuid"),
+            flags,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut buf_size,
+        )
+    };
+    if code != windows_sys::Win32::Foundation::NO_ERROR {
+        return Err(std::io::Error::from_raw_os_error(code as i32));
+    }
+
+    let mut buf = vec![0u16; buf_size as usize / std::mem::size_of::<u16>()];
+
+    // SAFETY: We pass a buffer of the size returned by the previous call. The
+    // result is verified below.
+    let code = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Cryptography"),
+            w!("MachineGuid"),
+            flags,
+            std::ptr::null_mut(),
+            buf.as_mut_ptr() as *mut std::ffi::c_void,
+            &mut buf_size,
+        )
+    };
+    if code != windows_sys::Win32::Foundation::NO_ERROR {
+        return Err(std::io::Error::from_raw_os_error(code as i32));
+    }
+
+    // The returned size includes the terminating null character.
+    buf.truncate(buf_size as usize / std::mem::size_of::<u16>());
+    while buf.last() == Some(&0) {
+        buf.pop();
+    }
+
+    let value = String::from_utf16(&buf)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    if value.is_empty() {
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+
+    Ok(crate::os::MachineId {
+        value,
+        source: crate::os::MachineIdSource::MachineGuid,
+    })
+}
+
+/// Returns the name of the timezone the system is configured with.
+pub fn timezone() -> std::io::Result<Option<String>> {
+    let (info, _) = dynamic_time_zone_information()?;
+
+    let len = info.TimeZoneKeyName.iter()
+        .position(|char| *char == 0)
+        .unwrap_or(info.TimeZoneKeyName.len());
+    if len == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf16_lossy(&info.TimeZoneKeyName[..len])))
+}
+
+/// Returns the offset of the local time from UTC (in seconds) now in effect.
+pub fn utc_offset() -> std::io::Result<i32> {
+    // Value returned when daylight saving time is in effect [1].
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows/win32/api/timezoneapi/nf-timezoneapi-getdynamictimezoneinformation
+    const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+
+    let (info, id) = dynamic_time_zone_information()?;
+
+    // Biases are in minutes and are the difference between UTC and the local
+    // time (so they are negative east of UTC).
+    let bias = match id {
+        TIME_ZONE_ID_DAYLIGHT => info.Bias + info.DaylightBias,
+        _ => info.Bias + info.StandardBias,
+    };
+
+    Ok(-bias * 60)
+}
+
+/// Returns the current timezone settings of the system.
+///
+/// Along with the settings, the identifier of the timezone period (standard or
+/// daylight saving time) currently in effect is returned.
+fn dynamic_time_zone_information() -> std::io::Result<(
+    windows_sys::Win32::System::Time::DYNAMIC_TIME_ZONE_INFORMATION,
+    u32,
+)> {
+    use windows_sys::Win32::System::Time::*;
+
+    // SAFETY: `DYNAMIC_TIME_ZONE_INFORMATION` is a plain C struct for which
+    // all-zeros is a valid value (it is overwritten by the call anyway).
+    let mut info = unsafe {
+        std::mem::zeroed::<DYNAMIC_TIME_ZONE_INFORMATION>()
+    };
+
+    // SAFETY: We pass a valid pointer to the struct we have just allocated. The
+    // result is verified below.
+    let id = unsafe {
+        GetDynamicTimeZoneInformation(&mut info)
+    };
+    if id == TIME_ZONE_ID_INVALID {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok((info, id))
+}
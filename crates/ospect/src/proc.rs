@@ -4,13 +4,13 @@
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
 
 #[cfg(target_os = "linux")]
-mod linux;
+pub mod linux;
 
 #[cfg(target_os = "macos")]
 mod macos;
 
 #[cfg(target_os = "windows")]
-mod windows;
+pub mod windows;
 
 mod sys {
     #[cfg(target_os = "linux")]
@@ -3,6 +3,16 @@
 // Use of this source code is governed by an MIT-style license that can be found
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
 
+//! Linux-specific process inspection functionalities.
+//!
+//! Information about processes on Linux is available through the `/proc`
+//! filesystem. Processes can exit at any moment, so their entries can vanish
+//! between listing the `/proc` directory and reading a particular file. The
+//! functions in this module take care of recognizing such cases, so that the
+//! callers can skip such processes rather than treating them as failures.
+
+use std::path::PathBuf;
+
 /// Returns an iterator yielding identifiers of all processes on the system.
 pub fn ids() -> std::io::Result<impl Iterator<Item = std::io::Result<u32>>> {
     Ids::new()
@@ -70,3 +80,1204 @@ impl Iterator for Ids {
 
     }
 }
+
+/// Returns the path to the given entry of the process with the specified pid.
+///
+/// # Examples
+///
+/// ```
+/// let path = ospect::proc::linux::entry_path(1337, "status");
+/// assert_eq!(path, std::path::Path::new("/proc/1337/status"));
+/// ```
+pub fn entry_path(pid: u32, entry: &str) -> PathBuf {
+    PathBuf::from(format!("/proc/{pid}/{entry}"))
+}
+
+/// Opens the given entry (e.g. `status`) of the process with the specified pid.
+///
+/// If the process has exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the entry cannot be opened for any
+/// other reason (e.g. insufficient permissions).
+///
+/// # Examples
+///
+/// ```
+/// let file = ospect::proc::linux::open_entry(std::process::id(), "status")
+///     .unwrap();
+///
+/// assert!(file.is_some());
+/// ```
+pub fn open_entry(pid: u32, entry: &str) -> std::io::Result<Option<std::fs::File>> {
+    skip_exited(std::fs::File::open(entry_path(pid, entry)))
+}
+
+/// Reads the given entry (e.g. `cmdline`) of the process with the specified pid.
+///
+/// If the process has exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the entry cannot be read for any
+/// other reason (e.g. insufficient permissions).
+pub fn read_entry(pid: u32, entry: &str) -> std::io::Result<Option<Vec<u8>>> {
+    skip_exited(std::fs::read(entry_path(pid, entry)))
+}
+
+/// Returns the path to the executable of the process with the specified pid.
+///
+/// If the executable was deleted after the process was started, the kernel
+/// appends ` (deleted)` to the path. If the process has exited (or is a kernel
+/// thread with no executable), `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the executable cannot be resolved for
+/// any other reason (e.g. insufficient permissions).
+///
+/// # Examples
+///
+/// ```
+/// let exe = ospect::proc::linux::exe(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert_eq!(exe, std::env::current_exe().unwrap());
+/// ```
+pub fn exe(pid: u32) -> std::io::Result<Option<PathBuf>> {
+    skip_exited(std::fs::read_link(entry_path(pid, "exe")))
+}
+
+/// Returns the command-line arguments of the process with the specified pid.
+///
+/// The first argument is usually (but not necessarily) the name with which the
+/// executable was invoked. Kernel threads and zombies have no arguments. If the
+/// process has exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the arguments cannot be read for any
+/// other reason (e.g. insufficient permissions).
+pub fn cmdline(pid: u32) -> std::io::Result<Option<Vec<std::ffi::OsString>>> {
+    let cmdline = match read_entry(pid, "cmdline")? {
+        Some(cmdline) => cmdline,
+        None => return Ok(None),
+    };
+
+    Ok(Some(parse_cmdline(&cmdline)))
+}
+
+/// Parses command-line arguments in the `/proc/<pid>/cmdline` format.
+///
+/// Arguments are separated (and terminated) by null bytes.
+fn parse_cmdline(cmdline: &[u8]) -> Vec<std::ffi::OsString> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let cmdline = cmdline.strip_suffix(b"\0").unwrap_or(cmdline);
+    if cmdline.is_empty() {
+        return Vec::new();
+    }
+
+    cmdline.split(|byte| *byte == 0)
+        .map(|arg| std::ffi::OsStr::from_bytes(arg).to_os_string())
+        .collect()
+}
+
+/// Returns the environment variables of the process with the specified pid.
+///
+/// Note that this is the environment the process was started with: changes
+/// made by the process itself later are not reflected. If the process has
+/// exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the environment cannot be read for
+/// any other reason (e.g. insufficient permissions).
+///
+/// # Examples
+///
+/// ```
+/// let environ = ospect::proc::linux::environ(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert_eq!(environ, std::env::vars_os().collect::<Vec<_>>());
+/// ```
+pub fn environ(pid: u32) -> std::io::Result<Option<Vec<(std::ffi::OsString, std::ffi::OsString)>>> {
+    let environ = match read_entry(pid, "environ")? {
+        Some(environ) => environ,
+        None => return Ok(None),
+    };
+
+    Ok(Some(parse_environ(&environ)))
+}
+
+/// Parses environment variables in the `/proc/<pid>/environ` format.
+///
+/// Variables are `NAME=VALUE` pairs separated (and terminated) by null bytes.
+/// Entries without the `=` sign are treated as variables with empty values.
+fn parse_environ(environ: &[u8]) -> Vec<(std::ffi::OsString, std::ffi::OsString)> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    environ.split(|byte| *byte == 0)
+        .filter(|var| !var.is_empty())
+        .map(|var| {
+            let (name, value) = match var.iter().position(|byte| *byte == b'=') {
+                Some(index) => (&var[..index], &var[index + 1..]),
+                None => (var, &b""[..]),
+            };
+
+            let name = std::ffi::OsStr::from_bytes(name).to_os_string();
+            let value = std::ffi::OsStr::from_bytes(value).to_os_string();
+            (name, value)
+        })
+        .collect()
+}
+
+/// Returns the identifier of the mount namespace of the specified process.
+///
+/// The identifier is the inode number of the namespace (as reported by the
+/// `/proc/<pid>/ns/mnt` symlink), so processes sharing the same mount namespace
+/// yield the same identifier. If the process has exited, `None` is returned
+/// instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the namespace cannot be inspected for
+/// any other reason (e.g. insufficient permissions).
+///
+/// # Examples
+///
+/// ```
+/// let mnt_ns = ospect::proc::linux::mount_namespace(std::process::id())
+///     .unwrap();
+///
+/// assert!(mnt_ns.is_some());
+/// ```
+pub fn mount_namespace(pid: u32) -> std::io::Result<Option<u64>> {
+    let link = match skip_exited(std::fs::read_link(entry_path(pid, "ns/mnt")))? {
+        Some(link) => link,
+        None => return Ok(None),
+    };
+
+    // The symlink target is not a real path but a string like `mnt:[4026531841]`
+    // where the number in brackets is the inode of the namespace.
+    let inode = link.to_str()
+        .and_then(|link| link.strip_prefix("mnt:["))
+        .and_then(|link| link.strip_suffix(']'))
+        .and_then(|inode| inode.parse::<u64>().ok())
+        .ok_or(std::io::ErrorKind::InvalidData)?;
+
+    Ok(Some(inode))
+}
+
+/// Names of Linux capabilities indexed by their numbers.
+///
+/// See `include/uapi/linux/capability.h` in the kernel sources for the list.
+const CAPABILITY_NAMES: [&str; 41] = [
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+/// Returns names of capabilities in the given capability set.
+///
+/// Capabilities unknown to this function (e.g. introduced by kernels newer
+/// than this code) are named using their numbers, the same way as `libcap`
+/// does it.
+///
+/// # Examples
+///
+/// ```
+/// let names = ospect::proc::linux::capability_names(1 << 1 | 1 << 13);
+/// assert_eq!(names, vec!["cap_dac_override", "cap_net_raw"]);
+/// ```
+pub fn capability_names(set: u64) -> Vec<String> {
+    (0..u64::BITS)
+        .filter(|index| set & (1 << index) != 0)
+        .map(|index| match CAPABILITY_NAMES.get(index as usize) {
+            Some(name) => String::from(*name),
+            None => format!("cap_{index}"),
+        })
+        .collect()
+}
+
+/// Status of a process as reported by the `/proc/<pid>/status` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// Name of the executable of the process (possibly truncated).
+    pub name: std::ffi::OsString,
+    /// Identifier of the parent process (0 for processes without one).
+    pub ppid: u32,
+    /// Capability sets of the process.
+    pub capabilities: Capabilities,
+    /// Effective identifier of the user owning the process (if reported).
+    pub uid: Option<u32>,
+    /// Effective identifier of the group owning the process (if reported).
+    pub gid: Option<u32>,
+}
+
+/// Capability sets of a process (as bitmasks indexed by capability numbers).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Capabilities preserved across `execve`.
+    pub inheritable: u64,
+    /// Capabilities that the process can make effective.
+    pub permitted: u64,
+    /// Capabilities used by the kernel for permission checks.
+    pub effective: u64,
+}
+
+/// Returns the status of the process with the specified pid.
+///
+/// If the process has exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the status cannot be read for any
+/// other reason (e.g. insufficient permissions) or if it is malformed.
+///
+/// # Examples
+///
+/// ```
+/// let status = ospect::proc::linux::status(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert!(!status.name.is_empty());
+/// ```
+pub fn status(pid: u32) -> std::io::Result<Option<Status>> {
+    match open_entry(pid, "status")? {
+        Some(file) => parse_status(std::io::BufReader::new(file)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses the contents of a `/proc/<pid>/status` file.
+///
+/// # Errors
+///
+/// This function will return an error if the contents cannot be read or if
+/// the name or any of the capability sets is missing or malformed.
+pub fn parse_status<R: std::io::BufRead>(mut status: R) -> std::io::Result<Status> {
+    use std::io::ErrorKind::InvalidData;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    fn parse_capabilities(value: &[u8]) -> std::io::Result<u64> {
+        let value = std::str::from_utf8(value)
+            .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+        u64::from_str_radix(value.trim(), 16)
+            .map_err(|error| std::io::Error::new(InvalidData, error))
+    }
+
+    // Identifiers are listed in the real, effective, saved and filesystem
+    // order, we are interested only in the effective one.
+    fn parse_effective_id(value: &[u8]) -> std::io::Result<u32> {
+        let value = std::str::from_utf8(value)
+            .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+        value.split_whitespace().nth(1)
+            .ok_or_else(|| std::io::Error::new(InvalidData, "missing effective id"))?
+            .parse()
+            .map_err(|error| std::io::Error::new(InvalidData, error))
+    }
+
+    let mut name = None;
+    let mut ppid = None;
+    let mut inheritable = None;
+    let mut permitted = None;
+    let mut effective = None;
+    let mut uid = None;
+    let mut gid = None;
+
+    // Process names can contain arbitrary bytes (except for newlines which
+    // are escaped by the kernel), so we cannot read lines as strings.
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if status.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let Some(separator) = line.iter().position(|byte| *byte == b':') else {
+            continue;
+        };
+        let (key, value) = (&line[..separator], &line[separator + 1..]);
+
+        match key {
+            b"Name" => {
+                let value = value.strip_prefix(b"\t").unwrap_or(value);
+                name = Some(std::ffi::OsStr::from_bytes(value).to_os_string());
+            }
+            b"PPid" => {
+                let value = std::str::from_utf8(value)
+                    .map_err(|error| std::io::Error::new(InvalidData, error))?;
+                let value = value.trim().parse()
+                    .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+                ppid = Some(value);
+            }
+            b"CapInh" => inheritable = Some(parse_capabilities(value)?),
+            b"CapPrm" => permitted = Some(parse_capabilities(value)?),
+            b"CapEff" => effective = Some(parse_capabilities(value)?),
+            b"Uid" => uid = Some(parse_effective_id(value)?),
+            b"Gid" => gid = Some(parse_effective_id(value)?),
+            _ => (),
+        }
+    }
+
+    let missing = |field: &str| {
+        std::io::Error::new(InvalidData, format!("missing '{field}' status field"))
+    };
+
+    Ok(Status {
+        name: name.ok_or_else(|| missing("Name"))?,
+        ppid: ppid.ok_or_else(|| missing("PPid"))?,
+        capabilities: Capabilities {
+            inheritable: inheritable.ok_or_else(|| missing("CapInh"))?,
+            permitted: permitted.ok_or_else(|| missing("CapPrm"))?,
+            effective: effective.ok_or_else(|| missing("CapEff"))?,
+        },
+        uid,
+        gid,
+    })
+}
+
+/// Returns the time at which the process with the specified pid was started.
+///
+/// Identifiers of processes are reused, so the start time (together with the
+/// pid) can be used to uniquely identify a process. If the process has exited,
+/// `None` is returned instead of an error.
+///
+/// Note that the kernel reports the start time in clock ticks since boot, so
+/// its precision is limited (usually to 10 milliseconds).
+///
+/// # Errors
+///
+/// This function will return an error if the start time cannot be read for any
+/// other reason or if the `/proc` entries are malformed.
+///
+/// # Examples
+///
+/// ```
+/// let start_time = ospect::proc::linux::start_time(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert!(start_time <= std::time::SystemTime::now());
+/// ```
+pub fn start_time(pid: u32) -> std::io::Result<Option<std::time::SystemTime>> {
+    let stat = match read_entry(pid, "stat")? {
+        Some(stat) => stat,
+        None => return Ok(None),
+    };
+
+    let ticks = parse_stat_start_ticks(&stat)?;
+
+    // SAFETY: `sysconf` is always safe to call, it just returns -1 for invalid
+    // names (which `_SC_CLK_TCK` is not).
+    let ticks_per_sec = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks_per_sec if ticks_per_sec > 0 => ticks_per_sec as u64,
+        _ => return Err(std::io::Error::last_os_error()),
+    };
+
+    let since_boot = std::time::Duration::from_secs(ticks / ticks_per_sec) +
+        std::time::Duration::from_nanos(ticks % ticks_per_sec * 1_000_000_000 / ticks_per_sec);
+
+    Ok(Some(boot_time()? + since_boot))
+}
+
+/// Returns the resident set size (in bytes) of the process with the specified pid.
+///
+/// If the process has exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the memory usage of the process
+/// cannot be read for any other reason.
+///
+/// # Examples
+///
+/// ```
+/// let rss = ospect::proc::linux::resident_set_size(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert!(rss > 0);
+/// ```
+pub fn resident_set_size(pid: u32) -> std::io::Result<Option<u64>> {
+    use std::io::ErrorKind::InvalidData;
+
+    let statm = match read_entry(pid, "statm")? {
+        Some(statm) => statm,
+        None => return Ok(None),
+    };
+
+    // The second field is the number of resident pages [1].
+    //
+    // [1]: https://man7.org/linux/man-pages/man5/proc_pid_statm.5.html
+    let pages = statm
+        .split(|byte| *byte == b' ')
+        .nth(1)
+        .ok_or(InvalidData)?;
+    let pages = std::str::from_utf8(pages)
+        .map_err(|error| std::io::Error::new(InvalidData, error))?
+        .parse::<u64>()
+        .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+    // SAFETY: `sysconf` is always safe to call, it just returns -1 for invalid
+    // names (which `_SC_PAGESIZE` is not).
+    let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        page_size if page_size > 0 => page_size as u64,
+        _ => return Err(std::io::Error::last_os_error()),
+    };
+
+    Ok(Some(pages * page_size))
+}
+
+/// Parses the start time (in clock ticks since boot) from `/proc/<pid>/stat`.
+fn parse_stat_start_ticks(stat: &[u8]) -> std::io::Result<u64> {
+    use std::io::ErrorKind::InvalidData;
+
+    // The second field is the name of the process in parentheses which can
+    // contain spaces and parentheses itself, so we have to skip until the last
+    // closing parenthesis first.
+    let fields_start = stat.iter().rposition(|byte| *byte == b')')
+        .ok_or(InvalidData)?;
+
+    // Start time is the 22nd field, so it is the 20th after the name.
+    let field = stat[fields_start + 1..]
+        .split(|byte| *byte == b' ')
+        .filter(|field| !field.is_empty())
+        .nth(19)
+        .ok_or(InvalidData)?;
+
+    std::str::from_utf8(field)
+        .map_err(|error| std::io::Error::new(InvalidData, error))?
+        .parse()
+        .map_err(|error| std::io::Error::new(InvalidData, error))
+}
+
+/// Returns the time at which the system was booted.
+fn boot_time() -> std::io::Result<std::time::SystemTime> {
+    use std::io::BufRead as _;
+    use std::io::ErrorKind::InvalidData;
+
+    let file = std::fs::File::open("/proc/stat")?;
+
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line?;
+
+        if let Some(btime) = line.strip_prefix("btime ") {
+            let btime = btime.trim().parse::<u64>()
+                .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+            return Ok(std::time::UNIX_EPOCH + std::time::Duration::from_secs(btime));
+        }
+    }
+
+    Err(std::io::Error::new(InvalidData, "missing boot time"))
+}
+
+/// Open file descriptor of a process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fd {
+    /// Number of the file descriptor.
+    pub fd: u32,
+    /// What the file descriptor refers to.
+    pub target: FdTarget,
+}
+
+/// Object that a file descriptor refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FdTarget {
+    /// File (or directory) at the given path.
+    ///
+    /// Note that the path can have a ` (deleted)` suffix if the file has been
+    /// unlinked since being opened.
+    Path(PathBuf),
+    /// Socket with the given inode number.
+    Socket(u64),
+    /// Pipe with the given inode number.
+    Pipe(u64),
+    /// Anonymous inode of the given type (e.g. `[eventfd]` or `inotify`).
+    AnonInode(std::ffi::OsString),
+    /// Some other kind of object (e.g. a namespace), given by the raw target.
+    Other(std::ffi::OsString),
+}
+
+impl FdTarget {
+
+    /// Parses the target of a `/proc/<pid>/fd/<fd>` symlink.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ospect::proc::linux::FdTarget;
+    ///
+    /// assert_eq!(FdTarget::parse("socket:[1337]".as_ref()), FdTarget::Socket(1337));
+    /// assert_eq!(FdTarget::parse("/tmp/foo".as_ref()), FdTarget::Path("/tmp/foo".into()));
+    /// ```
+    pub fn parse(link: &std::path::Path) -> FdTarget {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let bytes = link.as_os_str().as_bytes();
+
+        if bytes.starts_with(b"/") {
+            return FdTarget::Path(link.to_path_buf());
+        }
+        if let Some(inode) = parse_bracketed_inode(bytes, b"socket:") {
+            return FdTarget::Socket(inode);
+        }
+        if let Some(inode) = parse_bracketed_inode(bytes, b"pipe:") {
+            return FdTarget::Pipe(inode);
+        }
+        if let Some(kind) = bytes.strip_prefix(b"anon_inode:") {
+            let kind = std::ffi::OsStr::from_bytes(kind);
+            return FdTarget::AnonInode(kind.to_os_string());
+        }
+
+        FdTarget::Other(link.as_os_str().to_os_string())
+    }
+}
+
+/// Parses strings like `socket:[1337]` with the given prefix into an inode.
+fn parse_bracketed_inode(bytes: &[u8], prefix: &[u8]) -> Option<u64> {
+    let inode = bytes.strip_prefix(prefix)?
+        .strip_prefix(b"[")?
+        .strip_suffix(b"]")?;
+
+    std::str::from_utf8(inode).ok()?.parse().ok()
+}
+
+/// Returns open file descriptors of the specified process.
+///
+/// Descriptors are read from the `/proc/<pid>/fd` directory. Descriptors that
+/// get closed while they are being listed are skipped. If the process has
+/// exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the descriptors cannot be listed for
+/// any other reason (e.g. insufficient permissions to inspect the process).
+///
+/// # Examples
+///
+/// ```
+/// let fds = ospect::proc::linux::fds(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert!(!fds.is_empty());
+/// ```
+pub fn fds(pid: u32) -> std::io::Result<Option<Vec<Fd>>> {
+    let entries = match skip_exited(std::fs::read_dir(entry_path(pid, "fd")))? {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+
+    let mut fds = Vec::new();
+
+    for entry in entries {
+        let entry = match skip_exited(entry)? {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let fd = entry.file_name().to_str()
+            .and_then(|fd| fd.parse::<u32>().ok())
+            .ok_or(std::io::ErrorKind::InvalidData)?;
+
+        // The descriptor might have been closed since we listed the directory
+        // (which is especially common for descriptors of short-lived files).
+        let link = match skip_exited(std::fs::read_link(entry.path()))? {
+            Some(link) => link,
+            None => continue,
+        };
+
+        fds.push(Fd {
+            fd,
+            target: FdTarget::parse(&link),
+        });
+    }
+
+    fds.sort_by_key(|fd| fd.fd);
+
+    Ok(Some(fds))
+}
+
+/// Memory mapping of a process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    /// Address at which the mapping starts.
+    pub start: u64,
+    /// Address right past the end of the mapping.
+    pub end: u64,
+    /// Whether the mapped memory can be read.
+    pub readable: bool,
+    /// Whether the mapped memory can be written to.
+    pub writable: bool,
+    /// Whether the mapped memory can be executed.
+    pub executable: bool,
+    /// Whether the mapping is shared (rather than private copy-on-write).
+    pub shared: bool,
+    /// Offset into the mapped file at which the mapping starts.
+    pub offset: u64,
+    /// Inode number of the mapped file (zero for anonymous mappings).
+    pub inode: u64,
+    /// What is mapped into the memory.
+    pub target: MappingTarget,
+}
+
+/// Object that is mapped into the memory of a process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MappingTarget {
+    /// File at the given path (e.g. a shared library or the executable).
+    ///
+    /// Note that the path can have a ` (deleted)` suffix if the file has been
+    /// unlinked since being mapped.
+    Path(PathBuf),
+    /// Anonymous memory not backed by any file.
+    Anonymous,
+    /// Memory region with a name assigned by the kernel (e.g. `[heap]`).
+    Pseudo(std::ffi::OsString),
+}
+
+/// Returns memory mappings of the specified process.
+///
+/// Mappings are read from the `/proc/<pid>/maps` file. If the process has
+/// exited, `None` is returned instead of an error.
+///
+/// # Errors
+///
+/// This function will return an error if the mappings cannot be read for any
+/// other reason (e.g. insufficient permissions to inspect the process) or if
+/// they are malformed.
+///
+/// # Examples
+///
+/// ```
+/// let maps = ospect::proc::linux::maps(std::process::id())
+///     .unwrap().unwrap();
+///
+/// assert!(!maps.is_empty());
+/// ```
+pub fn maps(pid: u32) -> std::io::Result<Option<Vec<Mapping>>> {
+    match open_entry(pid, "maps")? {
+        Some(file) => parse_maps(std::io::BufReader::new(file)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses the contents of a `/proc/<pid>/maps` file.
+///
+/// # Errors
+///
+/// This function will return an error if the contents cannot be read or if any
+/// of the lines is malformed.
+///
+/// # Examples
+///
+/// ```
+/// use ospect::proc::linux::MappingTarget;
+///
+/// let maps = b"7f0000000000-7f0000001000 r-xp 00001000 08:01 1337 /usr/lib/libfoo.so\n";
+/// let maps = ospect::proc::linux::parse_maps(&maps[..])
+///     .unwrap();
+///
+/// assert_eq!(maps[0].start, 0x7f0000000000);
+/// assert!(maps[0].executable);
+/// assert_eq!(maps[0].target, MappingTarget::Path("/usr/lib/libfoo.so".into()));
+/// ```
+pub fn parse_maps<R: std::io::BufRead>(mut maps: R) -> std::io::Result<Vec<Mapping>> {
+    use std::io::ErrorKind::InvalidData;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    fn parse_hex(value: &[u8]) -> std::io::Result<u64> {
+        let value = std::str::from_utf8(value)
+            .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+        u64::from_str_radix(value, 16)
+            .map_err(|error| std::io::Error::new(InvalidData, error))
+    }
+
+    let mut result = Vec::new();
+
+    // Paths of mapped files can contain arbitrary bytes (including spaces),
+    // so we cannot split the lines as strings.
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if maps.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+
+        // The path is the only field that can contain spaces and it is the
+        // last one, so we split off the first five fields and the rest is the
+        // path (padded with spaces by the kernel).
+        let mut fields = line.splitn(6, |byte| *byte == b' ');
+        let mut field = || fields.next()
+            .ok_or_else(|| std::io::Error::new(InvalidData, "missing mapping field"));
+
+        let (start, end) = {
+            let range = field()?;
+            let separator = range.iter().position(|byte| *byte == b'-')
+                .ok_or_else(|| std::io::Error::new(InvalidData, "malformed mapping range"))?;
+
+            (parse_hex(&range[..separator])?, parse_hex(&range[separator + 1..])?)
+        };
+
+        let perms = field()?;
+        if perms.len() != 4 {
+            return Err(std::io::Error::new(InvalidData, "malformed mapping permissions"));
+        }
+
+        let offset = parse_hex(field()?)?;
+        let _device = field()?;
+
+        let inode = std::str::from_utf8(field()?)
+            .map_err(|error| std::io::Error::new(InvalidData, error))?
+            .parse()
+            .map_err(|error| std::io::Error::new(InvalidData, error))?;
+
+        let path = fields.next().unwrap_or(b"").trim_ascii_start();
+        let target = if path.is_empty() {
+            MappingTarget::Anonymous
+        } else if path.starts_with(b"/") {
+            MappingTarget::Path(std::ffi::OsStr::from_bytes(path).into())
+        } else {
+            MappingTarget::Pseudo(std::ffi::OsStr::from_bytes(path).to_os_string())
+        };
+
+        result.push(Mapping {
+            start,
+            end,
+            readable: perms[0] == b'r',
+            writable: perms[1] == b'w',
+            executable: perms[2] == b'x',
+            shared: perms[3] == b's',
+            offset,
+            inode,
+            target,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Converts errors indicating that a process has exited into `None`.
+///
+/// Depending on the moment at which the process exits, reading its entries in
+/// `/proc` fails either with `ENOENT` (if the entry is gone already) or with
+/// `ESRCH` (if the process is gone but the entry was opened before). All other
+/// errors are propagated.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Error, ErrorKind};
+///
+/// let result = ospect::proc::linux::skip_exited::<()>({
+///     Err(Error::from(ErrorKind::NotFound))
+/// });
+/// assert!(result.unwrap().is_none());
+///
+/// let result = ospect::proc::linux::skip_exited::<()>({
+///     Err(Error::from(ErrorKind::PermissionDenied))
+/// });
+/// assert!(result.is_err());
+/// ```
+pub fn skip_exited<T>(result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if is_exited_error(&error) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Checks whether the error indicates that the process has exited.
+fn is_exited_error(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::NotFound ||
+    error.raw_os_error() == Some(libc::ESRCH)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn open_entry_self() {
+        let file = open_entry(std::process::id(), "status")
+            .unwrap();
+
+        assert!(file.is_some());
+    }
+
+    #[test]
+    fn exe_self() {
+        let exe = exe(std::process::id())
+            .unwrap().unwrap();
+
+        assert_eq!(exe, std::env::current_exe().unwrap());
+    }
+
+    #[test]
+    fn cmdline_self() {
+        let cmdline = cmdline(std::process::id())
+            .unwrap().unwrap();
+
+        assert_eq!(cmdline, std::env::args_os().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn parse_environ_vars() {
+        assert_eq!(parse_environ(b"FOO=bar\0BAZ=a=b\0EMPTY=\0NOVALUE\0"), vec![
+            ("FOO".into(), "bar".into()),
+            ("BAZ".into(), "a=b".into()),
+            ("EMPTY".into(), "".into()),
+            ("NOVALUE".into(), "".into()),
+        ]);
+    }
+
+    #[test]
+    fn parse_cmdline_empty() {
+        assert!(parse_cmdline(b"").is_empty());
+    }
+
+    #[test]
+    fn parse_cmdline_empty_args() {
+        assert_eq!(parse_cmdline(b"/bin/foo\0\0bar\0"), vec!["/bin/foo", "", "bar"]);
+    }
+
+    #[test]
+    fn mount_namespace_self() {
+        let mnt_ns = mount_namespace(std::process::id())
+            .unwrap();
+
+        let link = std::fs::read_link("/proc/self/ns/mnt")
+            .unwrap();
+        assert_eq!(link.to_str(), Some(format!("mnt:[{}]", mnt_ns.unwrap()).as_str()));
+    }
+
+    #[test]
+    fn read_entry_self() {
+        let status = read_entry(std::process::id(), "status")
+            .unwrap().unwrap();
+
+        let status = String::from_utf8(status)
+            .unwrap();
+        assert!(status.contains(&format!("Pid:\t{}", std::process::id())));
+    }
+
+    #[test]
+    fn read_entry_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        // Once the child is reaped, its pid no longer exists (unless it gets
+        // reused in the meantime which is very unlikely).
+        child.wait()
+            .unwrap();
+
+        assert!(read_entry(pid, "status").unwrap().is_none());
+    }
+
+    #[test]
+    fn status_self() {
+        let status = status(std::process::id())
+            .unwrap().unwrap();
+
+        let comm = std::fs::read("/proc/self/comm")
+            .unwrap();
+        assert_eq!(status.name.as_encoded_bytes(), comm.trim_ascii_end());
+    }
+
+    #[test]
+    fn status_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        assert!(status(pid).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_status_capabilities() {
+        let status = b"\
+Name:\tping
+Umask:\t0022
+State:\tS (sleeping)
+Pid:\t1337
+PPid:\t42
+CapInh:\t0000000000000000
+CapPrm:\t0000000000003000
+CapEff:\t0000000000002000
+CapBnd:\t000001ffffffffff
+CapAmb:\t0000000000000000
+";
+
+        let status = parse_status(&status[..])
+            .unwrap();
+
+        assert_eq!(status.name, "ping");
+        assert_eq!(status.ppid, 42);
+        assert_eq!(status.capabilities, Capabilities {
+            inheritable: 0,
+            permitted: 1 << 12 | 1 << 13,
+            effective: 1 << 13,
+        });
+        assert_eq! {
+            capability_names(status.capabilities.permitted),
+            vec!["cap_net_admin", "cap_net_raw"]
+        };
+        assert_eq! {
+            capability_names(status.capabilities.effective),
+            vec!["cap_net_raw"]
+        };
+    }
+
+    #[test]
+    fn parse_status_missing_capabilities() {
+        let status = b"Name:\tfoo\nPPid:\t1\nCapInh:\t0000000000000000\n";
+
+        assert!(parse_status(&status[..]).is_err());
+    }
+
+    #[test]
+    fn parse_status_ids() {
+        let status = b"\
+Name:\tsudo
+PPid:\t1
+Uid:\t1000\t0\t0\t0
+Gid:\t1000\t1000\t1000\t1000
+CapInh:\t0000000000000000
+CapPrm:\t0000000000000000
+CapEff:\t0000000000000000
+";
+
+        let status = parse_status(&status[..])
+            .unwrap();
+
+        assert_eq!(status.uid, Some(0));
+        assert_eq!(status.gid, Some(1000));
+    }
+
+    #[test]
+    fn status_self_ppid() {
+        let status = status(std::process::id())
+            .unwrap().unwrap();
+
+        assert_eq!(status.ppid, std::os::unix::process::parent_id());
+    }
+
+    #[test]
+    fn start_time_child() {
+        let before = std::time::SystemTime::now();
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+
+        let start_time = start_time(child.id())
+            .unwrap().unwrap();
+
+        child.kill()
+            .unwrap();
+        child.wait()
+            .unwrap();
+
+        // Boot time has a precision of a second, so we allow some slack.
+        let slack = std::time::Duration::from_secs(2);
+        assert!(start_time + slack >= before);
+        assert!(start_time <= std::time::SystemTime::now() + slack);
+    }
+
+    #[test]
+    fn parse_stat_start_ticks_name_with_spaces() {
+        let stat = b"1337 (foo) (bar baz) S 1 1337 1337 0 -1 4194560 100 0 0 0 \
+                     1 2 0 0 20 0 1 0 123456 1000 100";
+
+        assert_eq!(parse_stat_start_ticks(stat).unwrap(), 123456);
+    }
+
+    #[test]
+    fn capability_names_unknown() {
+        assert_eq!(capability_names(0), Vec::<String>::new());
+        assert_eq!(capability_names(1 << 63), vec!["cap_63"]);
+    }
+
+    #[test]
+    fn fds_self_temp_file() {
+        use std::os::fd::AsRawFd as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        let fd = u32::try_from(tempfile.as_file().as_raw_fd())
+            .unwrap();
+
+        let fds = fds(std::process::id())
+            .unwrap().unwrap();
+
+        let target = fds.into_iter()
+            .find(|entry| entry.fd == fd)
+            .unwrap()
+            .target;
+
+        let path = tempfile.path().canonicalize()
+            .unwrap();
+        assert_eq!(target, FdTarget::Path(path));
+    }
+
+    #[test]
+    fn fds_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        assert!(fds(pid).unwrap().is_none());
+    }
+
+    #[test]
+    fn fd_target_parse() {
+        assert_eq!(FdTarget::parse("/foo/bar".as_ref()), FdTarget::Path("/foo/bar".into()));
+        assert_eq!(FdTarget::parse("socket:[42]".as_ref()), FdTarget::Socket(42));
+        assert_eq!(FdTarget::parse("pipe:[1337]".as_ref()), FdTarget::Pipe(1337));
+        assert_eq!(FdTarget::parse("anon_inode:[eventfd]".as_ref()), FdTarget::AnonInode("[eventfd]".into()));
+        assert_eq!(FdTarget::parse("net:[4026531840]".as_ref()), FdTarget::Other("net:[4026531840]".into()));
+        assert_eq!(FdTarget::parse("socket:[foo]".as_ref()), FdTarget::Other("socket:[foo]".into()));
+    }
+
+    #[test]
+    fn maps_self_exe() {
+        let exe = std::env::current_exe()
+            .unwrap();
+
+        let maps = maps(std::process::id())
+            .unwrap().unwrap();
+
+        assert! {
+            maps.iter().any(|mapping| mapping.target == MappingTarget::Path(exe.clone()))
+        };
+    }
+
+    #[test]
+    fn maps_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        assert!(maps(pid).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_maps_targets() {
+        let maps = b"\
+55d0c0a00000-55d0c0a21000 rw-p 00000000 00:00 0                          [heap]
+7f1c2a000000-7f1c2a021000 rw-p 00000000 00:00 0 \n\
+7f1c2b000000-7f1c2b1d6000 r-xs 00028000 fd:01 42                         /tmp/foo bar (deleted)
+";
+
+        let maps = parse_maps(&maps[..])
+            .unwrap();
+        assert_eq!(maps.len(), 3);
+
+        assert_eq!(maps[0].target, MappingTarget::Pseudo("[heap]".into()));
+        assert!(maps[0].readable && maps[0].writable && !maps[0].executable);
+
+        assert_eq!(maps[1].target, MappingTarget::Anonymous);
+        assert!(!maps[1].shared);
+
+        assert_eq!(maps[2].start, 0x7f1c2b000000);
+        assert_eq!(maps[2].end, 0x7f1c2b1d6000);
+        assert_eq!(maps[2].offset, 0x28000);
+        assert_eq!(maps[2].inode, 42);
+        assert!(maps[2].shared && maps[2].executable && !maps[2].writable);
+        assert_eq!(maps[2].target, MappingTarget::Path("/tmp/foo bar (deleted)".into()));
+    }
+
+    #[test]
+    fn parse_maps_malformed() {
+        assert!(parse_maps(&b"7f1c2b000000 r-xp 00000000 fd:01 42\n"[..]).is_err());
+        assert!(parse_maps(&b"0-1000 r-xp 00000000 fd:01 foo\n"[..]).is_err());
+    }
+
+    #[test]
+    fn skip_exited_enoent() {
+        let error = std::io::Error::from_raw_os_error(libc::ENOENT);
+
+        assert!(skip_exited::<()>(Err(error)).unwrap().is_none());
+    }
+
+    #[test]
+    fn skip_exited_esrch() {
+        let error = std::io::Error::from_raw_os_error(libc::ESRCH);
+
+        assert!(skip_exited::<()>(Err(error)).unwrap().is_none());
+    }
+
+    #[test]
+    fn skip_exited_eacces() {
+        let error = std::io::Error::from_raw_os_error(libc::EACCES);
+
+        assert!(skip_exited::<()>(Err(error)).is_err());
+    }
+
+    #[test]
+    fn skip_exited_ok() {
+        assert_eq!(skip_exited(Ok(42)).unwrap(), Some(42));
+    }
+}
@@ -86,8 +86,339 @@ impl Iterator for Ids {
     }
 }
 
+/// The maximum length of privilege names (in wide characters).
+const MAX_PRIVILEGE_NAME_LEN: usize = 256;
+
 /// The default capacity of the process identifiers buffer.
 const DEFAULT_PID_BUF_CAP: usize = 1024;
 
 /// The maximum capacity of the process identifiers buffer.
 const MAX_PID_BUF_CAP: usize = 16384;
+
+/// Mandatory integrity level of a Windows process.
+///
+/// See the [Mandatory Integrity Control][1] documentation for more details.
+///
+/// [1]: https://learn.microsoft.com/en-us/windows/win32/secauthz/mandatory-integrity-control
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IntegrityLevel {
+    /// Processes logged on anonymously.
+    Untrusted,
+    /// Sandboxed processes (e.g. browser renderers).
+    Low,
+    /// Processes of regular (non-elevated) users.
+    Medium,
+    /// Processes of elevated administrators.
+    High,
+    /// Services and other processes running as the system.
+    System,
+    /// Protected processes.
+    Protected,
+}
+
+impl IntegrityLevel {
+
+    /// Converts the relative identifier of the integrity label SID.
+    ///
+    /// Identifiers between the well-known ones (e.g. "medium plus") are mapped
+    /// to the closest lower level.
+    fn from_rid(rid: u32) -> IntegrityLevel {
+        // https://learn.microsoft.com/en-us/windows/win32/secauthz/well-known-sids
+        const SECURITY_MANDATORY_LOW_RID: u32 = 0x1000;
+        const SECURITY_MANDATORY_MEDIUM_RID: u32 = 0x2000;
+        const SECURITY_MANDATORY_HIGH_RID: u32 = 0x3000;
+        const SECURITY_MANDATORY_SYSTEM_RID: u32 = 0x4000;
+        const SECURITY_MANDATORY_PROTECTED_PROCESS_RID: u32 = 0x5000;
+
+        match rid {
+            SECURITY_MANDATORY_PROTECTED_PROCESS_RID.. => IntegrityLevel::Protected,
+            SECURITY_MANDATORY_SYSTEM_RID.. => IntegrityLevel::System,
+            SECURITY_MANDATORY_HIGH_RID.. => IntegrityLevel::High,
+            SECURITY_MANDATORY_MEDIUM_RID.. => IntegrityLevel::Medium,
+            SECURITY_MANDATORY_LOW_RID.. => IntegrityLevel::Low,
+            _ => IntegrityLevel::Untrusted,
+        }
+    }
+}
+
+/// Returns the mandatory integrity level of the process with the given pid.
+///
+/// # Errors
+///
+/// This function will return an error if the process does not exist or the
+/// caller is not allowed to query its access token.
+pub fn integrity_level(pid: u32) -> std::io::Result<IntegrityLevel> {
+    token_integrity_level(&open_token(pid)?)
+}
+
+/// Returns the SID of the user owning the process with the given pid.
+///
+/// The SID is returned in the string format (e.g. `S-1-5-18`).
+///
+/// # Errors
+///
+/// This function will return an error if the process does not exist or the
+/// caller is not allowed to query its access token.
+pub fn user_sid(pid: u32) -> std::io::Result<String> {
+    token_user_sid(&open_token(pid)?)
+}
+
+/// Returns names of privileges of the process with the given pid.
+///
+/// Both enabled and disabled privileges are returned (a process can enable
+/// any of the privileges present in its token at will). Names are returned in
+/// the format used by the system, e.g. `SeDebugPrivilege`.
+///
+/// # Errors
+///
+/// This function will return an error if the process does not exist or the
+/// caller is not allowed to query its access token.
+pub fn privileges(pid: u32) -> std::io::Result<Vec<String>> {
+    use windows_sys::Win32::Foundation::*;
+    use windows_sys::Win32::Security::*;
+
+    let buf = token_information(&open_token(pid)?, TokenPrivileges)?;
+
+    let count_len = std::mem::size_of::<u32>();
+    let count = match buf.get(..count_len) {
+        Some(count) => u32::from_ne_bytes(count.try_into().unwrap()) as usize,
+        None => return Err(std::io::ErrorKind::InvalidData.into()),
+    };
+
+    let offset = std::mem::offset_of!(TOKEN_PRIVILEGES, Privileges);
+    let size = std::mem::size_of::<LUID_AND_ATTRIBUTES>();
+    if buf.len() < offset + count * size {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+
+    let mut names = Vec::with_capacity(count);
+    for index in 0..count {
+        // SAFETY: We verified above that the buffer is big enough to hold all
+        // the entries. We read them unaligned as the buffer is just a vector of
+        // bytes.
+        let privilege = unsafe {
+            std::ptr::read_unaligned({
+                buf.as_ptr().add(offset + index * size) as *const LUID_AND_ATTRIBUTES
+            })
+        };
+
+        let mut name_buf = [0u16; MAX_PRIVILEGE_NAME_LEN];
+        let mut name_len = MAX_PRIVILEGE_NAME_LEN as u32;
+        // SAFETY: We pass a valid identifier and a buffer along with its size.
+        // The function will fail (instead of overflowing it) if it is too
+        // small. On success, the size is set to the length of the name.
+        let status = unsafe {
+            LookupPrivilegeNameW(
+                std::ptr::null(),
+                &privilege.Luid,
+                name_buf.as_mut_ptr(),
+                &mut name_len,
+            )
+        };
+        if status == FALSE {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        names.push(String::from_utf16_lossy(&name_buf[..name_len as usize]));
+    }
+
+    Ok(names)
+}
+
+/// Opens the access token of the process with the given pid for querying.
+fn open_token(pid: u32) -> std::io::Result<HandleGuard> {
+    use windows_sys::Win32::Foundation::*;
+    use windows_sys::Win32::Security::*;
+    use windows_sys::Win32::System::Threading::*;
+
+    // SAFETY: We just call the function with primitive values. The returned
+    // handle is verified below and closed by the guard.
+    let process = unsafe {
+        OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid)
+    };
+    if process == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let process = HandleGuard(process);
+
+    let mut token = std::mem::MaybeUninit::uninit();
+    // SAFETY: The process handle is valid (we verified it above) and we pass
+    // a valid pointer to the token handle location.
+    let status = unsafe {
+        OpenProcessToken(process.0, TOKEN_QUERY, token.as_mut_ptr())
+    };
+    if status == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: The call succeeded, so the token handle has been initialized.
+    Ok(HandleGuard(unsafe { token.assume_init() }))
+}
+
+/// Returns the integrity level stored in the given token.
+fn token_integrity_level(token: &HandleGuard) -> std::io::Result<IntegrityLevel> {
+    use windows_sys::Win32::Security::*;
+
+    let buf = token_information(token, TokenIntegrityLevel)?;
+
+    // SAFETY: The buffer has been filled by the system with information of
+    // the requested class, which starts with the `TOKEN_MANDATORY_LABEL`
+    // struct. We read it unaligned as the buffer is just a vector of bytes.
+    let label = unsafe {
+        std::ptr::read_unaligned(buf.as_ptr() as *const TOKEN_MANDATORY_LABEL)
+    };
+    let sid = label.Label.Sid;
+
+    // SAFETY: The SID points into the buffer that is still alive. The integrity
+    // label SID has exactly one sub-authority that is the level identifier.
+    let rid = unsafe {
+        let count = *GetSidSubAuthorityCount(sid);
+        if count == 0 {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        *GetSidSubAuthority(sid, u32::from(count) - 1)
+    };
+
+    Ok(IntegrityLevel::from_rid(rid))
+}
+
+/// Returns the string representation of the SID of the token user.
+fn token_user_sid(token: &HandleGuard) -> std::io::Result<String> {
+    use windows_sys::Win32::Foundation::*;
+    use windows_sys::Win32::Security::*;
+
+    let buf = token_information(token, TokenUser)?;
+
+    // SAFETY: The buffer has been filled by the system with information of
+    // the requested class, which starts with the `TOKEN_USER` struct.
+    let user = unsafe {
+        std::ptr::read_unaligned(buf.as_ptr() as *const TOKEN_USER)
+    };
+
+    let mut sid_str = std::mem::MaybeUninit::uninit();
+    // SAFETY: The SID points into the buffer that is still alive and we pass
+    // a valid pointer to the string location.
+    let status = unsafe {
+        windows_sys::Win32::Security::Authorization::ConvertSidToStringSidW(
+            user.User.Sid,
+            sid_str.as_mut_ptr(),
+        )
+    };
+    if status == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: The call succeeded, so the string pointer has been initialized
+    // and points to a null-terminated wide string.
+    let sid_str = unsafe { sid_str.assume_init() };
+
+    // SAFETY: The string is null-terminated, so we can find its length.
+    let sid_wide = unsafe {
+        let len = (0..).take_while(|&i| *sid_str.add(i) != 0).count();
+        std::slice::from_raw_parts(sid_str, len)
+    };
+    let result = String::from_utf16_lossy(sid_wide);
+
+    // SAFETY: The string was allocated by the system with `LocalAlloc` and we
+    // do not use it anymore.
+    unsafe {
+        LocalFree(sid_str as HLOCAL);
+    }
+
+    Ok(result)
+}
+
+/// Returns the raw token information of the given class.
+fn token_information(
+    token: &HandleGuard,
+    class: windows_sys::Win32::Security::TOKEN_INFORMATION_CLASS,
+) -> std::io::Result<Vec<u8>> {
+    use windows_sys::Win32::Foundation::*;
+    use windows_sys::Win32::Security::*;
+
+    let mut buf_len = 0;
+    // SAFETY: We query the required size of the buffer first: the call is
+    // expected to fail with the "insufficient buffer" error.
+    unsafe {
+        GetTokenInformation(token.0, class, std::ptr::null_mut(), 0, &mut buf_len);
+    }
+    if buf_len == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut buf = vec![0u8; buf_len as usize];
+    // SAFETY: We allocate the buffer above and pass its size. The function
+    // will fail (instead of overflowing it) if it is too small.
+    let status = unsafe {
+        GetTokenInformation(
+            token.0,
+            class,
+            buf.as_mut_ptr().cast(),
+            buf_len,
+            &mut buf_len,
+        )
+    };
+    if status == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(buf)
+}
+
+/// Wrapper over a system handle that closes it when dropped.
+struct HandleGuard(windows_sys::Win32::Foundation::HANDLE);
+
+impl Drop for HandleGuard {
+
+    fn drop(&mut self) {
+        // SAFETY: The handle is valid and owned by the guard.
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn integrity_level_self() {
+        let integrity_level = integrity_level(std::process::id())
+            .unwrap();
+
+        // Tests are run either by a regular user, an elevated administrator or
+        // as a service.
+        assert!(integrity_level >= IntegrityLevel::Medium);
+        assert!(integrity_level <= IntegrityLevel::System);
+    }
+
+    #[test]
+    fn user_sid_self() {
+        let user_sid = user_sid(std::process::id())
+            .unwrap();
+
+        assert!(user_sid.starts_with("S-1-"));
+    }
+
+    #[test]
+    fn privileges_self() {
+        let privileges = privileges(std::process::id())
+            .unwrap();
+
+        // Every process has the privilege of traversing directories that it
+        // does not have access to.
+        assert!(privileges.iter().any(|name| name == "SeChangeNotifyPrivilege"));
+    }
+
+    #[test]
+    fn integrity_level_from_rid() {
+        assert_eq!(IntegrityLevel::from_rid(0x0000), IntegrityLevel::Untrusted);
+        assert_eq!(IntegrityLevel::from_rid(0x1000), IntegrityLevel::Low);
+        assert_eq!(IntegrityLevel::from_rid(0x2000), IntegrityLevel::Medium);
+        assert_eq!(IntegrityLevel::from_rid(0x2100), IntegrityLevel::Medium);
+        assert_eq!(IntegrityLevel::from_rid(0x3000), IntegrityLevel::High);
+        assert_eq!(IntegrityLevel::from_rid(0x4000), IntegrityLevel::System);
+        assert_eq!(IntegrityLevel::from_rid(0x5000), IntegrityLevel::Protected);
+    }
+}
@@ -13,19 +13,60 @@ const PROTOS: &'static [&'static str] = &[
     "../../proto/rrg/os.proto",
     "../../proto/rrg/startup.proto",
     "../../proto/rrg/winreg.proto",
+    "../../proto/rrg/action/apply_agent_update.proto",
+    "../../proto/rrg/action/collect_artifact.proto",
+    "../../proto/rrg/action/delete_temp_files.proto",
+    "../../proto/rrg/action/find_crypto_material.proto",
+    "../../proto/rrg/action/get_agent_metrics.proto",
+    "../../proto/rrg/action/get_audit_events.proto",
+    "../../proto/rrg/action/get_auth_config.proto",
+    "../../proto/rrg/action/get_authorized_keys.proto",
+    "../../proto/rrg/action/get_client_actions.proto",
+    "../../proto/rrg/action/get_directory_size.proto",
+    "../../proto/rrg/action/get_dns_config.proto",
+    "../../proto/rrg/action/get_environment.proto",
+    "../../proto/rrg/action/get_event_log_records.proto",
     "../../proto/rrg/action/get_file_contents.proto",
+    "../../proto/rrg/action/get_file_entropy.proto",
+    "../../proto/rrg/action/get_file_extents.proto",
     "../../proto/rrg/action/get_file_hash.proto",
+    "../../proto/rrg/action/execute_signed_command.proto",
     "../../proto/rrg/action/get_file_metadata.proto",
     "../../proto/rrg/action/get_filesystem_timeline.proto",
+    "../../proto/rrg/action/get_journal_entries.proto",
+    "../../proto/rrg/action/get_shell_history.proto",
     "../../proto/rrg/action/get_system_metadata.proto",
+    "../../proto/rrg/action/get_time_config.proto",
     "../../proto/rrg/action/get_winreg_value.proto",
     "../../proto/rrg/action/grep_file_contents.proto",
+    "../../proto/rrg/action/hash_file_tree.proto",
+    "../../proto/rrg/action/list_autoruns.proto",
+    "../../proto/rrg/action/list_block_devices.proto",
     "../../proto/rrg/action/list_connections.proto",
+    "../../proto/rrg/action/list_directory.proto",
+    "../../proto/rrg/action/list_hosts_entries.proto",
     "../../proto/rrg/action/list_interfaces.proto",
+    "../../proto/rrg/action/list_launchd_jobs.proto",
+    "../../proto/rrg/action/list_mount_namespaces.proto",
     "../../proto/rrg/action/list_mounts.proto",
+    "../../proto/rrg/action/list_open_fds.proto",
+    "../../proto/rrg/action/list_path_executables.proto",
+    "../../proto/rrg/action/list_process_mappings.proto",
+    "../../proto/rrg/action/list_processes.proto",
+    "../../proto/rrg/action/list_recent_files.proto",
+    "../../proto/rrg/action/list_trusted_certificates.proto",
     "../../proto/rrg/action/list_winreg_keys.proto",
     "../../proto/rrg/action/list_winreg_values.proto",
+    "../../proto/rrg/action/quarantine_file.proto",
+    "../../proto/rrg/action/query_dbus_state.proto",
     "../../proto/rrg/action/query_wmi.proto",
+    "../../proto/rrg/action/resolve_hostnames.proto",
+    "../../proto/rrg/action/resolve_path.proto",
+    "../../proto/rrg/action/restore_quarantined_file.proto",
+    "../../proto/rrg/action/stream_file_contents.proto",
+    "../../proto/rrg/action/upload_file.proto",
+    "../../proto/rrg/action/watch_directory.proto",
+    "../../proto/rrg/action/write_file.proto",
 ];
 
 fn main() {
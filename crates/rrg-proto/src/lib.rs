@@ -19,6 +19,20 @@ impl From<ospect::os::Kind> for os::Type {
     }
 }
 
+impl From<ospect::os::MachineIdSource> for get_system_metadata::MachineIdSource {
+
+    fn from(source: ospect::os::MachineIdSource) -> get_system_metadata::MachineIdSource {
+        use ospect::os::MachineIdSource::*;
+
+        match source {
+            DmiProductUuid => get_system_metadata::MachineIdSource::DMI_PRODUCT_UUID,
+            MachineIdFile => get_system_metadata::MachineIdSource::MACHINE_ID_FILE,
+            MachineGuid => get_system_metadata::MachineIdSource::MACHINE_GUID,
+            PlatformUuid => get_system_metadata::MachineIdSource::PLATFORM_UUID,
+        }
+    }
+}
+
 impl From<std::path::PathBuf> for fs::Path {
 
     fn from(path: std::path::PathBuf) -> fs::Path {
@@ -72,6 +86,27 @@ impl From<std::fs::Metadata> for fs::FileMetadata {
             Err(_) => (), // TODO(@panhania): Consider logging.
         }
 
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::{FileTypeExt as _, MetadataExt as _};
+
+            proto.set_unix_dev(metadata.dev());
+            proto.set_unix_ino(metadata.ino());
+            proto.set_unix_mode(metadata.mode());
+            proto.set_unix_setuid(metadata.mode() & 0o4000 != 0);
+            proto.set_unix_setgid(metadata.mode() & 0o2000 != 0);
+            proto.set_unix_sticky(metadata.mode() & 0o1000 != 0);
+            proto.set_unix_nlink(metadata.nlink());
+            proto.set_unix_uid(metadata.uid());
+            proto.set_unix_gid(metadata.gid());
+            let file_type = metadata.file_type();
+            if file_type.is_block_device() || file_type.is_char_device() {
+                proto.set_unix_rdev(metadata.rdev());
+            }
+            proto.set_unix_blksize(metadata.blksize());
+            proto.set_unix_blocks(metadata.blocks());
+        }
+
         proto
     }
 }
@@ -291,6 +326,70 @@ impl From<ospect::net::Connection> for net::Connection {
     }
 }
 
+#[cfg(target_os = "linux")]
+impl From<ospect::net::linux::RawConnection> for net::RawConnection {
+
+    fn from(conn: ospect::net::linux::RawConnection) -> net::RawConnection {
+        let mut proto = net::RawConnection::default();
+        proto.set_pid(conn.pid());
+        proto.set_local_address(conn.local_addr().into());
+        proto.set_remote_address(conn.remote_addr().into());
+        proto.set_protocol(u32::from(conn.protocol()));
+
+        proto
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<ospect::net::linux::UnixSocketType> for net::UnixSocketType {
+
+    fn from(socket_type: ospect::net::linux::UnixSocketType) -> net::UnixSocketType {
+        use ospect::net::linux::UnixSocketType::*;
+        match socket_type {
+            Stream => net::UnixSocketType::STREAM,
+            Datagram => net::UnixSocketType::DATAGRAM,
+            SeqPacket => net::UnixSocketType::SEQPACKET,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<ospect::net::linux::UnixSocketState> for net::UnixSocketState {
+
+    fn from(state: ospect::net::linux::UnixSocketState) -> net::UnixSocketState {
+        use ospect::net::linux::UnixSocketState::*;
+        match state {
+            Unconnected => net::UnixSocketState::UNCONNECTED,
+            Connecting => net::UnixSocketState::CONNECTING,
+            Connected => net::UnixSocketState::CONNECTED,
+            Disconnecting => net::UnixSocketState::DISCONNECTING,
+            Listening => net::UnixSocketState::LISTENING,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<ospect::net::linux::UnixSocket> for net::UnixConnection {
+
+    fn from(socket: ospect::net::linux::UnixSocket) -> net::UnixConnection {
+        use ospect::net::linux::UnixSocketAddr::*;
+
+        let mut proto = net::UnixConnection::default();
+        proto.set_pid(socket.pid());
+        proto.set_type(socket.socket_type().into());
+        proto.set_state(socket.state().into());
+        proto.set_inode(socket.inode());
+
+        match socket.addr() {
+            Some(Pathname(path)) => proto.set_path(path.clone().into()),
+            Some(Abstract(name)) => proto.set_abstract_name(name.clone()),
+            None => (),
+        }
+
+        proto
+    }
+}
+
 impl From<ospect::net::Interface> for net::Interface {
 
     fn from(iface: ospect::net::Interface) -> net::Interface {
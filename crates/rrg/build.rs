@@ -0,0 +1,12 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+fn main() {
+    // The target triple is known only to build scripts, but the agent needs it
+    // to refuse updates built for other targets.
+    let target = std::env::var("TARGET")
+        .expect("no target triple");
+    println!("cargo:rustc-env=RRG_TARGET={target}");
+}
@@ -15,143 +15,1381 @@
 //! instance of the corresponding request type and send some (zero or more)
 //! instances of the corresponding response type.
 
-#[cfg(feature = "action-get_system_metadata")]
-pub mod get_system_metadata;
+/// Declares modules of actions and collects their handlers.
+///
+/// Every module is compiled only if the corresponding feature is enabled. It
+/// has to provide the `ACTION` constant (the action it implements) and the
+/// `handle` function (the handler of the action).
+macro_rules! actions {
+    ($($feature:literal => $module:ident,)*) => {
+        $(
+            #[cfg(feature = $feature)]
+            pub mod $module;
+        )*
 
-#[cfg(feature = "action-get_file_metadata")]
-pub mod get_file_metadata;
+        /// Registers handlers of all the actions enabled at compile time.
+        #[allow(unused_variables)]
+        fn register_all<S>(registry: &mut Registry<S>)
+        where
+            S: crate::session::Session,
+        {
+            $(
+                #[cfg(feature = $feature)]
+                registry.register(self::$module::ACTION, |session, request| {
+                    handle(session, request, self::$module::handle)
+                });
+            )*
+        }
+    };
+}
 
-#[cfg(feature = "action-get_file_contents")]
-pub mod get_file_contents;
+actions! {
+    "action-get_system_metadata" => get_system_metadata,
+    "action-get_file_metadata" => get_file_metadata,
+    "action-get_file_contents" => get_file_contents,
+    "action-grep_file_contents" => grep_file_contents,
+    "action-get_filesystem_timeline" => get_filesystem_timeline,
+    "action-list_connections" => list_connections,
+    "action-list_interfaces" => list_interfaces,
+    "action-list_mounts" => list_mounts,
+    "action-get_winreg_value" => get_winreg_value,
+    "action-list_winreg_values" => list_winreg_values,
+    "action-list_winreg_keys" => list_winreg_keys,
+    "action-query_wmi" => query_wmi,
+    "action-get_shell_history" => get_shell_history,
+    "action-collect_artifact" => collect_artifact,
+    "action-get_client_actions" => get_client_actions,
+    "action-resolve_path" => resolve_path,
+    "action-write_file" => write_file,
+    "action-get_directory_size" => get_directory_size,
+    "action-find_crypto_material" => find_crypto_material,
+    "action-stream_file_contents" => stream_file_contents,
+    "action-get_file_entropy" => get_file_entropy,
+    "action-list_mount_namespaces" => list_mount_namespaces,
+    "action-get_dns_config" => get_dns_config,
+    "action-list_hosts_entries" => list_hosts_entries,
+    "action-list_open_fds" => list_open_fds,
+    "action-list_processes" => list_processes,
+    "action-execute_signed_command" => execute_signed_command,
+    "action-get_agent_metrics" => get_agent_metrics,
+    "action-list_autoruns" => list_autoruns,
+    "action-list_launchd_jobs" => list_launchd_jobs,
+    "action-list_recent_files" => list_recent_files,
+    "action-list_block_devices" => list_block_devices,
+    "action-delete_temp_files" => delete_temp_files,
+    "action-resolve_hostnames" => resolve_hostnames,
+    "action-list_directory" => list_directory,
+    "action-get_environment" => get_environment,
+    "action-get_journal_entries" => get_journal_entries,
+    "action-get_file_hash" => get_file_hash,
+    "action-get_auth_config" => get_auth_config,
+    "action-get_authorized_keys" => get_authorized_keys,
+    "action-hash_file_tree" => hash_file_tree,
+    "action-get_event_log_records" => get_event_log_records,
+    "action-quarantine_file" => quarantine_file,
+    "action-restore_quarantined_file" => restore_quarantined_file,
+    "action-list_process_mappings" => list_process_mappings,
+    "action-query_dbus_state" => query_dbus_state,
+    "action-upload_file" => upload_file,
+    "action-list_path_executables" => list_path_executables,
+    "action-get_file_extents" => get_file_extents,
+    "action-get_audit_events" => get_audit_events,
+    "action-watch_directory" => watch_directory,
+    "action-list_trusted_certificates" => list_trusted_certificates,
+    "action-get_time_config" => get_time_config,
+    "action-apply_agent_update" => apply_agent_update,
+}
+
+use log::info;
+
+/// Actions that are not available unless explicitly put on the allow list.
+///
+/// These are actions that modify the state of the system (or send data outside
+/// of the Fleetspeak channel) and thus can be very dangerous in the wrong hands.
+const DISABLED_BY_DEFAULT: &[crate::request::Action] = &[
+    crate::request::Action::WriteFile,
+    crate::request::Action::ExecuteSignedCommand,
+    crate::request::Action::QuarantineFile,
+    crate::request::Action::RestoreQuarantinedFile,
+    crate::request::Action::UploadFile,
+    crate::request::Action::ApplyAgentUpdate,
+];
 
-#[cfg(feature = "action-grep_file_contents")]
-pub mod grep_file_contents;
+/// Checks whether the agent `args` permit the given action to be executed.
+///
+/// Actions that are not on the allow list (if the list is non-empty) or are on
+/// the deny list are not permitted. Actions that are disabled by default (e.g.
+/// `write_file`) are permitted only if they are explicitly put on the allow
+/// list. Note that this does not take into account whether the action was
+/// enabled at compile time.
+pub fn is_permitted(args: &crate::args::Args, action: crate::request::Action) -> bool {
+    if !args.allowed_actions.is_empty() && !args.allowed_actions.contains(&action) {
+        return false;
+    }
+    if DISABLED_BY_DEFAULT.contains(&action) && !args.allowed_actions.contains(&action) {
+        return false;
+    }
 
-#[cfg(feature = "action-get_filesystem_timeline")]
-pub mod get_filesystem_timeline;
+    !args.denied_actions.contains(&action)
+}
 
-#[cfg(feature = "action-list_connections")]
-pub mod list_connections;
+/// A function invoking an action handler for the given request.
+type Handler<S> = fn(&mut S, crate::Request) -> crate::session::Result<()>;
 
-#[cfg(feature = "action-list_interfaces")]
-pub mod list_interfaces;
+/// A mapping between actions and handlers that implement them.
+///
+/// The registry contains handlers only for the actions that were enabled at
+/// compile time (through `action-*` features) and that are permitted by the
+/// allow and deny lists specified in the agent arguments. It is supposed to be
+/// created once at the agent startup and then used to dispatch all requests.
+pub struct Registry<S> {
+    /// Handlers of all the actions available to the agent.
+    handlers: std::collections::HashMap<crate::request::Action, Handler<S>>,
+}
 
-#[cfg(feature = "action-list_mounts")]
-pub mod list_mounts;
+impl<S> Registry<S>
+where
+    S: crate::session::Session,
+{
+    /// Creates a new registry with handlers for all the enabled actions.
+    ///
+    /// Actions that are not on the allow list (if the list is non-empty) or are
+    /// on the deny list specified in `args` are not included in the registry.
+    /// Actions that are disabled by default (e.g. `write_file`) are included
+    /// only if they are explicitly put on the allow list.
+    pub fn new(args: &crate::args::Args) -> Registry<S> {
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
 
-#[cfg(feature = "action-get_winreg_value")]
-pub mod get_winreg_value;
+        register_all(&mut registry);
 
-#[cfg(feature = "action-list_winreg_values")]
-pub mod list_winreg_values;
+        registry.handlers.retain(|action, _| is_permitted(args, *action));
+        registry
+    }
 
-#[cfg(feature = "action-list_winreg_keys")]
-pub mod list_winreg_keys;
+    /// Registers the `handler` to be invoked for requests of the `action`.
+    #[allow(dead_code)]
+    fn register(&mut self, action: crate::request::Action, handler: Handler<S>) {
+        let old_handler = self.handlers.insert(action, handler);
+        assert!(old_handler.is_none(), "action '{action}' already registered");
+    }
 
-#[cfg(feature = "action-query_wmi")]
-pub mod query_wmi;
+    /// Returns an iterator over all actions that have handlers in the registry.
+    pub fn actions(&self) -> impl Iterator<Item = crate::request::Action> + '_ {
+        self.handlers.keys().copied()
+    }
 
-use log::info;
+    /// Dispatches the given `request` to an appropriate action handler.
+    ///
+    /// This method looks up the handler registered for the action specified in
+    /// the request and invokes it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the given action is unknown (or
+    /// not yet implemented).
+    ///
+    /// It will also error out if the action execution itself fails for whatever
+    /// reason.
+    pub fn dispatch(&self, session: &mut S, request: crate::Request) -> crate::session::Result<()> {
+        let request_id = request.id();
+        let action = request.action();
 
-/// Dispatches the given `request` to an appropriate action handler.
+        // There is no point in doing any work if the results are not going to
+        // be needed anyway.
+        if let Some(deadline) = request.deadline() {
+            if std::time::SystemTime::now() > deadline {
+                return Err(crate::session::Error::deadline_exceeded(deadline));
+            }
+        }
+
+        let handler = match self.handlers.get(&action) {
+            Some(handler) => handler,
+            None => return Err(crate::session::Error::unsupported_action(action)),
+        };
+
+        info!("dispatching request '{request_id}': {action}");
+
+        let result = handler(session, request);
+
+        info!("finished dispatching request '{request_id}'");
+
+        result
+    }
+}
+
+/// Handles a `request` using the specified `handler`.
 ///
-/// This method is a mapping between action names (as specified in the protocol)
-/// and action handlers (implemented on the agent).
+/// This method will attempt to interpret request arguments for the specific
+/// action and execute the handler with them.
 ///
 /// # Errors
 ///
-/// This function will return an error if the given action is unknown (or not
-/// yet implemented).
-///
-/// It will also error out if the action execution itself fails for whatever
-/// reason.
-pub fn dispatch<'s, S>(session: &mut S, request: crate::Request) -> Result<(), crate::session::Error>
+/// This function will return an error if the request arguments cannot be parsed
+/// for the specific action or if the action execution fails.
+#[allow(dead_code)]
+fn handle<S, A, H>(session: &mut S, request: crate::Request, handler: H) -> crate::session::Result<()>
 where
     S: crate::session::Session,
+    A: crate::request::Args,
+    H: FnOnce(&mut S, A) -> crate::session::Result<()>,
 {
-    use crate::request::Action::*;
+    Ok(handler(session, request.args()?)?)
+}
 
-    let request_id = request.id();
-    let action = request.action();
+#[cfg(test)]
+mod tests {
 
-    info!("dispatching request '{request_id}': {action}");
+    use super::*;
 
-    let result = match request.action() {
-        #[cfg(feature = "action-get_system_metadata")]
-        GetSystemMetadata => {
-            handle(session, request, self::get_system_metadata::handle)
-        }
+    #[test]
+    fn registry_contains_enabled_actions() {
+        use crate::request::Action;
+
+        let args = crate::args::Args::default();
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+
+        let mut actions = registry.actions()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>();
+        actions.sort();
+
+        let expected_actions = [
+            (cfg!(feature = "action-get_system_metadata"), Action::GetSystemMetadata),
+            (cfg!(feature = "action-get_file_metadata"), Action::GetFileMetadata),
+            (cfg!(feature = "action-get_file_contents"), Action::GetFileContents),
+            (cfg!(feature = "action-grep_file_contents"), Action::GrepFileContents),
+            (cfg!(feature = "action-get_filesystem_timeline"), Action::GetFilesystemTimeline),
+            (cfg!(feature = "action-list_connections"), Action::ListConnections),
+            (cfg!(feature = "action-list_interfaces"), Action::ListInterfaces),
+            (cfg!(feature = "action-list_mounts"), Action::ListMounts),
+            (cfg!(feature = "action-get_winreg_value"), Action::GetWinregValue),
+            (cfg!(feature = "action-list_winreg_values"), Action::ListWinregValues),
+            (cfg!(feature = "action-list_winreg_keys"), Action::ListWinregKeys),
+            (cfg!(feature = "action-query_wmi"), Action::QueryWmi),
+            (cfg!(feature = "action-get_shell_history"), Action::GetShellHistory),
+            (cfg!(feature = "action-collect_artifact"), Action::CollectArtifact),
+            (cfg!(feature = "action-get_client_actions"), Action::GetClientActions),
+            (cfg!(feature = "action-resolve_path"), Action::ResolvePath),
+            // Disabled unless explicitly allowed.
+            (false, Action::WriteFile),
+            (cfg!(feature = "action-get_directory_size"), Action::GetDirectorySize),
+            (cfg!(feature = "action-find_crypto_material"), Action::FindCryptoMaterial),
+            (cfg!(feature = "action-stream_file_contents"), Action::StreamFileContents),
+            (cfg!(feature = "action-get_file_entropy"), Action::GetFileEntropy),
+            (cfg!(feature = "action-list_mount_namespaces"), Action::ListMountNamespaces),
+            (cfg!(feature = "action-get_dns_config"), Action::GetDnsConfig),
+            (cfg!(feature = "action-list_hosts_entries"), Action::ListHostsEntries),
+            (cfg!(feature = "action-list_open_fds"), Action::ListOpenFds),
+            (cfg!(feature = "action-list_processes"), Action::ListProcesses),
+            // Disabled unless explicitly allowed.
+            (false, Action::ExecuteSignedCommand),
+            (cfg!(feature = "action-get_agent_metrics"), Action::GetAgentMetrics),
+            (cfg!(feature = "action-list_autoruns"), Action::ListAutoruns),
+            (cfg!(feature = "action-list_launchd_jobs"), Action::ListLaunchdJobs),
+            (cfg!(feature = "action-list_recent_files"), Action::ListRecentFiles),
+            (cfg!(feature = "action-list_block_devices"), Action::ListBlockDevices),
+            (cfg!(feature = "action-delete_temp_files"), Action::DeleteTempFiles),
+            (cfg!(feature = "action-resolve_hostnames"), Action::ResolveHostnames),
+            (cfg!(feature = "action-list_directory"), Action::ListDirectory),
+            (cfg!(feature = "action-get_environment"), Action::GetEnvironment),
+            (cfg!(feature = "action-get_journal_entries"), Action::GetJournalEntries),
+            (cfg!(feature = "action-get_file_hash"), Action::GetFileHash),
+            (cfg!(feature = "action-get_auth_config"), Action::GetAuthConfig),
+            (cfg!(feature = "action-get_authorized_keys"), Action::GetAuthorizedKeys),
+            (cfg!(feature = "action-hash_file_tree"), Action::HashFileTree),
+            (cfg!(feature = "action-get_event_log_records"), Action::GetEventLogRecords),
+            (cfg!(feature = "action-list_process_mappings"), Action::ListProcessMappings),
+            (cfg!(feature = "action-query_dbus_state"), Action::QueryDbusState),
+            (cfg!(feature = "action-list_path_executables"), Action::ListPathExecutables),
+            (cfg!(feature = "action-get_file_extents"), Action::GetFileExtents),
+            (cfg!(feature = "action-get_audit_events"), Action::GetAuditEvents),
+            (cfg!(feature = "action-watch_directory"), Action::WatchDirectory),
+            (cfg!(feature = "action-list_trusted_certificates"), Action::ListTrustedCertificates),
+            (cfg!(feature = "action-get_time_config"), Action::GetTimeConfig),
+            // Disabled unless explicitly allowed.
+            (false, Action::ApplyAgentUpdate),
+        ];
+
+        let mut expected_actions = expected_actions.into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, action)| action.to_string())
+            .collect::<Vec<_>>();
+        expected_actions.sort();
+
+        assert_eq!(actions, expected_actions);
+    }
+
+    #[test]
+    #[cfg(feature = "action-get_file_contents")]
+    fn registry_excludes_denied_actions() {
+        use crate::request::Action;
+
+        let mut args = crate::args::Args::default();
+        args.denied_actions.push(Action::GetFileContents);
+
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+        assert!(!registry.actions().any(|action| action == Action::GetFileContents));
         #[cfg(feature = "action-get_file_metadata")]
-        GetFileMetadata => {
-            handle(session, request, self::get_file_metadata::handle)
+        assert!(registry.actions().any(|action| action == Action::GetFileMetadata));
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "action-get_file_contents",
+        feature = "action-get_file_metadata",
+    ))]
+    fn registry_includes_only_allowed_actions() {
+        use crate::request::Action;
+
+        let mut args = crate::args::Args::default();
+        args.allowed_actions.push(Action::GetFileContents);
+        args.allowed_actions.push(Action::GetFileMetadata);
+        args.denied_actions.push(Action::GetFileMetadata);
+
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+        assert_eq!(registry.actions().collect::<Vec<_>>(), vec![
+            Action::GetFileContents,
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "action-write_file")]
+    fn registry_includes_explicitly_allowed_write_file() {
+        use crate::request::Action;
+
+        let mut args = crate::args::Args::default();
+        args.allowed_actions.push(Action::WriteFile);
+
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+        assert_eq!(registry.actions().collect::<Vec<_>>(), vec![
+            Action::WriteFile,
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "action-quarantine_file")]
+    fn registry_includes_explicitly_allowed_quarantine_file() {
+        use crate::request::Action;
+
+        let args = crate::args::Args::default();
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+        assert!(!registry.actions().any(|action| action == Action::QuarantineFile));
+
+        let mut args = crate::args::Args::default();
+        args.allowed_actions.push(Action::QuarantineFile);
+
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+        assert_eq!(registry.actions().collect::<Vec<_>>(), vec![
+            Action::QuarantineFile,
+        ]);
+    }
+
+    #[test]
+    fn registry_dispatch_deadline_exceeded() {
+        let args = crate::args::Args::default();
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+
+        let deadline = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_deadline(rrg_proto::into_timestamp(deadline));
+
+        let request = crate::Request::try_from(request)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let error = registry.dispatch(&mut session, request)
+            .unwrap_err();
+
+        assert_eq!(error.kind(), crate::session::ErrorKind::DeadlineExceeded);
+        assert_eq! {
+            rrg_proto::rrg::status::error::Type::from(error.kind()),
+            rrg_proto::rrg::status::error::Type::DEADLINE_EXCEEDED,
+        };
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn registry_dispatch_unsupported_action() {
+        let args = crate::args::Args::default();
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::LIST_NAMED_PIPES);
+
+        let request = crate::Request::try_from(request)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let error = registry.dispatch(&mut session, request)
+            .unwrap_err();
+
+        assert_eq!(error.kind(), crate::session::ErrorKind::UnsupportedAction);
+    }
+
+    #[test]
+    #[cfg(not(feature = "action-get_filesystem_timeline"))]
+    fn registry_dispatch_disabled_action() {
+        let args = crate::args::Args::default();
+        let registry = Registry::<crate::session::FakeSession>::new(&args);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_FILESYSTEM_TIMELINE);
+
+        let request = crate::Request::try_from(request)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let error = registry.dispatch(&mut session, request)
+            .unwrap_err();
+
+        assert_eq!(error.kind(), crate::session::ErrorKind::UnsupportedAction);
+    }
+
+    #[test]
+    fn registry_dispatch_action_timeout() {
+        use protobuf::Message as _;
+        use crate::session::{FleetspeakSession, Session as _};
+
+        /// Handler that never finishes on its own but checks the deadline.
+        fn handle_forever(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            loop {
+                session.check_deadline()?;
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
         }
-        #[cfg(feature = "action-get_file_contents")]
-        GetFileContents => {
-            handle(session, request, self::get_file_contents::handle)
+
+        let args = crate::args::Args {
+            action_timeout: Some(std::time::Duration::from_secs(60)),
+            action_timeout_overrides: vec![(
+                crate::request::Action::GetClientActions,
+                std::time::Duration::from_millis(100),
+            )],
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_forever);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let start = std::time::Instant::now();
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= std::time::Duration::from_millis(100));
+        assert!(elapsed < std::time::Duration::from_secs(60));
+
+        let status = transport.take_sent()
+            .into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .find(|response| response.has_status())
+            .unwrap();
+
+        assert_eq! {
+            status.status().error().type_(),
+            rrg_proto::rrg::status::error::Type::REAL_TIME_LIMIT_EXCEEDED,
+        };
+    }
+
+    #[test]
+    fn registry_dispatch_reply_sequence() {
+        use protobuf::Message as _;
+        use crate::session::{FleetspeakSession, Session as _};
+
+        /// Handler that yields a few replies and reports their count upfront.
+        fn handle_replies(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            session.set_total_count_hint(5);
+            for _ in 0..5 {
+                session.reply(())?;
+            }
+            Ok(())
         }
-        #[cfg(feature = "action-grep_file_contents")]
-        GrepFileContents => {
-            handle(session, request, self::grep_file_contents::handle)
+
+        let args = crate::args::Args {
+            reply_batch_count: 2,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_replies);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let mut responses = Vec::new();
+        for message in transport.take_sent() {
+            match message.kind.as_deref() {
+                Some("rrg.Response") => {
+                    responses.push(rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap());
+                }
+                Some("rrg.ResponseBatch") => {
+                    let batch = rrg_proto::rrg::ResponseBatch::parse_from_bytes(&message.data).unwrap();
+                    responses.extend(batch.responses);
+                }
+                _ => continue,
+            }
         }
-        #[cfg(feature = "action-get_filesystem_timeline")]
-        GetFilesystemTimeline => {
-            handle(session, request, self::get_filesystem_timeline::handle)
+
+        let sequences = responses.iter()
+            .filter(|response| response.has_result())
+            .map(|response| response.sequence())
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![0, 1, 2, 3, 4]);
+
+        let status = responses.iter()
+            .find(|response| response.has_status())
+            .unwrap()
+            .status();
+        assert!(!status.has_error());
+        assert_eq!(status.reply_count(), 5);
+        assert_eq!(status.total_count_hint(), 5);
+    }
+
+    #[test]
+    fn registry_dispatch_reply_tags() {
+        use protobuf::Message as _;
+        use crate::session::{FleetspeakSession, Session as _, Tag, TaggedSession};
+
+        /// Synthetic result of a sub-collector yielding strings.
+        struct Name(&'static str);
+
+        impl crate::response::Item for Name {
+
+            type Proto = protobuf::well_known_types::wrappers::StringValue;
+
+            fn into_proto(self) -> Self::Proto {
+                let mut proto = Self::Proto::new();
+                proto.value = String::from(self.0);
+
+                proto
+            }
         }
-        #[cfg(feature = "action-list_connections")]
-        ListConnections => {
-            handle(session, request, self::list_connections::handle)
+
+        /// Synthetic result of a sub-collector yielding numbers.
+        struct Number(u64);
+
+        impl crate::response::Item for Number {
+
+            type Proto = protobuf::well_known_types::wrappers::UInt64Value;
+
+            fn into_proto(self) -> Self::Proto {
+                let mut proto = Self::Proto::new();
+                proto.value = self.0;
+
+                proto
+            }
         }
-        #[cfg(feature = "action-list_interfaces")]
-        ListInterfaces => {
-            handle(session, request, self::list_interfaces::handle)
+
+        /// Handler that yields interleaved results of two sub-collectors.
+        fn handle_collectors(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            TaggedSession::new(&mut *session, Tag::new("names")).reply(Name("foo"))?;
+            TaggedSession::new(&mut *session, Tag::new("numbers")).reply(Number(42))?;
+            TaggedSession::new(&mut *session, Tag::new("names")).reply(Name("bar"))?;
+            session.reply_tagged(&Tag::new("numbers").child("big"), Number(1337))?;
+            session.reply(Number(0))?;
+
+            Ok(())
         }
-        #[cfg(feature = "action-list_mounts")]
-        ListMounts => {
-            handle(session, request, self::list_mounts::handle)
+
+        let args = crate::args::Args {
+            reply_batch_count: 3,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_collectors);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let mut batch_count = 0;
+        let mut responses = Vec::new();
+        for message in transport.take_sent() {
+            match message.kind.as_deref() {
+                Some("rrg.Response") => {
+                    responses.push(rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap());
+                }
+                Some("rrg.ResponseBatch") => {
+                    let batch = rrg_proto::rrg::ResponseBatch::parse_from_bytes(&message.data).unwrap();
+                    responses.extend(batch.responses);
+                    batch_count += 1;
+                }
+                _ => continue,
+            }
         }
-        #[cfg(feature = "action-get_winreg_value")]
-        GetWinregValue => {
-            handle(session, request, self::get_winreg_value::handle)
+        assert!(batch_count > 0);
+
+        let results = responses.iter()
+            .filter(|response| response.has_result())
+            .map(|response| (response.tag(), response.result().type_url.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(results, vec![
+            ("names", "type.googleapis.com/google.protobuf.StringValue"),
+            ("numbers", "type.googleapis.com/google.protobuf.UInt64Value"),
+            ("names", "type.googleapis.com/google.protobuf.StringValue"),
+            ("numbers/big", "type.googleapis.com/google.protobuf.UInt64Value"),
+            ("", "type.googleapis.com/google.protobuf.UInt64Value"),
+        ]);
+    }
+
+    /// Dispatches a request yielding a single log-like result and returns the
+    /// result response that the agent sent.
+    fn dispatch_serialized_result(
+        args: &crate::args::Args,
+        serialization: rrg_proto::rrg::request::Serialization,
+    ) -> rrg_proto::rrg::Response {
+        use protobuf::Message as _;
+        use crate::session::{FleetspeakSession, Session as _};
+
+        /// Synthetic result carrying a message.
+        struct Message(&'static str);
+
+        impl crate::response::Item for Message {
+
+            type Proto = rrg_proto::rrg::Log;
+
+            fn into_proto(self) -> rrg_proto::rrg::Log {
+                let mut proto = rrg_proto::rrg::Log::new();
+                proto.set_level(rrg_proto::rrg::log::Level::WARN);
+                proto.set_message(String::from(self.0));
+
+                proto
+            }
         }
-        #[cfg(feature = "action-list_winreg_values")]
-        ListWinregValues => {
-            handle(session, request, self::list_winreg_values::handle)
+
+        fn handle_message(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            session.reply(Message("foo \"bar\""))
         }
-        #[cfg(feature = "action-list_winreg_keys")]
-        ListWinregKeys => {
-            handle(session, request, self::list_winreg_keys::handle)
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_message);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_result_serialization(serialization);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let responses = transport.take_sent()
+            .into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .collect::<Vec<_>>();
+
+        let status = responses.iter()
+            .find(|response| response.has_status())
+            .unwrap()
+            .status();
+        assert!(!status.has_error());
+        assert_eq!(status.reply_count(), 1);
+
+        responses.into_iter()
+            .find(|response| response.has_result() || response.has_result_json())
+            .unwrap()
+    }
+
+    #[test]
+    fn registry_dispatch_serialization_default() {
+        let response = dispatch_serialized_result(
+            &crate::args::Args::default(),
+            rrg_proto::rrg::request::Serialization::UNSET,
+        );
+
+        assert!(!response.has_result_json());
+
+        let result = response.result().unpack::<rrg_proto::rrg::Log>()
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.level(), rrg_proto::rrg::log::Level::WARN);
+        assert_eq!(result.message(), "foo \"bar\"");
+    }
+
+    #[test]
+    fn registry_dispatch_serialization_json() {
+        let response = dispatch_serialized_result(
+            &crate::args::Args::default(),
+            rrg_proto::rrg::request::Serialization::JSON,
+        );
+
+        assert!(!response.has_result());
+
+        let json = crate::json::parse(response.result_json());
+        assert_eq!(json.get("@type").and_then(crate::json::Value::as_str), Some("type.googleapis.com/rrg.Log"));
+        assert_eq!(json.get("level").and_then(crate::json::Value::as_str), Some("WARN"));
+        assert_eq!(json.get("message").and_then(crate::json::Value::as_str), Some("foo \"bar\""));
+    }
+
+    #[test]
+    fn registry_dispatch_serialization_args() {
+        let args = crate::args::Args {
+            result_serialization: crate::Serialization::Json,
+            ..Default::default()
+        };
+
+        let response = dispatch_serialized_result(
+            &args,
+            rrg_proto::rrg::request::Serialization::UNSET,
+        );
+        assert!(response.has_result_json());
+
+        // Requests can still ask for the default format explicitly.
+        let response = dispatch_serialized_result(
+            &args,
+            rrg_proto::rrg::request::Serialization::PROTOBUF,
+        );
+        assert!(response.has_result());
+    }
+
+    #[test]
+    fn registry_dispatch_pages() {
+        use protobuf::Message as _;
+        use crate::session::FleetspeakSession;
+
+        /// Synthetic result of a paged enumeration.
+        struct Number(u64);
+
+        impl crate::response::Item for Number {
+
+            type Proto = protobuf::well_known_types::wrappers::UInt64Value;
+
+            fn into_proto(self) -> Self::Proto {
+                let mut proto = Self::Proto::new();
+                proto.value = self.0;
+
+                proto
+            }
         }
-        #[cfg(feature = "action-query_wmi")]
-        QueryWmi => {
-            handle(session, request, self::query_wmi::handle)
+
+        /// Handler that enumerates a big set of numbers in pages.
+        fn handle_numbers(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            use crate::session::{InvalidPageCursorError, Paged};
+
+            crate::session::reply_paged(session, |cursor| {
+                let start = match cursor {
+                    Some(cursor) => <[u8; 8]>::try_from(cursor)
+                        .map(|cursor| u64::from_be_bytes(cursor) + 1)
+                        .map_err(|_| InvalidPageCursorError)?,
+                    None => 0,
+                };
+
+                Ok::<_, InvalidPageCursorError>((start..10_000).map(|number| Paged {
+                    item: Number(number),
+                    cursor: number.to_be_bytes().to_vec(),
+                }))
+            })
         }
-        // We allow `unreachable_patterns` because otherwise we get a warning if
-        // we compile with all the actions enabled.
-        #[allow(unreachable_patterns)]
-        action => {
-            return Err(crate::session::Error::unsupported_action(action));
-        },
-    };
 
-    info!("finished dispatching request '{request_id}'");
+        let args = crate::args::Args::default();
 
-    result
-}
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_numbers);
 
-/// Handles a `request` using the specified `handler`.
-///
-/// This method will attempt to interpret request arguments for the specific
-/// action and execute the handler with them.
-///
-/// # Errors
-///
-/// This function will return an error if the request arguments cannot be parsed
-/// for the specific action or if the action execution fails.
-fn handle<S, A, H>(session: &mut S, request: crate::Request, handler: H) -> crate::session::Result<()>
-where
-    S: crate::session::Session,
-    A: crate::request::Args,
-    H: FnOnce(&mut S, A) -> crate::session::Result<()>,
-{
-    Ok(handler(session, request.args()?)?)
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let mut numbers = Vec::new();
+        let mut page_cursor = Vec::new();
+
+        loop {
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+            request.set_page_size(1001);
+            request.set_page_cursor(page_cursor);
+
+            let request = crate::Request::try_from(request);
+            FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+            let responses = transport.take_sent().into_iter()
+                .filter(|message| message.kind.as_deref() == Some("rrg.Response"))
+                .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+                .collect::<Vec<_>>();
+
+            for response in responses.iter().filter(|response| response.has_result()) {
+                let number = protobuf::well_known_types::wrappers::UInt64Value::parse_from_bytes({
+                    &response.result().value
+                }).unwrap();
+                numbers.push(number.value);
+            }
+
+            let status = responses.iter()
+                .find(|response| response.has_status())
+                .unwrap()
+                .status();
+            assert!(!status.has_error());
+            assert!(status.reply_count() <= 1001);
+
+            if status.next_page_cursor().is_empty() {
+                break;
+            }
+            page_cursor = status.next_page_cursor().to_vec();
+        }
+
+        // Every number is sent exactly once (and in order).
+        assert_eq!(numbers, (0..10_000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn registry_dispatch_invalid_page_cursor() {
+        use protobuf::Message as _;
+        use crate::session::FleetspeakSession;
+
+        fn handle_numbers(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            crate::session::reply_paged(session, |cursor| match cursor {
+                Some(_) => Err(crate::session::InvalidPageCursorError),
+                None => Ok(std::iter::empty::<crate::session::Paged<()>>()),
+            })
+        }
+
+        let args = crate::args::Args::default();
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_numbers);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_page_size(10);
+        request.set_page_cursor(b"foo".to_vec());
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let status = transport.take_sent().into_iter()
+            .filter(|message| message.kind.as_deref() == Some("rrg.Response"))
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .find(|response| response.has_status())
+            .unwrap()
+            .take_status();
+        assert_eq! {
+            status.error().type_(),
+            rrg_proto::rrg::status::error::Type::INVALID_PAGE_CURSOR,
+        };
+    }
+
+    #[test]
+    fn registry_dispatch_audit_log() {
+        use protobuf::Message as _;
+        use crate::session::FleetspeakSession;
+
+        crate::log::init_test();
+
+        let args = crate::args::Args::default();
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, |session, request| {
+            handle(session, request, |_: &mut FleetspeakSession, ()| Ok(()))
+        });
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_flow_id(0xC0FFEE);
+        request.set_request_id(42);
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_log_level(rrg_proto::rrg::log::Level::INFO);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let logs = transport.take_sent().into_iter()
+            .filter(|message| message.kind.as_deref() == Some("rrg.Response"))
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .filter(|response| response.has_log())
+            .map(|response| String::from(response.log().message()))
+            .collect::<Vec<_>>();
+
+        assert! {
+            logs.iter().any(|log| log.starts_with("audit: action=get_client_actions flow_id=C0FFEE request_id=42")),
+            "no audit log in: {logs:?}"
+        };
+    }
+
+    #[test]
+    fn registry_dispatch_reply_limit() {
+        use protobuf::Message as _;
+        use crate::session::{FleetspeakSession, Session as _};
+
+        /// Handler that attempts to yield more replies than allowed.
+        fn handle_replies(
+            session: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            for _ in 0..10 {
+                session.reply(())?;
+            }
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            max_replies: Some(3),
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_replies);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = crate::Request::try_from(request);
+        FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let responses = transport.take_sent().into_iter()
+            .filter(|message| message.kind.as_deref() == Some("rrg.Response"))
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(responses.iter().filter(|response| response.has_result()).count(), 3);
+
+        let status = responses.iter()
+            .find(|response| response.has_status())
+            .unwrap()
+            .status();
+        assert_eq!(status.error().type_(), rrg_proto::rrg::status::error::Type::REPLY_LIMIT_EXCEEDED);
+        assert_eq!(status.reply_count(), 3);
+    }
+
+    #[test]
+    fn registry_serve_concurrent() {
+        use crate::session::FleetspeakSession;
+
+        /// Time spans in which invocations of the slow handler were executing.
+        static SPANS: std::sync::Mutex<Vec<(std::time::Instant, std::time::Instant)>> = {
+            std::sync::Mutex::new(Vec::new())
+        };
+
+        /// Handler that takes a while to finish and records when it ran.
+        fn handle_slow(
+            _: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            let start = std::time::Instant::now();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            let end = std::time::Instant::now();
+
+            SPANS.lock().unwrap().push((start, end));
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            action_concurrency: 2,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_slow);
+
+        let requests = (0..2).map(|_| {
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+            crate::Request::try_from(request)
+        });
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::serve(&args, &transport_dyn, &rate_limiter, &registry, requests);
+
+        let spans = SPANS.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        // Both handlers must have started before any of them finished.
+        let (start_0, end_0) = spans[0];
+        let (start_1, end_1) = spans[1];
+        assert!(start_0 < end_1 && start_1 < end_0);
+
+        // Each request yields a status regardless of the concurrency.
+        assert_eq!(transport.take_sent().len(), 2);
+    }
+
+    #[test]
+    fn registry_serve_concurrency_limit_queue() {
+        use crate::session::FleetspeakSession;
+        use protobuf::Message as _;
+
+        /// Time spans in which invocations of the slow handler were executing.
+        static SPANS: std::sync::Mutex<Vec<(std::time::Instant, std::time::Instant)>> = {
+            std::sync::Mutex::new(Vec::new())
+        };
+
+        /// Handler that takes a while to finish and records when it ran.
+        fn handle_slow(
+            _: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            let start = std::time::Instant::now();
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            let end = std::time::Instant::now();
+
+            SPANS.lock().unwrap().push((start, end));
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            action_concurrency: 3,
+            action_concurrency_limits: vec![(crate::request::Action::GetClientActions, 1)],
+            action_concurrency_overflow: crate::session::OverflowPolicy::Queue,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_slow);
+
+        let requests = (0..3).map(|_| {
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+            crate::Request::try_from(request)
+        });
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::serve(&args, &transport_dyn, &rate_limiter, &registry, requests);
+
+        let mut spans = SPANS.lock().unwrap().clone();
+        assert_eq!(spans.len(), 3);
+
+        // Despite enough workers, no invocations can overlap.
+        spans.sort();
+        for window in spans.windows(2) {
+            let (_, end_prev) = window[0];
+            let (start_next, _) = window[1];
+            assert!(end_prev <= start_next);
+        }
+
+        let statuses = transport.take_sent().into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .filter(|response| response.has_status())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses.len(), 3);
+        assert!(statuses.iter().all(|response| !response.status().has_error()));
+    }
+
+    #[cfg(feature = "action-get_file_contents")]
+    #[test]
+    fn registry_dispatch_bandwidth_compressed() {
+        use crate::session::FleetspeakSession;
+        use protobuf::Message as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"foobarbaz".repeat(64 * 1024))
+            .unwrap();
+
+        let args = crate::args::Args {
+            compression: crate::transport::Compression::Gzip,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetFileContents, |session, request| {
+            handle(session, request, self::get_file_contents::handle)
+        });
+
+        let mut args_proto = rrg_proto::get_file_contents::Args::new();
+        args_proto.set_path(tempfile.path().to_path_buf().into());
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_FILE_CONTENTS);
+        request.set_args(protobuf::well_known_types::any::Any::pack(&args_proto).unwrap());
+
+        let transport = std::sync::Arc::new(crate::transport::CompressingTransport::new(
+            crate::transport::LoopbackTransport::new(),
+            args.compression,
+        ));
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::dispatch(
+            &args,
+            &transport_dyn,
+            &rate_limiter,
+            &registry,
+            crate::Request::try_from(request),
+        );
+
+        let sent = transport.inner().take_sent();
+        assert!(sent.iter().any(|message| {
+            message.kind.as_deref() == Some("rrg.CompressedMessage")
+        }));
+
+        let status = sent.iter()
+            .filter(|message| message.kind.as_deref() == Some("rrg.Response"))
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .find(|response| response.has_status())
+            .unwrap();
+        let status = status.status();
+        assert!(!status.has_error());
+
+        assert_eq!(status.disk_bytes_read(), 9 * 64 * 1024);
+        assert!(status.network_bytes_sent() > status.disk_bytes_read());
+        assert!(status.wire_bytes_sent() < status.disk_bytes_read());
+        assert_eq!(status.wire_bytes_sent(), {
+            sent.iter()
+                .take(sent.len() - 1)
+                .map(|message| message.data.len() as u64)
+                .sum::<u64>()
+        });
+    }
+
+    #[test]
+    fn registry_serve_concurrency_limit_reject() {
+        use crate::session::FleetspeakSession;
+        use protobuf::Message as _;
+
+        /// Number of invocations of the slow handler.
+        static COUNT: std::sync::atomic::AtomicUsize = {
+            std::sync::atomic::AtomicUsize::new(0)
+        };
+
+        /// Handler that takes a while to finish and records that it ran.
+        fn handle_slow(
+            _: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            action_concurrency: 3,
+            action_concurrency_limits: vec![(crate::request::Action::GetClientActions, 1)],
+            action_concurrency_overflow: crate::session::OverflowPolicy::Reject,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_slow);
+
+        let requests = (0..3).map(|_| {
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+            crate::Request::try_from(request)
+        });
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::serve(&args, &transport_dyn, &rate_limiter, &registry, requests);
+
+        // All the requests are picked up at once by the workers, so only the
+        // first one gets to run and the others are rejected.
+        assert_eq!(COUNT.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let statuses = transport.take_sent().into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .filter(|response| response.has_status())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses.len(), 3);
+
+        let busy_count = statuses.iter()
+            .filter(|response| response.status().error().type_() == rrg_proto::rrg::status::error::Type::BUSY)
+            .count();
+        assert_eq!(busy_count, 2);
+    }
+
+    #[test]
+    fn registry_serve_request_queue_full() {
+        use crate::session::FleetspeakSession;
+        use protobuf::Message as _;
+
+        /// Number of invocations of the slow handler.
+        static COUNT: std::sync::atomic::AtomicUsize = {
+            std::sync::atomic::AtomicUsize::new(0)
+        };
+
+        /// Handler that takes a while to finish and records that it ran.
+        fn handle_slow(
+            _: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            action_concurrency: 2,
+            request_queue_size: 1,
+            request_queue_overflow: crate::session::OverflowPolicy::Reject,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_slow);
+
+        let requests = (0..4).map(|i| {
+            // We give the workers some time to pick up every request, so that
+            // the first two are running, the third one fills the queue and the
+            // fourth one does not fit into it anymore.
+            if i > 0 {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_request_id(i);
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+            crate::Request::try_from(request)
+        });
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::serve(&args, &transport_dyn, &rate_limiter, &registry, requests);
+
+        assert_eq!(COUNT.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let statuses = transport.take_sent().into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .filter(|response| response.has_status())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses.len(), 4);
+
+        let queue_full_ids = statuses.iter()
+            .filter(|response| response.status().error().type_() == rrg_proto::rrg::status::error::Type::QUEUE_FULL)
+            .map(|response| response.request_id())
+            .collect::<Vec<_>>();
+        assert_eq!(queue_full_ids, vec![3]);
+    }
+
+    #[test]
+    fn registry_serve_request_queue_overflow_queue() {
+        use crate::session::FleetspeakSession;
+        use protobuf::Message as _;
+
+        /// Handler that takes a while to finish.
+        fn handle_slow(
+            _: &mut FleetspeakSession,
+            _: crate::Request,
+        ) -> crate::session::Result<()> {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            Ok(())
+        }
+
+        let args = crate::args::Args {
+            action_concurrency: 2,
+            request_queue_size: 1,
+            request_queue_overflow: crate::session::OverflowPolicy::Queue,
+            ..Default::default()
+        };
+
+        let mut registry = Registry {
+            handlers: std::collections::HashMap::new(),
+        };
+        registry.register(crate::request::Action::GetClientActions, handle_slow);
+
+        let requests = (0..6).map(|_| {
+            let mut request = rrg_proto::rrg::Request::new();
+            request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+
+            crate::Request::try_from(request)
+        });
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        FleetspeakSession::serve(&args, &transport_dyn, &rate_limiter, &registry, requests);
+
+        // With the queueing policy, the collection of requests waits instead of
+        // rejecting them.
+        let statuses = transport.take_sent().into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .filter(|response| response.has_status())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses.len(), 6);
+        assert!(statuses.iter().all(|response| !response.status().has_error()));
+    }
 }
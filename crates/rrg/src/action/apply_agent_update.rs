@@ -0,0 +1,1097 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Installation of new versions of the agent.
+//!
+//! Because replacing the agent executable gives whoever controls the update
+//! full control over the system, this action is disabled unless explicitly
+//! allowed and installs only executables signed with the key the agent was
+//! configured with (through the `--update-verification-key` flag). The signed
+//! manifest of the update also binds it to a version, a target and an expiry
+//! time, so that old or foreign updates cannot be installed.
+//!
+//! The new executable is transferred as content-addressed blobs which are kept
+//! in the temporary directory of the agent until all of them arrive (possibly
+//! with multiple requests). The executable is then assembled and staged next to
+//! the current one (so that it is on the same filesystem) and renamed into
+//! place, which atomically swaps the executables. The running agent keeps using
+//! the old one until it exits, after which Fleetspeak starts the new one. On
+//! Windows, executables of running processes cannot be replaced but they can be
+//! renamed, so the old one is moved aside first and removed once the new agent
+//! starts.
+
+use std::path::{Path, PathBuf};
+
+/// Extension of the file the new executable is staged in.
+const STAGED_EXTENSION: &str = "update";
+
+/// Extension of the file the old executable is moved to on Windows.
+#[cfg(target_family = "windows")]
+const OLD_EXTENSION: &str = "old";
+
+/// Name of the directory (within the temporary one) with the received blobs.
+const BLOB_DIR_NAME: &str = "update-blobs";
+
+/// Target triple the agent is built for.
+const TARGET: &str = env!("RRG_TARGET");
+
+/// Arguments of the `apply_agent_update` action.
+pub struct Args {
+    /// Contents of blobs of the new agent executable.
+    blobs: Vec<Vec<u8>>,
+    /// Serialized manifest of the update (exactly as it was signed).
+    manifest: Vec<u8>,
+    /// Ed25519 signature of the serialized manifest.
+    signature: Vec<u8>,
+    /// Whether to only verify and stage the update without installing it.
+    dry_run: bool,
+}
+
+/// Manifest of an update, parsed only once its signature is verified.
+struct Manifest {
+    /// SHA-256 digest of the new agent executable.
+    sha256: [u8; 32],
+    /// Length of the new agent executable in bytes.
+    len: u64,
+    /// SHA-256 digests of consecutive blobs of the new agent executable.
+    blob_sha256s: Vec<[u8; 32]>,
+    /// Version of the new agent.
+    version: Version,
+    /// Target triple the new agent is built for.
+    target: String,
+    /// Time after which the update is not accepted anymore.
+    expiry_time: std::time::SystemTime,
+}
+
+/// Version of the agent (`x.y.z-pre`).
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    /// Major component of the version.
+    major: u32,
+    /// Minor component of the version.
+    minor: u32,
+    /// Patch component of the version.
+    patch: u32,
+    /// Pre-release label of the version (empty for releases).
+    pre: String,
+}
+
+/// Result of the `apply_agent_update` action.
+struct Item {
+    /// Path to the agent executable that is (or is going to be) replaced.
+    path: PathBuf,
+    /// Path at which the new executable was staged (if it was).
+    staged_path: Option<PathBuf>,
+    /// SHA-256 digests of blobs that are still needed to install the update.
+    missing_blob_sha256s: Vec<[u8; 32]>,
+    /// Whether nothing was actually installed.
+    dry_run: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ApplyAgentUpdate;
+
+/// Handles invocations of the `apply_agent_update` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    // All the checks that do not need the filesystem are done before anything
+    // is written, so that a bogus update never touches the disk.
+    let verification_key = session.args().update_verification_key.as_ref();
+    let manifest = verify(&args, verification_key, std::time::SystemTime::now())
+        .map_err(crate::session::Error::action)?;
+
+    let path = std::env::current_exe()
+        .map_err(crate::session::Error::action)?;
+
+    // Blobs of other updates (e.g. abandoned ones) are never going to be used,
+    // so there is no point in keeping them around.
+    let blob_dir = crate::temp_dir(session.args())
+        .map_err(crate::session::Error::action)?
+        .join(BLOB_DIR_NAME);
+    remove_stale_blobs(&blob_dir, &manifest);
+
+    if let Err(error) = store_blobs(&blob_dir, &args.blobs) {
+        remove_blob_dir(&blob_dir);
+        return Err(crate::session::Error::action(error));
+    }
+
+    let missing_blob_sha256s = manifest.blob_sha256s.iter()
+        .filter(|sha256| !blob_path(&blob_dir, sha256).exists())
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing_blob_sha256s.is_empty() {
+        session.reply(Item {
+            path,
+            staged_path: None,
+            missing_blob_sha256s,
+            dry_run: args.dry_run,
+        })?;
+
+        return Ok(());
+    }
+
+    // The blobs are not needed anymore once the executable is assembled. If
+    // the assembly fails, they are removed as well: they are either corrupted
+    // or do not make up the executable that was signed.
+    let staged_path = stage(&path, &blob_dir, &manifest);
+    remove_blob_dir(&blob_dir);
+
+    let staged_path = staged_path
+        .map_err(crate::session::Error::action)?;
+
+    if args.dry_run {
+        if let Err(error) = std::fs::remove_file(&staged_path) {
+            log::warn!("failed to remove staged update '{}': {error}", staged_path.display());
+        }
+
+        session.reply(Item {
+            path,
+            staged_path: Some(staged_path),
+            missing_blob_sha256s: Vec::new(),
+            dry_run: true,
+        })?;
+
+        return Ok(());
+    }
+
+    if let Err(error) = install(&staged_path, &path) {
+        if let Err(error) = std::fs::remove_file(&staged_path) {
+            log::warn!("failed to remove staged update '{}': {error}", staged_path.display());
+        }
+
+        return Err(crate::session::Error::action(error));
+    }
+
+    log::info!("installed agent update at '{}'", path.display());
+
+    session.reply(Item {
+        path,
+        staged_path: Some(staged_path),
+        missing_blob_sha256s: Vec::new(),
+        dry_run: false,
+    })?;
+
+    crate::request_restart();
+
+    Ok(())
+}
+
+/// Removes the executable left behind by an update installed on Windows.
+///
+/// This should be called when the agent starts, as the old executable cannot
+/// be removed for as long as the old agent is running.
+#[cfg(target_family = "windows")]
+pub fn remove_old_executable() {
+    let path = match std::env::current_exe() {
+        Ok(path) => old_path(&path),
+        Err(error) => {
+            log::warn!("failed to determine the agent executable: {error}");
+            return;
+        }
+    };
+
+    match std::fs::remove_file(&path) {
+        Ok(()) => log::info!("removed old agent executable '{}'", path.display()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => (),
+        Err(error) => {
+            log::warn!("failed to remove old agent executable '{}': {error}", path.display());
+        }
+    }
+}
+
+/// Verifies the manifest of the update and the digests of the given blobs.
+///
+/// Returns the parsed manifest if the update can be installed at `now`.
+fn verify(
+    args: &Args,
+    verification_key: Option<&crate::signed::VerifyingKey>,
+    now: std::time::SystemTime,
+) -> Result<Manifest, VerifyError> {
+    use sha2::Digest as _;
+
+    crate::signed::verify_signed(verification_key, &args.manifest, &args.signature)
+        .map_err(VerifyError::Signature)?;
+
+    // Only now that we know the manifest comes from the server, we look inside.
+    let manifest = Manifest::parse(&args.manifest)
+        .map_err(VerifyError::Manifest)?;
+
+    if manifest.target != TARGET {
+        return Err(VerifyError::Target(manifest.target));
+    }
+    if !manifest.version.is_newer_than(&Version::current()) {
+        return Err(VerifyError::Version(manifest.version));
+    }
+    if manifest.expiry_time < now {
+        return Err(VerifyError::Expired);
+    }
+
+    // Only blobs of the signed update are accepted, so that the request cannot
+    // be used to write arbitrary data to the disk.
+    for blob in &args.blobs {
+        let sha256 = <[u8; 32]>::from(sha2::Sha256::digest(blob));
+        if !manifest.blob_sha256s.contains(&sha256) {
+            return Err(VerifyError::UnexpectedBlob);
+        }
+    }
+
+    Ok(manifest)
+}
+
+impl Manifest {
+
+    /// Parses a manifest from its serialized protobuf representation.
+    fn parse(bytes: &[u8]) -> std::io::Result<Manifest> {
+        use protobuf::Message as _;
+        use std::io::{Error, ErrorKind};
+
+        let mut proto = rrg_proto::apply_agent_update::Manifest::parse_from_bytes(bytes)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        let sha256 = <[u8; 32]>::try_from(proto.sha256())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, DigestLenError {
+                len: proto.sha256().len(),
+            }))?;
+
+        let blob_sha256s = proto.blob_sha256s.iter()
+            .map(|sha256| <[u8; 32]>::try_from(&sha256[..]).map_err(|_| {
+                Error::new(ErrorKind::InvalidData, DigestLenError {
+                    len: sha256.len(),
+                })
+            }))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut version = proto.version.take()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing version"))?;
+
+        let expiry_time = proto.expiry_time.take()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing expiry time"))?;
+        let expiry_time = u64::try_from(expiry_time.seconds)
+            .ok()
+            .and_then(|secs| {
+                std::time::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs))
+            })
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid expiry time"))?;
+
+        Ok(Manifest {
+            sha256,
+            len: proto.length(),
+            blob_sha256s,
+            version: Version {
+                major: version.major(),
+                minor: version.minor(),
+                patch: version.patch(),
+                pre: version.take_pre(),
+            },
+            target: proto.take_target(),
+            expiry_time,
+        })
+    }
+}
+
+impl Version {
+
+    /// Returns the version of the running agent.
+    fn current() -> Version {
+        Version {
+            major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+            minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+            patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+            pre: String::from(env!("CARGO_PKG_VERSION_PRE")),
+        }
+    }
+
+    /// Returns whether this version is newer than the `other` one.
+    ///
+    /// Versions are ordered by their numeric components. A pre-release is older
+    /// than the release of the same version, but pre-releases of the same
+    /// version are not comparable (their labels are free-form), so none of them
+    /// is considered newer than another.
+    fn is_newer_than(&self, other: &Version) -> bool {
+        use std::cmp::Ordering::*;
+
+        let numbers = (self.major, self.minor, self.patch);
+        let other_numbers = (other.major, other.minor, other.patch);
+
+        match numbers.cmp(&other_numbers) {
+            Greater => true,
+            Less => false,
+            Equal => self.pre.is_empty() && !other.pre.is_empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for Version {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(fmt, "-{}", self.pre)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes blobs that do not belong to the update from the blob directory.
+///
+/// This also removes partially written blobs left behind by failed requests.
+fn remove_stale_blobs(blob_dir: &Path, manifest: &Manifest) {
+    let entries = match std::fs::read_dir(blob_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return,
+        Err(error) => {
+            log::warn!("failed to list update blobs in '{}': {error}", blob_dir.display());
+            return;
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                log::warn!("failed to list update blobs in '{}': {error}", blob_dir.display());
+                continue;
+            }
+        };
+
+        let is_used = manifest.blob_sha256s.iter()
+            .any(|sha256| blob_path(blob_dir, sha256) == path);
+        if is_used {
+            continue;
+        }
+
+        if let Err(error) = std::fs::remove_file(&path) {
+            log::warn!("failed to remove stale update blob '{}': {error}", path.display());
+        }
+    }
+}
+
+/// Removes the blob directory along with all the blobs in it.
+fn remove_blob_dir(blob_dir: &Path) {
+    match std::fs::remove_dir_all(blob_dir) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            log::warn!("failed to remove update blobs '{}': {error}", blob_dir.display());
+        }
+        _ => (),
+    }
+}
+
+/// Returns the path at which the blob with the given digest is stored.
+fn blob_path(blob_dir: &Path, sha256: &[u8; 32]) -> PathBuf {
+    blob_dir.join(sha256.iter().map(|byte| format!("{byte:02x}")).collect::<String>())
+}
+
+/// Writes the given (already verified) blobs to the blob directory.
+///
+/// Each blob is written to a temporary file first and renamed afterwards, so
+/// that the presence of a blob file means that the blob is complete.
+fn store_blobs(blob_dir: &Path, blobs: &[Vec<u8>]) -> std::io::Result<()> {
+    use sha2::Digest as _;
+
+    crate::fs::create_private_dir(blob_dir)?;
+
+    for blob in blobs {
+        let path = blob_path(blob_dir, &sha2::Sha256::digest(blob).into());
+
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(".part");
+
+        std::fs::write(&part_path, blob)?;
+        std::fs::rename(&part_path, &path)?;
+    }
+
+    Ok(())
+}
+
+/// Assembles the new executable from the blobs and writes it next to the one
+/// at `path`.
+///
+/// The written file is read back and its digest is verified before it is
+/// considered staged. Returns the path of the staged executable.
+fn stage(path: &Path, blob_dir: &Path, manifest: &Manifest) -> std::io::Result<PathBuf> {
+    let mut staged_path = path.as_os_str().to_os_string();
+    staged_path.push(".");
+    staged_path.push(STAGED_EXTENSION);
+    let staged_path = PathBuf::from(staged_path);
+
+    let result = write_staged(&staged_path, blob_dir, manifest)
+        .and_then(|()| verify_staged(&staged_path, &manifest.sha256));
+
+    if let Err(error) = result {
+        if let Err(error) = std::fs::remove_file(&staged_path) {
+            log::warn!("failed to remove staged update '{}': {error}", staged_path.display());
+        }
+
+        return Err(error);
+    }
+
+    Ok(staged_path)
+}
+
+/// Writes the concatenated blobs of the update to `staged_path`.
+fn write_staged(staged_path: &Path, blob_dir: &Path, manifest: &Manifest) -> std::io::Result<()> {
+    use sha2::Digest as _;
+    use std::io::Write as _;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        // Leftovers of earlier failed updates are overwritten, but we do not
+        // want to follow symlinks planted in their place.
+        options.mode(0o755);
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+
+    let mut file = options.open(staged_path)?;
+
+    // If the file already existed, the mode passed to `open` had no effect.
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        file.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    let mut len = 0;
+    for sha256 in &manifest.blob_sha256s {
+        use std::io::{Error, ErrorKind};
+
+        let blob_path = blob_path(blob_dir, sha256);
+        let blob = std::fs::read(&blob_path)?;
+
+        // Blobs are verified when they are received, but they spent some time
+        // on the disk since then.
+        if <[u8; 32]>::from(sha2::Sha256::digest(&blob)) != *sha256 {
+            // The blob is removed, so that it is reported as missing the next
+            // time and can be sent again.
+            if let Err(error) = std::fs::remove_file(&blob_path) {
+                log::warn!("failed to remove update blob '{}': {error}", blob_path.display());
+            }
+
+            return Err(Error::new(ErrorKind::InvalidData, "update blob digest mismatch"));
+        }
+
+        file.write_all(&blob)?;
+        len += blob.len() as u64;
+    }
+
+    if len != manifest.len {
+        use std::io::{Error, ErrorKind};
+
+        return Err(Error::new(ErrorKind::InvalidData, format! {
+            "update length mismatch (expected {}, got {len})", manifest.len
+        }));
+    }
+
+    file.sync_all()
+}
+
+/// Verifies that the staged executable at `path` has the expected digest.
+fn verify_staged(path: &Path, sha256: &[u8; 32]) -> std::io::Result<()> {
+    use sha2::Digest as _;
+
+    let mut file = std::fs::File::open(path)?;
+
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+
+    if <[u8; 32]>::from(hasher.finalize()) != *sha256 {
+        use std::io::{Error, ErrorKind};
+
+        return Err(Error::new(ErrorKind::InvalidData, "staged update digest mismatch"));
+    }
+
+    Ok(())
+}
+
+/// Replaces the executable at `path` with the one staged at `staged_path`.
+#[cfg(target_family = "unix")]
+fn install(staged_path: &Path, path: &Path) -> std::io::Result<()> {
+    // Renames within a single filesystem are atomic and do not affect the
+    // running process, which keeps the old executable open.
+    std::fs::rename(staged_path, path)
+}
+
+/// Replaces the executable at `path` with the one staged at `staged_path`.
+///
+/// The old executable is moved aside (see [`remove_old_executable`]).
+#[cfg(target_family = "windows")]
+fn install(staged_path: &Path, path: &Path) -> std::io::Result<()> {
+    let old_path = old_path(path);
+
+    // There might be a leftover of an earlier update if the agent could not
+    // remove it when it started.
+    match std::fs::remove_file(&old_path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+        _ => (),
+    }
+
+    // Executables of running processes cannot be overwritten or removed, but
+    // they can be renamed.
+    std::fs::rename(path, &old_path)?;
+
+    if let Err(error) = std::fs::rename(staged_path, path) {
+        // We have to put the old executable back, otherwise there would be no
+        // agent to start at all.
+        if let Err(error) = std::fs::rename(&old_path, path) {
+            log::error!("failed to restore agent executable '{}': {error}", path.display());
+        }
+
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Returns the path the old executable at `path` is moved to on Windows.
+#[cfg(target_family = "windows")]
+fn old_path(path: &Path) -> PathBuf {
+    let mut old_path = path.as_os_str().to_os_string();
+    old_path.push(".");
+    old_path.push(OLD_EXTENSION);
+
+    PathBuf::from(old_path)
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::apply_agent_update::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            blobs: std::mem::take(&mut proto.blobs),
+            manifest: proto.take_manifest(),
+            signature: proto.take_signature(),
+            dry_run: proto.dry_run(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::apply_agent_update::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        if let Some(staged_path) = self.staged_path {
+            proto.set_staged_path(staged_path.into());
+        }
+        proto.set_dry_run(self.dry_run);
+        proto.set_missing_blob_sha256s(self.missing_blob_sha256s.into_iter()
+            .map(Vec::from)
+            .collect());
+
+        proto
+    }
+}
+
+/// An error indicating that an update cannot be installed.
+#[derive(Debug)]
+enum VerifyError {
+    /// One of the blobs does not belong to the update.
+    UnexpectedBlob,
+    /// The signature of the update cannot be accepted.
+    Signature(crate::signed::SignedError),
+    /// The manifest of the update is malformed.
+    Manifest(std::io::Error),
+    /// The update is built for another target (the one given).
+    Target(String),
+    /// The update is not newer than the running agent (it is of the given version).
+    Version(Version),
+    /// The update is not accepted anymore.
+    Expired,
+}
+
+impl std::fmt::Display for VerifyError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use VerifyError::*;
+
+        match self {
+            UnexpectedBlob => write!(fmt, "blob not belonging to the update"),
+            Signature(error) => write!(fmt, "update signature not accepted: {error}"),
+            Manifest(error) => write!(fmt, "malformed update manifest: {error}"),
+            Target(target) => {
+                write!(fmt, "update for target '{target}' (instead of '{TARGET}')")
+            }
+            Version(version) => {
+                write!(fmt, "update version {version} not newer than {}", self::Version::current())
+            }
+            Expired => write!(fmt, "expired update"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VerifyError::Signature(error) => Some(error),
+            VerifyError::Manifest(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// An error indicating that a digest of the update has invalid length.
+#[derive(Debug)]
+struct DigestLenError {
+    /// Length of the provided digest.
+    len: usize,
+}
+
+impl std::fmt::Display for DigestLenError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "invalid SHA-256 digest length: {}", self.len)
+    }
+}
+
+impl std::error::Error for DigestLenError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Seed of the private key used to sign test updates.
+    const TEST_SEED: [u8; 32] = [0x72; 32];
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest as _;
+
+        sha2::Sha256::digest(data).into()
+    }
+
+    /// Returns a manifest of an update consisting of the given blobs.
+    ///
+    /// The update is of a version newer than the running agent.
+    fn test_manifest(blobs: &[&[u8]]) -> rrg_proto::apply_agent_update::Manifest {
+        let executable = blobs.concat();
+
+        let mut manifest = rrg_proto::apply_agent_update::Manifest::new();
+        manifest.set_sha256(sha256(&executable).to_vec());
+        manifest.set_length(executable.len() as u64);
+        manifest.set_blob_sha256s(blobs.iter().map(|blob| sha256(blob).to_vec()).collect());
+        manifest.mut_version().set_major(Version::current().major + 1);
+        manifest.set_target(String::from(TARGET));
+        manifest.set_expiry_time(rrg_proto::into_timestamp({
+            std::time::SystemTime::now() + std::time::Duration::from_secs(60 * 60)
+        }));
+
+        manifest
+    }
+
+    /// Returns signed arguments of an update with the given manifest.
+    fn sign(
+        manifest: &rrg_proto::apply_agent_update::Manifest,
+        blobs: &[&[u8]],
+        dry_run: bool,
+    ) -> Args {
+        use protobuf::Message as _;
+
+        let manifest = manifest.write_to_bytes()
+            .unwrap();
+
+        Args {
+            blobs: blobs.iter().map(|blob| blob.to_vec()).collect(),
+            signature: crate::signed::sign(&TEST_SEED, &manifest).to_vec(),
+            manifest,
+            dry_run,
+        }
+    }
+
+    /// Returns arguments of an update consisting of the given blobs.
+    ///
+    /// Only blobs for which `sent` is `true` are included in the request.
+    fn test_args(blobs: &[(&[u8], bool)], dry_run: bool) -> Args {
+        let manifest = test_manifest(&blobs.iter()
+            .map(|(blob, _)| *blob)
+            .collect::<Vec<_>>());
+
+        let sent_blobs = blobs.iter()
+            .filter(|(_, sent)| *sent)
+            .map(|(blob, _)| *blob)
+            .collect::<Vec<_>>();
+
+        sign(&manifest, &sent_blobs, dry_run)
+    }
+
+    fn session_with_key(temp_dir: &Path) -> crate::session::FakeSession {
+        let args = crate::args::Args {
+            update_verification_key: Some(crate::signed::public_key(&TEST_SEED)),
+            temp_dir: temp_dir.to_path_buf(),
+            ..Default::default()
+        };
+
+        crate::session::FakeSession::with_args(args)
+    }
+
+    /// Lock held by tests staging updates next to the test executable (as they
+    /// would interfere with each other otherwise).
+    static STAGED_PATH_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    fn staged_path() -> PathBuf {
+        let mut staged_path = std::env::current_exe()
+            .unwrap()
+            .into_os_string();
+        staged_path.push(".update");
+
+        PathBuf::from(staged_path)
+    }
+
+    fn version(major: u32, minor: u32, patch: u32, pre: &str) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: String::from(pre),
+        }
+    }
+
+    #[test]
+    fn handle_dry_run() {
+        let _guard = STAGED_PATH_LOCK.lock()
+            .unwrap();
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let exe = std::env::current_exe()
+            .unwrap();
+        let exe_len = exe.metadata()
+            .unwrap()
+            .len();
+
+        let mut session = session_with_key(tempdir.path());
+        let args = test_args(&[(b"foo", true), (b"bar", false)], true);
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, exe);
+        assert_eq!(item.staged_path, None);
+        assert_eq!(item.missing_blob_sha256s, vec![sha256(b"bar")]);
+        assert!(item.dry_run);
+        assert!(!staged_path().exists());
+
+        // The blob sent before does not have to be sent again.
+        let args = test_args(&[(b"foo", false), (b"bar", true)], true);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.path, exe);
+        assert_eq!(item.staged_path, Some(staged_path()));
+        assert!(item.missing_blob_sha256s.is_empty());
+        assert!(item.dry_run);
+
+        // The staged executable is removed and the test binary left intact.
+        assert!(!staged_path().exists());
+        assert_eq!(exe.metadata().unwrap().len(), exe_len);
+
+        // The blobs are not needed anymore.
+        assert!(!tempdir.path().join(BLOB_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn handle_stale_blobs() {
+        let _guard = STAGED_PATH_LOCK.lock()
+            .unwrap();
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut session = session_with_key(tempdir.path());
+
+        // Blobs of an update that was never completed.
+        let args = test_args(&[(b"foo", true), (b"bar", false)], true);
+        assert!(handle(&mut session, args).is_ok());
+
+        let args = test_args(&[(b"baz", true), (b"quux", false)], true);
+        assert!(handle(&mut session, args).is_ok());
+
+        let blob_dir = tempdir.path().join(BLOB_DIR_NAME);
+        assert!(!blob_path(&blob_dir, &sha256(b"foo")).exists());
+        assert!(blob_path(&blob_dir, &sha256(b"baz")).exists());
+    }
+
+    #[test]
+    fn handle_stage_failure() {
+        let _guard = STAGED_PATH_LOCK.lock()
+            .unwrap();
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut manifest = test_manifest(&[b"foo", b"bar"]);
+        manifest.set_length(1337);
+
+        let mut session = session_with_key(tempdir.path());
+        assert!(handle(&mut session, sign(&manifest, &[b"foo", b"bar"], true)).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!staged_path().exists());
+        assert!(!tempdir.path().join(BLOB_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn handle_unexpected_blob() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut args = test_args(&[(b"foo", true)], true);
+        args.blobs.push(b"bar".to_vec());
+
+        let mut session = session_with_key(tempdir.path());
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!tempdir.path().join(BLOB_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn handle_unsigned() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut args = test_args(&[(b"foo", true)], false);
+        args.signature.clear();
+
+        let mut session = session_with_key(tempdir.path());
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!tempdir.path().join(BLOB_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn handle_invalid_signature() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut args = test_args(&[(b"foo", true)], false);
+        args.signature[0] ^= 0xff;
+
+        let mut session = session_with_key(tempdir.path());
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!tempdir.path().join(BLOB_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn handle_no_verification_key() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, test_args(&[(b"foo", true)], false)).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn verify_ok() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let args = test_args(&[(b"foo", true), (b"bar", false)], false);
+        let manifest = verify(&args, Some(&key), std::time::SystemTime::now())
+            .unwrap();
+
+        assert_eq!(manifest.sha256, sha256(b"foobar"));
+        assert_eq!(manifest.len, 6);
+        assert_eq!(manifest.blob_sha256s, vec![sha256(b"foo"), sha256(b"bar")]);
+    }
+
+    #[test]
+    fn verify_unsigned() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let mut args = test_args(&[(b"foo", true)], false);
+        args.signature.clear();
+
+        assert!(matches! {
+            verify(&args, Some(&key), std::time::SystemTime::now()),
+            Err(VerifyError::Signature(crate::signed::SignedError::Unsigned))
+        });
+    }
+
+    #[test]
+    fn verify_manifest_not_signed() {
+        use protobuf::Message as _;
+
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let mut args = test_args(&[(b"foo", true)], false);
+        args.manifest = test_manifest(&[b"bar"]).write_to_bytes().unwrap();
+
+        assert! {
+            matches! {
+                verify(&args, Some(&key), std::time::SystemTime::now()),
+                Err(VerifyError::Signature(_))
+            }
+        };
+    }
+
+    #[test]
+    fn verify_other_target() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let mut manifest = test_manifest(&[b"foo"]);
+        manifest.set_target(String::from("mips-unknown-linux-gnu"));
+
+        let args = sign(&manifest, &[b"foo"], false);
+        assert!(matches! {
+            verify(&args, Some(&key), std::time::SystemTime::now()),
+            Err(VerifyError::Target(_))
+        });
+    }
+
+    #[test]
+    fn verify_current_version() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let current = Version::current();
+
+        let mut manifest = test_manifest(&[b"foo"]);
+        manifest.mut_version().set_major(current.major);
+        manifest.mut_version().set_minor(current.minor);
+        manifest.mut_version().set_patch(current.patch);
+        manifest.mut_version().set_pre(current.pre);
+
+        let args = sign(&manifest, &[b"foo"], false);
+        assert!(matches! {
+            verify(&args, Some(&key), std::time::SystemTime::now()),
+            Err(VerifyError::Version(_))
+        });
+    }
+
+    #[test]
+    fn verify_expired() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let args = test_args(&[(b"foo", true)], false);
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(2 * 60 * 60);
+
+        assert!(matches!(verify(&args, Some(&key), later), Err(VerifyError::Expired)));
+    }
+
+    #[test]
+    fn verify_missing_expiry_time() {
+        let key = crate::signed::public_key(&TEST_SEED);
+
+        let mut manifest = test_manifest(&[b"foo"]);
+        manifest.clear_expiry_time();
+
+        let args = sign(&manifest, &[b"foo"], false);
+        assert!(matches! {
+            verify(&args, Some(&key), std::time::SystemTime::now()),
+            Err(VerifyError::Manifest(_))
+        });
+    }
+
+    #[test]
+    fn version_is_newer_than() {
+        assert!(version(1, 0, 0, "").is_newer_than(&version(0, 9, 9, "")));
+        assert!(version(0, 2, 0, "").is_newer_than(&version(0, 1, 9, "")));
+        assert!(version(0, 1, 2, "").is_newer_than(&version(0, 1, 1, "")));
+        assert!(version(0, 1, 0, "").is_newer_than(&version(0, 1, 0, "beta")));
+
+        assert!(!version(0, 1, 0, "").is_newer_than(&version(0, 1, 0, "")));
+        assert!(!version(0, 1, 0, "beta").is_newer_than(&version(0, 1, 0, "")));
+        assert!(!version(0, 1, 0, "beta.2").is_newer_than(&version(0, 1, 0, "beta.1")));
+        assert!(!version(0, 9, 9, "").is_newer_than(&version(1, 0, 0, "")));
+    }
+
+    #[test]
+    fn stage_and_install() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("rrg");
+        std::fs::write(&path, b"old")
+            .unwrap();
+
+        let args = test_args(&[(b"ne", true), (b"w", true)], false);
+        let manifest = Manifest::parse(&args.manifest)
+            .unwrap();
+
+        let blob_dir = tempdir.path().join(BLOB_DIR_NAME);
+        store_blobs(&blob_dir, &args.blobs)
+            .unwrap();
+
+        let staged_path = stage(&path, &blob_dir, &manifest)
+            .unwrap();
+
+        assert_eq!(staged_path, tempdir.path().join("rrg.update"));
+        assert_eq!(std::fs::read(&staged_path).unwrap(), b"new");
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let metadata = staged_path.metadata()
+                .unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+
+        install(&staged_path, &path)
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!staged_path.exists());
+    }
+
+    #[test]
+    fn stage_length_mismatch() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("rrg");
+
+        let args = test_args(&[(b"new", true)], false);
+        let mut manifest = Manifest::parse(&args.manifest)
+            .unwrap();
+        manifest.len = 4;
+
+        let blob_dir = tempdir.path().join(BLOB_DIR_NAME);
+        store_blobs(&blob_dir, &args.blobs)
+            .unwrap();
+
+        assert!(stage(&path, &blob_dir, &manifest).is_err());
+        assert!(!tempdir.path().join("rrg.update").exists());
+    }
+
+    #[test]
+    fn stage_corrupted_blob() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("rrg");
+
+        let args = test_args(&[(b"new", true)], false);
+        let manifest = Manifest::parse(&args.manifest)
+            .unwrap();
+
+        let blob_dir = tempdir.path().join(BLOB_DIR_NAME);
+        store_blobs(&blob_dir, &args.blobs)
+            .unwrap();
+        std::fs::write(blob_path(&blob_dir, &sha256(b"new")), b"bad")
+            .unwrap();
+
+        assert!(stage(&path, &blob_dir, &manifest).is_err());
+        assert!(!tempdir.path().join("rrg.update").exists());
+
+        // The corrupted blob should be reported as missing next time.
+        assert!(!blob_path(&blob_dir, &sha256(b"new")).exists());
+    }
+}
@@ -0,0 +1,672 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of artifacts composed of multiple sources.
+//!
+//! An artifact is a named group of sources, each of which is collected using
+//! one of the existing actions. Results of these actions are tagged with the
+//! name of the source that produced them and sent back as results of this
+//! action.
+//!
+//! Sources are subject to the same restrictions as the actions collecting them
+//! would be if invoked directly: a source using an action that is not permitted
+//! by the agent configuration fails. In particular, commands are executed only
+//! if they are signed and the `execute_signed_command` action is explicitly
+//! allowed.
+
+use std::path::PathBuf;
+
+/// Arguments of the `collect_artifact` action.
+pub struct Args {
+    /// Sources that make up the artifact to collect.
+    sources: Vec<Source>,
+}
+
+/// Individual source of an artifact.
+struct Source {
+    /// Name of the source used to tag the collected results.
+    name: String,
+    /// Specification of what to collect.
+    kind: SourceKind,
+}
+
+/// Specification of what an artifact source collects.
+enum SourceKind {
+    /// Metadata of files matching the given glob patterns.
+    File {
+        path_globs: Vec<PathBuf>,
+    },
+    /// Values of the specified Windows Registry key.
+    WinregValues {
+        #[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+        args: rrg_proto::list_winreg_values::Args,
+    },
+    /// Command signed by the server to execute.
+    Command {
+        #[cfg_attr(not(feature = "action-execute_signed_command"), allow(dead_code))]
+        args: rrg_proto::execute_signed_command::Args,
+    },
+}
+
+/// Result of the `collect_artifact` action.
+struct Item {
+    /// Name of the source that produced the result.
+    source: String,
+    /// Result of the underlying action or an error message if it failed.
+    result: Result<protobuf::well_known_types::any::Any, String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::CollectArtifact;
+
+/// Handles invocations of the `collect_artifact` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for source in args.sources {
+        let mut source_session = SourceSession {
+            session: &mut *session,
+            source: &source.name,
+        };
+
+        let error = match collect(&mut source_session, source.kind) {
+            Ok(()) => continue,
+            Err(error) => error,
+        };
+
+        use crate::session::ErrorKind::*;
+        match error.kind() {
+            // Limits apply to the whole request and not to individual sources,
+            // so there is no point in continuing.
+            NetworkBytesLimitExceeded | RealTimeLimitExceeded | DeadlineExceeded => {
+                return Err(error);
+            }
+            _ => {
+                log::warn!("failed to collect source '{}': {:#}", source.name, error);
+            }
+        }
+
+        session.reply_tagged(&crate::session::Tag::new(&source.name), Item {
+            source: source.name,
+            result: Err(format!("{error:#}")),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Collects a single artifact source using appropriate action handlers.
+fn collect<S>(session: &mut S, kind: SourceKind) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use crate::request::Action;
+
+    match kind {
+        SourceKind::File { path_globs } => {
+            ensure_permitted(session, Action::GetFileMetadata)?;
+
+            for path_glob in path_globs {
+                let paths = crate::fs::glob(&path_glob)
+                    .map_err(crate::session::Error::action)?;
+
+                for path in paths {
+                    session.check_deadline()?;
+
+                    let mut args = rrg_proto::get_file_metadata::Args::new();
+                    args.set_path(path.into());
+
+                    invoke(session, args, crate::action::get_file_metadata::handle)?;
+                }
+            }
+
+            Ok(())
+        }
+        #[cfg(target_family = "windows")]
+        SourceKind::WinregValues { args } => {
+            ensure_permitted(session, Action::ListWinregValues)?;
+            invoke(session, args, crate::action::list_winreg_values::handle)
+        }
+        #[cfg(not(target_family = "windows"))]
+        SourceKind::WinregValues { args: _ } => {
+            use std::io::{Error, ErrorKind};
+            Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+        }
+        #[cfg(feature = "action-execute_signed_command")]
+        SourceKind::Command { args } => {
+            ensure_permitted(session, Action::ExecuteSignedCommand)?;
+            invoke(session, args, crate::action::execute_signed_command::handle)
+        }
+        #[cfg(not(feature = "action-execute_signed_command"))]
+        SourceKind::Command { args: _ } => {
+            Err(crate::session::Error::unsupported_action(Action::ExecuteSignedCommand))
+        }
+    }
+}
+
+/// Verifies that the agent configuration permits the given `action`.
+///
+/// Sources must not allow to sidestep the allow and deny lists of actions, so
+/// this should be checked before invoking any of the action handlers.
+fn ensure_permitted<S>(session: &S, action: crate::request::Action) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if !crate::action::is_permitted(session.args(), action) {
+        return Err(crate::session::Error::unsupported_action(action));
+    }
+
+    Ok(())
+}
+
+/// Invokes the given action `handler` with arguments given as a proto message.
+fn invoke<S, A, H>(session: &mut S, args: A::Proto, handler: H) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+    A: crate::request::Args,
+    H: FnOnce(&mut S, A) -> crate::session::Result<()>,
+{
+    handler(session, A::from_proto(args)?)
+}
+
+/// Session wrapper tagging all replies with the name of the artifact source.
+struct SourceSession<'s, S> {
+    /// Underlying session of the `collect_artifact` action.
+    session: &'s mut S,
+    /// Name of the source to tag the replies with.
+    source: &'s str,
+}
+
+impl<'s, S> crate::session::Session for SourceSession<'s, S>
+where
+    S: crate::session::Session,
+{
+    fn args(&self) -> &crate::args::Args {
+        self.session.args()
+    }
+
+    fn deadline(&self) -> Option<std::time::SystemTime> {
+        self.session.deadline()
+    }
+
+    fn check_deadline(&self) -> crate::session::Result<()> {
+        self.session.check_deadline()
+    }
+
+    fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        let result = protobuf::well_known_types::any::Any::pack(&item.into_proto())
+            .map_err(crate::session::Error::action)?;
+
+        self.session.reply_tagged(&crate::session::Tag::new(self.source), Item {
+            source: self.source.to_string(),
+            result: Ok(result),
+        })
+    }
+
+    fn reply_tagged<I>(&mut self, tag: &crate::session::Tag, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        let result = protobuf::well_known_types::any::Any::pack(&item.into_proto())
+            .map_err(crate::session::Error::action)?;
+
+        self.session.reply_tagged(&crate::session::Tag::new(self.source).join(tag), Item {
+            source: self.source.to_string(),
+            result: Ok(result),
+        })
+    }
+
+    fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        self.session.send(sink, item)
+    }
+
+    fn throttle(&self, len: u64) -> crate::session::Result<()> {
+        self.session.throttle(len)
+    }
+
+    fn record_bytes_read(&mut self, len: u64) {
+        self.session.record_bytes_read(len)
+    }
+
+    fn set_total_count_hint(&mut self, count: u64) {
+        self.session.set_total_count_hint(count)
+    }
+
+    fn page_size(&self) -> Option<u64> {
+        self.session.page_size()
+    }
+
+    fn page_cursor(&self) -> Option<&[u8]> {
+        self.session.page_cursor()
+    }
+
+    fn set_next_page_cursor(&mut self, cursor: Vec<u8>) {
+        self.session.set_next_page_cursor(cursor)
+    }
+
+    fn heartbeat(&mut self) {
+        self.session.heartbeat()
+    }
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::collect_artifact::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+        use rrg_proto::collect_artifact::source::Source as ProtoSource;
+
+        let mut sources = Vec::new();
+
+        for mut source in proto.take_sources() {
+            let kind = match source.source.take() {
+                Some(ProtoSource::File(mut file)) => {
+                    let path_globs = file.take_path_globs().into_iter()
+                        .map(PathBuf::try_from)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|error| ParseArgsError::invalid_field("sources", error))?;
+
+                    SourceKind::File { path_globs }
+                }
+                Some(ProtoSource::WinregValues(args)) => {
+                    SourceKind::WinregValues { args }
+                }
+                Some(ProtoSource::Command(args)) => {
+                    SourceKind::Command { args }
+                }
+                _ => {
+                    return Err(ParseArgsError::invalid_field("sources", EmptySourceError {
+                        name: source.take_name(),
+                    }));
+                }
+            };
+
+            sources.push(Source {
+                name: source.take_name(),
+                kind,
+            });
+        }
+
+        Ok(Args {
+            sources,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::collect_artifact::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_source(self.source);
+        match self.result {
+            Ok(result) => proto.set_result(result),
+            Err(error) => proto.set_error(error),
+        }
+
+        proto
+    }
+}
+
+/// An error indicating that an artifact source does not specify what to collect.
+#[derive(Debug)]
+struct EmptySourceError {
+    /// Name of the empty source.
+    name: String,
+}
+
+impl std::fmt::Display for EmptySourceError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "source '{}' does not specify anything to collect", self.name)
+    }
+}
+
+impl std::error::Error for EmptySourceError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_empty() {
+        let args = Args {
+            sources: vec![],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_file_source() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo.txt"), b"")
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar.txt"), b"")
+            .unwrap();
+        std::fs::write(tempdir.path().join("baz.log"), b"")
+            .unwrap();
+
+        let args = Args {
+            sources: vec![Source {
+                name: String::from("txt"),
+                kind: SourceKind::File {
+                    path_globs: vec![tempdir.path().join("*.txt")],
+                },
+            }],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let paths = session.replies::<Item>()
+            .map(|item| {
+                assert_eq!(item.source, "txt");
+
+                let result = item.result.as_ref().unwrap()
+                    .unpack::<rrg_proto::get_file_metadata::Result>()
+                    .unwrap().unwrap();
+
+                PathBuf::try_from(result.path.unwrap()).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(paths, vec![
+            tempdir.path().join("bar.txt"),
+            tempdir.path().join("foo.txt"),
+        ]);
+    }
+
+    #[test]
+    fn handle_failed_source_does_not_abort_others() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"")
+            .unwrap();
+
+        let args = Args {
+            sources: vec![
+                Source {
+                    name: String::from("invalid"),
+                    kind: SourceKind::File {
+                        path_globs: vec![PathBuf::from("relative/*")],
+                    },
+                },
+                Source {
+                    name: String::from("valid"),
+                    kind: SourceKind::File {
+                        path_globs: vec![tempdir.path().join("f*")],
+                    },
+                },
+            ],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.source, "invalid");
+        assert!(item.result.is_err());
+        assert_eq!(session.reply_tag(0), Some(&crate::session::Tag::new("invalid")));
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.source, "valid");
+        assert!(item.result.is_ok());
+        assert_eq!(session.reply_tag(1), Some(&crate::session::Tag::new("valid")));
+    }
+
+    /// A session that reports the deadline as exceeded after a number of checks.
+    ///
+    /// The deadline itself is not set, so that only sessions forwarding their
+    /// deadline checks can observe it.
+    struct ExpiringSession {
+        /// Session to delegate to.
+        inner: crate::session::FakeSession,
+        /// Number of deadline checks that still pass.
+        checks_left: std::cell::Cell<usize>,
+    }
+
+    impl crate::session::Session for ExpiringSession {
+
+        fn args(&self) -> &crate::args::Args {
+            crate::session::Session::args(&self.inner)
+        }
+
+        fn check_deadline(&self) -> crate::session::Result<()> {
+            match self.checks_left.get() {
+                0 => {
+                    let deadline = std::time::SystemTime::now();
+                    Err(crate::session::Error::deadline_exceeded(deadline))
+                }
+                checks_left => {
+                    self.checks_left.set(checks_left - 1);
+                    Ok(())
+                }
+            }
+        }
+
+        fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            crate::session::Session::reply(&mut self.inner, item)
+        }
+
+        fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            crate::session::Session::send(&mut self.inner, sink, item)
+        }
+    }
+
+    #[test]
+    fn handle_deadline_exceeded_in_source() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"")
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar"), b"")
+            .unwrap();
+        std::fs::write(tempdir.path().join("baz"), b"")
+            .unwrap();
+
+        let args = Args {
+            sources: vec![
+                Source {
+                    name: String::from("slow"),
+                    kind: SourceKind::File {
+                        path_globs: vec![tempdir.path().join("*")],
+                    },
+                },
+                Source {
+                    name: String::from("skipped"),
+                    kind: SourceKind::File {
+                        path_globs: vec![tempdir.path().join("*")],
+                    },
+                },
+            ],
+        };
+
+        let mut session = ExpiringSession {
+            inner: crate::session::FakeSession::new(),
+            checks_left: std::cell::Cell::new(1),
+        };
+
+        let error = handle(&mut session, args)
+            .unwrap_err();
+        assert_eq!(error.kind(), crate::session::ErrorKind::DeadlineExceeded);
+
+        // Only the file collected before the deadline passed is reported and
+        // no error is reported for the sources themselves.
+        assert_eq!(session.inner.reply_count(), 1);
+
+        let item = session.inner.reply::<Item>(0);
+        assert_eq!(item.source, "slow");
+        assert!(item.result.is_ok());
+    }
+
+    #[test]
+    fn handle_command_source_not_permitted() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"")
+            .unwrap();
+
+        let args = Args {
+            sources: vec![
+                Source {
+                    name: String::from("command"),
+                    kind: SourceKind::Command {
+                        args: rrg_proto::execute_signed_command::Args::new(),
+                    },
+                },
+                Source {
+                    name: String::from("file"),
+                    kind: SourceKind::File {
+                        path_globs: vec![tempdir.path().join("*")],
+                    },
+                },
+            ],
+        };
+
+        // The action executing commands is disabled by default.
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.source, "command");
+        assert!(item.result.is_err());
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.source, "file");
+        assert!(item.result.is_ok());
+    }
+
+    #[test]
+    fn handle_file_source_denied() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"")
+            .unwrap();
+
+        let args = Args {
+            sources: vec![Source {
+                name: String::from("file"),
+                kind: SourceKind::File {
+                    path_globs: vec![tempdir.path().join("*")],
+                },
+            }],
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            denied_actions: vec![crate::request::Action::GetFileMetadata],
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert!(session.reply::<Item>(0).result.is_err());
+    }
+
+    #[cfg(all(target_family = "unix", feature = "action-execute_signed_command"))]
+    #[test]
+    fn handle_file_and_command_sources() {
+        use protobuf::Message as _;
+
+        let seed = [0x42; 32];
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"")
+            .unwrap();
+
+        let mut command = rrg_proto::execute_signed_command::Command::new();
+        command.set_path(PathBuf::from("/bin/sh").into());
+        command.set_args(vec![String::from("-c"), String::from("echo foo")]);
+        command.set_issue_time(rrg_proto::into_timestamp(std::time::SystemTime::now()));
+        command.set_nonce(rand::random::<[u8; 16]>().to_vec());
+        let command = command.write_to_bytes()
+            .unwrap();
+
+        let mut command_args = rrg_proto::execute_signed_command::Args::new();
+        command_args.set_command_ed25519_signature(crate::signed::sign(&seed, &command).to_vec());
+        command_args.set_command(command);
+
+        let args = Args {
+            sources: vec![
+                Source {
+                    name: String::from("file"),
+                    kind: SourceKind::File {
+                        path_globs: vec![tempdir.path().join("*")],
+                    },
+                },
+                Source {
+                    name: String::from("command"),
+                    kind: SourceKind::Command {
+                        args: command_args,
+                    },
+                },
+            ],
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            allowed_actions: vec![
+                crate::request::Action::CollectArtifact,
+                crate::request::Action::GetFileMetadata,
+                crate::request::Action::ExecuteSignedCommand,
+            ],
+            command_verification_key: Some(crate::signed::public_key(&seed)),
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.source, "file");
+        assert_eq!(session.reply_tag(0), Some(&crate::session::Tag::new("file")));
+
+        let result = item.result.as_ref().unwrap()
+            .unpack::<rrg_proto::get_file_metadata::Result>()
+            .unwrap().unwrap();
+        assert_eq!(PathBuf::try_from(result.path.unwrap()).unwrap(), tempdir.path().join("foo"));
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.source, "command");
+        assert_eq!(session.reply_tag(1), Some(&crate::session::Tag::new("command")));
+
+        let result = item.result.as_ref().unwrap()
+            .unpack::<rrg_proto::execute_signed_command::Result>()
+            .unwrap().unwrap();
+        assert_eq!(result.stdout(), b"foo\n");
+        assert_eq!(result.exit_code(), 0);
+    }
+}
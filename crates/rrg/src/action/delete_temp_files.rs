@@ -0,0 +1,230 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+/// Arguments of the `delete_temp_files` action.
+pub struct Args {
+    /// Whether to only report the matching files without deleting them.
+    dry_run: bool,
+}
+
+/// A result of the `delete_temp_files` action.
+struct Item {
+    /// Path to the file in the temporary directory of the agent.
+    path: std::path::PathBuf,
+    /// Whether the file was deleted (or skipped as not created by the agent).
+    deleted: bool,
+    /// Whether the file matches the temporary file pattern of the agent.
+    matched: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::DeleteTempFiles;
+
+/// Handles invocations of the `delete_temp_files` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let temp_dir = crate::temp_dir(session.args())
+        .map_err(crate::session::Error::action)?
+        .to_path_buf();
+
+    // The glob is anchored at the temporary directory and has exactly one
+    // component more, so it can only match its immediate children.
+    let pattern = crate::fs::Glob::new(temp_dir.join(&session.args().temp_file_pattern))
+        .map_err(crate::session::Error::action)?;
+
+    let entries = std::fs::read_dir(&temp_dir)
+        .map_err(crate::session::Error::action)?;
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("failed to read temporary directory entry: {error}");
+                continue;
+            }
+        };
+        let path = entry.path();
+
+        let is_file = match entry.file_type() {
+            Ok(file_type) => file_type.is_file(),
+            Err(error) => {
+                log::warn!("failed to obtain type of '{}': {error}", path.display());
+                continue;
+            }
+        };
+
+        // Uploads keep their configuration in the temporary directory until
+        // they finish, so removing it would break the ones still in progress.
+        #[cfg(feature = "action-upload_file")]
+        let is_file = is_file && !super::upload_file::is_in_flight_config(&path);
+
+        if !is_file || !pattern.is_prefix_match(&path) {
+            session.reply(Item {
+                path,
+                deleted: false,
+                matched: false,
+            })?;
+            continue;
+        }
+
+        if args.dry_run {
+            session.reply(Item {
+                path,
+                deleted: false,
+                matched: true,
+            })?;
+            continue;
+        }
+
+        if let Err(error) = std::fs::remove_file(&path) {
+            log::error!("failed to delete '{}': {error}", path.display());
+            continue;
+        }
+
+        session.reply(Item {
+            path,
+            deleted: true,
+            matched: true,
+        })?;
+    }
+
+    Ok(())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::delete_temp_files::Args;
+
+    fn from_proto(proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            dry_run: proto.dry_run(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::delete_temp_files::Result;
+
+    fn into_proto(self) -> rrg_proto::delete_temp_files::Result {
+        let mut proto = rrg_proto::delete_temp_files::Result::default();
+        proto.set_path(self.path.into());
+        proto.set_deleted(self.deleted);
+        proto.set_matched(self.matched);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_matching_and_non_matching() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("rrg-foo"), b"")
+            .unwrap();
+        std::fs::write(tempdir.join("rrg-bar"), b"")
+            .unwrap();
+        std::fs::write(tempdir.join("other"), b"")
+            .unwrap();
+        std::fs::create_dir(tempdir.join("rrg-dir"))
+            .unwrap();
+
+        let args = crate::args::Args {
+            temp_dir: tempdir.clone(),
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut session, Args { dry_run: false }).is_ok());
+
+        assert!(!tempdir.join("rrg-foo").exists());
+        assert!(!tempdir.join("rrg-bar").exists());
+        assert!(tempdir.join("other").exists());
+        assert!(tempdir.join("rrg-dir").exists());
+
+        let mut deleted = session.replies::<Item>()
+            .filter(|item| item.deleted)
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        deleted.sort();
+        assert_eq!(deleted, vec![tempdir.join("rrg-bar"), tempdir.join("rrg-foo")]);
+
+        let mut skipped = session.replies::<Item>()
+            .filter(|item| !item.deleted)
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        skipped.sort();
+        assert_eq!(skipped, vec![tempdir.join("other"), tempdir.join("rrg-dir")]);
+    }
+
+    #[test]
+    fn handle_dry_run() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("rrg-foo"), b"")
+            .unwrap();
+        std::fs::write(tempdir.join("other"), b"")
+            .unwrap();
+
+        let args = crate::args::Args {
+            temp_dir: tempdir.clone(),
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut session, Args { dry_run: true }).is_ok());
+
+        assert!(tempdir.join("rrg-foo").exists());
+        assert!(tempdir.join("other").exists());
+
+        assert!(session.replies::<Item>().all(|item| !item.deleted));
+
+        let matched = session.replies::<Item>()
+            .filter(|item| item.matched)
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(matched, vec![tempdir.join("rrg-foo")]);
+    }
+
+    #[cfg(feature = "action-upload_file")]
+    #[test]
+    fn handle_in_flight_upload_config() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let in_flight = format!("rrg-upload-{}-1.curlrc", std::process::id());
+        std::fs::write(tempdir.join(&in_flight), b"")
+            .unwrap();
+        // Process 0 is never an agent, so the upload cannot be in progress.
+        std::fs::write(tempdir.join("rrg-upload-0-1.curlrc"), b"")
+            .unwrap();
+
+        let args = crate::args::Args {
+            temp_dir: tempdir.clone(),
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut session, Args { dry_run: false }).is_ok());
+
+        assert!(tempdir.join(&in_flight).exists());
+        assert!(!tempdir.join("rrg-upload-0-1.curlrc").exists());
+    }
+}
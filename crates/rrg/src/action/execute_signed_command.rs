@@ -0,0 +1,749 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Execution of commands signed by the server.
+//!
+//! Running arbitrary commands gives whoever controls the request full control
+//! over the system, so this action is disabled unless explicitly allowed and
+//! runs only commands signed with the key the agent was configured with
+//! (through the `--command-verification-key` flag). The signature covers the
+//! whole command including the data fed to its standard input, so scripts can
+//! be run by signing the interpreter invocation together with the script.
+//! Commands also carry the time they were signed at and a random nonce, so that
+//! a captured command cannot be run again.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default maximum time a command can run for.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Maximum length of the standard output and error of a command to report.
+const MAX_OUTPUT_LEN: usize = 1024 * 1024; // 1 MiB.
+
+/// Interval at which the command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Guard remembering nonces of the executed commands.
+///
+/// Commands can be embedded in other actions (e.g. `collect_artifact`), so the
+/// guard is shared by all invocations regardless of how they were issued.
+static REPLAY_GUARD: crate::signed::ReplayGuard = crate::signed::ReplayGuard::new();
+
+/// Arguments of the `execute_signed_command` action.
+pub struct Args {
+    /// Serialized command to execute (exactly as it was signed).
+    command: Vec<u8>,
+    /// Ed25519 signature of the serialized command.
+    signature: Vec<u8>,
+    /// Maximum time the command can run for.
+    timeout: Duration,
+    /// Whether to report the environment the command was executed with.
+    report_env: bool,
+}
+
+/// Command to execute, parsed only once its signature is verified.
+struct Command {
+    /// Path to the executable to run.
+    path: PathBuf,
+    /// Arguments to run the executable with.
+    args: Vec<String>,
+    /// Data to feed to the standard input of the command.
+    stdin: Vec<u8>,
+    /// Environment variables to set for the command.
+    env: Vec<(String, String)>,
+    /// Names of inherited environment variables to remove for the command.
+    env_remove: Vec<String>,
+    /// Whether not to inherit the environment of the agent.
+    env_clear: bool,
+    /// Directory to run the command in (if not the one of the agent).
+    working_directory: Option<PathBuf>,
+    /// Name of the user to run the command as (if not the one of the agent).
+    user: Option<String>,
+    /// Time at which the command was signed.
+    issue_time: Option<protobuf::well_known_types::timestamp::Timestamp>,
+    /// Random value unique to the command.
+    nonce: Vec<u8>,
+}
+
+/// Result of the `execute_signed_command` action.
+struct Item {
+    /// Exit code of the command (unless it was killed).
+    exit_code: Option<i32>,
+    /// Standard output of the command.
+    stdout: Output,
+    /// Standard error of the command.
+    stderr: Output,
+    /// Whether the command was killed because it exceeded the timeout.
+    timed_out: bool,
+    /// Environment the command was executed with (if requested).
+    env: Option<Vec<(OsString, OsString)>>,
+}
+
+/// Captured output stream of a command.
+struct Output {
+    /// Beginning of the data written to the stream.
+    data: Vec<u8>,
+    /// Whether there was more data than reported.
+    truncated: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ExecuteSignedCommand;
+
+/// Handles invocations of the `execute_signed_command` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let verification_key = session.args().command_verification_key.as_ref();
+    crate::signed::verify_signed(verification_key, &args.command, &args.signature)
+        .map_err(crate::session::Error::action)?;
+
+    // Only now that we know the command comes from the server, we look inside.
+    let mut command = Command::parse(&args.command)
+        .map_err(crate::session::Error::action)?;
+
+    let max_age = session.args().request_max_age;
+    let nonce = std::mem::take(&mut command.nonce);
+    REPLAY_GUARD.check(command.issue_time.as_ref(), nonce, max_age, std::time::SystemTime::now())
+        .map_err(crate::session::Error::action)?;
+
+    log::info!("executing signed command '{}'", command.path.display());
+
+    let env = command.env();
+
+    let mut item = run(&command, &env, args.timeout)
+        .map_err(crate::session::Error::action)?;
+    if args.report_env {
+        item.env = Some(env);
+    }
+
+    session.reply(item)?;
+
+    Ok(())
+}
+
+impl Command {
+
+    /// Parses a command from its serialized protobuf representation.
+    fn parse(bytes: &[u8]) -> std::io::Result<Command> {
+        use protobuf::Message as _;
+        use std::io::{Error, ErrorKind};
+
+        let mut proto = rrg_proto::execute_signed_command::Command::parse_from_bytes(bytes)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        if !path.is_absolute() {
+            return Err(Error::new(ErrorKind::InvalidInput, "relative command path"));
+        }
+
+        let working_directory = if proto.has_working_directory() {
+            let path = PathBuf::try_from(proto.take_working_directory())
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+            if !path.is_absolute() {
+                return Err(Error::new(ErrorKind::InvalidInput, "relative working directory"));
+            }
+
+            Some(path)
+        } else {
+            None
+        };
+
+        let user = match proto.take_user() {
+            user if user.is_empty() => None,
+            user => Some(user),
+        };
+
+        // Maps are not ordered, so we sort the variables to make the resulting
+        // environment deterministic.
+        let mut env = proto.take_env().into_iter()
+            .collect::<Vec<_>>();
+        env.sort();
+
+        Ok(Command {
+            path,
+            args: proto.take_args(),
+            stdin: proto.take_stdin(),
+            env,
+            env_remove: proto.take_env_remove(),
+            env_clear: proto.env_clear(),
+            working_directory,
+            user,
+            issue_time: proto.issue_time.take(),
+            nonce: proto.take_nonce(),
+        })
+    }
+
+    /// Computes the environment that the command should be executed with.
+    fn env(&self) -> Vec<(OsString, OsString)> {
+        let mut env = if self.env_clear {
+            std::collections::BTreeMap::new()
+        } else {
+            std::env::vars_os().collect()
+        };
+
+        for name in &self.env_remove {
+            env.remove(&OsString::from(name));
+        }
+        for (name, value) in &self.env {
+            env.insert(OsString::from(name), OsString::from(value));
+        }
+
+        env.into_iter().collect()
+    }
+}
+
+/// Runs the given command with the `env` to completion, killing it after `timeout`.
+fn run(
+    command: &Command,
+    env: &[(OsString, OsString)],
+    timeout: Duration,
+) -> std::io::Result<Item> {
+    use std::process::Stdio;
+
+    let mut process = std::process::Command::new(&command.path);
+    process
+        .args(&command.args)
+        .env_clear()
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // The command gets its own process group, so that everything it spawned
+    // can be killed along with it (otherwise leftovers could keep the output
+    // pipes open indefinitely).
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::process::CommandExt as _;
+        process.process_group(0);
+    }
+
+    if let Some(working_directory) = &command.working_directory {
+        process.current_dir(working_directory);
+    }
+
+    // The user is resolved before spawning, so that a typo in its name yields
+    // a clear error. The privileges themselves are dropped in the child after
+    // the fork and before the command is executed.
+    #[cfg(target_family = "unix")]
+    if let Some(user) = &command.user {
+        use std::os::unix::process::CommandExt as _;
+
+        let (uid, gid) = ospect::os::user_ids(std::ffi::OsStr::new(user))?
+            .ok_or_else(|| {
+                use std::io::{Error, ErrorKind};
+                Error::new(ErrorKind::NotFound, format!("unknown user '{user}'"))
+            })?;
+
+        process.uid(uid).gid(gid);
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    if command.user.is_some() {
+        use std::io::{Error, ErrorKind};
+        return Err(Error::new(ErrorKind::Unsupported, "running as another user"));
+    }
+
+    let mut child = process.spawn()?;
+
+    let mut stdin = child.stdin.take()
+        .expect("no stdin of the child process");
+    let stdout = child.stdout.take()
+        .expect("no stdout of the child process");
+    let stderr = child.stderr.take()
+        .expect("no stderr of the child process");
+
+    // Feeding the input and draining the outputs is done in separate threads,
+    // as otherwise the command could get blocked on one of the pipes.
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            use std::io::Write as _;
+
+            // The command does not have to read all of its input, so failures
+            // to write it are not errors.
+            if let Err(error) = stdin.write_all(&command.stdin) {
+                log::debug!("failed to write command input: {error}");
+            }
+        });
+
+        let stdout = scope.spawn(move || read_output(stdout, MAX_OUTPUT_LEN));
+        let stderr = scope.spawn(move || read_output(stderr, MAX_OUTPUT_LEN));
+
+        let status = wait(&mut child, timeout)?;
+
+        let stdout = stdout.join()
+            .expect("stdout thread panicked")?;
+        let stderr = stderr.join()
+            .expect("stderr thread panicked")?;
+
+        Ok(Item {
+            exit_code: status.and_then(|status| status.code()),
+            stdout,
+            stderr,
+            timed_out: status.is_none(),
+            env: None,
+        })
+    })
+}
+
+/// Waits for the `child` to exit, killing it after `timeout`.
+///
+/// Returns `None` if the child had to be killed.
+fn wait(
+    child: &mut std::process::Child,
+    timeout: Duration,
+) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let start = std::time::Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() >= timeout {
+            break;
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    log::warn!("killing command running for more than {timeout:?}");
+
+    #[cfg(target_family = "unix")]
+    {
+        let pgid = libc::pid_t::try_from(child.id())
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+        // SAFETY: We send a signal to the process group of the child, which we
+        // created when spawning it. The result is verified below.
+        if unsafe { libc::kill(-pgid, libc::SIGKILL) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    child.kill()?;
+
+    child.wait()?;
+
+    Ok(None)
+}
+
+/// Reads the whole `stream` keeping at most `max_len` of its first bytes.
+///
+/// The rest of the stream is read and discarded, so that the writer is never
+/// blocked on a full pipe.
+fn read_output<R>(mut stream: R, max_len: usize) -> std::io::Result<Output>
+where
+    R: std::io::Read,
+{
+    use std::io::Read as _;
+
+    let mut data = Vec::new();
+    (&mut stream).take(max_len as u64).read_to_end(&mut data)?;
+
+    let truncated = std::io::copy(&mut stream, &mut std::io::sink())? > 0;
+
+    Ok(Output {
+        data,
+        truncated,
+    })
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::execute_signed_command::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let timeout = if proto.has_timeout() {
+            rrg_proto::try_from_duration(proto.take_timeout())
+                .map_err(|error| ParseArgsError::invalid_field("timeout", error))?
+        } else {
+            DEFAULT_TIMEOUT
+        };
+
+        Ok(Args {
+            command: proto.take_command(),
+            signature: proto.take_command_ed25519_signature(),
+            timeout,
+            report_env: proto.report_env(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::execute_signed_command::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        if let Some(exit_code) = self.exit_code {
+            proto.set_exit_code(exit_code);
+        }
+        proto.set_stdout(self.stdout.data);
+        proto.set_stdout_truncated(self.stdout.truncated);
+        proto.set_stderr(self.stderr.data);
+        proto.set_stderr_truncated(self.stderr.truncated);
+        proto.set_timed_out(self.timed_out);
+        if let Some(env) = self.env {
+            proto.set_env(env.into_iter()
+                .map(|(name, value)| {
+                    (name.to_string_lossy().into_owned(), value.to_string_lossy().into_owned())
+                })
+                .collect());
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Private key the test commands are signed with.
+    const TEST_SEED: [u8; 32] = [0x42; 32];
+
+    fn session_with_key() -> crate::session::FakeSession {
+        let args = crate::args::Args {
+            command_verification_key: Some(crate::signed::public_key(&TEST_SEED)),
+            ..Default::default()
+        };
+
+        crate::session::FakeSession::with_args(args)
+    }
+
+    fn command(path: &str, args: &[&str], stdin: &[u8]) -> rrg_proto::execute_signed_command::Command {
+        let mut command = rrg_proto::execute_signed_command::Command::new();
+        command.set_path(PathBuf::from(path).into());
+        command.set_args(args.iter().map(|arg| arg.to_string()).collect());
+        command.set_stdin(stdin.to_vec());
+        command.set_issue_time(rrg_proto::into_timestamp(std::time::SystemTime::now()));
+        command.set_nonce(rand::random::<[u8; 16]>().to_vec());
+
+        command
+    }
+
+    fn sign(command: rrg_proto::execute_signed_command::Command) -> Args {
+        use protobuf::Message as _;
+
+        let command = command.write_to_bytes()
+            .unwrap();
+        let signature = crate::signed::sign(&TEST_SEED, &command);
+
+        Args {
+            command,
+            signature: signature.to_vec(),
+            timeout: DEFAULT_TIMEOUT,
+            report_env: false,
+        }
+    }
+
+    fn signed_args(path: &str, args: &[&str], stdin: &[u8]) -> Args {
+        sign(command(path, args, stdin))
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_output() {
+        let args = signed_args("/bin/sh", &["-c", "echo foo; echo bar >&2; exit 3"], b"");
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.exit_code, Some(3));
+        assert_eq!(item.stdout.data, b"foo\n");
+        assert!(!item.stdout.truncated);
+        assert_eq!(item.stderr.data, b"bar\n");
+        assert!(!item.stderr.truncated);
+        assert!(!item.timed_out);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_script_stdin() {
+        let args = signed_args("/bin/sh", &[], b"echo $((1 + 2))\n");
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.exit_code, Some(0));
+        assert_eq!(item.stdout.data, b"3\n");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_timeout() {
+        let mut args = signed_args("/bin/sh", &["-c", "sleep 60 & sleep 60"], b"");
+        args.timeout = Duration::from_millis(100);
+
+        let start = std::time::Instant::now();
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+
+        // The background process holding the pipes is killed as well.
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.exit_code, None);
+        assert!(item.timed_out);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_env() {
+        let mut command = command("/usr/bin/env", &[], b"");
+        command.mut_env().insert(String::from("RRG_TEST_FOO"), String::from("foo"));
+        command.mut_env_remove().push(String::from("PATH"));
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, sign(command)).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.env.is_none());
+
+        let stdout = String::from_utf8(item.stdout.data.clone())
+            .unwrap();
+        assert!(stdout.lines().any(|line| line == "RRG_TEST_FOO=foo"));
+        assert!(!stdout.lines().any(|line| line.starts_with("PATH=")));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_env_clear() {
+        let mut command = command("/usr/bin/env", &[], b"");
+        command.mut_env().insert(String::from("RRG_TEST_FOO"), String::from("foo"));
+        command.mut_env().insert(String::from("RRG_TEST_BAR"), String::from("bar"));
+        command.set_env_clear(true);
+
+        let mut args = sign(command);
+        args.report_env = true;
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.stdout.data, b"RRG_TEST_BAR=bar\nRRG_TEST_FOO=foo\n");
+        assert_eq!(item.env, Some(vec![
+            (OsString::from("RRG_TEST_BAR"), OsString::from("bar")),
+            (OsString::from("RRG_TEST_FOO"), OsString::from("foo")),
+        ]));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_env_clear_empty() {
+        let mut command = command("/usr/bin/env", &[], b"");
+        command.set_env_clear(true);
+
+        let mut args = sign(command);
+        args.report_env = true;
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.stdout.data, b"");
+        assert_eq!(item.env, Some(vec![]));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_working_directory() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let mut command = command("/bin/sh", &["-c", "pwd -P"], b"");
+        command.set_working_directory(tempdir.clone().into());
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, sign(command)).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.stdout.data, format!("{}\n", tempdir.display()).as_bytes());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_unknown_user() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let marker = tempdir.path().join("marker");
+
+        let script = format!("touch '{}'", marker.display());
+        let mut command = command("/bin/sh", &["-c", &script], b"");
+        command.set_user(String::from("rrg-nonexistent-user"));
+
+        let mut session = session_with_key();
+        let error = handle(&mut session, sign(command))
+            .unwrap_err();
+        assert!(error.to_string().contains("rrg-nonexistent-user"));
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!marker.exists());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_user() {
+        // SAFETY: Getting the effective user identifier has no preconditions.
+        if unsafe { libc::geteuid() } != 0 {
+            // Dropping privileges requires to have them in the first place.
+            return;
+        }
+
+        let (uid, gid) = match ospect::os::user_ids(std::ffi::OsStr::new("nobody")).unwrap() {
+            Some(ids) => ids,
+            None => return,
+        };
+
+        let mut command = command("/bin/sh", &["-c", "id -u; id -g"], b"");
+        command.set_user(String::from("nobody"));
+        // The working directory of the tests might not be accessible to the
+        // user, so we make sure to run it somewhere where it is.
+        command.set_working_directory(PathBuf::from("/").into());
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, sign(command)).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.exit_code, Some(0));
+        assert_eq!(item.stdout.data, format!("{uid}\n{gid}\n").as_bytes());
+    }
+
+    #[test]
+    fn handle_unsigned() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let marker = tempdir.path().join("marker");
+
+        let script = format!("touch '{}'", marker.display());
+        let mut args = signed_args("/bin/sh", &["-c", &script], b"");
+        args.signature = vec![];
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn handle_invalid_signature() {
+        let mut args = signed_args("/bin/sh", &["-c", "true"], b"");
+        args.signature[0] ^= 0xff;
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_tampered_command() {
+        let mut args = signed_args("/bin/sh", &["-c", "true"], b"");
+        args.command.push(0x00);
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_no_verification_key() {
+        let args = signed_args("/bin/sh", &["-c", "true"], b"");
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_relative_path() {
+        let args = signed_args("sh", &["-c", "true"], b"");
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_replayed_command() {
+        let args = signed_args("/bin/sh", &["-c", "true"], b"");
+        let replayed_args = Args {
+            command: args.command.clone(),
+            signature: args.signature.clone(),
+            timeout: args.timeout,
+            report_env: args.report_env,
+        };
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, args).is_ok());
+        assert!(handle(&mut session, replayed_args).is_err());
+
+        assert_eq!(session.reply_count(), 1);
+    }
+
+    #[test]
+    fn handle_expired_command() {
+        let mut command = command("/bin/sh", &["-c", "true"], b"");
+        command.set_issue_time(rrg_proto::into_timestamp({
+            std::time::SystemTime::now() - Duration::from_secs(2 * 60 * 60)
+        }));
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, sign(command)).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_no_nonce() {
+        let mut command = command("/bin/sh", &["-c", "true"], b"");
+        command.clear_nonce();
+
+        let mut session = session_with_key();
+        assert!(handle(&mut session, sign(command)).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn read_output_truncated() {
+        let output = read_output(&b"foobarbaz"[..], 6)
+            .unwrap();
+
+        assert_eq!(output.data, b"foobar");
+        assert!(output.truncated);
+    }
+
+    #[test]
+    fn read_output_exact() {
+        let output = read_output(&b"foobar"[..], 6)
+            .unwrap();
+
+        assert_eq!(output.data, b"foobar");
+        assert!(!output.truncated);
+    }
+}
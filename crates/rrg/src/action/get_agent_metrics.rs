@@ -0,0 +1,132 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+/// Result of the `get_agent_metrics` action.
+struct Item {
+    /// Counters describing the activity of the agent.
+    metrics: crate::metrics::Metrics,
+    /// Resident set size (in bytes) of the agent process (if known).
+    rss: Option<u64>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetAgentMetrics;
+
+/// Handles invocations of the `get_agent_metrics` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    session.reply(Item {
+        metrics: crate::metrics::snapshot(),
+        rss: rss(),
+    })?;
+
+    Ok(())
+}
+
+/// Returns the resident set size (in bytes) of the agent process.
+#[cfg(target_os = "linux")]
+fn rss() -> Option<u64> {
+    match ospect::proc::linux::resident_set_size(std::process::id()) {
+        Ok(rss) => rss,
+        Err(error) => {
+            log::warn!("failed to obtain agent memory usage: {error}");
+            None
+        }
+    }
+}
+
+/// Returns the resident set size (in bytes) of the agent process.
+#[cfg(not(target_os = "linux"))]
+fn rss() -> Option<u64> {
+    None
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_agent_metrics::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::rrg::status::error::Type;
+
+        // Distinct kinds of errors can map to the same category (e.g. in case
+        // of invalid requests), so we merge them.
+        let mut actions_failed = std::collections::BTreeMap::new();
+        for (kind, count) in self.metrics.actions_failed {
+            *actions_failed.entry(Type::from(kind) as i32).or_insert(0) += count;
+        }
+
+        let mut proto = Self::Proto::default();
+        proto.set_actions_executed(self.metrics.actions_executed);
+        proto.set_actions_failed(actions_failed.into_iter().map(|(kind, count)| {
+            rrg_proto::get_agent_metrics::FailureCount {
+                type_: protobuf::EnumOrUnknown::from_i32(kind),
+                count,
+                ..Default::default()
+            }
+        }).collect());
+        proto.set_bytes_sent(self.metrics.bytes_sent);
+        proto.set_uptime(self.metrics.uptime.into());
+        if let Some(rss) = self.rss {
+            proto.set_rss(rss);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_default() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        #[cfg(target_os = "linux")]
+        assert!(item.rss.unwrap() > 0);
+        #[cfg(not(target_os = "linux"))]
+        assert!(item.rss.is_none());
+    }
+
+    #[test]
+    fn into_proto_merges_failures() {
+        use crate::response::Item as _;
+        use crate::session::ErrorKind;
+
+        let item = Item {
+            metrics: crate::metrics::Metrics {
+                actions_executed: 5,
+                actions_failed: vec![
+                    (ErrorKind::UnsupportedAction, 2),
+                    (ErrorKind::ActionFailure, 1),
+                ],
+                bytes_sent: 1024,
+                uptime: std::time::Duration::from_secs(60),
+            },
+            rss: Some(4096),
+        };
+
+        let proto = item.into_proto();
+        assert_eq!(proto.actions_executed(), 5);
+        assert_eq!(proto.bytes_sent(), 1024);
+        assert_eq!(proto.uptime().seconds, 60);
+        assert_eq!(proto.rss(), 4096);
+
+        let actions_failed = proto.actions_failed.iter()
+            .map(|failed| (failed.type_(), failed.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(actions_failed, vec![
+            (rrg_proto::rrg::status::error::Type::UNSUPPORTED_ACTION, 2),
+            (rrg_proto::rrg::status::error::Type::ACTION_FAILURE, 1),
+        ]);
+    }
+}
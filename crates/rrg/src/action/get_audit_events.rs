@@ -0,0 +1,890 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of events from the Linux audit log.
+//!
+//! The log written by auditd consists of lines with individual records. A
+//! single event (e.g. a system call) usually spans multiple records (like
+//! `SYSCALL`, `EXECVE` and `PATH`) that share the timestamp and the serial
+//! number of the event, so records are grouped back into events. Records of
+//! different events can interleave, so a few events are kept pending until
+//! they are complete (as marked by the `EOE` record) or until they are old
+//! enough to not expect any more records.
+
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+/// Default path to the audit log.
+#[cfg(target_os = "linux")]
+const DEFAULT_PATH: &str = "/var/log/audit/audit.log";
+
+/// Default limit on the number of returned records.
+#[cfg(target_os = "linux")]
+const DEFAULT_MAX_RECORDS: u64 = 1000;
+
+/// Maximum number of events that are kept pending when grouping records.
+///
+/// Records of an event are logged close to each other, so once this many
+/// events started after the one at the front of the queue, we consider it to
+/// have all its records (even if the `EOE` record never came).
+#[cfg(target_os = "linux")]
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Arguments of the `get_audit_events` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Paths to audit log files to read.
+    paths: Vec<PathBuf>,
+    /// Time before which events are not returned (if any).
+    start_time: Option<std::time::SystemTime>,
+    /// Time after which events are not returned (if any).
+    end_time: Option<std::time::SystemTime>,
+    /// Types of records to return (all if empty).
+    record_types: Vec<String>,
+    /// Maximum number of records to return.
+    max_records: u64,
+}
+
+/// Result of the `get_audit_events` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Audit event to report.
+    event: Event,
+}
+
+/// A single event of the audit log.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+struct Event {
+    /// Identifier of the event.
+    id: EventId,
+    /// Records that make up the event.
+    records: Vec<Record>,
+}
+
+/// Identifier of an audit event.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EventId {
+    /// Time (in milliseconds since epoch) at which the event happened.
+    time_millis: u64,
+    /// Serial number of the event.
+    serial: u64,
+}
+
+/// A single record of an audit event.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+struct Record {
+    /// Type of the record (e.g. `SYSCALL`).
+    r#type: String,
+    /// Fields of the record.
+    fields: Vec<Field>,
+}
+
+/// A field of an audit record.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+struct Field {
+    /// Name of the field.
+    name: String,
+    /// Value of the field (without the quotes).
+    value: String,
+    /// Whether the value was quoted in the log.
+    quoted: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetAuditEvents;
+
+/// Handles invocations of the `get_audit_events` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `get_audit_events` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let paths = if args.paths.is_empty() {
+        vec![PathBuf::from(DEFAULT_PATH)]
+    } else {
+        args.paths.clone()
+    };
+
+    let mut records_left = args.max_records;
+
+    for path in paths {
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                log::warn!("failed to open audit log '{}': {error}", path.display());
+                continue;
+            }
+        };
+
+        for mut event in Events::new(std::io::BufReader::new(file)) {
+            if !args.matches_time(event.id) {
+                continue;
+            }
+
+            if !args.record_types.is_empty() {
+                event.records.retain(|record| args.record_types.contains(&record.r#type));
+            }
+            if event.records.is_empty() {
+                continue;
+            }
+
+            // Events are never split, so we stop at the first one that does not
+            // fit in the limit.
+            let record_count = event.records.len() as u64;
+            if record_count > records_left {
+                log::info!("reached the limit of {} records", args.max_records);
+                return Ok(());
+            }
+            records_left -= record_count;
+
+            session.reply(Item {
+                event,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+impl Args {
+
+    /// Checks whether the event with the given identifier should be returned.
+    fn matches_time(&self, id: EventId) -> bool {
+        let time = id.time();
+
+        if self.start_time.is_some_and(|start_time| time < start_time) {
+            return false;
+        }
+        if self.end_time.is_some_and(|end_time| time > end_time) {
+            return false;
+        }
+
+        true
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl EventId {
+
+    /// Returns the time at which the event happened.
+    fn time(&self) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + std::time::Duration::from_millis(self.time_millis)
+    }
+}
+
+/// Iterator over events of an audit log (grouped from individual records).
+#[cfg(target_os = "linux")]
+struct Events<R> {
+    /// Reader of the audit log.
+    reader: R,
+    /// Events that might still get more records.
+    ///
+    /// The flag tells whether the event is known to be complete.
+    pending: std::collections::VecDeque<(Event, bool)>,
+    /// Whether the end of the log has been reached.
+    eof: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl<R: std::io::BufRead> Events<R> {
+
+    /// Creates an iterator over events of the audit log read from `reader`.
+    fn new(reader: R) -> Events<R> {
+        Events {
+            reader,
+            pending: std::collections::VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    /// Reads the next record of the log and adds it to its event.
+    fn read_record(&mut self) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            self.eof = true;
+            return Ok(());
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        let (id, record) = match parse_record(line) {
+            Some(record) => record,
+            None => {
+                if !line.is_empty() {
+                    log::debug!("skipping malformed audit record: {line:?}");
+                }
+                return Ok(());
+            }
+        };
+
+        let pending = self.pending.iter_mut()
+            .find(|(event, _)| event.id == id);
+
+        // The end of event record carries no information, it just tells that
+        // there are no more records of the event.
+        if record.r#type == "EOE" {
+            if let Some((_, complete)) = pending {
+                *complete = true;
+            }
+            return Ok(());
+        }
+
+        match pending {
+            Some((event, _)) => event.records.push(record),
+            None => self.pending.push_back((Event {
+                id,
+                records: vec![record],
+            }, false)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<R: std::io::BufRead> Iterator for Events<R> {
+
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            match self.pending.front() {
+                Some((_, true)) => return self.pending.pop_front().map(|(event, _)| event),
+                Some(_) if self.eof || self.pending.len() > MAX_PENDING_EVENTS => {
+                    return self.pending.pop_front().map(|(event, _)| event);
+                }
+                None if self.eof => return None,
+                _ => (),
+            }
+
+            if let Err(error) = self.read_record() {
+                log::warn!("failed to read audit log: {error}");
+                self.eof = true;
+            }
+        }
+    }
+}
+
+/// Parses a single line of the audit log.
+///
+/// A line looks like `type=PATH msg=audit(1700000000.123:42): item=0 ...`
+/// (optionally prefixed with the node name). Logs written in the enriched
+/// format have an interpreted copy of the fields after a group separator
+/// character, which we ignore as it can be recreated from the raw fields.
+#[cfg(target_os = "linux")]
+fn parse_record(line: &str) -> Option<(EventId, Record)> {
+    let line = match line.split_once('\u{1d}') {
+        Some((line, _)) => line,
+        None => line,
+    };
+
+    let mut fields = parse_fields(line);
+
+    let type_index = fields.iter().position(|field| field.name == "type")?;
+    let r#type = fields.remove(type_index).value;
+
+    let msg_index = fields.iter().position(|field| field.name == "msg")?;
+    let msg = fields.remove(msg_index).value;
+
+    let (time, serial) = msg.strip_prefix("audit(")?
+        .strip_suffix("):")?
+        .split_once(':')?;
+    let (secs, millis) = time.split_once('.')?;
+
+    let id = EventId {
+        time_millis: secs.parse::<u64>().ok()?
+            .checked_mul(1000)?
+            .checked_add(millis.parse::<u64>().ok()?)?,
+        serial: serial.parse().ok()?,
+    };
+
+    Some((id, Record {
+        r#type,
+        fields,
+    }))
+}
+
+/// Parses whitespace-separated `name=value` fields of an audit record.
+///
+/// Values can be enclosed in double quotes. Values enclosed in single quotes
+/// (like `msg='op=login res=success'` of records sent by user-space programs)
+/// are fields themselves and are flattened into the result.
+#[cfg(target_os = "linux")]
+fn parse_fields(string: &str) -> Vec<Field> {
+    let mut fields = Vec::new();
+
+    let mut rest = string;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        let token_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let Some(name_end) = rest[..token_end].find('=') else {
+            // Tokens that are not fields are skipped.
+            rest = &rest[token_end..];
+            continue;
+        };
+
+        let name = &rest[..name_end];
+        let value = &rest[name_end + 1..];
+
+        let (value, quote, value_rest) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(value_end) => (&value[1..value_end + 1], Some(quote), &value[value_end + 2..]),
+                // Unterminated quotes span the rest of the line.
+                None => (&value[1..], Some(quote), ""),
+            },
+            _ => {
+                let value_end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..value_end], None, &value[value_end..])
+            }
+        };
+
+        match quote {
+            Some('\'') => fields.extend(parse_fields(value)),
+            _ => fields.push(Field {
+                name: String::from(name),
+                value: String::from(value),
+                quoted: quote.is_some(),
+            }),
+        }
+
+        rest = value_rest;
+    }
+
+    fields
+}
+
+#[cfg(target_os = "linux")]
+impl Field {
+
+    /// Decodes the value of a field with an untrusted string.
+    ///
+    /// The audit subsystem logs strings that could contain special characters
+    /// (like file names or program arguments) hex-encoded, and leaves other
+    /// strings quoted. Unset values are logged as `(null)`.
+    fn decode(&self) -> Option<Vec<u8>> {
+        if self.quoted {
+            return Some(self.value.clone().into_bytes());
+        }
+        if self.value == "(null)" {
+            return None;
+        }
+
+        match decode_hex(&self.value) {
+            Some(bytes) => Some(bytes),
+            None => Some(self.value.clone().into_bytes()),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Record {
+
+    /// Returns the first field with the given name (if any).
+    fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Returns the value of the first field with the given name (if any).
+    fn value(&self, name: &str) -> Option<&str> {
+        self.field(name).map(|field| field.value.as_str())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Event {
+
+    /// Reconstructs arguments of the executed program from `EXECVE` records.
+    ///
+    /// Long argument lists are split into multiple records and long arguments
+    /// are split into multiple fields (`a1[0]`, `a1[1]` and so on), so all of
+    /// them are joined back together.
+    fn execve_args(&self) -> Vec<Vec<u8>> {
+        let mut args = std::collections::BTreeMap::<u64, Vec<u8>>::new();
+        let mut argc = None;
+
+        let records = self.records.iter()
+            .filter(|record| record.r#type == "EXECVE");
+
+        for field in records.flat_map(|record| record.fields.iter()) {
+            if field.name == "argc" {
+                argc = field.value.parse::<u64>().ok();
+                continue;
+            }
+
+            let Some(name) = field.name.strip_prefix('a') else {
+                continue;
+            };
+
+            let (index, is_chunk) = match name.split_once('[') {
+                Some((index, chunk)) if chunk.ends_with(']') => (index, true),
+                Some(_) => continue,
+                None => (name, false),
+            };
+            let Ok(index) = index.parse::<u64>() else {
+                // This is the `aN_len` field with the length of a split one.
+                continue;
+            };
+
+            let value = field.decode().unwrap_or_default();
+            if is_chunk {
+                args.entry(index).or_default().extend(value);
+            } else {
+                args.insert(index, value);
+            }
+        }
+
+        let argc = argc.unwrap_or_else(|| {
+            args.keys().next_back().map(|index| index + 1).unwrap_or(0)
+        });
+
+        (0..argc)
+            .map(|index| args.remove(&index).unwrap_or_default())
+            .collect()
+    }
+
+    /// Returns the first `SYSCALL` record of the event (if any).
+    fn syscall(&self) -> Option<&Record> {
+        self.records.iter().find(|record| record.r#type == "SYSCALL")
+    }
+
+    /// Returns the `PATH` records of the event.
+    fn paths(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter().filter(|record| record.r#type == "PATH")
+    }
+}
+
+/// Decodes a string of hexadecimal digits to bytes.
+#[cfg(target_os = "linux")]
+fn decode_hex(string: &str) -> Option<Vec<u8>> {
+    if string.is_empty() || !string.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..string.len()).step_by(2)
+        .map(|index| {
+            let digits = string.get(index..index + 2)?;
+            u8::from_str_radix(digits, 16).ok()
+        })
+        .collect()
+}
+
+/// Converts bytes of a path logged by the audit subsystem to a path.
+#[cfg(target_os = "linux")]
+fn into_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt as _;
+
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+/// Parses the given timestamp message (if present).
+#[cfg(target_os = "linux")]
+fn parse_time(
+    name: &'static str,
+    timestamp: Option<protobuf::well_known_types::timestamp::Timestamp>,
+) -> Result<Option<std::time::SystemTime>, crate::request::ParseArgsError> {
+    let Some(timestamp) = timestamp else {
+        return Ok(None);
+    };
+
+    // Timestamp is (a bit simplifying) just a duration since epoch, so we
+    // reuse the existing conversion routine for durations.
+    let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+    since_epoch.seconds = timestamp.seconds;
+    since_epoch.nanos = timestamp.nanos;
+
+    let since_epoch = rrg_proto::try_from_duration(since_epoch)
+        .map_err(|error| crate::request::ParseArgsError::invalid_field(name, error))?;
+
+    Ok(Some(std::time::UNIX_EPOCH + since_epoch))
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_audit_events::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let paths = proto.take_paths().into_iter()
+            .map(PathBuf::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| ParseArgsError::invalid_field("paths", error))?;
+
+        Ok(Args {
+            paths,
+            start_time: parse_time("start_time", proto.start_time.take())?,
+            end_time: parse_time("end_time", proto.end_time.take())?,
+            record_types: proto.take_record_types(),
+            max_records: proto.max_records.unwrap_or(DEFAULT_MAX_RECORDS),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_audit_events::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::get_audit_events as proto;
+
+        let mut result = Self::Proto::default();
+        result.set_time(rrg_proto::into_timestamp(self.event.id.time()));
+        result.set_serial(self.event.id.serial);
+        result.set_execve_args(self.event.execve_args());
+
+        if let Some(record) = self.event.syscall() {
+            let mut syscall = proto::Syscall::default();
+            if let Some(number) = record.value("syscall").and_then(|value| value.parse().ok()) {
+                syscall.set_syscall(number);
+            }
+            if let Some(arch) = record.value("arch").and_then(|value| u32::from_str_radix(value, 16).ok()) {
+                syscall.set_arch(arch);
+            }
+            syscall.set_success(record.value("success") == Some("yes"));
+            if let Some(exit) = record.value("exit").and_then(|value| value.parse().ok()) {
+                syscall.set_exit(exit);
+            }
+            if let Some(pid) = record.value("pid").and_then(|value| value.parse().ok()) {
+                syscall.set_pid(pid);
+            }
+            if let Some(ppid) = record.value("ppid").and_then(|value| value.parse().ok()) {
+                syscall.set_ppid(ppid);
+            }
+            if let Some(uid) = record.value("uid").and_then(|value| value.parse().ok()) {
+                syscall.set_uid(uid);
+            }
+            if let Some(auid) = record.value("auid").and_then(|value| value.parse().ok()) {
+                syscall.set_auid(auid);
+            }
+            if let Some(comm) = record.field("comm").and_then(Field::decode) {
+                syscall.set_comm(comm);
+            }
+            if let Some(exe) = record.field("exe").and_then(Field::decode) {
+                syscall.set_exe(into_path(exe).into());
+            }
+            if let Some(key) = record.field("key").and_then(Field::decode) {
+                syscall.set_key(String::from_utf8_lossy(&key).into_owned());
+            }
+
+            result.set_syscall(syscall);
+        }
+
+        result.set_paths(self.event.paths().map(|record| {
+            let mut path = proto::Path::default();
+            if let Some(item) = record.value("item").and_then(|value| value.parse().ok()) {
+                path.set_item(item);
+            }
+            if let Some(name) = record.field("name").and_then(Field::decode) {
+                path.set_name(into_path(name).into());
+            }
+            if let Some(inode) = record.value("inode").and_then(|value| value.parse().ok()) {
+                path.set_inode(inode);
+            }
+            if let Some(mode) = record.value("mode").and_then(|value| u32::from_str_radix(value, 8).ok()) {
+                path.set_mode(mode);
+            }
+            if let Some(nametype) = record.value("nametype") {
+                path.set_nametype(String::from(nametype));
+            }
+
+            path
+        }).collect());
+
+        result.set_records(self.event.records.into_iter().map(|record| {
+            let mut proto_record = proto::Record::default();
+            proto_record.set_type(record.r#type);
+            proto_record.set_fields(record.fields.into_iter().map(|field| {
+                let mut proto_field = proto::Field::default();
+                proto_field.set_name(field.name);
+                proto_field.set_value(field.value);
+
+                proto_field
+            }).collect());
+
+            proto_record
+        }).collect());
+
+        result
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    const SAMPLE: &str = "\
+type=SYSCALL msg=audit(1700000000.123:100): arch=c000003e syscall=59 success=yes exit=0 a0=55d0 a1=55d1 items=2 ppid=1000 pid=1001 auid=1000 uid=0 tty=pts0 ses=1 comm=\"ls\" exe=\"/usr/bin/ls\" key=\"exec\"
+type=EXECVE msg=audit(1700000000.123:100): argc=3 a0=\"ls\" a1=\"-l\" a2=2F746D702F6D7920646972
+type=USER_LOGIN msg=audit(1700000000.200:101): pid=900 uid=0 auid=1000 ses=1 msg='op=login id=1000 exe=\"/usr/sbin/sshd\" addr=10.0.0.1 res=success'
+type=CWD msg=audit(1700000000.123:100): cwd=\"/root\"
+type=PATH msg=audit(1700000000.123:100): item=0 name=\"/usr/bin/ls\" inode=1234 dev=fd:00 mode=0100755 nametype=NORMAL
+type=PATH msg=audit(1700000000.123:100): item=1 name=(null) inode=5678 dev=fd:00 mode=0100755 nametype=NORMAL
+type=PROCTITLE msg=audit(1700000000.123:100): proctitle=6C73002D6C
+type=EOE msg=audit(1700000000.123:100):
+this is not an audit record
+type=EXECVE msg=audit(1700000100.000:102): argc=2 a0=\"cat\" a1_len=7 a1[0]=666F6F a1[1]=62617221
+type=SYSCALL msg=audit(1700000100.000:102): arch=c000003e syscall=59 success=no exit=-13 ppid=1 pid=2 auid=4294967295 uid=1000 comm=\"cat\" exe=\"/usr/bin/cat\" key=(null)\u{1d}ARCH=x86_64 SYSCALL=execve AUID=\"unset\" UID=\"user\"
+";
+
+    fn sample_args(tempdir: &tempfile::TempDir) -> Args {
+        let path = tempdir.path().join("audit.log");
+        std::fs::write(&path, SAMPLE)
+            .unwrap();
+
+        Args {
+            paths: vec![path],
+            start_time: None,
+            end_time: None,
+            record_types: vec![],
+            max_records: DEFAULT_MAX_RECORDS,
+        }
+    }
+
+    fn record_types(event: &Event) -> Vec<&str> {
+        event.records.iter()
+            .map(|record| record.r#type.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn handle_sample_grouped() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, sample_args(&tempdir)).is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+
+        let event = &items[0].event;
+        assert_eq!(event.id, EventId { time_millis: 1700000000123, serial: 100 });
+        assert_eq!(record_types(event), vec!["SYSCALL", "EXECVE", "CWD", "PATH", "PATH", "PROCTITLE"]);
+
+        let event = &items[1].event;
+        assert_eq!(event.id, EventId { time_millis: 1700000000200, serial: 101 });
+        assert_eq!(record_types(event), vec!["USER_LOGIN"]);
+
+        let event = &items[2].event;
+        assert_eq!(event.id, EventId { time_millis: 1700000100000, serial: 102 });
+        assert_eq!(record_types(event), vec!["EXECVE", "SYSCALL"]);
+    }
+
+    #[test]
+    fn handle_sample_execve_args() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, sample_args(&tempdir)).is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+
+        assert_eq! {
+            items[0].event.execve_args(),
+            vec![b"ls".to_vec(), b"-l".to_vec(), b"/tmp/my dir".to_vec()],
+        };
+        assert!(items[1].event.execve_args().is_empty());
+        assert_eq! {
+            items[2].event.execve_args(),
+            vec![b"cat".to_vec(), b"foobar!".to_vec()],
+        };
+    }
+
+    #[test]
+    fn handle_sample_record_types() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            record_types: vec![String::from("SYSCALL"), String::from("USER_LOGIN")],
+            ..sample_args(&tempdir)
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(record_types(&items[0].event), vec!["SYSCALL"]);
+        assert_eq!(record_types(&items[1].event), vec!["USER_LOGIN"]);
+        assert_eq!(record_types(&items[2].event), vec!["SYSCALL"]);
+    }
+
+    #[test]
+    fn handle_sample_time_range() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            start_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_millis(1700000000150)),
+            end_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1700000050)),
+            ..sample_args(&tempdir)
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).event.id.serial, 101);
+    }
+
+    #[test]
+    fn handle_sample_max_records() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            max_records: 7,
+            ..sample_args(&tempdir)
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        // The third event has two records and does not fit in the limit.
+        let serials = session.replies::<Item>()
+            .map(|item| item.event.id.serial)
+            .collect::<Vec<_>>();
+        assert_eq!(serials, vec![100, 101]);
+    }
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().join("audit.log")],
+            start_time: None,
+            end_time: None,
+            record_types: vec![],
+            max_records: DEFAULT_MAX_RECORDS,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn events_interleaved_without_eoe() {
+        let log = "\
+type=SYSCALL msg=audit(1.000:1): pid=1
+type=SYSCALL msg=audit(2.000:2): pid=2
+type=PATH msg=audit(1.000:1): item=0
+type=PATH msg=audit(2.000:2): item=0
+";
+
+        let events = Events::new(log.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id.serial, 1);
+        assert_eq!(record_types(&events[0]), vec!["SYSCALL", "PATH"]);
+        assert_eq!(events[1].id.serial, 2);
+        assert_eq!(record_types(&events[1]), vec!["SYSCALL", "PATH"]);
+    }
+
+    #[test]
+    fn parse_record_node() {
+        let (id, record) = parse_record("node=foo type=CWD msg=audit(17.5:42): cwd=\"/\"")
+            .unwrap();
+        assert_eq!(id, EventId { time_millis: 17005, serial: 42 });
+        assert_eq!(record.r#type, "CWD");
+        assert_eq!(record.value("node"), Some("foo"));
+        assert_eq!(record.value("cwd"), Some("/"));
+    }
+
+    #[test]
+    fn parse_record_malformed() {
+        assert!(parse_record("").is_none());
+        assert!(parse_record("type=CWD cwd=\"/\"").is_none());
+        assert!(parse_record("type=CWD msg=audit(foo:42): cwd=\"/\"").is_none());
+    }
+
+    #[test]
+    fn parse_fields_user_message() {
+        let fields = parse_fields("pid=1 msg='op=login exe=\"/usr/sbin/sshd\" res=success' ses=2");
+
+        let names = fields.iter()
+            .map(|field| field.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["pid", "op", "exe", "res", "ses"]);
+        assert_eq!(fields[2].value, "/usr/sbin/sshd");
+        assert!(fields[2].quoted);
+    }
+
+    #[test]
+    fn into_proto_sample() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, sample_args(&tempdir)).is_ok());
+
+        let proto = crate::response::Item::into_proto(Item {
+            event: session.reply::<Item>(0).event.clone(),
+        });
+        assert_eq!(proto.time().seconds, 1700000000);
+        assert_eq!(proto.time().nanos, 123_000_000);
+        assert_eq!(proto.serial(), 100);
+        assert_eq!(proto.records.len(), 6);
+        assert_eq!(proto.execve_args.len(), 3);
+
+        let syscall = proto.syscall();
+        assert_eq!(syscall.syscall(), 59);
+        assert_eq!(syscall.arch(), 0xc000003e);
+        assert!(syscall.success());
+        assert_eq!(syscall.pid(), 1001);
+        assert_eq!(syscall.ppid(), 1000);
+        assert_eq!(syscall.auid(), 1000);
+        assert_eq!(syscall.comm(), b"ls");
+        assert_eq!(syscall.exe().raw_bytes(), b"/usr/bin/ls");
+        assert_eq!(syscall.key(), "exec");
+
+        assert_eq!(proto.paths.len(), 2);
+        assert_eq!(proto.paths[0].name().raw_bytes(), b"/usr/bin/ls");
+        assert_eq!(proto.paths[0].inode(), 1234);
+        assert_eq!(proto.paths[0].mode(), 0o100755);
+        assert_eq!(proto.paths[0].nametype(), "NORMAL");
+        assert!(!proto.paths[1].has_name());
+        assert_eq!(proto.paths[1].item(), 1);
+    }
+
+    #[test]
+    fn decode_hex_invalid() {
+        assert_eq!(decode_hex("666F6F"), Some(b"foo".to_vec()));
+        assert_eq!(decode_hex(""), None);
+        assert_eq!(decode_hex("666"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
@@ -0,0 +1,587 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of authentication-related configuration files.
+//!
+//! Attackers often escalate privileges or keep access to a machine by tweaking
+//! `sudo` rules, PAM modules or the configuration of the SSH daemon, so these
+//! are collected from their well-known locations (following includes).
+//!
+//! Includes of individual files are followed wherever they point to, but the
+//! only directories that are ever listed are the well-known ones. PAM files
+//! can include only other PAM files, so they are collected without parsing.
+
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+
+/// Maximum number of bytes of a single file that are collected.
+#[cfg(target_os = "linux")]
+const MAX_FILE_LEN: u64 = 1024 * 1024; // 1 MiB.
+
+/// Maximum depth of nested includes that are followed.
+#[cfg(target_os = "linux")]
+const MAX_INCLUDE_DEPTH: u32 = 8;
+
+/// Directories that can be listed to collect the files in them.
+#[cfg(target_os = "linux")]
+const KNOWN_DIRS: &[&str] = &[
+    "/etc/sudoers.d",
+    "/etc/pam.d",
+    "/etc/ssh",
+    "/etc/ssh/sshd_config.d",
+];
+
+/// Result of the `get_auth_config` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Kind of configuration the file belongs to.
+    source: Source,
+    /// Path to the file.
+    path: PathBuf,
+    /// Path to the file that included this one (if any).
+    included_from: Option<PathBuf>,
+    /// Contents of the file or the error that prevented reading it.
+    contents: std::io::Result<Contents>,
+}
+
+/// Contents of a collected file.
+#[cfg(target_os = "linux")]
+struct Contents {
+    /// Collected bytes of the file.
+    data: Vec<u8>,
+    /// Whether the file was bigger than the collected bytes.
+    truncated: bool,
+}
+
+/// Kind of authentication-related configuration.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    /// Rules of `sudo`.
+    Sudoers,
+    /// Configuration of PAM modules.
+    Pam,
+    /// Configuration of the SSH daemon.
+    SshdConfig,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetAuthConfig;
+
+/// Handles invocations of the `get_auth_config` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `get_auth_config` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    collect(session, Path::new("/"))
+}
+
+/// Collects the configuration files from the system mounted at `root`.
+///
+/// Reported paths (and paths in includes) are relative to `root`, as if it was
+/// the root of the filesystem.
+#[cfg(target_os = "linux")]
+fn collect<S>(session: &mut S, root: &Path) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut collector = Collector {
+        root,
+        visited: std::collections::HashSet::new(),
+    };
+
+    // `/etc/sudoers` usually includes the whole `/etc/sudoers.d` directory, so
+    // most of its files are collected as includes. We still want the ones that
+    // are not included though.
+    collector.collect_file(session, Source::Sudoers, Path::new("/etc/sudoers"), None, 0)?;
+    for path in collector.list_dir(Path::new("/etc/sudoers.d")) {
+        collector.collect_file(session, Source::Sudoers, &path, None, 0)?;
+    }
+
+    for path in collector.list_dir(Path::new("/etc/pam.d")) {
+        collector.collect_file(session, Source::Pam, &path, None, 0)?;
+    }
+
+    collector.collect_file(session, Source::SshdConfig, Path::new("/etc/ssh/sshd_config"), None, 0)?;
+
+    Ok(())
+}
+
+/// State of the collection of configuration files.
+#[cfg(target_os = "linux")]
+struct Collector<'a> {
+    /// Root of the filesystem to collect the files from.
+    root: &'a Path,
+    /// Paths to the files collected so far (to avoid include cycles).
+    visited: std::collections::HashSet<PathBuf>,
+}
+
+/// Reference to other configuration files.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+enum Include {
+    /// A single file.
+    File(PathBuf),
+    /// All the files in a directory.
+    Dir(PathBuf),
+    /// All the files matching a glob pattern.
+    Glob(PathBuf),
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> Collector<'a> {
+
+    /// Collects the file at `path` and all the files it includes.
+    ///
+    /// Files that do not exist (or have already been collected) are skipped.
+    fn collect_file<S>(
+        &mut self,
+        session: &mut S,
+        source: Source,
+        path: &Path,
+        included_from: Option<&Path>,
+        depth: u32,
+    ) -> crate::session::Result<()>
+    where
+        S: crate::session::Session,
+    {
+        if self.visited.contains(path) {
+            return Ok(());
+        }
+
+        let contents = match read_file(&self.host_path(path)) {
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => {
+                log::warn!("failed to read '{}': {error}", path.display());
+                Err(error)
+            }
+            Ok(contents) => Ok(contents),
+        };
+        self.visited.insert(path.to_path_buf());
+
+        let includes = match &contents {
+            Ok(contents) => includes(source, path, &contents.data),
+            Err(_) => Vec::new(),
+        };
+
+        session.reply(Item {
+            source,
+            path: path.to_path_buf(),
+            included_from: included_from.map(Path::to_path_buf),
+            contents,
+        })?;
+
+        if !includes.is_empty() && depth >= MAX_INCLUDE_DEPTH {
+            log::warn!("too deeply nested includes in '{}'", path.display());
+            return Ok(());
+        }
+
+        for include in includes {
+            let paths = match include {
+                Include::File(path) => vec![path],
+                Include::Dir(dir) => self.list_dir(&dir),
+                Include::Glob(pattern) => self.glob(&pattern),
+            };
+
+            for include_path in paths {
+                self.collect_file(session, source, &include_path, Some(path), depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns paths to files in the directory at `path` (if it is known).
+    ///
+    /// Subdirectories are not included and are never descended into.
+    fn list_dir(&self, path: &Path) -> Vec<PathBuf> {
+        if !KNOWN_DIRS.iter().any(|dir| Path::new(dir) == path) {
+            log::warn!("not listing unknown directory '{}'", path.display());
+            return Vec::new();
+        }
+
+        let entries = match std::fs::read_dir(self.host_path(path)) {
+            Ok(entries) => entries,
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("failed to list '{}': {error}", path.display());
+                }
+                return Vec::new();
+            }
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(error) => {
+                    log::warn!("failed to read entry of '{}': {error}", path.display());
+                    None
+                }
+            })
+            // We want to report files that cannot be read (e.g. because of a
+            // symlink loop), so we skip only entries known to be directories.
+            .filter(|entry| !entry.path().metadata().is_ok_and(|metadata| metadata.is_dir()))
+            .map(|entry| path.join(entry.file_name()))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        paths
+    }
+
+    /// Returns paths to files matching the given glob `pattern`.
+    ///
+    /// Only the last component of the pattern can contain wildcards and the
+    /// parent has to be one of the known directories.
+    fn glob(&self, pattern: &Path) -> Vec<PathBuf> {
+        let parent = match pattern.parent() {
+            Some(parent) if KNOWN_DIRS.iter().any(|dir| Path::new(dir) == parent) => parent,
+            _ => {
+                log::warn!("not expanding pattern '{}' outside of known directories", pattern.display());
+                return Vec::new();
+            }
+        };
+
+        let file_pattern = match pattern.file_name() {
+            Some(file_pattern) => file_pattern,
+            None => return Vec::new(),
+        };
+
+        match crate::fs::glob(self.host_path(parent).join(file_pattern)) {
+            Ok(paths) => paths.into_iter()
+                .filter_map(|path| path.file_name().map(|name| parent.join(name)))
+                .collect(),
+            Err(error) => {
+                log::warn!("failed to expand pattern '{}': {error}", pattern.display());
+                Vec::new()
+            }
+        }
+    }
+
+    /// Maps an absolute path on the collected system to a path on the host.
+    fn host_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+/// Reads the first [`MAX_FILE_LEN`] bytes of the file at `path`.
+#[cfg(target_os = "linux")]
+fn read_file(path: &Path) -> std::io::Result<Contents> {
+    use std::io::Read as _;
+
+    let file = crate::fs::open_file(path)?;
+    if file.metadata()?.is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "is a directory"));
+    }
+
+    let mut data = Vec::new();
+    // We read one byte more than needed to learn whether the file is bigger.
+    file.take(MAX_FILE_LEN + 1).read_to_end(&mut data)?;
+
+    let truncated = data.len() as u64 > MAX_FILE_LEN;
+    data.truncate(MAX_FILE_LEN as usize);
+
+    Ok(Contents {
+        data,
+        truncated,
+    })
+}
+
+/// Parses includes of other files from a file at `path` with given `data`.
+#[cfg(target_os = "linux")]
+fn includes(source: Source, path: &Path, data: &[u8]) -> Vec<Include> {
+    let data = String::from_utf8_lossy(data);
+
+    match source {
+        Source::Sudoers => data.lines()
+            .filter_map(|line| sudoers_include(path, line))
+            .collect(),
+        Source::SshdConfig => data.lines()
+            .flat_map(sshd_config_includes)
+            .collect(),
+        Source::Pam => Vec::new(),
+    }
+}
+
+/// Parses an include directive from a line of a sudoers file at `path`.
+///
+/// Relative paths are resolved against the directory of the including file
+/// (as done by `sudo` since version 1.9.1).
+#[cfg(target_os = "linux")]
+fn sudoers_include(path: &Path, line: &str) -> Option<Include> {
+    let line = line.trim();
+
+    // Both the legacy (`#`) and the modern (`@`) prefixes are supported.
+    let (is_dir, arg) = ["#includedir", "@includedir", "#include", "@include"].iter()
+        .find_map(|directive| {
+            let arg = line.strip_prefix(directive)?;
+            // The argument has to be separated from the directive as otherwise
+            // this is a comment (e.g. `#included by default`).
+            if !arg.starts_with(char::is_whitespace) {
+                return None;
+            }
+
+            Some((directive.ends_with("dir"), arg.trim()))
+        })?;
+
+    let arg = arg.strip_prefix('"')
+        .and_then(|arg| arg.strip_suffix('"'))
+        .unwrap_or(arg);
+    if arg.is_empty() {
+        return None;
+    }
+
+    let include_path = match path.parent() {
+        Some(parent) => parent.join(arg),
+        None => PathBuf::from(arg),
+    };
+
+    if is_dir {
+        Some(Include::Dir(include_path))
+    } else {
+        Some(Include::File(include_path))
+    }
+}
+
+/// Parses include directives from a line of an `sshd_config` file.
+///
+/// Relative paths are resolved against `/etc/ssh` (as done by `sshd`).
+#[cfg(target_os = "linux")]
+fn sshd_config_includes(line: &str) -> Vec<Include> {
+    let mut args = line.split_whitespace();
+
+    let is_include = args.next()
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("include"));
+    if !is_include {
+        return Vec::new();
+    }
+
+    args.map(|arg| arg.trim_matches('"'))
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| {
+            let include_path = Path::new("/etc/ssh").join(arg);
+            if arg.contains(['*', '?', '[']) {
+                Include::Glob(include_path)
+            } else {
+                Include::File(include_path)
+            }
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_auth_config::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::get_auth_config::Source as ProtoSource;
+
+        let mut proto = Self::Proto::default();
+        proto.set_source(match self.source {
+            Source::Sudoers => ProtoSource::SUDOERS,
+            Source::Pam => ProtoSource::PAM,
+            Source::SshdConfig => ProtoSource::SSHD_CONFIG,
+        });
+        proto.set_path(self.path.into());
+        if let Some(included_from) = self.included_from {
+            proto.set_included_from(included_from.into());
+        }
+        match self.contents {
+            Ok(contents) => {
+                proto.set_contents(contents.data);
+                proto.set_truncated(contents.truncated);
+            }
+            Err(error) => proto.set_error(error.to_string()),
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_some() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+    }
+
+    #[test]
+    fn collect_sources() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        for dir in ["etc/sudoers.d", "etc/pam.d", "etc/ssh/sshd_config.d", "etc/other.d"] {
+            std::fs::create_dir_all(root.join(dir))
+                .unwrap();
+        }
+
+        std::fs::write(root.join("etc/sudoers"), concat! {
+            "root ALL=(ALL:ALL) ALL\n",
+            "#includedir /etc/sudoers.d\n",
+            "@include sudoers.local\n",
+            "#include /etc/sudoers.missing\n",
+            "#includedir /etc/other.d\n",
+        }).unwrap();
+        std::fs::write(root.join("etc/sudoers.local"), "foo ALL=(ALL) NOPASSWD: ALL\n")
+            .unwrap();
+        std::fs::write(root.join("etc/sudoers.d/bar"), "bar ALL=(ALL) ALL\n")
+            .unwrap();
+        std::fs::write(root.join("etc/other.d/baz"), "baz ALL=(ALL) ALL\n")
+            .unwrap();
+
+        std::fs::write(root.join("etc/pam.d/sshd"), "@include common-auth\n")
+            .unwrap();
+        std::fs::write(root.join("etc/pam.d/common-auth"), "auth required pam_unix.so\n")
+            .unwrap();
+
+        std::fs::write(root.join("etc/ssh/sshd_config"), concat! {
+            "Include /etc/ssh/sshd_config.d/*.conf\n",
+            "Include /etc/other.d/*\n",
+            "PermitRootLogin no\n",
+        }).unwrap();
+        std::fs::write(root.join("etc/ssh/sshd_config.d/10-foo.conf"), "PasswordAuthentication no\n")
+            .unwrap();
+        std::fs::write(root.join("etc/ssh/sshd_config.d/ignored.txt"), "PasswordAuthentication yes\n")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(collect(&mut session, root).is_ok());
+
+        let items = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.source, item.included_from.clone()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(items, vec![
+            (PathBuf::from("/etc/sudoers"), Source::Sudoers, None),
+            (PathBuf::from("/etc/sudoers.d/bar"), Source::Sudoers, Some(PathBuf::from("/etc/sudoers"))),
+            (PathBuf::from("/etc/sudoers.local"), Source::Sudoers, Some(PathBuf::from("/etc/sudoers"))),
+            (PathBuf::from("/etc/pam.d/common-auth"), Source::Pam, None),
+            (PathBuf::from("/etc/pam.d/sshd"), Source::Pam, None),
+            (PathBuf::from("/etc/ssh/sshd_config"), Source::SshdConfig, None),
+            (PathBuf::from("/etc/ssh/sshd_config.d/10-foo.conf"), Source::SshdConfig, Some(PathBuf::from("/etc/ssh/sshd_config"))),
+        ]);
+
+        let item = session.replies::<Item>()
+            .find(|item| item.path == Path::new("/etc/sudoers.local"))
+            .unwrap();
+        let contents = item.contents.as_ref().unwrap();
+        assert_eq!(contents.data, b"foo ALL=(ALL) NOPASSWD: ALL\n");
+        assert!(!contents.truncated);
+    }
+
+    #[test]
+    fn collect_unreadable() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("etc/pam.d"))
+            .unwrap();
+        std::fs::write(root.join("etc/pam.d/login"), "auth required pam_unix.so\n")
+            .unwrap();
+        // A symlink loop cannot be read (regardless of permissions).
+        std::os::unix::fs::symlink(root.join("etc/pam.d/loop"), root.join("etc/pam.d/loop"))
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(collect(&mut session, root).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item_login = session.reply::<Item>(0);
+        assert_eq!(item_login.path, Path::new("/etc/pam.d/login"));
+        assert!(item_login.contents.is_ok());
+
+        let item_loop = session.reply::<Item>(1);
+        assert_eq!(item_loop.path, Path::new("/etc/pam.d/loop"));
+        assert_eq!(item_loop.source, Source::Pam);
+        assert!(item_loop.contents.is_err());
+    }
+
+    #[test]
+    fn collect_include_cycle() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("etc"))
+            .unwrap();
+        std::fs::write(root.join("etc/sudoers"), "@include /etc/sudoers.foo\n")
+            .unwrap();
+        std::fs::write(root.join("etc/sudoers.foo"), "@include /etc/sudoers\n")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(collect(&mut session, root).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+    }
+
+    #[test]
+    fn read_file_truncated() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), vec![0; MAX_FILE_LEN as usize + 1])
+            .unwrap();
+
+        let contents = read_file(&tempdir.path().join("foo"))
+            .unwrap();
+        assert_eq!(contents.data.len() as u64, MAX_FILE_LEN);
+        assert!(contents.truncated);
+    }
+
+    #[test]
+    fn sudoers_include_directives() {
+        let path = Path::new("/etc/sudoers");
+
+        assert_eq! {
+            sudoers_include(path, "#include /etc/foo"),
+            Some(Include::File(PathBuf::from("/etc/foo"))),
+        };
+        assert_eq! {
+            sudoers_include(path, "@includedir /etc/sudoers.d"),
+            Some(Include::Dir(PathBuf::from("/etc/sudoers.d"))),
+        };
+        assert_eq! {
+            sudoers_include(path, "@include \"bar baz\""),
+            Some(Include::File(PathBuf::from("/etc/bar baz"))),
+        };
+        assert_eq!(sudoers_include(path, "#included by default"), None);
+        assert_eq!(sudoers_include(path, "# include /etc/foo"), None);
+        assert_eq!(sudoers_include(path, "root ALL=(ALL) ALL"), None);
+    }
+
+    #[test]
+    fn sshd_config_includes_directives() {
+        assert_eq! {
+            sshd_config_includes("Include foo.conf /etc/ssh/sshd_config.d/*.conf"),
+            vec![
+                Include::File(PathBuf::from("/etc/ssh/foo.conf")),
+                Include::Glob(PathBuf::from("/etc/ssh/sshd_config.d/*.conf")),
+            ],
+        };
+        assert_eq!(sshd_config_includes("include /etc/foo").len(), 1);
+        assert!(sshd_config_includes("#Include /etc/foo").is_empty());
+        assert!(sshd_config_includes("PermitRootLogin no").is_empty());
+    }
+}
@@ -0,0 +1,441 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of SSH keys that are authorized to log in as local users.
+//!
+//! Adding a key to `~/.ssh/authorized_keys` is one of the most common ways of
+//! persisting access to a machine, so the keys (along with options that can
+//! e.g. force a particular command) are returned for all local users.
+
+use std::path::{Path, PathBuf};
+
+/// Paths (relative to the home directory) of authorized keys files.
+const AUTHORIZED_KEYS_FILES: &[&str] = &[
+    ".ssh/authorized_keys",
+    ".ssh/authorized_keys2",
+];
+
+/// Prefixes of key types supported by OpenSSH.
+///
+/// This includes certificates (e.g. `ssh-ed25519-cert-v01@openssh.com`) and
+/// security keys (e.g. `sk-ssh-ed25519@openssh.com`).
+const KEY_TYPE_PREFIXES: &[&str] = &[
+    "ssh-",
+    "ecdsa-",
+    "sk-",
+];
+
+/// Result of the `get_authorized_keys` action.
+struct Item {
+    /// Name of the user that the key authorizes logging in as.
+    user: std::ffi::OsString,
+    /// Path to the file the key comes from.
+    path: PathBuf,
+    /// Key parsed from the file.
+    key: Key,
+}
+
+/// Entry of an authorized keys file.
+#[derive(Debug)]
+struct Key {
+    /// Type of the key (e.g. `ssh-ed25519`).
+    key_type: String,
+    /// Fingerprint of the key in the OpenSSH format (e.g. `SHA256:V9OE...`).
+    fingerprint: String,
+    /// Comment of the key (empty if there is none).
+    comment: String,
+    /// Options of the key in the order of their appearance.
+    options: Vec<KeyOption>,
+}
+
+/// Single option of an authorized key (e.g. `command="/bin/false"`).
+#[derive(Debug, PartialEq, Eq)]
+struct KeyOption {
+    /// Name of the option.
+    name: String,
+    /// Value of the option with quotes removed (for non-flag options).
+    value: Option<String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetAuthorizedKeys;
+
+/// Handles invocations of the `get_authorized_keys` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let users = ospect::os::users()
+        .map_err(crate::session::Error::action)?;
+
+    for user in users {
+        handle_user(session, &user)?;
+    }
+
+    Ok(())
+}
+
+/// Collects authorized keys of the given `user` and replies with them.
+fn handle_user<S>(
+    session: &mut S,
+    user: &ospect::os::User,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for authorized_keys_file in AUTHORIZED_KEYS_FILES {
+        let path = user.home.join(authorized_keys_file);
+
+        // Most users do not have any authorized keys (and system accounts do
+        // not even have home directories), we do not want to spam logs for
+        // them.
+        let keys = match read_keys(&path) {
+            Ok(keys) => keys,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(error) => {
+                log::warn! {
+                    "failed to read authorized keys file '{}' of user '{}': {}",
+                    path.display(), user.name.to_string_lossy(), error,
+                };
+                continue;
+            }
+        };
+
+        for key in keys {
+            session.reply(Item {
+                user: user.name.clone(),
+                path: path.clone(),
+                key,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads all the keys from the authorized keys file at the given `path`.
+///
+/// Malformed lines are skipped (with a warning).
+fn read_keys(path: &Path) -> std::io::Result<Vec<Key>> {
+    let data = std::fs::read(path)?;
+
+    let mut keys = Vec::new();
+    for line in String::from_utf8_lossy(&data).lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_line(line) {
+            Some(key) => keys.push(key),
+            None => log::warn! {
+                "malformed line in authorized keys file '{}'",
+                path.display(),
+            },
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Parses a single (non-empty and non-comment) line of an authorized keys file.
+///
+/// The line has the `[options] type base64-key [comment]` format as described
+/// in the `sshd(8)` manual.
+fn parse_line(line: &str) -> Option<Key> {
+    // Similarly to OpenSSH, we first try to parse the line as a key without
+    // options and only if it fails, we consider the first field to be options.
+    if let Some(key) = parse_key(line) {
+        return Some(key);
+    }
+
+    let (options, rest) = split_options(line)?;
+
+    let mut key = parse_key(rest.trim_start())?;
+    key.options = parse_options(options)?;
+
+    Some(key)
+}
+
+/// Parses a key without options (i.e. in the `type base64-key [comment]` form).
+fn parse_key(line: &str) -> Option<Key> {
+    use sha2::Digest as _;
+
+    let (key_type, rest) = split_whitespace(line)?;
+    if !KEY_TYPE_PREFIXES.iter().any(|prefix| key_type.starts_with(prefix)) {
+        return None;
+    }
+
+    let (blob, comment) = match split_whitespace(rest.trim_start()) {
+        Some((blob, comment)) => (blob, comment.trim()),
+        None => (rest.trim_start(), ""),
+    };
+
+    let blob = data_encoding::BASE64.decode(blob.as_bytes()).ok()?;
+    let digest = sha2::Sha256::digest(&blob);
+
+    Some(Key {
+        key_type: key_type.to_string(),
+        fingerprint: format!("SHA256:{}", data_encoding::BASE64_NOPAD.encode(&digest)),
+        comment: comment.to_string(),
+        options: Vec::new(),
+    })
+}
+
+/// Splits the given string at the first whitespace.
+fn split_whitespace(string: &str) -> Option<(&str, &str)> {
+    string.split_once(|char: char| char.is_ascii_whitespace())
+}
+
+/// Splits the line into the options field and the rest.
+///
+/// Options end at the first whitespace that is not within quotes.
+fn split_options(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+
+    let mut chars = line.char_indices().peekable();
+    while let Some((idx, char)) = chars.next() {
+        match char {
+            '\\' if matches!(chars.peek(), Some((_, '"'))) => {
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            char if char.is_ascii_whitespace() && !quoted => {
+                return Some((&line[..idx], &line[idx..]));
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Parses a comma-separated list of options (e.g. `no-pty,from="10.0.0.1"`).
+///
+/// Quotes are removed from the values and escaped quotes (`\"`) are unescaped,
+/// other backslashes are kept as they are (like OpenSSH does).
+fn parse_options(options: &str) -> Option<Vec<KeyOption>> {
+    let mut result = Vec::new();
+
+    let mut name = String::new();
+    let mut value = None::<String>;
+    let mut quoted = false;
+
+    let mut chars = options.chars().peekable();
+    while let Some(char) = chars.next() {
+        match (value.as_mut(), char) {
+            (_, ',') if !quoted => result.push(KeyOption {
+                name: std::mem::take(&mut name),
+                value: value.take(),
+            }),
+            (None, '=') => value = Some(String::new()),
+            (None, char) => name.push(char),
+            (Some(value), '\\') if chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            (Some(_), '"') => quoted = !quoted,
+            (Some(value), char) => value.push(char),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    result.push(KeyOption { name, value });
+
+    if result.iter().any(|option| option.name.is_empty()) {
+        return None;
+    }
+
+    Some(result)
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_authorized_keys::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_user(self.user.to_string_lossy().into_owned());
+        proto.set_path(self.path.into());
+        proto.set_key_type(self.key.key_type);
+        proto.set_fingerprint(self.key.fingerprint);
+        proto.set_comment(self.key.comment);
+
+        for option in self.key.options {
+            let mut option_proto = rrg_proto::get_authorized_keys::KeyOption::default();
+            option_proto.set_name(option.name);
+            if let Some(value) = option.value {
+                option_proto.set_value(value);
+            }
+
+            proto.mut_options().push(option_proto);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const ED25519_KEY: &str = "\
+ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAICKxjurh8EpnuBXZUfCkf6EjsebbkKfLiv6R31Sp4tMd";
+
+    const ED25519_FINGERPRINT: &str = "\
+SHA256:V9OEkpsg1cpXIQJKfGDj47eCREsqWGAD8pvcHvu8wdI";
+
+    fn option(name: &str, value: Option<&str>) -> KeyOption {
+        KeyOption {
+            name: name.to_string(),
+            value: value.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn handle_user_with_options() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join(".ssh"))
+            .unwrap();
+        std::fs::write(tempdir.path().join(".ssh").join("authorized_keys"), format! {
+            "# Keys of the administrators.\n\
+             {ED25519_KEY} alice@example.com\n\
+             \n\
+             command=\"/usr/bin/backup --dir \\\"/var/b,c\\\"\",from=\"10.0.0.1,10.0.0.2\",no-pty {ED25519_KEY} backup\n"
+        }).unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.user, "foo");
+        assert_eq!(item.path, tempdir.path().join(".ssh").join("authorized_keys"));
+        assert_eq!(item.key.key_type, "ssh-ed25519");
+        assert_eq!(item.key.fingerprint, ED25519_FINGERPRINT);
+        assert_eq!(item.key.comment, "alice@example.com");
+        assert!(item.key.options.is_empty());
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.user, "foo");
+        assert_eq!(item.key.key_type, "ssh-ed25519");
+        assert_eq!(item.key.fingerprint, ED25519_FINGERPRINT);
+        assert_eq!(item.key.comment, "backup");
+        assert_eq!(item.key.options, vec![
+            option("command", Some("/usr/bin/backup --dir \"/var/b,c\"")),
+            option("from", Some("10.0.0.1,10.0.0.2")),
+            option("no-pty", None),
+        ]);
+    }
+
+    #[test]
+    fn handle_user_authorized_keys2() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join(".ssh"))
+            .unwrap();
+        std::fs::write(tempdir.path().join(".ssh").join("authorized_keys2"), ED25519_KEY)
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.path().join(".ssh").join("authorized_keys2"));
+        assert_eq!(item.key.comment, "");
+    }
+
+    #[test]
+    fn handle_user_non_existent_home() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().join("nonexistent"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_user_malformed_lines() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join(".ssh"))
+            .unwrap();
+        std::fs::write(tempdir.path().join(".ssh").join("authorized_keys"), format! {
+            "foo bar baz\n\
+             ssh-ed25519 not-base64!\n\
+             command=\"unterminated {ED25519_KEY}\n\
+             {ED25519_KEY}\n"
+        }).unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).key.fingerprint, ED25519_FINGERPRINT);
+    }
+
+    #[test]
+    fn parse_line_certificate() {
+        let key = parse_line("cert-authority,principals=\"root\" ssh-ed25519-cert-v01@openssh.com AAAA ca")
+            .unwrap();
+
+        assert_eq!(key.key_type, "ssh-ed25519-cert-v01@openssh.com");
+        assert_eq!(key.comment, "ca");
+        assert_eq!(key.options, vec![
+            option("cert-authority", None),
+            option("principals", Some("root")),
+        ]);
+    }
+
+    #[test]
+    fn parse_options_empty_name() {
+        assert!(parse_options("no-pty,,no-agent-forwarding").is_none());
+    }
+
+    #[test]
+    fn parse_options_backslash() {
+        assert_eq!(parse_options("command=\"echo \\\\n\"").unwrap(), vec![
+            option("command", Some("echo \\\\n")),
+        ]);
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+/// Result of the `get_client_actions` action.
+struct Item {
+    /// Metadata about the agent.
+    metadata: crate::startup::Metadata,
+    /// Actions that the agent can execute.
+    actions: Vec<crate::request::Action>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetClientActions;
+
+/// Handles invocations of the `get_client_actions` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let registry = crate::action::Registry::<S>::new(session.args());
+
+    let mut actions = registry.actions().collect::<Vec<_>>();
+    actions.sort_by_key(|action| rrg_proto::rrg::Action::from(*action) as i32);
+
+    session.reply(Item {
+        metadata: crate::startup::Metadata::from_cargo(),
+        actions,
+    })?;
+
+    Ok(())
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_client_actions::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_metadata(self.metadata.into());
+        proto.set_actions(self.actions.into_iter()
+            .map(|action| rrg_proto::rrg::Action::from(action).into())
+            .collect());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_default() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let registry = crate::action::Registry::<crate::session::FakeSession>::new(&Default::default());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.actions.len(), registry.actions().count());
+        assert!(registry.actions().all(|action| item.actions.contains(&action)));
+        assert!(item.actions.contains(&crate::request::Action::GetClientActions));
+    }
+
+    #[test]
+    #[cfg(feature = "action-get_file_contents")]
+    fn handle_denied_action() {
+        use crate::request::Action;
+
+        let mut args = crate::args::Args::default();
+        args.denied_actions.push(Action::GetFileContents);
+
+        let mut session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut session, ()).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(!item.actions.contains(&Action::GetFileContents));
+        assert!(item.actions.contains(&Action::GetClientActions));
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Computation of directory sizes (similar to the `du` utility).
+//!
+//! Note that files with multiple hardlinks are counted only once (for the first
+//! path through which they are encountered). It means that if hardlinks to the
+//! same file are present in different subdirectories, only one of subtotals of
+//! these subdirectories includes it and which one depends on the order of the
+//! traversal.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Arguments of the `get_directory_size` action.
+pub struct Args {
+    /// Path to the directory to compute the size of.
+    path: PathBuf,
+    /// Depth up to which subtotals for subdirectories are reported.
+    max_depth: u32,
+}
+
+/// Result of the `get_directory_size` action.
+struct Item {
+    /// Path to the directory the sizes are computed for.
+    path: PathBuf,
+    /// Sizes of the directory subtree.
+    totals: Totals,
+}
+
+/// Accumulated sizes of a directory subtree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Totals {
+    /// Total apparent size of all the files in the subtree.
+    apparent_size: u64,
+    /// Total size of disk space allocated for all the files in the subtree.
+    allocated_size: u64,
+    /// Total number of files in the subtree.
+    file_count: u64,
+    /// Total number of directories in the subtree.
+    dir_count: u64,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetDirectorySize;
+
+/// Handles invocations of the `get_directory_size` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut totals = HashMap::new();
+    totals.insert(args.path.clone(), Totals::default());
+
+    #[cfg(target_family = "unix")]
+    let mut inodes = std::collections::HashSet::new();
+
+    // Walking the directory does not cross device boundaries, so we will not
+    // end up computing sizes of other (e.g. network) filesystems.
+    let entries = crate::fs::walk_dir(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("failed to obtain directory entry: {error}");
+                continue;
+            }
+        };
+
+        // Relative path of the entry has at least one component, so the depth
+        // is always positive.
+        let rel_path = match entry.path.strip_prefix(&args.path) {
+            Ok(rel_path) => rel_path,
+            Err(_) => continue,
+        };
+        let depth = rel_path.components().count();
+
+        let is_dir = entry.metadata.is_dir();
+        if is_dir && depth <= args.max_depth as usize {
+            totals.entry(entry.path.clone()).or_default();
+        }
+
+        let mut entry_totals = Totals::default();
+        if is_dir {
+            entry_totals.dir_count = 1;
+        } else {
+            #[cfg(target_family = "unix")]
+            {
+                use std::os::unix::fs::MetadataExt as _;
+
+                let metadata = &entry.metadata;
+                if metadata.nlink() > 1 && !inodes.insert((metadata.dev(), metadata.ino())) {
+                    continue;
+                }
+            }
+
+            entry_totals.file_count = 1;
+            entry_totals.apparent_size = entry.metadata.len();
+            entry_totals.allocated_size = allocated_size(&entry.metadata);
+        }
+
+        for ancestor in ancestors(&args.path, rel_path, args.max_depth) {
+            if let Some(ancestor_totals) = totals.get_mut(&ancestor) {
+                ancestor_totals.add(&entry_totals);
+            }
+        }
+    }
+
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+
+    for (path, totals) in totals {
+        session.reply(Item {
+            path,
+            totals,
+        })?;
+    }
+
+    Ok(())
+}
+
+impl Totals {
+
+    /// Adds the other totals to these ones.
+    fn add(&mut self, other: &Totals) {
+        self.apparent_size += other.apparent_size;
+        self.allocated_size += other.allocated_size;
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+    }
+}
+
+/// Returns paths to directories that should include the entry in their totals.
+///
+/// These are the `root` and all the proper ancestors of the entry (given by its
+/// relative path `rel_path`) up to the `max_depth`.
+fn ancestors(root: &Path, rel_path: &Path, max_depth: u32) -> Vec<PathBuf> {
+    let mut ancestors = vec![root.to_path_buf()];
+
+    let mut path = root.to_path_buf();
+
+    let mut components = rel_path.components().collect::<Vec<_>>();
+    components.pop();
+
+    for component in components.into_iter().take(max_depth as usize) {
+        path.push(component);
+        ancestors.push(path.clone());
+    }
+
+    ancestors
+}
+
+/// Returns the size of disk space allocated for the file.
+#[cfg(target_family = "unix")]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt as _;
+
+    // `st_blocks` is always expressed in 512-byte units, regardless of the
+    // actual block size of the filesystem.
+    metadata.blocks() * 512
+}
+
+/// Returns the size of disk space allocated for the file.
+#[cfg(not(target_family = "unix"))]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_directory_size::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+            max_depth: proto.max_depth(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_directory_size::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_apparent_size(self.totals.apparent_size);
+        proto.set_allocated_size(self.totals.allocated_size);
+        proto.set_file_count(self.totals.file_count);
+        proto.set_dir_count(self.totals.dir_count);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Creates a tree with known sizes under the given directory.
+    ///
+    /// The tree looks as follows:
+    ///
+    /// ```text
+    /// root/
+    /// ├── a (1 byte)
+    /// ├── foo/
+    /// │   ├── b (10 bytes)
+    /// │   └── bar/
+    /// │       └── c (100 bytes)
+    /// └── baz/
+    ///     └── d (1000 bytes)
+    /// ```
+    fn create_tree(root: &Path) {
+        std::fs::create_dir_all(root.join("foo").join("bar")).unwrap();
+        std::fs::create_dir_all(root.join("baz")).unwrap();
+
+        std::fs::write(root.join("a"), [0; 1]).unwrap();
+        std::fs::write(root.join("foo").join("b"), [0; 10]).unwrap();
+        std::fs::write(root.join("foo").join("bar").join("c"), [0; 100]).unwrap();
+        std::fs::write(root.join("baz").join("d"), [0; 1000]).unwrap();
+    }
+
+    fn totals(session: &crate::session::FakeSession) -> Vec<(PathBuf, Totals)> {
+        session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.totals))
+            .collect()
+    }
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            max_depth: 0,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_max_depth_0() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        create_tree(tempdir.path());
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            max_depth: 0,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let totals = totals(&session);
+        assert_eq!(totals.len(), 1);
+
+        let (path, totals) = &totals[0];
+        assert_eq!(path, tempdir.path());
+        assert_eq!(totals.apparent_size, 1111);
+        assert_eq!(totals.file_count, 4);
+        assert_eq!(totals.dir_count, 3);
+    }
+
+    #[test]
+    fn handle_max_depth_1() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        create_tree(tempdir.path());
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            max_depth: 1,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let totals = totals(&session)
+            .into_iter()
+            .map(|(path, totals)| (path, (totals.apparent_size, totals.file_count, totals.dir_count)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(totals, vec![
+            (tempdir.path().to_path_buf(), (1111, 4, 3)),
+            (tempdir.path().join("baz"), (1000, 1, 0)),
+            (tempdir.path().join("foo"), (110, 2, 1)),
+        ]);
+    }
+
+    #[test]
+    fn handle_max_depth_2() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        create_tree(tempdir.path());
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            max_depth: 2,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let totals = totals(&session)
+            .into_iter()
+            .map(|(path, totals)| (path, totals.apparent_size))
+            .collect::<Vec<_>>();
+
+        assert_eq!(totals, vec![
+            (tempdir.path().to_path_buf(), 1111),
+            (tempdir.path().join("baz"), 1000),
+            (tempdir.path().join("foo"), 110),
+            (tempdir.path().join("foo").join("bar"), 100),
+        ]);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_hardlinks_counted_once() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), [0; 42])
+            .unwrap();
+        std::fs::hard_link(tempdir.path().join("foo"), tempdir.path().join("bar"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            max_depth: 0,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let totals = totals(&session);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].1.apparent_size, 42);
+        assert_eq!(totals[0].1.file_count, 1);
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of the DNS resolver configuration of the system.
+//!
+//! Tampering with resolvers is a common way of redirecting traffic of a host,
+//! so knowing which name servers it uses is often useful during investigations.
+
+/// Result of the `get_dns_config` action.
+struct Item {
+    /// DNS resolver configuration of the system.
+    config: ospect::net::DnsConfig,
+    /// Source of the configuration (if known).
+    source: Option<Source>,
+}
+
+/// Source of the DNS resolver configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    /// Configuration is maintained directly in the `/etc/resolv.conf` file.
+    ResolvConf,
+    /// Configuration is managed by `systemd-resolved`.
+    SystemdResolved,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetDnsConfig;
+
+/// Handles invocations of the `get_dns_config` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let config = ospect::net::dns_config()
+        .map_err(crate::session::Error::action)?;
+
+    session.reply(Item {
+        config,
+        source: source(),
+    })?;
+
+    Ok(())
+}
+
+/// Determines the source of the DNS resolver configuration.
+#[cfg(target_os = "linux")]
+fn source() -> Option<Source> {
+    // `systemd-resolved` manages the configuration by making `/etc/resolv.conf`
+    // a symlink to one of the files it maintains in its runtime directory [1].
+    //
+    // [1]: https://www.freedesktop.org/software/systemd/man/latest/systemd-resolved.service.html#/etc/resolv.conf
+    match std::fs::canonicalize("/etc/resolv.conf") {
+        Ok(path) if path.starts_with("/run/systemd/resolve") => {
+            Some(Source::SystemdResolved)
+        }
+        Ok(_) => Some(Source::ResolvConf),
+        Err(error) => {
+            // The file might legitimately not exist (or be a dangling symlink)
+            // in which case there is no source to speak of.
+            if error.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("failed to resolve '/etc/resolv.conf': {error}");
+            }
+            None
+        }
+    }
+}
+
+/// Determines the source of the DNS resolver configuration.
+#[cfg(not(target_os = "linux"))]
+fn source() -> Option<Source> {
+    None
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_dns_config::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::get_dns_config::Source as ProtoSource;
+
+        let mut proto = Self::Proto::default();
+        proto.set_servers(self.config.servers.into_iter().map(Into::into).collect());
+        proto.set_search_domains(self.config.search_domains);
+        proto.set_source(match self.source {
+            Some(Source::ResolvConf) => ProtoSource::RESOLV_CONF,
+            Some(Source::SystemdResolved) => ProtoSource::SYSTEMD_RESOLVED,
+            None => ProtoSource::UNKNOWN,
+        });
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_some_reply() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_source_consistent_with_resolv_conf() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let item = session.reply::<Item>(0);
+        let exists = std::path::Path::new("/etc/resolv.conf").exists();
+        assert_eq!(item.source.is_some(), exists);
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of environment variables of processes.
+//!
+//! The environment a service was launched with is often useful to understand
+//! how it behaves (e.g. injected libraries through `LD_PRELOAD`). Because it is
+//! also a common place for secrets, values can be redacted.
+
+use std::ffi::OsString;
+
+/// Arguments of the `get_environment` action.
+pub struct Args {
+    /// Identifiers of processes to get the environment of (agent if empty).
+    pids: Vec<u32>,
+    /// Patterns of names of variables with values to redact.
+    redact_patterns: Vec<regex::Regex>,
+}
+
+/// Result of the `get_environment` action.
+struct Item {
+    /// Identifier of the process the environment belongs to.
+    pid: u32,
+    /// Environment variables of the process or the error that prevented it.
+    vars: std::io::Result<Vec<Var>>,
+}
+
+/// A single environment variable.
+struct Var {
+    /// Name of the variable.
+    name: OsString,
+    /// Value of the variable (`None` if redacted).
+    value: Option<OsString>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetEnvironment;
+
+/// Handles invocations of the `get_environment` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let redact = |(name, value): (OsString, OsString)| {
+        let is_redacted = args.redact_patterns.iter()
+            .any(|pattern| pattern.is_match(&name.to_string_lossy()));
+
+        Var {
+            name,
+            value: if is_redacted { None } else { Some(value) },
+        }
+    };
+
+    if args.pids.is_empty() {
+        session.reply(Item {
+            pid: std::process::id(),
+            vars: Ok(std::env::vars_os().map(redact).collect()),
+        })?;
+
+        return Ok(());
+    }
+
+    for pid in args.pids {
+        let vars = environ(pid);
+        if let Err(error) = &vars {
+            log::warn!("failed to get environment of process {pid}: {error}");
+        }
+
+        session.reply(Item {
+            pid,
+            vars: vars.map(|vars| vars.into_iter().map(redact).collect()),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the environment variables of the process with the specified pid.
+#[cfg(target_os = "linux")]
+fn environ(pid: u32) -> std::io::Result<Vec<(OsString, OsString)>> {
+    match ospect::proc::linux::environ(pid)? {
+        Some(vars) => Ok(vars),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "process has exited",
+        )),
+    }
+}
+
+/// Returns the environment variables of the process with the specified pid.
+#[cfg(not(target_os = "linux"))]
+fn environ(_: u32) -> std::io::Result<Vec<(OsString, OsString)>> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Converts a redaction pattern with wildcards to a regex matching it.
+fn pattern_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    let mut regex = String::from("(?i)^");
+    for char in pattern.chars() {
+        match char {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&char.to_string())),
+        }
+    }
+    regex.push('$');
+
+    regex::Regex::new(&regex)
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_environment::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let redact_patterns = proto.take_redact_patterns().iter()
+            .map(|pattern| pattern_regex(pattern))
+            .collect::<Result<_, _>>()
+            .map_err(|error| ParseArgsError::invalid_field("redact_patterns", error))?;
+
+        Ok(Args {
+            pids: proto.take_pids(),
+            redact_patterns,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_environment::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_pid(self.pid);
+        match self.vars {
+            Ok(vars) => {
+                proto.set_variables(vars.into_iter().map(|var| {
+                    let mut proto = rrg_proto::get_environment::Variable::default();
+                    proto.set_name(var.name.to_string_lossy().into_owned());
+                    match var.value {
+                        Some(value) => proto.set_value(value.to_string_lossy().into_owned()),
+                        None => proto.set_redacted(true),
+                    }
+
+                    proto
+                }).collect());
+            }
+            Err(error) => proto.set_error(error.to_string()),
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_agent() {
+        let args = Args {
+            pids: Vec::new(),
+            redact_patterns: Vec::new(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.pid, std::process::id());
+
+        let vars = item.vars.as_ref().unwrap();
+        assert_eq!(vars.len(), std::env::vars_os().count());
+        assert!(vars.iter().all(|var| var.value.is_some()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_process_redacted() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .env_clear()
+            .env("RRG_TEST_FOO", "bar")
+            .env("RRG_TEST_API_TOKEN", "secret")
+            .spawn()
+            .unwrap();
+
+        let args = Args {
+            pids: vec![child.id()],
+            redact_patterns: vec![pattern_regex("*token*").unwrap()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        let result = handle(&mut session, args);
+
+        child.kill()
+            .unwrap();
+        child.wait()
+            .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        let vars = item.vars.as_ref().unwrap();
+
+        let foo = vars.iter().find(|var| var.name == "RRG_TEST_FOO").unwrap();
+        assert_eq!(foo.value.as_deref(), Some(std::ffi::OsStr::new("bar")));
+
+        let token = vars.iter().find(|var| var.name == "RRG_TEST_API_TOKEN").unwrap();
+        assert_eq!(token.value, None);
+    }
+
+    #[test]
+    fn handle_unreadable_process() {
+        let args = Args {
+            // There is no process with such a large pid.
+            pids: vec![u32::MAX, std::process::id()],
+            redact_patterns: Vec::new(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+        assert!(session.reply::<Item>(0).vars.is_err());
+        #[cfg(target_os = "linux")]
+        assert!(session.reply::<Item>(1).vars.is_ok());
+    }
+
+    #[test]
+    fn pattern_regex_wildcards() {
+        let regex = pattern_regex("*PASS?ORD*").unwrap();
+        assert!(regex.is_match("DB_PASSWORD"));
+        assert!(regex.is_match("password"));
+        assert!(!regex.is_match("PASSPORT"));
+
+        let regex = pattern_regex("a.b").unwrap();
+        assert!(regex.is_match("A.B"));
+        assert!(!regex.is_match("axb"));
+    }
+}
@@ -0,0 +1,710 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of records of the Windows event log.
+//!
+//! Records are queried through the [Windows Event Log API] (`EvtQuery` and
+//! `EvtNext`) with an XPath filter built from the action arguments and then
+//! rendered with `EvtRender` (system properties) and `EvtFormatMessage` (the
+//! message of the event as defined by its provider).
+//!
+//! [Windows Event Log API]: https://learn.microsoft.com/en-us/windows/win32/wes/windows-event-log-reference
+
+/// Default limit on the number of returned records.
+#[cfg(target_family = "windows")]
+const DEFAULT_MAX_RECORDS: u64 = 1000;
+
+/// Channels to read the records of if none are specified.
+#[cfg(target_family = "windows")]
+const DEFAULT_CHANNELS: [&str; 3] = ["System", "Security", "Application"];
+
+/// Number of event handles that we request from the system at once.
+#[cfg(target_family = "windows")]
+const BATCH_SIZE: usize = 64;
+
+/// Arguments of the `get_event_log_records` action.
+#[cfg(target_family = "windows")]
+pub struct Args {
+    /// Channels to read the records of.
+    channels: Vec<String>,
+    /// Time before which records are not returned (if any).
+    start_time: Option<std::time::SystemTime>,
+    /// Time after which records are not returned (if any).
+    end_time: Option<std::time::SystemTime>,
+    /// Identifiers of events to return the records of (all if empty).
+    event_ids: Vec<u32>,
+    /// Maximum number of records to return.
+    max_records: u64,
+}
+
+/// Result of the `get_event_log_records` action.
+#[cfg(target_family = "windows")]
+struct Item {
+    /// Channel the record was read from.
+    channel: String,
+    /// Record read from the channel or error if the channel failed.
+    record: std::io::Result<Record>,
+}
+
+/// A single rendered record of the event log.
+#[cfg(target_family = "windows")]
+#[derive(Debug)]
+struct Record {
+    /// Name of the provider that logged the event.
+    provider: String,
+    /// Identifier of the event.
+    event_id: u32,
+    /// Level (severity) of the event.
+    level: u32,
+    /// Time at which the event was logged (if known).
+    time: Option<std::time::SystemTime>,
+    /// Identifier of the record within the channel.
+    record_id: u64,
+    /// Message of the event (if it could be rendered).
+    message: Option<String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetEventLogRecords;
+
+/// Handles invocations of the `get_event_log_records` action.
+#[cfg(target_family = "unix")]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `get_event_log_records` action.
+#[cfg(target_family = "windows")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let channels = if args.channels.is_empty() {
+        DEFAULT_CHANNELS.iter().map(|channel| String::from(*channel)).collect()
+    } else {
+        args.channels.clone()
+    };
+
+    let query = args.query();
+    log::info!("querying event log channels {channels:?} with '{query}'");
+
+    let context = EvtHandle::system_render_context()
+        .map_err(crate::session::Error::action)?;
+
+    // Metadata of publishers is needed to render messages. Opening it is not
+    // cheap and most of the records come from a handful of providers, so we
+    // keep it around (including failures, so we do not retry in vain).
+    let mut publishers = std::collections::HashMap::new();
+
+    let mut records_left = args.max_records;
+
+    for channel in channels {
+        if records_left == 0 {
+            break;
+        }
+
+        let events = match Events::query(&channel, &query) {
+            Ok(events) => events,
+            Err(error) => {
+                log::warn!("failed to open event log channel '{channel}': {error}");
+                session.reply(Item {
+                    channel,
+                    record: Err(error),
+                })?;
+                continue;
+            }
+        };
+
+        for event in events {
+            if records_left == 0 {
+                break;
+            }
+
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    log::warn!("failed to read event log channel '{channel}': {error}");
+                    session.reply(Item {
+                        channel: channel.clone(),
+                        record: Err(error),
+                    })?;
+                    break;
+                }
+            };
+
+            let mut record = match event.render_record(&context) {
+                Ok(record) => record,
+                Err(error) => {
+                    log::warn!("failed to render event from channel '{channel}': {error}");
+                    continue;
+                }
+            };
+
+            let publisher = publishers.entry(record.provider.clone())
+                .or_insert_with(|| match EvtHandle::publisher_metadata(&record.provider) {
+                    Ok(publisher) => Some(publisher),
+                    Err(error) => {
+                        log::warn! {
+                            "failed to open metadata of event provider '{}': {error}",
+                            record.provider,
+                        };
+                        None
+                    }
+                });
+
+            if let Some(publisher) = publisher {
+                match event.format_message(publisher) {
+                    Ok(message) => record.message = Some(message),
+                    Err(error) => log::debug! {
+                        "failed to format message of event {} from '{}': {error}",
+                        record.record_id,
+                        record.provider,
+                    },
+                }
+            }
+
+            session.reply(Item {
+                channel: channel.clone(),
+                record: Ok(record),
+            })?;
+
+            records_left -= 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_family = "windows")]
+impl Args {
+
+    /// Builds an XPath query selecting events matching the arguments.
+    fn query(&self) -> String {
+        let mut conditions = Vec::new();
+
+        if !self.event_ids.is_empty() {
+            let event_ids = self.event_ids.iter()
+                .map(|event_id| format!("EventID={event_id}"))
+                .collect::<Vec<_>>();
+            conditions.push(format!("({})", event_ids.join(" or ")));
+        }
+
+        let mut time_conditions = Vec::new();
+        if let Some(start_time) = self.start_time {
+            let start_time = humantime::format_rfc3339_millis(start_time);
+            time_conditions.push(format!("@SystemTime>='{start_time}'"));
+        }
+        if let Some(end_time) = self.end_time {
+            let end_time = humantime::format_rfc3339_millis(end_time);
+            time_conditions.push(format!("@SystemTime<='{end_time}'"));
+        }
+        if !time_conditions.is_empty() {
+            conditions.push(format!("TimeCreated[{}]", time_conditions.join(" and ")));
+        }
+
+        if conditions.is_empty() {
+            String::from("*")
+        } else {
+            format!("*[System[{}]]", conditions.join(" and "))
+        }
+    }
+}
+
+/// Owned handle to an object of the Windows Event Log API.
+#[cfg(target_family = "windows")]
+struct EvtHandle(windows_sys::Win32::System::EventLog::EVT_HANDLE);
+
+#[cfg(target_family = "windows")]
+impl EvtHandle {
+
+    /// Wraps the given raw handle, failing with the last error if it is null.
+    fn from_raw(handle: windows_sys::Win32::System::EventLog::EVT_HANDLE) -> std::io::Result<EvtHandle> {
+        if handle == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(EvtHandle(handle))
+        }
+    }
+
+    /// Creates a context for rendering system properties of events.
+    fn system_render_context() -> std::io::Result<EvtHandle> {
+        use windows_sys::Win32::System::EventLog::*;
+
+        // SAFETY: We pass no value paths (as required for system contexts).
+        let handle = unsafe {
+            EvtCreateRenderContext(0, std::ptr::null(), EvtRenderContextSystem as u32)
+        };
+
+        EvtHandle::from_raw(handle)
+    }
+
+    /// Opens metadata of the given event provider on the local machine.
+    fn publisher_metadata(provider: &str) -> std::io::Result<EvtHandle> {
+        let provider = wide(provider);
+
+        // SAFETY: The provider name is a null-terminated wide string that
+        // outlives the call. Null log file path and zero locale and flags are
+        // valid values for the remaining arguments.
+        let handle = unsafe {
+            windows_sys::Win32::System::EventLog::EvtOpenPublisherMetadata(
+                0,
+                provider.as_ptr(),
+                std::ptr::null(),
+                0,
+                0,
+            )
+        };
+
+        EvtHandle::from_raw(handle)
+    }
+
+    /// Renders system properties of the event into a record.
+    fn render_record(&self, context: &EvtHandle) -> std::io::Result<Record> {
+        use windows_sys::Win32::System::EventLog::*;
+
+        // Rendered values refer to strings stored past the array of values in
+        // the same buffer, so the buffer is sized in bytes as reported by the
+        // system but allocated as values to keep it properly aligned.
+        let mut buf = Vec::<EVT_VARIANT>::new();
+        let count = loop {
+            let mut buf_used = 0;
+            let mut count = 0;
+
+            // SAFETY: The buffer is valid (and writable) for the given size.
+            let status = unsafe {
+                EvtRender(
+                    context.0,
+                    self.0,
+                    EvtRenderEventValues as u32,
+                    (buf.len() * std::mem::size_of::<EVT_VARIANT>()) as u32,
+                    buf.as_mut_ptr().cast(),
+                    &mut buf_used,
+                    &mut count,
+                )
+            };
+            if status != 0 {
+                break count as usize;
+            }
+
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(error);
+            }
+
+            let len = (buf_used as usize).div_ceil(std::mem::size_of::<EVT_VARIANT>());
+            // SAFETY: Variants are plain data, all-zero bytes are a valid
+            // (null-typed) variant.
+            buf = vec![unsafe { std::mem::zeroed() }; len];
+        };
+
+        let values = &buf[..count.min(buf.len())];
+        let value = |id: EVT_SYSTEM_PROPERTY_ID, var_type: EVT_VARIANT_TYPE| {
+            values.get(id as usize)
+                .filter(|value| value.Type == var_type as u32)
+        };
+
+        let provider = match value(EvtSystemProviderName, EvtVarTypeString) {
+            // SAFETY: Values of the string type hold a pointer to a valid
+            // null-terminated wide string (within the buffer).
+            Some(value) => unsafe { from_wide_ptr(value.Anonymous.StringVal) },
+            None => String::new(),
+        };
+        // SAFETY: We access only the union fields matching the value types.
+        let event_id = value(EvtSystemEventID, EvtVarTypeUInt16)
+            .map(|value| unsafe { value.Anonymous.UInt16Val })
+            .unwrap_or(0);
+        let level = value(EvtSystemLevel, EvtVarTypeByte)
+            .map(|value| unsafe { value.Anonymous.ByteVal })
+            .unwrap_or(0);
+        let time = value(EvtSystemTimeCreated, EvtVarTypeFileTime)
+            .map(|value| unsafe { value.Anonymous.FileTimeVal })
+            .map(from_file_time);
+        let record_id = value(EvtSystemEventRecordId, EvtVarTypeUInt64)
+            .map(|value| unsafe { value.Anonymous.UInt64Val })
+            .unwrap_or(0);
+
+        Ok(Record {
+            provider,
+            event_id: u32::from(event_id),
+            level: u32::from(level),
+            time,
+            record_id,
+            message: None,
+        })
+    }
+
+    /// Formats the message of the event using the given publisher metadata.
+    fn format_message(&self, publisher: &EvtHandle) -> std::io::Result<String> {
+        use windows_sys::Win32::System::EventLog::*;
+
+        let mut buf = Vec::<u16>::new();
+        loop {
+            let mut buf_used = 0;
+
+            // SAFETY: The buffer is valid (and writable) for the given number
+            // of wide characters. We pass no custom insertion values.
+            let status = unsafe {
+                EvtFormatMessage(
+                    publisher.0,
+                    self.0,
+                    0,
+                    0,
+                    std::ptr::null(),
+                    EvtFormatMessageEvent as u32,
+                    buf.len() as u32,
+                    buf.as_mut_ptr(),
+                    &mut buf_used,
+                )
+            };
+            if status != 0 {
+                break;
+            }
+
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(error);
+            }
+
+            buf = vec![0; buf_used as usize];
+        }
+
+        let len = buf.iter().position(|char| *char == 0).unwrap_or(buf.len());
+        Ok(String::from_utf16_lossy(&buf[..len]).trim_end().to_string())
+    }
+}
+
+#[cfg(target_family = "windows")]
+impl Drop for EvtHandle {
+
+    fn drop(&mut self) {
+        // SAFETY: The handle is valid and owned by us, so we can close it.
+        let status = unsafe {
+            windows_sys::Win32::System::EventLog::EvtClose(self.0)
+        };
+        if status == 0 {
+            let error = std::io::Error::last_os_error();
+            log::error!("failed to close event log handle: {error}");
+        }
+    }
+}
+
+/// Iterator over events of a single channel matching a query.
+#[cfg(target_family = "windows")]
+struct Events {
+    /// Handle to the query result set.
+    query: EvtHandle,
+    /// Handles of events already fetched from the result set (in reverse).
+    batch: Vec<EvtHandle>,
+    /// Whether the result set has been exhausted (or failed).
+    done: bool,
+}
+
+#[cfg(target_family = "windows")]
+impl Events {
+
+    /// Queries events of the given channel, starting with the most recent.
+    fn query(channel: &str, query: &str) -> std::io::Result<Events> {
+        use windows_sys::Win32::System::EventLog::*;
+
+        let channel_wide = wide(channel);
+        let query_wide = wide(query);
+
+        // SAFETY: Both strings are null-terminated and outlive the call.
+        let handle = unsafe {
+            EvtQuery(
+                0,
+                channel_wide.as_ptr(),
+                query_wide.as_ptr(),
+                (EvtQueryChannelPath | EvtQueryReverseDirection) as u32,
+            )
+        };
+
+        let query = EvtHandle::from_raw(handle)
+            .map_err(|error| std::io::Error::new(error.kind(), format! {
+                "failed to query channel '{channel}': {error}"
+            }))?;
+
+        Ok(Events {
+            query,
+            batch: Vec::new(),
+            done: false,
+        })
+    }
+}
+
+#[cfg(target_family = "windows")]
+impl Iterator for Events {
+
+    type Item = std::io::Result<EvtHandle>;
+
+    fn next(&mut self) -> Option<std::io::Result<EvtHandle>> {
+        if let Some(event) = self.batch.pop() {
+            return Some(Ok(event));
+        }
+        if self.done {
+            return None;
+        }
+
+        let mut handles = [0; BATCH_SIZE];
+        let mut returned = 0;
+
+        // SAFETY: The handle array is valid for the given number of handles.
+        let status = unsafe {
+            windows_sys::Win32::System::EventLog::EvtNext(
+                self.query.0,
+                BATCH_SIZE as u32,
+                handles.as_mut_ptr(),
+                u32::MAX, // Infinite timeout.
+                0,
+                &mut returned,
+            )
+        };
+        if status == 0 {
+            self.done = true;
+
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_NO_MORE_ITEMS) {
+                return None;
+            }
+            return Some(Err(error));
+        }
+
+        let returned = (returned as usize).min(BATCH_SIZE);
+        self.batch.extend(handles[..returned].iter().rev().map(|handle| EvtHandle(*handle)));
+
+        self.batch.pop().map(Ok)
+    }
+}
+
+/// Error code indicating that the given buffer is too small.
+#[cfg(target_family = "windows")]
+const ERROR_INSUFFICIENT_BUFFER: i32 =
+    windows_sys::Win32::Foundation::ERROR_INSUFFICIENT_BUFFER as i32;
+
+/// Error code indicating that there are no more events in the result set.
+#[cfg(target_family = "windows")]
+const ERROR_NO_MORE_ITEMS: i32 =
+    windows_sys::Win32::Foundation::ERROR_NO_MORE_ITEMS as i32;
+
+/// Converts the given string into a null-terminated wide string.
+#[cfg(target_family = "windows")]
+fn wide(string: &str) -> Vec<u16> {
+    string.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Converts the null-terminated wide string at the given pointer to a string.
+///
+/// # Safety
+///
+/// The pointer must point to a valid null-terminated wide string.
+#[cfg(target_family = "windows")]
+unsafe fn from_wide_ptr(ptr: *const u16) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    let mut len = 0;
+    // SAFETY: The string is null-terminated, so we never read past its end.
+    while unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+
+    // SAFETY: We just verified that the string has `len` characters.
+    String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+/// Converts the given `FILETIME` value (in 100-nanosecond intervals since
+/// January 1, 1601) to system time.
+#[cfg(target_family = "windows")]
+fn from_file_time(file_time: u64) -> std::time::SystemTime {
+    /// Number of 100-nanosecond intervals between 1601 and 1970.
+    const UNIX_EPOCH_FILE_TIME: u64 = 116_444_736_000_000_000;
+
+    if file_time >= UNIX_EPOCH_FILE_TIME {
+        let intervals = file_time - UNIX_EPOCH_FILE_TIME;
+        std::time::UNIX_EPOCH + std::time::Duration::from_nanos(intervals) * 100
+    } else {
+        let intervals = UNIX_EPOCH_FILE_TIME - file_time;
+        std::time::UNIX_EPOCH - std::time::Duration::from_nanos(intervals) * 100
+    }
+}
+
+/// Parses the given timestamp message (if present).
+#[cfg(target_family = "windows")]
+fn parse_time(
+    name: &'static str,
+    timestamp: Option<protobuf::well_known_types::timestamp::Timestamp>,
+) -> Result<Option<std::time::SystemTime>, crate::request::ParseArgsError> {
+    let Some(timestamp) = timestamp else {
+        return Ok(None);
+    };
+
+    let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+    since_epoch.seconds = timestamp.seconds;
+    since_epoch.nanos = timestamp.nanos;
+
+    let since_epoch = rrg_proto::try_from_duration(since_epoch)
+        .map_err(|error| crate::request::ParseArgsError::invalid_field(name, error))?;
+
+    Ok(Some(std::time::UNIX_EPOCH + since_epoch))
+}
+
+#[cfg(target_family = "windows")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_event_log_records::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            channels: proto.take_channels(),
+            start_time: parse_time("start_time", proto.start_time.take())?,
+            end_time: parse_time("end_time", proto.end_time.take())?,
+            event_ids: proto.take_event_ids(),
+            max_records: proto.max_records.unwrap_or(DEFAULT_MAX_RECORDS),
+        })
+    }
+}
+
+#[cfg(target_family = "windows")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_event_log_records::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_channel(self.channel);
+
+        match self.record {
+            Ok(record) => {
+                proto.set_provider(record.provider);
+                proto.set_event_id(record.event_id);
+                proto.set_level(record.level);
+                if let Some(time) = record.time {
+                    proto.set_time(rrg_proto::into_timestamp(time));
+                }
+                proto.set_record_id(record.record_id);
+                if let Some(message) = record.message {
+                    proto.set_message(message);
+                }
+            }
+            Err(error) => {
+                proto.set_error(error.to_string());
+            }
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_family = "windows")]
+mod tests {
+
+    use super::*;
+
+    fn args() -> Args {
+        Args {
+            channels: vec![],
+            start_time: None,
+            end_time: None,
+            event_ids: vec![],
+            max_records: DEFAULT_MAX_RECORDS,
+        }
+    }
+
+    #[test]
+    fn query_all() {
+        assert_eq!(args().query(), "*");
+    }
+
+    #[test]
+    fn query_event_ids_and_time() {
+        let args = Args {
+            event_ids: vec![4624, 4625],
+            start_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)),
+            end_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_003_600)),
+            ..args()
+        };
+
+        assert_eq! {
+            args.query(),
+            "*[System[(EventID=4624 or EventID=4625) and \
+             TimeCreated[@SystemTime>='2023-11-14T22:13:20.000Z' and \
+             @SystemTime<='2023-11-14T23:13:20.000Z']]]"
+        };
+    }
+
+    #[test]
+    fn from_file_time_unix_epoch() {
+        assert_eq!(from_file_time(116_444_736_000_000_000), std::time::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn handle_system_time_range() {
+        let end_time = std::time::SystemTime::now();
+        let start_time = end_time - std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+        let args = Args {
+            channels: vec![String::from("System")],
+            start_time: Some(start_time),
+            end_time: Some(end_time),
+            max_records: 64,
+            ..args()
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.reply_count() <= 64);
+        for item in session.replies::<Item>() {
+            assert_eq!(item.channel, "System");
+
+            let record = item.record.as_ref().unwrap();
+            assert!(!record.provider.is_empty());
+
+            // Event log times have a millisecond precision in queries, so we
+            // allow for a bit of slack at the boundaries.
+            let slack = std::time::Duration::from_millis(1);
+            let time = record.time.unwrap();
+            assert!(time + slack >= start_time);
+            assert!(time <= end_time + slack);
+        }
+    }
+
+    #[test]
+    fn handle_max_records() {
+        let args = Args {
+            channels: vec![String::from("System")],
+            max_records: 1,
+            ..args()
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.reply_count() <= 1);
+    }
+
+    #[test]
+    fn handle_unknown_channel() {
+        let args = Args {
+            channels: vec![String::from("RrgNonExistentChannel")],
+            ..args()
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.channel, "RrgNonExistentChannel");
+        assert!(item.record.is_err());
+    }
+}
@@ -7,6 +7,12 @@ use std::path::PathBuf;
 /// Limit on the size of individual file part blob sent to the blob sink.
 const MAX_BLOB_LEN: usize = 2 * 1024 * 1024; // 2 MiB.
 
+/// Default minimum length of file parts when using content-defined chunking.
+const DEFAULT_CHUNKING_MIN_LEN: usize = 256 * 1024; // 256 KiB.
+
+/// Default average length of file parts when using content-defined chunking.
+const DEFAULT_CHUNKING_AVG_LEN: usize = 1024 * 1024; // 1 MiB.
+
 /// Arguments of the `get_file_contents` action.
 pub struct Args {
     /// Path to the file to get the contents of.
@@ -15,6 +21,10 @@ pub struct Args {
     offset: u64,
     /// Number of bytes to read from the file.
     len: usize,
+    /// Configuration of content-defined chunking (if it should be used).
+    chunking: Option<crate::io::ChunkingConfig>,
+    /// Whether compressed files should be decompressed before sending.
+    decompress: bool,
 }
 
 /// Result of the `get_file_contents` action.
@@ -25,27 +35,115 @@ pub struct Item {
     len: usize,
     /// SHA-256 digest of the file part sent to the blob sink.
     blob_sha256: [u8; 32],
+    /// Whether the file part comes from the decompressed file contents.
+    decompressed: bool,
+}
+
+/// Compression formats that can be recognized by their magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    /// The gzip format (RFC 1952).
+    Gzip,
+    /// The Zstandard format (RFC 8878).
+    Zstd,
+    /// The XZ format.
+    Xz,
+}
+
+impl Compression {
+
+    /// Recognizes the compression format from the given file header.
+    fn from_magic(header: &[u8]) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFileContents;
+
 /// Handle invocations of the `get_file_contents` action.
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
 where
     S: crate::session::Session,
 {
     use std::io::{Read as _, Seek as _};
-    use sha2::Digest as _;
 
-    let mut file = std::fs::File::open(args.path)
+    let mut file = crate::fs::open_file(&args.path)
         .map_err(crate::session::Error::action)?;
 
+    let compression = if args.decompress {
+        compression(&mut file)
+            .map_err(crate::session::Error::action)?
+    } else {
+        None
+    };
+
+    let decompressed = match compression {
+        Some(Compression::Gzip) => true,
+        Some(compression) => {
+            log::warn! {
+                "unsupported compression ({compression:?}) of '{}', sending raw contents",
+                args.path.display(),
+            };
+            false
+        }
+        None => false,
+    };
+
     let mut offset = args.offset;
     let mut len_left = args.len;
 
-    file.seek(std::io::SeekFrom::Start(offset))
-        .map_err(crate::session::Error::action)?;
+    let mut file: Box<dyn std::io::Read> = if decompressed {
+        // Log files are often rotated by appending gzip members to existing
+        // archives, so we want to decode all of them and not just the first.
+        let mut file = flate2::read::MultiGzDecoder::new(std::io::BufReader::new(file));
+
+        // Decompressed contents cannot be seeked, so we have to decode all the
+        // bytes up to the offset and discard them.
+        std::io::copy(&mut (&mut file).take(offset), &mut std::io::sink())
+            .map_err(crate::session::Error::action)?;
+
+        Box::new(file)
+    } else {
+        file.seek(std::io::SeekFrom::Start(offset))
+            .map_err(crate::session::Error::action)?;
+
+        Box::new(file)
+    };
+
+    if let Some(config) = args.chunking {
+        let file = file.take(len_left as u64);
+
+        for chunk in crate::io::ContentChunks::new(file, config) {
+            session.check_deadline()?;
+
+            let chunk = chunk
+                .map_err(crate::session::Error::action)?;
+
+            let len = chunk.len();
+            session.record_bytes_read(len as u64);
+            session.throttle(len as u64)?;
+            send_part(session, offset, chunk, decompressed)?;
+
+            offset += len as u64;
+        }
+
+        return Ok(());
+    }
 
     loop {
+        session.check_deadline()?;
+
         let mut buf = vec![0; std::cmp::min(len_left, MAX_BLOB_LEN)];
+        session.throttle(buf.len() as u64)?;
 
         let len_read = file.read(&mut buf[..])
             .map_err(crate::session::Error::action)?;
@@ -54,17 +152,10 @@ where
             break;
         }
 
-        buf.truncate(len_read);
-
-        let blob = crate::blob::Blob::from(buf);
-        let blob_sha256 = sha2::Sha256::digest(blob.as_bytes()).into();
+        session.record_bytes_read(len_read as u64);
 
-        session.send(crate::Sink::Blob, blob)?;
-        session.reply(Item {
-            offset,
-            len: len_read,
-            blob_sha256,
-        })?;
+        buf.truncate(len_read);
+        send_part(session, offset, buf, decompressed)?;
 
         offset += len_read as u64;
         len_left -= len_read;
@@ -73,6 +164,47 @@ where
     Ok(())
 }
 
+/// Recognizes the compression format of the given file.
+///
+/// The file is rewound to the beginning afterwards.
+fn compression(file: &mut std::fs::File) -> std::io::Result<Option<Compression>> {
+    use std::io::{Read as _, Seek as _};
+
+    let mut header = Vec::with_capacity(6);
+    file.by_ref().take(6).read_to_end(&mut header)?;
+    file.rewind()?;
+
+    Ok(Compression::from_magic(&header))
+}
+
+/// Sends the file part starting at `offset` to the blob sink and replies.
+fn send_part<S>(
+    session: &mut S,
+    offset: u64,
+    buf: Vec<u8>,
+    decompressed: bool,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use sha2::Digest as _;
+
+    let len = buf.len();
+
+    let blob = crate::blob::Blob::from(buf);
+    let blob_sha256 = sha2::Sha256::digest(blob.as_bytes()).into();
+
+    session.send(crate::Sink::Blob, blob)?;
+    session.reply(Item {
+        offset,
+        len,
+        blob_sha256,
+        decompressed,
+    })?;
+
+    Ok(())
+}
+
 impl crate::request::Args for Args {
 
     type Proto = rrg_proto::get_file_contents::Args;
@@ -80,6 +212,33 @@ impl crate::request::Args for Args {
     fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
         use crate::request::ParseArgsError;
 
+        #[cfg(not(target_os = "linux"))]
+        if proto.has_process_fd() {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "process descriptors are supported only on Linux");
+            return Err(ParseArgsError::unsupported_field("process_fd", error));
+        }
+
+        // Descriptors are read through their links in `/proc`, so afterwards
+        // they are no different from regular paths.
+        #[cfg(target_os = "linux")]
+        if proto.has_process_fd() {
+            if proto.has_path() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "both path and process descriptor");
+                return Err(ParseArgsError::invalid_field("process_fd", error));
+            }
+
+            let process_fd = proto.take_process_fd();
+            let process_fd = crate::fs::linux::ProcessFd {
+                pid: process_fd.pid(),
+                fd: process_fd.fd(),
+            };
+            proto.set_path(process_fd.path().into());
+        }
+
+        if !proto.has_path() {
+            return Err(ParseArgsError::missing_field("path"));
+        }
+
         let path = PathBuf::try_from(proto.take_path())
             .map_err(|error| ParseArgsError::invalid_field("path", error))?;
 
@@ -93,10 +252,50 @@ impl crate::request::Args for Args {
             len => len as usize,
         };
 
+        let chunking = if proto.has_content_defined_chunking() {
+            let proto = proto.take_content_defined_chunking();
+
+            let max_len = match proto.max_length() {
+                0 => MAX_BLOB_LEN,
+                len => usize::try_from(len).unwrap_or(usize::MAX),
+            };
+            let avg_len = match proto.avg_length() {
+                0 => std::cmp::min(DEFAULT_CHUNKING_AVG_LEN, max_len),
+                len => usize::try_from(len).unwrap_or(usize::MAX),
+            };
+            let min_len = match proto.min_length() {
+                0 => std::cmp::min(DEFAULT_CHUNKING_MIN_LEN, avg_len),
+                len => usize::try_from(len).unwrap_or(usize::MAX),
+            };
+
+            let config = crate::io::ChunkingConfig {
+                min_len,
+                avg_len,
+                max_len,
+            };
+
+            let is_ordered = 0 < min_len &&
+                min_len <= avg_len &&
+                avg_len <= max_len &&
+                max_len <= MAX_BLOB_LEN;
+
+            if !is_ordered {
+                return Err(ParseArgsError::invalid_field("content_defined_chunking", ChunkingError {
+                    config,
+                }));
+            }
+
+            Some(config)
+        } else {
+            None
+        };
+
         Ok(Args {
             path,
             offset: proto.offset(),
             len,
+            chunking,
+            decompress: proto.decompress(),
         })
     }
 }
@@ -110,6 +309,7 @@ impl crate::response::Item for Item {
         proto.set_offset(self.offset);
         proto.set_length(self.len as u64);
         proto.set_blob_sha256(self.blob_sha256.into());
+        proto.set_decompressed(self.decompressed);
 
         proto
     }
@@ -135,6 +335,27 @@ impl std::fmt::Display for LenError {
 impl std::error::Error for LenError {
 }
 
+/// An error indicating that the action was invoked with invalid chunking.
+#[derive(Debug)]
+struct ChunkingError {
+    config: crate::io::ChunkingConfig,
+}
+
+impl std::fmt::Display for ChunkingError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "invalid chunk lengths (min: {}, avg: {}, max: {}, allowed: {})",
+            self.config.min_len, self.config.avg_len, self.config.max_len,
+            MAX_BLOB_LEN
+        }
+    }
+}
+
+impl std::error::Error for ChunkingError {
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -152,6 +373,8 @@ mod tests {
             path: tempdir.path().join("foo"),
             offset: 0,
             len: usize::MAX,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -162,6 +385,44 @@ mod tests {
         assert_eq!(session.parcel_count(crate::Sink::Blob), 0);
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_preserve_atime() {
+        // Other tests do not depend on the access time, so it is fine to leave
+        // it enabled for them.
+        crate::fs::set_preserve_atime(true);
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("foo");
+
+        std::fs::write(&path, b"0123456789")
+            .unwrap();
+
+        // With `relatime` (the usual default) access time is updated only if
+        // it is older than the modification time, so we move it way back.
+        let atime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        std::fs::File::options().write(true).open(&path)
+            .unwrap()
+            .set_times(std::fs::FileTimes::new().set_accessed(atime))
+            .unwrap();
+
+        let args = Args {
+            path: path.clone(),
+            offset: 0,
+            len: usize::MAX,
+            chunking: None,
+            decompress: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, args)
+            .unwrap();
+
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 1);
+        assert_eq!(std::fs::metadata(&path).unwrap().accessed().unwrap(), atime);
+    }
+
     #[test]
     fn handle_small_file_all() {
         let tempdir = tempfile::tempdir()
@@ -174,6 +435,8 @@ mod tests {
             path: tempdir.path().join("foo"),
             offset: 0,
             len: usize::MAX,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -192,6 +455,36 @@ mod tests {
         assert_eq!(blob.as_bytes(), b"0123456789");
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_deleted_process_fd() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"0123456789")
+            .unwrap();
+        let file = std::fs::File::open(tempdir.path().join("foo"))
+            .unwrap();
+        std::fs::remove_file(tempdir.path().join("foo"))
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_contents::Args::default();
+        proto.mut_process_fd().set_pid(std::process::id());
+        proto.mut_process_fd().set_fd(std::os::fd::AsRawFd::as_raw_fd(&file) as u32);
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, Args::from_proto(proto).unwrap())
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).len, 10);
+
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"0123456789");
+    }
+
     #[test]
     fn handle_small_file_from_offset() {
         let tempdir = tempfile::tempdir()
@@ -204,6 +497,8 @@ mod tests {
             path: tempdir.path().join("foo"),
             offset: 5,
             len: usize::MAX,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -234,6 +529,8 @@ mod tests {
             path: tempdir.path().join("foo"),
             offset: 0,
             len: 5,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -260,6 +557,8 @@ mod tests {
             path: PathBuf::from("/dev/zero"),
             offset: 0,
             len: MAX_BLOB_LEN * 2 + 1337,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -300,6 +599,8 @@ mod tests {
             path: PathBuf::from("/dev/zero"),
             offset: 0xb33f,
             len: MAX_BLOB_LEN + 1337,
+            chunking: None,
+            decompress: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -316,4 +617,176 @@ mod tests {
         assert_eq!(item.offset, 0xb33f + MAX_BLOB_LEN as u64);
         assert_eq!(item.len, 1337);
     }
+
+    #[test]
+    fn handle_content_defined_chunking() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let data = (0..256 * 1024u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+
+        std::fs::write(tempdir.path().join("foo"), &data)
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            offset: 0,
+            len: usize::MAX,
+            chunking: Some(crate::io::ChunkingConfig {
+                min_len: 1024,
+                avg_len: 4096,
+                max_len: 16384,
+            }),
+            decompress: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, args)
+            .unwrap();
+
+        assert!(session.reply_count() > 1);
+        assert_eq!(session.reply_count(), session.parcel_count(crate::Sink::Blob));
+
+        let mut offset = 0;
+        for item in session.replies::<Item>() {
+            assert_eq!(item.offset, offset);
+            offset += item.len as u64;
+        }
+        assert_eq!(offset, data.len() as u64);
+
+        let blobs = session.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+            .map(|blob| blob.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(blobs.concat(), data);
+    }
+
+    #[test]
+    fn handle_decompress_gzip() {
+        use std::io::Write as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"Oct 14 12:00:00 foo: bar\nOct 14 12:00:01 foo: baz\n")
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("syslog.1.gz"), encoder.finish().unwrap())
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("syslog.1.gz"),
+            offset: 0,
+            len: usize::MAX,
+            chunking: None,
+            decompress: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, args)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(item.decompressed);
+
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"Oct 14 12:00:00 foo: bar\nOct 14 12:00:01 foo: baz\n");
+    }
+
+    #[test]
+    fn handle_decompress_gzip_from_offset_to_len() {
+        use std::io::Write as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"0123456789")
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo.gz"), encoder.finish().unwrap())
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo.gz"),
+            offset: 3,
+            len: 4,
+            chunking: None,
+            decompress: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, args)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.offset, 3);
+        assert_eq!(item.len, 4);
+
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"3456");
+    }
+
+    #[test]
+    fn handle_decompress_not_compressed() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo.gz"), b"0123456789")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo.gz"),
+            offset: 0,
+            len: usize::MAX,
+            chunking: None,
+            decompress: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle(&mut session, args)
+            .unwrap();
+
+        let item = session.reply::<Item>(0);
+        assert!(!item.decompressed);
+
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"0123456789");
+    }
+
+    #[test]
+    fn handle_decompress_truncated() {
+        use std::io::Write as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[0x42; 1024])
+            .unwrap();
+
+        let mut data = encoder.finish()
+            .unwrap();
+        data.truncate(data.len() / 2);
+
+        std::fs::write(tempdir.path().join("foo.gz"), data)
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo.gz"),
+            offset: 0,
+            len: usize::MAX,
+            chunking: None,
+            decompress: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
 }
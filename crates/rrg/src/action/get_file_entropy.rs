@@ -0,0 +1,338 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Computation of Shannon entropy of file contents.
+//!
+//! High entropy is a typical trait of compressed or encrypted data, so it can
+//! be used to find files produced by packers or ransomware.
+
+use std::path::PathBuf;
+
+/// Size of the buffer used for reading the file.
+const BUF_LEN: usize = 64 * 1024; // 64 KiB.
+
+/// Arguments of the `get_file_entropy` action.
+pub struct Args {
+    /// Path to the file to compute the entropy of.
+    path: PathBuf,
+    /// Length of windows to compute the entropy of (if any).
+    window_len: Option<u64>,
+    /// Entropy above which results are flagged (if any).
+    threshold: Option<f64>,
+}
+
+/// Result of the `get_file_entropy` action.
+struct Item {
+    /// Path to the file the entropy was computed for.
+    path: PathBuf,
+    /// Byte offset of the window the entropy was computed for.
+    offset: u64,
+    /// Number of bytes the entropy was computed for.
+    len: u64,
+    /// Shannon entropy of the bytes (in bits per byte).
+    entropy: f64,
+    /// Whether the entropy exceeds the threshold.
+    above_threshold: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFileEntropy;
+
+/// Handles invocations of the `get_file_entropy` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::Read as _;
+
+    let mut file = crate::fs::open_file(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    let window_len = args.window_len.unwrap_or(u64::MAX);
+
+    let mut buf = vec![0; BUF_LEN];
+    let mut histogram = Histogram::new();
+    let mut offset = 0;
+
+    loop {
+        // We never read past the end of the current window, so that each read
+        // contributes to exactly one window.
+        let len_left = window_len - histogram.len;
+        let buf_len = std::cmp::min(len_left, buf.len() as u64) as usize;
+
+        let len_read = match file.read(&mut buf[..buf_len]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(crate::session::Error::action(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+
+        histogram.update(&buf[..len_read]);
+
+        if histogram.len == window_len {
+            let window = std::mem::replace(&mut histogram, Histogram::new());
+            let len = window.len;
+
+            reply(session, &args, offset, window)?;
+            offset += len;
+
+            session.check_deadline()?;
+        }
+    }
+
+    // The last (possibly incomplete) window is reported unless it is empty, but
+    // an empty file still yields a single result (when not using windows).
+    if histogram.len > 0 || (offset == 0 && args.window_len.is_none()) {
+        reply(session, &args, offset, histogram)?;
+    }
+
+    Ok(())
+}
+
+/// Sends the entropy of bytes described by `histogram` as a reply.
+fn reply<S>(
+    session: &mut S,
+    args: &Args,
+    offset: u64,
+    histogram: Histogram,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let entropy = histogram.entropy();
+    let above_threshold = match args.threshold {
+        Some(threshold) => entropy > threshold,
+        None => false,
+    };
+
+    session.reply(Item {
+        path: args.path.clone(),
+        offset,
+        len: histogram.len,
+        entropy,
+        above_threshold,
+    })
+}
+
+/// Counts of occurrences of individual byte values.
+struct Histogram {
+    /// Number of occurrences of each byte value.
+    counts: [u64; 256],
+    /// Total number of bytes.
+    len: u64,
+}
+
+impl Histogram {
+
+    /// Creates a new, empty, histogram.
+    fn new() -> Histogram {
+        Histogram {
+            counts: [0; 256],
+            len: 0,
+        }
+    }
+
+    /// Adds the given bytes to the histogram.
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.counts[usize::from(*byte)] += 1;
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    /// Computes the Shannon entropy of the bytes (in bits per byte).
+    fn entropy(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+
+        let len = self.len as f64;
+
+        let entropy = self.counts.iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / len;
+                -p * p.log2()
+            })
+            .sum::<f64>();
+
+        // Due to rounding errors, the sum can be slightly negative (e.g. for
+        // files consisting of a single byte value) which is not meaningful.
+        entropy.max(0.0)
+    }
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_file_entropy::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        let window_len = match proto.window_length() {
+            0 => None,
+            window_len => Some(window_len),
+        };
+
+        Ok(Args {
+            path,
+            window_len,
+            threshold: proto.threshold,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_file_entropy::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_offset(self.offset);
+        proto.set_length(self.len);
+        proto.set_entropy(self.entropy);
+        proto.set_above_threshold(self.above_threshold);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Returns pseudo-random bytes with virtually uniform distribution.
+    fn random_bytes(len: usize) -> Vec<u8> {
+        // We use simple xorshift generator to avoid extra dependencies: its
+        // quality is more than enough for this purpose.
+        let mut state = 0x2545F4914F6CDD1Du64;
+
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            window_len: None,
+            threshold: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_empty() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            window_len: None,
+            threshold: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.len, 0);
+        assert_eq!(item.entropy, 0.0);
+    }
+
+    #[test]
+    fn handle_zeros_and_random() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("zeros"), vec![0; 1024 * 1024])
+            .unwrap();
+        std::fs::write(tempdir.path().join("random"), random_bytes(1024 * 1024))
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        for name in ["zeros", "random"] {
+            let args = Args {
+                path: tempdir.path().join(name),
+                window_len: None,
+                threshold: Some(7.2),
+            };
+
+            assert!(handle(&mut session, args).is_ok());
+        }
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.len, 1024 * 1024);
+        assert_eq!(item.entropy, 0.0);
+        assert!(!item.above_threshold);
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.len, 1024 * 1024);
+        assert!(item.entropy > 7.99);
+        assert!(item.above_threshold);
+    }
+
+    #[test]
+    fn handle_windows() {
+        let mut contents = vec![0; 200 * 1024];
+        contents.extend(random_bytes(100 * 1024));
+        contents.extend(vec![0; 50 * 1024]);
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), &contents)
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            window_len: Some(100 * 1024),
+            threshold: Some(7.2),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let windows = session.replies::<Item>()
+            .map(|item| (item.offset, item.len, item.above_threshold))
+            .collect::<Vec<_>>();
+
+        assert_eq!(windows, vec![
+            (0, 100 * 1024, false),
+            (100 * 1024, 100 * 1024, false),
+            (200 * 1024, 100 * 1024, true),
+            (300 * 1024, 50 * 1024, false),
+        ]);
+    }
+
+    #[test]
+    fn histogram_entropy_two_values() {
+        let mut histogram = Histogram::new();
+        histogram.update(b"abababab");
+
+        assert!((histogram.entropy() - 1.0).abs() < f64::EPSILON);
+    }
+}
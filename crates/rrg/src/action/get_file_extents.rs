@@ -0,0 +1,345 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of block allocation maps of files.
+//!
+//! The map tells where on the underlying device the data of a file lives,
+//! which is useful for understanding fragmentation and for targeting raw reads
+//! of the device (e.g. to recover slack space past the end of the file). The
+//! map is obtained through the `FS_IOC_FIEMAP` ioctl, so this action is
+//! available only on Linux.
+
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+/// Arguments of the `get_file_extents` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Path to the file to get the block allocation map of.
+    path: PathBuf,
+    /// Whether to write dirty pages of the file back before mapping it.
+    sync: bool,
+}
+
+/// Result of the `get_file_extents` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Path to the file the allocation map is for.
+    path: PathBuf,
+    /// Size of the file (in bytes).
+    size: u64,
+    /// Extents of the file (or `None` if the filesystem cannot report them).
+    extents: Option<Vec<ospect::fs::linux::Extent>>,
+    /// Ranges of the file not covered by any extent.
+    holes: Vec<std::ops::Range<u64>>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFileExtents;
+
+/// Handles invocations of the `get_file_extents` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `get_file_extents` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let file = crate::fs::open_regular_file(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    let size = file.metadata()
+        .map_err(crate::session::Error::action)?
+        .len();
+
+    let extents = match ospect::fs::linux::extents(&file, args.sync) {
+        Ok(extents) => Some(extents),
+        Err(error) if error.kind() == std::io::ErrorKind::Unsupported => {
+            log::info!("extents of '{}' not supported: {error}", args.path.display());
+            None
+        }
+        Err(error) => return Err(crate::session::Error::action(error)),
+    };
+
+    let holes = match &extents {
+        Some(extents) => holes(extents, size),
+        None => Vec::new(),
+    };
+
+    session.reply(Item {
+        path: args.path,
+        size,
+        extents,
+        holes,
+    })?;
+
+    Ok(())
+}
+
+/// Returns ranges below `size` that are not covered by any of the extents.
+///
+/// The extents are expected to be sorted by their offsets within the file.
+#[cfg(target_os = "linux")]
+fn holes(extents: &[ospect::fs::linux::Extent], size: u64) -> Vec<std::ops::Range<u64>> {
+    let mut holes = Vec::new();
+
+    let mut offset = 0;
+    for extent in extents {
+        if offset >= size {
+            break;
+        }
+
+        if offset < extent.logical_offset {
+            holes.push(offset..std::cmp::min(extent.logical_offset, size));
+        }
+
+        let end = extent.logical_offset.saturating_add(extent.length);
+        offset = std::cmp::max(offset, end);
+    }
+
+    if offset < size {
+        holes.push(offset..size);
+    }
+
+    holes
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_file_extents::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+            sync: proto.sync(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_file_extents::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_size(self.size);
+
+        match self.extents {
+            Some(extents) => {
+                proto.set_extents(extents.into_iter().map(|extent| {
+                    let mut proto_extent = rrg_proto::get_file_extents::Extent::default();
+                    proto_extent.set_logical_offset(extent.logical_offset);
+                    proto_extent.set_physical_offset(extent.physical_offset);
+                    proto_extent.set_length(extent.length);
+                    proto_extent.set_flags(extent.flags);
+                    proto_extent.set_last(extent.is_last());
+                    proto_extent.set_unknown_location(extent.is_unknown());
+                    proto_extent.set_delalloc(extent.is_delalloc());
+                    proto_extent.set_encoded(extent.is_encoded());
+                    proto_extent.set_inline(extent.is_inline());
+                    proto_extent.set_unwritten(extent.is_unwritten());
+                    proto_extent.set_shared(extent.is_shared());
+
+                    proto_extent
+                }).collect());
+            }
+            None => proto.set_unsupported(true),
+        }
+
+        proto.set_holes(self.holes.into_iter().map(|hole| {
+            let mut proto_hole = rrg_proto::get_file_extents::Range::default();
+            proto_hole.set_offset(hole.start);
+            proto_hole.set_length(hole.end - hole.start);
+
+            proto_hole
+        }).collect());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_regular_file() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, vec![0xf0; 3 * 4096 + 17])
+            .unwrap();
+
+        let args = Args {
+            path: path.clone(),
+            sync: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, path);
+        assert_eq!(item.size, 3 * 4096 + 17);
+
+        let extents = match &item.extents {
+            Some(extents) => extents,
+            // The filesystem of the temporary directory does not support the
+            // ioctl, which is reported cleanly.
+            None => {
+                assert!(item.holes.is_empty());
+                return;
+            }
+        };
+        assert!(!extents.is_empty());
+        assert!(item.holes.is_empty());
+
+        // The mapped ranges cover the whole file (with no overlaps).
+        let mut offset = 0;
+        for extent in extents {
+            assert_eq!(extent.logical_offset, offset);
+            offset += extent.length;
+        }
+        assert!(offset >= item.size);
+    }
+
+    #[test]
+    fn handle_sparse_file() {
+        use std::io::{Seek as _, Write as _};
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+
+        let mut file = std::fs::File::create(&path)
+            .unwrap();
+        file.write_all(&vec![0xf0; 4096])
+            .unwrap();
+        file.seek(std::io::SeekFrom::Start(1024 * 1024))
+            .unwrap();
+        file.write_all(&vec![0xf0; 4096])
+            .unwrap();
+        file.set_len(2 * 1024 * 1024)
+            .unwrap();
+        drop(file);
+
+        let args = Args {
+            path: path.clone(),
+            sync: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        if item.extents.is_none() {
+            return;
+        }
+
+        // Filesystems are free to allocate more than was written, so we can only
+        // check that the trailing part of the file is not allocated.
+        let last_hole = item.holes.last()
+            .unwrap();
+        assert!(last_hole.start >= 1024 * 1024 + 4096);
+        assert_eq!(last_hole.end, 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            sync: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_unsupported() {
+        // Files in `/proc` are not backed by any device and cannot be mapped.
+        let args = Args {
+            path: PathBuf::from("/proc/self/status"),
+            sync: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.extents.is_none());
+
+        let proto = crate::response::Item::into_proto(Item {
+            path: item.path.clone(),
+            size: item.size,
+            extents: None,
+            holes: Vec::new(),
+        });
+        assert!(proto.unsupported());
+        assert!(proto.extents.is_empty());
+    }
+
+    #[test]
+    fn holes_none() {
+        let extents = [
+            extent(0, 4096),
+            extent(4096, 8192),
+        ];
+
+        assert!(holes(&extents, 8192).is_empty());
+        assert!(holes(&extents, 10000).is_empty());
+    }
+
+    #[test]
+    fn holes_gaps() {
+        let extents = [
+            extent(4096, 4096),
+            extent(16384, 4096),
+        ];
+
+        assert_eq!(holes(&extents, 32768), vec![0..4096, 8192..16384, 20480..32768]);
+        assert_eq!(holes(&extents, 10000), vec![0..4096, 8192..10000]);
+    }
+
+    #[test]
+    fn holes_empty_map() {
+        assert_eq!(holes(&[], 1024), vec![0..1024]);
+        assert!(holes(&[], 0).is_empty());
+    }
+
+    fn extent(logical_offset: u64, length: u64) -> ospect::fs::linux::Extent {
+        ospect::fs::linux::Extent {
+            logical_offset,
+            physical_offset: 0,
+            length,
+            flags: 0,
+        }
+    }
+}
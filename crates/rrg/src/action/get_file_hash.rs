@@ -0,0 +1,817 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Computation of hashes of file contents.
+//!
+//! Besides cryptographic hashes (to identify files) the action supports CRC32
+//! (to cheaply verify integrity) and ssdeep (to find files similar to a known
+//! one). All the requested hashes are computed in a single pass over the file.
+//!
+//! Mach-O binaries can additionally be parsed to report their architecture
+//! slices. Universal binaries bundle builds for multiple architectures, so the
+//! digest of the whole file does not match the one of the binary that actually
+//! runs on a particular machine. Because of that, every slice is hashed on its
+//! own as well.
+//!
+//! ELF binaries can be parsed in a similar way. Their digest covers only the
+//! loadable segments (the parts of the file that actually get executed), so it
+//! does not change when sections irrelevant at runtime are added or stripped.
+
+use std::path::PathBuf;
+
+/// Size of the buffer used for reading the file.
+const BUF_LEN: usize = 64 * 1024; // 64 KiB.
+
+/// Arguments of the `get_file_hash` action.
+pub struct Args {
+    /// Path to the file to hash.
+    path: PathBuf,
+    /// Byte offset from which the contents should be hashed.
+    offset: u64,
+    /// Number of bytes to hash (if limited).
+    len: Option<u64>,
+    /// Whether to compute the MD5 digest.
+    md5: bool,
+    /// Whether to compute the SHA-1 digest.
+    sha1: bool,
+    /// Whether to compute the SHA-256 digest.
+    sha256: bool,
+    /// Whether to compute the CRC32 checksum.
+    crc32: bool,
+    /// Whether to compute the ssdeep fuzzy hash.
+    ssdeep: bool,
+    /// Whether to parse the file as a Mach-O binary.
+    macho: bool,
+    /// Whether to parse the file as an ELF binary.
+    elf: bool,
+}
+
+/// Result of the `get_file_hash` action.
+struct Item {
+    /// Canonical path of the hashed file.
+    path: PathBuf,
+    /// Byte offset from which the contents were hashed.
+    offset: u64,
+    /// Number of bytes that were hashed.
+    len: u64,
+    /// MD5 digest of the contents (if requested).
+    md5: Option<[u8; 16]>,
+    /// SHA-1 digest of the contents (if requested).
+    sha1: Option<[u8; 20]>,
+    /// SHA-256 digest of the contents (if requested).
+    sha256: Option<[u8; 32]>,
+    /// CRC32 checksum of the contents (if requested).
+    crc32: Option<u32>,
+    /// ssdeep fuzzy hash of the contents (if requested).
+    ssdeep: Option<String>,
+    /// Slices of the Mach-O binary (if requested and the file is one).
+    macho: Option<MachO>,
+    /// ELF binary along with the SHA-256 digest of its loadable segments (if
+    /// requested and the file is one).
+    elf: Option<(crate::elf::Elf, [u8; 32])>,
+    /// Error of parsing the file as an executable (if it is a malformed one).
+    parse_error: Option<String>,
+}
+
+/// Information about a Mach-O binary.
+struct MachO {
+    /// Whether the binary is a universal one.
+    universal: bool,
+    /// Slices of the binary along with their SHA-256 digests.
+    slices: Vec<(crate::macho::Slice, [u8; 32])>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFileHash;
+
+/// Handles invocations of the `get_file_hash` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Read as _, Seek as _};
+
+    use sha2::Digest as _;
+
+    let mut file = crate::fs::open_file(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    let path = args.path.canonicalize()
+        .map_err(crate::session::Error::action)?;
+
+    let file_len = file.metadata()
+        .map_err(crate::session::Error::action)?
+        .len();
+
+    // ssdeep cannot digest arbitrarily long inputs, so we fail early rather
+    // than reading the whole file just to learn that.
+    let len_to_hash = file_len.saturating_sub(args.offset);
+    let len_to_hash = match args.len {
+        Some(len) => std::cmp::min(len, len_to_hash),
+        None => len_to_hash,
+    };
+    if args.ssdeep && len_to_hash > crate::ssdeep::MAX_LEN {
+        return Err(crate::session::Error::action(UnavailableAlgorithmError {
+            algorithm: "ssdeep",
+            len: len_to_hash,
+            max_len: crate::ssdeep::MAX_LEN,
+        }));
+    }
+
+    let mut parse_error = None;
+
+    let macho = match args.macho {
+        true => parsed(crate::macho::parse(&mut file, file_len), "Mach-O", &mut parse_error)?,
+        false => None,
+    };
+    let macho = match macho {
+        Some(macho) => {
+            let mut slices = Vec::with_capacity(macho.slices.len());
+            for slice in macho.slices {
+                let sha256 = sha256_ranges(session, &mut file, &[(slice.offset, slice.len)])?;
+                slices.push((slice, sha256));
+            }
+
+            Some(MachO {
+                universal: macho.universal,
+                slices,
+            })
+        }
+        None => None,
+    };
+
+    let elf = match args.elf {
+        true => parsed(crate::elf::parse(&mut file, file_len), "ELF", &mut parse_error)?,
+        false => None,
+    };
+    let elf = match elf {
+        Some(elf) => {
+            let load_segments_sha256 = sha256_ranges(session, &mut file, &elf.load_segments)?;
+            Some((elf, load_segments_sha256))
+        }
+        None => None,
+    };
+
+    file.seek(std::io::SeekFrom::Start(args.offset))
+        .map_err(crate::session::Error::action)?;
+
+    let mut file = file.take(args.len.unwrap_or(u64::MAX));
+
+    let mut md5 = args.md5.then(md5::Md5::new);
+    let mut sha1 = args.sha1.then(sha1::Sha1::new);
+    let mut sha256 = args.sha256.then(sha2::Sha256::new);
+    let mut crc32 = args.crc32.then(crc32fast::Hasher::new);
+    let mut ssdeep = args.ssdeep.then(crate::ssdeep::Hasher::new);
+
+    let mut buf = vec![0; BUF_LEN];
+    let mut len = 0;
+
+    loop {
+        session.check_deadline()?;
+
+        let len_read = match file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(crate::session::Error::action(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+        session.throttle(len_read as u64)?;
+
+        let buf = &buf[..len_read];
+        if let Some(md5) = &mut md5 {
+            md5.update(buf);
+        }
+        if let Some(sha1) = &mut sha1 {
+            sha1.update(buf);
+        }
+        if let Some(sha256) = &mut sha256 {
+            sha256.update(buf);
+        }
+        if let Some(crc32) = &mut crc32 {
+            crc32.update(buf);
+        }
+        if let Some(ssdeep) = &mut ssdeep {
+            ssdeep.update(buf);
+        }
+
+        len += len_read as u64;
+    }
+
+    // The file might have grown since we checked its size.
+    let ssdeep = match ssdeep {
+        Some(ssdeep) => match ssdeep.digest() {
+            Some(digest) => Some(digest),
+            None => return Err(crate::session::Error::action(UnavailableAlgorithmError {
+                algorithm: "ssdeep",
+                len,
+                max_len: crate::ssdeep::MAX_LEN,
+            })),
+        },
+        None => None,
+    };
+
+    session.reply(Item {
+        path,
+        offset: args.offset,
+        len,
+        md5: md5.map(|md5| md5.finalize().into()),
+        sha1: sha1.map(|sha1| sha1.finalize().into()),
+        sha256: sha256.map(|sha256| sha256.finalize().into()),
+        crc32: crc32.map(|crc32| crc32.finalize()),
+        ssdeep,
+        macho,
+        elf,
+        parse_error,
+    })?;
+
+    Ok(())
+}
+
+/// Classifies the result of parsing the file as an executable.
+///
+/// Files that do not look like executables of the format yield `None`, as do
+/// malformed ones: they are just hashed as usual and the parse error is stored
+/// in `parse_error`, so that the server can tell them apart.
+fn parsed<T>(
+    result: std::io::Result<Option<T>>,
+    format: &str,
+    parse_error: &mut Option<String>,
+) -> crate::session::Result<Option<T>> {
+    match result {
+        Ok(parsed) => Ok(parsed),
+        Err(error) if error.kind() == std::io::ErrorKind::InvalidData => {
+            log::warn!("malformed {format} binary: {error}");
+            *parse_error = Some(format!("malformed {format} binary: {error}"));
+            Ok(None)
+        }
+        Err(error) => Err(crate::session::Error::action(error)),
+    }
+}
+
+/// Computes the SHA-256 digest of the given ranges (offsets and sizes) of the
+/// file concatenated.
+fn sha256_ranges<S>(
+    session: &mut S,
+    file: &mut std::fs::File,
+    ranges: &[(u64, u64)],
+) -> crate::session::Result<[u8; 32]>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Read as _, Seek as _};
+
+    use sha2::Digest as _;
+
+    let mut buf = vec![0; BUF_LEN];
+    let mut sha256 = sha2::Sha256::new();
+
+    for &(offset, len) in ranges {
+        file.seek(std::io::SeekFrom::Start(offset))
+            .map_err(crate::session::Error::action)?;
+
+        let mut range_file = (&mut *file).take(len);
+        loop {
+            session.check_deadline()?;
+
+            let len_read = match range_file.read(&mut buf[..]) {
+                Ok(len_read) => len_read,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(crate::session::Error::action(error)),
+            };
+
+            if len_read == 0 {
+                break;
+            }
+            session.record_bytes_read(len_read as u64);
+            session.throttle(len_read as u64)?;
+
+            sha256.update(&buf[..len_read]);
+        }
+    }
+
+    Ok(sha256.finalize().into())
+}
+
+/// An error type for when a hash algorithm cannot be used for the input.
+#[derive(Debug)]
+struct UnavailableAlgorithmError {
+    /// Name of the algorithm.
+    algorithm: &'static str,
+    /// Number of bytes to hash.
+    len: u64,
+    /// Maximum number of bytes the algorithm can hash.
+    max_len: u64,
+}
+
+impl std::fmt::Display for UnavailableAlgorithmError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "hash algorithm '{}' unavailable for {} bytes (at most {} supported)",
+            self.algorithm,
+            self.len,
+            self.max_len,
+        }
+    }
+}
+
+impl std::error::Error for UnavailableAlgorithmError {
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_file_hash::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        if !proto.has_path() {
+            return Err(ParseArgsError::missing_field("path"));
+        }
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+            offset: proto.offset(),
+            len: match proto.length() {
+                0 => None,
+                len => Some(len),
+            },
+            md5: proto.md5(),
+            sha1: proto.sha1(),
+            sha256: proto.sha256(),
+            crc32: proto.crc32(),
+            ssdeep: proto.ssdeep(),
+            macho: proto.macho(),
+            elf: proto.elf(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_file_hash::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_offset(self.offset);
+        proto.set_length(self.len);
+        if let Some(md5) = self.md5 {
+            proto.set_md5(md5.to_vec());
+        }
+        if let Some(sha1) = self.sha1 {
+            proto.set_sha1(sha1.to_vec());
+        }
+        if let Some(sha256) = self.sha256 {
+            proto.set_sha256(sha256.to_vec());
+        }
+        if let Some(crc32) = self.crc32 {
+            proto.set_crc32(crc32);
+        }
+        if let Some(ssdeep) = self.ssdeep {
+            proto.set_ssdeep(ssdeep);
+        }
+        if let Some(macho) = self.macho {
+            proto.set_macho(macho.into());
+        }
+        if let Some((elf, load_segments_sha256)) = self.elf {
+            use rrg_proto::get_file_hash::elf::Class;
+
+            let mut proto_elf = rrg_proto::get_file_hash::Elf::default();
+            proto_elf.set_class(if elf.is_64 { Class::ELF64 } else { Class::ELF32 });
+            proto_elf.set_big_endian(elf.is_big_endian);
+            proto_elf.set_file_type(u32::from(elf.file_type));
+            proto_elf.set_machine(u32::from(elf.machine));
+            if let Some(machine_name) = elf.machine_name() {
+                proto_elf.set_machine_name(String::from(machine_name));
+            }
+            if let Some(interpreter) = elf.interpreter {
+                proto_elf.set_interpreter(interpreter);
+            }
+            proto_elf.set_needed(elf.needed);
+            proto_elf.set_stripped(elf.stripped);
+            proto_elf.set_load_segments_sha256(load_segments_sha256.to_vec());
+
+            proto.set_elf(proto_elf);
+        }
+        if let Some(parse_error) = self.parse_error {
+            proto.set_parse_error(parse_error);
+        }
+
+        proto
+    }
+}
+
+impl From<MachO> for rrg_proto::get_file_hash::MachO {
+
+    fn from(macho: MachO) -> rrg_proto::get_file_hash::MachO {
+        let mut proto = rrg_proto::get_file_hash::MachO::default();
+        proto.set_universal(macho.universal);
+
+        for (slice, sha256) in macho.slices {
+            let mut proto_slice = rrg_proto::get_file_hash::MachOSlice::default();
+            proto_slice.set_cpu_type(slice.cpu_type);
+            proto_slice.set_cpu_subtype(slice.cpu_subtype);
+            if let Some(arch) = slice.arch() {
+                proto_slice.set_arch(String::from(arch));
+            }
+            proto_slice.set_offset(slice.offset);
+            proto_slice.set_length(slice.len);
+            proto_slice.set_file_type(slice.file_type);
+            if let Some(uuid) = slice.uuid {
+                proto_slice.set_uuid(uuid.to_vec());
+            }
+            proto_slice.set_code_signature(slice.code_signature);
+            proto_slice.set_encrypted(slice.encrypted);
+            proto_slice.set_dylibs(slice.dylibs);
+            proto_slice.set_rpaths(slice.rpaths);
+            proto_slice.set_sha256(sha256.to_vec());
+
+            proto.mut_slices().push(proto_slice);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Returns arguments hashing the whole file at `path` with all algorithms.
+    fn args_all(path: PathBuf) -> Args {
+        Args {
+            path,
+            offset: 0,
+            len: None,
+            md5: true,
+            sha1: true,
+            sha256: true,
+            crc32: true,
+            ssdeep: true,
+            macho: false,
+            elf: false,
+        }
+    }
+
+    #[test]
+    fn handle_all() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"The quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args_all(tempdir.path().join("foo"))).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.path().join("foo").canonicalize().unwrap());
+        assert_eq!(item.offset, 0);
+        assert_eq!(item.len, 43);
+        assert_eq!(item.md5, Some([
+            0x9e, 0x10, 0x7d, 0x9d, 0x37, 0x2b, 0xb6, 0x82,
+            0x6b, 0xd8, 0x1d, 0x35, 0x42, 0xa4, 0x19, 0xd6,
+        ]));
+        assert_eq!(item.sha1, Some([
+            0x2f, 0xd4, 0xe1, 0xc6, 0x7a, 0x2d, 0x28, 0xfc, 0xed, 0x84,
+            0x9e, 0xe1, 0xbb, 0x76, 0xe7, 0x39, 0x1b, 0x93, 0xeb, 0x12,
+        ]));
+        assert_eq!(item.sha256, Some([
+            0xd7, 0xa8, 0xfb, 0xb3, 0x07, 0xd7, 0x80, 0x94,
+            0x69, 0xca, 0x9a, 0xbc, 0xb0, 0x08, 0x2e, 0x4f,
+            0x8d, 0x56, 0x51, 0xe4, 0x6d, 0x3c, 0xdb, 0x76,
+            0x2d, 0x02, 0xd0, 0xbf, 0x37, 0xc9, 0xe5, 0x92,
+        ]));
+        assert_eq!(item.crc32, Some(0x414fa339));
+        assert!(item.ssdeep.is_some());
+    }
+
+    #[test]
+    fn handle_range() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"0123456789")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            offset: 2,
+            len: Some(3),
+            md5: false,
+            sha1: false,
+            sha256: false,
+            crc32: true,
+            ssdeep: false,
+            macho: false,
+            elf: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.offset, 2);
+        assert_eq!(item.len, 3);
+        assert_eq!(item.crc32, Some(crc32fast::hash(b"234")));
+        assert_eq!(item.md5, None);
+        assert_eq!(item.ssdeep, None);
+    }
+
+    #[test]
+    fn handle_ssdeep_similar_files() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        // Pseudo-random text, so that the fuzzy hash has enough variety.
+        let mut state = 0x2545f491u32;
+        let contents = (0..64 * 1024).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            b"abcdefghijklmnopqrstuvwxyz \n"[(state >> 16) as usize % 28]
+        }).collect::<Vec<u8>>();
+
+        let mut contents_modified = contents.clone();
+        contents_modified[1024..1024 + 8].copy_from_slice(b"modified");
+
+        std::fs::write(tempdir.path().join("foo"), &contents)
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar"), &contents)
+            .unwrap();
+        std::fs::write(tempdir.path().join("baz"), &contents_modified)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        for name in ["foo", "bar", "baz"] {
+            assert!(handle(&mut session, args_all(tempdir.path().join(name))).is_ok());
+        }
+
+        let ssdeep_foo = session.reply::<Item>(0).ssdeep.clone().unwrap();
+        let ssdeep_bar = session.reply::<Item>(1).ssdeep.clone().unwrap();
+        let ssdeep_baz = session.reply::<Item>(2).ssdeep.clone().unwrap();
+
+        assert_eq!(ssdeep_foo, ssdeep_bar);
+        assert_ne!(ssdeep_foo, ssdeep_baz);
+
+        // Only the beginning of the digest should be affected.
+        let (block_size_foo, digest_foo) = ssdeep_foo.split_once(':').unwrap();
+        let (block_size_baz, digest_baz) = ssdeep_baz.split_once(':').unwrap();
+        assert_eq!(block_size_foo, block_size_baz);
+        assert_eq!(digest_foo[16..32], digest_baz[16..32]);
+
+        // Cryptographic digests of similar files are completely different.
+        assert_ne!(session.reply::<Item>(0).sha256, session.reply::<Item>(2).sha256);
+    }
+
+    #[test]
+    fn handle_ssdeep_too_long() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        // The file is sparse, so it does not use any disk space.
+        let file = std::fs::File::create(tempdir.path().join("foo"))
+            .unwrap();
+        file.set_len(crate::ssdeep::MAX_LEN + 1)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let error = handle(&mut session, args_all(tempdir.path().join("foo")))
+            .unwrap_err();
+
+        assert!(error.to_string().contains("ssdeep"));
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_ssdeep_too_long_range() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let file = std::fs::File::create(tempdir.path().join("foo"))
+            .unwrap();
+        file.set_len(crate::ssdeep::MAX_LEN + 1)
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            offset: crate::ssdeep::MAX_LEN - 1024,
+            len: None,
+            md5: false,
+            sha1: false,
+            sha256: false,
+            crc32: false,
+            ssdeep: true,
+            macho: false,
+            elf: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.len, 1025);
+        assert!(item.ssdeep.is_some());
+    }
+
+    #[test]
+    fn handle_macho_thin() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let binary = crate::macho::fixture_thin(0x0100000c, 0, true);
+        std::fs::write(tempdir.path().join("foo"), &binary)
+            .unwrap();
+
+        let args = Args {
+            macho: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        let macho = item.macho.as_ref().unwrap();
+        assert!(!macho.universal);
+        assert_eq!(macho.slices.len(), 1);
+
+        let (slice, sha256) = &macho.slices[0];
+        assert_eq!(slice.arch(), Some("arm64"));
+        assert!(slice.code_signature);
+
+        // The only slice of a thin binary is the whole file.
+        assert_eq!(Some(*sha256), item.sha256);
+        assert_eq!(*sha256, <[u8; 32]>::from(sha2::Sha256::digest(&binary)));
+    }
+
+    #[test]
+    fn handle_macho_universal() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let binary_x86_64 = crate::macho::fixture_thin(0x01000007, 3, false);
+        let binary_arm64 = crate::macho::fixture_thin(0x0100000c, 0, true);
+
+        let binary = crate::macho::fixture_fat(&[
+            binary_x86_64.clone(),
+            binary_arm64.clone(),
+        ]);
+        std::fs::write(tempdir.path().join("foo"), &binary)
+            .unwrap();
+
+        let args = Args {
+            macho: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.len, binary.len() as u64);
+
+        let macho = item.macho.as_ref().unwrap();
+        assert!(macho.universal);
+
+        let archs = macho.slices.iter()
+            .map(|(slice, _)| slice.arch().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(archs, vec!["x86_64", "arm64"]);
+
+        let code_signatures = macho.slices.iter()
+            .map(|(slice, _)| slice.code_signature)
+            .collect::<Vec<_>>();
+        assert_eq!(code_signatures, vec![false, true]);
+
+        assert_eq!(macho.slices[0].1, <[u8; 32]>::from(sha2::Sha256::digest(&binary_x86_64)));
+        assert_eq!(macho.slices[1].1, <[u8; 32]>::from(sha2::Sha256::digest(&binary_arm64)));
+    }
+
+    #[test]
+    fn handle_macho_not_macho() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"The quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        let args = Args {
+            macho: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.macho.is_none());
+        assert_eq!(item.len, 43);
+        assert!(item.sha256.is_some());
+    }
+
+    #[test]
+    fn handle_elf() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let (binary, load_len) = crate::elf::fixture(false);
+        std::fs::write(tempdir.path().join("foo"), &binary)
+            .unwrap();
+
+        let args = Args {
+            elf: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.parse_error.is_none());
+
+        let (elf, load_segments_sha256) = item.elf.as_ref().unwrap();
+        assert_eq!(elf.machine_name(), Some("x86_64"));
+        assert_eq!(elf.interpreter.as_deref(), Some(crate::elf::FIXTURE_INTERPRETER));
+        assert_eq!(elf.needed, vec!["libc.so.6", "libm.so.6"]);
+        assert!(!elf.stripped);
+
+        let load_segment = &binary[..load_len as usize];
+        assert_eq!(*load_segments_sha256, <[u8; 32]>::from(sha2::Sha256::digest(load_segment)));
+        assert_ne!(Some(*load_segments_sha256), item.sha256);
+    }
+
+    #[test]
+    fn handle_elf_truncated() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let (binary, _) = crate::elf::fixture(false);
+        std::fs::write(tempdir.path().join("foo"), &binary[..100])
+            .unwrap();
+
+        let args = Args {
+            elf: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.elf.is_none());
+        assert!(item.parse_error.as_ref().unwrap().contains("ELF"));
+        assert_eq!(item.len, 100);
+        assert!(item.sha256.is_some());
+    }
+
+    #[test]
+    fn handle_elf_not_elf() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"The quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        let args = Args {
+            elf: true,
+            ..args_all(tempdir.path().join("foo"))
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.elf.is_none());
+        assert!(item.parse_error.is_none());
+        assert!(item.sha256.is_some());
+    }
+
+    #[test]
+    fn from_proto_missing_path() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_file_hash::Args::default();
+        proto.set_ssdeep(true);
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+}
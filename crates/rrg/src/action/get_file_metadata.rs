@@ -2,14 +2,107 @@
 //
 // Use of this source code is governed by an MIT-style license that can be found
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
+mod attrs;
+mod baseline;
+mod contents;
+mod hash;
+
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
+#[cfg(target_os = "linux")]
+use self::attrs::{
+    acl_entry_into_proto, collect_acls, file_capabilities, linux_statx,
+    query_block_device_size, query_inode_flags, security_label, Acls,
+};
+#[cfg(target_os = "windows")]
+use self::attrs::{probe_read_access, ReadAccess};
+use self::baseline::{parse_baseline, report_removed, Baseline, BaselineChange};
+use self::contents::{
+    collect_contents, detect_file_type, read_preview, Contents, ContentsBudget,
+    DEFAULT_MAX_INLINE_LEN, MAX_PREVIEW_LEN,
+};
+use self::hash::{
+    compute_content_id, parse_hash_condition, ContentId, ContentIdType,
+    HashCondition, HashMatch,
+};
+
 /// Arguments of the `get_file_metadata` action.
 pub struct Args {
-    /// Root path to the file to get the metadata of.
-    path: PathBuf,
+    /// Root paths to the files to get the metadata of.
+    paths: Vec<PathBuf>,
+    /// Whether the paths were requested as a batch.
+    ///
+    /// In the batch mode, replies are tagged with the index of the path they
+    /// correspond to and failures are reported per path.
+    batch: bool,
     /// Limit on the depth of recursion when visiting subfolders.
     max_depth: u32,
+    /// Patterns of paths to exclude when visiting subfolders.
+    ///
+    /// If empty, the default excludes of the root path are used.
+    excludes: Vec<crate::fs::Glob>,
+    /// Whether to detect types of files based on their magic bytes.
+    detect_file_type: bool,
+    /// Condition on the hash of the contents of files to report (if any).
+    hash_condition: Option<HashCondition>,
+    /// Condition on the special mode bits of files to report (if any).
+    mode_condition: Option<ModeCondition>,
+    /// Number of bytes of regular files to include as a preview (if any).
+    preview_len: Option<u64>,
+    /// Kind of content identity of regular files to report (if any).
+    content_id_type: Option<ContentIdType>,
+    /// Whether to collect and report only the essential fields.
+    ///
+    /// In the minimal mode, only the path, size, modification time and type of
+    /// files (and results of conditions) are reported. This overrides all the
+    /// options enabling collection of optional information.
+    minimal: bool,
+    /// Whether to resolve identifiers of owners of files to names (Unix-only).
+    resolve_owner_names: bool,
+    /// Alternate root at which the paths were reinterpreted (if any).
+    alternate_root: Option<PathBuf>,
+    /// Image root within which the paths are resolved (if any).
+    #[cfg(target_os = "linux")]
+    image_root: Option<PathBuf>,
+    /// Whether to collect POSIX access control lists of files.
+    collect_acls: bool,
+    /// Maximum size of regular files to inline contents of (if requested).
+    ///
+    /// Contents of bigger files are sent to the blob sink and referenced.
+    max_inline_len: Option<u64>,
+    /// Maximum total size of contents to collect in the request (if limited).
+    ///
+    /// Once the files with collected contents add up to it, contents of the
+    /// remaining files are not collected.
+    max_contents_total_len: Option<u64>,
+    /// Whether device files can be opened to query more information.
+    open_devices: bool,
+    /// Baseline to compare the files against (if any).
+    baseline: Option<Baseline>,
+    /// Maximum number of links of symlink chains to report (0 to not report).
+    symlink_chain_depth: u32,
+    /// Types of filesystems to descend into (all if empty).
+    include_fs_types: Vec<String>,
+    /// Types of filesystems not to descend into.
+    exclude_fs_types: Vec<String>,
+    /// File descriptor of a process to get the metadata of (if any).
+    ///
+    /// If set, the only path is the one through which the descriptor can be
+    /// accessed.
+    #[cfg(target_os = "linux")]
+    process_fd: Option<crate::fs::linux::ProcessFd>,
+}
+
+/// Condition on the special mode bits of a file.
+#[derive(Clone, Copy, Default)]
+struct ModeCondition {
+    /// Whether to match files with the set-user-ID bit.
+    setuid: bool,
+    /// Whether to match files with the set-group-ID bit.
+    setgid: bool,
+    /// Whether to match files with the sticky bit.
+    sticky: bool,
 }
 
 /// Result of the `get_file_metadata` action.
@@ -21,38 +114,291 @@ struct Item {
     /// Extended attributes of the file.
     #[cfg(target_family = "unix")]
     ext_attrs: Vec<ospect::fs::ExtAttr>,
-    // TODO(@panhania): Add support for file flags (also known as attributes).
-    //
-    // Collection of them is already implemented in the `ospect` crate, but it
-    // is no clear which protobuf message should include them and whether we
-    // should have separate fields for each platform or to cram everything into
-    // one.
-    //
-    // It is also not clear how the field should be named as on Linux this
-    // feature is called "attributes" (not to be confused with file extended
-    // attributes!) and on macOS it is called "flags".
+    /// Inode flags (also known as attributes) of the file (if supported).
+    #[cfg(target_os = "linux")]
+    inode_flags: Option<crate::fs::linux::InodeFlags>,
+    // TODO(@panhania): Add support for file flags on macOS.
     /// Path to the file pointed by a symlink (if available).
     symlink: Option<PathBuf>,
+    /// Chain of symlinks leading from the file to its final target (if requested).
+    symlink_chain: Option<SymlinkChain>,
+    /// Whether the file was deleted (while still being open by a process).
+    deleted: bool,
+    /// Filesystem on which the file lives (if known).
+    #[cfg(target_family = "unix")]
+    mount: Option<ospect::fs::Mount>,
+    /// Metadata obtained through the `statx` system call (if supported).
+    #[cfg(target_os = "linux")]
+    statx: Option<ospect::fs::linux::Statx>,
+    /// Capacity of the block device (if the file is one).
+    #[cfg(target_os = "linux")]
+    block_device_size: Option<u64>,
+    /// POSIX access and default ACLs of the file (if requested).
+    #[cfg(target_os = "linux")]
+    acls: Option<Acls>,
+    /// Capabilities granted to executables of the file (if any).
+    #[cfg(target_os = "linux")]
+    capabilities: Option<crate::fs::linux::FileCapabilities>,
+    /// SELinux security context of the file (if any).
+    #[cfg(target_os = "linux")]
+    selinux_context: Option<String>,
+    /// SMACK security label of the file (if any).
+    #[cfg(target_os = "linux")]
+    smack_label: Option<String>,
+    /// Whether the file can be opened for reading (if it is a regular file).
+    #[cfg(target_os = "windows")]
+    read_access: Option<ReadAccess>,
+    /// Digest that satisfied the hash condition (if it was specified).
+    hash_match: Option<HashMatch>,
+    /// How the file differs from the baseline (if it was specified).
+    baseline_change: Option<BaselineChange>,
+    /// Type of the file detected based on its magic bytes (if requested).
+    file_type: Option<&'static str>,
+    /// Contents of the file (if requested and it is a regular file).
+    contents: Option<Contents>,
+    /// Prefix of contents of the file (if requested and it is a regular file).
+    preview: Option<Vec<u8>>,
+    /// Identity of contents of the file (if requested and it is a regular file).
+    content_id: Option<ContentId>,
+    /// Index of the requested path the file corresponds to (in batch mode).
+    path_index: Option<u32>,
+    /// Names of the user and the group owning the file (if requested).
+    #[cfg(target_family = "unix")]
+    owner_names: Option<(String, String)>,
+    /// Whether only the essential fields should be reported.
+    minimal: bool,
 }
 
+/// Chain of symlinks leading from a symlink to its final target.
+struct SymlinkChain {
+    /// Consecutive links of the chain as pairs of symlink paths and targets.
+    ///
+    /// Relative targets are resolved against the directory of the symlink and
+    /// the target of the last link is canonicalized (unless truncated).
+    links: Vec<(PathBuf, PathBuf)>,
+    /// Whether the chain is longer than the depth limit.
+    truncated: bool,
+    /// Whether the chain is longer than the maximum symlink depth of the agent.
+    too_many_levels: bool,
+}
+
+/// Result of the `get_file_metadata` action for a path that failed (in batch mode).
+struct ErrorItem {
+    /// Index of the requested path that failed.
+    path_index: u32,
+    /// Requested path that failed.
+    path: PathBuf,
+    /// Description of the failure.
+    error: String,
+}
+
+/// Result of the `get_file_metadata` action for a directory with too many entries.
+struct TruncatedDirItem {
+    /// Path to the directory that was truncated.
+    path: PathBuf,
+    /// Index of the requested path the directory corresponds to (in batch mode).
+    path_index: Option<u32>,
+}
+
+/// Result of the `get_file_metadata` action for an entry that timed out.
+struct TimedOutItem {
+    /// Path to the entry that timed out.
+    path: PathBuf,
+    /// Index of the requested path the entry corresponds to (in batch mode).
+    path_index: Option<u32>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFileMetadata;
+
 /// Handles invocations of the `get_file_metadata` action.
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
 where
     S: crate::session::Session,
 {
-    if args.path.is_relative() {
+    // Reading the cache is not free, so we do it only if we need to hash.
+    let mut hash_cache = if args.hash_condition.is_some() || args.baseline.is_some() || args.content_id_type.is_some() {
+        use crate::hash_cache::{HashCache, DEFAULT_CAPACITY};
+        match crate::temp_dir(session.args()) {
+            Ok(temp_dir) => HashCache::load(temp_dir, DEFAULT_CAPACITY),
+            Err(_) => HashCache::new(DEFAULT_CAPACITY),
+        }
+    } else {
+        crate::hash_cache::HashCache::new(0)
+    };
+
+    // Paths of the baseline that were visited, the rest of them are checked
+    // for removal once all the requested paths are done.
+    let mut baseline_visited = HashSet::new();
+
+    // The store is shared by all the paths, so that identical files (which are
+    // common among configuration files) are sent only once.
+    let mut blob_store = crate::session::BlobStore::new();
+
+    // Similarly, the budget applies to the whole request and not individual
+    // paths, as otherwise broad requests could still collect a lot of data.
+    let mut contents_budget = ContentsBudget::new(args.max_contents_total_len);
+
+    // Owners are resolved once per walk, as most of the files are owned by the
+    // same few users.
+    #[cfg(target_family = "unix")]
+    let mut owner_names = crate::owner::OwnerNames::new();
+
+    if args.batch {
+        for (path_index, path) in (0..).zip(&args.paths) {
+            let result = handle_path(
+                session,
+                &args,
+                path,
+                Some(path_index),
+                &mut hash_cache,
+                &mut blob_store,
+                &mut contents_budget,
+                &mut baseline_visited,
+                #[cfg(target_family = "unix")]
+                &mut owner_names,
+            );
+
+            match result {
+                Ok(()) => (),
+                // Failure to get the metadata of one of the paths should not
+                // abort the whole batch, so we report it as a regular reply.
+                Err(error) if error.kind() == crate::session::ErrorKind::ActionFailure => {
+                    session.reply(ErrorItem {
+                        path_index,
+                        path: path.clone(),
+                        error: error.to_string(),
+                    })?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    } else {
+        // Outside of the batch mode there is always exactly one path.
+        for path in &args.paths {
+            handle_path(
+                session,
+                &args,
+                path,
+                None,
+                &mut hash_cache,
+                &mut blob_store,
+                &mut contents_budget,
+                &mut baseline_visited,
+                #[cfg(target_family = "unix")]
+                &mut owner_names,
+            )?;
+        }
+    }
+
+    if let Some(baseline) = &args.baseline {
+        report_removed(session, &args, baseline, &baseline_visited)?;
+    }
+
+    if let Err(error) = hash_cache.save() {
+        log::warn!("failed to save hash cache: {error}");
+    }
+
+    Ok(())
+}
+
+/// Reports metadata of the given path (and its subfolders, if requested).
+#[allow(clippy::too_many_arguments)]
+fn handle_path<S>(
+    session: &mut S,
+    args: &Args,
+    root: &Path,
+    path_index: Option<u32>,
+    hash_cache: &mut crate::hash_cache::HashCache,
+    blob_store: &mut crate::session::BlobStore,
+    contents_budget: &mut ContentsBudget,
+    baseline_visited: &mut HashSet<PathBuf>,
+    #[cfg(target_family = "unix")]
+    owner_names: &mut crate::owner::OwnerNames,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if root.is_relative() {
         use std::io::{Error, ErrorKind};
 
         let error = Error::new(ErrorKind::InvalidInput, "relative path");
         return Err(crate::session::Error::action(error));
     }
 
-    let metadata = args.path.symlink_metadata()
-        .map_err(crate::session::Error::action)?;
+    if let Some(alternate_root) = &args.alternate_root {
+        crate::fs::check_within_root(alternate_root, root)
+            .map_err(crate::session::Error::action)?;
+    }
+
+    // Unlike with alternate roots, symlinks within an image root are not
+    // rejected but resolved as if the image root was the filesystem root.
+    #[cfg(target_os = "linux")]
+    let resolved_root;
+    #[cfg(target_os = "linux")]
+    let root = match &args.image_root {
+        Some(image_root) => {
+            let max_symlink_depth = session.args().max_symlink_depth;
+            resolved_root = crate::fs::linux::resolve_in_root(image_root, root, max_symlink_depth)
+                .map_err(crate::session::Error::action)?;
+            resolved_root.as_path()
+        }
+        None => root,
+    };
+
+    // Files open by processes are accessed through links in `/proc` that have
+    // to be followed (we want the open file, not the link) and are reported
+    // with paths they were opened with.
+    #[cfg(target_os = "linux")]
+    let process_fd_target = match &args.process_fd {
+        Some(process_fd) => {
+            let target = process_fd.target()
+                .map_err(crate::session::Error::action)?;
+
+            Some((target.path, target.deleted))
+        }
+        None => None,
+    };
+    #[cfg(not(target_os = "linux"))]
+    let process_fd_target = None::<(PathBuf, bool)>;
+
+    let metadata = if process_fd_target.is_some() {
+        root.metadata()
+    } else {
+        root.symlink_metadata()
+    }.map_err(crate::session::Error::action)?;
+
+    let is_mode_match = match &args.mode_condition {
+        Some(mode_condition) => mode_condition.check(&metadata),
+        None => true,
+    };
+
+    let hash_match = match &args.hash_condition {
+        Some(hash_condition) if is_mode_match => hash_condition.check(root, &metadata, hash_cache)
+            .map_err(crate::session::Error::action)?,
+        _ => None,
+    };
+
+    let baseline_change = match &args.baseline {
+        Some(baseline) if metadata.is_file() => {
+            baseline_visited.insert(root.to_path_buf());
+            baseline.check(root, &metadata, hash_cache)
+                .map_err(crate::session::Error::action)?
+        }
+        _ => None,
+    };
 
+    // Extended attributes are not collected for descriptors, as they would be
+    // the attributes of the link in `/proc` rather than of the open file. Nor
+    // are they collected in the minimal mode, which also means that none of
+    // the information derived from them (e.g. capabilities) is collected.
     #[cfg(target_family = "unix")]
     let ext_attrs = || -> std::io::Result<Vec<ospect::fs::ExtAttr>> {
-        ospect::fs::ext_attrs(args.path.as_ref())?
+        if process_fd_target.is_some() || args.minimal {
+            return Ok(Vec::new());
+        }
+
+        ospect::fs::ext_attrs(root)?
             .collect()
     }().map_err(crate::session::Error::action)?;
 
@@ -62,41 +408,267 @@ where
     // to canonicalize the parent part of the path.
     let path;
     let symlink;
+    let deleted;
 
-    if metadata.is_symlink() {
-        path = canonicalize_parent(&args.path);
-        symlink = Some(std::fs::read_link(&args.path));
+    if let Some((target_path, target_deleted)) = process_fd_target.clone() {
+        path = Ok(target_path);
+        symlink = None;
+        deleted = target_deleted;
+    } else if metadata.is_symlink() {
+        path = canonicalize_parent(root);
+        symlink = Some(std::fs::read_link(root));
+        deleted = false;
     } else {
-        path = args.path.canonicalize();
+        path = root.canonicalize();
         symlink = None;
+        deleted = false;
     };
 
     let path = path.map_err(crate::session::Error::action)?;
     let symlink = symlink.transpose().map_err(crate::session::Error::action)?;
 
-    session.reply(Item {
-        path: path.clone(),
-        metadata,
-        #[cfg(target_family = "unix")]
-        ext_attrs,
-        symlink,
-    })?;
+    let symlink_chain = if args.symlink_chain_depth > 0 && metadata.is_symlink() {
+        follow_symlink_chain(root, args.symlink_chain_depth, session.args().max_symlink_depth)
+    } else {
+        None
+    };
+
+    // Information about mounts is not crucial, so in case we fail to collect
+    // it we just omit it from the results.
+    #[cfg(target_family = "unix")]
+    let mounts = match crate::fs::Mounts::new() {
+        _ if args.minimal => None,
+        Ok(mounts) => Some(mounts),
+        Err(error) => {
+            log::error!("failed to list mounts: {error}");
+            None
+        }
+    };
+
+    #[cfg(target_family = "unix")]
+    let mount = mounts.as_ref()
+        .and_then(|mounts| mounts.get(&metadata))
+        .cloned();
+
+    #[cfg(target_os = "linux")]
+    let inode_flags = if !args.minimal {
+        query_inode_flags(root, &metadata)
+    } else {
+        None
+    };
+
+    // Similarly to extended attributes, `statx` would not follow the link.
+    #[cfg(target_os = "linux")]
+    let statx = if process_fd_target.is_none() && !args.minimal {
+        linux_statx(root, |path| ospect::fs::linux::statx(path))
+    } else {
+        None
+    };
+
+    #[cfg(target_os = "linux")]
+    let block_device_size = if args.open_devices {
+        query_block_device_size(root, &metadata)
+    } else {
+        None
+    };
+
+    #[cfg(target_os = "linux")]
+    let acls = if args.collect_acls {
+        collect_acls(root)
+    } else {
+        None
+    };
+
+    #[cfg(target_os = "linux")]
+    let capabilities = file_capabilities(root, &ext_attrs);
+
+    #[cfg(target_os = "linux")]
+    let selinux_context = security_label(&ext_attrs, crate::fs::linux::SELINUX_EXT_ATTR);
+
+    #[cfg(target_os = "linux")]
+    let smack_label = security_label(&ext_attrs, crate::fs::linux::SMACK_EXT_ATTR);
+
+    #[cfg(target_os = "windows")]
+    let read_access = if !args.minimal {
+        probe_read_access(root, &metadata)
+    } else {
+        None
+    };
+
+    let file_type = if args.detect_file_type {
+        detect_file_type(root, &metadata)
+    } else {
+        None
+    };
+
+    let contents = match args.max_inline_len {
+        Some(max_inline_len) => {
+            collect_contents(session, root, &metadata, max_inline_len, blob_store, contents_budget)?
+        }
+        None => None,
+    };
+
+    let preview = match args.preview_len {
+        Some(preview_len) => read_preview(root, &metadata, preview_len),
+        None => None,
+    };
+
+    let content_id = match args.content_id_type {
+        Some(content_id_type) => compute_content_id(root, &metadata, content_id_type, hash_cache),
+        None => None,
+    };
+
+    #[cfg(target_family = "unix")]
+    let item_owner_names = resolve_owner_names(args, owner_names, &metadata);
+
+    // Even if the root does not satisfy the hash condition or does not differ
+    // from the baseline (e.g. because it is a directory), we still want to
+    // visit its contents.
+    let is_hash_match = args.hash_condition.is_none() || hash_match.is_some();
+    let is_baseline_change = args.baseline.is_none() || baseline_change.is_some();
+    if is_mode_match && is_hash_match && is_baseline_change {
+        session.reply(Item {
+            path: path.clone(),
+            metadata,
+            #[cfg(target_family = "unix")]
+            ext_attrs,
+            #[cfg(target_os = "linux")]
+            inode_flags,
+            symlink,
+            symlink_chain,
+            deleted,
+            #[cfg(target_family = "unix")]
+            mount,
+            #[cfg(target_os = "linux")]
+            statx,
+            #[cfg(target_os = "linux")]
+            block_device_size,
+            #[cfg(target_os = "linux")]
+            acls,
+            #[cfg(target_os = "linux")]
+            capabilities,
+            #[cfg(target_os = "linux")]
+            selinux_context,
+            #[cfg(target_os = "linux")]
+            smack_label,
+            #[cfg(target_os = "windows")]
+            read_access,
+            hash_match,
+            baseline_change,
+            file_type,
+            contents,
+            preview,
+            content_id,
+            path_index,
+            #[cfg(target_family = "unix")]
+            owner_names: item_owner_names,
+            minimal: args.minimal,
+        })?;
+    }
 
     if args.max_depth > 0 {
-        for entry in crate::fs::walk_dir(&path)
+        let excludes = if args.excludes.is_empty() {
+            crate::fs::default_walk_excludes(root)
+        } else {
+            args.excludes.clone()
+        };
+
+        let max_dir_entries = session.args().max_dir_entries;
+        let max_symlink_depth = session.args().max_symlink_depth;
+
+        let mut entries = crate::fs::walk_dir(&path)
             .map_err(crate::session::Error::action)?
             .with_max_depth(args.max_depth)
-        {
+            .with_max_dir_entries(max_dir_entries)
+            .with_excludes(excludes);
+
+        // Without a filter walks do not leave the filesystem of the root, with
+        // it they enter other filesystems as long as their type is allowed.
+        if !args.include_fs_types.is_empty() || !args.exclude_fs_types.is_empty() {
+            let fs_type_filter = crate::fs::FsTypeFilter::new(
+                args.include_fs_types.clone(),
+                args.exclude_fs_types.clone(),
+            ).map_err(crate::session::Error::action)?;
+
+            entries = entries.with_fs_type_filter(fs_type_filter);
+        }
+        if let Some(entry_timeout) = session.args().fs_entry_timeout {
+            entries = entries.with_entry_timeout(entry_timeout);
+        }
+
+        for entry in entries {
             let entry = match entry {
                 Ok(entry) => entry,
                 Err(error) => {
+                    // Truncation is not a failure: entries up to the limit have
+                    // been reported and the caller has to know that there are
+                    // more of them.
+                    if let Some(error) = crate::fs::TruncatedDirError::from_io_error(&error) {
+                        log::warn!("{error}");
+
+                        session.reply(TruncatedDirItem {
+                            path: error.path().to_path_buf(),
+                            path_index,
+                        })?;
+                        continue
+                    }
+                    // Same goes for entries that timed out: the caller has to
+                    // know that they (or their subtrees) are missing.
+                    if let Some(error) = crate::fs::TimedOutEntryError::from_io_error(&error) {
+                        log::warn!("{error}");
+
+                        session.reply(TimedOutItem {
+                            path: error.path().to_path_buf(),
+                            path_index,
+                        })?;
+                        continue
+                    }
+
                     log::error!("failed to read directory entry: {error}");
                     continue
                 }
             };
 
+            // We check the conditions first, as there is no point in doing any
+            // other work for entries that are not going to be reported. The
+            // mode condition is the cheapest one, so it goes first.
+            if let Some(mode_condition) = &args.mode_condition {
+                if !mode_condition.check(&entry.metadata) {
+                    continue
+                }
+            }
+
+            let hash_match = match &args.hash_condition {
+                Some(hash_condition) => match hash_condition.check(&entry.path, &entry.metadata, hash_cache) {
+                    Ok(Some(hash_match)) => Some(hash_match),
+                    Ok(None) => continue,
+                    Err(error) => {
+                        log::error!("failed to hash '{}': {error}", entry.path.display());
+                        continue
+                    }
+                },
+                None => None,
+            };
+
+            let baseline_change = match &args.baseline {
+                Some(_) if !entry.metadata.is_file() => continue,
+                Some(baseline) => {
+                    baseline_visited.insert(entry.path.clone());
+                    match baseline.check(&entry.path, &entry.metadata, hash_cache) {
+                        Ok(Some(baseline_change)) => Some(baseline_change),
+                        Ok(None) => continue,
+                        Err(error) => {
+                            log::error!("failed to hash '{}': {error}", entry.path.display());
+                            continue
+                        }
+                    }
+                }
+                None => None,
+            };
+
             #[cfg(target_family = "unix")]
             let ext_attrs = match ospect::fs::ext_attrs(&entry.path) {
+                _ if args.minimal => Vec::default(),
                 Ok(ext_attrs) => ext_attrs.filter_map(|ext_attr| match ext_attr {
                     Ok(ext_attr) => Some(ext_attr),
                     Err(error) => {
@@ -134,12 +706,132 @@ where
                 None
             };
 
+            let symlink_chain = if args.symlink_chain_depth > 0 && entry.metadata.is_symlink() {
+                follow_symlink_chain(&entry.path, args.symlink_chain_depth, max_symlink_depth)
+            } else {
+                None
+            };
+
+            #[cfg(target_family = "unix")]
+            let mount = mounts.as_ref()
+                .and_then(|mounts| mounts.get(&entry.metadata))
+                .cloned();
+
+            #[cfg(target_os = "linux")]
+            let inode_flags = if !args.minimal {
+                query_inode_flags(&entry.path, &entry.metadata)
+            } else {
+                None
+            };
+
+            #[cfg(target_os = "linux")]
+            let statx = if !args.minimal {
+                linux_statx(&entry.path, |path| ospect::fs::linux::statx(path))
+            } else {
+                None
+            };
+
+            #[cfg(target_os = "linux")]
+            let block_device_size = if args.open_devices {
+                query_block_device_size(&entry.path, &entry.metadata)
+            } else {
+                None
+            };
+
+            #[cfg(target_os = "linux")]
+            let acls = if args.collect_acls {
+                collect_acls(&entry.path)
+            } else {
+                None
+            };
+
+            #[cfg(target_os = "linux")]
+            let capabilities = file_capabilities(&entry.path, &ext_attrs);
+
+            #[cfg(target_os = "linux")]
+            let selinux_context = security_label(&ext_attrs, crate::fs::linux::SELINUX_EXT_ATTR);
+
+            #[cfg(target_os = "linux")]
+            let smack_label = security_label(&ext_attrs, crate::fs::linux::SMACK_EXT_ATTR);
+
+            #[cfg(target_os = "windows")]
+            let read_access = if !args.minimal {
+                probe_read_access(&entry.path, &entry.metadata)
+            } else {
+                None
+            };
+
+            let file_type = if args.detect_file_type {
+                detect_file_type(&entry.path, &entry.metadata)
+            } else {
+                None
+            };
+
+            let contents = match args.max_inline_len {
+                Some(max_inline_len) => {
+                    collect_contents(
+                        session,
+                        &entry.path,
+                        &entry.metadata,
+                        max_inline_len,
+                        blob_store,
+                        contents_budget,
+                    )?
+                }
+                None => None,
+            };
+
+            let preview = match args.preview_len {
+                Some(preview_len) => read_preview(&entry.path, &entry.metadata, preview_len),
+                None => None,
+            };
+
+            let content_id = match args.content_id_type {
+                Some(content_id_type) => {
+                    compute_content_id(&entry.path, &entry.metadata, content_id_type, hash_cache)
+                }
+                None => None,
+            };
+
+            #[cfg(target_family = "unix")]
+            let item_owner_names = resolve_owner_names(args, owner_names, &entry.metadata);
+
             session.reply(Item {
                 path: entry.path,
                 metadata: entry.metadata,
                 #[cfg(target_family = "unix")]
                 ext_attrs,
+                #[cfg(target_os = "linux")]
+                inode_flags,
                 symlink,
+                symlink_chain,
+                deleted: false,
+                #[cfg(target_family = "unix")]
+                mount,
+                #[cfg(target_os = "linux")]
+                statx,
+                #[cfg(target_os = "linux")]
+                block_device_size,
+                #[cfg(target_os = "linux")]
+                acls,
+                #[cfg(target_os = "linux")]
+                capabilities,
+                #[cfg(target_os = "linux")]
+                selinux_context,
+                #[cfg(target_os = "linux")]
+                smack_label,
+                #[cfg(target_os = "windows")]
+                read_access,
+                hash_match,
+                baseline_change,
+                file_type,
+                contents,
+                preview,
+                content_id,
+                path_index,
+                #[cfg(target_family = "unix")]
+                owner_names: item_owner_names,
+                minimal: args.minimal,
             })?;
         }
     }
@@ -147,126 +839,1032 @@ where
     Ok(())
 }
 
-impl crate::request::Args for Args {
-
-    type Proto = rrg_proto::get_file_metadata::Args;
-
-    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
-        use crate::request::ParseArgsError;
-
-        let path = PathBuf::try_from(proto.take_path())
-            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
-
-        Ok(Args {
-            path,
-            max_depth: proto.max_depth(),
-        })
-    }
-}
-
-impl crate::response::Item for Item {
-
-    type Proto = rrg_proto::get_file_metadata::Result;
-
-    fn into_proto(self) -> Self::Proto {
-        let mut proto = rrg_proto::get_file_metadata::Result::default();
-        proto.set_path(self.path.into());
-        proto.set_metadata(self.metadata.into());
+impl ModeCondition {
 
+    /// Checks whether the file with the given metadata satisfies the condition.
+    fn check(&self, metadata: &std::fs::Metadata) -> bool {
         #[cfg(target_family = "unix")]
         {
-            for ext_attr in self.ext_attrs {
-                proto.mut_ext_attrs().push(ext_attr.into());
-            }
+            use std::os::unix::fs::MetadataExt as _;
+
+            let mode = metadata.mode();
+            (self.setuid && mode & 0o4000 != 0) ||
+            (self.setgid && mode & 0o2000 != 0) ||
+            (self.sticky && mode & 0o1000 != 0)
         }
 
-        if let Some(symlink) = self.symlink {
-            proto.set_symlink(symlink.into());
+        #[cfg(not(target_family = "unix"))]
+        {
+            let _ = metadata;
+            false
         }
+    }
+}
 
-        proto
+/// Resolves names of the user and the group owning a file (if requested).
+#[cfg(target_family = "unix")]
+fn resolve_owner_names(
+    args: &Args,
+    owner_names: &mut crate::owner::OwnerNames,
+    metadata: &std::fs::Metadata,
+) -> Option<(String, String)> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    if !args.resolve_owner_names {
+        return None;
     }
+
+    let user_name = String::from(owner_names.user(metadata.uid()));
+    let group_name = String::from(owner_names.group(metadata.gid()));
+
+    Some((user_name, group_name))
 }
 
-/// Returns the canonical, absolute form of the path.
+/// Follows the chain of symlinks starting at `path` up to `depth` links.
 ///
-/// This is similar to [`std::fs::canonicalize`] but modifies only the dirname
-/// part of the path. This might be important for symlinks, because we want to
-/// return the canonical path to the symlink itself, not the file it points to.
-fn canonicalize_parent<P>(path: P) -> std::io::Result<PathBuf>
-where
-    P: AsRef<Path>,
-{
-    let path = path.as_ref();
-
-    let parent = match path.parent() {
-        Some(parent) => parent,
-        None => {
-            // There is no parent, we are at root so there is no need to do any
-            // canonicalization.
-            return Ok(PathBuf::from(path));
+/// The depth is capped at `max_depth` (the maximum symlink depth of the agent)
+/// and chains longer than that are marked as having too many levels. Failures
+/// to follow the chain are logged, as they should not prevent reporting the
+/// rest of the metadata.
+fn follow_symlink_chain(path: &Path, depth: u32, max_depth: usize) -> Option<SymlinkChain> {
+    let len = std::cmp::min(depth as usize, max_depth);
+
+    let chain = match crate::fs::symlink_chain_with_max_len(path, len) {
+        Ok(chain) => chain,
+        Err(error) => {
+            log::error!("failed to follow symlink chain of '{}': {error}", path.display());
+            return None;
         }
     };
 
-    let mut canonicalized = parent.canonicalize()?;
+    // Every link points to the next one and the last one to the final target.
+    let targets = chain.links.iter()
+        .skip(1)
+        .cloned()
+        .chain(std::iter::once(chain.target));
 
-    match path.file_name() {
-        Some(file_name) => canonicalized.push(file_name),
-        None => {
-            // This should never happen: if we are not a root path, there always
-            // should be a file name as long as we don't end with something like
-            // `..` or `.`. But then the behaviour is not well defined anyway
-            // (and we use this function on something we are sure is a symlink).
-            return Err(std::io::ErrorKind::InvalidInput.into());
-        },
+    let too_many_levels = chain.truncated && len == max_depth;
+    if too_many_levels {
+        log::warn!("{}", crate::fs::SymlinkLoopError::io_error(path, max_depth));
     }
 
-    Ok(canonicalized)
+    Some(SymlinkChain {
+        links: chain.links.iter().cloned().zip(targets).collect(),
+        truncated: chain.truncated,
+        too_many_levels,
+    })
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_file_metadata::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let alternate_root = if proto.has_alternate_root() {
+            let alternate_root = PathBuf::try_from(proto.take_alternate_root())
+                .map_err(|error| ParseArgsError::invalid_field("alternate_root", error))?;
+
+            if !alternate_root.is_absolute() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "relative path");
+                return Err(ParseArgsError::invalid_field("alternate_root", error));
+            }
+
+            Some(alternate_root)
+        } else {
+            None
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        if proto.has_image_root() {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "image roots are supported only on Linux");
+            return Err(ParseArgsError::unsupported_field("image_root", error));
+        }
+
+        let image_root = if proto.has_image_root() {
+            let image_root = PathBuf::try_from(proto.take_image_root())
+                .map_err(|error| ParseArgsError::invalid_field("image_root", error))?;
+
+            if !image_root.is_absolute() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "relative path");
+                return Err(ParseArgsError::invalid_field("image_root", error));
+            }
+
+            if alternate_root.is_some() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "both alternate and image root");
+                return Err(ParseArgsError::invalid_field("image_root", error));
+            }
+
+            Some(image_root)
+        } else {
+            None
+        };
+
+        // With an alternate (or image) root, all the paths of the request are
+        // interpreted as rooted at it.
+        let reroot = |path: PathBuf| match alternate_root.as_ref().or(image_root.as_ref()) {
+            Some(new_root) => crate::fs::reroot(new_root, path),
+            None => Ok(path),
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        if proto.has_process_fd() {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "process descriptors are supported only on Linux");
+            return Err(ParseArgsError::unsupported_field("process_fd", error));
+        }
+
+        #[cfg(target_os = "linux")]
+        let process_fd = if proto.has_process_fd() {
+            let is_path = proto.has_path() || !proto.paths.is_empty();
+            let is_reroot = alternate_root.is_some() || image_root.is_some();
+            if is_path || is_reroot || proto.max_depth() > 0 || proto.has_baseline() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "process descriptor with paths, recursion or baseline");
+                return Err(ParseArgsError::invalid_field("process_fd", error));
+            }
+
+            let process_fd = proto.take_process_fd();
+            Some(crate::fs::linux::ProcessFd {
+                pid: process_fd.pid(),
+                fd: process_fd.fd(),
+            })
+        } else {
+            None
+        };
+
+        let batch = !proto.paths.is_empty();
+        if batch && proto.has_path() {
+            let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "both path and paths");
+            return Err(ParseArgsError::invalid_field("paths", error));
+        }
+
+        if !batch && !proto.has_path() && !proto.has_process_fd() {
+            return Err(ParseArgsError::missing_field("path"));
+        }
+
+        let (field, paths) = if batch {
+            ("paths", proto.take_paths())
+        } else if proto.has_process_fd() {
+            ("process_fd", Vec::new())
+        } else {
+            ("path", vec![proto.take_path()])
+        };
+
+        let paths = paths.into_iter()
+            .map(|path| {
+                let path = PathBuf::try_from(path)
+                    .map_err(|error| ParseArgsError::invalid_field(field, error))?;
+
+                reroot(path)
+                    .map_err(|error| ParseArgsError::invalid_field(field, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        #[cfg(target_os = "linux")]
+        let paths = match &process_fd {
+            Some(process_fd) => vec![process_fd.path()],
+            None => paths,
+        };
+
+        let exclude_path_globs = proto.take_exclude_path_globs().into_iter()
+            .map(|path| {
+                let path = PathBuf::try_from(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))?;
+                let path = reroot(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))?;
+
+                crate::fs::Glob::new(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let hash_condition = if proto.has_hash_condition() {
+            Some(parse_hash_condition(proto.take_hash_condition())?)
+        } else {
+            None
+        };
+
+        // In the minimal mode, collection of optional information is disabled
+        // regardless of what the other options ask for.
+        let minimal = proto.minimal();
+
+        let max_inline_len = match (proto.collect_contents(), proto.max_inline_contents_length()) {
+            _ if minimal => None,
+            (false, _) => None,
+            (true, 0) => Some(DEFAULT_MAX_INLINE_LEN),
+            (true, len) => Some(len),
+        };
+
+        let preview_len = match proto.preview_length() {
+            _ if minimal => None,
+            0 => None,
+            len => Some(std::cmp::min(len, MAX_PREVIEW_LEN)),
+        };
+
+        let content_id_type = match proto.content_id_type() {
+            _ if minimal => None,
+            rrg_proto::get_file_metadata::ContentIdType::UNSPECIFIED_CONTENT_ID_TYPE => None,
+            rrg_proto::get_file_metadata::ContentIdType::GIT_BLOB_SHA1 => Some(ContentIdType::GitBlobSha1),
+            rrg_proto::get_file_metadata::ContentIdType::CONTENT_SHA256 => Some(ContentIdType::Sha256),
+        };
+
+        let max_contents_total_len = match proto.max_contents_total_length() {
+            0 => None,
+            len => Some(len),
+        };
+
+        let baseline = if proto.has_baseline() {
+            Some(parse_baseline(proto.take_baseline(), reroot)?)
+        } else {
+            None
+        };
+
+        let symlink_chain_depth = if !minimal {
+            proto.symlink_chain_depth()
+        } else {
+            0
+        };
+
+        Ok(Args {
+            paths,
+            batch,
+            max_depth: proto.max_depth(),
+            excludes: exclude_path_globs,
+            detect_file_type: proto.detect_file_type() && !minimal,
+            hash_condition,
+            mode_condition: proto.mode_condition.take().map(|proto| ModeCondition {
+                setuid: proto.setuid(),
+                setgid: proto.setgid(),
+                sticky: proto.sticky(),
+            }),
+            preview_len,
+            content_id_type,
+            minimal,
+            alternate_root,
+            #[cfg(target_os = "linux")]
+            image_root,
+            collect_acls: proto.collect_acls() && !minimal,
+            max_inline_len,
+            max_contents_total_len,
+            open_devices: proto.open_devices() && !minimal,
+            baseline,
+            symlink_chain_depth,
+            resolve_owner_names: proto.resolve_owner_names() && !minimal,
+            include_fs_types: proto.take_include_fs_types(),
+            exclude_fs_types: proto.take_exclude_fs_types(),
+            #[cfg(target_os = "linux")]
+            process_fd,
+        })
+    }
+}
+
+/// Parses an optional timestamp from its protobuf representation.
+fn parse_time(
+    name: &'static str,
+    timestamp: protobuf::MessageField<protobuf::well_known_types::timestamp::Timestamp>,
+) -> Result<Option<std::time::SystemTime>, crate::request::ParseArgsError> {
+    let timestamp = match timestamp.into_option() {
+        Some(timestamp) => timestamp,
+        None => return Ok(None),
+    };
+
+    // Timestamp is (a bit simplifying) just a duration since epoch, so we
+    // reuse the existing conversion routine for durations.
+    let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+    since_epoch.seconds = timestamp.seconds;
+    since_epoch.nanos = timestamp.nanos;
+
+    let since_epoch = rrg_proto::try_from_duration(since_epoch)
+        .map_err(|error| crate::request::ParseArgsError::invalid_field(name, error))?;
+
+    Ok(Some(std::time::UNIX_EPOCH + since_epoch))
+}
+
+impl Item {
+
+    /// Converts the item to a result with only the essential fields set.
+    fn into_minimal_proto(self) -> rrg_proto::get_file_metadata::Result {
+        let mut proto_metadata = rrg_proto::fs::FileMetadata::default();
+        proto_metadata.set_type(self.metadata.file_type().into());
+        proto_metadata.set_size(self.metadata.len());
+        if let Ok(mtime) = self.metadata.modified() {
+            proto_metadata.set_modification_time(rrg_proto::into_timestamp(mtime));
+        }
+
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        proto.set_metadata(proto_metadata);
+
+        // Results of conditions are not optional information: they are why the
+        // file is reported in the first place.
+        if let Some(hash_match) = self.hash_match {
+            proto.set_hash_match(hash_match.into());
+        }
+        if let Some(baseline_change) = self.baseline_change {
+            proto.set_baseline_change(baseline_change.into());
+        }
+        if let Some(path_index) = self.path_index {
+            proto.set_path_index(path_index);
+        }
+
+        proto
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_file_metadata::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        if self.minimal {
+            return self.into_minimal_proto();
+        }
+
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        proto.set_deleted(self.deleted);
+
+        #[allow(unused_mut)]
+        let mut proto_metadata = rrg_proto::fs::FileMetadata::from(self.metadata);
+        #[cfg(target_family = "unix")]
+        if let Some((user_name, group_name)) = self.owner_names {
+            proto_metadata.set_unix_user_name(user_name);
+            proto_metadata.set_unix_group_name(group_name);
+        }
+        proto.set_metadata(proto_metadata);
+
+        #[cfg(target_os = "windows")]
+        if let Some(read_access) = self.read_access {
+            use rrg_proto::get_file_metadata::WindowsReadAccess;
+
+            proto.set_windows_read_access(match read_access {
+                ReadAccess::Readable => WindowsReadAccess::READABLE,
+                ReadAccess::SharingViolation => WindowsReadAccess::SHARING_VIOLATION,
+                ReadAccess::AccessDenied => WindowsReadAccess::ACCESS_DENIED,
+                ReadAccess::Other => WindowsReadAccess::OTHER_ERROR,
+            });
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(inode_flags) = self.inode_flags {
+            let mut proto_inode_flags = rrg_proto::get_file_metadata::LinuxInodeFlags::default();
+            proto_inode_flags.set_raw(inode_flags.0);
+            proto_inode_flags.set_immutable(inode_flags.is_immutable());
+            proto_inode_flags.set_append_only(inode_flags.is_append_only());
+            proto_inode_flags.set_compressed(inode_flags.is_compressed());
+            proto_inode_flags.set_encrypted(inode_flags.is_encrypted());
+            proto_inode_flags.set_nodump(inode_flags.is_nodump());
+
+            proto.set_linux_inode_flags(proto_inode_flags);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(statx) = self.statx {
+            let mut proto_statx = rrg_proto::get_file_metadata::LinuxStatx::default();
+            if let Some(mount_id) = statx.mount_id {
+                proto_statx.set_mount_id(mount_id);
+            }
+            if let Some(compressed) = statx.is_compressed() {
+                proto_statx.set_compressed(compressed);
+            }
+            if let Some(encrypted) = statx.is_encrypted() {
+                proto_statx.set_encrypted(encrypted);
+            }
+            if let Some(immutable) = statx.is_immutable() {
+                proto_statx.set_immutable(immutable);
+            }
+            if let Some(append_only) = statx.is_append_only() {
+                proto_statx.set_append_only(append_only);
+            }
+
+            if let Some(birth_time) = statx.birth_time {
+                let birth_time = rrg_proto::into_timestamp(birth_time);
+                proto.mut_metadata().set_creation_time(birth_time);
+            }
+
+            proto.set_linux_statx(proto_statx);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(block_device_size) = self.block_device_size {
+            proto.mut_metadata().set_size(block_device_size);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(acls) = self.acls {
+            for entry in acls.access {
+                proto.mut_linux_access_acl().push(acl_entry_into_proto(entry));
+            }
+            for entry in acls.default {
+                proto.mut_linux_default_acl().push(acl_entry_into_proto(entry));
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(capabilities) = self.capabilities {
+            use ospect::proc::linux::capability_names;
+
+            let proto_capabilities = proto.mut_linux_capabilities();
+            proto_capabilities.set_permitted(capability_names(capabilities.permitted));
+            proto_capabilities.set_inheritable(capability_names(capabilities.inheritable));
+            proto_capabilities.set_effective(capabilities.effective);
+            if let Some(root_id) = capabilities.root_id {
+                proto_capabilities.set_root_id(root_id);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(selinux_context) = self.selinux_context {
+            proto.set_linux_selinux_context(selinux_context);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(smack_label) = self.smack_label {
+            proto.set_linux_smack_label(smack_label);
+        }
+
+        #[cfg(target_family = "unix")]
+        {
+            for ext_attr in self.ext_attrs {
+                proto.mut_ext_attrs().push(ext_attr.into());
+            }
+        }
+
+        if let Some(symlink) = self.symlink {
+            proto.set_symlink(symlink.into());
+        }
+
+        if let Some(symlink_chain) = self.symlink_chain {
+            for (path, target) in symlink_chain.links {
+                let mut proto_link = rrg_proto::get_file_metadata::SymlinkChainLink::default();
+                proto_link.set_path(path.into());
+                proto_link.set_target(target.into());
+
+                proto.mut_symlink_chain().push(proto_link);
+            }
+            proto.set_symlink_chain_truncated(symlink_chain.truncated);
+            proto.set_symlink_chain_too_many_levels(symlink_chain.too_many_levels);
+        }
+
+        #[cfg(target_family = "unix")]
+        if let Some(mount) = self.mount {
+            proto.set_mount(mount.into());
+        }
+
+        if let Some(hash_match) = self.hash_match {
+            proto.set_hash_match(hash_match.into());
+        }
+
+        if let Some(baseline_change) = self.baseline_change {
+            proto.set_baseline_change(baseline_change.into());
+        }
+
+        if let Some(file_type) = self.file_type {
+            proto.set_file_type(file_type.to_string());
+        }
+
+        match self.contents {
+            Some(Contents::Inline(contents)) => proto.set_contents(contents),
+            Some(Contents::Ref(file_ref)) => {
+                let mut proto_file_ref = rrg_proto::get_file_metadata::FileContentsRef::default();
+                proto_file_ref.set_sha256(file_ref.sha256.to_vec());
+                proto_file_ref.set_length(file_ref.len);
+                proto_file_ref.set_blob_sha256s(file_ref.blob_sha256s.into_iter()
+                    .map(|blob_sha256| blob_sha256.to_vec())
+                    .collect());
+
+                proto.set_contents_ref(proto_file_ref);
+            }
+            Some(Contents::BudgetExceeded) => proto.set_contents_budget_exceeded(true),
+            None => (),
+        }
+
+        if let Some(preview) = self.preview {
+            proto.set_preview(preview);
+        }
+
+        if let Some(content_id) = self.content_id {
+            proto.set_content_id(content_id.into());
+        }
+
+        if let Some(path_index) = self.path_index {
+            proto.set_path_index(path_index);
+        }
+
+        proto
+    }
+}
+
+impl crate::response::Item for ErrorItem {
+
+    type Proto = rrg_proto::get_file_metadata::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        proto.set_path_index(self.path_index);
+        proto.set_error(self.error);
+
+        proto
+    }
+}
+
+impl crate::response::Item for TruncatedDirItem {
+
+    type Proto = rrg_proto::get_file_metadata::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        if let Some(path_index) = self.path_index {
+            proto.set_path_index(path_index);
+        }
+        proto.set_truncated_dir(true);
+
+        proto
+    }
+}
+
+impl crate::response::Item for TimedOutItem {
+
+    type Proto = rrg_proto::get_file_metadata::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        if let Some(path_index) = self.path_index {
+            proto.set_path_index(path_index);
+        }
+        proto.set_timed_out(true);
+
+        proto
+    }
+}
+
+/// Returns the canonical, absolute form of the path.
+///
+/// This is similar to [`std::fs::canonicalize`] but modifies only the dirname
+/// part of the path. This might be important for symlinks, because we want to
+/// return the canonical path to the symlink itself, not the file it points to.
+fn canonicalize_parent<P>(path: P) -> std::io::Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => {
+            // There is no parent, we are at root so there is no need to do any
+            // canonicalization.
+            return Ok(PathBuf::from(path));
+        }
+    };
+
+    let mut canonicalized = parent.canonicalize()?;
+
+    match path.file_name() {
+        Some(file_name) => canonicalized.push(file_name),
+        None => {
+            // This should never happen: if we are not a root path, there always
+            // should be a file name as long as we don't end with something like
+            // `..` or `.`. But then the behaviour is not well defined anyway
+            // (and we use this function on something we are sure is a symlink).
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        },
+    }
+
+    Ok(canonicalized)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::*;
+    use super::*;
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().join("foo")],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_relative() {
+        let args = Args {
+            paths: vec![PathBuf::from("foo/bar/baz")],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_regular_file() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::File::create(tempdir.join("foo"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.join("foo").to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("foo"));
+        assert_eq!(item.metadata.is_file(), true);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_regular_file_unix_metadata() {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"foobar")
+            .unwrap();
+        std::fs::hard_link(tempdir.join("foo"), tempdir.join("bar"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.join("foo").to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let metadata = std::fs::metadata(tempdir.join("foo"))
+            .unwrap();
+
+        let item = session.reply::<Item>(0);
+        let proto = rrg_proto::fs::FileMetadata::from(item.metadata.clone());
+        assert_ne!(proto.unix_ino(), 0);
+        assert_eq!(proto.unix_ino(), metadata.ino());
+        assert_eq!(proto.unix_dev(), metadata.dev());
+        assert_eq!(proto.unix_nlink(), 2);
+        assert_eq!(proto.unix_mode(), metadata.mode());
+        assert_eq!(proto.unix_uid(), metadata.uid());
+        assert_eq!(proto.unix_gid(), metadata.gid());
+        assert_eq!(proto.unix_blksize(), metadata.blksize());
+        assert_eq!(proto.unix_blocks(), metadata.blocks());
+        assert!(!proto.has_unix_rdev());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_resolve_owner_names() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"")
+            .unwrap();
+        std::fs::write(tempdir.join("bar"), b"")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 3);
+
+        // Files we create are owned by the user running the tests, so we ask
+        // the system for its name independently of the agent code.
+        let output = std::process::Command::new("id")
+            .arg("-un")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let user_name = String::from_utf8(output.stdout)
+            .unwrap();
+        let user_name = user_name.trim_end();
+
+        for item in session.replies::<Item>() {
+            let (item_user_name, _) = item.owner_names.as_ref()
+                .unwrap();
+            assert_eq!(item_user_name, user_name);
+        }
+    }
 
+    #[cfg(target_family = "unix")]
     #[test]
-    fn handle_non_existent() {
+    fn handle_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt as _;
+
         let tempdir = tempfile::tempdir()
             .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        for (name, mode) in [
+            ("normal", 0o755),
+            ("setuid", 0o4755),
+            ("setgid", 0o2755),
+            ("sticky", 0o1755),
+        ] {
+            std::fs::write(tempdir.join(name), b"")
+                .unwrap();
+            std::fs::set_permissions(tempdir.join(name), std::fs::Permissions::from_mode(mode))
+                .unwrap();
+        }
 
         let args = Args {
-            path: tempdir.path().join("foo"),
+            paths: vec![tempdir.join("normal"), tempdir.join("setuid"), tempdir.join("setgid"), tempdir.join("sticky")],
+            batch: false,
             max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
-        assert!(handle(&mut session, args).is_err());
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 4);
+
+        let protos = session.replies::<Item>()
+            .map(|item| rrg_proto::fs::FileMetadata::from(item.metadata.clone()))
+            .collect::<Vec<_>>();
+
+        assert!(!protos[0].unix_setuid());
+        assert!(!protos[0].unix_setgid());
+        assert!(!protos[0].unix_sticky());
+
+        assert!(protos[1].unix_setuid());
+        assert!(!protos[1].unix_setgid());
+        assert!(!protos[1].unix_sticky());
+
+        // The kernel may clear the set-group-ID bit if we are not a member of
+        // the group owning the file, so we check it only if it stuck.
+        let setgid_mode = std::fs::metadata(tempdir.join("setgid")).unwrap()
+            .permissions().mode();
+        assert_eq!(protos[2].unix_setgid(), setgid_mode & 0o2000 != 0);
+        assert!(!protos[2].unix_setuid());
+
+        assert!(protos[3].unix_sticky());
+        assert!(!protos[3].unix_setuid());
     }
 
+    #[cfg(target_family = "unix")]
     #[test]
-    fn handle_relative() {
+    fn handle_mode_condition_setuid() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("usr"))
+            .unwrap();
+
+        for (name, mode) in [
+            ("normal", 0o755),
+            ("setuid", 0o4755),
+            ("usr/normal", 0o755),
+            ("usr/setuid", 0o4755),
+        ] {
+            std::fs::write(tempdir.join(name), b"")
+                .unwrap();
+            std::fs::set_permissions(tempdir.join(name), std::fs::Permissions::from_mode(mode))
+                .unwrap();
+        }
+
         let args = Args {
-            path: PathBuf::from("foo/bar/baz"),
-            max_depth: 0,
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 32,
+            excludes: vec![crate::fs::Glob::new(tempdir.join("usr").join("**")).unwrap()],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: Some(ModeCondition {
+                setuid: true,
+                ..Default::default()
+            }),
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
-        assert!(handle(&mut session, args).is_err());
+        assert!(handle(&mut session, args).is_ok());
+
+        let paths = session.replies::<Item>()
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec![tempdir.join("setuid")]);
     }
 
+    #[cfg(target_family = "unix")]
     #[test]
-    fn handle_regular_file() {
+    fn handle_symlink() {
         let tempdir = tempfile::tempdir()
             .unwrap();
         let tempdir = tempdir.path().canonicalize()
             .unwrap();
 
-        std::fs::File::create(tempdir.join("foo"))
+        std::fs::File::create(tempdir.join("file"))
+            .unwrap();
+        std::os::unix::fs::symlink(tempdir.join("file"), tempdir.join("link"))
             .unwrap();
 
         let args = Args {
-            path: tempdir.join("foo").to_path_buf(),
+            paths: vec![tempdir.join("link")],
+            batch: false,
             max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -275,26 +1873,195 @@ mod tests {
         assert_eq!(session.reply_count(), 1);
 
         let item = session.reply::<Item>(0);
-        assert_eq!(item.path, tempdir.join("foo"));
-        assert_eq!(item.metadata.is_file(), true);
+        assert_eq!(item.path, tempdir.join("link"));
+        assert_eq!(item.metadata.is_symlink(), true);
+        assert_eq!(item.symlink, Some(tempdir.join("file")));
     }
 
     #[cfg(target_family = "unix")]
     #[test]
-    fn handle_symlink() {
+    fn handle_symlink_chain() {
         let tempdir = tempfile::tempdir()
             .unwrap();
         let tempdir = tempdir.path().canonicalize()
             .unwrap();
 
-        std::fs::File::create(tempdir.join("file"))
+        std::fs::create_dir(tempdir.join("dir"))
             .unwrap();
-        std::os::unix::fs::symlink(tempdir.join("file"), tempdir.join("link"))
+        std::fs::File::create(tempdir.join("dir").join("file"))
+            .unwrap();
+
+        // The chain mixes absolute and relative targets: link1 -> link2 ->
+        // dir/link3 -> dir/file.
+        std::os::unix::fs::symlink(tempdir.join("link2"), tempdir.join("link1"))
+            .unwrap();
+        std::os::unix::fs::symlink("dir/link3", tempdir.join("link2"))
+            .unwrap();
+        std::os::unix::fs::symlink("file", tempdir.join("dir").join("link3"))
+            .unwrap();
+
+        let args = |symlink_chain_depth| Args {
+            paths: vec![tempdir.join("link1")],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(8)).is_ok());
+        assert!(handle(&mut session, args(2)).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let chain = session.reply::<Item>(0).symlink_chain.as_ref()
+            .unwrap();
+        assert_eq!(chain.links, vec![
+            (tempdir.join("link1"), tempdir.join("link2")),
+            (tempdir.join("link2"), tempdir.join("dir").join("link3")),
+            (tempdir.join("dir").join("link3"), tempdir.join("dir").join("file")),
+        ]);
+        assert!(!chain.truncated);
+
+        let chain = session.reply::<Item>(1).symlink_chain.as_ref()
+            .unwrap();
+        assert_eq!(chain.links, vec![
+            (tempdir.join("link1"), tempdir.join("link2")),
+            (tempdir.join("link2"), tempdir.join("dir").join("link3")),
+        ]);
+        assert!(chain.truncated);
+        assert!(!chain.too_many_levels);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink_chain_too_many_levels() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("dir"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("dir").join("file"))
+            .unwrap();
+
+        // Chain of 8 links: link7 -> link6 -> ... -> link0 -> file.
+        std::os::unix::fs::symlink("file", tempdir.join("dir").join("link0"))
+            .unwrap();
+        for i in 1..8 {
+            let target = format!("link{}", i - 1);
+            std::os::unix::fs::symlink(target, tempdir.join("dir").join(format!("link{i}")))
+                .unwrap();
+        }
+
+        let args = Args {
+            paths: vec![tempdir.join("dir")],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 100,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            max_symlink_depth: 4,
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.replies_of::<Item>()
+            .map(|item| (item.path.clone(), item))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        // The walk proceeds past the deep chain: all entries are reported.
+        assert_eq!(items.len(), 1 + 1 + 8);
+        assert!(items[&tempdir.join("dir").join("file")].symlink_chain.is_none());
+
+        let chain = items[&tempdir.join("dir").join("link7")].symlink_chain.as_ref()
+            .unwrap();
+        assert_eq!(chain.links.len(), 4);
+        assert!(chain.truncated);
+        assert!(chain.too_many_levels);
+
+        // Shorter chains are within the limit and are reported in full.
+        let chain = items[&tempdir.join("dir").join("link3")].symlink_chain.as_ref()
+            .unwrap();
+        assert_eq!(chain.links.len(), 4);
+        assert!(!chain.truncated);
+        assert!(!chain.too_many_levels);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_mount() {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
             .unwrap();
 
         let args = Args {
-            path: tempdir.join("link"),
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
             max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -303,9 +2070,106 @@ mod tests {
         assert_eq!(session.reply_count(), 1);
 
         let item = session.reply::<Item>(0);
-        assert_eq!(item.path, tempdir.join("link"));
-        assert_eq!(item.metadata.is_symlink(), true);
-        assert_eq!(item.symlink, Some(tempdir.join("file")));
+        let mount = item.mount.as_ref().unwrap();
+        assert!(item.path.starts_with(&mount.path));
+        assert_eq!(mount.path.metadata().unwrap().dev(), item.metadata.dev());
+    }
+
+    #[test]
+    fn args_preview_length_capped() {
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.mut_paths().push(std::env::temp_dir().into());
+        proto.set_preview_length(u64::MAX);
+
+        let args = <Args as crate::request::Args>::from_proto(proto)
+            .unwrap();
+        assert_eq!(args.preview_len, Some(MAX_PREVIEW_LEN));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_fifo() {
+        use std::os::unix::ffi::OsStrExt as _;
+        use std::os::unix::fs::FileTypeExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("fifo");
+
+        let path_c = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .unwrap();
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
+
+        let metadata = handle_special_file(&path);
+        assert!(metadata.file_type().is_fifo());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_char_device() {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        let metadata = handle_special_file(Path::new("/dev/null"));
+        assert!(metadata.file_type().is_char_device());
+    }
+
+    /// Runs the action on the special file at `path` with the options that open
+    /// files and returns the reported metadata.
+    ///
+    /// Opening special files (e.g. a FIFO without a writer) can block forever,
+    /// so the action is run on a separate thread and has to finish in time.
+    #[cfg(target_family = "unix")]
+    fn handle_special_file(path: &Path) -> std::fs::Metadata {
+        let path = path.to_path_buf();
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let args = Args {
+                paths: vec![path],
+                batch: false,
+                max_depth: 0,
+                excludes: vec![],
+                alternate_root: None,
+                #[cfg(target_os = "linux")]
+                image_root: None,
+                collect_acls: true,
+                max_inline_len: None,
+                max_contents_total_len: None,
+                open_devices: false,
+                baseline: None,
+                symlink_chain_depth: 0,
+                include_fs_types: vec![],
+                exclude_fs_types: vec![],
+                #[cfg(target_os = "linux")]
+                process_fd: None,
+                detect_file_type: true,
+                hash_condition: None,
+                mode_condition: None,
+                preview_len: Some(MAX_PREVIEW_LEN),
+                content_id_type: None,
+                minimal: false,
+                resolve_owner_names: false,
+            };
+
+            let mut session = crate::session::FakeSession::new();
+            let result = handle(&mut session, args);
+            let item = session.replies::<Item>()
+                .next()
+                .map(|item| (item.metadata.clone(), item.preview.clone()));
+
+            sender.send((result.is_ok(), item)).unwrap();
+        });
+
+        let (is_ok, item) = receiver.recv_timeout(std::time::Duration::from_secs(10))
+            .expect("action blocked on a special file");
+        assert!(is_ok);
+
+        let (metadata, preview) = item.expect("no metadata reported");
+        // Special files are never read, so they cannot have a preview.
+        assert!(preview.is_none());
+
+        metadata
     }
 
     #[cfg(feature = "test-setfattr")]
@@ -326,8 +2190,30 @@ mod tests {
         };
 
         let args = Args {
-            path: tempfile.path().to_path_buf(),
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
             max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -359,8 +2245,30 @@ mod tests {
         };
 
         let args = Args {
-            path: tempfile.path().to_owned(),
+            paths: vec![tempfile.path().to_owned()],
+            batch: false,
             max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -376,80 +2284,371 @@ mod tests {
     }
 
     #[test]
-    fn handle_dir_max_depth_0() {
+    fn handle_dir_max_depth_0() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::File::create(tempdir.join("foo"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("bar"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let paths = session.replies::<Item>()
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+
+        assert!(paths.contains((&tempdir).into()));
+        assert!(!paths.contains(&tempdir.join("foo")));
+        assert!(!paths.contains(&tempdir.join("bar")));
+    }
+
+    #[test]
+    fn handle_dir_max_dir_entries() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        for i in 0..10 {
+            std::fs::File::create(tempdir.join(format!("file{i}")))
+                .unwrap();
+        }
+
+        let args = Args {
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let session_args = crate::args::Args {
+            max_dir_entries: 4,
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(session_args);
+        assert!(handle(&mut session, args).is_ok());
+
+        // The root itself and entries up to the limit.
+        assert_eq!(session.replies_of::<Item>().count(), 1 + 4);
+
+        let truncated_items = session.replies_of::<TruncatedDirItem>()
+            .collect::<Vec<_>>();
+        assert_eq!(truncated_items.len(), 1);
+        assert_eq!(truncated_items[0].path, tempdir);
+    }
+
+    #[test]
+    fn handle_dir_max_depth_1() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::File::create(tempdir.join("file1"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("file2"))
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("subdir"))
+            .unwrap();
+
+        std::fs::File::create(tempdir.join("subdir").join("file1"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("subdir").join("file2"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items_by_path = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        assert!(items_by_path.contains_key(&tempdir));
+        assert!(items_by_path[&tempdir].metadata.is_dir());
+
+        assert!(items_by_path.contains_key(&tempdir.join("file1")));
+        assert!(items_by_path[&tempdir.join("file1")].metadata.is_file());
+
+        assert!(items_by_path.contains_key(&tempdir.join("file2")));
+        assert!(items_by_path[&tempdir.join("file2")].metadata.is_file());
+
+        assert!(items_by_path.contains_key(&tempdir.join("subdir")));
+        assert!(items_by_path[&tempdir.join("subdir")].metadata.is_dir());
+
+        assert!(!items_by_path.contains_key(&tempdir.join("subdir").join("file1")));
+        assert!(!items_by_path.contains_key(&tempdir.join("subdir").join("file2")));
+    }
+
+    #[test]
+    fn handle_dir_excludes() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("foo"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("foo").join("file"))
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("bar"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("bar").join("file"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
+            max_depth: u32::MAX,
+            excludes: vec![crate::fs::Glob::new(tempdir.join("foo")).unwrap()],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let mut paths = session.replies::<Item>()
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            tempdir.clone(),
+            tempdir.join("bar"),
+            tempdir.join("bar").join("file"),
+        ]);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_dir_exclude_fs_types() {
         let tempdir = tempfile::tempdir()
             .unwrap();
         let tempdir = tempdir.path().canonicalize()
             .unwrap();
 
-        std::fs::File::create(tempdir.join("foo"))
+        std::fs::create_dir(tempdir.join("foo"))
             .unwrap();
-        std::fs::File::create(tempdir.join("bar"))
+        std::fs::File::create(tempdir.join("foo").join("file"))
+            .unwrap();
+
+        let metadata = tempdir.metadata()
             .unwrap();
+        // We exclude the filesystem the temporary directory lives on, so that
+        // none of its subdirectories is descended into.
+        let fs_type = crate::fs::Mounts::new().unwrap()
+            .get(&metadata).unwrap()
+            .fs_type.clone();
 
         let args = Args {
-            path: tempdir.to_path_buf(),
-            max_depth: 0,
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![fs_type],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, args).is_ok());
 
-        let paths = session.replies::<Item>()
+        let mut paths = session.replies::<Item>()
             .map(|item| item.path.clone())
             .collect::<Vec<_>>();
+        paths.sort();
 
-        assert!(paths.contains((&tempdir).into()));
-        assert!(!paths.contains(&tempdir.join("foo")));
-        assert!(!paths.contains(&tempdir.join("bar")));
+        assert_eq!(paths, vec![
+            tempdir.clone(),
+            tempdir.join("foo"),
+        ]);
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn handle_dir_max_depth_1() {
+    fn handle_process_fd_deleted() {
         let tempdir = tempfile::tempdir()
             .unwrap();
         let tempdir = tempdir.path().canonicalize()
             .unwrap();
 
-        std::fs::File::create(tempdir.join("file1"))
+        std::fs::write(tempdir.join("foo"), b"0123456789")
             .unwrap();
-        std::fs::File::create(tempdir.join("file2"))
+        let file = std::fs::File::open(tempdir.join("foo"))
             .unwrap();
-
-        std::fs::create_dir(tempdir.join("subdir"))
+        std::fs::remove_file(tempdir.join("foo"))
             .unwrap();
 
-        std::fs::File::create(tempdir.join("subdir").join("file1"))
-            .unwrap();
-        std::fs::File::create(tempdir.join("subdir").join("file2"))
-            .unwrap();
+        let process_fd = crate::fs::linux::ProcessFd {
+            pid: std::process::id(),
+            fd: std::os::fd::AsRawFd::as_raw_fd(&file) as u32,
+        };
 
         let args = Args {
-            path: tempdir.to_path_buf(),
-            max_depth: 1,
+            paths: vec![process_fd.path()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            process_fd: Some(process_fd),
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, args).is_ok());
 
-        let items_by_path = session.replies::<Item>()
-            .map(|item| (item.path.clone(), item))
-            .collect::<std::collections::HashMap<_, _>>();
-
-        assert!(items_by_path.contains_key(&tempdir));
-        assert!(items_by_path[&tempdir].metadata.is_dir());
+        assert_eq!(session.reply_count(), 1);
 
-        assert!(items_by_path.contains_key(&tempdir.join("file1")));
-        assert!(items_by_path[&tempdir.join("file1")].metadata.is_file());
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("foo"));
+        assert!(item.deleted);
+        assert!(item.metadata.is_file());
+        assert_eq!(item.metadata.len(), 10);
+    }
 
-        assert!(items_by_path.contains_key(&tempdir.join("file2")));
-        assert!(items_by_path[&tempdir.join("file2")].metadata.is_file());
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_proto_process_fd_with_path() {
+        use crate::request::Args as _;
 
-        assert!(items_by_path.contains_key(&tempdir.join("subdir")));
-        assert!(items_by_path[&tempdir.join("subdir")].metadata.is_dir());
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.mut_process_fd().set_pid(std::process::id());
+        proto.mut_process_fd().set_fd(0);
+        proto.mut_paths().push(PathBuf::from("/").into());
 
-        assert!(!items_by_path.contains_key(&tempdir.join("subdir").join("file1")));
-        assert!(!items_by_path.contains_key(&tempdir.join("subdir").join("file2")));
+        assert!(Args::from_proto(proto).is_err());
     }
 
     #[cfg(target_family = "unix")]
@@ -467,8 +2666,30 @@ mod tests {
             .unwrap();
 
         let args = Args {
-            path: tempdir.to_path_buf(),
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
             max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -521,8 +2742,30 @@ mod tests {
         };
 
         let args = Args {
-            path: tempdir.to_path_buf(),
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
             max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -580,8 +2823,30 @@ mod tests {
         };
 
         let args = Args {
-            path: tempdir.to_path_buf(),
+            paths: vec![tempdir.to_path_buf()],
+            batch: false,
             max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -606,6 +2871,303 @@ mod tests {
         assert_eq!(item_file2.ext_attrs[0].value, b"value2");
     }
 
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_alternate_root() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("etc"))
+            .unwrap();
+        std::fs::write(tempdir.join("etc").join("passwd"), b"root:x:0:0::/root:/bin/snap\n")
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/etc/passwd").into());
+        proto.set_alternate_root(tempdir.clone().into());
+
+        let args = Args::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("etc").join("passwd"));
+        assert_eq!(item.metadata.len(), 28);
+    }
+
+    #[test]
+    fn handle_batch() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"foo")
+            .unwrap();
+        std::fs::write(tempdir.join("bar"), b"barbaz")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![
+                tempdir.join("foo"),
+                tempdir.join("quux"),
+                PathBuf::from("relative"),
+                tempdir.join("bar"),
+            ],
+            batch: true,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 4);
+
+        let items = session.replies_of::<Item>()
+            .map(|item| (item.path_index, item.path.clone(), item.metadata.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![
+            (Some(0), tempdir.join("foo"), 3),
+            (Some(3), tempdir.join("bar"), 6),
+        ]);
+
+        let errors = session.replies_of::<ErrorItem>()
+            .map(|item| (item.path_index, item.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            (1, tempdir.join("quux")),
+            (2, PathBuf::from("relative")),
+        ]);
+    }
+
+    #[test]
+    fn handle_batch_max_depth() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("foo"))
+            .unwrap();
+        std::fs::write(tempdir.join("foo").join("bar"), b"")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.join("quux"), tempdir.join("foo")],
+            batch: true,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.replies_of::<Item>()
+            .map(|item| (item.path_index, item.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![
+            (Some(1), tempdir.join("foo")),
+            (Some(1), tempdir.join("foo").join("bar")),
+        ]);
+    }
+
+    #[test]
+    fn from_proto_batch() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.mut_paths().push(PathBuf::from("/foo").into());
+        proto.mut_paths().push(PathBuf::from("/bar").into());
+
+        let args = Args::from_proto(proto)
+            .unwrap();
+        assert!(args.batch);
+        assert_eq!(args.paths, vec![PathBuf::from("/foo"), PathBuf::from("/bar")]);
+    }
+
+    #[test]
+    fn from_proto_batch_with_path() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/foo").into());
+        proto.mut_paths().push(PathBuf::from("/bar").into());
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[test]
+    fn from_proto_alternate_root_parent_dir() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(tempdir.path().join("..").join("..").join("etc").into());
+        proto.set_alternate_root(tempdir.path().to_path_buf().into());
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_alternate_root_symlink_escape() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::os::unix::fs::symlink("/etc", tempdir.path().join("etc"))
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/etc/passwd").into());
+        proto.set_alternate_root(tempdir.path().to_path_buf().into());
+
+        let args = Args::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_image_root() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        // Absolute symlink within the image should be resolved within the image
+        // and not against the filesystem of the machine.
+        std::fs::create_dir_all(tempdir.join("usr").join("etc"))
+            .unwrap();
+        std::fs::write(tempdir.join("usr").join("etc").join("passwd"), b"root:x:0:0::/root:/bin/img\n")
+            .unwrap();
+        std::os::unix::fs::symlink("/usr/etc", tempdir.join("etc"))
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/etc/passwd").into());
+        proto.set_image_root(tempdir.clone().into());
+
+        let args = Args::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("usr").join("etc").join("passwd"));
+        assert_eq!(item.metadata.len(), 27);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_image_root_parent_escape() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/../../etc/passwd").into());
+        proto.set_image_root(tempdir.path().to_path_buf().into());
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_image_root_symlink_escape() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::os::unix::fs::symlink("../../../../../../etc", tempdir.path().join("etc"))
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/etc/passwd").into());
+        proto.set_image_root(tempdir.path().to_path_buf().into());
+
+        let args = Args::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn args_from_proto_alternate_and_image_root() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(PathBuf::from("/etc/passwd").into());
+        proto.set_alternate_root(PathBuf::from("/mnt/snap").into());
+        proto.set_image_root(PathBuf::from("/mnt/image").into());
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
     macro_rules! path {
         ($root:expr) => {{
             ::std::path::PathBuf::from($root)
@@ -723,4 +3285,159 @@ mod tests {
             path!(tempdir, "dir", "file.l")
         }
     }
+
+    #[test]
+    fn args_minimal_overrides_options() {
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.mut_paths().push(std::env::temp_dir().into());
+        proto.set_detect_file_type(true);
+        proto.set_preview_length(1024);
+        proto.set_collect_contents(true);
+        proto.set_collect_acls(true);
+        proto.set_open_devices(true);
+        proto.set_symlink_chain_depth(8);
+        proto.set_minimal(true);
+
+        let args = <Args as crate::request::Args>::from_proto(proto)
+            .unwrap();
+        assert!(args.minimal);
+        assert!(!args.detect_file_type);
+        assert_eq!(args.preview_len, None);
+        assert_eq!(args.max_inline_len, None);
+        assert!(!args.collect_acls);
+        assert!(!args.open_devices);
+        assert_eq!(args.symlink_chain_depth, 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_minimal() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"#!/bin/sh\n")
+            .unwrap();
+        std::os::unix::fs::symlink(tempdir.join("foo"), tempdir.join("bar"))
+            .unwrap();
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(tempdir.clone().into());
+        proto.set_max_depth(1);
+        proto.set_detect_file_type(true);
+        proto.set_preview_length(1024);
+        proto.set_collect_contents(true);
+        proto.set_collect_acls(true);
+        proto.set_symlink_chain_depth(8);
+        proto.set_minimal(true);
+
+        let args = <Args as crate::request::Args>::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 3);
+
+        for item in session.replies::<Item>() {
+            assert!(item.minimal);
+            assert!(item.ext_attrs.is_empty());
+            assert!(item.inode_flags.is_none());
+            assert!(item.statx.is_none());
+            assert!(item.mount.is_none());
+            assert!(item.acls.is_none());
+            assert!(item.symlink_chain.is_none());
+            assert!(item.file_type.is_none());
+            assert!(item.contents.is_none());
+            assert!(item.preview.is_none());
+        }
+    }
+
+    #[cfg(feature = "test-setfattr")]
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_minimal_ext_attrs() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        assert! {
+            std::process::Command::new("setfattr")
+                .arg("--no-dereference")
+                .arg("--name").arg("user.foo")
+                .arg("--value").arg("bar")
+                .arg(tempfile.path().as_os_str())
+                .status().unwrap()
+                .success()
+        };
+
+        let mut proto = rrg_proto::get_file_metadata::Args::default();
+        proto.set_path(tempfile.path().to_path_buf().into());
+        proto.set_minimal(true);
+
+        let args = <Args as crate::request::Args>::from_proto(proto)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(item.ext_attrs.is_empty());
+        assert!(item.inode_flags.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn into_proto_minimal() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let item = Item {
+            path: tempfile.path().to_path_buf(),
+            metadata: tempfile.path().symlink_metadata().unwrap(),
+            ext_attrs: vec![ospect::fs::ExtAttr {
+                name: "user.foo".into(),
+                value: b"bar".to_vec(),
+            }],
+            inode_flags: Some(crate::fs::linux::InodeFlags(0x10)),
+            symlink: None,
+            symlink_chain: None,
+            deleted: false,
+            mount: None,
+            statx: None,
+            block_device_size: None,
+            acls: None,
+            capabilities: None,
+            selinux_context: Some(String::from("foo_u:bar_r:baz_t:s0")),
+            smack_label: None,
+            hash_match: Some(HashMatch::Sha256([0; 32])),
+            baseline_change: None,
+            file_type: Some("script"),
+            contents: None,
+            preview: Some(b"#!".to_vec()),
+            content_id: None,
+            path_index: Some(42),
+            #[cfg(target_family = "unix")]
+            owner_names: None,
+            minimal: true,
+        };
+
+        let proto = crate::response::Item::into_proto(item);
+        assert_eq!(proto.path_index(), 42);
+        assert!(proto.has_hash_match());
+        assert!(proto.metadata().modification_time.is_some());
+        assert_eq!(proto.metadata().type_(), rrg_proto::fs::file_metadata::Type::FILE);
+
+        assert!(proto.ext_attrs.is_empty());
+        assert!(!proto.has_linux_inode_flags());
+        assert!(!proto.has_linux_selinux_context());
+        assert!(proto.file_type().is_empty());
+        assert!(!proto.has_preview());
+        assert!(proto.metadata().access_time.is_none());
+        assert!(proto.metadata().creation_time.is_none());
+        assert!(!proto.metadata().has_unix_mode());
+        assert!(!proto.metadata().has_unix_uid());
+    }
 }
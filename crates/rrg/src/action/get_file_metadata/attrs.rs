@@ -0,0 +1,783 @@
+// Copyright 2023 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Platform-specific attributes of files (ACLs, capabilities, inode flags, etc.).
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use std::path::Path;
+
+/// Outcome of an attempt to open a file for reading.
+#[cfg(target_os = "windows")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ReadAccess {
+    /// The file could be opened for reading.
+    Readable,
+    /// The file is opened by another process that does not share it.
+    SharingViolation,
+    /// The agent lacks permissions to read the file.
+    AccessDenied,
+    /// The file could not be opened for some other reason.
+    Other,
+}
+
+/// POSIX access control lists of a file.
+#[cfg(target_os = "linux")]
+pub(super) struct Acls {
+    /// Entries of the access ACL.
+    pub(super) access: Vec<crate::fs::linux::AclEntry>,
+    /// Entries of the default ACL.
+    pub(super) default: Vec<crate::fs::linux::AclEntry>,
+}
+
+/// Collects `statx` metadata of the file using the given `statx` function.
+///
+/// Kernels older than 4.11 do not support `statx` at all, in which case we just
+/// return nothing and rely on the standard metadata. Other errors are logged,
+/// as they are not crucial either.
+#[cfg(target_os = "linux")]
+pub(super) fn linux_statx<F>(path: &Path, statx: F) -> Option<ospect::fs::linux::Statx>
+where
+    F: FnOnce(&Path) -> std::io::Result<ospect::fs::linux::Statx>,
+{
+    match statx(path) {
+        Ok(statx) => Some(statx),
+        Err(error) if error.raw_os_error() == Some(libc::ENOSYS) => None,
+        Err(error) => {
+            log::error!("failed to statx '{}': {error}", path.display());
+            None
+        }
+    }
+}
+
+/// Verifies whether the file at `path` can be opened for reading.
+///
+/// The file is opened with all the sharing modes enabled, so the probe does not
+/// prevent other processes from using the file in any way. Only regular files
+/// are probed, for other kinds of files nothing is returned.
+#[cfg(target_os = "windows")]
+pub(super) fn probe_read_access(path: &Path, metadata: &std::fs::Metadata) -> Option<ReadAccess> {
+    use std::os::windows::fs::OpenOptionsExt as _;
+
+    // https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-createfilew
+    const FILE_SHARE_READ: u32 = 0x00000001;
+    const FILE_SHARE_WRITE: u32 = 0x00000002;
+    const FILE_SHARE_DELETE: u32 = 0x00000004;
+
+    // https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+        .open(path);
+
+    match file {
+        Ok(_) => Some(ReadAccess::Readable),
+        Err(error) if error.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+            Some(ReadAccess::SharingViolation)
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::PermissionDenied => {
+            Some(ReadAccess::AccessDenied)
+        }
+        Err(error) => {
+            log::warn!("failed to open '{}' for reading: {error}", path.display());
+            Some(ReadAccess::Other)
+        }
+    }
+}
+
+/// Collects POSIX access control lists of the file at `path`.
+///
+/// ACLs are not crucial, so failures to collect them are only logged.
+#[cfg(target_os = "linux")]
+pub(super) fn collect_acls(path: &Path) -> Option<Acls> {
+    let acls = || -> std::io::Result<Acls> {
+        Ok(Acls {
+            access: crate::fs::linux::access_acl(path)?,
+            default: crate::fs::linux::default_acl(path)?,
+        })
+    }();
+
+    match acls {
+        Ok(acls) => Some(acls),
+        Err(error) => {
+            log::error!("failed to collect ACLs of '{}': {error}", path.display());
+            None
+        }
+    }
+}
+
+/// Decodes capabilities of the file at `path` from its extended attributes.
+///
+/// Files without capabilities yield nothing. Malformed values are logged, as
+/// they should not prevent reporting the rest of the metadata.
+#[cfg(target_os = "linux")]
+pub(super) fn file_capabilities(
+    path: &Path,
+    ext_attrs: &[ospect::fs::ExtAttr],
+) -> Option<crate::fs::linux::FileCapabilities> {
+    let ext_attr = ext_attrs.iter()
+        .find(|ext_attr| ext_attr.name == crate::fs::linux::CAPABILITY_EXT_ATTR)?;
+
+    match crate::fs::linux::parse_file_capabilities(&ext_attr.value) {
+        Ok(capabilities) => Some(capabilities),
+        Err(error) => {
+            log::error!("failed to decode capabilities of '{}': {error}", path.display());
+            None
+        }
+    }
+}
+
+/// Returns the security label stored in the given extended attribute (if any).
+#[cfg(target_os = "linux")]
+pub(super) fn security_label(ext_attrs: &[ospect::fs::ExtAttr], name: &str) -> Option<String> {
+    ext_attrs.iter()
+        .find(|ext_attr| ext_attr.name == name)
+        .map(|ext_attr| crate::fs::linux::parse_security_label(&ext_attr.value))
+}
+
+/// Converts an ACL entry into its protobuf representation.
+#[cfg(target_os = "linux")]
+pub(super) fn acl_entry_into_proto(
+    entry: crate::fs::linux::AclEntry,
+) -> rrg_proto::get_file_metadata::LinuxAclEntry {
+    use crate::fs::linux::AclTag;
+    use rrg_proto::get_file_metadata::LinuxAclTag;
+
+    let tag = match entry.tag {
+        AclTag::UserObj => LinuxAclTag::USER_OBJ,
+        AclTag::User => LinuxAclTag::USER,
+        AclTag::GroupObj => LinuxAclTag::GROUP_OBJ,
+        AclTag::Group => LinuxAclTag::GROUP,
+        AclTag::Mask => LinuxAclTag::MASK,
+        AclTag::Other => LinuxAclTag::OTHER,
+    };
+
+    let mut proto = rrg_proto::get_file_metadata::LinuxAclEntry::default();
+    proto.set_tag(tag);
+    if let Some(qualifier) = entry.qualifier {
+        proto.set_qualifier(qualifier);
+    }
+    proto.set_read(entry.perms & 0o4 != 0);
+    proto.set_write(entry.perms & 0o2 != 0);
+    proto.set_execute(entry.perms & 0o1 != 0);
+
+    proto
+}
+
+/// Returns inode flags of the file at `path` (if it has any).
+///
+/// Querying flags requires opening the file, which for special files (e.g.
+/// FIFOs) might block or have side effects, so only regular files and
+/// directories are considered. Filesystems without support for inode flags are
+/// not an error and yield `None`.
+#[cfg(target_os = "linux")]
+pub(super) fn query_inode_flags(path: &Path, metadata: &std::fs::Metadata) -> Option<crate::fs::linux::InodeFlags> {
+    if !metadata.is_file() && !metadata.is_dir() {
+        return None;
+    }
+
+    match ospect::fs::linux::flags(path) {
+        Ok(flags) => Some(crate::fs::linux::InodeFlags(flags)),
+        Err(error) if error.raw_os_error() == Some(libc::ENOTTY) => None,
+        Err(error) if error.kind() == std::io::ErrorKind::Unsupported => None,
+        Err(error) => {
+            log::warn! {
+                "failed to get inode flags of '{}': {error}",
+                path.display()
+            };
+
+            None
+        }
+    }
+}
+
+/// Returns the capacity of the block device at `path` (if it is one).
+///
+/// Block devices report zero as their size in the metadata, so we query their
+/// real capacity. Doing so requires opening the device (which is usually a
+/// privileged operation), so in case of failure we just log the error and keep
+/// the size from the metadata.
+#[cfg(target_os = "linux")]
+pub(super) fn query_block_device_size(path: &Path, metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::FileTypeExt as _;
+
+    if !metadata.file_type().is_block_device() {
+        return None;
+    }
+
+    let size = std::fs::File::open(path)
+        .and_then(|file| ospect::fs::linux::block_device_size(&file));
+
+    match size {
+        Ok(size) => Some(size),
+        Err(error) => {
+            log::warn! {
+                "failed to get size of block device '{}': {error}",
+                path.display()
+            };
+
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::{handle, Args, Item};
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn handle_regular_file_windows_read_access() {
+        use std::os::windows::fs::OpenOptionsExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::File::create(tempdir.join("readable"))
+            .unwrap();
+        std::fs::File::create(tempdir.join("locked"))
+            .unwrap();
+
+        // We keep the file open without sharing it with anyone for the whole
+        // duration of the action.
+        let _locked = std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(0)
+            .open(tempdir.join("locked"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![
+                tempdir.join("readable"),
+                tempdir.join("locked"),
+            ],
+            batch: true,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item_readable = session.reply::<Item>(0);
+        assert_eq!(item_readable.read_access, Some(ReadAccess::Readable));
+
+        let item_locked = session.reply::<Item>(1);
+        assert_eq!(item_locked.read_access, Some(ReadAccess::SharingViolation));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_statx() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+
+        // All supported kernels have `statx` and the standard library uses it
+        // for the creation time as well, so the two should be consistent.
+        let statx = item.statx.unwrap();
+        assert_eq!(statx.birth_time, item.metadata.created().ok());
+        assert!(statx.mount_id.is_some());
+        assert_ne!(statx.is_immutable(), Some(true));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_inode_flags_append_only() {
+        use std::os::unix::io::AsRawFd as _;
+
+        // https://elixir.bootlin.com/linux/v6.8/source/include/uapi/linux/fs.h#L296
+        const FS_APPEND_FL: libc::c_int = 0x00000020;
+
+        /// Sets inode flags of the given file, returning whether it worked.
+        fn set_flags(file: &std::fs::File, flags: libc::c_int) -> bool {
+            // SAFETY: The file descriptor is valid for the lifetime of `file`
+            // and we pass a pointer to a valid integer, as the ioctl expects.
+            let code = unsafe {
+                libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags)
+            };
+            code == 0
+        }
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        // Setting the append-only flag requires the `CAP_LINUX_IMMUTABLE`
+        // capability and a supporting filesystem, without them there is
+        // nothing that we can test.
+        if !set_flags(tempfile.as_file(), FS_APPEND_FL) {
+            return;
+        }
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        let result = handle(&mut session, args);
+
+        // Append-only files cannot be removed, so we have to clear the flag
+        // before asserting anything (to not leave the file behind).
+        assert!(set_flags(tempfile.as_file(), 0));
+        assert!(result.is_ok());
+
+        let item = session.reply::<Item>(0);
+
+        let inode_flags = item.inode_flags.unwrap();
+        assert!(inode_flags.is_append_only());
+        assert!(!inode_flags.is_immutable());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_acls_none() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        for collect_acls in [false, true] {
+            let args = Args {
+                paths: vec![tempfile.path().to_path_buf()],
+                batch: false,
+                max_depth: 0,
+                excludes: vec![],
+                alternate_root: None,
+                #[cfg(target_os = "linux")]
+                image_root: None,
+                collect_acls,
+                max_inline_len: None,
+                max_contents_total_len: None,
+                open_devices: false,
+                baseline: None,
+                symlink_chain_depth: 0,
+                include_fs_types: vec![],
+                exclude_fs_types: vec![],
+                #[cfg(target_os = "linux")]
+                process_fd: None,
+                detect_file_type: false,
+                hash_condition: None,
+                mode_condition: None,
+                preview_len: None,
+                content_id_type: None,
+                minimal: false,
+                resolve_owner_names: false,
+            };
+
+            assert!(handle(&mut session, args).is_ok());
+        }
+
+        assert_eq!(session.reply_count(), 2);
+        assert!(session.reply::<Item>(0).acls.is_none());
+
+        // Files without extended ACLs should yield empty lists, not errors.
+        let acls = session.reply::<Item>(1).acls.as_ref().unwrap();
+        assert!(acls.access.is_empty());
+        assert!(acls.default.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_file_capabilities() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        // Equivalent of `setcap cap_net_raw+ep <path>`.
+        let value = [
+            0x01, 0x00, 0x00, 0x02,
+            0x00, 0x20, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let path = std::ffi::CString::new(tempfile.path().as_os_str().as_bytes())
+            .unwrap();
+        let name = std::ffi::CString::new(crate::fs::linux::CAPABILITY_EXT_ATTR)
+            .unwrap();
+
+        // SAFETY: Path and name are valid null-terminated strings and we pass
+        // a valid buffer along with its length.
+        let code = unsafe {
+            libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+        };
+        if code != 0 {
+            // Setting capabilities requires elevated privileges, otherwise
+            // there is nothing to test.
+            return;
+        }
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let capabilities = session.reply::<Item>(0).capabilities.unwrap();
+        assert_eq!(capabilities.permitted, 1 << 13);
+        assert!(capabilities.effective);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_file_capabilities_none() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.capabilities.is_none());
+        // On SELinux hosts all files (including temporary ones) have a context
+        // so we can only verify that it is not empty.
+        assert!(item.selinux_context.as_ref().is_none_or(|label| !label.is_empty()));
+        assert!(item.smack_label.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_security_labels() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let path = std::ffi::CString::new(tempfile.path().as_os_str().as_bytes())
+            .unwrap();
+
+        for (name, value) in [
+            (crate::fs::linux::SELINUX_EXT_ATTR, &b"system_u:object_r:tmp_t:s0\0"[..]),
+            (crate::fs::linux::SMACK_EXT_ATTR, &b"_"[..]),
+        ] {
+            let name = std::ffi::CString::new(name)
+                .unwrap();
+
+            // SAFETY: Path and name are valid null-terminated strings and we
+            // pass a valid buffer along with its length.
+            let code = unsafe {
+                libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+            };
+            if code != 0 {
+                // Setting security labels requires elevated privileges and the
+                // active security module can reject them, in which case there
+                // is nothing to test.
+                return;
+            }
+        }
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.selinux_context.as_deref(), Some("system_u:object_r:tmp_t:s0"));
+        assert_eq!(item.smack_label.as_deref(), Some("_"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn acl_entry_into_proto_named_group() {
+        use crate::fs::linux::{AclEntry, AclTag};
+        use rrg_proto::get_file_metadata::LinuxAclTag;
+
+        let proto = acl_entry_into_proto(AclEntry {
+            tag: AclTag::Group,
+            qualifier: Some(42),
+            perms: 0o5,
+        });
+
+        assert_eq!(proto.tag(), LinuxAclTag::GROUP);
+        assert_eq!(proto.qualifier(), 42);
+        assert!(proto.read());
+        assert!(!proto.write());
+        assert!(proto.execute());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn query_block_device_size_regular_file() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let metadata = tempfile.path().metadata()
+            .unwrap();
+
+        assert_eq!(query_block_device_size(tempfile.path(), &metadata), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_block_device() {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        // We need a block device that we can open which is possible only with
+        // elevated privileges, otherwise there is nothing to test.
+        let path = match std::fs::read_dir("/dev") {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .find(|entry| {
+                    entry.file_type().is_ok_and(|file_type| file_type.is_block_device()) &&
+                    std::fs::File::open(entry.path()).is_ok()
+                })
+                .map(|entry| entry.path()),
+            Err(_) => None,
+        };
+        let path = match path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let args = Args {
+            paths: vec![path.clone()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: true,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        let file = std::fs::File::open(&path)
+            .unwrap();
+        assert_eq! {
+            item.block_device_size,
+            Some(ospect::fs::linux::block_device_size(&file).unwrap())
+        };
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn statx_enosys_fallback() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let statx = linux_statx(tempfile.path(), |_| {
+            Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        });
+        assert!(statx.is_none());
+
+        let item = Item {
+            path: tempfile.path().to_path_buf(),
+            metadata: tempfile.path().symlink_metadata().unwrap(),
+            ext_attrs: vec![],
+            inode_flags: None,
+            symlink: None,
+            symlink_chain: None,
+            deleted: false,
+            mount: None,
+            statx,
+            block_device_size: None,
+            acls: None,
+            capabilities: None,
+            selinux_context: None,
+            smack_label: None,
+            hash_match: None,
+            baseline_change: None,
+            file_type: None,
+            contents: None,
+            preview: None,
+            content_id: None,
+            path_index: None,
+            #[cfg(target_family = "unix")]
+            owner_names: None,
+            minimal: false,
+        };
+
+        let proto = crate::response::Item::into_proto(item);
+        assert!(!proto.has_linux_statx());
+        assert_eq!(proto.metadata().size(), 0);
+        assert_eq! {
+            proto.metadata().creation_time.is_some(),
+            tempfile.path().symlink_metadata().unwrap().created().is_ok()
+        };
+    }
+}
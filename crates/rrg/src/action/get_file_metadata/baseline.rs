@@ -0,0 +1,375 @@
+// Copyright 2023 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Comparison of files against a baseline of previously collected metadata.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::{parse_time, Args};
+use super::hash::{hash_file, DigestLenError};
+
+/// Expected state of files to compare against.
+pub(super) struct Baseline {
+    /// Expected state of files keyed by their paths.
+    entries: HashMap<PathBuf, BaselineEntry>,
+    /// Whether to skip hashing files with size and mtime matching the baseline.
+    fast_path: bool,
+}
+
+/// Expected state of a single file.
+struct BaselineEntry {
+    /// SHA-256 digest of the file contents.
+    sha256: [u8; 32],
+    /// Size of the file (if known).
+    size: Option<u64>,
+    /// Time at which the file was last modified (if known).
+    mtime: Option<std::time::SystemTime>,
+}
+
+/// Way in which a file differs from the baseline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum BaselineChange {
+    /// The file is not in the baseline.
+    Added,
+    /// The contents of the file differ from the baseline.
+    Modified,
+    /// The file is in the baseline but no longer exists.
+    Removed,
+}
+
+/// Result of the `get_file_metadata` action for a file removed since the baseline.
+struct RemovedItem {
+    /// Path to the file of the baseline that no longer exists.
+    path: PathBuf,
+    /// Index of the requested path the file corresponds to (in batch mode).
+    path_index: Option<u32>,
+}
+
+impl Baseline {
+
+    /// Checks how the regular file differs from the baseline.
+    ///
+    /// With the fast path enabled, files with size and modification time equal
+    /// to the ones in the baseline are assumed to be unchanged and are not read
+    /// at all. Otherwise, the file is hashed (unless its digest is cached).
+    pub(super) fn check(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        cache: &mut crate::hash_cache::HashCache,
+    ) -> std::io::Result<Option<BaselineChange>> {
+        let entry = match self.entries.get(path) {
+            Some(entry) => entry,
+            None => return Ok(Some(BaselineChange::Added)),
+        };
+
+        if self.fast_path && entry.matches_metadata(metadata) {
+            return Ok(None);
+        }
+
+        let mut digests = cache.get(path, metadata)
+            .cloned()
+            .unwrap_or_default();
+
+        let sha256 = match digests.sha256 {
+            Some(sha256) => sha256,
+            None => {
+                digests.merge(hash_file(path, false, false, true, false)?);
+                cache.insert(path, metadata, digests.clone());
+
+                digests.sha256
+                    .expect("no SHA-256 digest")
+            }
+        };
+
+        if sha256 == entry.sha256 {
+            Ok(None)
+        } else {
+            Ok(Some(BaselineChange::Modified))
+        }
+    }
+}
+
+impl BaselineEntry {
+
+    /// Checks whether the file metadata matches the size and time of the entry.
+    ///
+    /// Entries without the size or time never match.
+    fn matches_metadata(&self, metadata: &std::fs::Metadata) -> bool {
+        let (Some(size), Some(mtime)) = (self.size, self.mtime) else {
+            return false;
+        };
+
+        metadata.len() == size && metadata.modified().ok() == Some(mtime)
+    }
+}
+
+/// Reports files of the baseline that were not visited and no longer exist.
+///
+/// Files that exist but were not visited (e.g. because they are beyond the
+/// depth limit) are not reported at all.
+pub(super) fn report_removed<S>(
+    session: &mut S,
+    args: &Args,
+    baseline: &Baseline,
+    visited: &HashSet<PathBuf>,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut removed = baseline.entries.keys()
+        .filter(|path| !visited.contains(*path))
+        .filter(|path| match path.symlink_metadata() {
+            Ok(_) => false,
+            Err(error) => error.kind() == std::io::ErrorKind::NotFound,
+        })
+        .collect::<Vec<_>>();
+    removed.sort();
+
+    for path in removed {
+        let path_index = if args.batch {
+            (0..).zip(&args.paths)
+                .find(|(_, root)| path.starts_with(root))
+                .map(|(path_index, _)| path_index)
+        } else {
+            None
+        };
+
+        session.reply(RemovedItem {
+            path: path.clone(),
+            path_index,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Parses the baseline from its protobuf representation.
+///
+/// Paths of the baseline are passed through `reroot`, so that they correspond
+/// to the paths of the request.
+pub(super) fn parse_baseline<F>(
+    mut proto: rrg_proto::get_file_metadata::Baseline,
+    reroot: F,
+) -> Result<Baseline, crate::request::ParseArgsError>
+where
+    F: Fn(PathBuf) -> std::io::Result<PathBuf>,
+{
+    use crate::request::ParseArgsError;
+
+    let entries = proto.take_entries().into_iter()
+        .map(|mut proto| {
+            let path = PathBuf::try_from(proto.take_path())
+                .map_err(|error| ParseArgsError::invalid_field("baseline.entries.path", error))?;
+            let path = reroot(path)
+                .map_err(|error| ParseArgsError::invalid_field("baseline.entries.path", error))?;
+
+            let sha256 = <[u8; 32]>::try_from(proto.sha256())
+                .map_err(|_| ParseArgsError::invalid_field("baseline.entries.sha256", DigestLenError {
+                    len: proto.sha256().len(),
+                    expected_len: 32,
+                }))?;
+
+            let entry = BaselineEntry {
+                sha256,
+                size: proto.size,
+                mtime: parse_time("baseline.entries.modification_time", proto.modification_time)?,
+            };
+
+            Ok((path, entry))
+        })
+        .collect::<Result<_, ParseArgsError>>()?;
+
+    Ok(Baseline {
+        entries,
+        fast_path: proto.fast_path(),
+    })
+}
+
+impl crate::response::Item for RemovedItem {
+
+    type Proto = rrg_proto::get_file_metadata::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = rrg_proto::get_file_metadata::Result::default();
+        proto.set_path(self.path.into());
+        if let Some(path_index) = self.path_index {
+            proto.set_path_index(path_index);
+        }
+        proto.set_baseline_change(BaselineChange::Removed.into());
+
+        proto
+    }
+}
+
+impl From<BaselineChange> for rrg_proto::get_file_metadata::BaselineChange {
+
+    fn from(change: BaselineChange) -> rrg_proto::get_file_metadata::BaselineChange {
+        use rrg_proto::get_file_metadata::BaselineChange as Proto;
+
+        match change {
+            BaselineChange::Added => Proto::ADDED,
+            BaselineChange::Modified => Proto::MODIFIED,
+            BaselineChange::Removed => Proto::REMOVED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::{handle, Args, Item};
+
+    #[test]
+    fn handle_baseline() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("root"))
+            .unwrap();
+        std::fs::write(tempdir.join("root").join("unchanged"), b"foo")
+            .unwrap();
+        std::fs::write(tempdir.join("root").join("modified"), b"bar (modified)")
+            .unwrap();
+        std::fs::write(tempdir.join("root").join("added"), b"baz")
+            .unwrap();
+
+        let entry = |contents: &[u8]| BaselineEntry {
+            sha256: sha2::Sha256::digest(contents).into(),
+            size: None,
+            mtime: None,
+        };
+
+        let baseline = Baseline {
+            entries: [
+                (tempdir.join("root").join("unchanged"), entry(b"foo")),
+                (tempdir.join("root").join("modified"), entry(b"bar")),
+                (tempdir.join("root").join("removed"), entry(b"quux")),
+            ].into(),
+            fast_path: false,
+        };
+
+        let args = Args {
+            paths: vec![tempdir.join("root")],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: Some(baseline),
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            temp_dir: tempdir.clone(),
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 3);
+
+        let mut changes = session.replies_of::<Item>()
+            .map(|item| (item.path.clone(), item.baseline_change))
+            .collect::<Vec<_>>();
+        changes.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+
+        assert_eq!(changes, vec![
+            (tempdir.join("root").join("added"), Some(BaselineChange::Added)),
+            (tempdir.join("root").join("modified"), Some(BaselineChange::Modified)),
+        ]);
+
+        let removed = session.replies_of::<RemovedItem>()
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(removed, vec![tempdir.join("root").join("removed")]);
+    }
+
+    #[test]
+    fn handle_baseline_fast_path() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let path = tempdir.join("foo");
+        std::fs::write(&path, b"bar")
+            .unwrap();
+
+        let metadata = path.metadata()
+            .unwrap();
+
+        // The digest in the baseline does not match, but size and time do, so
+        // the file is assumed to be unchanged with the fast path enabled.
+        let args = |fast_path| Args {
+            paths: vec![path.clone()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: Some(Baseline {
+                entries: [(path.clone(), BaselineEntry {
+                    sha256: sha2::Sha256::digest(b"baz").into(),
+                    size: Some(metadata.len()),
+                    mtime: Some(metadata.modified().unwrap()),
+                })].into(),
+                fast_path,
+            }),
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            temp_dir: tempdir.clone(),
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args(true)).is_ok());
+        assert_eq!(session.reply_count(), 0);
+
+        assert!(handle(&mut session, args(false)).is_ok());
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).baseline_change, Some(BaselineChange::Modified));
+    }
+}
@@ -0,0 +1,539 @@
+// Copyright 2023 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of contents of files and detection of their types.
+
+use std::path::Path;
+
+/// Contents of a file collected along with its metadata.
+pub(super) enum Contents {
+    /// Contents small enough to be included in the result directly.
+    Inline(Vec<u8>),
+    /// Reference to contents sent to the blob sink.
+    Ref(crate::session::FileRef),
+    /// Contents not collected as the request collected enough of them already.
+    BudgetExceeded,
+}
+
+/// Budget of file contents that a single request can still collect.
+pub(super) struct ContentsBudget {
+    /// Number of bytes that can still be collected (unlimited if unset).
+    remaining: Option<u64>,
+    /// Whether some contents were already not collected due to the budget.
+    exceeded: bool,
+}
+
+impl ContentsBudget {
+
+    /// Creates a new budget allowing to collect `len` bytes (if limited).
+    pub(super) fn new(len: Option<u64>) -> ContentsBudget {
+        ContentsBudget {
+            remaining: len,
+            exceeded: false,
+        }
+    }
+
+    /// Attempts to spend `len` bytes of the budget.
+    ///
+    /// Once an attempt fails, all subsequent ones fail as well, so that only
+    /// the files visited first have their contents collected.
+    fn spend(&mut self, len: u64) -> bool {
+        if self.exceeded {
+            return false;
+        }
+
+        match self.remaining {
+            Some(remaining) if remaining < len => {
+                self.exceeded = true;
+                false
+            }
+            Some(remaining) => {
+                self.remaining = Some(remaining - len);
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+/// Default maximum size of files to inline the contents of.
+pub(super) const DEFAULT_MAX_INLINE_LEN: u64 = 64 * 1024; // 64 KiB.
+
+/// Length of the file prefix read for detecting the type of the file.
+const MAGIC_LEN: usize = 16;
+
+/// Maximum length of previews of contents of files.
+pub(super) const MAX_PREVIEW_LEN: u64 = 4 * 1024; // 4 KiB.
+
+/// Known magic byte sequences and names of file types they correspond to.
+const MAGICS: &[(&[u8], &str)] = &[
+    (b"\x7fELF", "elf"),
+    (b"MZ", "pe"),
+    (b"\xfe\xed\xfa\xce", "mach-o"),
+    (b"\xfe\xed\xfa\xcf", "mach-o"),
+    (b"\xce\xfa\xed\xfe", "mach-o"),
+    (b"\xcf\xfa\xed\xfe", "mach-o"),
+    (b"#!", "script"),
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpeg"),
+    (b"GIF87a", "gif"),
+    (b"GIF89a", "gif"),
+    (b"%PDF-", "pdf"),
+    (b"\x1f\x8b", "gzip"),
+    (b"PK\x03\x04", "zip"),
+];
+
+/// Detects the type of the regular file at `path` based on its magic bytes.
+///
+/// Detection is done using a single small read from the beginning of the file.
+/// Failures are only logged, as they should not prevent reporting the rest of
+/// the metadata.
+pub(super) fn detect_file_type(path: &Path, metadata: &std::fs::Metadata) -> Option<&'static str> {
+    use std::io::Read as _;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let mut buf = [0; MAGIC_LEN];
+    let len = crate::fs::open_regular_file(path)
+        .and_then(|mut file| file.read(&mut buf[..]));
+
+    match len {
+        Ok(len) => Some(file_type(&buf[..len])),
+        Err(error) => {
+            log::warn! {
+                "failed to read magic bytes of '{}': {error}",
+                path.display()
+            };
+
+            None
+        }
+    }
+}
+
+/// Collects contents of the regular file at `path` (inline or as a reference).
+///
+/// Files not bigger than `max_inline_len` have their contents inlined, bigger
+/// ones are sent to the blob sink through the `blob_store`. Files that do not
+/// fit in the `budget` do not have their contents collected at all. Failures
+/// to read the file are only logged, as they should not prevent reporting the
+/// rest of the metadata.
+pub(super) fn collect_contents<S>(
+    session: &mut S,
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    max_inline_len: u64,
+    blob_store: &mut crate::session::BlobStore,
+    budget: &mut ContentsBudget,
+) -> crate::session::Result<Option<Contents>>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Read as _, Seek as _};
+
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+
+    if !budget.spend(metadata.len()) {
+        return Ok(Some(Contents::BudgetExceeded));
+    }
+
+    let result = || -> std::io::Result<Result<Vec<u8>, std::fs::File>> {
+        let mut file = crate::fs::open_regular_file(path)?;
+
+        // The file might have grown since we got its metadata, so we read one
+        // byte more than the limit to detect that.
+        let mut contents = Vec::new();
+        (&mut file).take(max_inline_len.saturating_add(1)).read_to_end(&mut contents)?;
+
+        if contents.len() as u64 <= max_inline_len {
+            return Ok(Ok(contents));
+        }
+
+        file.rewind()?;
+        Ok(Err(file))
+    }();
+
+    let file = match result {
+        Ok(Ok(contents)) => return Ok(Some(Contents::Inline(contents))),
+        Ok(Err(file)) => file,
+        Err(error) => {
+            log::warn!("failed to read contents of '{}': {error}", path.display());
+            return Ok(None);
+        }
+    };
+
+    match blob_store.store_file(session, file) {
+        Ok(file_ref) => Ok(Some(Contents::Ref(file_ref))),
+        // Read errors are not a reason to fail the whole action, but session
+        // errors (e.g. exceeded limits) are.
+        Err(error) if error.kind() == crate::session::ErrorKind::ActionFailure => {
+            log::warn!("failed to store contents of '{}': {error:#}", path.display());
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Reads at most `len` bytes from the beginning of the regular file at `path`.
+///
+/// Special files (e.g. devices or FIFOs) are never opened. Failures are only
+/// logged, as they should not prevent reporting the rest of the metadata.
+pub(super) fn read_preview(path: &Path, metadata: &std::fs::Metadata, len: u64) -> Option<Vec<u8>> {
+    use std::io::Read as _;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    // A single read can return fewer bytes than available, so we read until
+    // either the limit or the end of the file is reached.
+    let mut preview = Vec::new();
+    let result = crate::fs::open_regular_file(path)
+        .and_then(|file| file.take(len).read_to_end(&mut preview));
+
+    match result {
+        Ok(_) => Some(preview),
+        Err(error) => {
+            log::warn! {
+                "failed to read preview of '{}': {error}",
+                path.display()
+            };
+
+            None
+        }
+    }
+}
+
+/// Classifies a file based on the given prefix of its contents.
+fn file_type(prefix: &[u8]) -> &'static str {
+    MAGICS.iter()
+        .find(|(magic, _)| prefix.starts_with(magic))
+        .map(|(_, file_type)| *file_type)
+        .unwrap_or("unknown")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::{handle, Args, Item};
+
+    #[test]
+    fn handle_preview() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("script"), b"#!/bin/sh\necho foo\n")
+            .unwrap();
+        std::fs::write(tempdir.join("binary"), b"\x7fELF\x00\x00\x00\x00\x01")
+            .unwrap();
+        std::fs::write(tempdir.join("short"), b"foo")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: Some(8),
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items_by_path = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        assert_eq!(items_by_path[&tempdir].preview, None);
+        assert_eq! {
+            items_by_path[&tempdir.join("script")].preview.as_deref(),
+            Some(&b"#!/bin/s"[..])
+        };
+        assert_eq! {
+            items_by_path[&tempdir.join("binary")].preview.as_deref(),
+            Some(&b"\x7fELF\x00\x00\x00\x00"[..])
+        };
+        assert_eq! {
+            items_by_path[&tempdir.join("short")].preview.as_deref(),
+            Some(&b"foo"[..])
+        };
+    }
+
+    #[test]
+    fn handle_detect_file_type() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("elf"), b"\x7fELF\x02\x01\x01\x00")
+            .unwrap();
+        std::fs::write(tempdir.join("pe"), b"MZ\x90\x00\x03\x00\x00\x00")
+            .unwrap();
+        std::fs::write(tempdir.join("gzip"), b"\x1f\x8b\x08\x00\x00\x00\x00\x00")
+            .unwrap();
+        std::fs::write(tempdir.join("script"), b"#!/bin/sh\necho foo\n")
+            .unwrap();
+        std::fs::write(tempdir.join("short"), b"M")
+            .unwrap();
+        std::fs::write(tempdir.join("text"), b"lorem ipsum dolor sit amet")
+            .unwrap();
+        std::fs::create_dir(tempdir.join("dir"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: true,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let file_types = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.file_type))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        assert_eq!(file_types[&tempdir], None);
+        assert_eq!(file_types[&tempdir.join("dir")], None);
+        assert_eq!(file_types[&tempdir.join("elf")], Some("elf"));
+        assert_eq!(file_types[&tempdir.join("pe")], Some("pe"));
+        assert_eq!(file_types[&tempdir.join("gzip")], Some("gzip"));
+        assert_eq!(file_types[&tempdir.join("script")], Some("script"));
+        assert_eq!(file_types[&tempdir.join("short")], Some("unknown"));
+        assert_eq!(file_types[&tempdir.join("text")], Some("unknown"));
+    }
+
+    #[test]
+    fn handle_detect_file_type_disabled() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"\x7fELF\x02\x01\x01\x00")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply::<Item>(0).file_type, None);
+    }
+
+    #[test]
+    fn handle_contents_inline_and_ref() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("small"), b"foo")
+            .unwrap();
+        std::fs::write(tempdir.join("big"), b"foobarbaz")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: Some(4),
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items_by_path = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        assert!(items_by_path[&tempdir].contents.is_none());
+
+        match &items_by_path[&tempdir.join("small")].contents {
+            Some(Contents::Inline(contents)) => assert_eq!(contents, b"foo"),
+            _ => panic!("small file not inlined"),
+        }
+
+        let file_ref = match &items_by_path[&tempdir.join("big")].contents {
+            Some(Contents::Ref(file_ref)) => file_ref,
+            _ => panic!("big file not referenced"),
+        };
+        assert_eq!(file_ref.len, 9);
+        assert_eq!(file_ref.sha256, <[u8; 32]>::from(sha2::Sha256::digest(b"foobarbaz")));
+
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 1);
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"foobarbaz");
+    }
+
+    #[test]
+    fn handle_contents_budget() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"foo")
+            .unwrap();
+        std::fs::write(tempdir.join("bar"), b"bar")
+            .unwrap();
+        std::fs::write(tempdir.join("baz"), b"baz")
+            .unwrap();
+        std::fs::write(tempdir.join("quux"), b"quux")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![
+                tempdir.join("foo"),
+                tempdir.join("bar"),
+                tempdir.join("quux"),
+                tempdir.join("baz"),
+            ],
+            batch: true,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: Some(2),
+            max_contents_total_len: Some(8),
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 4);
+
+        // The budget is shared by inlined and referenced contents alike.
+        assert!(matches!(session.reply::<Item>(0).contents, Some(Contents::Ref(_))));
+        assert!(matches!(session.reply::<Item>(1).contents, Some(Contents::Ref(_))));
+
+        // The file that does not fit exhausts the budget even though the next
+        // one would still fit in what is left of it.
+        assert!(matches!(session.reply::<Item>(2).contents, Some(Contents::BudgetExceeded)));
+        assert!(matches!(session.reply::<Item>(3).contents, Some(Contents::BudgetExceeded)));
+
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 2);
+    }
+
+    #[test]
+    fn file_type_magics() {
+        assert_eq!(file_type(b"\x7fELF\x01\x01\x01\x00"), "elf");
+        assert_eq!(file_type(b"MZ\x90\x00"), "pe");
+        assert_eq!(file_type(b"\xcf\xfa\xed\xfe\x07\x00\x00\x01"), "mach-o");
+        assert_eq!(file_type(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"), "png");
+        assert_eq!(file_type(b"\x1f\x8b\x08\x00"), "gzip");
+        assert_eq!(file_type(b"PK\x03\x04\x14\x00"), "zip");
+        assert_eq!(file_type(b"#!/usr/bin/env python3\n"), "script");
+        assert_eq!(file_type(b"\x7fEL"), "unknown");
+        assert_eq!(file_type(b""), "unknown");
+    }
+}
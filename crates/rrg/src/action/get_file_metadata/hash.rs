@@ -0,0 +1,765 @@
+// Copyright 2023 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Hashing of contents of files and matching them against conditions.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::parse_time;
+
+/// Condition on the hash of the contents of a file.
+#[derive(Default)]
+pub(super) struct HashCondition {
+    /// MD5 digests to match the files against.
+    md5: HashSet<[u8; 16]>,
+    /// SHA-1 digests to match the files against.
+    sha1: HashSet<[u8; 20]>,
+    /// SHA-256 digests to match the files against.
+    sha256: HashSet<[u8; 32]>,
+    /// Minimum size of files to hash.
+    min_size: u64,
+    /// Maximum size of files to hash (if any).
+    max_size: Option<u64>,
+    /// Time after which the files to hash were last modified (if any).
+    min_mtime: Option<std::time::SystemTime>,
+    /// Time before which the files to hash were last modified (if any).
+    max_mtime: Option<std::time::SystemTime>,
+}
+
+/// Digest of a file that matched one of the values of a hash condition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum HashMatch {
+    Md5([u8; 16]),
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+/// Kind of identity of file contents that does not depend on file metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ContentIdType {
+    /// SHA-1 digest of the contents as a Git blob object.
+    GitBlobSha1,
+    /// SHA-256 digest of the contents.
+    Sha256,
+}
+
+/// Identity of the contents of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ContentId {
+    GitBlobSha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+impl HashCondition {
+
+    /// Checks whether the file satisfies the condition.
+    ///
+    /// The file is read and hashed only if its metadata satisfies the size and
+    /// time conditions and the digests are not in the cache already. Only the
+    /// algorithms for which there are digests to match against are computed.
+    /// In case of a match, the matching digest is returned.
+    pub(super) fn check(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        cache: &mut crate::hash_cache::HashCache,
+    ) -> std::io::Result<Option<HashMatch>> {
+        if !self.matches_metadata(metadata) {
+            return Ok(None);
+        }
+
+        let mut digests = cache.get(path, metadata)
+            .cloned()
+            .unwrap_or_default();
+
+        let md5 = !self.md5.is_empty() && digests.md5.is_none();
+        let sha1 = !self.sha1.is_empty() && digests.sha1.is_none();
+        let sha256 = !self.sha256.is_empty() && digests.sha256.is_none();
+
+        if md5 || sha1 || sha256 {
+            digests.merge(hash_file(path, md5, sha1, sha256, false)?);
+            cache.insert(path, metadata, digests.clone());
+        }
+
+        if let Some(digest) = digests.sha256 {
+            if self.sha256.contains(&digest) {
+                return Ok(Some(HashMatch::Sha256(digest)));
+            }
+        }
+        if let Some(digest) = digests.sha1 {
+            if self.sha1.contains(&digest) {
+                return Ok(Some(HashMatch::Sha1(digest)));
+            }
+        }
+        if let Some(digest) = digests.md5 {
+            if self.md5.contains(&digest) {
+                return Ok(Some(HashMatch::Md5(digest)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Checks whether the file metadata satisfies the size and time conditions.
+    fn matches_metadata(&self, metadata: &std::fs::Metadata) -> bool {
+        if !metadata.is_file() {
+            return false;
+        }
+
+        let size = metadata.len();
+        if size < self.min_size {
+            return false;
+        }
+        if let Some(max_size) = self.max_size {
+            if size > max_size {
+                return false;
+            }
+        }
+
+        if self.min_mtime.is_some() || self.max_mtime.is_some() {
+            let mtime = match metadata.modified() {
+                Ok(mtime) => mtime,
+                Err(_) => return false,
+            };
+
+            if let Some(min_mtime) = self.min_mtime {
+                if mtime < min_mtime {
+                    return false;
+                }
+            }
+            if let Some(max_mtime) = self.max_mtime {
+                if mtime > max_mtime {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+/// Computes the requested digests of the file contents in a single pass.
+pub(super) fn hash_file(
+    path: &Path,
+    md5: bool,
+    sha1: bool,
+    sha256: bool,
+    git_blob_sha1: bool,
+) -> std::io::Result<crate::hash_cache::Digests> {
+    use std::io::Read as _;
+    use sha2::Digest as _;
+
+    let mut md5 = md5.then(md5::Md5::new);
+    let mut sha1 = sha1.then(sha1::Sha1::new);
+    let mut sha256 = sha256.then(sha2::Sha256::new);
+    let mut git_blob_sha1 = git_blob_sha1.then(sha1::Sha1::new);
+
+    let mut file = crate::fs::open_regular_file(path)?;
+    let mut buf = vec![0; 64 * 1024];
+
+    // Git blob objects are prefixed with a header that includes the length of
+    // the contents [1], so it has to be known upfront. If the file is resized
+    // while being read, the digest would be bogus and we fail instead.
+    //
+    // [1]: https://git-scm.com/book/en/v2/Git-Internals-Git-Objects
+    let expected_len = file.metadata()?.len();
+    let mut len_total = 0;
+    if let Some(git_blob_sha1) = &mut git_blob_sha1 {
+        git_blob_sha1.update(format!("blob {expected_len}\0").as_bytes());
+    }
+
+    loop {
+        let len = match file.read(&mut buf[..]) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+
+        if let Some(md5) = &mut md5 {
+            md5.update(&buf[..len]);
+        }
+        if let Some(sha1) = &mut sha1 {
+            sha1.update(&buf[..len]);
+        }
+        if let Some(sha256) = &mut sha256 {
+            sha256.update(&buf[..len]);
+        }
+        if let Some(git_blob_sha1) = &mut git_blob_sha1 {
+            git_blob_sha1.update(&buf[..len]);
+        }
+
+        len_total += len as u64;
+    }
+
+    if git_blob_sha1.is_some() && len_total != expected_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("file size changed while hashing (from {expected_len} to {len_total})"),
+        ));
+    }
+
+    Ok(crate::hash_cache::Digests {
+        md5: md5.map(|md5| md5.finalize().into()),
+        sha1: sha1.map(|sha1| sha1.finalize().into()),
+        sha256: sha256.map(|sha256| sha256.finalize().into()),
+        git_blob_sha1: git_blob_sha1.map(|git_blob_sha1| git_blob_sha1.finalize().into()),
+    })
+}
+
+/// Computes the identity of contents of the regular file at `path`.
+///
+/// Digests are taken from the cache if the file did not change since they
+/// were computed. Failures are only logged, as they should not prevent
+/// reporting the rest of the metadata.
+pub(super) fn compute_content_id(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    content_id_type: ContentIdType,
+    cache: &mut crate::hash_cache::HashCache,
+) -> Option<ContentId> {
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let mut digests = cache.get(path, metadata)
+        .cloned()
+        .unwrap_or_default();
+
+    let git_blob_sha1 = content_id_type == ContentIdType::GitBlobSha1 && digests.git_blob_sha1.is_none();
+    let sha256 = content_id_type == ContentIdType::Sha256 && digests.sha256.is_none();
+
+    if git_blob_sha1 || sha256 {
+        match hash_file(path, false, false, sha256, git_blob_sha1) {
+            Ok(new_digests) => digests.merge(new_digests),
+            Err(error) => {
+                log::warn! {
+                    "failed to compute content identity of '{}': {error}",
+                    path.display()
+                };
+
+                return None;
+            }
+        }
+        cache.insert(path, metadata, digests.clone());
+    }
+
+    match content_id_type {
+        ContentIdType::GitBlobSha1 => digests.git_blob_sha1.map(ContentId::GitBlobSha1),
+        ContentIdType::Sha256 => digests.sha256.map(ContentId::Sha256),
+    }
+}
+
+/// Parses the hash condition from its protobuf representation.
+pub(super) fn parse_hash_condition(
+    mut proto: rrg_proto::get_file_metadata::HashCondition,
+) -> Result<HashCondition, crate::request::ParseArgsError> {
+    use crate::request::ParseArgsError;
+
+    fn digests<const N: usize>(
+        name: &'static str,
+        digests: Vec<Vec<u8>>,
+    ) -> Result<HashSet<[u8; N]>, ParseArgsError> {
+        digests.into_iter()
+            .map(|digest| {
+                <[u8; N]>::try_from(digest.as_slice())
+                    .map_err(|_| ParseArgsError::invalid_field(name, DigestLenError {
+                        len: digest.len(),
+                        expected_len: N,
+                    }))
+            })
+            .collect()
+    }
+
+    Ok(HashCondition {
+        md5: digests("hash_condition.md5", proto.take_md5())?,
+        sha1: digests("hash_condition.sha1", proto.take_sha1())?,
+        sha256: digests("hash_condition.sha256", proto.take_sha256())?,
+        min_size: proto.min_size(),
+        max_size: proto.max_size,
+        min_mtime: parse_time("hash_condition.min_modification_time", proto.min_modification_time)?,
+        max_mtime: parse_time("hash_condition.max_modification_time", proto.max_modification_time)?,
+    })
+}
+
+/// An error indicating that a digest in a hash condition has invalid length.
+#[derive(Debug)]
+pub(super) struct DigestLenError {
+    /// Length of the provided digest.
+    pub(super) len: usize,
+    /// Length expected for the digest algorithm.
+    pub(super) expected_len: usize,
+}
+
+impl std::fmt::Display for DigestLenError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "invalid digest length (expected {}, got {})",
+            self.expected_len, self.len,
+        }
+    }
+}
+
+impl std::error::Error for DigestLenError {
+}
+
+impl From<HashMatch> for rrg_proto::get_file_metadata::HashMatch {
+
+    fn from(hash_match: HashMatch) -> rrg_proto::get_file_metadata::HashMatch {
+        use rrg_proto::get_file_metadata::HashAlgorithm;
+
+        let (algorithm, digest) = match hash_match {
+            HashMatch::Md5(digest) => (HashAlgorithm::MD5, digest.to_vec()),
+            HashMatch::Sha1(digest) => (HashAlgorithm::SHA1, digest.to_vec()),
+            HashMatch::Sha256(digest) => (HashAlgorithm::SHA256, digest.to_vec()),
+        };
+
+        let mut proto = rrg_proto::get_file_metadata::HashMatch::default();
+        proto.set_algorithm(algorithm);
+        proto.set_digest(digest);
+
+        proto
+    }
+}
+
+impl From<ContentId> for rrg_proto::get_file_metadata::ContentId {
+
+    fn from(content_id: ContentId) -> rrg_proto::get_file_metadata::ContentId {
+        use rrg_proto::get_file_metadata::ContentIdType;
+
+        let (content_id_type, digest) = match content_id {
+            ContentId::GitBlobSha1(digest) => (ContentIdType::GIT_BLOB_SHA1, digest.to_vec()),
+            ContentId::Sha256(digest) => (ContentIdType::CONTENT_SHA256, digest.to_vec()),
+        };
+
+        let mut proto = rrg_proto::get_file_metadata::ContentId::default();
+        proto.set_type(content_id_type);
+        proto.set_digest(digest);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::{handle, Args, Item};
+
+    #[test]
+    fn handle_content_id_git_blob_sha1() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"hello\n")
+            .unwrap();
+        std::fs::write(tempdir.join("bar"), b"hello\n")
+            .unwrap();
+        std::fs::write(tempdir.join("baz"), b"world\n")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: Some(ContentIdType::GitBlobSha1),
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let content_ids_by_path = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.content_id))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        assert_eq!(content_ids_by_path[&tempdir], None);
+
+        let foo = content_ids_by_path[&tempdir.join("foo")];
+        let bar = content_ids_by_path[&tempdir.join("bar")];
+        let baz = content_ids_by_path[&tempdir.join("baz")];
+
+        // Digest reported by `git hash-object` for the same contents.
+        let digest = [
+            0xce, 0x01, 0x36, 0x25, 0x03, 0x0b, 0xa8, 0xdb, 0xa9, 0x06,
+            0xf7, 0x56, 0x96, 0x7f, 0x9e, 0x9c, 0xa3, 0x94, 0x46, 0x4a,
+        ];
+        assert_eq!(foo, Some(ContentId::GitBlobSha1(digest)));
+
+        assert_eq!(foo, bar);
+        assert_ne!(foo, baz);
+        assert!(baz.is_some());
+    }
+
+    #[test]
+    fn handle_content_id_sha256() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"hello\n")
+            .unwrap();
+        std::fs::write(tempdir.join("bar"), b"hello\n")
+            .unwrap();
+        std::fs::write(tempdir.join("baz"), b"world\n")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: 1,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            open_devices: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: None,
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: Some(ContentIdType::Sha256),
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let content_ids_by_path = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.content_id))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        let foo = content_ids_by_path[&tempdir.join("foo")];
+        let bar = content_ids_by_path[&tempdir.join("bar")];
+        let baz = content_ids_by_path[&tempdir.join("baz")];
+
+        assert_eq!(foo, Some(ContentId::Sha256(sha2::Sha256::digest(b"hello\n").into())));
+        assert_eq!(foo, bar);
+        assert_ne!(foo, baz);
+    }
+
+    #[test]
+    fn handle_hash_condition() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.join("dir"))
+            .unwrap();
+        std::fs::write(tempdir.join("foo"), b"foo")
+            .unwrap();
+        std::fs::write(tempdir.join("dir").join("bar"), b"bar")
+            .unwrap();
+        std::fs::write(tempdir.join("dir").join("planted"), b"malicious")
+            .unwrap();
+
+        let hash_condition = HashCondition {
+            md5: [md5::Md5::digest(b"quux").into()].into(),
+            sha1: [sha1::Sha1::digest(b"norf").into()].into(),
+            sha256: [
+                sha2::Sha256::digest(b"malicious").into(),
+                sha2::Sha256::digest(b"thud").into(),
+            ].into(),
+            ..Default::default()
+        };
+
+        let args = Args {
+            paths: vec![tempdir.clone()],
+            batch: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: Some(hash_condition),
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("dir").join("planted"));
+        assert_eq! {
+            item.hash_match,
+            Some(HashMatch::Sha256(sha2::Sha256::digest(b"malicious").into()))
+        };
+    }
+
+    #[test]
+    fn handle_hash_condition_md5() {
+        use sha2::Digest as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"malicious")
+            .unwrap();
+
+        let md5 = md5::Md5::digest(b"malicious").into();
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: Some(HashCondition {
+                md5: [md5].into(),
+                ..Default::default()
+            }),
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).hash_match, Some(HashMatch::Md5(md5)));
+    }
+
+    #[test]
+    fn handle_hash_condition_cache() {
+        use sha2::Digest as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"malicious")
+            .unwrap();
+
+        let sha256 = sha2::Sha256::digest(b"malicious").into();
+
+        let args = || Args {
+            paths: vec![path.clone()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: Some(HashCondition {
+                sha256: [sha256].into(),
+                ..Default::default()
+            }),
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            temp_dir: tempdir.path().to_path_buf(),
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args()).is_ok());
+        assert_eq!(session.reply_count(), 1);
+
+        let mut cache = crate::hash_cache::HashCache::load(tempdir.path(), 1);
+        let digests = cache.get(&path, &path.metadata().unwrap())
+            .unwrap();
+        assert_eq!(digests.sha256, Some(sha256));
+
+        // Once the file is modified, its cached digest must not be used.
+        std::fs::write(&path, b"harmless")
+            .unwrap();
+
+        assert!(handle(&mut session, args()).is_ok());
+        assert_eq!(session.reply_count(), 1);
+    }
+
+    #[test]
+    fn handle_hash_condition_size() {
+        use sha2::Digest as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"malicious")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempfile.path().to_path_buf()],
+            batch: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            collect_acls: false,
+            max_inline_len: None,
+            max_contents_total_len: None,
+            open_devices: false,
+            baseline: None,
+            symlink_chain_depth: 0,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            #[cfg(target_os = "linux")]
+            process_fd: None,
+            detect_file_type: false,
+            hash_condition: Some(HashCondition {
+                sha256: [sha2::Sha256::digest(b"malicious").into()].into(),
+                max_size: Some(8),
+                ..Default::default()
+            }),
+            mode_condition: None,
+            preview_len: None,
+            content_id_type: None,
+            minimal: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn hash_condition_matches_metadata() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foobar")
+            .unwrap();
+
+        let file_metadata = tempdir.path().join("foo").metadata()
+            .unwrap();
+        let dir_metadata = tempdir.path().metadata()
+            .unwrap();
+
+        let mtime = file_metadata.modified()
+            .unwrap();
+        let hour = std::time::Duration::from_secs(60 * 60);
+
+        assert!(HashCondition::default().matches_metadata(&file_metadata));
+        assert!(!HashCondition::default().matches_metadata(&dir_metadata));
+
+        assert!(HashCondition {
+            min_size: 6,
+            max_size: Some(6),
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+        assert!(!HashCondition {
+            min_size: 7,
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+        assert!(!HashCondition {
+            max_size: Some(5),
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+
+        assert!(HashCondition {
+            min_mtime: Some(mtime - hour),
+            max_mtime: Some(mtime + hour),
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+        assert!(!HashCondition {
+            min_mtime: Some(mtime + hour),
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+        assert!(!HashCondition {
+            max_mtime: Some(mtime - hour),
+            ..Default::default()
+        }.matches_metadata(&file_metadata));
+    }
+
+    #[test]
+    fn parse_hash_condition_invalid_digest_len() {
+        let mut proto = rrg_proto::get_file_metadata::HashCondition::default();
+        proto.mut_sha256().push(vec![0; 20]);
+
+        let error = parse_hash_condition(proto).err()
+            .unwrap();
+        assert_eq! {
+            error.kind(),
+            crate::request::ParseArgsErrorKind::InvalidField("hash_condition.sha256")
+        };
+    }
+}
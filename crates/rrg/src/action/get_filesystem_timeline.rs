@@ -9,9 +9,51 @@ use std::path::PathBuf;
 
 use rrg_proto::convert::FromLossy;
 
+/// Size of parts of the timeline in the JSONL format.
+const JSONL_PART_SIZE: usize = 1024 * 1024; // 1 MiB.
+
 /// Arguments of the `get_filesystem_timeline` action.
 pub struct Args {
+    /// Root path to the directory to get the timeline of.
     root: PathBuf,
+    /// Whether to collect extended attributes of the entries.
+    ext_attrs: bool,
+    /// Limit on the depth of recursion when visiting subfolders.
+    max_depth: u32,
+    /// Patterns of paths to exclude from the timeline.
+    excludes: Vec<crate::fs::Glob>,
+    /// Format in which the timeline is sent to the blob sink.
+    output_format: OutputFormat,
+    /// Alternate root at which the paths were reinterpreted (if any).
+    alternate_root: Option<PathBuf>,
+    /// Image root within which the paths are resolved (if any).
+    #[cfg(target_os = "linux")]
+    image_root: Option<PathBuf>,
+    /// Time after which entries have to be changed to be included (if any).
+    since: Option<std::time::SystemTime>,
+    /// Types of filesystems to descend into (all if empty).
+    include_fs_types: Vec<String>,
+    /// Types of filesystems not to descend into.
+    exclude_fs_types: Vec<String>,
+    /// Whether to flag entries with listed types disagreeing with metadata.
+    check_entry_types: bool,
+    /// Whether to include only the essential fields of the entries.
+    ///
+    /// In the minimal mode, only the path, size, modification time and type of
+    /// entries are included. This overrides options enabling collection of
+    /// optional information (e.g. extended attributes).
+    minimal: bool,
+    /// Compression of the timeline in the gzchunked format.
+    compression: crate::gzchunked::Compression,
+}
+
+/// Format in which the timeline is sent to the blob sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Serialized entry messages in the gzchunked format.
+    Gzchunked,
+    /// Newline-delimited JSON objects, one for each entry.
+    Jsonl,
 }
 
 /// Result of the `get_filesystem_timeline` action.
@@ -20,15 +62,38 @@ pub struct Item {
     blob_sha256: [u8; 32],
     // Number of entries in the batch sent to the blob sink.
     entry_count: usize,
+    /// Directories truncated because of too many entries while collecting the batch.
+    truncated_dirs: Vec<PathBuf>,
+    /// Entries abandoned because of a timeout while collecting the batch.
+    timed_out_paths: Vec<PathBuf>,
+    /// Compression of the batch sent to the blob sink (if compressed).
+    compression: Option<crate::gzchunked::Compression>,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetFilesystemTimeline;
+
 /// Handles requests for the timeline action.
-pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+pub fn handle<S>(session: &mut S, mut args: Args) -> crate::session::Result<()>
 where
     S: crate::session::Session,
 {
     use sha2::Digest as _;
 
+    if let Some(alternate_root) = &args.alternate_root {
+        crate::fs::check_within_root(alternate_root, &args.root)
+            .map_err(crate::session::Error::action)?;
+    }
+
+    // Unlike with alternate roots, symlinks within an image root are not
+    // rejected but resolved as if the image root was the filesystem root.
+    #[cfg(target_os = "linux")]
+    if let Some(image_root) = &args.image_root {
+        let max_symlink_depth = session.args().max_symlink_depth;
+        args.root = crate::fs::linux::resolve_in_root(image_root, &args.root, max_symlink_depth)
+            .map_err(crate::session::Error::action)?;
+    }
+
     // `entry_count` keeps track of the number of entries that are included in
     // each batch. Each time the `entries` iterator (defined below) yields an
     // entry, we increase the count (through `Iterator::inspect`). We read the
@@ -40,21 +105,140 @@ where
     // when we process batches.
     let entry_count = std::cell::Cell::new(0);
 
-    let entries = crate::fs::walk_dir(&args.root)
-        .map_err(crate::session::Error::action)?
+    // Similarly, `truncated_dirs` and `timed_out_paths` collect directories that
+    // were truncated and entries that timed out while building the current
+    // batch and are drained when sending it.
+    let truncated_dirs = std::cell::RefCell::new(Vec::new());
+    let timed_out_paths = std::cell::RefCell::new(Vec::new());
+
+    let max_dir_entries = session.args().max_dir_entries;
+    let entry_timeout = session.args().fs_entry_timeout;
+
+    // Without a filter walks do not leave the filesystem of the root, with it
+    // they enter other filesystems as long as their type is allowed.
+    let fs_type_filter = if args.include_fs_types.is_empty() && args.exclude_fs_types.is_empty() {
+        None
+    } else {
+        let fs_type_filter = crate::fs::FsTypeFilter::new(
+            args.include_fs_types,
+            args.exclude_fs_types,
+        ).map_err(crate::session::Error::action)?;
+
+        Some(fs_type_filter)
+    };
+
+    // Timeline collection is mostly bound by stat-ing files. On machines with
+    // fast storage it can be sped up by doing it in parallel.
+    let entries: Box<dyn Iterator<Item = std::io::Result<crate::fs::Entry>>> =
+        match (args.max_depth, session.args().timeline_concurrency) {
+            (0, _) => {
+                let metadata = args.root.symlink_metadata()
+                    .map_err(crate::session::Error::action)?;
+
+                Box::new(std::iter::once(Ok(crate::fs::Entry {
+                    path: args.root.clone(),
+                    metadata,
+                    listed_type: None,
+                })))
+            }
+            (max_depth, 0 | 1) => {
+                let mut entries = crate::fs::walk_dir(&args.root)
+                    .map_err(crate::session::Error::action)?
+                    .with_max_depth(max_depth)
+                    .with_max_dir_entries(max_dir_entries)
+                    .with_excludes(args.excludes);
+
+                if let Some(fs_type_filter) = fs_type_filter {
+                    entries = entries.with_fs_type_filter(fs_type_filter);
+                }
+                if let Some(entry_timeout) = entry_timeout {
+                    entries = entries.with_entry_timeout(entry_timeout);
+                }
+
+                Box::new(entries)
+            }
+            (max_depth, concurrency) => {
+                let entries = crate::fs::par_walk_dir(
+                    &args.root,
+                    max_depth,
+                    max_dir_entries,
+                    args.excludes,
+                    fs_type_filter,
+                    entry_timeout,
+                    concurrency,
+                ).map_err(crate::session::Error::action)?;
+
+                Box::new(entries)
+            }
+        };
+
+    let entries = entries
         .filter_map(|entry| match entry {
             Ok(entry) => Some(entry),
             Err(error) => {
+                if let Some(error) = crate::fs::TruncatedDirError::from_io_error(&error) {
+                    truncated_dirs.borrow_mut().push(error.path().to_path_buf());
+                }
+                if let Some(error) = crate::fs::TimedOutEntryError::from_io_error(&error) {
+                    timed_out_paths.borrow_mut().push(error.path().to_path_buf());
+                }
                 log::warn!("failed to obtain directory entry: {}", error);
                 None
             }
         })
+        // Note that we filter entries only after the walk, so directories that
+        // did not change are still traversed (their descendants might have).
+        .filter(|entry| match args.since {
+            Some(since) => is_changed_since(&entry.metadata, since),
+            None => true,
+        })
         .inspect(|_| {
             entry_count.set(entry_count.get() + 1);
         })
-        .map(rrg_proto::get_filesystem_timeline::Entry::from_lossy);
+        .map(|entry| {
+            if args.minimal {
+                return minimal_entry(entry);
+            }
+
+            #[cfg(target_family = "unix")]
+            let ext_attrs = if args.ext_attrs {
+                ext_attrs(&entry.path)
+            } else {
+                Vec::new()
+            };
+
+            let type_mismatch = entry.has_type_mismatch();
+
+            #[allow(unused_mut)]
+            let mut proto = rrg_proto::get_filesystem_timeline::Entry::from_lossy(entry);
+
+            if args.check_entry_types {
+                proto.set_type_mismatch(type_mismatch);
+            }
+
+            #[cfg(target_family = "unix")]
+            for ext_attr in ext_attrs {
+                proto.mut_ext_attrs().push(ext_attr.into());
+            }
 
-    for batch in crate::gzchunked::encode(entries) {
+            proto
+        });
+
+    let compression = match args.output_format {
+        OutputFormat::Gzchunked => Some(args.compression),
+        OutputFormat::Jsonl => None,
+    };
+
+    let batches: Box<dyn Iterator<Item = std::io::Result<Vec<u8>>> + '_> =
+        match args.output_format {
+            OutputFormat::Gzchunked => Box::new(crate::gzchunked::encode_with_opts(entries, crate::gzchunked::EncodeOpts {
+                compression: args.compression,
+                ..Default::default()
+            })),
+            OutputFormat::Jsonl => Box::new(encode_jsonl(entries, JSONL_PART_SIZE).map(Ok)),
+        };
+
+    for batch in batches {
         let batch = batch
             .map_err(crate::session::Error::action)?;
 
@@ -65,14 +249,188 @@ where
         session.reply(Item {
             blob_sha256,
             entry_count: entry_count.get(),
+            truncated_dirs: truncated_dirs.take(),
+            timed_out_paths: timed_out_paths.take(),
+            compression,
         })?;
 
         entry_count.set(0);
+
+        session.heartbeat();
+        session.check_deadline()?;
     }
 
     Ok(())
 }
 
+/// Checks whether a file with the given metadata changed after `since`.
+///
+/// A file is considered changed if its modification time (or on Unix also its
+/// status change time) is after `since`. Files for which the times cannot be
+/// determined are considered changed.
+fn is_changed_since(metadata: &std::fs::Metadata, since: std::time::SystemTime) -> bool {
+    let mtime = match metadata.modified() {
+        Ok(mtime) => mtime,
+        Err(_) => return true,
+    };
+    if mtime > since {
+        return true;
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let ctime_secs = u64::try_from(metadata.ctime());
+        let ctime_nanos = u32::try_from(metadata.ctime_nsec());
+
+        let ctime = match (ctime_secs, ctime_nanos) {
+            (Ok(secs), Ok(nanos)) if nanos < 1_000_000_000 => {
+                std::time::UNIX_EPOCH + std::time::Duration::new(secs, nanos)
+            }
+            _ => return true,
+        };
+        if ctime > since {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Collects extended attributes of the file under the given path.
+///
+/// Errors are only logged as failure to read extended attributes of a single
+/// file should not abort the whole timeline collection.
+#[cfg(target_family = "unix")]
+fn ext_attrs(path: &std::path::Path) -> Vec<ospect::fs::ExtAttr> {
+    let ext_attrs = match ospect::fs::ext_attrs(path) {
+        Ok(ext_attrs) => ext_attrs,
+        Err(error) => {
+            log::warn! {
+                "failed to list extended attributes for '{}': {}",
+                path.display(), error,
+            };
+            return Vec::new();
+        }
+    };
+
+    ext_attrs.filter_map(|ext_attr| match ext_attr {
+        Ok(ext_attr) => Some(ext_attr),
+        Err(error) => {
+            log::warn! {
+                "failed to read an extended attribute for '{}': {}",
+                path.display(), error,
+            };
+            None
+        }
+    }).collect()
+}
+
+/// Encodes the given entries as parts of newline-delimited JSON.
+///
+/// Similarly to the gzchunked format, a part is finished as soon as its size
+/// reaches `part_size`, so it can exceed it by at most one line. Lines are never
+/// split across parts.
+fn encode_jsonl<I>(entries: I, part_size: usize) -> impl Iterator<Item = Vec<u8>>
+where
+    I: Iterator<Item = rrg_proto::get_filesystem_timeline::Entry>,
+{
+    let mut entries = entries.fuse();
+
+    std::iter::from_fn(move || {
+        let mut part = String::new();
+
+        for entry in entries.by_ref() {
+            push_jsonl_entry(&mut part, &entry);
+            if part.len() >= part_size {
+                break;
+            }
+        }
+
+        if part.is_empty() {
+            None
+        } else {
+            Some(part.into_bytes())
+        }
+    })
+}
+
+/// Appends the given entry to `buf` as a single line of JSON.
+fn push_jsonl_entry(buf: &mut String, entry: &rrg_proto::get_filesystem_timeline::Entry) {
+    let mut fields = Vec::<(&str, String)>::new();
+
+    if entry.has_path() {
+        // JSON strings have to be valid Unicode, so in case the path is not we
+        // report it lossily for humans and precisely as hex-encoded bytes.
+        let path = entry.path();
+        fields.push(("path", json_string(&String::from_utf8_lossy(path))));
+        if std::str::from_utf8(path).is_err() {
+            let path_hex = path.iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+
+            fields.push(("path_hex", json_string(&path_hex)));
+        }
+    }
+
+    macro_rules! push_field {
+        ($name:ident, $has_name:ident) => {
+            if entry.$has_name() {
+                fields.push((stringify!($name), entry.$name().to_string()));
+            }
+        };
+    }
+
+    push_field!(unix_mode, has_unix_mode);
+    push_field!(size, has_size);
+    push_field!(unix_dev, has_unix_dev);
+    push_field!(unix_ino, has_unix_ino);
+    push_field!(unix_nlink, has_unix_nlink);
+    push_field!(unix_uid, has_unix_uid);
+    push_field!(unix_gid, has_unix_gid);
+    push_field!(atime_nanos, has_atime_nanos);
+    push_field!(mtime_nanos, has_mtime_nanos);
+    push_field!(ctime_nanos, has_ctime_nanos);
+    push_field!(btime_nanos, has_btime_nanos);
+    push_field!(windows_attributes, has_windows_attributes);
+    push_field!(type_mismatch, has_type_mismatch);
+
+    buf.push('{');
+    for (idx, (name, value)) in fields.into_iter().enumerate() {
+        if idx > 0 {
+            buf.push(',');
+        }
+        buf.push_str(&json_string(name));
+        buf.push(':');
+        buf.push_str(&value);
+    }
+    buf.push_str("}\n");
+}
+
+/// Encodes the given string as a JSON string literal.
+fn json_string(string: &str) -> String {
+    let mut result = String::with_capacity(string.len() + 2);
+
+    result.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            char if (char as u32) < 0x20 => {
+                result.push_str(&format!("\\u{:04x}", char as u32));
+            }
+            char => result.push(char),
+        }
+    }
+    result.push('"');
+
+    result
+}
+
 impl crate::request::Args for Args {
 
     type Proto = rrg_proto::get_filesystem_timeline::Args;
@@ -80,15 +438,144 @@ impl crate::request::Args for Args {
     fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
         use crate::request::ParseArgsError;
 
+        let alternate_root = if proto.has_alternate_root() {
+            let alternate_root = PathBuf::try_from(proto.take_alternate_root())
+                .map_err(|error| ParseArgsError::invalid_field("alternate_root", error))?;
+
+            if !alternate_root.is_absolute() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "relative path");
+                return Err(ParseArgsError::invalid_field("alternate_root", error));
+            }
+
+            Some(alternate_root)
+        } else {
+            None
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        if proto.has_image_root() {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "image roots are supported only on Linux");
+            return Err(ParseArgsError::unsupported_field("image_root", error));
+        }
+
+        let image_root = if proto.has_image_root() {
+            let image_root = PathBuf::try_from(proto.take_image_root())
+                .map_err(|error| ParseArgsError::invalid_field("image_root", error))?;
+
+            if !image_root.is_absolute() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "relative path");
+                return Err(ParseArgsError::invalid_field("image_root", error));
+            }
+
+            if alternate_root.is_some() {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "both alternate and image root");
+                return Err(ParseArgsError::invalid_field("image_root", error));
+            }
+
+            Some(image_root)
+        } else {
+            None
+        };
+
+        // With an alternate (or image) root, all the paths of the request are
+        // interpreted as rooted at it.
+        let reroot = |path: PathBuf| match alternate_root.as_ref().or(image_root.as_ref()) {
+            Some(new_root) => crate::fs::reroot(new_root, path),
+            None => Ok(path),
+        };
+
         let root = PathBuf::try_from(proto.take_root())
             .map_err(|error| ParseArgsError::invalid_field("root", error))?;
+        let root = reroot(root)
+            .map_err(|error| ParseArgsError::invalid_field("root", error))?;
+
+        let exclude_path_globs = proto.take_exclude_path_globs().into_iter()
+            .map(|path| {
+                let path = PathBuf::try_from(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))?;
+                let path = reroot(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))?;
+
+                crate::fs::Glob::new(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let excludes = if exclude_path_globs.is_empty() {
+            crate::fs::default_walk_excludes(&root)
+        } else {
+            exclude_path_globs
+        };
+
+        let since = match proto.since.take() {
+            Some(timestamp) => {
+                // Timestamp is (a bit simplifying) just a duration since epoch,
+                // so we reuse the existing conversion routine for durations.
+                let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+                since_epoch.seconds = timestamp.seconds;
+                since_epoch.nanos = timestamp.nanos;
+
+                let since_epoch = rrg_proto::try_from_duration(since_epoch)
+                    .map_err(|error| ParseArgsError::invalid_field("since", error))?;
+
+                Some(std::time::UNIX_EPOCH + since_epoch)
+            }
+            None => None,
+        };
+
+        use rrg_proto::get_filesystem_timeline::CompressionAlgorithm;
+
+        match proto.compression_algorithm() {
+            CompressionAlgorithm::GZIP => (),
+            // TODO: Add support for Zstandard compression.
+            CompressionAlgorithm::ZSTD => {
+                let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "Zstandard compression");
+                return Err(ParseArgsError::unsupported_field("compression_algorithm", error));
+            }
+        }
+
+        let compression = match proto.compression_level {
+            Some(level @ 0..=9) => crate::gzchunked::Compression::new(level),
+            Some(level) => {
+                let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("level {level} out of range"));
+                return Err(ParseArgsError::invalid_field("compression_level", error));
+            }
+            None => crate::gzchunked::Compression::default(),
+        };
 
         Ok(Args {
             root: root,
+            max_depth: proto.max_depth.unwrap_or(u32::MAX),
+            excludes,
+            output_format: proto.output_format().into(),
+            alternate_root,
+            #[cfg(target_os = "linux")]
+            image_root,
+            since,
+            include_fs_types: proto.take_include_fs_types(),
+            exclude_fs_types: proto.take_exclude_fs_types(),
+            // In the minimal mode, collection of optional information is
+            // disabled regardless of what the other options ask for.
+            ext_attrs: proto.ext_attrs() && !proto.minimal(),
+            check_entry_types: proto.check_entry_types() && !proto.minimal(),
+            minimal: proto.minimal(),
+            compression,
         })
     }
 }
 
+impl From<rrg_proto::get_filesystem_timeline::OutputFormat> for OutputFormat {
+
+    fn from(proto: rrg_proto::get_filesystem_timeline::OutputFormat) -> OutputFormat {
+        use rrg_proto::get_filesystem_timeline::OutputFormat::*;
+
+        match proto {
+            GZCHUNKED => OutputFormat::Gzchunked,
+            JSONL => OutputFormat::Jsonl,
+        }
+    }
+}
+
 impl crate::response::Item for Item {
 
     type Proto = rrg_proto::get_filesystem_timeline::Result;
@@ -97,11 +584,54 @@ impl crate::response::Item for Item {
         let mut proto = Self::Proto::default();
         proto.set_blob_sha256(self.blob_sha256.into());
         proto.set_entry_count(self.entry_count as u64);
+        for truncated_dir in self.truncated_dirs {
+            proto.mut_truncated_dirs().push(truncated_dir.into());
+        }
+        for timed_out_path in self.timed_out_paths {
+            proto.mut_timed_out_paths().push(timed_out_path.into());
+        }
+        if let Some(compression) = self.compression {
+            use rrg_proto::get_filesystem_timeline::CompressionAlgorithm;
+
+            proto.set_compression_algorithm(CompressionAlgorithm::GZIP);
+            proto.set_compression_level(compression.level());
+        }
 
         proto
     }
 }
 
+/// Converts the given entry to a timeline entry with only the essential fields.
+fn minimal_entry(entry: crate::fs::Entry) -> rrg_proto::get_filesystem_timeline::Entry {
+    let mut proto = rrg_proto::get_filesystem_timeline::Entry::default();
+    proto.set_path(rrg_proto::path::into_bytes(entry.path));
+    proto.set_size(entry.metadata.len());
+
+    let mtime_nanos = entry.metadata.modified().ok()
+        .and_then(|time| i64::try_from(rrg_proto::nanos(time).ok()?).ok());
+    if let Some(mtime_nanos) = mtime_nanos {
+        proto.set_mtime_nanos(mtime_nanos);
+    }
+
+    // Only the file type bits of the mode are kept, permissions are optional.
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+
+        proto.set_unix_mode(i64::from(entry.metadata.mode() & 0o170000));
+    }
+
+    #[cfg(target_family = "windows")]
+    {
+        use std::os::windows::fs::MetadataExt as _;
+
+        let attributes = entry.metadata.file_attributes();
+        proto.set_windows_attributes(u64::from(attributes));
+    }
+
+    proto
+}
+
 impl FromLossy<crate::fs::Entry> for rrg_proto::get_filesystem_timeline::Entry {
 
     fn from_lossy(entry: crate::fs::Entry) -> Self {
@@ -134,6 +664,7 @@ impl FromLossy<crate::fs::Entry> for rrg_proto::get_filesystem_timeline::Entry {
 
             proto.set_unix_mode(i64::from(entry.metadata.mode()));
             proto.set_unix_ino(entry.metadata.ino());
+            proto.set_unix_nlink(entry.metadata.nlink());
             if let Some(dev) = i64::try_from(entry.metadata.dev()).ok() {
                 proto.set_unix_dev(dev);
             }
@@ -168,7 +699,20 @@ mod tests {
         let tempdir = tempfile::tempdir().unwrap();
 
         let request = Args {
-            root: tempdir.path().join("foo")
+            root: tempdir.path().join("foo"),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -182,6 +726,19 @@ mod tests {
 
         let request = Args {
             root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -200,6 +757,19 @@ mod tests {
 
         let request = Args {
             root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -214,69 +784,133 @@ mod tests {
         assert_eq!(path(&entries[2]), Some(tempdir.path().join("c")));
     }
 
+    #[cfg(target_family = "unix")]
     #[test]
-    fn handle_dir_with_nested_dirs() {
+    fn handle_check_entry_types() {
         let tempdir = tempfile::tempdir().unwrap();
-        let tempdir_path = tempdir.path().to_path_buf();
-
-        std::fs::create_dir_all(tempdir_path.join("a").join("b")).unwrap();
+        std::fs::File::create(tempdir.path().join("file")).unwrap();
+        std::os::unix::fs::symlink("file", tempdir.path().join("link")).unwrap();
 
         let request = Args {
-            root: tempdir_path.clone(),
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: true,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, request).is_ok());
 
-        let mut entries = entries(&session);
-        entries.sort_by_key(|entry| entry.path().to_owned());
-
+        let entries = entries(&session);
         assert_eq!(entries.len(), 2);
-        assert_eq!(path(&entries[0]), Some(tempdir_path.join("a")));
-        assert_eq!(path(&entries[1]), Some(tempdir_path.join("a").join("b")));
+        assert!(entries.iter().all(|entry| entry.has_type_mismatch()));
+        assert!(entries.iter().all(|entry| !entry.type_mismatch()));
     }
 
-    // Symlinking is supported only on Unix-like systems.
-    #[cfg(target_family = "unix")]
     #[test]
-    fn handle_dir_with_circular_symlinks() {
+    fn handle_unchecked_entry_types() {
         let tempdir = tempfile::tempdir().unwrap();
+        std::fs::File::create(tempdir.path().join("file")).unwrap();
 
-        let root_path = tempdir.path().to_path_buf();
-        let dir_path = root_path.join("dir");
-        let symlink_path = dir_path.join("symlink");
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
 
-        std::fs::create_dir(&dir_path).unwrap();
-        std::os::unix::fs::symlink(&dir_path, &symlink_path).unwrap();
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1);
+        assert!(!entries[0].has_type_mismatch());
+    }
+
+    #[test]
+    fn handle_since() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let old_file = std::fs::File::create(tempdir.path().join("old")).unwrap();
+        old_file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1337))
+            .unwrap();
+        drop(old_file);
+
+        // Filesystem timestamps can be coarser than the system clock, so we
+        // give them some slack around the threshold.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let since = std::time::SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        std::fs::File::create(tempdir.path().join("new")).unwrap();
 
         let request = Args {
-            root: root_path.clone(),
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: Some(since),
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, request).is_ok());
 
-        let mut entries = entries(&session);
-        entries.sort_by_key(|entry| entry.path().to_owned());
-
-        assert_eq!(entries.len(), 2);
-        assert_eq!(path(&entries[0]), Some(dir_path));
-        assert_eq!(path(&entries[1]), Some(symlink_path));
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(path(&entries[0]), Some(tempdir.path().join("new")));
     }
 
     #[test]
-    fn handle_dir_with_unicode_files() {
+    fn handle_dir_with_nested_dirs() {
         let tempdir = tempfile::tempdir().unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
 
-        let root_path = tempdir.path().to_path_buf();
-        let file_path_1 = root_path.join("zażółć gęślą jaźń");
-        let file_path_2 = root_path.join("што й па мору");
-
-        std::fs::File::create(&file_path_1).unwrap();
-        std::fs::File::create(&file_path_2).unwrap();
+        std::fs::create_dir_all(tempdir_path.join("a").join("b")).unwrap();
 
         let request = Args {
-            root: root_path.clone(),
+            root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -286,87 +920,623 @@ mod tests {
         entries.sort_by_key(|entry| entry.path().to_owned());
 
         assert_eq!(entries.len(), 2);
-
-        // macOS mangles Unicode-specific characters in filenames.
-        #[cfg(not(target_os = "macos"))]
-        {
-            assert_eq!(path(&entries[0]), Some(file_path_1));
-            assert_eq!(path(&entries[1]), Some(file_path_2));
-        }
+        assert_eq!(path(&entries[0]), Some(tempdir_path.join("a")));
+        assert_eq!(path(&entries[1]), Some(tempdir_path.join("a").join("b")));
     }
 
     #[test]
-    fn handle_file_metadata() {
+    fn handle_max_depth_0() {
         let tempdir = tempfile::tempdir().unwrap();
-        std::fs::write(tempdir.path().join("foo"), b"123456789").unwrap();
+        std::fs::create_dir(tempdir.path().join("a")).unwrap();
 
         let request = Args {
             root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: 0,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, request).is_ok());
 
-        let mut entries = entries(&session);
-        entries.sort_by_key(|entry| entry.path().to_owned());
-
+        let entries = entries(&session);
         assert_eq!(entries.len(), 1);
-        assert_eq!(path(&entries[0]), Some(tempdir.path().join("foo")));
-        assert_eq!(entries[0].size(), 9);
+        assert_eq!(path(&entries[0]), Some(tempdir.path().to_path_buf()));
+    }
 
-        // Information about the file mode, user and group identifiers is
-        // available only on UNIX systems.
-        #[cfg(target_family = "unix")]
-        {
-            let mode = entries[0].unix_mode() as libc::mode_t;
-            assert_eq!(mode & libc::S_IFMT, libc::S_IFREG);
+    #[test]
+    fn handle_max_depth_2() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
 
-            let uid = unsafe { libc::getuid() };
-            assert_eq!(entries[0].unix_uid(), uid.into());
+        std::fs::create_dir_all(tempdir_path.join("a").join("b").join("c")).unwrap();
+        std::fs::File::create(tempdir_path.join("a").join("b").join("foo")).unwrap();
+        std::fs::File::create(tempdir_path.join("a").join("bar")).unwrap();
 
-            let gid = unsafe { libc::getgid() };
-            assert_eq!(entries[0].unix_gid(), gid.into());
-        }
+        let request = Args {
+            root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: 2,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(path(&entries[0]), Some(tempdir_path.join("a")));
+        assert_eq!(path(&entries[1]), Some(tempdir_path.join("a").join("b")));
+        assert_eq!(path(&entries[2]), Some(tempdir_path.join("a").join("bar")));
     }
 
     #[test]
-    fn handle_hardlink_metadata() {
+    fn handle_max_depth_parallel() {
         let tempdir = tempfile::tempdir().unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
 
-        let root_path = tempdir.path().to_path_buf();
-        let file_path = root_path.join("file");
-        let hardlink_path = root_path.join("hardlink");
-
-        std::fs::File::create(&file_path).unwrap();
-        std::fs::hard_link(&file_path, &hardlink_path).unwrap();
+        std::fs::create_dir_all(tempdir_path.join("a").join("b").join("c")).unwrap();
 
         let request = Args {
-            root: root_path.clone(),
+            root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: 2,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
-        let mut session = crate::session::FakeSession::new();
+        let args = crate::args::Args {
+            timeline_concurrency: 4,
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(args);
         assert!(handle(&mut session, request).is_ok());
 
         let mut entries = entries(&session);
         entries.sort_by_key(|entry| entry.path().to_owned());
 
         assert_eq!(entries.len(), 2);
-        assert_eq!(path(&entries[0]), Some(file_path));
-        assert_eq!(path(&entries[1]), Some(hardlink_path));
-
-        // Information about inode is not available on Windows.
-        #[cfg(not(target_os = "windows"))]
-        assert_eq!(entries[0].unix_ino(), entries[1].unix_ino());
+        assert_eq!(path(&entries[0]), Some(tempdir_path.join("a")));
+        assert_eq!(path(&entries[1]), Some(tempdir_path.join("a").join("b")));
     }
 
     #[test]
-    // Attributes are supported only on Windows.
-    #[cfg(target_family = "windows")]
-    fn handle_file_attributes() {
-        use std::os::windows::ffi::OsStrExt as _;
-        use windows_sys::Win32::Storage::FileSystem::*;
+    fn handle_max_dir_entries() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
 
-        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir_path.join("a")).unwrap();
+        for i in 0..10 {
+            std::fs::File::create(tempdir_path.join("a").join(i.to_string())).unwrap();
+        }
+
+        let request = Args {
+            root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let args = crate::args::Args {
+            max_dir_entries: 4,
+            ..Default::default()
+        };
+
+        let mut session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1 + 4);
+
+        let truncated_dirs = session.replies::<Item>()
+            .flat_map(|item| item.truncated_dirs.iter().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(truncated_dirs, vec![tempdir_path.join("a")]);
+    }
+
+    #[test]
+    fn handle_excludes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
+
+        std::fs::create_dir_all(tempdir_path.join("foo").join("bar")).unwrap();
+        std::fs::create_dir_all(tempdir_path.join("baz").join("quux")).unwrap();
+
+        let request = Args {
+            root: tempdir_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![crate::fs::Glob::new(tempdir_path.join("foo")).unwrap()],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(path(&entries[0]), Some(tempdir_path.join("baz")));
+        assert_eq!(path(&entries[1]), Some(tempdir_path.join("baz").join("quux")));
+    }
+
+    // Symlinking is supported only on Unix-like systems.
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_dir_with_circular_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let root_path = tempdir.path().to_path_buf();
+        let dir_path = root_path.join("dir");
+        let symlink_path = dir_path.join("symlink");
+
+        std::fs::create_dir(&dir_path).unwrap();
+        std::os::unix::fs::symlink(&dir_path, &symlink_path).unwrap();
+
+        let request = Args {
+            root: root_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(path(&entries[0]), Some(dir_path));
+        assert_eq!(path(&entries[1]), Some(symlink_path));
+    }
+
+    #[test]
+    fn handle_dir_with_unicode_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let root_path = tempdir.path().to_path_buf();
+        let file_path_1 = root_path.join("zażółć gęślą jaźń");
+        let file_path_2 = root_path.join("што й па мору");
+
+        std::fs::File::create(&file_path_1).unwrap();
+        std::fs::File::create(&file_path_2).unwrap();
+
+        let request = Args {
+            root: root_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+
+        // macOS mangles Unicode-specific characters in filenames.
+        #[cfg(not(target_os = "macos"))]
+        {
+            assert_eq!(path(&entries[0]), Some(file_path_1));
+            assert_eq!(path(&entries[1]), Some(file_path_2));
+        }
+    }
+
+    #[test]
+    fn handle_parallel_same_as_serial() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        for i in 0..4 {
+            let dir = tempdir.path().join(format!("dir{i}"));
+            std::fs::create_dir(&dir).unwrap();
+
+            for j in 0..4 {
+                std::fs::write(dir.join(format!("file{j}")), b"foo").unwrap();
+            }
+        }
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        }).is_ok());
+
+        let args = crate::args::Args {
+            timeline_concurrency: 4,
+            ..Default::default()
+        };
+
+        let mut par_session = crate::session::FakeSession::with_args(args);
+        assert!(handle(&mut par_session, Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        }).is_ok());
+
+        // Access time of directories changes when they are listed, so we only
+        // compare fields that are stable across both walks.
+        fn key(entry: &rrg_proto::get_filesystem_timeline::Entry) -> (Vec<u8>, u64, i64) {
+            (entry.path().to_owned(), entry.size(), entry.mtime_nanos())
+        }
+
+        let mut par_keys = entries(&par_session).iter()
+            .map(key)
+            .collect::<Vec<_>>();
+        par_keys.sort();
+
+        let mut keys = entries(&session).iter()
+            .map(key)
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        assert_eq!(keys.len(), 4 * 5);
+        assert_eq!(par_keys, keys);
+    }
+
+    #[test]
+    fn handle_file_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"123456789").unwrap();
+
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(path(&entries[0]), Some(tempdir.path().join("foo")));
+        assert_eq!(entries[0].size(), 9);
+
+        // Information about the file mode, user and group identifiers is
+        // available only on UNIX systems.
+        #[cfg(target_family = "unix")]
+        {
+            let mode = entries[0].unix_mode() as libc::mode_t;
+            assert_eq!(mode & libc::S_IFMT, libc::S_IFREG);
+
+            let uid = unsafe { libc::getuid() };
+            assert_eq!(entries[0].unix_uid(), uid.into());
+
+            let gid = unsafe { libc::getgid() };
+            assert_eq!(entries[0].unix_gid(), gid.into());
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_ext_attrs_disabled() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"").unwrap();
+
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].ext_attrs.is_empty());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_minimal() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"1234").unwrap();
+        std::fs::set_permissions(tempdir.path().join("foo"), std::fs::Permissions::from_mode(0o640))
+            .unwrap();
+
+        // Options enabling optional information are set but overridden.
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: true,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: true,
+            minimal: true,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1);
+
+        let entry = &entries[0];
+        assert_eq!(path(entry), Some(tempdir.path().join("foo")));
+        assert_eq!(entry.size(), 4);
+        assert!(entry.has_mtime_nanos());
+        assert_eq!(entry.unix_mode(), i64::from(libc::S_IFREG));
+
+        assert!(entry.ext_attrs.is_empty());
+        assert!(!entry.has_type_mismatch());
+        assert!(!entry.has_atime_nanos());
+        assert!(!entry.has_ctime_nanos());
+        assert!(!entry.has_btime_nanos());
+        assert!(!entry.has_unix_ino());
+        assert!(!entry.has_unix_uid());
+        assert!(!entry.has_unix_gid());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "test-setfattr"))]
+    #[test]
+    fn handle_minimal_ext_attrs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"").unwrap();
+
+        assert! {
+            std::process::Command::new("setfattr")
+                .arg("--name").arg("user.quux")
+                .arg("--value").arg("norf")
+                .arg(tempdir.path().join("foo"))
+                .status().unwrap()
+                .success()
+        };
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(tempdir.path().to_path_buf().into());
+        proto.set_ext_attrs(true);
+        proto.set_minimal(true);
+
+        let request = <Args as crate::request::Args>::from_proto(proto)
+            .unwrap();
+        assert!(!request.ext_attrs);
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = entries(&session);
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].ext_attrs.is_empty());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "test-setfattr"))]
+    #[test]
+    fn handle_ext_attrs_enabled() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"").unwrap();
+        std::fs::write(tempdir.path().join("bar"), b"").unwrap();
+
+        assert! {
+            std::process::Command::new("setfattr")
+                .arg("--name").arg("user.quux")
+                .arg("--value").arg("norf")
+                .arg(tempdir.path().join("foo"))
+                .status().unwrap()
+                .success()
+        };
+
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: true,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(path(&entries[0]), Some(tempdir.path().join("bar")));
+        assert!(entries[0].ext_attrs.is_empty());
+
+        assert_eq!(path(&entries[1]), Some(tempdir.path().join("foo")));
+        assert_eq!(entries[1].ext_attrs.len(), 1);
+        assert_eq!(entries[1].ext_attrs[0].name(), b"user.quux");
+        assert_eq!(entries[1].ext_attrs[0].value(), b"norf");
+    }
+
+    #[test]
+    fn handle_hardlink_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let root_path = tempdir.path().to_path_buf();
+        let file_path = root_path.join("file");
+        let hardlink_path = root_path.join("hardlink");
+
+        std::fs::File::create(&file_path).unwrap();
+        std::fs::hard_link(&file_path, &hardlink_path).unwrap();
+
+        let request = Args {
+            root: root_path.clone(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(path(&entries[0]), Some(file_path));
+        assert_eq!(path(&entries[1]), Some(hardlink_path));
+
+        // Information about inode is not available on Windows.
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert_eq!(entries[0].unix_dev(), entries[1].unix_dev());
+            assert_eq!(entries[0].unix_ino(), entries[1].unix_ino());
+            assert_eq!(entries[0].unix_nlink(), 2);
+            assert_eq!(entries[1].unix_nlink(), 2);
+        }
+    }
+
+    #[test]
+    // Attributes are supported only on Windows.
+    #[cfg(target_family = "windows")]
+    fn handle_file_attributes() {
+        use std::os::windows::ffi::OsStrExt as _;
+        use windows_sys::Win32::Storage::FileSystem::*;
+
+        let temp_dir = tempfile::tempdir().unwrap();
 
         let temp_path = temp_dir.path().join("foo");
         std::fs::write(&temp_path, b"").unwrap();
@@ -388,6 +1558,19 @@ mod tests {
 
         let request = Args {
             root: temp_dir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Gzchunked,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
         };
 
         let mut session = crate::session::FakeSession::new();
@@ -401,6 +1584,486 @@ mod tests {
         assert_eq!(attributes & FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_HIDDEN);
     }
 
+    #[test]
+    fn handle_jsonl() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"123456789").unwrap();
+        std::fs::write(tempdir.path().join("bar \"quux\""), b"").unwrap();
+        std::fs::create_dir(tempdir.path().join("baz")).unwrap();
+
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Jsonl,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = jsonl_entries(&session);
+        entries.sort_by(|entry_a, entry_b| entry_a["path"].cmp(&entry_b["path"]));
+
+        assert_eq! {
+            entries.iter().map(|entry| entry["path"].clone()).collect::<Vec<_>>(),
+            vec![
+                JsonValue::String(tempdir.path().join("bar \"quux\"").display().to_string()),
+                JsonValue::String(tempdir.path().join("baz").display().to_string()),
+                JsonValue::String(tempdir.path().join("foo").display().to_string()),
+            ]
+        };
+
+        assert_eq!(entries[0]["size"], JsonValue::Number(0));
+        assert_eq!(entries[2]["size"], JsonValue::Number(9));
+        assert!(entries[2].contains_key("mtime_nanos"));
+
+        #[cfg(target_family = "unix")]
+        {
+            let mode = match entries[1]["unix_mode"] {
+                JsonValue::Number(mode) => mode as libc::mode_t,
+                _ => panic!("unexpected mode"),
+            };
+            assert_eq!(mode & libc::S_IFMT, libc::S_IFDIR);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_jsonl_non_utf8_path() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let path = tempdir.path().join(std::ffi::OsStr::from_bytes(b"foo\xffbar"));
+        std::fs::write(&path, b"").unwrap();
+
+        let request = Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Jsonl,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::default(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let entries = jsonl_entries(&session);
+        assert_eq!(entries.len(), 1);
+
+        let path_hex = path.as_os_str().as_bytes().iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        assert_eq!(entries[0]["path_hex"], JsonValue::String(path_hex));
+        assert_eq! {
+            entries[0]["path"],
+            JsonValue::String(path.to_string_lossy().into_owned())
+        };
+    }
+
+    #[test]
+    fn encode_jsonl_parts() {
+        let entries = (0..100).map(|i| {
+            let mut entry = rrg_proto::get_filesystem_timeline::Entry::default();
+            entry.set_path(format!("/foo/{i}").into_bytes());
+            entry.set_size(i);
+            entry
+        });
+
+        let parts = encode_jsonl(entries, 128).collect::<Vec<_>>();
+        assert!(parts.len() > 1);
+
+        let mut sizes = Vec::new();
+        for part in parts {
+            let part = String::from_utf8(part).unwrap();
+            assert!(part.ends_with('\n'));
+
+            for line in part.lines() {
+                match parse_json_object(line)["size"] {
+                    JsonValue::Number(size) => sizes.push(size),
+                    _ => panic!("unexpected size"),
+                }
+            }
+        }
+
+        assert_eq!(sizes, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("foo"), "\"foo\"");
+        assert_eq!(json_string("foo\"bar"), "\"foo\\\"bar\"");
+        assert_eq!(json_string("C:\\foo"), "\"C:\\\\foo\"");
+        assert_eq!(json_string("foo\nbar\u{1}"), "\"foo\\nbar\\u0001\"");
+        assert_eq!(json_string("zażółć"), "\"zażółć\"");
+    }
+
+    /// A value of a field of a parsed JSON timeline entry.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum JsonValue {
+        String(String),
+        Number(i128),
+    }
+
+    /// Parses a flat JSON object with string and integer values.
+    ///
+    /// This is a minimal parser sufficient for what the JSONL encoding of the
+    /// timeline produces, it panics on anything it does not understand.
+    fn parse_json_object(line: &str) -> std::collections::HashMap<String, JsonValue> {
+        fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+            assert_eq!(chars.next(), Some('"'));
+
+            let mut string = String::new();
+            loop {
+                match chars.next().unwrap() {
+                    '"' => return string,
+                    '\\' => match chars.next().unwrap() {
+                        '"' => string.push('"'),
+                        '\\' => string.push('\\'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => {
+                            let code = chars.by_ref().take(4).collect::<String>();
+                            let code = u32::from_str_radix(&code, 16).unwrap();
+                            string.push(char::from_u32(code).unwrap());
+                        }
+                        char => panic!("unexpected escape: {char:?}"),
+                    },
+                    char => string.push(char),
+                }
+            }
+        }
+
+        let mut chars = line.chars().peekable();
+        let mut object = std::collections::HashMap::new();
+
+        assert_eq!(chars.next(), Some('{'));
+        loop {
+            let name = parse_string(&mut chars);
+            assert_eq!(chars.next(), Some(':'));
+
+            let value = if chars.peek() == Some(&'"') {
+                JsonValue::String(parse_string(&mut chars))
+            } else {
+                let mut number = String::new();
+                while let Some(char) = chars.next_if(|char| *char == '-' || char.is_ascii_digit()) {
+                    number.push(char);
+                }
+                JsonValue::Number(number.parse().unwrap())
+            };
+            assert!(object.insert(name, value).is_none());
+
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                char => panic!("unexpected character: {char:?}"),
+            }
+        }
+        assert_eq!(chars.next(), None);
+
+        object
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_alternate_root() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path().canonicalize().unwrap();
+
+        std::fs::create_dir_all(tempdir.join("var").join("log")).unwrap();
+        std::fs::write(tempdir.join("var").join("log").join("foo.log"), b"foo").unwrap();
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/var").into());
+        proto.set_alternate_root(tempdir.clone().into());
+
+        let request = Args::from_proto(proto).unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(path(&entries[0]), Some(tempdir.join("var").join("log")));
+        assert_eq!(path(&entries[1]), Some(tempdir.join("var").join("log").join("foo.log")));
+        assert_eq!(entries[1].size(), 3);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_alternate_root_symlink_escape() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("/", tempdir.path().join("var")).unwrap();
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/var/log").into());
+        proto.set_alternate_root(tempdir.path().to_path_buf().into());
+
+        let request = Args::from_proto(proto).unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_image_root() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path().canonicalize().unwrap();
+
+        // `/var` of the image is an absolute symlink that has to be resolved
+        // within the image rather than against the filesystem of the machine.
+        std::fs::create_dir_all(tempdir.join("data").join("log")).unwrap();
+        std::fs::write(tempdir.join("data").join("log").join("foo.log"), b"foo").unwrap();
+        std::os::unix::fs::symlink("/data", tempdir.join("var")).unwrap();
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/var").into());
+        proto.set_image_root(tempdir.clone().into());
+
+        let request = Args::from_proto(proto).unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_ok());
+
+        let mut entries = entries(&session);
+        entries.sort_by_key(|entry| entry.path().to_owned());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(path(&entries[0]), Some(tempdir.join("data").join("log")));
+        assert_eq!(path(&entries[1]), Some(tempdir.join("data").join("log").join("foo.log")));
+        assert_eq!(entries[1].size(), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_image_root_symlink_escape() {
+        use crate::request::Args as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("../../../../../..", tempdir.path().join("var")).unwrap();
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/var/log").into());
+        proto.set_image_root(tempdir.path().to_path_buf().into());
+
+        let request = Args::from_proto(proto).unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, request).is_err());
+    }
+
+    #[test]
+    fn handle_compression_levels() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for i in 0..1024 {
+            let name = format!("very_long_and_very_compressible_file_name_{i:04}");
+            std::fs::write(tempdir.path().join(name), b"foo").unwrap();
+        }
+
+        let timeline = |level| {
+            let mut session = crate::session::FakeSession::new();
+            assert!(handle(&mut session, Args {
+                root: tempdir.path().to_path_buf(),
+                ext_attrs: false,
+                max_depth: u32::MAX,
+                excludes: vec![],
+                alternate_root: None,
+                #[cfg(target_os = "linux")]
+                image_root: None,
+                output_format: OutputFormat::Gzchunked,
+                since: None,
+                include_fs_types: vec![],
+                exclude_fs_types: vec![],
+                check_entry_types: false,
+                minimal: false,
+                compression: crate::gzchunked::Compression::new(level),
+            }).is_ok());
+
+            for item in session.replies::<Item>() {
+                assert_eq!(item.compression.map(|compression| compression.level()), Some(level));
+            }
+
+            session
+        };
+
+        let session_fast = timeline(1);
+        let session_best = timeline(9);
+
+        fn blobs_len(session: &crate::session::FakeSession) -> usize {
+            session.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+                .map(|blob| blob.as_bytes().len())
+                .sum()
+        }
+
+        assert!(blobs_len(&session_best) < blobs_len(&session_fast));
+
+        fn paths(session: &crate::session::FakeSession) -> Vec<PathBuf> {
+            let mut paths = entries(session).iter()
+                .map(|entry| path(entry).unwrap())
+                .collect::<Vec<_>>();
+            paths.sort();
+
+            paths
+        }
+
+        assert_eq!(paths(&session_fast).len(), 1024);
+        assert_eq!(paths(&session_fast), paths(&session_best));
+    }
+
+    #[test]
+    fn handle_jsonl_uncompressed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"foo").unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, Args {
+            root: tempdir.path().to_path_buf(),
+            ext_attrs: false,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            alternate_root: None,
+            #[cfg(target_os = "linux")]
+            image_root: None,
+            output_format: OutputFormat::Jsonl,
+            since: None,
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            check_entry_types: false,
+            minimal: false,
+            compression: crate::gzchunked::Compression::best(),
+        }).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert!(item.compression.is_none());
+    }
+
+    #[test]
+    fn args_compression_level() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/").into());
+        proto.set_compression_level(9);
+
+        let args = Args::from_proto(proto).unwrap();
+        assert_eq!(args.compression.level(), 9);
+    }
+
+    #[test]
+    fn args_compression_level_default() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/").into());
+
+        let args = Args::from_proto(proto).unwrap();
+        assert_eq!(args.compression.level(), 5);
+    }
+
+    #[test]
+    fn args_compression_level_invalid() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/").into());
+        proto.set_compression_level(10);
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[test]
+    fn args_compression_algorithm_zstd() {
+        use crate::request::Args as _;
+        use rrg_proto::get_filesystem_timeline::CompressionAlgorithm;
+
+        let mut proto = rrg_proto::get_filesystem_timeline::Args::default();
+        proto.set_root(PathBuf::from("/").into());
+        proto.set_compression_algorithm(CompressionAlgorithm::ZSTD);
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[test]
+    fn item_compression_into_proto() {
+        use crate::response::Item as _;
+        use rrg_proto::get_filesystem_timeline::CompressionAlgorithm;
+
+        let proto = Item {
+            blob_sha256: [0; 32],
+            entry_count: 0,
+            truncated_dirs: vec![],
+            timed_out_paths: vec![],
+            compression: Some(crate::gzchunked::Compression::new(7)),
+        }.into_proto();
+        assert_eq!(proto.compression_algorithm(), CompressionAlgorithm::GZIP);
+        assert_eq!(proto.compression_level(), 7);
+
+        let proto = Item {
+            blob_sha256: [0; 32],
+            entry_count: 0,
+            truncated_dirs: vec![],
+            timed_out_paths: vec![],
+            compression: None,
+        }.into_proto();
+        assert!(!proto.has_compression_algorithm());
+        assert!(!proto.has_compression_level());
+    }
+
+    /// Retrieves timeline entries in the JSONL format from the given session.
+    fn jsonl_entries(
+        session: &crate::session::FakeSession,
+    ) -> Vec<std::collections::HashMap<String, JsonValue>> {
+        let blob_count = session.parcel_count(crate::Sink::Blob);
+        let reply_count = session.reply_count();
+        assert_eq!(blob_count, reply_count);
+
+        let mut entries = Vec::new();
+        for blob in session.parcels::<crate::blob::Blob>(crate::Sink::Blob) {
+            let part = std::str::from_utf8(blob.as_bytes()).unwrap();
+            assert!(part.ends_with('\n'));
+
+            entries.extend(part.lines().map(parse_json_object));
+        }
+
+        let total_entry_count = session.replies::<Item>()
+            .map(|item| item.entry_count)
+            .sum();
+
+        assert_eq!(entries.len(), total_entry_count);
+
+        entries
+    }
+
     /// Retrieves timeline entries from the given session object.
     fn entries(
         session: &crate::session::FakeSession,
@@ -0,0 +1,968 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of entries of the systemd journal.
+//!
+//! Journal files are parsed directly (following the [journal file format])
+//! rather than through `libsystemd`, so that the agent does not depend on it.
+//! Objects of every file are scanned sequentially, so neither hash tables nor
+//! entry arrays have to be trusted. Compressed fields are skipped and so are
+//! entries that turn out to be malformed.
+//!
+//! [journal file format]: https://systemd.io/JOURNAL_FILE_FORMAT/
+
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+/// Default limit on the number of returned entries.
+#[cfg(target_os = "linux")]
+const DEFAULT_MAX_ENTRIES: u64 = 1000;
+
+/// Default limit on the total size of fields of returned entries.
+#[cfg(target_os = "linux")]
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024; // 1 MiB.
+
+/// Maximum size of a journal object that we are willing to read.
+///
+/// Objects are never this big in practice (journald limits the size of fields
+/// to much less than that), so bigger ones mean that the file is corrupted.
+#[cfg(target_os = "linux")]
+const MAX_OBJECT_SIZE: u64 = 64 * 1024 * 1024; // 64 MiB.
+
+/// Signature at the beginning of every journal file.
+#[cfg(target_os = "linux")]
+const JOURNAL_SIGNATURE: &[u8; 8] = b"LPKSHHRH";
+
+/// Size of the journal file header fields that we need to read.
+#[cfg(target_os = "linux")]
+const JOURNAL_HEADER_MIN_SIZE: usize = 144;
+
+/// Incompatible flag of journal files with the compact layout of objects.
+#[cfg(target_os = "linux")]
+const JOURNAL_INCOMPATIBLE_COMPACT: u32 = 1 << 4;
+
+/// All incompatible flags of journal files that we know how to handle.
+///
+/// Flags indicating compression or keyed hashes do not affect the layout of
+/// objects, so we can read such files (skipping compressed fields).
+#[cfg(target_os = "linux")]
+const JOURNAL_INCOMPATIBLE_SUPPORTED: u32 = 0b11111;
+
+/// Size of the header of every journal object.
+#[cfg(target_os = "linux")]
+const OBJECT_HEADER_SIZE: u64 = 16;
+
+/// Type of journal objects with field data.
+#[cfg(target_os = "linux")]
+const OBJECT_TYPE_DATA: u8 = 1;
+
+/// Type of journal objects with entries.
+#[cfg(target_os = "linux")]
+const OBJECT_TYPE_ENTRY: u8 = 3;
+
+/// Flags of journal objects indicating that their payload is compressed.
+#[cfg(target_os = "linux")]
+const OBJECT_COMPRESSED_MASK: u8 = 0b111;
+
+/// Arguments of the `get_journal_entries` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Directories with journal files to read.
+    journal_dirs: Vec<PathBuf>,
+    /// Time before which entries are not returned (if any).
+    start_time: Option<std::time::SystemTime>,
+    /// Time after which entries are not returned (if any).
+    end_time: Option<std::time::SystemTime>,
+    /// Units to return the entries of (all if empty).
+    units: Vec<String>,
+    /// Maximum priority of entries to return (if any).
+    max_priority: Option<u32>,
+    /// Maximum number of entries to return.
+    max_entries: u64,
+    /// Maximum total size of fields of returned entries.
+    max_size: u64,
+}
+
+/// Result of the `get_journal_entries` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Journal entry to report.
+    entry: Entry,
+}
+
+/// A single entry of the journal.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct Entry {
+    /// Time (in microseconds since epoch) at which the entry was added.
+    realtime: u64,
+    /// Fields of the entry as pairs of names and values.
+    fields: Vec<(String, Vec<u8>)>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetJournalEntries;
+
+/// Handles invocations of the `get_journal_entries` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `get_journal_entries` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let journal_dirs = if args.journal_dirs.is_empty() {
+        vec![PathBuf::from("/var/log/journal"), PathBuf::from("/run/log/journal")]
+    } else {
+        args.journal_dirs.clone()
+    };
+
+    // We keep only the most recent entries that fit in the limits. The heap is
+    // ordered so that the oldest entry is on top and can be evicted quickly.
+    let mut entries = std::collections::BinaryHeap::new();
+    let mut entries_size = 0;
+
+    for path in journal_files(&journal_dirs) {
+        let file = match JournalFile::open(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                log::warn!("failed to open journal file '{}': {error}", path.display());
+                continue;
+            }
+        };
+
+        for entry in file.entries() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    log::warn!("malformed journal file '{}': {error}", path.display());
+                    break;
+                }
+            };
+
+            if !args.matches_time(entry.realtime) {
+                continue;
+            }
+
+            let entry = match file.read_entry(&entry) {
+                Ok(entry) => entry,
+                Err(error) => {
+                    log::warn! {
+                        "skipping malformed journal entry at {} in '{}': {error}",
+                        entry.offset,
+                        path.display(),
+                    };
+                    continue;
+                }
+            };
+
+            if !args.matches_fields(&entry) {
+                continue;
+            }
+
+            entries_size += entry.size();
+            entries.push(std::cmp::Reverse(ByTime(entry)));
+
+            while entries.len() as u64 > args.max_entries || entries_size > args.max_size {
+                let Some(std::cmp::Reverse(ByTime(oldest))) = entries.pop() else {
+                    break;
+                };
+                entries_size -= oldest.size();
+            }
+        }
+    }
+
+    // Sorting the reversed entries gives the newest first, so we have to turn
+    // the order around again.
+    for std::cmp::Reverse(ByTime(entry)) in entries.into_sorted_vec().into_iter().rev() {
+        session.reply(Item {
+            entry,
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+impl Args {
+
+    /// Checks whether entries added at the given time should be returned.
+    fn matches_time(&self, realtime: u64) -> bool {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_micros(realtime);
+
+        if self.start_time.is_some_and(|start_time| time < start_time) {
+            return false;
+        }
+        if self.end_time.is_some_and(|end_time| time > end_time) {
+            return false;
+        }
+
+        true
+    }
+
+    /// Checks whether the given entry matches the unit and priority filters.
+    fn matches_fields(&self, entry: &Entry) -> bool {
+        // Similarly to `journalctl --unit`, we consider both messages from the
+        // unit itself and messages from systemd about the unit.
+        if !self.units.is_empty() {
+            let is_unit_matched = entry.fields.iter().any(|(name, value)| {
+                (name == "_SYSTEMD_UNIT" || name == "UNIT") &&
+                self.units.iter().any(|unit| unit.as_bytes() == value.as_slice())
+            });
+            if !is_unit_matched {
+                return false;
+            }
+        }
+
+        if let Some(max_priority) = self.max_priority {
+            let priority = entry.field("PRIORITY")
+                .and_then(|value| std::str::from_utf8(value).ok())
+                .and_then(|value| value.parse::<u32>().ok());
+            match priority {
+                Some(priority) if priority <= max_priority => (),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Entry {
+
+    /// Returns the value of the first field with the given name (if any).
+    fn field(&self, name: &str) -> Option<&[u8]> {
+        self.fields.iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the total size of the fields of the entry.
+    fn size(&self) -> u64 {
+        self.fields.iter()
+            .map(|(name, value)| (name.len() + value.len()) as u64)
+            .sum()
+    }
+}
+
+/// Wrapper ordering entries by the time they were added at.
+#[cfg(target_os = "linux")]
+struct ByTime(Entry);
+
+#[cfg(target_os = "linux")]
+impl PartialEq for ByTime {
+
+    fn eq(&self, other: &ByTime) -> bool {
+        self.0.realtime == other.0.realtime
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Eq for ByTime {
+}
+
+#[cfg(target_os = "linux")]
+impl PartialOrd for ByTime {
+
+    fn partial_cmp(&self, other: &ByTime) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Ord for ByTime {
+
+    fn cmp(&self, other: &ByTime) -> std::cmp::Ordering {
+        self.0.realtime.cmp(&other.0.realtime)
+    }
+}
+
+/// Returns paths of journal files in the given directories.
+///
+/// journald stores files in subdirectories named after the machine identifier,
+/// so immediate subdirectories are searched as well.
+#[cfg(target_os = "linux")]
+fn journal_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    fn list(dir: &std::path::Path, depth: u32, paths: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                log::debug!("failed to list journal directory '{}': {error}", dir.display());
+                return;
+            }
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    log::warn!("failed to read journal directory entry: {error}");
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(error) => {
+                    log::warn!("failed to obtain type of '{}': {error}", path.display());
+                    continue;
+                }
+            };
+
+            if file_type.is_dir() && depth == 0 {
+                list(&path, depth + 1, paths);
+            } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "journal") {
+                paths.push(path);
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    for dir in dirs {
+        list(dir, 0, &mut paths);
+    }
+    paths.sort();
+
+    paths
+}
+
+/// Journal file opened for reading.
+#[cfg(target_os = "linux")]
+struct JournalFile {
+    /// Underlying file handle.
+    file: std::fs::File,
+    /// Length of the file (at the time it was opened).
+    len: u64,
+    /// Offset of the first object of the file.
+    header_size: u64,
+    /// Offset of the last object of the file.
+    tail_object_offset: u64,
+    /// Whether objects of the file use the compact layout.
+    compact: bool,
+}
+
+/// Entry object of a journal file.
+#[cfg(target_os = "linux")]
+struct EntryObject {
+    /// Offset of the object within the file.
+    offset: u64,
+    /// Time (in microseconds since epoch) at which the entry was added.
+    realtime: u64,
+    /// Offsets of data objects with the fields of the entry.
+    data_offsets: Vec<u64>,
+}
+
+/// Iterator over entry objects of a journal file.
+#[cfg(target_os = "linux")]
+struct EntryObjects<'a> {
+    /// File the objects are read from.
+    file: &'a JournalFile,
+    /// Offset of the next object to read (`None` if finished).
+    offset: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl JournalFile {
+
+    /// Opens the journal file at the given path.
+    fn open(path: &std::path::Path) -> std::io::Result<JournalFile> {
+        use std::io::Read as _;
+
+        let mut file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let mut header = [0; JOURNAL_HEADER_MIN_SIZE];
+        file.read_exact(&mut header)?;
+
+        JournalFile::from_header(file, len, &header)
+    }
+
+    /// Creates a journal file given its (already read) header.
+    fn from_header(
+        file: std::fs::File,
+        len: u64,
+        header: &[u8; JOURNAL_HEADER_MIN_SIZE],
+    ) -> std::io::Result<JournalFile> {
+        if &header[0..8] != JOURNAL_SIGNATURE {
+            return Err(invalid_data("invalid signature"));
+        }
+
+        let incompatible_flags = u32_le(&header[12..16]);
+        if incompatible_flags & !JOURNAL_INCOMPATIBLE_SUPPORTED != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("unsupported incompatible flags: {incompatible_flags:#x}"),
+            ));
+        }
+
+        let header_size = u64_le(&header[88..96]);
+        if header_size < JOURNAL_HEADER_MIN_SIZE as u64 {
+            return Err(invalid_data("header too small"));
+        }
+
+        Ok(JournalFile {
+            file,
+            len,
+            header_size,
+            tail_object_offset: u64_le(&header[136..144]),
+            compact: incompatible_flags & JOURNAL_INCOMPATIBLE_COMPACT != 0,
+        })
+    }
+
+    /// Returns an iterator over entry objects of the file.
+    fn entries(&self) -> EntryObjects<'_> {
+        EntryObjects {
+            file: self,
+            offset: Some(self.header_size),
+        }
+    }
+
+    /// Reads fields of the given entry object.
+    fn read_entry(&self, entry: &EntryObject) -> std::io::Result<Entry> {
+        let mut fields = Vec::with_capacity(entry.data_offsets.len());
+
+        for data_offset in &entry.data_offsets {
+            let (object_type, flags, data) = self.read_object(*data_offset)?;
+            if object_type != OBJECT_TYPE_DATA {
+                return Err(invalid_data("entry item is not a data object"));
+            }
+
+            // We do not support decompression, but compressed fields are normal
+            // (e.g. big messages) so they are not an error.
+            if flags & OBJECT_COMPRESSED_MASK != 0 {
+                log::debug!("skipping compressed journal field at {data_offset}");
+                continue;
+            }
+
+            let payload_offset = if self.compact { 56 } else { 48 };
+            let payload = data.get(payload_offset..)
+                .ok_or_else(|| invalid_data("data object too small"))?;
+
+            let separator = payload.iter().position(|byte| *byte == b'=')
+                .ok_or_else(|| invalid_data("field without separator"))?;
+
+            let name = std::str::from_utf8(&payload[..separator])
+                .map_err(|_| invalid_data("non-UTF-8 field name"))?;
+
+            fields.push((name.to_string(), payload[separator + 1..].to_vec()));
+        }
+
+        Ok(Entry {
+            realtime: entry.realtime,
+            fields,
+        })
+    }
+
+    /// Reads the object at the given offset.
+    ///
+    /// The object is returned as its type, flags and data (everything that
+    /// follows the object header).
+    fn read_object(&self, offset: u64) -> std::io::Result<(u8, u8, Vec<u8>)> {
+        use std::os::unix::fs::FileExt as _;
+
+        if offset < self.header_size || !offset.is_multiple_of(8) || offset > self.tail_object_offset {
+            return Err(invalid_data("invalid object offset"));
+        }
+
+        let mut header = [0; OBJECT_HEADER_SIZE as usize];
+        self.file.read_exact_at(&mut header, offset)?;
+
+        let size = u64_le(&header[8..16]);
+        if !(OBJECT_HEADER_SIZE..=MAX_OBJECT_SIZE).contains(&size) {
+            return Err(invalid_data("invalid object size"));
+        }
+        if offset.checked_add(size).is_none_or(|end| end > self.len) {
+            return Err(invalid_data("object beyond end of file"));
+        }
+
+        let mut data = vec![0; (size - OBJECT_HEADER_SIZE) as usize];
+        self.file.read_exact_at(&mut data, offset + OBJECT_HEADER_SIZE)?;
+
+        Ok((header[0], header[1], data))
+    }
+
+    /// Parses an entry object from the data of the object at the given offset.
+    fn parse_entry(&self, offset: u64, data: &[u8]) -> std::io::Result<EntryObject> {
+        // Entry objects start with sequence number, realtime and monotonic
+        // timestamps, boot identifier and hash, followed by the items.
+        let items = data.get(48..)
+            .ok_or_else(|| invalid_data("entry object too small"))?;
+
+        let data_offsets = if self.compact {
+            items.chunks_exact(4).map(|item| u64::from(u32_le(item))).collect()
+        } else {
+            items.chunks_exact(16).map(|item| u64_le(&item[0..8])).collect()
+        };
+
+        Ok(EntryObject {
+            offset,
+            realtime: u64_le(&data[8..16]),
+            data_offsets,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> Iterator for EntryObjects<'a> {
+
+    type Item = std::io::Result<EntryObject>;
+
+    fn next(&mut self) -> Option<std::io::Result<EntryObject>> {
+        loop {
+            let offset = self.offset?;
+            if offset > self.file.tail_object_offset {
+                self.offset = None;
+                return None;
+            }
+
+            let (object_type, data) = match self.file.read_object(offset) {
+                Ok((object_type, _, data)) => (object_type, data),
+                Err(error) => {
+                    // There is no way to find the next object if the current
+                    // one is malformed, so we have to stop.
+                    self.offset = None;
+                    return Some(Err(error));
+                }
+            };
+
+            // Objects are aligned to 8 bytes. Reading the object guarantees
+            // that its end does not overflow.
+            let size = OBJECT_HEADER_SIZE + data.len() as u64;
+            self.offset = Some(offset + size.next_multiple_of(8));
+
+            if object_type == OBJECT_TYPE_ENTRY {
+                return Some(self.file.parse_entry(offset, &data));
+            }
+        }
+    }
+}
+
+/// Parses a little-endian 32-bit integer from the given 4 bytes.
+#[cfg(target_os = "linux")]
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("invalid integer length"))
+}
+
+/// Parses a little-endian 64-bit integer from the given 8 bytes.
+#[cfg(target_os = "linux")]
+fn u64_le(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().expect("invalid integer length"))
+}
+
+/// Creates an error indicating malformed journal file.
+#[cfg(target_os = "linux")]
+fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Parses the given timestamp message (if present).
+#[cfg(target_os = "linux")]
+fn parse_time(
+    name: &'static str,
+    timestamp: Option<protobuf::well_known_types::timestamp::Timestamp>,
+) -> Result<Option<std::time::SystemTime>, crate::request::ParseArgsError> {
+    let Some(timestamp) = timestamp else {
+        return Ok(None);
+    };
+
+    // Timestamp is (a bit simplifying) just a duration since epoch, so we
+    // reuse the existing conversion routine for durations.
+    let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+    since_epoch.seconds = timestamp.seconds;
+    since_epoch.nanos = timestamp.nanos;
+
+    let since_epoch = rrg_proto::try_from_duration(since_epoch)
+        .map_err(|error| crate::request::ParseArgsError::invalid_field(name, error))?;
+
+    Ok(Some(std::time::UNIX_EPOCH + since_epoch))
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_journal_entries::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let journal_dirs = proto.take_journal_dirs().into_iter()
+            .map(PathBuf::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| ParseArgsError::invalid_field("journal_dirs", error))?;
+
+        Ok(Args {
+            journal_dirs,
+            start_time: parse_time("start_time", proto.start_time.take())?,
+            end_time: parse_time("end_time", proto.end_time.take())?,
+            units: proto.take_units(),
+            max_priority: proto.max_priority,
+            max_entries: proto.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+            max_size: proto.max_size.unwrap_or(DEFAULT_MAX_SIZE),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_journal_entries::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_micros(self.entry.realtime);
+
+        let mut proto = Self::Proto::default();
+        proto.set_time(rrg_proto::into_timestamp(time));
+        proto.set_fields(self.entry.fields.into_iter().map(|(name, value)| {
+            let mut proto = rrg_proto::get_journal_entries::Field::default();
+            proto.set_name(name);
+            proto.set_value(value);
+
+            proto
+        }).collect());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    /// Builder of journal files for tests.
+    struct JournalBuilder {
+        /// Whether to use the compact layout of objects.
+        compact: bool,
+        /// Objects of the journal file (following the header).
+        objects: Vec<u8>,
+        /// Offset of the last object of the file.
+        tail_object_offset: u64,
+    }
+
+    /// Size of the header of journal files created by the builder.
+    const BUILDER_HEADER_SIZE: u64 = 256;
+
+    impl JournalBuilder {
+
+        fn new(compact: bool) -> JournalBuilder {
+            JournalBuilder {
+                compact,
+                objects: Vec::new(),
+                tail_object_offset: 0,
+            }
+        }
+
+        fn object(&mut self, object_type: u8, flags: u8, data: &[u8]) -> u64 {
+            let offset = BUILDER_HEADER_SIZE + self.objects.len() as u64;
+
+            self.objects.push(object_type);
+            self.objects.push(flags);
+            self.objects.extend_from_slice(&[0; 6]);
+            self.objects.extend_from_slice(&(OBJECT_HEADER_SIZE + data.len() as u64).to_le_bytes());
+            self.objects.extend_from_slice(data);
+            while !self.objects.len().is_multiple_of(8) {
+                self.objects.push(0);
+            }
+
+            self.tail_object_offset = offset;
+            offset
+        }
+
+        fn data(&mut self, payload: &[u8], flags: u8) -> u64 {
+            // Hash, offsets of the next hash and field objects, offsets of
+            // entries and entry arrays and the number of entries (plus the
+            // tail entry array information in the compact mode).
+            let mut data = vec![0; if self.compact { 56 } else { 48 }];
+            data.extend_from_slice(payload);
+
+            self.object(OBJECT_TYPE_DATA, flags, &data)
+        }
+
+        fn entry(&mut self, realtime: u64, data_offsets: &[u64]) -> u64 {
+            let mut data = Vec::new();
+            data.extend_from_slice(&1u64.to_le_bytes()); // Sequence number.
+            data.extend_from_slice(&realtime.to_le_bytes());
+            data.extend_from_slice(&[0; 8]); // Monotonic timestamp.
+            data.extend_from_slice(&[0; 16]); // Boot identifier.
+            data.extend_from_slice(&[0; 8]); // Hash.
+            for data_offset in data_offsets {
+                if self.compact {
+                    data.extend_from_slice(&(*data_offset as u32).to_le_bytes());
+                } else {
+                    data.extend_from_slice(&data_offset.to_le_bytes());
+                    data.extend_from_slice(&[0; 8]); // Hash.
+                }
+            }
+
+            self.object(OBJECT_TYPE_ENTRY, 0, &data)
+        }
+
+        /// Adds an entry with a message from the given unit.
+        fn message(&mut self, realtime: u64, unit: &str, priority: u32, message: &str) -> u64 {
+            let offsets = [
+                self.data(format!("MESSAGE={message}").as_bytes(), 0),
+                self.data(format!("PRIORITY={priority}").as_bytes(), 0),
+                self.data(format!("_SYSTEMD_UNIT={unit}").as_bytes(), 0),
+            ];
+
+            self.entry(realtime, &offsets)
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let mut header = vec![0; BUILDER_HEADER_SIZE as usize];
+            header[0..8].copy_from_slice(JOURNAL_SIGNATURE);
+            if self.compact {
+                header[12..16].copy_from_slice(&JOURNAL_INCOMPATIBLE_COMPACT.to_le_bytes());
+            }
+            header[88..96].copy_from_slice(&BUILDER_HEADER_SIZE.to_le_bytes());
+            header[136..144].copy_from_slice(&self.tail_object_offset.to_le_bytes());
+
+            [header, self.objects.clone()].concat()
+        }
+    }
+
+    fn args(journal_dir: &std::path::Path) -> Args {
+        Args {
+            journal_dirs: vec![journal_dir.to_path_buf()],
+            start_time: None,
+            end_time: None,
+            units: vec![],
+            max_priority: None,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    fn messages(session: &crate::session::FakeSession) -> Vec<&[u8]> {
+        session.replies::<Item>()
+            .map(|item| item.entry.field("MESSAGE").unwrap())
+            .collect()
+    }
+
+    fn micros(secs: u64) -> u64 {
+        secs * 1_000_000
+    }
+
+    fn time(secs: u64) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+    }
+
+    /// Writes a sample journal with messages at seconds 10, 20, 30 and 40.
+    fn write_sample(dir: &std::path::Path, compact: bool) {
+        let mut builder = JournalBuilder::new(compact);
+        builder.message(micros(10), "sshd.service", 6, "foo");
+        builder.message(micros(20), "cron.service", 3, "bar");
+        builder.message(micros(30), "sshd.service", 3, "baz");
+        builder.message(micros(40), "cron.service", 6, "quux");
+
+        // journald puts files in directories named after the machine.
+        let machine_dir = dir.join("0123456789abcdef0123456789abcdef");
+        std::fs::create_dir(&machine_dir)
+            .unwrap();
+        std::fs::write(machine_dir.join("system.journal"), builder.build())
+            .unwrap();
+    }
+
+    #[test]
+    fn handle_sample_all() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), false);
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path())).is_ok());
+
+        assert_eq!(messages(&session), vec![
+            b"foo".as_slice(),
+            b"bar".as_slice(),
+            b"baz".as_slice(),
+            b"quux".as_slice(),
+        ]);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.entry.realtime, micros(10));
+        assert_eq!(item.entry.field("_SYSTEMD_UNIT"), Some(b"sshd.service".as_slice()));
+        assert_eq!(item.entry.field("PRIORITY"), Some(b"6".as_slice()));
+    }
+
+    #[test]
+    fn handle_sample_compact() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), true);
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path())).is_ok());
+
+        assert_eq!(messages(&session).len(), 4);
+    }
+
+    #[test]
+    fn handle_sample_time_range() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), false);
+
+        let args = Args {
+            start_time: Some(time(15)),
+            end_time: Some(time(30)),
+            ..args(tempdir.path())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(messages(&session), vec![
+            b"bar".as_slice(),
+            b"baz".as_slice(),
+        ]);
+    }
+
+    #[test]
+    fn handle_sample_unit_and_priority() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), false);
+
+        let args = Args {
+            units: vec![String::from("sshd.service")],
+            max_priority: Some(3),
+            ..args(tempdir.path())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(messages(&session), vec![
+            b"baz".as_slice(),
+        ]);
+    }
+
+    #[test]
+    fn handle_sample_max_entries() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), false);
+
+        let args = Args {
+            max_entries: 2,
+            ..args(tempdir.path())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        // Only the most recent entries should be returned.
+        assert_eq!(messages(&session), vec![
+            b"baz".as_slice(),
+            b"quux".as_slice(),
+        ]);
+    }
+
+    #[test]
+    fn handle_sample_max_size() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        write_sample(tempdir.path(), false);
+
+        // Each entry has 3 fields of about 10 to 30 bytes, so that should fit
+        // only the last one.
+        let args = Args {
+            max_size: 60,
+            ..args(tempdir.path())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(messages(&session), vec![
+            b"quux".as_slice(),
+        ]);
+    }
+
+    #[test]
+    fn handle_malformed() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut builder = JournalBuilder::new(false);
+        builder.message(micros(10), "sshd.service", 6, "foo");
+
+        // Entry pointing at a non-data object should be skipped.
+        let entry = builder.message(micros(20), "sshd.service", 6, "bar");
+        builder.entry(micros(30), &[entry]);
+
+        // Compressed fields should be skipped but the entry still reported.
+        let message = builder.data(b"MESSAGE=\x00\x01\x02", 0b100);
+        let unit = builder.data(b"_SYSTEMD_UNIT=sshd.service", 0);
+        builder.entry(micros(40), &[message, unit]);
+
+        let mut data = builder.build();
+        // Truncated object at the end should stop the parsing.
+        data.extend_from_slice(&[OBJECT_TYPE_ENTRY, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&1024u64.to_le_bytes());
+        let tail_object_offset = BUILDER_HEADER_SIZE + builder.objects.len() as u64;
+        data[136..144].copy_from_slice(&tail_object_offset.to_le_bytes());
+
+        std::fs::write(tempdir.path().join("system.journal"), data)
+            .unwrap();
+        // Files that are not journals should be ignored.
+        std::fs::write(tempdir.path().join("other.journal"), b"foobar")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path())).is_ok());
+
+        assert_eq!(session.reply_count(), 3);
+        assert_eq!(session.reply::<Item>(0).entry.field("MESSAGE"), Some(b"foo".as_slice()));
+        assert_eq!(session.reply::<Item>(1).entry.field("MESSAGE"), Some(b"bar".as_slice()));
+
+        let item = session.reply::<Item>(2);
+        assert_eq!(item.entry.realtime, micros(40));
+        assert_eq!(item.entry.field("MESSAGE"), None);
+        assert_eq!(item.entry.field("_SYSTEMD_UNIT"), Some(b"sshd.service".as_slice()));
+    }
+
+    #[test]
+    fn handle_system_time_range() {
+        let journal_dirs = vec![PathBuf::from("/var/log/journal"), PathBuf::from("/run/log/journal")];
+        if journal_files(&journal_dirs).is_empty() {
+            // There is no journald on the system, we cannot test anything.
+            return;
+        }
+
+        let start_time = std::time::SystemTime::now() - std::time::Duration::from_secs(24 * 60 * 60);
+
+        let args = Args {
+            journal_dirs,
+            start_time: Some(start_time),
+            end_time: None,
+            units: vec![],
+            max_priority: None,
+            max_entries: 64,
+            max_size: DEFAULT_MAX_SIZE,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.reply_count() <= 64);
+        for item in session.replies::<Item>() {
+            let time = std::time::UNIX_EPOCH + std::time::Duration::from_micros(item.entry.realtime);
+            assert!(time >= start_time);
+        }
+    }
+}
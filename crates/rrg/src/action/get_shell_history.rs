@@ -0,0 +1,274 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::path::{Path, PathBuf};
+
+/// Default limit on the number of bytes read from a single history file.
+const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024; // 1 MiB.
+
+/// Paths (relative to the home directory) of known shell history files.
+const HISTORY_FILES: &[&str] = &[
+    ".bash_history",
+    ".zsh_history",
+    ".zhistory",
+    ".sh_history",
+    ".ash_history",
+    ".history",
+    ".local/share/fish/fish_history",
+    "AppData/Roaming/Microsoft/Windows/PowerShell/PSReadLine/ConsoleHost_history.txt",
+];
+
+/// Arguments of the `get_shell_history` action.
+pub struct Args {
+    /// Maximum number of bytes to read from each of the history files.
+    max_file_size: u64,
+}
+
+/// Result of the `get_shell_history` action.
+struct Item {
+    /// Name of the user that owns the history file.
+    user: std::ffi::OsString,
+    /// Path to the history file the line comes from.
+    path: PathBuf,
+    /// Line of the history file.
+    line: String,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetShellHistory;
+
+/// Handles invocations of the `get_shell_history` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let users = ospect::os::users()
+        .map_err(crate::session::Error::action)?;
+
+    for user in users {
+        handle_user(session, &user, args.max_file_size)?;
+    }
+
+    Ok(())
+}
+
+/// Collects history of the given `user` and replies with its lines.
+fn handle_user<S>(
+    session: &mut S,
+    user: &ospect::os::User,
+    max_file_size: u64,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    // There are a lot of system accounts with non-existent home directories
+    // (e.g. `/nonexistent`), we do not want to spam logs for them.
+    match std::fs::read_dir(&user.home) {
+        Ok(_) => (),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(());
+        }
+        Err(error) => {
+            log::warn! {
+                "failed to read home directory '{}' of user '{}': {}",
+                user.home.display(), user.name.to_string_lossy(), error,
+            };
+            return Ok(());
+        }
+    }
+
+    for history_file in HISTORY_FILES {
+        let path = user.home.join(history_file);
+
+        let lines = match read_tail_lines(&path, max_file_size) {
+            Ok(lines) => lines,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(error) => {
+                log::warn! {
+                    "failed to read history file '{}': {}",
+                    path.display(), error,
+                };
+                continue;
+            }
+        };
+
+        for line in lines {
+            session.reply(Item {
+                user: user.name.clone(),
+                path: path.clone(),
+                line,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads lines from the last `max_size` bytes of the file at the given `path`.
+///
+/// If the file is bigger than `max_size`, the first (likely incomplete) line of
+/// the read part is discarded.
+fn read_tail_lines(path: &Path, max_size: u64) -> std::io::Result<Vec<String>> {
+    use std::io::{Read as _, Seek as _};
+
+    let mut file = std::fs::File::open(path)?;
+
+    let size = file.metadata()?.len();
+    let truncated = size > max_size;
+    if truncated {
+        file.seek(std::io::SeekFrom::Start(size - max_size))?;
+    }
+
+    let mut buf = Vec::new();
+    file.take(max_size).read_to_end(&mut buf)?;
+
+    let mut lines = buf.split(|byte| *byte == b'\n');
+    if truncated {
+        lines.next();
+    }
+
+    Ok(lines
+        .filter(|line| !line.is_empty())
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::get_shell_history::Args;
+
+    fn from_proto(proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        let max_file_size = match proto.max_file_size() {
+            0 => DEFAULT_MAX_FILE_SIZE,
+            max_file_size => max_file_size,
+        };
+
+        Ok(Args {
+            max_file_size,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_shell_history::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_user(self.user.to_string_lossy().into_owned());
+        proto.set_path(self.path.into());
+        proto.set_line(self.line);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_user_bash_history() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join(".bash_history"), b"ls\ncd /tmp\n")
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user, DEFAULT_MAX_FILE_SIZE)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.user, "foo");
+        assert_eq!(item.path, tempdir.path().join(".bash_history"));
+        assert_eq!(item.line, "ls");
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.user, "foo");
+        assert_eq!(item.path, tempdir.path().join(".bash_history"));
+        assert_eq!(item.line, "cd /tmp");
+    }
+
+    #[test]
+    fn handle_user_multiple_history_files() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join(".bash_history"), b"ls\n")
+            .unwrap();
+        std::fs::write(tempdir.path().join(".zsh_history"), b"pwd\n")
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user, DEFAULT_MAX_FILE_SIZE)
+            .unwrap();
+
+        let mut items = session.replies::<Item>()
+            .map(|item| (item.path.clone(), item.line.as_str()))
+            .collect::<Vec<_>>();
+        items.sort();
+
+        assert_eq!(items, vec![
+            (tempdir.path().join(".bash_history"), "ls"),
+            (tempdir.path().join(".zsh_history"), "pwd"),
+        ]);
+    }
+
+    #[test]
+    fn handle_user_max_file_size() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join(".bash_history"), b"foo\nbar\nbaz\n")
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user, 6)
+            .unwrap();
+
+        // Only the last 6 bytes (`r\nbaz\n`) are read and the incomplete line
+        // is discarded.
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).line, "baz");
+    }
+
+    #[test]
+    fn handle_user_missing_home() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().join("nonexistent"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user, DEFAULT_MAX_FILE_SIZE)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 0);
+    }
+}
@@ -18,6 +18,8 @@ struct Item {
     fqdn: Option<std::ffi::OsString>,
     /// Estimated time at which the operating system was installed.
     installed: Option<std::time::SystemTime>,
+    /// Unique identifier of the machine the agent is running on.
+    machine_id: Option<ospect::os::MachineId>,
 }
 
 impl Item {
@@ -59,6 +61,13 @@ impl Item {
                 None
             }
         };
+        let machine_id = match ospect::os::machine_id() {
+            Ok(machine_id) => Some(machine_id),
+            Err(error) => {
+                error!("failed to collect machine identifier: {error}");
+                None
+            }
+        };
 
         Ok(Item {
             kind: ospect::os::kind(),
@@ -67,6 +76,7 @@ impl Item {
             hostname,
             fqdn,
             installed,
+            machine_id,
         })
     }
 }
@@ -95,12 +105,19 @@ impl crate::response::Item for Item {
         if let Some(installed) = self.installed {
             proto.set_install_time(into_timestamp(installed));
         }
+        if let Some(machine_id) = self.machine_id {
+            proto.set_machine_id(machine_id.value);
+            proto.set_machine_id_source(machine_id.source.into());
+        }
 
         proto
     }
 }
 
 // Handles invocations of the `get_system_metadata` action.
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetSystemMetadata;
+
 pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
 where
     S: crate::session::Session,
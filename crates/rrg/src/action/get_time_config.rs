@@ -0,0 +1,306 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Collection of the timezone and clock settings of the system.
+//!
+//! Correlating timelines collected from multiple hosts requires knowing the
+//! timezone of each of them (to interpret local timestamps, e.g. in logs) and
+//! how much their clocks are off (to interpret all the other ones). All the
+//! clock information is collected on a best-effort basis: whatever cannot be
+//! determined on a particular system is just not reported.
+
+/// Timezone reported if the one of the system cannot be determined.
+const FALLBACK_TIMEZONE: &str = "UTC";
+
+/// Result of the `get_time_config` action.
+struct Item {
+    /// Name of the timezone the system is configured with (if known).
+    timezone: Option<String>,
+    /// Offset of the local time from UTC (in seconds) now in effect.
+    utc_offset: Option<i32>,
+    /// Current time of the system clock.
+    time: std::time::SystemTime,
+    /// State of synchronization of the system clock.
+    clock: Clock,
+}
+
+/// State of synchronization of the system clock.
+#[derive(Debug, Default)]
+struct Clock {
+    /// Name of the timezone as reported by the time synchronization service.
+    timezone: Option<String>,
+    /// Whether time synchronization over NTP is enabled.
+    ntp_active: Option<bool>,
+    /// Whether the system clock is synchronized with its time source.
+    ntp_synchronized: Option<bool>,
+    /// Estimated offset of the system clock from its time source (in ns).
+    offset_nanos: Option<i64>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetTimeConfig;
+
+/// Handles invocations of the `get_time_config` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let timezone = match ospect::os::timezone() {
+        Ok(timezone) => timezone,
+        Err(error) => {
+            log::warn!("failed to determine timezone: {error}");
+            None
+        }
+    };
+
+    let utc_offset = match ospect::os::utc_offset() {
+        Ok(utc_offset) => Some(utc_offset),
+        Err(error) => {
+            log::warn!("failed to determine UTC offset: {error}");
+            None
+        }
+    };
+
+    let clock = clock();
+
+    session.reply(Item {
+        timezone: timezone.or_else(|| clock.timezone.clone()),
+        utc_offset,
+        time: std::time::SystemTime::now(),
+        clock,
+    })?;
+
+    Ok(())
+}
+
+/// Collects the state of synchronization of the system clock.
+#[cfg(target_os = "linux")]
+fn clock() -> Clock {
+    let mut clock = Clock::default();
+
+    // The kernel keeps track of the clock discipline whatever daemon (if any)
+    // synchronizes it, so this works even without systemd.
+    match kernel_clock() {
+        Ok((synchronized, offset_nanos)) => {
+            clock.ntp_synchronized = Some(synchronized);
+            clock.offset_nanos = Some(offset_nanos);
+        }
+        Err(error) => log::warn!("failed to query kernel clock: {error}"),
+    }
+
+    let output = std::process::Command::new("timedatectl")
+        .arg("show")
+        .arg("--property=Timezone")
+        .arg("--property=NTP")
+        .stdin(std::process::Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let output = String::from_utf8_lossy(&output.stdout);
+            for line in output.lines() {
+                match line.split_once('=') {
+                    Some(("Timezone", timezone)) if !timezone.is_empty() => {
+                        clock.timezone = Some(String::from(timezone));
+                    }
+                    Some(("NTP", ntp)) => {
+                        clock.ntp_active = Some(ntp == "yes");
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(output) => {
+            log::warn! {
+                "failed to run timedatectl: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            };
+        }
+        // Systems without systemd (or containers) do not have `timedatectl` at
+        // all, this is not worth a warning.
+        Err(error) => log::debug!("failed to run timedatectl: {error}"),
+    }
+
+    clock
+}
+
+/// Queries the kernel for the clock synchronization state and offset.
+#[cfg(target_os = "linux")]
+fn kernel_clock() -> std::io::Result<(bool, i64)> {
+    // SAFETY: `timex` is a plain C struct for which all-zeros is a valid value.
+    // Zero modes mean that nothing is adjusted, only read.
+    let mut timex = unsafe {
+        std::mem::zeroed::<libc::timex>()
+    };
+
+    // SAFETY: We pass a valid pointer to the struct we have just allocated. The
+    // result is verified below.
+    let state = unsafe {
+        libc::adjtimex(&mut timex)
+    };
+    if state == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let synchronized = state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0;
+
+    // The offset is in microseconds unless the nanosecond resolution is on.
+    let offset_nanos = if timex.status & libc::STA_NANO != 0 {
+        timex.offset as i64
+    } else {
+        (timex.offset as i64).saturating_mul(1000)
+    };
+
+    Ok((synchronized, offset_nanos))
+}
+
+/// Collects the state of synchronization of the system clock.
+#[cfg(target_os = "macos")]
+fn clock() -> Clock {
+    let mut clock = Clock::default();
+
+    let output = std::process::Command::new("/usr/sbin/systemsetup")
+        .arg("-getusingnetworktime")
+        .stdin(std::process::Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let output = String::from_utf8_lossy(&output.stdout);
+            match output.trim().strip_prefix("Network Time: ") {
+                Some("On") => clock.ntp_active = Some(true),
+                Some("Off") => clock.ntp_active = Some(false),
+                _ => log::warn!("unexpected systemsetup output: {output}"),
+            }
+        }
+        Ok(output) => {
+            log::warn! {
+                "failed to run systemsetup: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            };
+        }
+        Err(error) => log::warn!("failed to run systemsetup: {error}"),
+    }
+
+    clock
+}
+
+/// Collects the state of synchronization of the system clock.
+#[cfg(target_family = "windows")]
+fn clock() -> Clock {
+    let mut clock = Clock::default();
+
+    // The Windows Time service is configured through its registry key [1]. All
+    // the synchronization types other than `NoSync` involve NTP.
+    //
+    // [1]: https://learn.microsoft.com/en-us/windows-server/networking/windows-time-service/windows-time-service-tools-and-settings
+    let key = winreg::PredefinedKey::LocalMachine
+        .open("SYSTEM\\CurrentControlSet\\Services\\W32Time\\Parameters");
+
+    let sync_type = key.and_then(|key| key.value_data(std::ffi::OsStr::new("Type")));
+    match sync_type {
+        Ok(winreg::ValueData::String(sync_type)) => {
+            clock.ntp_active = Some(!sync_type.eq_ignore_ascii_case("NoSync"));
+        }
+        Ok(_) => log::warn!("non-string Windows Time synchronization type"),
+        Err(error) => log::warn!("failed to read Windows Time synchronization type: {error}"),
+    }
+
+    clock
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::get_time_config::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        match self.timezone {
+            Some(timezone) => proto.set_timezone(timezone),
+            None => {
+                proto.set_timezone(String::from(FALLBACK_TIMEZONE));
+                proto.set_timezone_unresolved(true);
+            }
+        }
+        if let Some(utc_offset) = self.utc_offset {
+            proto.set_utc_offset(utc_offset);
+        }
+        proto.set_time(rrg_proto::into_timestamp(self.time));
+        if let Some(ntp_active) = self.clock.ntp_active {
+            proto.set_ntp_active(ntp_active);
+        }
+        if let Some(ntp_synchronized) = self.clock.ntp_synchronized {
+            proto.set_ntp_synchronized(ntp_synchronized);
+        }
+        if let Some(offset_nanos) = self.clock.offset_nanos {
+            proto.set_clock_offset_nanos(offset_nanos);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_time_close() {
+        let before = std::time::SystemTime::now();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let after = std::time::SystemTime::now();
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(before <= item.time && item.time <= after);
+    }
+
+    #[test]
+    fn handle_timezone_populated() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let item = session.reply::<Item>(0);
+        if let Some(timezone) = &item.timezone {
+            assert!(!timezone.is_empty());
+        }
+
+        let proto = crate::response::Item::into_proto(Item {
+            timezone: item.timezone.clone(),
+            utc_offset: item.utc_offset,
+            time: item.time,
+            clock: Clock::default(),
+        });
+        assert!(!proto.timezone().is_empty());
+        assert_eq!(proto.timezone_unresolved(), item.timezone.is_none());
+    }
+
+    #[test]
+    fn item_unresolved_timezone_into_proto() {
+        let item = Item {
+            timezone: None,
+            utc_offset: Some(0),
+            time: std::time::SystemTime::now(),
+            clock: Clock::default(),
+        };
+
+        let proto = crate::response::Item::into_proto(item);
+        assert_eq!(proto.timezone(), "UTC");
+        assert!(proto.timezone_unresolved());
+        assert!(!proto.has_ntp_active());
+        assert!(!proto.has_clock_offset_nanos());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn kernel_clock_ok() {
+        assert!(kernel_clock().is_ok());
+    }
+}
@@ -25,6 +25,9 @@ struct Item {
     value: winreg::Value,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GetWinregValue;
+
 /// Handles invocations of the `get_winreg_value` action.
 #[cfg(target_family = "unix")]
 pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
@@ -21,12 +21,15 @@ pub struct Item {
     content: String,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::GrepFileContents;
+
 /// Handles invocations of the `grep_file_contents` action.
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
 where
     S: crate::session::Session,
 {
-    let file = std::fs::File::open(&args.path)
+    let file = crate::fs::open_file(&args.path)
         .map_err(crate::session::Error::action)?;
 
     let mut file = std::io::BufReader::new(file);
@@ -45,6 +48,7 @@ where
             Ok(len) => len,
             Err(error) => return Err(crate::session::Error::action(error)),
         };
+        session.record_bytes_read(len as u64);
 
         for matcz in args.regex.find_iter(&line) {
             session.reply(Item {
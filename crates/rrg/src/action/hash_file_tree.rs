@@ -0,0 +1,660 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Walking a directory tree and hashing all the files in it.
+//!
+//! This is equivalent to collecting metadata of all the files in the tree and
+//! then hashing each of them but is done in a single pass: every file is hashed
+//! right after it is encountered using the metadata obtained during the walk.
+//! This makes it a cheap building block for integrity baselines.
+
+use std::path::{Path, PathBuf};
+
+/// Size of the buffer used for reading files.
+const BUF_LEN: usize = 64 * 1024; // 64 KiB.
+
+/// Arguments of the `hash_file_tree` action.
+pub struct Args {
+    /// Root of the tree to hash.
+    root: PathBuf,
+    /// Limit on the depth of recursion when visiting subfolders.
+    max_depth: u32,
+    /// Patterns of paths to exclude from the walk.
+    excludes: Vec<crate::fs::Glob>,
+    /// Types of filesystems to descend into (all if empty).
+    include_fs_types: Vec<String>,
+    /// Types of filesystems not to descend into.
+    exclude_fs_types: Vec<String>,
+    /// Whether to compute MD5 digests.
+    md5: bool,
+    /// Whether to compute SHA-1 digests.
+    sha1: bool,
+    /// Whether to compute SHA-256 digests.
+    sha256: bool,
+    /// Maximum size of files to hash (if limited).
+    max_file_size: Option<u64>,
+}
+
+/// Result of the `hash_file_tree` action.
+struct Item {
+    /// Path to the file.
+    path: PathBuf,
+    /// Metadata of the file.
+    metadata: std::fs::Metadata,
+    /// Digests of the file contents (for regular files).
+    digests: Option<std::io::Result<Digests>>,
+}
+
+/// Digests of contents of a single file.
+#[derive(Debug)]
+struct Digests {
+    /// MD5 digest of the contents (if requested).
+    md5: Option<[u8; 16]>,
+    /// SHA-1 digest of the contents (if requested).
+    sha1: Option<[u8; 20]>,
+    /// SHA-256 digest of the contents (if requested).
+    sha256: Option<[u8; 32]>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::HashFileTree;
+
+/// Handles invocations of the `hash_file_tree` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let metadata = args.root.symlink_metadata()
+        .map_err(crate::session::Error::action)?;
+
+    // Reads of files that hang (e.g. on an unresponsive network mount) are
+    // abandoned if the agent is configured to do so.
+    let watchdog = session.args().fs_entry_timeout.map(|entry_timeout| {
+        std::sync::Arc::new(crate::fs::Watchdog::new(entry_timeout))
+    });
+
+    // Files (and trees limited to the root) are still hashed, so that callers
+    // do not have to special-case them.
+    if !metadata.is_dir() || args.max_depth == 0 {
+        let entry = crate::fs::Entry {
+            path: args.root.clone(),
+            metadata,
+            listed_type: None,
+        };
+
+        return handle_entry(session, &args, watchdog.as_ref(), entry);
+    }
+
+    let mut entries = crate::fs::walk_dir(&args.root)
+        .map_err(crate::session::Error::action)?
+        .with_max_depth(args.max_depth)
+        .with_max_dir_entries(session.args().max_dir_entries)
+        .with_excludes(args.excludes.clone());
+
+    // Without a filter walks do not leave the filesystem of the root, with it
+    // they enter other filesystems as long as their type is allowed.
+    if !args.include_fs_types.is_empty() || !args.exclude_fs_types.is_empty() {
+        let fs_type_filter = crate::fs::FsTypeFilter::new(
+            args.include_fs_types.clone(),
+            args.exclude_fs_types.clone(),
+        ).map_err(crate::session::Error::action)?;
+
+        entries = entries.with_fs_type_filter(fs_type_filter);
+    }
+    if let Some(entry_timeout) = session.args().fs_entry_timeout {
+        entries = entries.with_entry_timeout(entry_timeout);
+    }
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("failed to obtain directory entry: {error}");
+                continue;
+            }
+        };
+
+        handle_entry(session, &args, watchdog.as_ref(), entry)?;
+    }
+
+    Ok(())
+}
+
+/// Hashes the file of the given `entry` (if needed) and replies with it.
+///
+/// If `watchdog` is given, operations on the file are run under it.
+fn handle_entry<S>(
+    session: &mut S,
+    args: &Args,
+    watchdog: Option<&std::sync::Arc<crate::fs::Watchdog>>,
+    entry: crate::fs::Entry,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    session.check_deadline()?;
+
+    let digests = match args.max_file_size {
+        _ if !entry.metadata.is_file() => None,
+        Some(max_file_size) if entry.metadata.len() > max_file_size => {
+            Some(Err(std::io::Error::other(FileTooBigError {
+                len: entry.metadata.len(),
+                max_len: max_file_size,
+            })))
+        }
+        _ => match hash_file(session, args, watchdog, &entry.path) {
+            Ok(digests) => Some(Ok(digests)),
+            // Limits of the session are not failures of a particular file and
+            // have to abort the whole action.
+            Err(HashError::Session(error)) => return Err(error),
+            Err(HashError::Io(error)) => {
+                log::warn!("failed to hash '{}': {error}", entry.path.display());
+                Some(Err(error))
+            }
+        },
+    };
+
+    session.reply(Item {
+        path: entry.path,
+        metadata: entry.metadata,
+        digests,
+    })?;
+
+    session.heartbeat();
+
+    Ok(())
+}
+
+/// Error that can occur while hashing a file.
+enum HashError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// Limits of the session were exceeded.
+    Session(crate::session::Error),
+}
+
+/// Computes digests (requested in `args`) of the file at the given `path`.
+fn hash_file<S>(
+    session: &mut S,
+    args: &Args,
+    watchdog: Option<&std::sync::Arc<crate::fs::Watchdog>>,
+    path: &Path,
+) -> Result<Digests, HashError>
+where
+    S: crate::session::Session,
+{
+    use std::io::Read as _;
+
+    use sha2::Digest as _;
+
+    let mut file: Box<dyn std::io::Read> = match watchdog {
+        Some(watchdog) => {
+            let path = path.to_path_buf();
+            let file = watchdog.run(move || crate::fs::open_file(path))
+                .map_err(HashError::Io)?;
+
+            Box::new(crate::fs::WatchdogReader::new(file, watchdog.clone()))
+        }
+        None => {
+            let file = crate::fs::open_file(path)
+                .map_err(HashError::Io)?;
+
+            Box::new(file)
+        }
+    };
+
+    let mut md5 = args.md5.then(md5::Md5::new);
+    let mut sha1 = args.sha1.then(sha1::Sha1::new);
+    let mut sha256 = args.sha256.then(sha2::Sha256::new);
+
+    let mut buf = vec![0; BUF_LEN];
+
+    loop {
+        session.check_deadline()
+            .map_err(HashError::Session)?;
+
+        let len_read = match file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(HashError::Io(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+        session.throttle(len_read as u64)
+            .map_err(HashError::Session)?;
+
+        let buf = &buf[..len_read];
+        if let Some(md5) = &mut md5 {
+            md5.update(buf);
+        }
+        if let Some(sha1) = &mut sha1 {
+            sha1.update(buf);
+        }
+        if let Some(sha256) = &mut sha256 {
+            sha256.update(buf);
+        }
+    }
+
+    Ok(Digests {
+        md5: md5.map(|md5| md5.finalize().into()),
+        sha1: sha1.map(|sha1| sha1.finalize().into()),
+        sha256: sha256.map(|sha256| sha256.finalize().into()),
+    })
+}
+
+/// An error type for when a file is too big to be hashed.
+#[derive(Debug)]
+struct FileTooBigError {
+    /// Size of the file.
+    len: u64,
+    /// Maximum size of files that are hashed.
+    max_len: u64,
+}
+
+impl std::fmt::Display for FileTooBigError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "file of {} bytes bigger than the limit of {} bytes", self.len, self.max_len)
+    }
+}
+
+impl std::error::Error for FileTooBigError {
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::hash_file_tree::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        if !proto.has_root() {
+            return Err(ParseArgsError::missing_field("root"));
+        }
+
+        let root = PathBuf::try_from(proto.take_root())
+            .map_err(|error| ParseArgsError::invalid_field("root", error))?;
+
+        let exclude_path_globs = proto.take_exclude_path_globs().into_iter()
+            .map(|path| {
+                let path = PathBuf::try_from(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))?;
+
+                crate::fs::Glob::new(path)
+                    .map_err(|error| ParseArgsError::invalid_field("exclude_path_globs", error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let excludes = if exclude_path_globs.is_empty() {
+            crate::fs::default_walk_excludes(&root)
+        } else {
+            exclude_path_globs
+        };
+
+        // SHA-256 is the most common choice for baselines, so we default to it
+        // instead of returning bare metadata.
+        let sha256 = proto.sha256() || (!proto.md5() && !proto.sha1());
+
+        Ok(Args {
+            root,
+            max_depth: proto.max_depth.unwrap_or(u32::MAX),
+            excludes,
+            include_fs_types: proto.take_include_fs_types(),
+            exclude_fs_types: proto.take_exclude_fs_types(),
+            md5: proto.md5(),
+            sha1: proto.sha1(),
+            sha256,
+            max_file_size: match proto.max_file_size() {
+                0 => None,
+                max_file_size => Some(max_file_size),
+            },
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::hash_file_tree::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_metadata(self.metadata.into());
+
+        match self.digests {
+            Some(Ok(digests)) => {
+                if let Some(md5) = digests.md5 {
+                    proto.set_md5(md5.to_vec());
+                }
+                if let Some(sha1) = digests.sha1 {
+                    proto.set_sha1(sha1.to_vec());
+                }
+                if let Some(sha256) = digests.sha256 {
+                    proto.set_sha256(sha256.to_vec());
+                }
+            }
+            Some(Err(error)) => proto.set_hash_error(error.to_string()),
+            None => (),
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Returns arguments hashing the tree at `root` with SHA-256 only.
+    fn args(root: PathBuf) -> Args {
+        Args {
+            root,
+            max_depth: u32::MAX,
+            excludes: vec![],
+            include_fs_types: vec![],
+            exclude_fs_types: vec![],
+            md5: false,
+            sha1: false,
+            sha256: true,
+            max_file_size: None,
+        }
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest as _;
+
+        sha2::Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn handle_tree() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foo")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("bar"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar").join("baz"), b"baz")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("bar").join("quux"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar").join("quux").join("norf"), b"")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path().to_path_buf())).is_ok());
+
+        let mut items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| item.path.clone());
+
+        assert_eq!(items.len(), 5);
+
+        assert_eq!(items[0].path, tempdir.path().join("bar"));
+        assert!(items[0].metadata.is_dir());
+        assert!(items[0].digests.is_none());
+
+        assert_eq!(items[1].path, tempdir.path().join("bar").join("baz"));
+        assert_eq!(items[1].metadata.len(), 3);
+        let digests = items[1].digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"baz")));
+        assert_eq!(digests.md5, None);
+        assert_eq!(digests.sha1, None);
+
+        assert_eq!(items[2].path, tempdir.path().join("bar").join("quux"));
+        assert!(items[2].metadata.is_dir());
+        assert!(items[2].digests.is_none());
+
+        assert_eq!(items[3].path, tempdir.path().join("bar").join("quux").join("norf"));
+        assert_eq!(items[3].metadata.len(), 0);
+        let digests = items[3].digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"")));
+
+        assert_eq!(items[4].path, tempdir.path().join("foo"));
+        assert_eq!(items[4].metadata.len(), 3);
+        let digests = items[4].digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"foo")));
+    }
+
+    #[test]
+    fn handle_all_digests() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"The quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        let args = Args {
+            md5: true,
+            sha1: true,
+            sha256: true,
+            ..args(tempdir.path().to_path_buf())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        let digests = item.digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.md5, Some([
+            0x9e, 0x10, 0x7d, 0x9d, 0x37, 0x2b, 0xb6, 0x82,
+            0x6b, 0xd8, 0x1d, 0x35, 0x42, 0xa4, 0x19, 0xd6,
+        ]));
+        assert_eq!(digests.sha1, Some([
+            0x2f, 0xd4, 0xe1, 0xc6, 0x7a, 0x2d, 0x28, 0xfc, 0xed, 0x84,
+            0x9e, 0xe1, 0xbb, 0x76, 0xe7, 0x39, 0x1b, 0x93, 0xeb, 0x12,
+        ]));
+        assert_eq!(digests.sha256, Some(sha256(b"The quick brown fox jumps over the lazy dog")));
+    }
+
+    #[test]
+    fn handle_max_depth() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join("foo"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("foo").join("bar"), b"bar")
+            .unwrap();
+
+        let args = Args {
+            max_depth: 1,
+            ..args(tempdir.path().to_path_buf())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).path, tempdir.path().join("foo"));
+    }
+
+    #[test]
+    fn handle_excludes() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join("foo"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("foo").join("bar"), b"bar")
+            .unwrap();
+        std::fs::write(tempdir.path().join("baz"), b"baz")
+            .unwrap();
+
+        let args = Args {
+            excludes: vec![crate::fs::Glob::new(tempdir.path().join("foo")).unwrap()],
+            ..args(tempdir.path().to_path_buf())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).path, tempdir.path().join("baz"));
+    }
+
+    #[test]
+    fn handle_max_file_size() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foobar")
+            .unwrap();
+
+        let args = Args {
+            max_file_size: Some(5),
+            ..args(tempdir.path().to_path_buf())
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.metadata.len(), 6);
+        assert!(item.digests.as_ref().unwrap().is_err());
+    }
+
+    #[test]
+    fn handle_file_root() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foo")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path().join("foo"))).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.path().join("foo"));
+        let digests = item.digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"foo")));
+    }
+
+    #[test]
+    fn handle_non_existent_root() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args(tempdir.path().join("foo"))).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    fn mkfifo(path: &Path) {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let path_c = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .unwrap();
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_entry_timeout_fifo() {
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foo")
+            .unwrap();
+        mkfifo(&tempdir.path().join("fifo"));
+
+        let mut session = crate::session::FakeSession::new();
+
+        let args = args(tempdir.path().to_path_buf());
+        let watchdog = std::sync::Arc::new({
+            crate::fs::Watchdog::new(std::time::Duration::from_millis(100))
+        });
+
+        // We simulate a regular file replaced with a FIFO (without a writer, so
+        // opening it blocks) after the walk stat-ed it.
+        let metadata = tempdir.path().join("foo").symlink_metadata()
+            .unwrap();
+        let entry = crate::fs::Entry {
+            path: tempdir.path().join("fifo"),
+            metadata: metadata.clone(),
+            listed_type: None,
+        };
+        assert!(handle_entry(&mut session, &args, Some(&watchdog), entry).is_ok());
+
+        let entry = crate::fs::Entry {
+            path: tempdir.path().join("foo"),
+            metadata,
+            listed_type: None,
+        };
+        assert!(handle_entry(&mut session, &args, Some(&watchdog), entry).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.path().join("fifo"));
+        let error = item.digests.as_ref().unwrap().as_ref().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.path, tempdir.path().join("foo"));
+        let digests = item.digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"foo")));
+
+        // We unblock the abandoned open so that it does not outlive the test.
+        let _ = std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(tempdir.path().join("fifo"));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_entry_timeout_tree_with_fifo() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"foo")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("bar"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar").join("baz"), b"baz")
+            .unwrap();
+        mkfifo(&tempdir.path().join("bar").join("fifo"));
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            fs_entry_timeout: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args(tempdir.path().to_path_buf())).is_ok());
+
+        let mut items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| item.path.clone());
+
+        assert_eq!(items.len(), 4);
+
+        assert_eq!(items[1].path, tempdir.path().join("bar").join("baz"));
+        let digests = items[1].digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"baz")));
+
+        // The FIFO is reported but not read from.
+        assert_eq!(items[2].path, tempdir.path().join("bar").join("fifo"));
+        assert!(items[2].digests.is_none());
+
+        assert_eq!(items[3].path, tempdir.path().join("foo"));
+        let digests = items[3].digests.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(digests.sha256, Some(sha256(b"foo")));
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of autorun entries in the Windows registry.
+//!
+//! The action inspects a fixed set of registry locations commonly used for
+//! persistence: `Run` and `RunOnce` keys of the machine and the current user,
+//! the `Winlogon` shell and user initialization commands and image paths of
+//! the services.
+
+/// Registry keys all values of which are autorun entries.
+#[cfg(target_family = "windows")]
+const RUN_KEYS: &[(winreg::PredefinedKey, &str)] = &[
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\RunOnce"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\Run"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\RunOnce"),
+    (winreg::PredefinedKey::CurrentUser, "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run"),
+    (winreg::PredefinedKey::CurrentUser, "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\RunOnce"),
+];
+
+/// Key with values of `Winlogon` that specify commands run at user logon.
+#[cfg(target_family = "windows")]
+const WINLOGON_KEY: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon";
+
+/// Names of `Winlogon` values that are autorun entries.
+#[cfg(target_family = "windows")]
+const WINLOGON_VALUE_NAMES: &[&str] = &["Shell", "Userinit"];
+
+/// Key with subkeys describing services installed on the system.
+#[cfg(target_family = "windows")]
+const SERVICES_KEY: &str = "SYSTEM\\CurrentControlSet\\Services";
+
+/// A result of the `list_autoruns` action.
+#[cfg(target_family = "windows")]
+struct Item {
+    /// Root predefined key of the key with the entry.
+    root: winreg::PredefinedKey,
+    /// Key relative to `root` with the entry.
+    key: std::ffi::OsString,
+    /// Name of the value with the entry.
+    name: std::ffi::OsString,
+    /// Command that is executed.
+    command: std::ffi::OsString,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListAutoruns;
+
+/// Handles invocations of the `list_autoruns` action.
+#[cfg(target_family = "windows")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::ffi::OsStr;
+
+    for (root, key) in RUN_KEYS {
+        report_values(session, *root, OsStr::new(key), |_| true)?;
+    }
+
+    report_values(session, winreg::PredefinedKey::LocalMachine, OsStr::new(WINLOGON_KEY), |name| {
+        WINLOGON_VALUE_NAMES.iter().any(|winlogon_name| {
+            name.eq_ignore_ascii_case(winlogon_name)
+        })
+    })?;
+
+    report_services(session)?;
+
+    Ok(())
+}
+
+/// Handles invocations of the `list_autoruns` action.
+#[cfg(target_family = "unix")]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Reports string values of the given key with names matching the predicate.
+///
+/// Keys that do not exist or cannot be opened contribute no entries.
+#[cfg(target_family = "windows")]
+fn report_values<S, F>(
+    session: &mut S,
+    root: winreg::PredefinedKey,
+    key: &std::ffi::OsStr,
+    mut pred: F,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+    F: FnMut(&std::ffi::OsStr) -> bool,
+{
+    let open_key = match root.open(key) {
+        Ok(open_key) => open_key,
+        Err(error) => {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("failed to open autorun key '{key:?}': {error}");
+            }
+            return Ok(());
+        }
+    };
+
+    let info = open_key.info()
+        .map_err(crate::session::Error::action)?;
+
+    for value in info.values() {
+        let value = match value {
+            Ok(value) => value,
+            Err(error) => {
+                log::warn!("failed to list value of key '{key:?}': {error}");
+                continue;
+            }
+        };
+
+        if !pred(&value.name) {
+            continue;
+        }
+
+        let command = match value.data {
+            winreg::ValueData::String(command) => command,
+            winreg::ValueData::ExpandString(command) => command,
+            _ => {
+                log::warn!("non-string autorun value '{:?}' in key '{key:?}'", value.name);
+                continue;
+            }
+        };
+
+        session.reply(Item {
+            root,
+            key: key.to_os_string(),
+            name: value.name,
+            command,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Reports image paths of all services installed on the system.
+#[cfg(target_family = "windows")]
+fn report_services<S>(session: &mut S) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::ffi::{OsStr, OsString};
+
+    let services = match winreg::PredefinedKey::LocalMachine.open(OsStr::new(SERVICES_KEY)) {
+        Ok(services) => services,
+        Err(error) => {
+            log::warn!("failed to open services key: {error}");
+            return Ok(());
+        }
+    };
+
+    let info = services.info()
+        .map_err(crate::session::Error::action)?;
+
+    for service in info.subkeys() {
+        let service = match service {
+            Ok(service) => service,
+            Err(error) => {
+                log::warn!("failed to list service: {error}");
+                continue;
+            }
+        };
+
+        let mut key = OsString::from(SERVICES_KEY);
+        key.push("\\");
+        key.push(&service);
+
+        // Not all services (e.g. kernel drivers loaded by name) have an image
+        // path, so we silently skip those that do not have one.
+        let image_path = services.open(&service)
+            .and_then(|service| service.value_data(OsStr::new("ImagePath")));
+
+        let command = match image_path {
+            Ok(winreg::ValueData::String(command)) => command,
+            Ok(winreg::ValueData::ExpandString(command)) => command,
+            Ok(_) => {
+                log::warn!("non-string image path of service '{service:?}'");
+                continue;
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => {
+                log::warn!("failed to get image path of service '{service:?}': {error}");
+                continue;
+            }
+        };
+
+        session.reply(Item {
+            root: winreg::PredefinedKey::LocalMachine,
+            key,
+            name: OsString::from("ImagePath"),
+            command,
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_family = "windows")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_autoruns::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = rrg_proto::list_autoruns::Result::new();
+        proto.set_root(self.root.into());
+        proto.set_key(self.key.to_string_lossy().into_owned());
+        proto.set_name(self.name.to_string_lossy().into_owned());
+        proto.set_command(self.command.to_string_lossy().into_owned());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_family = "windows")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_ok() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        // Every Windows system has some services with image paths.
+        assert! {
+            session.replies::<Item>()
+                .any(|item| item.name == "ImagePath")
+        };
+    }
+
+    #[test]
+    fn report_values_non_existent() {
+        let mut session = crate::session::FakeSession::new();
+        assert! {
+            report_values(&mut session,
+                winreg::PredefinedKey::CurrentUser,
+                std::ffi::OsStr::new("SOFTWARE\\FOOWARE\\Run"),
+                |_| true,
+            ).is_ok()
+        };
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn report_values_synthetic_run_key() {
+        let key = format!("SOFTWARE\\rrg-test-{}\\Run", std::process::id());
+
+        let status = std::process::Command::new("reg")
+            .arg("add").arg(format!("HKCU\\{key}"))
+            .arg("/v").arg("Foo")
+            .arg("/t").arg("REG_SZ")
+            .arg("/d").arg("C:\\foo.exe --bar")
+            .arg("/f")
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut session = crate::session::FakeSession::new();
+        let result = report_values(&mut session,
+            winreg::PredefinedKey::CurrentUser,
+            std::ffi::OsStr::new(&key),
+            |_| true,
+        );
+
+        std::process::Command::new("reg")
+            .arg("delete").arg(format!("HKCU\\SOFTWARE\\rrg-test-{}", std::process::id()))
+            .arg("/f")
+            .status()
+            .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.root, winreg::PredefinedKey::CurrentUser);
+        assert_eq!(item.key, std::ffi::OsString::from(&key));
+        assert_eq!(item.name, "Foo");
+        assert_eq!(item.command, "C:\\foo.exe --bar");
+    }
+}
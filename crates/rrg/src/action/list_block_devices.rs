@@ -0,0 +1,417 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of block devices (and their partitions) available on the system.
+//!
+//! Block devices are enumerated using the `/sys/block` directory, similarly to
+//! what the `lsblk` utility does.
+
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+
+/// Path to the directory with block devices known to the kernel.
+#[cfg(target_os = "linux")]
+const SYS_BLOCK_PATH: &str = "/sys/block";
+
+/// Size of the units in which `/sys/block` reports sizes and offsets.
+///
+/// Regardless of the actual sector size of the device, the kernel always uses
+/// 512-byte units in these files.
+#[cfg(target_os = "linux")]
+const SECTOR_LEN: u64 = 512;
+
+/// Result of the `list_block_devices` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Information about the block device.
+    device: BlockDevice,
+}
+
+/// Information about a block device.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct BlockDevice {
+    /// Name of the device (e.g. `sda`).
+    name: String,
+    /// Size of the device in bytes.
+    size: u64,
+    /// Model of the device (if known).
+    model: Option<String>,
+    /// Serial number of the device (if known).
+    serial: Option<String>,
+    /// Whether the device uses removable media.
+    removable: bool,
+    /// Whether the device is read-only.
+    read_only: bool,
+    /// Paths at which the device (as a whole) is mounted.
+    mountpoints: Vec<PathBuf>,
+    /// Partitions of the device (ordered by their number).
+    partitions: Vec<Partition>,
+}
+
+/// Information about a partition of a block device.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct Partition {
+    /// Name of the partition (e.g. `sda1`).
+    name: String,
+    /// Number of the partition in the partition table (if known).
+    number: Option<u32>,
+    /// Offset (in bytes) at which the partition starts.
+    start: u64,
+    /// Size of the partition in bytes.
+    size: u64,
+    /// Whether the partition is read-only.
+    read_only: bool,
+    /// Paths at which the partition is mounted.
+    mountpoints: Vec<PathBuf>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListBlockDevices;
+
+/// Handles invocations of the `list_block_devices` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut devices = block_devices(Path::new(SYS_BLOCK_PATH))
+        .map_err(crate::session::Error::action)?;
+
+    let mut mountpoints = mountpoints();
+    for device in &mut devices {
+        device.mountpoints = mountpoints.remove(&device.name)
+            .unwrap_or_default();
+
+        for partition in &mut device.partitions {
+            partition.mountpoints = mountpoints.remove(&partition.name)
+                .unwrap_or_default();
+        }
+    }
+
+    for device in devices {
+        session.reply(Item {
+            device,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Handles invocations of the `list_block_devices` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Collects block devices from the given `/sys/block`-like directory.
+///
+/// Devices that disappear while they are inspected (e.g. because they were
+/// unplugged) are skipped. Other failures to inspect individual devices are
+/// logged and the devices are skipped as well.
+#[cfg(target_os = "linux")]
+fn block_devices(sys_block: &Path) -> std::io::Result<Vec<BlockDevice>> {
+    let mut devices = Vec::new();
+
+    for entry in std::fs::read_dir(sys_block)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("failed to read block device entry: {error}");
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        match block_device(&path) {
+            Ok(device) => devices.push(device),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("block device '{}' disappeared", path.display());
+            }
+            Err(error) => {
+                log::warn!("failed to inspect block device '{}': {error}", path.display());
+            }
+        }
+    }
+
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(devices)
+}
+
+/// Collects information about the block device at the given sysfs `path`.
+#[cfg(target_os = "linux")]
+fn block_device(path: &Path) -> std::io::Result<BlockDevice> {
+    let mut partitions = Vec::new();
+
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+
+        // Subdirectories of the device directory are not only partitions but
+        // also things like `queue` or `holders`, partitions are the ones that
+        // have the `partition` attribute.
+        if !path.join("partition").exists() {
+            continue;
+        }
+
+        match partition(&path) {
+            Ok(partition) => partitions.push(partition),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("partition '{}' disappeared", path.display());
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    partitions.sort_by_key(|partition| partition.number);
+
+    Ok(BlockDevice {
+        name: file_name(path),
+        size: read_sectors(&path.join("size"))?,
+        model: read_attr(&path.join("device").join("model"))?,
+        serial: read_attr(&path.join("device").join("serial"))?,
+        removable: read_attr(&path.join("removable"))?.as_deref() == Some("1"),
+        read_only: read_attr(&path.join("ro"))?.as_deref() == Some("1"),
+        mountpoints: Vec::new(),
+        partitions,
+    })
+}
+
+/// Collects information about the partition at the given sysfs `path`.
+#[cfg(target_os = "linux")]
+fn partition(path: &Path) -> std::io::Result<Partition> {
+    let number = read_attr(&path.join("partition"))?
+        .and_then(|number| number.parse().ok());
+
+    Ok(Partition {
+        name: file_name(path),
+        number,
+        start: read_sectors(&path.join("start"))?,
+        size: read_sectors(&path.join("size"))?,
+        read_only: read_attr(&path.join("ro"))?.as_deref() == Some("1"),
+        mountpoints: Vec::new(),
+    })
+}
+
+/// Reads a sysfs attribute (with surrounding whitespace trimmed).
+///
+/// Attributes that do not exist or are empty yield nothing.
+#[cfg(target_os = "linux")]
+fn read_attr(path: &Path) -> std::io::Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => Ok(Some(String::from(value.trim()))),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Reads a sysfs attribute with a sector count and converts it to bytes.
+///
+/// Unlike with [`read_attr`], the attribute has to exist.
+#[cfg(target_os = "linux")]
+fn read_sectors(path: &Path) -> std::io::Result<u64> {
+    let value = std::fs::read_to_string(path)?;
+
+    let sectors = value.trim().parse::<u64>()
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    Ok(sectors.saturating_mul(SECTOR_LEN))
+}
+
+/// Returns the name of the block device or partition at the sysfs `path`.
+#[cfg(target_os = "linux")]
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns mountpoints of mounted block devices, keyed by the device name.
+///
+/// Failures to list mounts are logged and yield no mountpoints.
+#[cfg(target_os = "linux")]
+fn mountpoints() -> std::collections::HashMap<String, Vec<PathBuf>> {
+    let mut mountpoints = std::collections::HashMap::<_, Vec<_>>::new();
+
+    let mounts = match ospect::fs::mounts() {
+        Ok(mounts) => mounts,
+        Err(error) => {
+            log::warn!("failed to list mounts: {error}");
+            return mountpoints;
+        }
+    };
+
+    for mount in mounts {
+        let mount = match mount {
+            Ok(mount) => mount,
+            Err(error) => {
+                log::warn!("failed to obtain mount information: {error}");
+                continue;
+            }
+        };
+
+        // Filesystems are often mounted through symlinks to devices (e.g. in
+        // `/dev/disk/by-uuid`), so we resolve them to find the actual device.
+        let device = match Path::new(&mount.name).canonicalize() {
+            Ok(device) => device,
+            Err(_) => continue,
+        };
+
+        if let Ok(name) = device.strip_prefix("/dev") {
+            let name = name.to_string_lossy().into_owned();
+            mountpoints.entry(name).or_default().push(mount.path);
+        }
+    }
+
+    mountpoints
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_block_devices::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_name(self.device.name);
+        proto.set_size(self.device.size);
+        if let Some(model) = self.device.model {
+            proto.set_model(model);
+        }
+        if let Some(serial) = self.device.serial {
+            proto.set_serial(serial);
+        }
+        proto.set_removable(self.device.removable);
+        proto.set_read_only(self.device.read_only);
+        proto.set_mountpoints(self.device.mountpoints.into_iter().map(Into::into).collect());
+
+        for partition in self.device.partitions {
+            let mut proto_partition = rrg_proto::list_block_devices::Partition::default();
+            proto_partition.set_name(partition.name);
+            if let Some(number) = partition.number {
+                proto_partition.set_number(number);
+            }
+            proto_partition.set_start(partition.start);
+            proto_partition.set_size(partition.size);
+            proto_partition.set_read_only(partition.read_only);
+            proto_partition.set_mountpoints(partition.mountpoints.into_iter().map(Into::into).collect());
+
+            proto.partitions.push(proto_partition);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    /// Writes the given sysfs attribute, creating parent directories.
+    fn write_attr(path: &Path, value: &str) {
+        std::fs::create_dir_all(path.parent().unwrap())
+            .unwrap();
+        std::fs::write(path, format!("{value}\n"))
+            .unwrap();
+    }
+
+    #[test]
+    fn block_devices_synthetic() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let sys_block = tempdir.path();
+
+        let sda = sys_block.join("sda");
+        write_attr(&sda.join("size"), "2000000");
+        write_attr(&sda.join("removable"), "0");
+        write_attr(&sda.join("ro"), "0");
+        write_attr(&sda.join("device").join("model"), "QEMU HARDDISK   ");
+        write_attr(&sda.join("device").join("serial"), "QM00001");
+        write_attr(&sda.join("queue").join("rotational"), "1");
+        write_attr(&sda.join("sda2").join("partition"), "2");
+        write_attr(&sda.join("sda2").join("start"), "1050624");
+        write_attr(&sda.join("sda2").join("size"), "949376");
+        write_attr(&sda.join("sda1").join("partition"), "1");
+        write_attr(&sda.join("sda1").join("start"), "2048");
+        write_attr(&sda.join("sda1").join("size"), "1048576");
+
+        let sr0 = sys_block.join("sr0");
+        write_attr(&sr0.join("size"), "0");
+        write_attr(&sr0.join("removable"), "1");
+        write_attr(&sr0.join("ro"), "1");
+
+        let devices = block_devices(sys_block)
+            .unwrap();
+
+        assert_eq!(devices.len(), 2);
+
+        let sda = &devices[0];
+        assert_eq!(sda.name, "sda");
+        assert_eq!(sda.size, 2000000 * 512);
+        assert_eq!(sda.model.as_deref(), Some("QEMU HARDDISK"));
+        assert_eq!(sda.serial.as_deref(), Some("QM00001"));
+        assert!(!sda.removable);
+        assert!(!sda.read_only);
+
+        assert_eq!(sda.partitions.len(), 2);
+        assert_eq!(sda.partitions[0].name, "sda1");
+        assert_eq!(sda.partitions[0].number, Some(1));
+        assert_eq!(sda.partitions[0].start, 2048 * 512);
+        assert_eq!(sda.partitions[0].size, 1048576 * 512);
+        assert_eq!(sda.partitions[1].name, "sda2");
+        assert_eq!(sda.partitions[1].number, Some(2));
+        assert_eq!(sda.partitions[1].start, 1050624 * 512);
+
+        let sr0 = &devices[1];
+        assert_eq!(sr0.name, "sr0");
+        assert_eq!(sr0.model, None);
+        assert!(sr0.removable);
+        assert!(sr0.read_only);
+        assert!(sr0.partitions.is_empty());
+    }
+
+    #[test]
+    fn block_devices_disappeared() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let sys_block = tempdir.path();
+
+        write_attr(&sys_block.join("sda").join("size"), "2048");
+
+        // Entries of `/sys/block` are symlinks to the device directories, so a
+        // device that got unplugged leaves a dangling symlink behind for a bit.
+        std::os::unix::fs::symlink(sys_block.join("gone"), sys_block.join("sdb"))
+            .unwrap();
+
+        let devices = block_devices(sys_block)
+            .unwrap();
+
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "sda");
+    }
+
+    #[test]
+    fn handle_some_devices() {
+        // Containers and sandboxes do not always expose sysfs.
+        if !Path::new(SYS_BLOCK_PATH).exists() {
+            return;
+        }
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        for item in session.replies::<Item>() {
+            assert!(!item.device.name.is_empty());
+        }
+    }
+}
@@ -7,15 +7,112 @@ use log::warn;
 
 /// A result of the `list_connections` action.
 struct Item {
-    // Information about the individual connection.
-    conn: ospect::net::Connection,
+    /// Information about the individual connection.
+    conn: Connection,
+    /// Information about the process owning the connection (if available).
+    process: Option<Process>,
+}
+
+/// Information about an individual connection of any kind.
+enum Connection {
+    /// Information about an individual Internet (TCP or UDP) connection.
+    Inet(ospect::net::Connection),
+    /// Information about an individual raw socket.
+    #[cfg(target_os = "linux")]
+    Raw(ospect::net::linux::RawConnection),
+    /// Information about an individual Unix domain socket.
+    #[cfg(target_os = "linux")]
+    Unix(ospect::net::linux::UnixSocket),
+}
+
+impl Connection {
+
+    /// Returns the identifier of the process that owns the connection.
+    fn pid(&self) -> u32 {
+        match self {
+            Connection::Inet(conn) => conn.pid(),
+            #[cfg(target_os = "linux")]
+            Connection::Raw(conn) => conn.pid(),
+            #[cfg(target_os = "linux")]
+            Connection::Unix(socket) => socket.pid(),
+        }
+    }
+}
+
+/// Information about a process owning a connection.
+#[derive(Clone, Debug)]
+struct Process {
+    /// Path to the executable of the process (if known).
+    exe: Option<std::path::PathBuf>,
+    /// Command-line arguments of the process.
+    cmdline: Vec<std::ffi::OsString>,
+}
+
+impl Process {
+
+    /// Collects information about the process with the specified pid.
+    ///
+    /// If the process has exited in the meantime, `None` is returned.
+    #[cfg(target_os = "linux")]
+    fn collect(pid: u32) -> Option<Process> {
+        let exe = match ospect::proc::linux::exe(pid) {
+            Ok(Some(exe)) => Some(exe),
+            Ok(None) => return None,
+            Err(error) => {
+                warn!("failed to obtain executable of process {pid}: {error}");
+                None
+            }
+        };
+
+        let cmdline = match ospect::proc::linux::cmdline(pid) {
+            Ok(Some(cmdline)) => cmdline,
+            // The process has exited after we resolved the executable, but the
+            // path is still valuable on its own.
+            Ok(None) => Vec::new(),
+            Err(error) => {
+                warn!("failed to obtain command line of process {pid}: {error}");
+                Vec::new()
+            }
+        };
+
+        Some(Process {
+            exe,
+            cmdline,
+        })
+    }
+
+    /// Collects information about the process with the specified pid.
+    ///
+    /// Processes can be inspected only on Linux, so this always returns `None`.
+    #[cfg(not(target_os = "linux"))]
+    fn collect(_: u32) -> Option<Process> {
+        None
+    }
 }
 
 // Handles invocations of the `list_connections` action.
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListConnections;
+
 pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
 where
     S: crate::session::Session,
 {
+    // The same process usually owns many connections (and connections can be
+    // reported for every process sharing the network namespace), so we cache
+    // to inspect every process only once.
+    let mut processes = std::collections::HashMap::new();
+    let mut reply = |session: &mut S, conn: Connection| {
+        let process = processes.entry(conn.pid())
+            .or_insert_with(|| Process::collect(conn.pid()))
+            .clone();
+
+        session.reply(Item {
+            conn,
+            process,
+        })
+    };
+
     let conns = ospect::net::all_connections()
         .map_err(crate::session::Error::action)?;
 
@@ -28,9 +125,40 @@ where
             }
         };
 
-        session.reply(Item {
-            conn,
-        })?;
+        reply(session, Connection::Inet(conn))?;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let conns = ospect::net::linux::all_raw_connections()
+            .map_err(crate::session::Error::action)?;
+
+        for conn in conns {
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(error) => {
+                    warn!("failed to obtain raw socket information: {}", error);
+                    continue;
+                }
+            };
+
+            reply(session, Connection::Raw(conn))?;
+        }
+
+        let sockets = ospect::net::linux::all_unix_sockets()
+            .map_err(crate::session::Error::action)?;
+
+        for socket in sockets {
+            let socket = match socket {
+                Ok(socket) => socket,
+                Err(error) => {
+                    warn!("failed to obtain Unix socket information: {}", error);
+                    continue;
+                }
+            };
+
+            reply(session, Connection::Unix(socket))?;
+        }
     }
 
     Ok(())
@@ -42,7 +170,22 @@ impl crate::response::Item for Item {
 
     fn into_proto(self) -> rrg_proto::list_connections::Result {
         let mut proto = rrg_proto::list_connections::Result::new();
-        proto.set_connection(self.conn.into());
+        match self.conn {
+            Connection::Inet(conn) => proto.set_connection(conn.into()),
+            #[cfg(target_os = "linux")]
+            Connection::Raw(conn) => proto.mut_connection().set_raw(conn.into()),
+            #[cfg(target_os = "linux")]
+            Connection::Unix(socket) => proto.mut_connection().set_unix(socket.into()),
+        }
+
+        if let Some(process) = self.process {
+            if let Some(exe) = process.exe {
+                proto.set_process_path(exe.into());
+            }
+            proto.set_process_args(process.cmdline.into_iter().map(|arg| {
+                arg.to_string_lossy().into_owned()
+            }).collect());
+        }
 
         proto
     }
@@ -65,11 +208,12 @@ mod tests {
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, ()).is_ok());
 
-        let item = session.replies::<Item>().find(|item| {
-            item.conn.local_addr() == server_addr
+        let conn = session.replies::<Item>().find_map(|item| match &item.conn {
+            Connection::Inet(conn) if conn.local_addr() == server_addr => Some(conn),
+            _ => None,
         }).unwrap();
 
-        if let ospect::net::Connection::Tcp(conn) = item.conn {
+        if let ospect::net::Connection::Tcp(conn) = conn {
             assert_eq!(conn.state(), ospect::net::TcpState::Listen);
         } else {
             panic!();
@@ -88,10 +232,79 @@ mod tests {
         let mut session = crate::session::FakeSession::new();
         assert!(handle(&mut session, ()).is_ok());
 
-        let item = session.replies::<Item>().find(|item| {
-            item.conn.local_addr() == socket_addr
+        let conn = session.replies::<Item>().find_map(|item| match &item.conn {
+            Connection::Inet(conn) if conn.local_addr() == socket_addr => Some(conn),
+            _ => None,
+        }).unwrap();
+
+        assert!(matches!(conn, ospect::net::Connection::Udp(_)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_local_unix_listener() {
+        use crate::response::Item as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("socket");
+
+        let _listener = std::os::unix::net::UnixListener::bind(&path)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let socket = session.replies::<Item>().find_map(|item| match &item.conn {
+            Connection::Unix(socket) => {
+                use ospect::net::linux::UnixSocketAddr::Pathname;
+                match socket.addr() {
+                    Some(Pathname(socket_path)) if *socket_path == path => {
+                        Some(socket)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
         }).unwrap();
 
-        assert!(matches!(item.conn, ospect::net::Connection::Udp(_)));
+        assert_eq!(socket.socket_type(), ospect::net::linux::UnixSocketType::Stream);
+        assert_eq!(socket.state(), ospect::net::linux::UnixSocketState::Listening);
+
+        let proto = Item {
+            conn: Connection::Unix(socket.clone()),
+            process: None,
+        }.into_proto();
+        let proto = proto.connection().unix();
+        assert_eq!(proto.type_(), rrg_proto::net::UnixSocketType::STREAM);
+        assert_eq!(proto.state(), rrg_proto::net::UnixSocketState::LISTENING);
+        assert_eq!(std::path::PathBuf::try_from(proto.path().clone()).unwrap(), path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_local_tcp_connection_process() {
+        use std::net::Ipv4Addr;
+
+        let server = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap();
+        let server_addr = server.local_addr()
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let item = session.replies::<Item>().find(|item| match &item.conn {
+            Connection::Inet(conn) => {
+                conn.local_addr() == server_addr &&
+                conn.pid() == std::process::id()
+            }
+            _ => false,
+        }).unwrap();
+
+        let process = item.process.as_ref()
+            .unwrap();
+        assert_eq!(process.exe, Some(std::env::current_exe().unwrap()));
+        assert_eq!(process.cmdline, std::env::args_os().collect::<Vec<_>>());
     }
 }
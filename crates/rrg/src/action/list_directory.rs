@@ -0,0 +1,312 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::path::PathBuf;
+
+/// Arguments of the `list_directory` action.
+pub struct Args {
+    /// Path to the directory to list.
+    path: PathBuf,
+    /// Whether to collect full metadata of every entry.
+    metadata: bool,
+    /// Whether to compare listed types of entries with their metadata.
+    check_types: bool,
+}
+
+/// Result of the `list_directory` action.
+struct Item {
+    /// Path to the directory entry.
+    path: PathBuf,
+    /// Type of the directory entry.
+    file_type: std::fs::FileType,
+    /// Metadata of the directory entry (if requested).
+    metadata: Option<std::fs::Metadata>,
+    /// Whether the type of the entry disagrees with its metadata (if checked).
+    type_mismatch: Option<bool>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListDirectory;
+
+/// Handles invocations of the `list_directory` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let entries = std::fs::read_dir(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn! {
+                    "failed to read entry of '{}': {error}",
+                    args.path.display()
+                };
+                continue;
+            }
+        };
+
+        // Obtaining the type of the entry does not require an extra `stat` call
+        // as long as the listing reports it (which is the case for most of the
+        // filesystems), otherwise the standard library falls back to it.
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(error) => {
+                log::warn! {
+                    "failed to obtain type of '{}': {error}",
+                    entry.path().display()
+                };
+                continue;
+            }
+        };
+
+        let metadata = if args.metadata || args.check_types {
+            match entry.metadata() {
+                Ok(metadata) => Some(metadata),
+                Err(error) => {
+                    log::warn! {
+                        "failed to obtain metadata of '{}': {error}",
+                        entry.path().display()
+                    };
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // If the listing does not report the type, it is obtained from the
+        // metadata and there is no mismatch, so filesystems like that do not
+        // raise false alarms.
+        let type_mismatch = if args.check_types {
+            metadata.as_ref().map(|metadata| {
+                crate::fs::is_type_mismatch(file_type, metadata)
+            })
+        } else {
+            None
+        };
+
+        if let Some(true) = type_mismatch {
+            log::warn! {
+                "listed type of '{}' disagrees with its metadata",
+                entry.path().display()
+            };
+        }
+
+        session.reply(Item {
+            path: entry.path(),
+            file_type,
+            metadata: if args.metadata { metadata } else { None },
+            type_mismatch,
+        })?;
+    }
+
+    Ok(())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_directory::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        if !proto.has_path() {
+            return Err(ParseArgsError::missing_field("path"));
+        }
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+            metadata: proto.metadata(),
+            check_types: proto.check_types(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_directory::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_type(self.file_type.into());
+        if let Some(metadata) = self.metadata {
+            proto.set_metadata(metadata.into());
+        }
+        if let Some(type_mismatch) = self.type_mismatch {
+            proto.set_type_mismatch(type_mismatch);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            metadata: false,
+            check_types: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_types_only() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("file"), b"")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("dir"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            metadata: false,
+            check_types: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let mut items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| item.path.clone());
+
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].path, tempdir.path().join("dir"));
+        assert!(items[0].file_type.is_dir());
+        assert!(items[0].metadata.is_none());
+
+        assert_eq!(items[1].path, tempdir.path().join("file"));
+        assert!(items[1].file_type.is_file());
+        assert!(items[1].metadata.is_none());
+    }
+
+    // Without the search (execute) permission on a directory, entries can be
+    // listed but `stat` calls on them fail. This lets us verify that types are
+    // taken from the listing. Superusers bypass the permission checks, so the
+    // test is meaningless (but still passes) when run as one.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn handle_types_only_without_search_permission() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let dir = tempdir.path().join("dir");
+
+        std::fs::create_dir(&dir)
+            .unwrap();
+        std::fs::write(dir.join("file"), b"")
+            .unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o400))
+            .unwrap();
+
+        let args = Args {
+            path: dir.clone(),
+            metadata: false,
+            check_types: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        let result = handle(&mut session, args);
+
+        // We need to restore permissions so that the directory can be cleaned.
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+            .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, dir.join("file"));
+        assert!(item.file_type.is_file());
+    }
+
+    #[test]
+    fn handle_metadata() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("file"), b"foobar")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            metadata: true,
+            check_types: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(item.file_type.is_file());
+        assert_eq!(item.metadata.as_ref().unwrap().len(), 6);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_check_types() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("file"), b"")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("dir"))
+            .unwrap();
+        std::os::unix::fs::symlink("file", tempdir.path().join("link"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            metadata: false,
+            check_types: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let mut items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| item.path.clone());
+
+        assert_eq!(items.len(), 3);
+
+        assert!(items[0].file_type.is_dir());
+        assert_eq!(items[0].type_mismatch, Some(false));
+        assert!(items[0].metadata.is_none());
+
+        assert!(items[1].file_type.is_file());
+        assert_eq!(items[1].type_mismatch, Some(false));
+        assert!(items[1].metadata.is_none());
+
+        // Symlinks are not followed, so their listed type is a symlink as well.
+        assert!(items[2].file_type.is_symlink());
+        assert_eq!(items[2].type_mismatch, Some(false));
+        assert!(items[2].metadata.is_none());
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of entries of the hosts file of the system.
+//!
+//! Entries of the hosts file take precedence over DNS on most systems, so they
+//! are a simple way for attackers to hijack hostnames (e.g. to redirect update
+//! or security software traffic).
+
+use std::path::PathBuf;
+
+/// Result of the `list_hosts_entries` action.
+struct Item {
+    /// Path to the hosts file the entry comes from.
+    path: PathBuf,
+    /// Parsed entry of the hosts file.
+    entry: Entry,
+}
+
+/// Single entry of the hosts file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    /// IP address the names resolve to.
+    ip_addr: std::net::IpAddr,
+    /// Canonical hostname followed by its aliases.
+    names: Vec<String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListHostsEntries;
+
+/// Handles invocations of the `list_hosts_entries` action.
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let path = hosts_path();
+
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        // Lack of the hosts file is valid and equivalent to an empty one.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            log::info!("hosts file '{}' does not exist", path.display());
+            return Ok(());
+        }
+        Err(error) => return Err(crate::session::Error::action(error)),
+    };
+
+    let entries = parse_entries(std::io::BufReader::new(file))
+        .map_err(crate::session::Error::action)?;
+
+    for entry in entries {
+        session.reply(Item {
+            path: path.clone(),
+            entry,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the path to the hosts file of the system.
+#[cfg(target_family = "unix")]
+fn hosts_path() -> PathBuf {
+    PathBuf::from("/etc/hosts")
+}
+
+/// Returns the path to the hosts file of the system.
+#[cfg(target_family = "windows")]
+fn hosts_path() -> PathBuf {
+    let system_root = std::env::var_os("SystemRoot")
+        .unwrap_or_else(|| std::ffi::OsString::from("C:\\Windows"));
+
+    let mut path = PathBuf::from(system_root);
+    path.extend(["System32", "drivers", "etc", "hosts"]);
+    path
+}
+
+/// Parses entries of a hosts file [1].
+///
+/// Each line with an IP address followed by at least one name yields a single
+/// entry. Comments, blank lines and malformed lines are skipped.
+///
+/// [1]: https://man7.org/linux/man-pages/man5/hosts.5.html
+fn parse_entries<R>(reader: R) -> std::io::Result<Vec<Entry>>
+where
+    R: std::io::BufRead,
+{
+    let mut entries = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        let line = match line.split_once('#') {
+            Some((line, _)) => line,
+            None => &line[..],
+        };
+
+        let mut words = line.split_whitespace();
+
+        let Some(ip_addr) = words.next() else {
+            continue;
+        };
+        let ip_addr = match ip_addr.parse() {
+            Ok(ip_addr) => ip_addr,
+            Err(error) => {
+                log::warn!("invalid hosts file address '{ip_addr}': {error}");
+                continue;
+            }
+        };
+
+        let names = words.map(String::from).collect::<Vec<_>>();
+        if names.is_empty() {
+            continue;
+        }
+
+        entries.push(Entry {
+            ip_addr,
+            names,
+        });
+    }
+
+    Ok(entries)
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_hosts_entries::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_ip_address(self.entry.ip_addr.into());
+        proto.set_names(self.entry.names);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn handle_some_entries() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        for item in session.replies::<Item>() {
+            assert_eq!(item.path, hosts_path());
+            assert!(!item.entry.names.is_empty());
+        }
+    }
+
+    #[test]
+    fn parse_entries_empty() {
+        let entries = parse_entries(&b""[..])
+            .unwrap();
+
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn parse_entries_sample() {
+        let entries = parse_entries(&b"\
+# Static table lookup for hostnames.
+
+127.0.0.1\tlocalhost
+::1     localhost ip6-localhost ip6-loopback # IPv6 loopback.
+   # 10.0.0.1 commented.example.com
+192.168.1.10 update.example.com  update  updates
+foo.example.com 10.0.0.2
+10.0.0.3
+"[..]).unwrap();
+
+        assert_eq!(entries, vec! {
+            Entry {
+                ip_addr: Ipv4Addr::LOCALHOST.into(),
+                names: vec!["localhost".into()],
+            },
+            Entry {
+                ip_addr: Ipv6Addr::LOCALHOST.into(),
+                names: vec![
+                    "localhost".into(),
+                    "ip6-localhost".into(),
+                    "ip6-loopback".into(),
+                ],
+            },
+            Entry {
+                ip_addr: Ipv4Addr::new(192, 168, 1, 10).into(),
+                names: vec![
+                    "update.example.com".into(),
+                    "update".into(),
+                    "updates".into(),
+                ],
+            },
+        });
+    }
+}
@@ -0,0 +1,551 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of launchd jobs configured on macOS.
+//!
+//! Jobs are read from property lists in the standard `LaunchAgents` and
+//! `LaunchDaemons` directories (including the per-user ones) and merged with
+//! the output of `launchctl list` to tell which of them are loaded. Jobs that
+//! are loaded but have no definition in these directories are reported too.
+
+#[cfg(target_os = "macos")]
+use std::path::{Path, PathBuf};
+
+/// System directories with property lists defining launchd jobs.
+#[cfg(target_os = "macos")]
+const SYSTEM_DIRS: &[&str] = &[
+    "/Library/LaunchAgents",
+    "/Library/LaunchDaemons",
+    "/System/Library/LaunchAgents",
+    "/System/Library/LaunchDaemons",
+];
+
+/// Directory (relative to the home directory) with per-user launchd agents.
+#[cfg(target_os = "macos")]
+const USER_DIR: &str = "Library/LaunchAgents";
+
+/// A result of the `list_launchd_jobs` action.
+#[cfg(target_os = "macos")]
+struct Item {
+    /// Path to the property list defining the job (if known).
+    path: Option<PathBuf>,
+    /// Definition of the job.
+    job: Job,
+    /// Whether the job is currently loaded.
+    loaded: bool,
+    /// Identifier of the process of the job (if it is running).
+    pid: Option<u32>,
+}
+
+/// Definition of a launchd job.
+#[cfg(target_os = "macos")]
+#[derive(Debug, Default)]
+struct Job {
+    /// Label uniquely identifying the job.
+    label: String,
+    /// Path to the executable run by the job.
+    program: Option<PathBuf>,
+    /// Arguments passed to the executable.
+    program_arguments: Vec<String>,
+    /// Whether the job is started as soon as it is loaded.
+    run_at_load: bool,
+    /// Whether the job is kept alive.
+    keep_alive: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListLaunchdJobs;
+
+/// Handles invocations of the `list_launchd_jobs` action.
+#[cfg(target_os = "macos")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut dirs = SYSTEM_DIRS.iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+
+    match ospect::os::users() {
+        Ok(users) => dirs.extend(users.map(|user| user.home.join(USER_DIR))),
+        Err(error) => log::warn!("failed to list users: {error}"),
+    }
+
+    // `launchctl` not being available is unexpected, but we can still report
+    // the job definitions without the information on whether they are loaded.
+    let mut loaded = match launchctl_list() {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            log::warn!("failed to list loaded launchd jobs: {error}");
+            std::collections::HashMap::new()
+        }
+    };
+
+    for dir in dirs {
+        for (path, job) in jobs_in_dir(&dir) {
+            let pid = loaded.remove(&job.label);
+            session.reply(Item {
+                path: Some(path),
+                loaded: pid.is_some(),
+                pid: pid.flatten(),
+                job,
+            })?;
+        }
+    }
+
+    for (label, pid) in loaded {
+        session.reply(Item {
+            path: None,
+            job: Job {
+                label,
+                ..Default::default()
+            },
+            loaded: true,
+            pid,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Handles invocations of the `list_launchd_jobs` action.
+#[cfg(not(target_os = "macos"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Returns jobs defined by property lists in the given directory.
+///
+/// Directories that do not exist contribute no jobs. Files that are not valid
+/// job definitions are skipped with a warning.
+#[cfg(target_os = "macos")]
+fn jobs_in_dir(dir: &Path) -> Vec<(PathBuf, Job)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Vec::new();
+        }
+        Err(error) => {
+            log::warn!("failed to read launchd directory '{}': {error}", dir.display());
+            return Vec::new();
+        }
+    };
+
+    let mut jobs = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                log::warn!("failed to read entry of '{}': {error}", dir.display());
+                continue;
+            }
+        };
+
+        if path.extension() != Some(std::ffi::OsStr::new("plist")) {
+            continue;
+        }
+
+        match read_job(&path) {
+            Ok(job) => jobs.push((path, job)),
+            Err(error) => {
+                log::warn!("skipping malformed launchd job '{}': {error}", path.display());
+            }
+        }
+    }
+
+    jobs
+}
+
+/// Reads a job definition from the property list at the given path.
+#[cfg(target_os = "macos")]
+fn read_job(path: &Path) -> std::io::Result<Job> {
+    let mut contents = std::fs::read(path)?;
+
+    // Property lists can be stored in a binary format that we do not parse
+    // ourselves, so we ask the system to convert them to XML.
+    if contents.starts_with(b"bplist") {
+        let output = std::process::Command::new("plutil")
+            .args(["-convert", "xml1", "-o", "-"])
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, {
+                String::from_utf8_lossy(&output.stderr).into_owned()
+            }));
+        }
+        contents = output.stdout;
+    }
+
+    let contents = String::from_utf8(contents)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    parse_job(&contents)
+}
+
+/// Parses a job definition from an XML property list.
+#[cfg(target_os = "macos")]
+fn parse_job(contents: &str) -> std::io::Result<Job> {
+    let entries = match plist::parse(contents)? {
+        plist::Value::Dict(entries) => entries,
+        _ => return Err(plist::invalid("job definition is not a dictionary")),
+    };
+
+    let mut job = Job::default();
+    let mut label = None;
+
+    for (key, value) in entries {
+        match (key.as_str(), value) {
+            ("Label", plist::Value::String(value)) => label = Some(value),
+            ("Program", plist::Value::String(value)) => {
+                job.program = Some(PathBuf::from(value));
+            }
+            ("ProgramArguments", plist::Value::Array(values)) => {
+                job.program_arguments = values.into_iter()
+                    .filter_map(|value| match value {
+                        plist::Value::String(value) => Some(value),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            ("RunAtLoad", plist::Value::Bool(value)) => job.run_at_load = value,
+            ("KeepAlive", plist::Value::Bool(value)) => job.keep_alive = value,
+            // Job can be also kept alive depending on a set of conditions. We
+            // do not report these, only that the job can be kept alive.
+            ("KeepAlive", plist::Value::Dict(conditions)) => {
+                job.keep_alive = !conditions.is_empty();
+            }
+            _ => continue,
+        }
+    }
+
+    job.label = label.ok_or_else(|| plist::invalid("job without a label"))?;
+
+    if job.program.is_none() {
+        job.program = job.program_arguments.first().map(PathBuf::from);
+    }
+
+    Ok(job)
+}
+
+/// Returns labels of loaded jobs along with pids of their processes.
+#[cfg(target_os = "macos")]
+fn launchctl_list() -> std::io::Result<std::collections::HashMap<String, Option<u32>>> {
+    let output = std::process::Command::new("launchctl")
+        .arg("list")
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(std::io::ErrorKind::Other, {
+            String::from_utf8_lossy(&output.stderr).into_owned()
+        }));
+    }
+
+    Ok(parse_launchctl_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the output of `launchctl list`.
+///
+/// The output consists of a header followed by tab-separated lines with the
+/// pid (or `-` if the job is not running), last exit status and label.
+#[cfg(target_os = "macos")]
+fn parse_launchctl_list(output: &str) -> std::collections::HashMap<String, Option<u32>> {
+    output.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let pid = parts.next()?;
+            let _status = parts.next()?;
+            let label = parts.next()?;
+
+            Some((String::from(label), pid.parse().ok()))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_launchd_jobs::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        if let Some(path) = self.path {
+            proto.set_path(path.into());
+        }
+        proto.set_label(self.job.label);
+        if let Some(program) = self.job.program {
+            proto.set_program(program.into());
+        }
+        proto.set_program_arguments(self.job.program_arguments);
+        proto.set_run_at_load(self.job.run_at_load);
+        proto.set_keep_alive(self.job.keep_alive);
+        proto.set_loaded(self.loaded);
+        if let Some(pid) = self.pid {
+            proto.set_pid(pid);
+        }
+
+        proto
+    }
+}
+
+/// A minimal parser of property lists in the XML format.
+///
+/// Only the subset of the format needed to read job definitions is supported:
+/// values of types other than strings, booleans, arrays and dictionaries are
+/// parsed but their contents are not interpreted.
+#[cfg(target_os = "macos")]
+mod plist {
+
+    /// A value stored in a property list.
+    #[derive(Debug, PartialEq)]
+    pub enum Value {
+        String(String),
+        Bool(bool),
+        Array(Vec<Value>),
+        Dict(Vec<(String, Value)>),
+        /// Value of any other type (integer, real, date or data).
+        Other,
+    }
+
+    /// Parses the top-level value of the given XML property list.
+    pub fn parse(contents: &str) -> std::io::Result<Value> {
+        let start = contents.find("<plist")
+            .ok_or_else(|| invalid("no `plist` element"))?;
+
+        let mut parser = Parser {
+            rest: &contents[start..],
+        };
+        parser.open_tag()?;
+        parser.value()
+    }
+
+    /// Creates an error for malformed property lists.
+    pub fn invalid(message: &str) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+    }
+
+    /// State of the parser: the part of the document that is not yet parsed.
+    struct Parser<'a> {
+        rest: &'a str,
+    }
+
+    impl<'a> Parser<'a> {
+
+        /// Parses a single value (including its tags).
+        fn value(&mut self) -> std::io::Result<Value> {
+            let tag = self.open_tag()?;
+            match tag {
+                "true/" => Ok(Value::Bool(true)),
+                "false/" => Ok(Value::Bool(false)),
+                "string/" => Ok(Value::String(String::new())),
+                "array/" => Ok(Value::Array(Vec::new())),
+                "dict/" => Ok(Value::Dict(Vec::new())),
+                "string" => Ok(Value::String(self.text("string")?)),
+                "integer" | "real" | "date" | "data" => {
+                    self.text(tag)?;
+                    Ok(Value::Other)
+                }
+                "array" => {
+                    let mut values = Vec::new();
+                    while !self.close_tag("array") {
+                        values.push(self.value()?);
+                    }
+                    Ok(Value::Array(values))
+                }
+                "dict" => {
+                    let mut entries = Vec::new();
+                    while !self.close_tag("dict") {
+                        if self.open_tag()? != "key" {
+                            return Err(invalid("dictionary entry without a key"));
+                        }
+                        let key = self.text("key")?;
+                        entries.push((key, self.value()?));
+                    }
+                    Ok(Value::Dict(entries))
+                }
+                _ => Err(invalid("unexpected element")),
+            }
+        }
+
+        /// Consumes the next opening tag and returns its name.
+        ///
+        /// Attributes are not part of the returned name but the slash of an
+        /// empty element is (e.g. `true/`).
+        fn open_tag(&mut self) -> std::io::Result<&'a str> {
+            self.skip_misc();
+
+            let rest = self.rest.strip_prefix('<')
+                .ok_or_else(|| invalid("expected an element"))?;
+            let end = rest.find('>')
+                .ok_or_else(|| invalid("unterminated tag"))?;
+
+            let tag = &rest[..end];
+            self.rest = &rest[end + 1..];
+
+            match tag.find(char::is_whitespace) {
+                Some(index) => Ok(&tag[..index]),
+                None => Ok(tag),
+            }
+        }
+
+        /// Consumes the closing tag with the given name if it is next.
+        fn close_tag(&mut self, name: &str) -> bool {
+            self.skip_misc();
+
+            let rest = match self.rest.strip_prefix("</") {
+                Some(rest) => rest,
+                None => return false,
+            };
+            match rest.strip_prefix(name).and_then(|rest| rest.strip_prefix('>')) {
+                Some(rest) => {
+                    self.rest = rest;
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Consumes the text of an element and its closing tag.
+        fn text(&mut self, name: &str) -> std::io::Result<String> {
+            let end = self.rest.find("</")
+                .ok_or_else(|| invalid("unterminated element"))?;
+
+            let text = unescape(&self.rest[..end]);
+            self.rest = &self.rest[end..];
+
+            if !self.close_tag(name) {
+                return Err(invalid("mismatched closing tag"));
+            }
+
+            Ok(text)
+        }
+
+        /// Skips whitespace, comments and XML declarations.
+        fn skip_misc(&mut self) {
+            loop {
+                self.rest = self.rest.trim_start();
+
+                let end = if self.rest.starts_with("<!--") {
+                    self.rest.find("-->").map(|index| index + "-->".len())
+                } else if self.rest.starts_with("<?") || self.rest.starts_with("<!") {
+                    self.rest.find('>').map(|index| index + 1)
+                } else {
+                    return;
+                };
+
+                self.rest = match end {
+                    Some(end) => &self.rest[end..],
+                    None => "",
+                };
+            }
+        }
+    }
+
+    /// Replaces predefined XML entities with the characters they denote.
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "macos")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_ok() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        // There are plenty of system jobs on every macOS installation.
+        assert!(session.reply_count() > 0);
+    }
+
+    #[test]
+    fn parse_job_sample() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.example.foo</string>
+    <!-- Arguments of the program. -->
+    <key>ProgramArguments</key>
+    <array>
+        <string>/usr/local/bin/foo</string>
+        <string>--bar=&quot;baz&quot;</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>StartInterval</key>
+    <integer>3600</integer>
+</dict>
+</plist>
+"#;
+
+        let job = parse_job(contents)
+            .unwrap();
+
+        assert_eq!(job.label, "com.example.foo");
+        assert_eq!(job.program, Some(PathBuf::from("/usr/local/bin/foo")));
+        assert_eq!(job.program_arguments, vec!["/usr/local/bin/foo", "--bar=\"baz\""]);
+        assert!(job.run_at_load);
+        assert!(job.keep_alive);
+    }
+
+    #[test]
+    fn parse_job_program() {
+        let contents = r#"<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.example.bar</string>
+    <key>Program</key>
+    <string>/usr/sbin/bar</string>
+    <key>KeepAlive</key>
+    <false/>
+</dict>
+</plist>
+"#;
+
+        let job = parse_job(contents)
+            .unwrap();
+
+        assert_eq!(job.label, "com.example.bar");
+        assert_eq!(job.program, Some(PathBuf::from("/usr/sbin/bar")));
+        assert!(job.program_arguments.is_empty());
+        assert!(!job.run_at_load);
+        assert!(!job.keep_alive);
+    }
+
+    #[test]
+    fn parse_job_malformed() {
+        assert!(parse_job("<plist><dict><key>Label</key>").is_err());
+        assert!(parse_job("<plist><array/></plist>").is_err());
+        assert!(parse_job("<plist><dict></dict></plist>").is_err());
+    }
+
+    #[test]
+    fn parse_launchctl_list_ok() {
+        let output = "PID\tStatus\tLabel\n123\t0\tcom.example.foo\n-\t78\tcom.example.bar\n";
+
+        let loaded = parse_launchctl_list(output);
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["com.example.foo"], Some(123));
+        assert_eq!(loaded["com.example.bar"], None);
+    }
+}
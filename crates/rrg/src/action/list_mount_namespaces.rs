@@ -0,0 +1,194 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of mount namespaces of processes on the system.
+//!
+//! Processes are grouped by the inode of their mount namespace. On container
+//! hosts this allows responders to understand container boundaries: processes
+//! in separate containers usually live in separate mount namespaces and see
+//! different filesystems.
+
+/// Result of the `list_mount_namespaces` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Inode number identifying the mount namespace.
+    inode: u64,
+    /// Identifiers of the processes that live in the mount namespace.
+    pids: Vec<u32>,
+    /// Whether the agent itself lives in the mount namespace.
+    agent_namespace: bool,
+    /// Filesystems mounted in the namespace (or error if not available).
+    mounts: std::io::Result<Vec<ospect::fs::Mount>>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListMountNamespaces;
+
+/// Handles invocations of the `list_mount_namespaces` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `list_mount_namespaces` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use ospect::proc::linux::mount_namespace;
+
+    let agent_inode = mount_namespace(std::process::id())
+        .map_err(crate::session::Error::action)?;
+
+    let pids = ospect::proc::ids()
+        .map_err(crate::session::Error::action)?;
+
+    let mut namespaces = std::collections::BTreeMap::<u64, Vec<u32>>::new();
+
+    for pid in pids {
+        let pid = match pid {
+            Ok(pid) => pid,
+            Err(error) => {
+                log::warn!("failed to obtain process identifier: {error}");
+                continue;
+            }
+        };
+
+        // Without the namespace inode there is no way to tell which group the
+        // process belongs to, so we have to skip it.
+        let inode = match mount_namespace(pid) {
+            Ok(Some(inode)) => inode,
+            Ok(None) => continue,
+            Err(error) => {
+                log::warn!("failed to obtain mount namespace of process {pid}: {error}");
+                continue;
+            }
+        };
+
+        namespaces.entry(inode).or_default().push(pid);
+    }
+
+    for (inode, pids) in namespaces {
+        let mounts = namespace_mounts(&pids);
+
+        session.reply(Item {
+            inode,
+            pids,
+            agent_namespace: agent_inode == Some(inode),
+            mounts,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Collects filesystems mounted in the namespace of the given processes.
+///
+/// All the processes are assumed to live in the same namespace, so it is enough
+/// to inspect any of them. Processes are tried one by one as they can exit at
+/// any moment.
+#[cfg(target_os = "linux")]
+fn namespace_mounts(pids: &[u32]) -> std::io::Result<Vec<ospect::fs::Mount>> {
+    let mut last_error = None;
+
+    for pid in pids {
+        let mounts = ospect::fs::linux::process_mounts(*pid)
+            .and_then(|mounts| mounts.collect::<std::io::Result<Vec<_>>>());
+
+        match ospect::proc::linux::skip_exited(mounts) {
+            Ok(Some(mounts)) => return Ok(mounts),
+            Ok(None) => continue,
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| std::io::ErrorKind::NotFound.into()))
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_mount_namespaces::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_inode(self.inode);
+        proto.set_pids(self.pids);
+        proto.set_agent_namespace(self.agent_namespace);
+
+        match self.mounts {
+            Ok(mounts) => {
+                for mount in mounts {
+                    proto.mut_mounts().push(mount.into());
+                }
+            }
+            Err(error) => proto.set_mounts_error(error.to_string()),
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_agent_namespace() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let items = session.replies::<Item>()
+            .filter(|item| item.agent_namespace)
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let item = items[0];
+        assert!(item.pids.contains(&std::process::id()));
+
+        let mounts = item.mounts.as_ref()
+            .unwrap();
+        assert!(mounts.iter().any(|mount| mount.path == std::path::Path::new("/")));
+    }
+
+    #[test]
+    fn handle_namespaces_disjoint() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, ()).is_ok());
+
+        let mut pids = session.replies::<Item>()
+            .flat_map(|item| item.pids.iter().copied())
+            .collect::<Vec<_>>();
+        let count = pids.len();
+
+        pids.sort();
+        pids.dedup();
+        assert_eq!(pids.len(), count);
+    }
+
+    #[test]
+    fn namespace_mounts_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        let mounts = namespace_mounts(&[pid, std::process::id()])
+            .unwrap();
+        assert!(!mounts.is_empty());
+
+        assert!(namespace_mounts(&[pid]).is_err());
+    }
+}
@@ -10,6 +10,9 @@ struct Item {
 }
 
 // Handles invocations of the `list_mounts` action.
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListMounts;
+
 pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
 where
     S: crate::session::Session,
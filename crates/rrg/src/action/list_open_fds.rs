@@ -0,0 +1,238 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of open file descriptors of processes (similar to `lsof`).
+//!
+//! File descriptors are read from the `/proc/<pid>/fd` directories, so this
+//! action is available only on Linux.
+
+/// Arguments of the `list_open_fds` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Identifiers of processes to list the file descriptors of (all if empty).
+    pids: Vec<u32>,
+}
+
+/// Result of the `list_open_fds` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Identifier of the process that has the file descriptor open.
+    pid: u32,
+    /// Open file descriptor of the process.
+    fd: ospect::proc::linux::Fd,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListOpenFds;
+
+/// Handles invocations of the `list_open_fds` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `list_open_fds` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if !args.pids.is_empty() {
+        for pid in args.pids {
+            // Explicitly requested processes that we fail to inspect are
+            // reported as errors, unless they have simply exited.
+            match ospect::proc::linux::fds(pid).map_err(crate::session::Error::action)? {
+                Some(fds) => reply(session, pid, fds)?,
+                None => log::warn!("process {pid} has exited"),
+            }
+        }
+
+        return Ok(());
+    }
+
+    let pids = ospect::proc::ids()
+        .map_err(crate::session::Error::action)?;
+
+    for pid in pids {
+        let pid = match pid {
+            Ok(pid) => pid,
+            Err(error) => {
+                log::warn!("failed to obtain process identifier: {error}");
+                continue;
+            }
+        };
+
+        match ospect::proc::linux::fds(pid) {
+            Ok(Some(fds)) => reply(session, pid, fds)?,
+            Ok(None) => continue,
+            Err(error) => {
+                log::warn!("failed to list file descriptors of process {pid}: {error}");
+                continue;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the given file descriptors of the process as replies.
+#[cfg(target_os = "linux")]
+fn reply<S>(
+    session: &mut S,
+    pid: u32,
+    fds: Vec<ospect::proc::linux::Fd>,
+) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for fd in fds {
+        session.reply(Item {
+            pid,
+            fd,
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_open_fds::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            pids: proto.take_pids(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_open_fds::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        use ospect::proc::linux::FdTarget;
+        use rrg_proto::list_open_fds::TargetType;
+
+        let mut proto = Self::Proto::default();
+        proto.set_pid(self.pid);
+        proto.set_fd(self.fd.fd);
+
+        match self.fd.target {
+            FdTarget::Path(path) => {
+                proto.set_target_type(TargetType::FILE);
+                proto.set_raw_target(path.as_os_str().as_bytes().to_vec());
+                proto.set_path(path.into());
+            }
+            FdTarget::Socket(inode) => {
+                proto.set_target_type(TargetType::SOCKET);
+                proto.set_raw_target(format!("socket:[{inode}]").into_bytes());
+                proto.set_inode(inode);
+            }
+            FdTarget::Pipe(inode) => {
+                proto.set_target_type(TargetType::PIPE);
+                proto.set_raw_target(format!("pipe:[{inode}]").into_bytes());
+                proto.set_inode(inode);
+            }
+            FdTarget::AnonInode(kind) => {
+                let mut raw_target = b"anon_inode:".to_vec();
+                raw_target.extend_from_slice(kind.as_bytes());
+
+                proto.set_target_type(TargetType::ANON_INODE);
+                proto.set_raw_target(raw_target);
+                proto.set_anon_inode_type(kind.to_string_lossy().into_owned());
+            }
+            FdTarget::Other(raw_target) => {
+                proto.set_target_type(TargetType::UNKNOWN);
+                proto.set_raw_target(raw_target.as_bytes().to_vec());
+            }
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_self_temp_file() {
+        use std::os::fd::AsRawFd as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        let fd = u32::try_from(tempfile.as_file().as_raw_fd())
+            .unwrap();
+
+        let args = Args {
+            pids: vec![std::process::id()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.replies::<Item>().all(|item| item.pid == std::process::id()));
+
+        let item = session.replies::<Item>()
+            .find(|item| item.fd.fd == fd)
+            .unwrap();
+
+        let path = tempfile.path().canonicalize()
+            .unwrap();
+        assert_eq!(item.fd.target, ospect::proc::linux::FdTarget::Path(path));
+    }
+
+    #[test]
+    fn handle_self_socket() {
+        use std::os::fd::AsRawFd as _;
+        use std::os::unix::fs::MetadataExt as _;
+
+        let socket = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .unwrap();
+        let fd = u32::try_from(socket.as_raw_fd())
+            .unwrap();
+
+        // Inode of the socket is the same as the one of the entry in `/proc`.
+        let inode = std::fs::metadata(format!("/proc/self/fd/{fd}"))
+            .unwrap()
+            .ino();
+
+        let args = Args {
+            pids: vec![std::process::id()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.replies::<Item>()
+            .find(|item| item.fd.fd == fd)
+            .unwrap();
+
+        assert_eq!(item.fd.target, ospect::proc::linux::FdTarget::Socket(inode));
+    }
+
+    #[test]
+    fn handle_all() {
+        let args = Args {
+            pids: vec![],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.replies::<Item>().any(|item| item.pid == std::process::id()));
+    }
+}
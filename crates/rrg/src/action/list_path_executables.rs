@@ -0,0 +1,572 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of executables reachable through the `PATH` environment variable.
+//!
+//! Replacing system binaries (or planting executables with the same names in
+//! directories that take precedence) is a common way of trojaning a system.
+//! The action reports every executable that can be invoked through `PATH` with
+//! its digest and flags names provided by more than one directory.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Size of the buffer used for reading executables.
+const BUF_LEN: usize = 64 * 1024; // 64 KiB.
+
+/// Arguments of the `list_path_executables` action.
+pub struct Args {
+    /// Directories to look for executables in (`PATH` of the agent if empty).
+    dirs: Vec<PathBuf>,
+    /// Maximum size of executables to hash (if limited).
+    max_file_size: Option<u64>,
+}
+
+/// Result of the `list_path_executables` action.
+struct Item {
+    /// Executable that was found.
+    executable: Executable,
+    /// SHA-256 digest of the executable or the reason it was not computed.
+    sha256: std::io::Result<[u8; 32]>,
+    /// Whether a directory that takes precedence has the same command.
+    shadowed: bool,
+    /// Whether a directory of lower precedence has the same command.
+    shadows: bool,
+}
+
+/// Executable found in one of the directories.
+struct Executable {
+    /// Name of the command under which the executable can be invoked.
+    name: OsString,
+    /// Path to the executable within the directory.
+    path: PathBuf,
+    /// Index of the directory the executable was found in.
+    dir_index: u32,
+    /// Canonical path to the target of the executable (if it is a symlink).
+    symlink_target: Option<PathBuf>,
+    /// Metadata of the executable (of the target in case of symlinks).
+    metadata: std::fs::Metadata,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListPathExecutables;
+
+/// Handles invocations of the `list_path_executables` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let dirs = if args.dirs.is_empty() {
+        match std::env::var_os("PATH") {
+            Some(path) => std::env::split_paths(&path).collect(),
+            None => Vec::new(),
+        }
+    } else {
+        args.dirs
+    };
+
+    let mut executables = Vec::new();
+    for (dir_index, dir) in (0..).zip(unique_dirs(dirs)) {
+        match list_executables(&dir, dir_index) {
+            Ok(dir_executables) => executables.extend(dir_executables),
+            Err(error) => {
+                log::warn!("failed to list executables in '{}': {error}", dir.display());
+            }
+        }
+    }
+
+    // Indices of the first and the last directory providing every command.
+    let mut dir_index_ranges = HashMap::<OsString, (u32, u32)>::new();
+    for executable in &executables {
+        dir_index_ranges.entry(command_key(&executable.name))
+            .and_modify(|(_, last)| *last = executable.dir_index)
+            .or_insert((executable.dir_index, executable.dir_index));
+    }
+
+    for executable in executables {
+        let (first, last) = dir_index_ranges[&command_key(&executable.name)];
+
+        let sha256 = match args.max_file_size {
+            Some(max_file_size) if executable.metadata.len() > max_file_size => {
+                Err(std::io::Error::other(FileTooBigError {
+                    len: executable.metadata.len(),
+                    max_len: max_file_size,
+                }))
+            }
+            _ => {
+                let path = executable.symlink_target.as_ref()
+                    .unwrap_or(&executable.path);
+
+                match hash(session, path) {
+                    Ok(sha256) => Ok(sha256),
+                    // Limits of the session are not failures of a particular
+                    // executable and have to abort the whole action.
+                    Err(HashError::Session(error)) => return Err(error),
+                    Err(HashError::Io(error)) => {
+                        log::warn!("failed to hash '{}': {error}", path.display());
+                        Err(error)
+                    }
+                }
+            }
+        };
+
+        let shadowed = first < executable.dir_index;
+        let shadows = executable.dir_index < last;
+
+        session.reply(Item {
+            executable,
+            sha256,
+            shadowed,
+            shadows,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the given directories without relative and repeated ones.
+///
+/// Directories are compared by their canonical paths, so that the same
+/// directory listed twice (e.g. through a symlink like `/bin` pointing to
+/// `/usr/bin`) does not appear to shadow itself. The first occurrence wins.
+fn unique_dirs(dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut canonical_dirs = std::collections::HashSet::new();
+
+    dirs.into_iter()
+        .filter(|dir| {
+            // Relative directories (including the empty ones meaning the
+            // current directory) depend on where the command is invoked from.
+            if dir.is_relative() {
+                log::warn!("ignoring relative directory '{}'", dir.display());
+                return false;
+            }
+
+            match dir.canonicalize() {
+                Ok(canonical_dir) => canonical_dirs.insert(canonical_dir),
+                Err(error) => {
+                    log::debug!("failed to canonicalize '{}': {error}", dir.display());
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+/// Lists executables in the given directory (sorted by their names).
+fn list_executables(dir: &Path, dir_index: u32) -> std::io::Result<Vec<Executable>> {
+    let mut executables = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::warn!("failed to read entry in '{}': {error}", dir.display());
+                continue
+            }
+        };
+        let path = entry.path();
+
+        let symlink_metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(error) => {
+                log::warn!("failed to stat '{}': {error}", path.display());
+                continue
+            }
+        };
+
+        // Executables are invoked through symlinks as if they were the target
+        // files, so we look at the targets but keep track of both.
+        let (metadata, symlink_target) = if symlink_metadata.is_symlink() {
+            let target = match path.canonicalize() {
+                Ok(target) => target,
+                // Dangling symlinks cannot be executed, so there is nothing to
+                // report.
+                Err(error) => {
+                    log::debug!("failed to resolve '{}': {error}", path.display());
+                    continue
+                }
+            };
+            match target.metadata() {
+                Ok(metadata) => (metadata, Some(target)),
+                Err(error) => {
+                    log::warn!("failed to stat '{}': {error}", target.display());
+                    continue
+                }
+            }
+        } else {
+            (symlink_metadata, None)
+        };
+
+        let name = entry.file_name();
+        if !is_executable(&name, &metadata) {
+            continue
+        }
+
+        executables.push(Executable {
+            name,
+            path,
+            dir_index,
+            symlink_target,
+            metadata,
+        });
+    }
+
+    executables.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+    Ok(executables)
+}
+
+/// Checks whether the file of the given name and metadata is an executable.
+#[cfg(target_family = "unix")]
+fn is_executable(_name: &std::ffi::OsStr, metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+/// Checks whether the file of the given name and metadata is an executable.
+#[cfg(target_family = "windows")]
+fn is_executable(name: &std::ffi::OsStr, metadata: &std::fs::Metadata) -> bool {
+    let extension = match Path::new(name).extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => return false,
+    };
+
+    let exts = std::env::var("PATHEXT")
+        .unwrap_or_else(|_| String::from(".COM;.EXE;.BAT;.CMD"));
+
+    metadata.is_file() && exts.split(';').any(|ext| {
+        ext.strip_prefix('.').is_some_and(|ext| ext.to_lowercase() == extension)
+    })
+}
+
+/// Returns a key identifying the command invoked by the executable name.
+///
+/// On Windows commands are invoked without their extensions and names are not
+/// case-sensitive, so e.g. `FOO.EXE` and `foo.bat` provide the same command.
+fn command_key(name: &std::ffi::OsStr) -> OsString {
+    #[cfg(target_family = "windows")]
+    {
+        let stem = Path::new(name).file_stem().unwrap_or(name);
+        OsString::from(stem.to_string_lossy().to_lowercase())
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    {
+        name.to_os_string()
+    }
+}
+
+/// Error that can occur while hashing an executable.
+enum HashError {
+    /// The executable could not be read.
+    Io(std::io::Error),
+    /// Limits of the session were exceeded.
+    Session(crate::session::Error),
+}
+
+/// Computes the SHA-256 digest of the file at the given `path`.
+fn hash<S>(session: &mut S, path: &Path) -> Result<[u8; 32], HashError>
+where
+    S: crate::session::Session,
+{
+    use std::io::Read as _;
+
+    use sha2::Digest as _;
+
+    let mut file = crate::fs::open_regular_file(path)
+        .map_err(HashError::Io)?;
+
+    let mut sha256 = sha2::Sha256::new();
+
+    let mut buf = vec![0; BUF_LEN];
+    loop {
+        session.check_deadline()
+            .map_err(HashError::Session)?;
+
+        let len_read = match file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(HashError::Io(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+        session.throttle(len_read as u64)
+            .map_err(HashError::Session)?;
+
+        sha256.update(&buf[..len_read]);
+    }
+
+    Ok(sha256.finalize().into())
+}
+
+/// An error type for when an executable is too big to be hashed.
+#[derive(Debug)]
+struct FileTooBigError {
+    /// Size of the executable.
+    len: u64,
+    /// Maximum size of executables that are hashed.
+    max_len: u64,
+}
+
+impl std::fmt::Display for FileTooBigError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "file of {} bytes bigger than the limit of {} bytes", self.len, self.max_len)
+    }
+}
+
+impl std::error::Error for FileTooBigError {
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_path_executables::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let dirs = proto.take_dirs().into_iter()
+            .map(|dir| {
+                PathBuf::try_from(dir)
+                    .map_err(|error| ParseArgsError::invalid_field("dirs", error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let max_file_size = match proto.max_file_size() {
+            0 => None,
+            max_file_size => Some(max_file_size),
+        };
+
+        Ok(Args {
+            dirs,
+            max_file_size,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_path_executables::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_name(self.executable.name.to_string_lossy().into_owned());
+        proto.set_path(self.executable.path.into());
+        proto.set_dir_index(self.executable.dir_index);
+        if let Some(symlink_target) = self.executable.symlink_target {
+            proto.set_symlink_target(symlink_target.into());
+        }
+        proto.set_size(self.executable.metadata.len());
+
+        match self.sha256 {
+            Ok(sha256) => proto.set_sha256(sha256.to_vec()),
+            Err(error) => proto.set_hash_error(error.to_string()),
+        }
+
+        proto.set_shadowed(self.shadowed);
+        proto.set_shadows(self.shadows);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest as _;
+
+        sha2::Sha256::digest(data).into()
+    }
+
+    /// Creates an executable file with the given contents.
+    #[cfg(target_family = "unix")]
+    fn write_executable(path: &Path, contents: &[u8]) {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        std::fs::write(path, contents)
+            .unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_shadowed() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let dir_local = tempdir.path().join("local");
+        let dir_system = tempdir.path().join("system");
+        std::fs::create_dir(&dir_local)
+            .unwrap();
+        std::fs::create_dir(&dir_system)
+            .unwrap();
+
+        write_executable(&dir_local.join("ls"), b"#!/bin/sh\nexfiltrate\n");
+        write_executable(&dir_system.join("ls"), b"#!/bin/sh\nls\n");
+        write_executable(&dir_system.join("cat"), b"#!/bin/sh\ncat\n");
+
+        let args = Args {
+            dirs: vec![dir_local.clone(), dir_system.clone()],
+            max_file_size: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(items[0].executable.path, dir_local.join("ls"));
+        assert_eq!(items[0].executable.dir_index, 0);
+        assert!(!items[0].shadowed);
+        assert!(items[0].shadows);
+        assert_eq!(items[0].sha256.as_ref().unwrap(), &sha256(b"#!/bin/sh\nexfiltrate\n"));
+
+        assert_eq!(items[1].executable.path, dir_system.join("cat"));
+        assert_eq!(items[1].executable.dir_index, 1);
+        assert!(!items[1].shadowed);
+        assert!(!items[1].shadows);
+
+        assert_eq!(items[2].executable.path, dir_system.join("ls"));
+        assert_eq!(items[2].executable.dir_index, 1);
+        assert!(items[2].shadowed);
+        assert!(!items[2].shadows);
+        assert_eq!(items[2].sha256.as_ref().unwrap(), &sha256(b"#!/bin/sh\nls\n"));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let dir = tempdir.join("bin");
+        std::fs::create_dir(&dir)
+            .unwrap();
+
+        write_executable(&tempdir.join("foo"), b"foo");
+        std::os::unix::fs::symlink(tempdir.join("foo"), dir.join("bar"))
+            .unwrap();
+        // Dangling symlinks are not reported.
+        std::os::unix::fs::symlink(tempdir.join("quux"), dir.join("baz"))
+            .unwrap();
+
+        let args = Args {
+            dirs: vec![dir.clone()],
+            max_file_size: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.executable.name, "bar");
+        assert_eq!(item.executable.path, dir.join("bar"));
+        assert_eq!(item.executable.symlink_target, Some(tempdir.join("foo")));
+        assert_eq!(item.executable.metadata.len(), 3);
+        assert_eq!(item.sha256.as_ref().unwrap(), &sha256(b"foo"));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_non_executable() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        write_executable(&tempdir.path().join("foo"), b"foo");
+        std::fs::write(tempdir.path().join("bar"), b"bar")
+            .unwrap();
+        std::fs::create_dir(tempdir.path().join("baz"))
+            .unwrap();
+
+        let args = Args {
+            dirs: vec![tempdir.path().to_path_buf()],
+            max_file_size: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).executable.name, "foo");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_duplicate_dirs() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let dir = tempdir.path().join("bin");
+        std::fs::create_dir(&dir)
+            .unwrap();
+        std::os::unix::fs::symlink(&dir, tempdir.path().join("bin-link"))
+            .unwrap();
+
+        write_executable(&dir.join("foo"), b"foo");
+
+        // The same directory listed twice does not shadow itself.
+        let args = Args {
+            dirs: vec![
+                dir.clone(),
+                PathBuf::from("relative"),
+                tempdir.path().join("bin-link"),
+                dir.clone(),
+            ],
+            max_file_size: None,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(!item.shadowed);
+        assert!(!item.shadows);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_max_file_size() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        write_executable(&tempdir.path().join("foo"), b"foo");
+        write_executable(&tempdir.path().join("bar"), b"barbaz");
+
+        let args = Args {
+            dirs: vec![tempdir.path().to_path_buf()],
+            max_file_size: Some(3),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].executable.name, "bar");
+        assert!(items[0].sha256.is_err());
+
+        assert_eq!(items[1].executable.name, "foo");
+        assert_eq!(items[1].sha256.as_ref().unwrap(), &sha256(b"foo"));
+    }
+}
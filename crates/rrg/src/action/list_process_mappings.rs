@@ -0,0 +1,180 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of memory mappings of processes (e.g. loaded shared libraries).
+//!
+//! Mappings are read from the `/proc/<pid>/maps` files, so this action is
+//! available only on Linux.
+
+/// Arguments of the `list_process_mappings` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Identifiers of processes to list the memory mappings of.
+    pids: Vec<u32>,
+}
+
+/// Result of the `list_process_mappings` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Identifier of the process that has the memory mapped.
+    pid: u32,
+    /// Memory mapping of the process.
+    mapping: ospect::proc::linux::Mapping,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListProcessMappings;
+
+/// Handles invocations of the `list_process_mappings` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `list_process_mappings` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for pid in args.pids {
+        let mappings = match ospect::proc::linux::maps(pid) {
+            Ok(Some(mappings)) => mappings,
+            Ok(None) => {
+                log::warn!("process {pid} has exited");
+                continue;
+            }
+            Err(error) => return Err(crate::session::Error::action(error)),
+        };
+
+        for mapping in mappings {
+            session.reply(Item {
+                pid,
+                mapping,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_process_mappings::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            pids: proto.take_pids(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_process_mappings::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use ospect::proc::linux::MappingTarget;
+        use rrg_proto::list_process_mappings::TargetType;
+
+        let mut proto = Self::Proto::default();
+        proto.set_pid(self.pid);
+        proto.set_start_address(self.mapping.start);
+        proto.set_end_address(self.mapping.end);
+        proto.set_readable(self.mapping.readable);
+        proto.set_writable(self.mapping.writable);
+        proto.set_executable(self.mapping.executable);
+        proto.set_shared(self.mapping.shared);
+        proto.set_offset(self.mapping.offset);
+
+        match self.mapping.target {
+            MappingTarget::Path(path) => {
+                proto.set_target_type(TargetType::FILE);
+                proto.set_path(path.into());
+                proto.set_inode(self.mapping.inode);
+            }
+            MappingTarget::Anonymous => {
+                proto.set_target_type(TargetType::ANONYMOUS);
+            }
+            MappingTarget::Pseudo(name) => {
+                proto.set_target_type(TargetType::PSEUDO);
+                proto.set_pseudo_name(name.to_string_lossy().into_owned());
+            }
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    use ospect::proc::linux::MappingTarget;
+
+    #[test]
+    fn handle_self() {
+        let args = Args {
+            pids: vec![std::process::id()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.replies::<Item>().all(|item| item.pid == std::process::id()));
+
+        let paths = session.replies::<Item>()
+            .filter_map(|item| match &item.mapping.target {
+                MappingTarget::Path(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let exe = std::env::current_exe()
+            .unwrap();
+        assert!(paths.contains(&exe));
+
+        // The test binary is dynamically linked against the C library, which
+        // is `libc.so.6` (or `libc-<version>.so` on older glibc versions).
+        assert!(paths.iter().any(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("libc.") || name.starts_with("libc-")
+        }));
+
+        assert! {
+            session.replies::<Item>()
+                .any(|item| item.mapping.target == MappingTarget::Pseudo("[stack]".into()))
+        };
+    }
+
+    #[test]
+    fn handle_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        let args = Args {
+            pids: vec![pid, std::process::id()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.reply_count() > 0);
+        assert!(session.replies::<Item>().all(|item| item.pid == std::process::id()));
+    }
+}
@@ -0,0 +1,675 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of processes running on the system.
+//!
+//! On Linux, processes are read from the `/proc` filesystem. Every process is
+//! reported along with its parent and start time, which is enough to
+//! reconstruct the process tree. Optionally, the action can order the
+//! processes as the tree and annotate them with their depth in it.
+//!
+//! On Linux, processes can also be listed in pages (if the server requests a
+//! page size). The cursor records the identifier of the last process sent, so
+//! processes that were spawned or exited in between pages may be missed.
+//!
+//! On Windows, processes are reported along with the integrity level and the
+//! owning user obtained from their access tokens. The tree mode is not
+//! supported there.
+
+/// Arguments of the `list_processes` action.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub struct Args {
+    /// Identifiers of processes to list (all if empty).
+    pids: Vec<u32>,
+    /// Whether to order the processes as a tree.
+    tree: bool,
+    /// Whether to resolve identifiers of owners of processes to names (Linux-only).
+    resolve_owner_names: bool,
+}
+
+/// Result of the `list_processes` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Identifier of the process.
+    pid: u32,
+    /// Status of the process.
+    status: ospect::proc::linux::Status,
+    /// Time at which the process was started (if known).
+    start_time: Option<std::time::SystemTime>,
+    /// Depth of the process in the process tree (in the tree mode).
+    depth: Option<u32>,
+    /// Name of the user owning the process (if requested).
+    user_name: Option<String>,
+    /// Name of the group owning the process (if requested).
+    group_name: Option<String>,
+}
+
+/// Result of the `list_processes` action.
+#[cfg(target_os = "windows")]
+struct Item {
+    /// Identifier of the process.
+    pid: u32,
+    /// Mandatory integrity level of the process (if known).
+    integrity_level: Option<ospect::proc::windows::IntegrityLevel>,
+    /// SID of the user owning the process (if known).
+    user_sid: Option<String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListProcesses;
+
+/// Handles invocations of the `list_processes` action.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `list_processes` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let mut items = Vec::new();
+
+    if !args.pids.is_empty() {
+        for pid in args.pids {
+            // Explicitly requested processes that we fail to inspect are
+            // reported as errors, unless they have simply exited.
+            match item(pid).map_err(crate::session::Error::action)? {
+                Some(item) => items.push(item),
+                None => log::warn!("process {pid} has exited"),
+            }
+        }
+    } else {
+        let pids = ospect::proc::ids()
+            .map_err(crate::session::Error::action)?;
+
+        for pid in pids {
+            let pid = match pid {
+                Ok(pid) => pid,
+                Err(error) => {
+                    log::warn!("failed to obtain process identifier: {error}");
+                    continue;
+                }
+            };
+
+            match item(pid) {
+                Ok(Some(item)) => items.push(item),
+                Ok(None) => continue,
+                Err(error) => {
+                    log::warn!("failed to inspect process {pid}: {error}");
+                    continue;
+                }
+            }
+        }
+    }
+
+    // Processes are usually owned by just a handful of users, so the cache
+    // saves us from hitting the user database for every one of them.
+    if args.resolve_owner_names {
+        let mut owner_names = crate::owner::OwnerNames::new();
+
+        for item in &mut items {
+            item.user_name = item.status.uid
+                .map(|uid| String::from(owner_names.user(uid)));
+            item.group_name = item.status.gid
+                .map(|gid| String::from(owner_names.group(gid)));
+        }
+    }
+
+    // Pages of processes are continued after the identifier of the last sent
+    // process, so the order has to be the same for all of them.
+    if args.tree {
+        items = into_tree_order(items);
+    } else {
+        items.sort_by_key(|item| item.pid);
+    }
+
+    session.set_total_count_hint(items.len() as u64);
+
+    crate::session::reply_paged(session, |cursor| {
+        use crate::session::{InvalidPageCursorError, Paged};
+
+        let items = match cursor {
+            Some(cursor) => {
+                let pid = <[u8; 4]>::try_from(cursor)
+                    .map(u32::from_be_bytes)
+                    .map_err(|_| InvalidPageCursorError)?;
+
+                if args.tree {
+                    // In the tree order we have no other choice but to look for
+                    // the exact process. If it exited, we cannot continue.
+                    match items.iter().position(|item| item.pid == pid) {
+                        Some(index) => items.split_off(index + 1),
+                        None => return Err(InvalidPageCursorError),
+                    }
+                } else {
+                    items.into_iter()
+                        .filter(|item| item.pid > pid)
+                        .collect()
+                }
+            }
+            None => items,
+        };
+
+        Ok(items.into_iter().map(|item| Paged {
+            cursor: item.pid.to_be_bytes().to_vec(),
+            item,
+        }))
+    })
+}
+
+/// Handles invocations of the `list_processes` action.
+#[cfg(target_os = "windows")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if args.tree {
+        log::warn!("tree ordering of processes is not supported on Windows");
+    }
+    if args.resolve_owner_names {
+        log::warn!("resolution of owner names is not supported on Windows");
+    }
+
+    let pids = if !args.pids.is_empty() {
+        args.pids
+    } else {
+        ospect::proc::ids()
+            .map_err(crate::session::Error::action)?
+            .filter_map(|pid| match pid {
+                Ok(pid) => Some(pid),
+                Err(error) => {
+                    log::warn!("failed to obtain process identifier: {error}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    session.set_total_count_hint(pids.len() as u64);
+
+    for pid in pids {
+        session.reply(item(pid))?;
+    }
+
+    Ok(())
+}
+
+/// Collects information about the process with the specified pid.
+///
+/// Processes that cannot be opened (e.g. protected or system ones queried by
+/// a non-elevated agent) are reported with the fields we failed to obtain left
+/// unset.
+#[cfg(target_os = "windows")]
+fn item(pid: u32) -> Item {
+    let integrity_level = match ospect::proc::windows::integrity_level(pid) {
+        Ok(integrity_level) => Some(integrity_level),
+        Err(error) => {
+            log::warn!("failed to get integrity level of process {pid}: {error}");
+            None
+        }
+    };
+
+    let user_sid = match ospect::proc::windows::user_sid(pid) {
+        Ok(user_sid) => Some(user_sid),
+        Err(error) => {
+            log::warn!("failed to get user of process {pid}: {error}");
+            None
+        }
+    };
+
+    Item {
+        pid,
+        integrity_level,
+        user_sid,
+    }
+}
+
+/// Collects information about the process with the specified pid.
+///
+/// If the process has exited, `None` is returned instead of an error.
+#[cfg(target_os = "linux")]
+fn item(pid: u32) -> std::io::Result<Option<Item>> {
+    let status = match ospect::proc::linux::status(pid)? {
+        Some(status) => status,
+        None => return Ok(None),
+    };
+
+    // The start time is needed only to disambiguate reused pids, so failing to
+    // get it is not a reason not to report the process at all.
+    let start_time = match ospect::proc::linux::start_time(pid) {
+        Ok(start_time) => start_time,
+        Err(error) => {
+            log::warn!("failed to get start time of process {pid}: {error}");
+            None
+        }
+    };
+
+    Ok(Some(Item {
+        pid,
+        status,
+        start_time,
+        depth: None,
+        user_name: None,
+        group_name: None,
+    }))
+}
+
+/// Orders the given processes as a tree and annotates them with their depth.
+///
+/// Processes are ordered depth-first, so every process is preceded by its
+/// parent (and its siblings' subtrees). A process is considered a child of the
+/// process with its parent's pid only if that process was started before it:
+/// otherwise the parent has exited and its pid was reused in the meantime and
+/// the process is treated as a root (like processes whose parents are not in
+/// the list at all).
+#[cfg(target_os = "linux")]
+fn into_tree_order(items: Vec<Item>) -> Vec<Item> {
+    use std::collections::HashMap;
+
+    let index_by_pid = items.iter()
+        .enumerate()
+        .map(|(index, item)| (item.pid, index))
+        .collect::<HashMap<_, _>>();
+
+    let parent_index = |item: &Item| -> Option<usize> {
+        let parent_index = *index_by_pid.get(&item.status.ppid)?;
+        let parent = &items[parent_index];
+
+        match (parent.start_time, item.start_time) {
+            (Some(parent_start_time), Some(start_time)) if parent_start_time > start_time => None,
+            _ => Some(parent_index),
+        }
+    };
+
+    let mut roots = Vec::new();
+    let mut children = vec![Vec::new(); items.len()];
+    for (index, item) in items.iter().enumerate() {
+        match parent_index(item) {
+            Some(parent_index) if parent_index != index => children[parent_index].push(index),
+            _ => roots.push(index),
+        }
+    }
+
+    let mut order = Vec::with_capacity(items.len());
+    let mut visited = vec![false; items.len()];
+
+    // Processes in a parent cycle (which should not happen but the data comes
+    // from a racy snapshot) are not reachable from any root, so we visit them
+    // afterwards as if they were roots.
+    for root in roots.into_iter().chain(0..items.len()) {
+        let mut stack = vec![(root, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            if visited[index] {
+                continue;
+            }
+            visited[index] = true;
+            order.push((index, depth));
+
+            // We push children in reverse, so that they are popped (and thus
+            // reported) in the order of the original list.
+            for child in children[index].iter().rev() {
+                stack.push((*child, depth + 1));
+            }
+        }
+    }
+
+    let mut items = items.into_iter().map(Some).collect::<Vec<_>>();
+    order.into_iter()
+        .map(|(index, depth)| {
+            let mut item = items[index].take()
+                .expect("process visited twice");
+            item.depth = Some(depth);
+            item
+        })
+        .collect()
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_processes::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        Ok(Args {
+            pids: proto.take_pids(),
+            tree: proto.tree(),
+            resolve_owner_names: proto.resolve_owner_names(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_processes::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use ospect::proc::linux::capability_names;
+
+        let capabilities = self.status.capabilities;
+
+        let mut proto = Self::Proto::default();
+        proto.set_pid(self.pid);
+        proto.set_name(self.status.name.to_string_lossy().into_owned());
+        proto.set_ppid(self.status.ppid);
+        if let Some(start_time) = self.start_time {
+            proto.set_start_time(rrg_proto::into_timestamp(start_time));
+        }
+        if let Some(depth) = self.depth {
+            proto.set_depth(depth);
+        }
+        if let Some(uid) = self.status.uid {
+            proto.set_unix_uid(uid);
+        }
+        if let Some(gid) = self.status.gid {
+            proto.set_unix_gid(gid);
+        }
+        if let Some(user_name) = self.user_name {
+            proto.set_unix_user_name(user_name);
+        }
+        if let Some(group_name) = self.group_name {
+            proto.set_unix_group_name(group_name);
+        }
+
+        let proto_capabilities = proto.mut_linux_capabilities();
+        proto_capabilities.set_inheritable(capability_names(capabilities.inheritable));
+        proto_capabilities.set_permitted(capability_names(capabilities.permitted));
+        proto_capabilities.set_effective(capability_names(capabilities.effective));
+
+        proto
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_processes::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use ospect::proc::windows::IntegrityLevel::*;
+        use rrg_proto::list_processes::WindowsIntegrityLevel;
+
+        let mut proto = Self::Proto::default();
+        proto.set_pid(self.pid);
+        if let Some(integrity_level) = self.integrity_level {
+            proto.set_windows_integrity_level(match integrity_level {
+                Untrusted => WindowsIntegrityLevel::UNTRUSTED,
+                Low => WindowsIntegrityLevel::LOW,
+                Medium => WindowsIntegrityLevel::MEDIUM,
+                High => WindowsIntegrityLevel::HIGH,
+                System => WindowsIntegrityLevel::SYSTEM,
+                Protected => WindowsIntegrityLevel::PROTECTED,
+            });
+        }
+        if let Some(user_sid) = self.user_sid {
+            proto.set_windows_user_sid(user_sid);
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "windows")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_self() {
+        use ospect::proc::windows::IntegrityLevel;
+
+        let args = Args {
+            pids: vec![std::process::id()],
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.total_count_hint(), Some(1));
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.pid, std::process::id());
+
+        // Tests are run either by a regular user, an elevated administrator or
+        // as a service.
+        let integrity_level = item.integrity_level.unwrap();
+        assert!(integrity_level >= IntegrityLevel::Medium);
+        assert!(integrity_level <= IntegrityLevel::System);
+
+        assert!(item.user_sid.as_ref().unwrap().starts_with("S-1-"));
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_self() {
+        let args = Args {
+            pids: vec![std::process::id()],
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.total_count_hint(), Some(1));
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.pid, std::process::id());
+        assert_eq!(item.status.ppid, std::os::unix::process::parent_id());
+        assert!(!item.status.name.is_empty());
+        assert!(item.start_time.is_some());
+        assert!(item.depth.is_none());
+
+        // The kernel guarantees that effective capabilities are a subset of
+        // the permitted ones.
+        let capabilities = item.status.capabilities;
+        assert_eq!(capabilities.effective & !capabilities.permitted, 0);
+    }
+
+    #[test]
+    fn handle_self_owner_names() {
+        let args = Args {
+            pids: vec![std::process::id()],
+            tree: false,
+            resolve_owner_names: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let output = std::process::Command::new("id")
+            .arg("-un")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let user_name = String::from_utf8(output.stdout)
+            .unwrap();
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.user_name.as_deref(), Some(user_name.trim_end()));
+        assert!(item.group_name.is_some());
+    }
+
+    #[test]
+    fn handle_exited() {
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        child.wait()
+            .unwrap();
+
+        let args = Args {
+            pids: vec![pid],
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_all() {
+        let args = Args {
+            pids: vec![],
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert! {
+            session.replies::<Item>()
+                .any(|item| item.pid == std::process::id())
+        };
+    }
+
+    #[test]
+    fn handle_pages() {
+        let mut pids = vec![std::process::id(), std::os::unix::process::parent_id()];
+        pids.sort();
+
+        let args = Args {
+            pids: pids.clone(),
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        session.set_page(Some(1), None);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).pid, pids[0]);
+
+        let cursor = session.next_page_cursor()
+            .unwrap()
+            .to_vec();
+
+        let args = Args {
+            pids: pids.clone(),
+            tree: false,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        session.set_page(Some(1), Some(cursor));
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).pid, pids[1]);
+        assert_eq!(session.next_page_cursor(), None);
+    }
+
+    #[test]
+    fn handle_tree_child() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+
+        let args = Args {
+            pids: vec![],
+            tree: true,
+            resolve_owner_names: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        let result = handle(&mut session, args);
+
+        child.kill()
+            .unwrap();
+        child.wait()
+            .unwrap();
+
+        assert!(result.is_ok());
+
+        let items = session.replies::<Item>()
+            .collect::<Vec<_>>();
+
+        let parent_index = items.iter()
+            .position(|item| item.pid == std::process::id())
+            .unwrap();
+        let child_index = items.iter()
+            .position(|item| item.pid == child.id())
+            .unwrap();
+
+        let parent = items[parent_index];
+        let child = items[child_index];
+
+        assert_eq!(child.status.ppid, parent.pid);
+        assert!(parent.start_time <= child.start_time);
+        assert!(parent_index < child_index);
+        assert_eq!(child.depth, Some(parent.depth.unwrap() + 1));
+    }
+
+    #[test]
+    fn into_tree_order_pid_reuse() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        fn item(pid: u32, ppid: u32, start_time: u64) -> Item {
+            Item {
+                pid,
+                status: ospect::proc::linux::Status {
+                    name: std::ffi::OsString::from(format!("proc{pid}")),
+                    ppid,
+                    capabilities: Default::default(),
+                    uid: None,
+                    gid: None,
+                },
+                start_time: Some(UNIX_EPOCH + Duration::from_secs(start_time)),
+                depth: None,
+                user_name: None,
+                group_name: None,
+            }
+        }
+
+        let items = into_tree_order(vec![
+            item(30, 10, 300),
+            item(1, 0, 100),
+            // The original parent of this process has exited and its pid was
+            // reused by a process started later.
+            item(20, 40, 200),
+            item(10, 1, 150),
+            item(40, 1, 400),
+        ]);
+
+        let order = items.iter()
+            .map(|item| (item.pid, item.depth.unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, vec![
+            (1, 0),
+            (10, 1),
+            (30, 2),
+            (40, 1),
+            (20, 0),
+        ]);
+    }
+}
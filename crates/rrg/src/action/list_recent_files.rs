@@ -0,0 +1,332 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of files recently used by users of desktop environments.
+//!
+//! Desktop environments following the freedesktop.org specifications keep
+//! track of recently used files in the [XBEL][1] format in `recently-used.xbel`
+//! file in the data directory of every user.
+//!
+//! [1]: https://www.freedesktop.org/wiki/Specifications/desktop-bookmark-spec/
+
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+
+/// Paths (relative to the home directory) of known recently used files lists.
+#[cfg(target_os = "linux")]
+const RECENT_FILES: &[&str] = &[
+    ".local/share/recently-used.xbel",
+    // Location used by older versions of GTK.
+    ".recently-used.xbel",
+];
+
+/// Result of the `list_recent_files` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Name of the user that recently used the file.
+    user: std::ffi::OsString,
+    /// Path to the list the entry comes from.
+    path: PathBuf,
+    /// Entry of the recently used files list.
+    bookmark: Bookmark,
+}
+
+/// Entry of the recently used files list.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct Bookmark {
+    /// URI of the recently used file.
+    uri: String,
+    /// Time at which the file was added to the list.
+    added: Option<std::time::SystemTime>,
+    /// Time at which the entry was last modified.
+    modified: Option<std::time::SystemTime>,
+    /// Time at which the file was last visited.
+    visited: Option<std::time::SystemTime>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListRecentFiles;
+
+/// Handles invocations of the `list_recent_files` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let users = ospect::os::users()
+        .map_err(crate::session::Error::action)?;
+
+    for user in users {
+        handle_user(session, &user)?;
+    }
+
+    Ok(())
+}
+
+/// Handles invocations of the `list_recent_files` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Collects recently used files of the given `user` and replies with them.
+#[cfg(target_os = "linux")]
+fn handle_user<S>(session: &mut S, user: &ospect::os::User) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for recent_files in RECENT_FILES {
+        let path = user.home.join(recent_files);
+
+        let bookmarks = match read_xbel(&path) {
+            Ok(bookmarks) => bookmarks,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(error) => {
+                log::warn! {
+                    "failed to read recently used files '{}': {}",
+                    path.display(), error,
+                };
+                continue;
+            }
+        };
+
+        for bookmark in bookmarks {
+            session.reply(Item {
+                user: user.name.clone(),
+                path: path.clone(),
+                bookmark,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads bookmarks from the XBEL file at the given `path`.
+#[cfg(target_os = "linux")]
+fn read_xbel(path: &Path) -> std::io::Result<Vec<Bookmark>> {
+    let contents = std::fs::read(path)?;
+    Ok(parse_xbel(&String::from_utf8_lossy(&contents)))
+}
+
+/// Parses bookmarks from the given XBEL document.
+///
+/// We are interested only in attributes of the `bookmark` elements, so instead
+/// of parsing the whole document we just look for their opening tags. Entries
+/// without the `href` attribute are skipped and timestamps that are missing or
+/// malformed are left unset.
+#[cfg(target_os = "linux")]
+fn parse_xbel(contents: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+
+    let mut rest = contents;
+    while let Some(start) = rest.find("<bookmark") {
+        rest = &rest[start + "<bookmark".len()..];
+
+        // This is some other element with a `bookmark` prefix (likely from the
+        // `bookmark` namespace like `<bookmark:applications>`).
+        if !rest.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let attrs = parse_attrs(&rest[..end]);
+        rest = &rest[end..];
+
+        let attr = |name: &str| -> Option<&str> {
+            attrs.iter()
+                .find(|(attr_name, _)| *attr_name == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let time = |name: &str| -> Option<std::time::SystemTime> {
+            humantime::parse_rfc3339_weak(attr(name)?).ok()
+        };
+
+        let uri = match attr("href") {
+            Some(uri) => String::from(uri),
+            None => continue,
+        };
+
+        bookmarks.push(Bookmark {
+            uri,
+            added: time("added"),
+            modified: time("modified"),
+            visited: time("visited"),
+        });
+    }
+
+    bookmarks
+}
+
+/// Parses attributes of an XML tag (the part after the element name).
+#[cfg(target_os = "linux")]
+fn parse_attrs(mut tag: &str) -> Vec<(&str, String)> {
+    let mut attrs = Vec::new();
+
+    loop {
+        tag = tag.trim_start();
+
+        let (name, rest) = match tag.split_once('=') {
+            Some((name, rest)) => (name.trim(), rest.trim_start()),
+            None => break,
+        };
+
+        let quote = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => break,
+        };
+        let (value, rest) = match rest[1..].split_once(quote) {
+            Some((value, rest)) => (value, rest),
+            None => break,
+        };
+
+        attrs.push((name, unescape(value)));
+        tag = rest;
+    }
+
+    attrs
+}
+
+/// Replaces predefined XML entities with the characters they denote.
+#[cfg(target_os = "linux")]
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_recent_files::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_user(self.user.to_string_lossy().into_owned());
+        proto.set_path(self.path.into());
+        proto.set_uri(self.bookmark.uri);
+        if let Some(added) = self.bookmark.added {
+            proto.set_added_time(rrg_proto::into_timestamp(added));
+        }
+        if let Some(modified) = self.bookmark.modified {
+            proto.set_modified_time(rrg_proto::into_timestamp(modified));
+        }
+        if let Some(visited) = self.bookmark.visited {
+            proto.set_visited_time(rrg_proto::into_timestamp(visited));
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    const SAMPLE_XBEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0"
+      xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks"
+      xmlns:mime="http://www.freedesktop.org/standards/shared-mime-info"
+>
+  <bookmark href="file:///home/foo/notes.txt" added="2024-01-02T10:00:00.123456Z" modified="2024-01-03T11:00:00Z" visited="2024-01-04T12:00:00Z">
+    <info>
+      <metadata owner="http://freedesktop.org">
+        <mime:mime-type type="text/plain"/>
+        <bookmark:applications>
+          <bookmark:application name="gedit" exec="&apos;gedit %u&apos;" modified="2024-01-03T11:00:00Z" count="2"/>
+        </bookmark:applications>
+      </metadata>
+    </info>
+  </bookmark>
+  <bookmark href="file:///tmp/a%20b&amp;c.pdf" added="bogus">
+  </bookmark>
+</xbel>
+"#;
+
+    #[test]
+    fn parse_xbel_sample() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let bookmarks = parse_xbel(SAMPLE_XBEL);
+        assert_eq!(bookmarks.len(), 2);
+
+        assert_eq!(bookmarks[0].uri, "file:///home/foo/notes.txt");
+        assert_eq! {
+            bookmarks[0].added,
+            Some(UNIX_EPOCH + Duration::from_secs(1704189600) + Duration::from_micros(123456))
+        };
+        assert_eq! {
+            bookmarks[0].modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1704279600))
+        };
+        assert_eq! {
+            bookmarks[0].visited,
+            Some(UNIX_EPOCH + Duration::from_secs(1704369600))
+        };
+
+        assert_eq!(bookmarks[1].uri, "file:///tmp/a%20b&c.pdf");
+        assert_eq!(bookmarks[1].added, None);
+        assert_eq!(bookmarks[1].modified, None);
+        assert_eq!(bookmarks[1].visited, None);
+    }
+
+    #[test]
+    fn handle_user_recently_used() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir_all(tempdir.path().join(".local/share"))
+            .unwrap();
+        std::fs::write(tempdir.path().join(".local/share/recently-used.xbel"), SAMPLE_XBEL)
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.user, "foo");
+        assert_eq!(item.path, tempdir.path().join(".local/share/recently-used.xbel"));
+        assert_eq!(item.bookmark.uri, "file:///home/foo/notes.txt");
+    }
+
+    #[test]
+    fn handle_user_missing() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let user = ospect::os::User {
+            name: "foo".into(),
+            home: tempdir.path().to_path_buf(),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        handle_user(&mut session, &user)
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 0);
+    }
+}
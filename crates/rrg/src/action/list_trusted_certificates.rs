@@ -0,0 +1,630 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Listing of certificates of the system trust store.
+//!
+//! Certificates of trusted certificate authorities are collected from where the
+//! platform keeps them: PEM (or DER) files in `/etc/ssl/certs` (and similar
+//! directories) on Linux, the system keychains on macOS and certificate stores
+//! in the registry on Windows. Unexpected authorities in the trust store may
+//! indicate that encrypted traffic of the endpoint is intercepted.
+//!
+//! Entries that cannot be parsed are skipped with a warning. Certificates that
+//! are stored in multiple places (e.g. both as a separate file and as a part of
+//! a bundle) are reported only once.
+
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+
+/// Default locations of the trust store on Linux.
+#[cfg(target_os = "linux")]
+const LINUX_PATHS: &[&str] = &[
+    "/etc/ssl/certs",
+    "/etc/pki/ca-trust/extracted/pem",
+];
+
+/// Limit on the size of files inspected for certificates.
+#[cfg(target_os = "linux")]
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024; // 16 MiB.
+
+/// Keychains with trusted certificates on macOS.
+#[cfg(target_os = "macos")]
+const MACOS_KEYCHAINS: &[&str] = &[
+    "/Library/Keychains/System.keychain",
+    "/System/Library/Keychains/SystemRootCertificates.keychain",
+];
+
+/// Registry keys of certificate stores with trusted certificates on Windows.
+#[cfg(target_family = "windows")]
+const WINDOWS_STORES: &[(winreg::PredefinedKey, &str)] = &[
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Microsoft\\SystemCertificates\\ROOT"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Microsoft\\SystemCertificates\\AuthRoot"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Policies\\Microsoft\\SystemCertificates\\Root"),
+    (winreg::PredefinedKey::LocalMachine, "SOFTWARE\\Microsoft\\EnterpriseCertificates\\Root"),
+    (winreg::PredefinedKey::CurrentUser, "SOFTWARE\\Microsoft\\SystemCertificates\\Root"),
+];
+
+/// Arguments of the `list_trusted_certificates` action.
+pub struct Args {
+    /// Paths to files or directories with certificates to list (if not default).
+    #[cfg(target_os = "linux")]
+    paths: Vec<PathBuf>,
+}
+
+/// Result of the `list_trusted_certificates` action.
+struct Item {
+    /// Location of the trust store the certificate was found in.
+    source: String,
+    /// Distinguished name of the subject of the certificate.
+    subject: String,
+    /// Distinguished name of the issuer of the certificate.
+    issuer: String,
+    /// Serial number of the certificate.
+    serial: String,
+    /// Time from which the certificate is valid.
+    not_before: Option<std::time::SystemTime>,
+    /// Time until which the certificate is valid.
+    not_after: Option<std::time::SystemTime>,
+    /// SHA-256 fingerprint of the DER encoding of the certificate.
+    sha256: [u8; 32],
+}
+
+/// DER-encoded certificate found in the trust store.
+struct Entry {
+    /// Location of the trust store the certificate was found in.
+    source: String,
+    /// DER encoding of the certificate.
+    der: Vec<u8>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListTrustedCertificates;
+
+/// Handles invocations of the `list_trusted_certificates` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use sha2::Digest as _;
+
+    let mut seen = std::collections::HashSet::new();
+
+    for entry in entries(&args) {
+        let certificate = match x509_parser::parse_x509_certificate(&entry.der) {
+            Ok((_, certificate)) => certificate,
+            Err(error) => {
+                log::warn!("skipping malformed certificate from '{}': {error}", entry.source);
+                continue;
+            }
+        };
+
+        let sha256: [u8; 32] = sha2::Sha256::digest(&entry.der).into();
+        if !seen.insert(sha256) {
+            continue;
+        }
+
+        let validity = certificate.validity();
+
+        session.reply(Item {
+            source: entry.source,
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            serial: certificate.raw_serial_as_string(),
+            not_before: system_time(validity.not_before.timestamp()),
+            not_after: system_time(validity.not_after.timestamp()),
+            sha256,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Collects certificates of the trust store specified by the arguments.
+#[cfg(target_os = "linux")]
+fn entries(args: &Args) -> Vec<Entry> {
+    let default = args.paths.is_empty();
+    let paths = if default {
+        LINUX_PATHS.iter().map(PathBuf::from).collect()
+    } else {
+        args.paths.clone()
+    };
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            // Not all distributions use all the default locations, so we do
+            // not complain about the ones that do not exist.
+            Err(error) if default && error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => {
+                log::warn!("failed to inspect '{}': {error}", path.display());
+                continue;
+            }
+        };
+
+        if !metadata.is_dir() {
+            entries.extend(file_entries(&path));
+            continue;
+        }
+
+        let dir_entries = match std::fs::read_dir(&path) {
+            Ok(dir_entries) => dir_entries,
+            Err(error) => {
+                log::warn!("failed to list '{}': {error}", path.display());
+                continue;
+            }
+        };
+
+        let mut file_paths = dir_entries
+            .filter_map(|dir_entry| match dir_entry {
+                Ok(dir_entry) => Some(dir_entry.path()),
+                Err(error) => {
+                    log::warn!("failed to list entry of '{}': {error}", path.display());
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        // Directories often contain both certificates and symlinks to them
+        // (named after hashes of their subjects), so we go in a deterministic
+        // order to report certificates always under the same path.
+        file_paths.sort();
+
+        for file_path in file_paths {
+            // We follow symlinks, as they are very common in trust stores.
+            match std::fs::metadata(&file_path) {
+                Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_FILE_SIZE => (),
+                Ok(_) => continue,
+                Err(error) => {
+                    log::warn!("failed to inspect '{}': {error}", file_path.display());
+                    continue;
+                }
+            }
+
+            entries.extend(file_entries(&file_path));
+        }
+    }
+
+    entries
+}
+
+/// Collects certificates of the trust store specified by the arguments.
+#[cfg(target_os = "macos")]
+fn entries(_: &Args) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for keychain in MACOS_KEYCHAINS {
+        // Keychains are stored in a format that we do not parse ourselves, so
+        // we ask the system to export all their certificates in PEM.
+        let output = std::process::Command::new("security")
+            .args(["find-certificate", "-a", "-p"])
+            .arg(keychain)
+            .output();
+
+        let output = match output {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                log::warn!("failed to export certificates of '{keychain}': {}", {
+                    String::from_utf8_lossy(&output.stderr)
+                });
+                continue;
+            }
+            Err(error) => {
+                log::warn!("failed to export certificates of '{keychain}': {error}");
+                continue;
+            }
+        };
+
+        entries.extend(pem_certificates(keychain, &output.stdout).into_iter().map(|der| Entry {
+            source: String::from(*keychain),
+            der,
+        }));
+    }
+
+    entries
+}
+
+/// Collects certificates of the trust store specified by the arguments.
+#[cfg(target_family = "windows")]
+fn entries(_: &Args) -> Vec<Entry> {
+    use std::ffi::OsStr;
+
+    let mut entries = Vec::new();
+    for (root, store) in WINDOWS_STORES {
+        let source = format!("{}\\{store}", root_name(*root));
+
+        let certificates = match root.open(OsStr::new(&format!("{store}\\Certificates"))) {
+            Ok(certificates) => certificates,
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("failed to open certificate store '{source}': {error}");
+                }
+                continue;
+            }
+        };
+
+        let info = match certificates.info() {
+            Ok(info) => info,
+            Err(error) => {
+                log::warn!("failed to inspect certificate store '{source}': {error}");
+                continue;
+            }
+        };
+
+        for thumbprint in info.subkeys() {
+            let thumbprint = match thumbprint {
+                Ok(thumbprint) => thumbprint,
+                Err(error) => {
+                    log::warn!("failed to list certificate of '{source}': {error}");
+                    continue;
+                }
+            };
+
+            let blob = certificates.open(&thumbprint)
+                .and_then(|certificate| certificate.value_data(OsStr::new("Blob")));
+
+            let blob = match blob {
+                Ok(winreg::ValueData::Bytes(blob)) => blob,
+                Ok(_) => {
+                    log::warn!("non-binary blob of certificate {thumbprint:?} in '{source}'");
+                    continue;
+                }
+                Err(error) => {
+                    log::warn!("failed to read certificate {thumbprint:?} in '{source}': {error}");
+                    continue;
+                }
+            };
+
+            match blob_certificate(&blob) {
+                Some(der) => entries.push(Entry {
+                    source: source.clone(),
+                    der: der.to_vec(),
+                }),
+                None => {
+                    log::warn!("no certificate in blob {thumbprint:?} in '{source}'");
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+/// Collects certificates stored in the file at the given path.
+///
+/// The file can contain any number of PEM-encoded certificates or be a single
+/// DER-encoded certificate.
+#[cfg(target_os = "linux")]
+fn file_entries(path: &Path) -> Vec<Entry> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) => {
+            log::warn!("failed to read '{}': {error}", path.display());
+            return Vec::new();
+        }
+    };
+
+    let source = path.display().to_string();
+
+    let ders = if data.windows(PEM_BEGIN.len()).any(|window| window == PEM_BEGIN) {
+        pem_certificates(&source, &data)
+    } else {
+        vec![data]
+    };
+
+    ders.into_iter()
+        .map(|der| Entry {
+            source: source.clone(),
+            der,
+        })
+        .collect()
+}
+
+/// Beginning of the boundary line of PEM blocks.
+#[cfg(target_os = "linux")]
+const PEM_BEGIN: &[u8] = b"-----BEGIN ";
+
+/// Returns DER encodings of all the PEM-encoded certificates in `data`.
+///
+/// Blocks with other labels (e.g. keys or certificate revocation lists) are
+/// ignored. The source is used only for reporting malformed blocks.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn pem_certificates(source: &str, data: &[u8]) -> Vec<Vec<u8>> {
+    let mut ders = Vec::new();
+    for pem in x509_parser::pem::Pem::iter_from_buffer(data) {
+        let pem = match pem {
+            Ok(pem) => pem,
+            Err(error) => {
+                // We do not know where the malformed block ends, so there is
+                // no reliable way to continue.
+                log::warn!("skipping rest of malformed PEM data in '{source}': {error}");
+                break;
+            }
+        };
+
+        // OpenSSL-specific `TRUSTED CERTIFICATE` blocks are certificates with
+        // additional trust settings appended, which the parser ignores.
+        match pem.label.as_str() {
+            "CERTIFICATE" | "X509 CERTIFICATE" | "TRUSTED CERTIFICATE" => {
+                ders.push(pem.contents);
+            }
+            _ => (),
+        }
+    }
+
+    ders
+}
+
+/// Returns the DER-encoded certificate from a registry blob of a store.
+///
+/// Blobs are sequences of serialized certificate properties, each of which
+/// consists of a 32-bit identifier, a reserved 32-bit value, a 32-bit length
+/// and the data of the property (all in little endian). The certificate itself
+/// is the property with the identifier `CERT_CERT_PROP_ID`.
+#[cfg(target_family = "windows")]
+fn blob_certificate(blob: &[u8]) -> Option<&[u8]> {
+    const CERT_CERT_PROP_ID: u32 = 32;
+
+    let mut rest = blob;
+    while rest.len() >= 12 {
+        let id = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+        let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+
+        let data = rest.get(12..12 + len)?;
+        if id == CERT_CERT_PROP_ID {
+            return Some(data);
+        }
+
+        rest = &rest[12 + len..];
+    }
+
+    None
+}
+
+/// Returns the full name of the given predefined registry key.
+#[cfg(target_family = "windows")]
+fn root_name(root: winreg::PredefinedKey) -> &'static str {
+    match root {
+        winreg::PredefinedKey::LocalMachine => "HKEY_LOCAL_MACHINE",
+        winreg::PredefinedKey::CurrentUser => "HKEY_CURRENT_USER",
+        _ => "HKEY_UNKNOWN",
+    }
+}
+
+/// Converts seconds since the Unix epoch to a system time.
+fn system_time(secs: i64) -> Option<std::time::SystemTime> {
+    let duration = std::time::Duration::from_secs(secs.unsigned_abs());
+
+    if secs >= 0 {
+        std::time::SystemTime::UNIX_EPOCH.checked_add(duration)
+    } else {
+        std::time::SystemTime::UNIX_EPOCH.checked_sub(duration)
+    }
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::list_trusted_certificates::Args;
+
+    #[cfg(target_os = "linux")]
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let paths = proto.take_paths().into_iter()
+            .map(PathBuf::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| ParseArgsError::invalid_field("paths", error))?;
+
+        Ok(Args {
+            paths,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn from_proto(proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        if !proto.paths.is_empty() {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "custom paths are supported only on Linux");
+            return Err(ParseArgsError::unsupported_field("paths", error));
+        }
+
+        Ok(Args {})
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::list_trusted_certificates::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::into_timestamp;
+
+        let mut proto = Self::Proto::default();
+        proto.set_source(self.source);
+        proto.set_subject(self.subject);
+        proto.set_issuer(self.issuer);
+        proto.set_serial(self.serial);
+        if let Some(not_before) = self.not_before {
+            proto.set_not_before(into_timestamp(not_before));
+        }
+        if let Some(not_after) = self.not_after {
+            proto.set_not_after(into_timestamp(not_after));
+        }
+        proto.set_sha256(self.sha256.to_vec());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    // Self-signed CA certificates generated specifically for these tests with:
+    //
+    //     openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 \
+    //         -nodes -keyout key.pem -out cert.pem -days 3650 \
+    //         -subj "/CN=RRG Test CA $N/O=RRG" -set_serial 0x$N$N \
+    //         -addext basicConstraints=critical,CA:TRUE
+    const CA_1_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBjTCCATSgAwIBAgIBETAKBggqhkjOPQQDAjAmMRYwFAYDVQQDDA1SUkcgVGVz
+dCBDQSAxMQwwCgYDVQQKDANSUkcwHhcNMjYxMDE0MTAwNTExWhcNMzYxMDExMTAw
+NTExWjAmMRYwFAYDVQQDDA1SUkcgVGVzdCBDQSAxMQwwCgYDVQQKDANSUkcwWTAT
+BgcqhkjOPQIBBggqhkjOPQMBBwNCAARl2nRpMGX5VllAR6pvIa4as6bfuPnsG8NG
+q48XS1TQ0SW3Y3x+5Ahjl04UXcABe3vxUEhgVjx42D3W9KcN7y5io1MwUTAdBgNV
+HQ4EFgQUoyq6TzTvgTNU/S9vDA8xrEgAfYswHwYDVR0jBBgwFoAUoyq6TzTvgTNU
+/S9vDA8xrEgAfYswDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNHADBEAiBy
+l5foS5WRPoujRmvLXxTm6n9YL24eMX0rEvr0ySGhZQIgEwLuD6+YlXvs8SHfTOF2
+WY+bgcy16oGsQnL6tosnaFs=
+-----END CERTIFICATE-----
+";
+
+    const CA_2_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBjjCCATSgAwIBAgIBIjAKBggqhkjOPQQDAjAmMRYwFAYDVQQDDA1SUkcgVGVz
+dCBDQSAyMQwwCgYDVQQKDANSUkcwHhcNMjYxMDE0MTAwNTExWhcNMzYxMDExMTAw
+NTExWjAmMRYwFAYDVQQDDA1SUkcgVGVzdCBDQSAyMQwwCgYDVQQKDANSUkcwWTAT
+BgcqhkjOPQIBBggqhkjOPQMBBwNCAASLAGPjzk6duYreN1ox4BU+NxrMo3XY2lK/
+q3UiIDtqYgX/2PVQsYlf5AGUykU4JOZmUaD0x2VStok5y707NPZwo1MwUTAdBgNV
+HQ4EFgQUYPXQSGsV7Mmb3bn4eJbRGFF4NW0wHwYDVR0jBBgwFoAUYPXQSGsV7Mmb
+3bn4eJbRGFF4NW0wDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiBA
+MFMfmBKaaJgoElQsI0vRtvskf+OU3/lNUbFbq+4YJAIhAKhH4oq6LpkbUz2B/GOA
+XDYCodNghPvUcIz3JAq2B8Nl
+-----END CERTIFICATE-----
+";
+
+    const CA_1_SHA256: [u8; 32] = [
+        0xA6, 0x80, 0x63, 0x03, 0xF5, 0x01, 0x1C, 0x24,
+        0xC8, 0x54, 0x76, 0xBE, 0x47, 0x71, 0x89, 0x09,
+        0x56, 0x5C, 0xDF, 0x83, 0xE5, 0x15, 0x3E, 0xE5,
+        0xA5, 0x69, 0x8D, 0x76, 0xF5, 0xBC, 0x7E, 0xCE,
+    ];
+
+    const CA_2_SHA256: [u8; 32] = [
+        0x39, 0x35, 0xE9, 0xA6, 0x0D, 0xC1, 0x97, 0x6D,
+        0xEE, 0xAC, 0x66, 0xE9, 0xBA, 0xCF, 0x4E, 0x4C,
+        0x5C, 0x92, 0x01, 0x4A, 0x15, 0x58, 0x41, 0xBA,
+        0xE7, 0x9A, 0xB9, 0x5E, 0xFB, 0x4A, 0x01, 0xFB,
+    ];
+
+    #[test]
+    fn handle_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("ca1.pem"), CA_1_PEM)
+            .unwrap();
+        std::fs::write(tempdir.path().join("ca2.pem"), CA_2_PEM)
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().to_path_buf()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item_1 = session.reply::<Item>(0);
+        assert_eq!(item_1.source, tempdir.path().join("ca1.pem").display().to_string());
+        assert_eq!(item_1.subject, "CN=RRG Test CA 1, O=RRG");
+        assert_eq!(item_1.issuer, "CN=RRG Test CA 1, O=RRG");
+        assert_eq!(item_1.serial, "11");
+        assert_eq!(item_1.not_before, system_time(1791972311));
+        assert_eq!(item_1.not_after, system_time(2107332311));
+        assert_eq!(item_1.sha256, CA_1_SHA256);
+
+        let item_2 = session.reply::<Item>(1);
+        assert_eq!(item_2.source, tempdir.path().join("ca2.pem").display().to_string());
+        assert_eq!(item_2.subject, "CN=RRG Test CA 2, O=RRG");
+        assert_eq!(item_2.serial, "22");
+        assert_eq!(item_2.sha256, CA_2_SHA256);
+    }
+
+    #[test]
+    fn handle_bundle_and_symlink_deduplicated() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("bundle.crt"), format!("{CA_1_PEM}{CA_2_PEM}"))
+            .unwrap();
+        std::fs::write(tempdir.path().join("ca1.pem"), CA_1_PEM)
+            .unwrap();
+        std::os::unix::fs::symlink("ca1.pem", tempdir.path().join("1a2b3c4d.0"))
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().to_path_buf()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let sha256s = session.replies::<Item>()
+            .map(|item| item.sha256)
+            .collect::<Vec<_>>();
+        assert_eq!(sha256s, vec![CA_1_SHA256, CA_2_SHA256]);
+    }
+
+    #[test]
+    fn handle_der_file() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let (_, pem) = x509_parser::pem::parse_x509_pem(CA_1_PEM.as_bytes())
+            .unwrap();
+        std::fs::write(tempdir.path().join("ca1.der"), pem.contents)
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().join("ca1.der")],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).sha256, CA_1_SHA256);
+    }
+
+    #[test]
+    fn handle_malformed_skipped() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("ca1.pem"), CA_1_PEM)
+            .unwrap();
+        std::fs::write(tempdir.path().join("garbage.pem"), "\
+-----BEGIN CERTIFICATE-----
+Zm9vYmFyCg==
+-----END CERTIFICATE-----
+").unwrap();
+        std::fs::write(tempdir.path().join("README"), "not a certificate")
+            .unwrap();
+
+        let args = Args {
+            paths: vec![tempdir.path().to_path_buf()],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+        assert_eq!(session.reply::<Item>(0).sha256, CA_1_SHA256);
+    }
+
+    #[test]
+    fn handle_default() {
+        let args = Args {
+            paths: vec![],
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+    }
+}
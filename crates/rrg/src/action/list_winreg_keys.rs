@@ -23,6 +23,9 @@ struct Item {
     subkey: std::ffi::OsString,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListWinregKeys;
+
 /// Handles invocations of the `list_winreg_keys` action.
 #[cfg(target_family = "windows")]
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
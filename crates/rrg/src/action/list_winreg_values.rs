@@ -23,6 +23,9 @@ struct Item {
     value: winreg::Value,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ListWinregValues;
+
 /// Handles invocations of the `list_winreg_values` action.
 #[cfg(target_family = "windows")]
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
@@ -0,0 +1,670 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Moving files into the quarantine directory of the agent.
+//!
+//! Because removing files from their original location is dangerous, this
+//! action is disabled unless explicitly allowed and it works only if the agent
+//! was configured with a quarantine directory (through the `--quarantine-dir`
+//! flag).
+//!
+//! Every quarantined file is accompanied by a metadata file (recording its
+//! original path, digest and permissions), so that the quarantine can always
+//! be reverted.
+
+use std::path::{Path, PathBuf};
+
+/// Size of the buffer used for reading the quarantined file.
+const BUF_LEN: usize = 64 * 1024;
+
+/// Extension of files with metadata of quarantined files.
+pub(crate) const METADATA_EXTENSION: &str = "meta";
+
+/// Arguments of the `quarantine_file` action.
+pub struct Args {
+    /// Path to the file to quarantine.
+    pub(crate) path: PathBuf,
+}
+
+/// Result of the `quarantine_file` action.
+pub(crate) struct Item {
+    /// Path to the file in the quarantine directory.
+    pub quarantine_path: PathBuf,
+    /// Path to the file with metadata of the quarantined file.
+    pub metadata_path: PathBuf,
+    /// Metadata recorded for the quarantined file.
+    pub metadata: Metadata,
+}
+
+/// Metadata of a quarantined file needed to restore it.
+#[derive(Clone, Debug)]
+pub(crate) struct Metadata {
+    /// Path at which the file was located before it was quarantined.
+    pub original_path: PathBuf,
+    /// SHA-256 digest of the contents of the file.
+    pub sha256: [u8; 32],
+    /// Size of the file (in bytes).
+    pub size: u64,
+    /// Permission bits the file had before it was quarantined.
+    pub mode: Option<u32>,
+    /// Time at which the file was quarantined.
+    pub quarantine_time: std::time::SystemTime,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::QuarantineFile;
+
+/// Handles invocations of the `quarantine_file` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let quarantine_dir = session.args().quarantine_dir.clone()
+        .ok_or_else(|| crate::session::Error::action(MissingQuarantineDirError))?;
+
+    let path = resolve(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    let file_metadata = path.symlink_metadata()
+        .map_err(crate::session::Error::action)?;
+    if !file_metadata.is_file() {
+        return Err(crate::session::Error::action(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is not a regular file", path.display()),
+        )));
+    }
+
+    create_quarantine_dir(&quarantine_dir)
+        .map_err(crate::session::Error::action)?;
+
+    let (sha256, size) = hash(session, &path)?;
+
+    #[cfg(target_family = "unix")]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt as _;
+        Some(file_metadata.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(target_family = "unix"))]
+    let mode = None;
+
+    let metadata = Metadata {
+        original_path: path.clone(),
+        sha256,
+        size,
+        mode,
+        quarantine_time: std::time::SystemTime::now(),
+    };
+
+    let name = quarantine_name(&metadata);
+    let quarantine_path = quarantine_dir.join(&name);
+    let metadata_path = quarantine_dir.join(format!("{name}.{METADATA_EXTENSION}"));
+
+    // Metadata is written first: if we fail to write it, the file was not yet
+    // touched and if we fail to move the file, the metadata is removed.
+    write_metadata(&metadata_path, &metadata)
+        .map_err(crate::session::Error::action)?;
+
+    if let Err(error) = move_file(&path, &quarantine_path, &sha256, false) {
+        if let Err(error) = std::fs::remove_file(&metadata_path) {
+            log::error! {
+                "failed to remove quarantine metadata '{}': {error}",
+                metadata_path.display(),
+            };
+        }
+        return Err(crate::session::Error::action(error));
+    }
+
+    restrict_permissions(&quarantine_path)
+        .map_err(crate::session::Error::action)?;
+
+    log::info! {
+        "quarantined '{}' as '{}'",
+        path.display(),
+        quarantine_path.display(),
+    };
+
+    session.reply(Item {
+        quarantine_path,
+        metadata_path,
+        metadata,
+    })?;
+
+    Ok(())
+}
+
+/// Resolves the path of the file to quarantine.
+///
+/// Only the parent of the path is canonicalized, so that the recorded original
+/// path is reliable but the file itself (if it is a symlink) is not followed.
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is not a path to a file", path.display()),
+        ));
+    };
+
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+
+    Ok(parent.canonicalize()?.join(name))
+}
+
+/// Creates the quarantine directory (if it does not exist yet).
+fn create_quarantine_dir(path: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::DirBuilderExt as _;
+        builder.mode(0o700);
+    }
+
+    builder.create(path)
+}
+
+/// Computes the SHA-256 digest and the size of the file at the given path.
+pub(crate) fn hash<S>(session: &mut S, path: &Path) -> crate::session::Result<([u8; 32], u64)>
+where
+    S: crate::session::Session,
+{
+    use std::io::Read as _;
+
+    use sha2::Digest as _;
+
+    let mut file = crate::fs::open_file(path)
+        .map_err(crate::session::Error::action)?;
+
+    let mut sha256 = sha2::Sha256::new();
+    let mut size = 0;
+
+    let mut buf = vec![0; BUF_LEN];
+    loop {
+        session.check_deadline()?;
+
+        let len_read = match file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(crate::session::Error::action(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+        session.throttle(len_read as u64)?;
+
+        sha256.update(&buf[..len_read]);
+        size += len_read as u64;
+    }
+
+    Ok((sha256.finalize().into(), size))
+}
+
+/// Returns a name under which the file is stored in the quarantine directory.
+fn quarantine_name(metadata: &Metadata) -> String {
+    let time = metadata.quarantine_time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let sha256 = metadata.sha256[..8].iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    format!("{time}-{sha256}")
+}
+
+/// Writes the metadata of a quarantined file to the given path.
+fn write_metadata(path: &Path, metadata: &Metadata) -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    use protobuf::Message as _;
+
+    let bytes = metadata.clone().into_proto().write_to_bytes()
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+
+    let result = file.write_all(&bytes)
+        .and_then(|()| file.sync_all());
+    if let Err(error) = result {
+        drop(file);
+        let _ = std::fs::remove_file(path);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Moves the file from `src` to `dst`.
+///
+/// If `replace` is not set and `dst` already exists, the move fails. If the
+/// paths are on different filesystems, the file is copied and then removed
+/// (see [`move_by_copy`]).
+pub(crate) fn move_file(
+    src: &Path,
+    dst: &Path,
+    sha256: &[u8; 32],
+    replace: bool,
+) -> std::io::Result<()> {
+    // Renaming silently replaces the target, so we want to be sure it does not
+    // exist if we are not supposed to replace it.
+    if !replace && dst.symlink_metadata().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", dst.display()),
+        ));
+    }
+
+    match std::fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::CrossesDevices => {
+            move_by_copy(src, dst, sha256)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Moves the file from `src` to `dst` by copying it and removing the original.
+///
+/// The file is copied next to `dst` first and renamed only once the copy is
+/// verified against the expected digest (as the file could have changed since
+/// it was hashed). On any failure the (partial) copy is removed and the
+/// original file is left intact.
+fn move_by_copy(src: &Path, dst: &Path, sha256: &[u8; 32]) -> std::io::Result<()> {
+    let mut partial = dst.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    if let Err(error) = copy_verified(src, &partial, sha256) {
+        remove_partial(&partial);
+        return Err(error);
+    }
+    if let Err(error) = std::fs::rename(&partial, dst) {
+        remove_partial(&partial);
+        return Err(error);
+    }
+    if let Err(error) = std::fs::remove_file(src) {
+        remove_partial(dst);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Removes the partial copy of a file (if it exists).
+fn remove_partial(path: &Path) {
+    if let Err(error) = std::fs::remove_file(path) {
+        if error.kind() != std::io::ErrorKind::NotFound {
+            log::error!("failed to remove partial copy '{}': {error}", path.display());
+        }
+    }
+}
+
+/// Copies the file from `src` to a new file at `dst`, verifying its digest.
+fn copy_verified(src: &Path, dst: &Path, sha256: &[u8; 32]) -> std::io::Result<()> {
+    use std::io::{Read as _, Write as _};
+
+    use sha2::Digest as _;
+
+    let mut src_file = crate::fs::open_file(src)?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+
+    let mut dst_file = options.open(dst)?;
+
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = vec![0; BUF_LEN];
+    loop {
+        let len_read = match src_file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..len_read]);
+        dst_file.write_all(&buf[..len_read])?;
+    }
+
+    if <[u8; 32]>::from(hasher.finalize()) != *sha256 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("'{}' changed while being quarantined", src.display()),
+        ));
+    }
+
+    dst_file.sync_all()
+}
+
+/// Makes the quarantined file read-only (and inaccessible to others).
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_family = "unix")]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt as _;
+        std::fs::Permissions::from_mode(0o400)
+    };
+
+    #[cfg(not(target_family = "unix"))]
+    let permissions = {
+        let mut permissions = path.metadata()?.permissions();
+        permissions.set_readonly(true);
+        permissions
+    };
+
+    std::fs::set_permissions(path, permissions)
+}
+
+/// An error type for when the agent was not configured with quarantine directory.
+#[derive(Debug)]
+struct MissingQuarantineDirError;
+
+impl std::fmt::Display for MissingQuarantineDirError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "quarantine directory not configured")
+    }
+}
+
+impl std::error::Error for MissingQuarantineDirError {
+}
+
+/// Reads the metadata of a quarantined file from the given path.
+#[cfg(feature = "action-restore_quarantined_file")]
+pub(crate) fn read_metadata(path: &Path) -> std::io::Result<Metadata> {
+    use protobuf::Message as _;
+
+    let bytes = std::fs::read(path)?;
+
+    let proto = rrg_proto::quarantine_file::Metadata::parse_from_bytes(&bytes)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+    Metadata::from_proto(proto)
+}
+
+impl Metadata {
+
+    /// Parses the metadata from its protobuf representation.
+    #[cfg(feature = "action-restore_quarantined_file")]
+    fn from_proto(mut proto: rrg_proto::quarantine_file::Metadata) -> std::io::Result<Metadata> {
+        fn invalid_data<E>(error: E) -> std::io::Error
+        where
+            E: Into<Box<dyn std::error::Error + Send + Sync>>,
+        {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+        }
+
+        let original_path = PathBuf::try_from(proto.take_original_path())
+            .map_err(invalid_data)?;
+
+        let sha256 = <[u8; 32]>::try_from(proto.sha256())
+            .map_err(|_| invalid_data("invalid SHA-256 digest length"))?;
+
+        // Timestamp is (a bit simplifying) just a duration since epoch, so we
+        // reuse the existing conversion routine for durations.
+        let timestamp = proto.take_quarantine_time();
+        let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+        since_epoch.seconds = timestamp.seconds;
+        since_epoch.nanos = timestamp.nanos;
+
+        let since_epoch = rrg_proto::try_from_duration(since_epoch)
+            .map_err(invalid_data)?;
+
+        Ok(Metadata {
+            original_path,
+            sha256,
+            size: proto.size(),
+            mode: proto.mode,
+            quarantine_time: std::time::UNIX_EPOCH + since_epoch,
+        })
+    }
+
+    /// Converts the metadata into its protobuf representation.
+    pub(crate) fn into_proto(self) -> rrg_proto::quarantine_file::Metadata {
+        let mut proto = rrg_proto::quarantine_file::Metadata::default();
+        proto.set_original_path(self.original_path.into());
+        proto.set_sha256(self.sha256.to_vec());
+        proto.set_size(self.size);
+        if let Some(mode) = self.mode {
+            proto.set_mode(mode);
+        }
+        proto.set_quarantine_time(rrg_proto::into_timestamp(self.quarantine_time));
+
+        proto
+    }
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::quarantine_file::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::quarantine_file::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_quarantine_path(self.quarantine_path.into());
+        proto.set_metadata_path(self.metadata_path.into());
+        proto.set_metadata(self.metadata.into_proto());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn session_with_quarantine_dir(path: &Path) -> crate::session::FakeSession {
+        let args = crate::args::Args {
+            quarantine_dir: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+
+        crate::session::FakeSession::with_args(args)
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest as _;
+        sha2::Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn handle_ok_and_restore() {
+        use protobuf::Message as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let quarantine_dir = tempdir.join("quarantine");
+        let path = tempdir.join("foo");
+        std::fs::write(&path, b"malicious")
+            .unwrap();
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750))
+                .unwrap();
+        }
+
+        let mut session = session_with_quarantine_dir(&quarantine_dir);
+        assert!(handle(&mut session, Args { path: path.clone() }).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert!(item.quarantine_path.starts_with(&quarantine_dir));
+        assert_eq!(item.metadata.original_path, path);
+        assert_eq!(item.metadata.sha256, sha256(b"malicious"));
+        assert_eq!(item.metadata.size, 9);
+
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&item.quarantine_path).unwrap(), b"malicious");
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode = item.quarantine_path.metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o400);
+
+            let mode = quarantine_dir.metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        // Now we restore the file using only what was recorded on disk.
+        let metadata_bytes = std::fs::read(&item.metadata_path)
+            .unwrap();
+        let mut metadata = rrg_proto::quarantine_file::Metadata::parse_from_bytes(&metadata_bytes)
+            .unwrap();
+        assert_eq!(metadata.sha256(), sha256(b"malicious"));
+
+        let original_path = PathBuf::try_from(metadata.take_original_path())
+            .unwrap();
+        assert_eq!(original_path, path);
+
+        std::fs::rename(&item.quarantine_path, &original_path)
+            .unwrap();
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            assert_eq!(metadata.mode(), 0o750);
+            let permissions = std::fs::Permissions::from_mode(metadata.mode());
+            std::fs::set_permissions(&original_path, permissions)
+                .unwrap();
+        }
+
+        let contents = std::fs::read(&path)
+            .unwrap();
+        assert_eq!(contents, b"malicious");
+        assert_eq!(sha256(&contents), metadata.sha256());
+    }
+
+    #[test]
+    fn handle_no_quarantine_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"bar")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, Args { path: path.clone() }).is_err());
+
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn handle_not_file() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::create_dir(&path)
+            .unwrap();
+
+        let mut session = session_with_quarantine_dir(&tempdir.path().join("quarantine"));
+        assert!(handle(&mut session, Args { path: path.clone() }).is_err());
+
+        assert!(path.exists());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let target = tempdir.path().join("target");
+        std::fs::write(&target, b"bar")
+            .unwrap();
+
+        let path = tempdir.path().join("link");
+        std::os::unix::fs::symlink(&target, &path)
+            .unwrap();
+
+        let mut session = session_with_quarantine_dir(&tempdir.path().join("quarantine"));
+        assert!(handle(&mut session, Args { path: path.clone() }).is_err());
+
+        assert!(path.symlink_metadata().is_ok());
+        assert!(target.exists());
+    }
+
+    #[test]
+    fn move_by_copy_ok() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let src = tempdir.path().join("src");
+        let dst = tempdir.path().join("dst");
+        std::fs::write(&src, b"foobar")
+            .unwrap();
+
+        move_by_copy(&src, &dst, &sha256(b"foobar"))
+            .unwrap();
+
+        assert!(!src.exists());
+        assert_eq!(std::fs::read(&dst).unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn move_by_copy_digest_mismatch() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let src = tempdir.path().join("src");
+        let dst = tempdir.path().join("dst");
+        std::fs::write(&src, b"foobar")
+            .unwrap();
+
+        assert!(move_by_copy(&src, &dst, &sha256(b"foo")).is_err());
+
+        assert_eq!(std::fs::read(&src).unwrap(), b"foobar");
+        assert!(!dst.exists());
+    }
+}
@@ -0,0 +1,722 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Querying of live state of system services over D-Bus.
+//!
+//! Unlike actions that parse service definitions from disk, this one asks the
+//! services themselves through the system bus: systemd for states of its units
+//! and NetworkManager for the connections it has activated. The bus is talked
+//! to through `busctl` (part of systemd), so this action is available only on
+//! Linux. Every call is subject to a timeout, so an unresponsive service cannot
+//! make the action hang.
+
+/// Maximum time to wait for a reply to a single bus call.
+#[cfg(target_os = "linux")]
+const CALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Bus name (and interface prefix) of NetworkManager.
+#[cfg(target_os = "linux")]
+const NETWORK_MANAGER: &str = "org.freedesktop.NetworkManager";
+
+/// Arguments of the `query_dbus_state` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Sources of the state to query (all if empty).
+    sources: Vec<Source>,
+}
+
+/// Source of the state available on the system bus.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    /// Units managed by systemd.
+    SystemdUnits,
+    /// Connections activated by NetworkManager.
+    NetworkManagerConnections,
+}
+
+/// All sources of the state, queried if none is specified explicitly.
+#[cfg(target_os = "linux")]
+const ALL_SOURCES: &[Source] = &[
+    Source::SystemdUnits,
+    Source::NetworkManagerConnections,
+];
+
+/// Result of the `query_dbus_state` action.
+#[cfg(target_os = "linux")]
+enum Item {
+    /// Unit managed by systemd.
+    SystemdUnit(SystemdUnit),
+    /// Connection activated by NetworkManager.
+    NetworkManagerConnection(NetworkManagerConnection),
+}
+
+/// State of a unit loaded by systemd.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+struct SystemdUnit {
+    /// Name of the unit (e.g. `sshd.service`).
+    name: String,
+    /// Human-readable description of the unit.
+    description: String,
+    /// Whether the unit definition was loaded (e.g. `loaded` or `not-found`).
+    load_state: String,
+    /// High-level activation state of the unit (e.g. `active` or `failed`).
+    active_state: String,
+    /// Low-level, unit type-specific activation state (e.g. `running`).
+    sub_state: String,
+    /// D-Bus object path of the unit.
+    object_path: String,
+}
+
+/// State of a connection activated by NetworkManager.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+struct NetworkManagerConnection {
+    /// Human-readable identifier of the connection.
+    id: String,
+    /// UUID of the connection profile.
+    uuid: String,
+    /// Type of the connection (e.g. `802-3-ethernet`).
+    kind: String,
+    /// Raw `NMActiveConnectionState` value of the connection.
+    state: u32,
+    /// Names of network interfaces the connection is active on.
+    interfaces: Vec<String>,
+    /// Whether the connection owns the default IPv4 route.
+    default_ipv4: bool,
+    /// Whether the connection owns the default IPv6 route.
+    default_ipv6: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::QueryDbusState;
+
+/// Handles invocations of the `query_dbus_state` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `query_dbus_state` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let is_explicit = !args.sources.is_empty();
+    let sources = if is_explicit {
+        args.sources
+    } else {
+        ALL_SOURCES.to_vec()
+    };
+
+    let mut last_error = None;
+    let mut queried_any = false;
+
+    for source in sources {
+        let items = match source {
+            Source::SystemdUnits => systemd_units()
+                .map(|units| units.into_iter().map(Item::SystemdUnit).collect()),
+            Source::NetworkManagerConnections => network_manager_connections()
+                .map(|conns| conns.into_iter().map(Item::NetworkManagerConnection).collect()),
+        };
+
+        let items: Vec<Item> = match items {
+            Ok(items) => items,
+            // Explicitly requested sources have to be available, others are
+            // just skipped (not every system runs NetworkManager).
+            Err(error) if is_explicit => return Err(crate::session::Error::action(error)),
+            Err(error) => {
+                log::warn!("failed to query {source:?}: {error}");
+                last_error = Some(error);
+                continue;
+            }
+        };
+        queried_any = true;
+
+        for item in items {
+            session.reply(item)?;
+        }
+    }
+
+    // If none of the sources could be queried, the bus itself is most likely
+    // not available and we want to let the server know about this.
+    match last_error {
+        Some(error) if !queried_any => Err(crate::session::Error::action(error)),
+        _ => Ok(()),
+    }
+}
+
+/// Returns states of all units currently loaded by systemd.
+#[cfg(target_os = "linux")]
+fn systemd_units() -> std::io::Result<Vec<SystemdUnit>> {
+    let mut values = busctl(&[
+        "call",
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+        "ListUnits",
+    ])?;
+
+    parse_units(&mut values)
+}
+
+/// Parses the reply to the `ListUnits` call of the systemd manager.
+#[cfg(target_os = "linux")]
+fn parse_units(values: &mut Values) -> std::io::Result<Vec<SystemdUnit>> {
+    values.signature("a(ssssssouso)")?;
+
+    let mut units = Vec::new();
+    for _ in 0..values.len()? {
+        let name = values.string()?;
+        let description = values.string()?;
+        let load_state = values.string()?;
+        let active_state = values.string()?;
+        let sub_state = values.string()?;
+        let _following = values.string()?;
+        let object_path = values.string()?;
+        let _job_id = values.u32()?;
+        let _job_type = values.string()?;
+        let _job_path = values.string()?;
+
+        units.push(SystemdUnit {
+            name,
+            description,
+            load_state,
+            active_state,
+            sub_state,
+            object_path,
+        });
+    }
+
+    Ok(units)
+}
+
+/// Returns states of all connections activated by NetworkManager.
+#[cfg(target_os = "linux")]
+fn network_manager_connections() -> std::io::Result<Vec<NetworkManagerConnection>> {
+    let mut values = busctl(&[
+        "get-property",
+        NETWORK_MANAGER,
+        "/org/freedesktop/NetworkManager",
+        NETWORK_MANAGER,
+        "ActiveConnections",
+    ])?;
+
+    values.signature("ao")?;
+    let paths = (0..values.len()?)
+        .map(|_| values.string())
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let mut conns = Vec::new();
+    for path in paths {
+        // Connections can get deactivated while we are querying them, so we
+        // do not fail the whole source because of a single one.
+        match network_manager_connection(&path) {
+            Ok(conn) => conns.push(conn),
+            Err(error) => log::warn! {
+                "failed to query NetworkManager connection '{path}': {error}"
+            },
+        }
+    }
+
+    Ok(conns)
+}
+
+/// Returns the state of the NetworkManager connection at the given object path.
+#[cfg(target_os = "linux")]
+fn network_manager_connection(path: &str) -> std::io::Result<NetworkManagerConnection> {
+    let mut values = busctl(&[
+        "get-property",
+        NETWORK_MANAGER,
+        path,
+        &format!("{NETWORK_MANAGER}.Connection.Active"),
+        "Id",
+        "Uuid",
+        "Type",
+        "State",
+        "Devices",
+        "Default",
+        "Default6",
+    ])?;
+
+    let (mut conn, device_paths) = parse_connection(&mut values)?;
+
+    for device_path in device_paths {
+        let mut values = busctl(&[
+            "get-property",
+            NETWORK_MANAGER,
+            &device_path,
+            &format!("{NETWORK_MANAGER}.Device"),
+            "Interface",
+        ])?;
+
+        values.signature("s")?;
+        conn.interfaces.push(values.string()?);
+    }
+
+    Ok(conn)
+}
+
+/// Parses properties of an active NetworkManager connection.
+///
+/// Interfaces of the returned connection are not filled, instead object paths
+/// of its devices are returned alongside it.
+#[cfg(target_os = "linux")]
+fn parse_connection(
+    values: &mut Values,
+) -> std::io::Result<(NetworkManagerConnection, Vec<String>)> {
+    values.signature("s")?;
+    let id = values.string()?;
+    values.signature("s")?;
+    let uuid = values.string()?;
+    values.signature("s")?;
+    let kind = values.string()?;
+    values.signature("u")?;
+    let state = values.u32()?;
+
+    values.signature("ao")?;
+    let device_paths = (0..values.len()?)
+        .map(|_| values.string())
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    values.signature("b")?;
+    let default_ipv4 = values.bool()?;
+    values.signature("b")?;
+    let default_ipv6 = values.bool()?;
+
+    let conn = NetworkManagerConnection {
+        id,
+        uuid,
+        kind,
+        state,
+        interfaces: Vec::new(),
+        default_ipv4,
+        default_ipv6,
+    };
+
+    Ok((conn, device_paths))
+}
+
+/// Invokes `busctl` against the system bus and returns the printed values.
+#[cfg(target_os = "linux")]
+fn busctl(args: &[&str]) -> std::io::Result<Values> {
+    let output = std::process::Command::new("busctl")
+        .arg("--system")
+        .arg(format!("--timeout={}", CALL_TIMEOUT.as_secs()))
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other({
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        }));
+    }
+
+    Values::parse(&output.stdout)
+}
+
+/// Values printed by `busctl` in its default (non-JSON) format.
+///
+/// Every top-level value is preceded by its signature and all the tokens are
+/// separated by whitespace. Strings (and object paths) are quoted and escaped
+/// C-style, arrays are printed as the number of elements followed by them and
+/// structs are flattened, e.g. `a(su) 2 "foo" 1 "bar" 2`.
+#[cfg(target_os = "linux")]
+struct Values {
+    /// Remaining tokens of the output.
+    tokens: std::vec::IntoIter<Token>,
+}
+
+/// Single token of the `busctl` output.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    /// Unescaped contents of a quoted string.
+    Quoted(String),
+    /// Unquoted word (a signature, number or boolean).
+    Bare(String),
+}
+
+#[cfg(target_os = "linux")]
+impl Values {
+
+    /// Splits the given `busctl` output into tokens.
+    fn parse(output: &[u8]) -> std::io::Result<Values> {
+        use std::io::ErrorKind::InvalidData;
+
+        let mut tokens = Vec::new();
+        let mut bytes = output.iter().copied().peekable();
+
+        loop {
+            while bytes.next_if(u8::is_ascii_whitespace).is_some() {
+            }
+
+            let Some(byte) = bytes.next() else {
+                break;
+            };
+
+            if byte != b'"' {
+                let mut word = vec![byte];
+                while let Some(byte) = bytes.next_if(|byte| !byte.is_ascii_whitespace()) {
+                    word.push(byte);
+                }
+
+                tokens.push(Token::Bare(String::from_utf8_lossy(&word).into_owned()));
+                continue;
+            }
+
+            let mut string = Vec::new();
+            loop {
+                let byte = match bytes.next() {
+                    Some(b'"') => break,
+                    Some(b'\\') => match bytes.next() {
+                        Some(b'a') => 0x07,
+                        Some(b'b') => 0x08,
+                        Some(b'f') => 0x0c,
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        Some(b'v') => 0x0b,
+                        Some(b'x') => {
+                            let hex = [bytes.next(), bytes.next()];
+                            let hex = hex.into_iter().collect::<Option<Vec<_>>>()
+                                .ok_or_else(|| std::io::Error::new(InvalidData, "truncated escape"))?;
+
+                            std::str::from_utf8(&hex).ok()
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| std::io::Error::new(InvalidData, "malformed escape"))?
+                        }
+                        Some(byte) => byte,
+                        None => return Err(std::io::Error::new(InvalidData, "truncated escape")),
+                    },
+                    Some(byte) => byte,
+                    None => return Err(std::io::Error::new(InvalidData, "unterminated string")),
+                };
+
+                string.push(byte);
+            }
+
+            tokens.push(Token::Quoted(String::from_utf8_lossy(&string).into_owned()));
+        }
+
+        Ok(Values {
+            tokens: tokens.into_iter(),
+        })
+    }
+
+    /// Consumes the signature of the next value and verifies it is as expected.
+    fn signature(&mut self, expected: &str) -> std::io::Result<()> {
+        let signature = self.bare()?;
+        if signature != expected {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, {
+                format!("unexpected signature '{signature}' (expected '{expected}')")
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Consumes the next string (or object path) value.
+    fn string(&mut self) -> std::io::Result<String> {
+        match self.tokens.next() {
+            Some(Token::Quoted(string)) => Ok(string),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected a string")),
+        }
+    }
+
+    /// Consumes the next unsigned 32-bit integer value.
+    fn u32(&mut self) -> std::io::Result<u32> {
+        self.bare()?.parse()
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    /// Consumes the next boolean value.
+    fn bool(&mut self) -> std::io::Result<bool> {
+        match self.bare()?.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected a boolean")),
+        }
+    }
+
+    /// Consumes the number of elements of the next array value.
+    fn len(&mut self) -> std::io::Result<usize> {
+        self.bare()?.parse()
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    /// Consumes the next unquoted token.
+    fn bare(&mut self) -> std::io::Result<String> {
+        match self.tokens.next() {
+            Some(Token::Bare(word)) => Ok(word),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected a word")),
+        }
+    }
+}
+
+/// An error type for situations where an unknown source is requested.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct UnknownSourceError {
+    /// Raw value of the unknown source.
+    value: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl std::fmt::Display for UnknownSourceError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "unknown source: {}", self.value)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::error::Error for UnknownSourceError {
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::query_dbus_state::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use rrg_proto::query_dbus_state::Source as ProtoSource;
+
+        let sources = proto.take_sources().into_iter()
+            .map(|source| match source.enum_value() {
+                Ok(ProtoSource::SYSTEMD_UNITS) => Ok(Source::SystemdUnits),
+                Ok(ProtoSource::NETWORK_MANAGER_CONNECTIONS) => Ok(Source::NetworkManagerConnections),
+                _ => Err(UnknownSourceError {
+                    value: source.value(),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| crate::request::ParseArgsError::invalid_field("sources", error))?;
+
+        Ok(Args {
+            sources,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::query_dbus_state::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::query_dbus_state::ConnectionState;
+
+        let mut proto = Self::Proto::default();
+
+        match self {
+            Item::SystemdUnit(unit) => {
+                let proto = proto.mut_systemd_unit();
+                proto.set_name(unit.name);
+                proto.set_description(unit.description);
+                proto.set_load_state(unit.load_state);
+                proto.set_active_state(unit.active_state);
+                proto.set_sub_state(unit.sub_state);
+                proto.set_object_path(unit.object_path);
+            }
+            Item::NetworkManagerConnection(conn) => {
+                let state = match conn.state {
+                    1 => ConnectionState::ACTIVATING,
+                    2 => ConnectionState::ACTIVATED,
+                    3 => ConnectionState::DEACTIVATING,
+                    4 => ConnectionState::DEACTIVATED,
+                    _ => ConnectionState::UNKNOWN,
+                };
+
+                let proto = proto.mut_network_manager_connection();
+                proto.set_id(conn.id);
+                proto.set_uuid(conn.uuid);
+                proto.set_type(conn.kind);
+                proto.set_state(state);
+                proto.set_interfaces(conn.interfaces);
+                proto.set_default_ipv4(conn.default_ipv4);
+                proto.set_default_ipv6(conn.default_ipv6);
+            }
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn values_parse_escapes() {
+        let mut values = Values::parse(br#"s "foo \"bar\"\n\x41\\""#)
+            .unwrap();
+
+        values.signature("s")
+            .unwrap();
+        assert_eq!(values.string().unwrap(), "foo \"bar\"\nA\\");
+        assert!(values.string().is_err());
+    }
+
+    #[test]
+    fn values_parse_unterminated() {
+        assert!(Values::parse(br#"s "foo"#).is_err());
+    }
+
+    #[test]
+    fn parse_units_ok() {
+        let output = concat! {
+            "a(ssssssouso) 2 ",
+            "\"sshd.service\" \"OpenSSH Daemon\" \"loaded\" \"active\" \"running\" \"\" ",
+            "\"/org/freedesktop/systemd1/unit/sshd_2eservice\" 0 \"\" \"/\" ",
+            "\"foo.service\" \"foo.service\" \"not-found\" \"failed\" \"failed\" \"\" ",
+            "\"/org/freedesktop/systemd1/unit/foo_2eservice\" 0 \"\" \"/\"\n",
+        };
+
+        let units = parse_units(&mut Values::parse(output.as_bytes()).unwrap())
+            .unwrap();
+
+        assert_eq!(units, vec![
+            SystemdUnit {
+                name: "sshd.service".into(),
+                description: "OpenSSH Daemon".into(),
+                load_state: "loaded".into(),
+                active_state: "active".into(),
+                sub_state: "running".into(),
+                object_path: "/org/freedesktop/systemd1/unit/sshd_2eservice".into(),
+            },
+            SystemdUnit {
+                name: "foo.service".into(),
+                description: "foo.service".into(),
+                load_state: "not-found".into(),
+                active_state: "failed".into(),
+                sub_state: "failed".into(),
+                object_path: "/org/freedesktop/systemd1/unit/foo_2eservice".into(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn parse_units_unexpected_signature() {
+        let mut values = Values::parse(b"as 1 \"foo\"")
+            .unwrap();
+
+        assert!(parse_units(&mut values).is_err());
+    }
+
+    #[test]
+    fn parse_connection_ok() {
+        let output = concat! {
+            "s \"Wired connection 1\"\n",
+            "s \"0d8b3e5a-9a4c-3d3b-8f6e-2d0a1b2c3d4e\"\n",
+            "s \"802-3-ethernet\"\n",
+            "u 2\n",
+            "ao 1 \"/org/freedesktop/NetworkManager/Devices/2\"\n",
+            "b true\n",
+            "b false\n",
+        };
+
+        let (conn, device_paths) = parse_connection(&mut Values::parse(output.as_bytes()).unwrap())
+            .unwrap();
+
+        assert_eq!(conn.id, "Wired connection 1");
+        assert_eq!(conn.uuid, "0d8b3e5a-9a4c-3d3b-8f6e-2d0a1b2c3d4e");
+        assert_eq!(conn.kind, "802-3-ethernet");
+        assert_eq!(conn.state, 2);
+        assert!(conn.default_ipv4);
+        assert!(!conn.default_ipv6);
+        assert_eq!(device_paths, vec!["/org/freedesktop/NetworkManager/Devices/2"]);
+    }
+
+    #[test]
+    fn item_into_proto_connection() {
+        use crate::response::Item as _;
+        use rrg_proto::query_dbus_state::ConnectionState;
+
+        let item = Item::NetworkManagerConnection(NetworkManagerConnection {
+            id: "foo".into(),
+            uuid: "bar".into(),
+            kind: "vpn".into(),
+            state: 2,
+            interfaces: vec!["tun0".into()],
+            default_ipv4: true,
+            default_ipv6: false,
+        });
+
+        let proto = item.into_proto();
+        assert!(!proto.has_systemd_unit());
+
+        let conn = proto.network_manager_connection();
+        assert_eq!(conn.id(), "foo");
+        assert_eq!(conn.type_(), "vpn");
+        assert_eq!(conn.state(), ConnectionState::ACTIVATED);
+        assert_eq!(conn.interfaces(), ["tun0"]);
+        assert!(conn.default_ipv4());
+    }
+
+    #[test]
+    fn item_into_proto_connection_unknown_state() {
+        use crate::response::Item as _;
+        use rrg_proto::query_dbus_state::ConnectionState;
+
+        let item = Item::NetworkManagerConnection(NetworkManagerConnection {
+            id: "foo".into(),
+            uuid: "bar".into(),
+            kind: "vpn".into(),
+            state: 42,
+            interfaces: vec![],
+            default_ipv4: false,
+            default_ipv6: false,
+        });
+
+        let proto = item.into_proto();
+        assert_eq!(proto.network_manager_connection().state(), ConnectionState::UNKNOWN);
+    }
+
+    #[test]
+    fn handle_no_bus() {
+        if std::path::Path::new("/run/dbus/system_bus_socket").exists() {
+            return;
+        }
+
+        let args = Args {
+            sources: vec![],
+        };
+
+        // Without a bus, none of the sources can be queried and this has to be
+        // reported as a failure rather than an empty result.
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn busctl_get_id() {
+        // Not every environment (e.g. a container) has a system bus running.
+        if !std::path::Path::new("/run/dbus/system_bus_socket").exists() {
+            return;
+        }
+
+        let mut values = busctl(&[
+            "call",
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "GetId",
+        ]).unwrap();
+
+        values.signature("s")
+            .unwrap();
+        assert!(!values.string().unwrap().is_empty());
+    }
+}
@@ -19,6 +19,9 @@ struct Item {
     row: wmi::QueryRow,
 }
 
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::QueryWmi;
+
 /// Handles invocations of the `query_wmi` action.
 #[cfg(target_family = "windows")]
 pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
@@ -0,0 +1,536 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Resolution of hostnames as seen by the host.
+//!
+//! Tampering with name resolution (e.g. through the `hosts` file or a rogue DNS
+//! server) is a common way of redirecting traffic of a host, so it is useful to
+//! verify what addresses a domain resolves to from the host's point of view.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// Time to wait for resolution of a single hostname if not specified.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// DNS record type of IPv4 addresses.
+const TYPE_A: u16 = 1;
+/// DNS record type of canonical names.
+const TYPE_CNAME: u16 = 5;
+/// DNS record type of IPv6 addresses.
+const TYPE_AAAA: u16 = 28;
+
+/// Maximum number of compression pointers followed when reading a DNS name.
+const MAX_NAME_JUMPS: usize = 64;
+
+/// Arguments of the `resolve_hostnames` action.
+pub struct Args {
+    /// Hostnames to resolve.
+    hostnames: Vec<String>,
+    /// Address of the DNS server to query (system resolver is used if none).
+    resolver: Option<SocketAddr>,
+    /// Maximum time to wait for resolution of a single hostname.
+    timeout: Duration,
+}
+
+/// Result of the `resolve_hostnames` action.
+struct Item {
+    /// Hostname that was resolved.
+    hostname: String,
+    /// Records the hostname resolved to or the error that prevented it.
+    records: std::io::Result<Records>,
+}
+
+/// Records obtained for a single hostname.
+#[derive(Debug, Default)]
+struct Records {
+    /// IPv4 and IPv6 addresses the hostname resolved to.
+    ip_addrs: Vec<IpAddr>,
+    /// Canonical names the hostname is an alias of.
+    cnames: Vec<String>,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ResolveHostnames;
+
+/// Handles invocations of the `resolve_hostnames` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    for hostname in args.hostnames {
+        let records = match args.resolver {
+            Some(resolver) => resolve_dns(&hostname, resolver, args.timeout),
+            None => resolve_system(&hostname, args.timeout),
+        };
+
+        if let Err(error) = &records {
+            log::info!("failed to resolve '{hostname}': {error}");
+        }
+
+        session.reply(Item {
+            hostname,
+            records,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Resolves the hostname using the system resolver.
+fn resolve_system(hostname: &str, timeout: Duration) -> std::io::Result<Records> {
+    use std::net::ToSocketAddrs as _;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    // There is no portable way of cancelling a blocking lookup, so in case of
+    // a timeout the thread is left running and its result is discarded.
+    let owned_hostname = String::from(hostname);
+    std::thread::Builder::new()
+        .name(String::from("rrg-resolve"))
+        .spawn(move || {
+            let addrs = (owned_hostname.as_str(), 0).to_socket_addrs()
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect::<Vec<_>>());
+
+            // The receiver might have given up already, which is fine.
+            let _ = sender.send(addrs);
+        })?;
+
+    let addrs = match receiver.recv_timeout(timeout) {
+        Ok(addrs) => addrs?,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => return Err(timed_out()),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+            return Err(std::io::Error::other("resolver thread terminated"));
+        }
+    };
+
+    // The system resolver reports each address once per socket type, so there
+    // are plenty of duplicates.
+    let mut records = Records::default();
+    for addr in addrs {
+        if !records.ip_addrs.contains(&addr) {
+            records.ip_addrs.push(addr);
+        }
+    }
+
+    Ok(records)
+}
+
+/// Resolves the hostname by querying the given DNS server directly.
+fn resolve_dns(
+    hostname: &str,
+    resolver: SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<Records> {
+    let deadline = std::time::Instant::now() + timeout;
+
+    let local_addr = match resolver {
+        SocketAddr::V4(_) => SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = std::net::UdpSocket::bind(local_addr)?;
+    socket.connect(resolver)?;
+
+    let mut records = Records::default();
+    let mut buf = [0; 4096];
+
+    for qtype in [TYPE_A, TYPE_AAAA] {
+        let id = query_id();
+        socket.send(&dns_query(id, hostname, qtype)?)?;
+
+        let len = loop {
+            let remaining = deadline.checked_duration_since(std::time::Instant::now())
+                .filter(|remaining| !remaining.is_zero())
+                .ok_or_else(timed_out)?;
+            socket.set_read_timeout(Some(remaining))?;
+
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(error) if matches! {
+                    error.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                } => return Err(timed_out()),
+                Err(error) => return Err(error),
+            };
+
+            // Responses to other queries (e.g. late ones) are ignored.
+            if buf[..len].starts_with(&id.to_be_bytes()) {
+                break len;
+            }
+        };
+
+        parse_dns_response(&buf[..len], &mut records)?;
+    }
+
+    Ok(records)
+}
+
+/// Returns an identifier for a new DNS query.
+fn query_id() -> u16 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+
+    (nanos ^ std::process::id()) as u16
+}
+
+/// Builds a DNS query for records of the given type of the hostname.
+fn dns_query(id: u16, hostname: &str, qtype: u16) -> std::io::Result<Vec<u8>> {
+    let mut query = Vec::new();
+    query.extend(id.to_be_bytes());
+    // Standard query with recursion desired.
+    query.extend(0x0100u16.to_be_bytes());
+    // Single question and no records of other kinds.
+    query.extend(1u16.to_be_bytes());
+    query.extend([0; 6]);
+
+    for label in hostname.strip_suffix('.').unwrap_or(hostname).split('.') {
+        let label_len = match u8::try_from(label.len()) {
+            Ok(label_len @ 1..=63) => label_len,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("invalid hostname '{hostname}'"),
+                ));
+            }
+        };
+
+        query.push(label_len);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend(qtype.to_be_bytes());
+    // Internet class.
+    query.extend(1u16.to_be_bytes());
+
+    Ok(query)
+}
+
+/// Parses a DNS response and adds the records from it to the given ones.
+fn parse_dns_response(response: &[u8], records: &mut Records) -> std::io::Result<()> {
+    let flags = read_u16(response, 2)?;
+    match flags & 0x000F {
+        0 => (),
+        3 => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "name does not exist",
+            ));
+        }
+        rcode => {
+            return Err(std::io::Error::other(format!("server error (code {rcode})")));
+        }
+    }
+
+    let question_count = read_u16(response, 4)?;
+    let answer_count = read_u16(response, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..question_count {
+        // Questions are followed by their type and class.
+        offset = skip_name(response, offset)? + 4;
+    }
+
+    for _ in 0..answer_count {
+        offset = skip_name(response, offset)?;
+
+        let rtype = read_u16(response, offset)?;
+        let rdata_len = usize::from(read_u16(response, offset + 8)?);
+        let rdata_offset = offset + 10;
+        let rdata = response.get(rdata_offset..rdata_offset + rdata_len)
+            .ok_or_else(malformed)?;
+
+        match rtype {
+            TYPE_A => {
+                let octets = <[u8; 4]>::try_from(rdata).map_err(|_| malformed())?;
+                records.ip_addrs.push(IpAddr::from(octets));
+            }
+            TYPE_AAAA => {
+                let octets = <[u8; 16]>::try_from(rdata).map_err(|_| malformed())?;
+                records.ip_addrs.push(IpAddr::from(octets));
+            }
+            TYPE_CNAME => {
+                // Responses to both queries contain the same chain of aliases.
+                let cname = read_name(response, rdata_offset)?;
+                if !records.cnames.contains(&cname) {
+                    records.cnames.push(cname);
+                }
+            }
+            _ => (),
+        }
+
+        offset = rdata_offset + rdata_len;
+    }
+
+    Ok(())
+}
+
+/// Reads a (possibly compressed) DNS name starting at the given offset.
+fn read_name(message: &[u8], mut offset: usize) -> std::io::Result<String> {
+    let mut labels = Vec::new();
+
+    // Compression pointers can form a loop, so we bound the number of jumps.
+    for _ in 0..MAX_NAME_JUMPS {
+        loop {
+            let len = *message.get(offset).ok_or_else(malformed)?;
+            if len == 0 {
+                return Ok(labels.join("."));
+            }
+
+            if len & 0xC0 == 0xC0 {
+                offset = usize::from(read_u16(message, offset)? & 0x3FFF);
+                break;
+            }
+
+            let label = message.get(offset + 1..offset + 1 + usize::from(len))
+                .ok_or_else(malformed)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            offset += 1 + usize::from(len);
+        }
+    }
+
+    Err(malformed())
+}
+
+/// Returns the offset right past the DNS name starting at the given offset.
+fn skip_name(message: &[u8], mut offset: usize) -> std::io::Result<usize> {
+    loop {
+        let len = *message.get(offset).ok_or_else(malformed)?;
+        if len == 0 {
+            return Ok(offset + 1);
+        }
+        // Compression pointer always terminates the name.
+        if len & 0xC0 == 0xC0 {
+            return Ok(offset + 2);
+        }
+
+        offset += 1 + usize::from(len);
+    }
+}
+
+/// Reads a big-endian 16-bit integer at the given offset.
+fn read_u16(message: &[u8], offset: usize) -> std::io::Result<u16> {
+    let bytes = message.get(offset..offset + 2)
+        .ok_or_else(malformed)?;
+
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Returns an error indicating a malformed DNS response.
+fn malformed() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed DNS response")
+}
+
+/// Returns an error indicating that the resolution has timed out.
+fn timed_out() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "resolution timed out")
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::resolve_hostnames::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let resolver = if proto.has_resolver() {
+            let resolver = proto.take_resolver();
+
+            let ip_addr = match resolver.ip_address().octets() {
+                octets if octets.len() == 4 => {
+                    IpAddr::from(<[u8; 4]>::try_from(octets).unwrap())
+                }
+                octets if octets.len() == 16 => {
+                    IpAddr::from(<[u8; 16]>::try_from(octets).unwrap())
+                }
+                octets => {
+                    let error = std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("invalid IP address length: {}", octets.len()),
+                    );
+                    return Err(ParseArgsError::invalid_field("resolver", error));
+                }
+            };
+
+            let port = u16::try_from(resolver.port())
+                .map_err(|error| ParseArgsError::invalid_field("resolver", error))?;
+
+            Some(SocketAddr::new(ip_addr, port))
+        } else {
+            None
+        };
+
+        let timeout = if proto.has_timeout() {
+            rrg_proto::try_from_duration(proto.take_timeout())
+                .map_err(|error| ParseArgsError::invalid_field("timeout", error))?
+        } else {
+            DEFAULT_TIMEOUT
+        };
+
+        Ok(Args {
+            hostnames: proto.take_hostnames(),
+            resolver,
+            timeout,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::resolve_hostnames::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_hostname(self.hostname);
+        match self.records {
+            Ok(records) => {
+                proto.set_ip_addresses(records.ip_addrs.into_iter()
+                    .map(Into::into)
+                    .collect());
+                proto.set_cnames(records.cnames);
+            }
+            Err(error) => proto.set_error(error.to_string()),
+        }
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_localhost_and_nonexistent() {
+        let args = Args {
+            hostnames: vec![
+                String::from("localhost"),
+                // The `.invalid` top-level domain is guaranteed not to exist.
+                String::from("nonexistent.invalid"),
+            ],
+            resolver: None,
+            timeout: DEFAULT_TIMEOUT,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 2);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.hostname, "localhost");
+        let records = item.records.as_ref().unwrap();
+        assert!(records.ip_addrs.iter().all(|ip_addr| ip_addr.is_loopback()));
+        assert!(!records.ip_addrs.is_empty());
+
+        let item = session.reply::<Item>(1);
+        assert_eq!(item.hostname, "nonexistent.invalid");
+        assert!(item.records.is_err());
+    }
+
+    #[test]
+    fn handle_resolver() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap();
+        let resolver = server.local_addr()
+            .unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            for _ in 0..2 {
+                let (len, addr) = server.recv_from(&mut buf)
+                    .unwrap();
+                let query = &buf[..len];
+
+                // `foo.example` is an alias of `bar.example`.
+                let cname = b"\x03bar\x07example\x00".to_vec();
+                let answers = match read_u16(query, len - 4).unwrap() {
+                    TYPE_A => vec![(TYPE_CNAME, cname), (TYPE_A, vec![192, 0, 2, 1])],
+                    TYPE_AAAA => vec![(TYPE_CNAME, cname), (TYPE_AAAA, {
+                        "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets().to_vec()
+                    })],
+                    qtype => panic!("unexpected query type: {qtype}"),
+                };
+
+                server.send_to(&response(query, &answers), addr)
+                    .unwrap();
+            }
+        });
+
+        let args = Args {
+            hostnames: vec![String::from("foo.example")],
+            resolver: Some(resolver),
+            timeout: DEFAULT_TIMEOUT,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        server.join()
+            .unwrap();
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        let records = item.records.as_ref().unwrap();
+        assert_eq!(records.ip_addrs, vec![
+            "192.0.2.1".parse::<IpAddr>().unwrap(),
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+        ]);
+        assert_eq!(records.cnames, vec![String::from("bar.example")]);
+    }
+
+    #[test]
+    fn handle_resolver_timeout() {
+        // The server is never going to respond.
+        let server = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap();
+
+        let args = Args {
+            hostnames: vec![String::from("foo.example")],
+            resolver: Some(server.local_addr().unwrap()),
+            timeout: Duration::from_millis(100),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        let error = item.records.as_ref().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn read_name_pointer_loop() {
+        // A name consisting of a label and a pointer back to its beginning.
+        let message = b"\x03foo\xC0\x00";
+
+        let error = read_name(message, 0).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Builds a response to the query with the given answers.
+    ///
+    /// Names of all the answers point to the name in the question.
+    fn response(query: &[u8], answers: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x80;
+        response[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+
+        for (rtype, rdata) in answers {
+            response.extend([0xC0, 0x0C]);
+            response.extend(rtype.to_be_bytes());
+            response.extend(1u16.to_be_bytes());
+            response.extend(60u32.to_be_bytes());
+            response.extend((rdata.len() as u16).to_be_bytes());
+            response.extend(rdata);
+        }
+
+        response
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::path::PathBuf;
+
+/// Arguments of the `resolve_path` action.
+pub struct Args {
+    /// Path to resolve.
+    path: PathBuf,
+}
+
+/// Result of the `resolve_path` action.
+struct Item {
+    /// Path that was resolved.
+    path: PathBuf,
+    /// Target of the symlink (as stored in it) if the path is a symlink.
+    symlink_target: Option<PathBuf>,
+    /// Real path obtained by following all the symlinks.
+    real_path: PathBuf,
+    /// Whether the final target of the path exists.
+    exists: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::ResolvePath;
+
+/// Handles invocations of the `resolve_path` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let chain = crate::fs::symlink_chain(&args.path, session.args().max_symlink_depth)
+        .map_err(crate::session::Error::action)?;
+
+    let symlink_target = if chain.links.is_empty() {
+        None
+    } else {
+        let symlink_target = std::fs::read_link(&args.path)
+            .map_err(crate::session::Error::action)?;
+
+        Some(symlink_target)
+    };
+
+    session.reply(Item {
+        path: args.path,
+        symlink_target,
+        real_path: chain.target,
+        exists: chain.exists,
+    })?;
+
+    Ok(())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::resolve_path::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        Ok(Args {
+            path,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::resolve_path::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        if let Some(symlink_target) = self.symlink_target {
+            proto.set_symlink_target(symlink_target.into());
+        }
+        proto.set_real_path(self.real_path.into());
+        proto.set_exists(self.exists);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_regular_file() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("foo"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("foo"));
+        assert_eq!(item.symlink_target, None);
+        assert_eq!(item.real_path, tempdir.join("foo"));
+        assert!(item.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"")
+            .unwrap();
+        std::os::unix::fs::symlink("foo", tempdir.join("bar"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("bar"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("bar"));
+        assert_eq!(item.symlink_target, Some(PathBuf::from("foo")));
+        assert_eq!(item.real_path, tempdir.join("foo"));
+        assert!(item.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_broken_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::os::unix::fs::symlink("foo", tempdir.join("bar"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("bar"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.symlink_target, Some(PathBuf::from("foo")));
+        assert_eq!(item.real_path, tempdir.join("foo"));
+        assert!(!item.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink_chain() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        std::fs::write(tempdir.join("foo"), b"")
+            .unwrap();
+        std::os::unix::fs::symlink("foo", tempdir.join("bar"))
+            .unwrap();
+        std::os::unix::fs::symlink("bar", tempdir.join("baz"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("baz"),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.symlink_target, Some(PathBuf::from("bar")));
+        assert_eq!(item.real_path, tempdir.join("foo"));
+        assert!(item.exists);
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Restoring files quarantined by the `quarantine_file` action.
+//!
+//! Files are restored to the original path recorded in their metadata, but only
+//! if their contents still match the recorded digest. Like quarantining, this
+//! action is disabled unless explicitly allowed.
+
+use std::path::{Path, PathBuf};
+
+use super::quarantine_file::{Metadata, METADATA_EXTENSION};
+
+/// Arguments of the `restore_quarantined_file` action.
+pub struct Args {
+    /// Path to the quarantined file to restore.
+    quarantine_path: PathBuf,
+    /// Whether to replace the file at the original path if it exists.
+    overwrite: bool,
+}
+
+/// Result of the `restore_quarantined_file` action.
+struct Item {
+    /// Path to which the file was restored.
+    path: PathBuf,
+    /// Metadata recorded for the file when it was quarantined.
+    metadata: Metadata,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::RestoreQuarantinedFile;
+
+/// Handles invocations of the `restore_quarantined_file` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    let quarantine_dir = session.args().quarantine_dir.clone()
+        .ok_or_else(|| crate::session::Error::action(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "quarantine directory not configured",
+        )))?;
+
+    let quarantine_path = resolve(&args.quarantine_path, &quarantine_dir)
+        .map_err(crate::session::Error::action)?;
+
+    let mut metadata_path = quarantine_path.as_os_str().to_owned();
+    metadata_path.push(".");
+    metadata_path.push(METADATA_EXTENSION);
+    let metadata_path = PathBuf::from(metadata_path);
+
+    let metadata = super::quarantine_file::read_metadata(&metadata_path)
+        .map_err(crate::session::Error::action)?;
+
+    let (sha256, _) = super::quarantine_file::hash(session, &quarantine_path)?;
+    if sha256 != metadata.sha256 {
+        return Err(crate::session::Error::action(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("digest of '{}' does not match the recorded one", quarantine_path.display()),
+        )));
+    }
+
+    let path = metadata.original_path.clone();
+    if !args.overwrite && path.symlink_metadata().is_ok() {
+        return Err(crate::session::Error::action(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", path.display()),
+        )));
+    }
+
+    super::quarantine_file::move_file(&quarantine_path, &path, &sha256, args.overwrite)
+        .map_err(crate::session::Error::action)?;
+
+    restore_permissions(&path, &metadata)
+        .map_err(crate::session::Error::action)?;
+
+    if let Err(error) = std::fs::remove_file(&metadata_path) {
+        log::error! {
+            "failed to remove quarantine metadata '{}': {error}",
+            metadata_path.display(),
+        };
+    }
+
+    log::info! {
+        "restored '{}' from '{}'",
+        path.display(),
+        quarantine_path.display(),
+    };
+
+    session.reply(Item {
+        path,
+        metadata,
+    })?;
+
+    Ok(())
+}
+
+/// Resolves the path of the quarantined file, verifying it is in the quarantine.
+fn resolve(path: &Path, quarantine_dir: &Path) -> std::io::Result<PathBuf> {
+    let path = path.canonicalize()?;
+    let quarantine_dir = quarantine_dir.canonicalize()?;
+
+    if path.parent() != Some(&quarantine_dir) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("'{}' is not in the quarantine directory", path.display()),
+        ));
+    }
+    if path.extension() == Some(std::ffi::OsStr::new(METADATA_EXTENSION)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is a quarantine metadata file", path.display()),
+        ));
+    }
+
+    Ok(path)
+}
+
+/// Restores permissions the file had before it was quarantined.
+fn restore_permissions(path: &Path, metadata: &Metadata) -> std::io::Result<()> {
+    #[cfg(target_family = "unix")]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt as _;
+        std::fs::Permissions::from_mode(metadata.mode.unwrap_or(0o600))
+    };
+
+    #[cfg(not(target_family = "unix"))]
+    let permissions = {
+        let _ = metadata;
+
+        let mut permissions = path.metadata()?.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        permissions
+    };
+
+    std::fs::set_permissions(path, permissions)
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::restore_quarantined_file::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let quarantine_path = PathBuf::try_from(proto.take_quarantine_path())
+            .map_err(|error| ParseArgsError::invalid_field("quarantine_path", error))?;
+
+        Ok(Args {
+            quarantine_path,
+            overwrite: proto.overwrite(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::restore_quarantined_file::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_metadata(self.metadata.into_proto());
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn session_with_quarantine_dir(path: &Path) -> crate::session::FakeSession {
+        let args = crate::args::Args {
+            quarantine_dir: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+
+        crate::session::FakeSession::with_args(args)
+    }
+
+    /// Quarantines the file at the given path and returns its quarantine path.
+    fn quarantine(session: &mut crate::session::FakeSession, path: &Path) -> PathBuf {
+        use super::super::quarantine_file;
+
+        let args = quarantine_file::Args {
+            path: path.to_path_buf(),
+        };
+        assert!(quarantine_file::handle(session, args).is_ok());
+
+        session.replies_of::<quarantine_file::Item>().last().unwrap()
+            .quarantine_path.clone()
+    }
+
+    #[test]
+    fn handle_ok() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let quarantine_dir = tempdir.join("quarantine");
+        let path = tempdir.join("foo");
+        std::fs::write(&path, b"malicious")
+            .unwrap();
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640))
+                .unwrap();
+        }
+
+        let mut session = session_with_quarantine_dir(&quarantine_dir);
+        let quarantine_path = quarantine(&mut session, &path);
+        assert!(!path.exists());
+
+        let args = Args {
+            quarantine_path: quarantine_path.clone(),
+            overwrite: false,
+        };
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.replies_of::<Item>().last().unwrap();
+        assert_eq!(item.path, path);
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"malicious");
+        assert!(!quarantine_path.exists());
+        assert_eq!(std::fs::read_dir(&quarantine_dir).unwrap().count(), 0);
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode = path.metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+    }
+
+    #[test]
+    fn handle_digest_mismatch() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let quarantine_dir = tempdir.join("quarantine");
+        let path = tempdir.join("foo");
+        std::fs::write(&path, b"malicious")
+            .unwrap();
+
+        let mut session = session_with_quarantine_dir(&quarantine_dir);
+        let quarantine_path = quarantine(&mut session, &path);
+
+        // We simulate corruption of the quarantined copy.
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&quarantine_path, std::fs::Permissions::from_mode(0o600))
+                .unwrap();
+        }
+        #[cfg(not(target_family = "unix"))]
+        {
+            let mut permissions = quarantine_path.metadata().unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            std::fs::set_permissions(&quarantine_path, permissions)
+                .unwrap();
+        }
+        std::fs::write(&quarantine_path, b"corrupted")
+            .unwrap();
+
+        let args = Args {
+            quarantine_path: quarantine_path.clone(),
+            overwrite: false,
+        };
+        assert!(handle(&mut session, args).is_err());
+
+        assert!(!path.exists());
+        assert!(quarantine_path.exists());
+    }
+
+    #[test]
+    fn handle_existing_without_overwrite() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let quarantine_dir = tempdir.join("quarantine");
+        let path = tempdir.join("foo");
+        std::fs::write(&path, b"malicious")
+            .unwrap();
+
+        let mut session = session_with_quarantine_dir(&quarantine_dir);
+        let quarantine_path = quarantine(&mut session, &path);
+
+        std::fs::write(&path, b"replacement")
+            .unwrap();
+
+        let args = Args {
+            quarantine_path: quarantine_path.clone(),
+            overwrite: false,
+        };
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"replacement");
+        assert!(quarantine_path.exists());
+
+        let args = Args {
+            quarantine_path: quarantine_path.clone(),
+            overwrite: true,
+        };
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"malicious");
+        assert!(!quarantine_path.exists());
+    }
+
+    #[test]
+    fn handle_outside_quarantine_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"bar")
+            .unwrap();
+
+        let mut session = session_with_quarantine_dir(&tempdir.path().join("quarantine"));
+        std::fs::create_dir(tempdir.path().join("quarantine"))
+            .unwrap();
+
+        let args = Args {
+            quarantine_path: path.clone(),
+            overwrite: true,
+        };
+        assert!(handle(&mut session, args).is_err());
+
+        assert!(path.exists());
+    }
+}
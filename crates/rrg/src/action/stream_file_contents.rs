@@ -0,0 +1,815 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Streaming of whole files in sequential chunks.
+//!
+//! Unlike `get_file_contents` (which is meant for fetching specific ranges of
+//! a file), this action sends the entire file as ordered, contiguous chunks
+//! along with the hash of everything sent so far. If the collection gets
+//! interrupted, it can be resumed from the offset right after the last chunk
+//! that the server received.
+//!
+//! Unless a fixed chunk length is requested, the length of chunks adapts to the
+//! throughput of the link: it grows as chunks are sent successfully and backs
+//! off whenever sending fails.
+//!
+//! Optionally, once all the chunks are sent, the file is re-read to verify that
+//! it did not change during the transfer and the outcome is sent as a trailing
+//! reply.
+
+use std::path::PathBuf;
+
+/// Limit on the length of individual chunks sent to the blob sink.
+const MAX_CHUNK_LEN: usize = 2 * 1024 * 1024; // 2 MiB.
+
+/// Arguments of the `stream_file_contents` action.
+pub struct Args {
+    /// Path to the file to stream the contents of.
+    path: PathBuf,
+    /// Offset from which to start streaming the file.
+    offset: u64,
+    /// Fixed length of individual chunks sent to the blob sink (if any).
+    chunk_len: Option<usize>,
+    /// Whether to verify that the file did not change during the transfer.
+    verify: bool,
+}
+
+/// Result of the `stream_file_contents` action.
+struct Item {
+    /// Byte offset of the chunk sent to the blob sink.
+    offset: u64,
+    /// Number of bytes of the chunk sent to the blob sink.
+    len: usize,
+    /// SHA-256 digest of the chunk sent to the blob sink.
+    blob_sha256: [u8; 32],
+    /// SHA-256 digest of all the bytes streamed so far (including the chunk).
+    running_sha256: [u8; 32],
+    /// Size of the file at the moment it was opened.
+    file_size: u64,
+}
+
+/// Trailing result of the `stream_file_contents` action (if verifying).
+struct VerificationItem {
+    /// Size of the file after all the chunks were streamed.
+    final_file_size: u64,
+    /// SHA-256 digest of the file contents re-read after the transfer.
+    final_sha256: [u8; 32],
+    /// Whether the file changed during the transfer.
+    changed: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::StreamFileContents;
+
+/// Handles invocations of the `stream_file_contents` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Read as _, Seek as _};
+    use sha2::Digest as _;
+
+    let mut file = crate::fs::open_file(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    // We do not want to read indefinitely from files that grow while we stream
+    // them (e.g. logs), so we stop at the size observed at the very beginning.
+    let file_size = file_size(&file)
+        .map_err(crate::session::Error::action)?;
+
+    // Not all platforms and filesystems support modification times, in which
+    // case verification has to rely on the size and contents alone.
+    let file_mtime = file.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    file.seek(std::io::SeekFrom::Start(args.offset))
+        .map_err(crate::session::Error::action)?;
+
+    let mut chunk_len_policy = match args.chunk_len {
+        Some(chunk_len) => ChunkLenPolicy::fixed(chunk_len),
+        None => ChunkLenPolicy::new(
+            session.args().stream_chunk_min_len,
+            session.args().stream_chunk_max_len,
+        ),
+    };
+
+    let mut offset = args.offset;
+    let mut running_hasher = sha2::Sha256::new();
+
+    while offset < file_size {
+        session.check_deadline()?;
+
+        let chunk_len = std::cmp::min(chunk_len_policy.len() as u64, file_size - offset);
+        session.throttle(chunk_len)?;
+
+        let mut buf = Vec::with_capacity(chunk_len as usize);
+
+        // A single `read` call might return fewer bytes than requested even if
+        // the end of the file has not been reached yet, so we read until the
+        // chunk is full to keep chunk boundaries predictable.
+        let len_read = (&mut file).take(chunk_len).read_to_end(&mut buf)
+            .map_err(crate::session::Error::action)?;
+
+        // The file might have been truncated since we opened it.
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+
+        let mut chunk_running_hasher = running_hasher.clone();
+        chunk_running_hasher.update(&buf);
+
+        let blob = crate::blob::Blob::from(buf);
+        let blob_sha256 = sha2::Sha256::digest(blob.as_bytes()).into();
+
+        if let Err(error) = session.send(crate::Sink::Blob, blob) {
+            // A smaller chunk might still get through a bad link, so unless we
+            // are already at the minimum we back off and retry the same chunk.
+            if !chunk_len_policy.back_off() {
+                return Err(error);
+            }
+
+            log::warn! {
+                "failed to send chunk of '{}' at offset {offset}, retrying with \
+                 chunk length {}: {error}",
+                args.path.display(), chunk_len_policy.len(),
+            };
+
+            file.seek(std::io::SeekFrom::Start(offset))
+                .map_err(crate::session::Error::action)?;
+
+            continue;
+        }
+
+        chunk_len_policy.grow();
+
+        running_hasher = chunk_running_hasher;
+        let running_sha256 = running_hasher.clone().finalize().into();
+
+        session.reply(Item {
+            offset,
+            len: len_read,
+            blob_sha256,
+            running_sha256,
+            file_size,
+        })?;
+
+        offset += len_read as u64;
+    }
+
+    if args.verify {
+        let running_sha256: [u8; 32] = running_hasher.finalize().into();
+
+        // We re-open the file rather than reuse the handle, as the file could
+        // have been replaced with a different one in the meantime.
+        let mut file = crate::fs::open_file(&args.path)
+            .map_err(crate::session::Error::action)?;
+
+        let final_file_size = self::file_size(&file)
+            .map_err(crate::session::Error::action)?;
+        let final_mtime = file.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        file.seek(std::io::SeekFrom::Start(args.offset))
+            .map_err(crate::session::Error::action)?;
+
+        let mut final_hasher = sha2::Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut file = file.take(file_size.saturating_sub(args.offset));
+        loop {
+            let len = match file.read(&mut buf[..]) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(crate::session::Error::action(error)),
+            };
+            session.record_bytes_read(len as u64);
+            final_hasher.update(&buf[..len]);
+        }
+        let final_sha256: [u8; 32] = final_hasher.finalize().into();
+
+        let changed = final_file_size != file_size ||
+            final_mtime != file_mtime ||
+            final_sha256 != running_sha256 ||
+            offset < file_size;
+
+        if changed {
+            log::warn!("'{}' changed during the transfer", args.path.display());
+        }
+
+        session.reply(VerificationItem {
+            final_file_size,
+            final_sha256,
+            changed,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Policy adapting the length of chunks to the throughput of the link.
+///
+/// The length starts at the minimum and doubles with every chunk that is sent
+/// successfully (up to the maximum). Whenever sending a chunk fails, the length
+/// is halved (down to the minimum).
+#[derive(Debug)]
+struct ChunkLenPolicy {
+    /// Minimum length of chunks.
+    min_len: usize,
+    /// Maximum length of chunks.
+    max_len: usize,
+    /// Current length of chunks.
+    len: usize,
+}
+
+impl ChunkLenPolicy {
+
+    /// Creates a new policy adapting the length within the given bounds.
+    ///
+    /// Bounds are adjusted to be non-zero, consistent and not to exceed the
+    /// maximum supported chunk length.
+    fn new(min_len: usize, max_len: usize) -> ChunkLenPolicy {
+        let max_len = max_len.clamp(1, MAX_CHUNK_LEN);
+        let min_len = min_len.clamp(1, max_len);
+
+        ChunkLenPolicy {
+            min_len,
+            max_len,
+            len: min_len,
+        }
+    }
+
+    /// Creates a new policy that never changes the length.
+    fn fixed(len: usize) -> ChunkLenPolicy {
+        ChunkLenPolicy::new(len, len)
+    }
+
+    /// Returns the length of the next chunk to send.
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Increases the length after a chunk has been sent successfully.
+    fn grow(&mut self) {
+        self.len = std::cmp::min(self.len.saturating_mul(2), self.max_len);
+    }
+
+    /// Decreases the length after a chunk failed to be sent.
+    ///
+    /// Returns `false` if the length is already at the minimum (in which case
+    /// there is no point in retrying).
+    fn back_off(&mut self) -> bool {
+        if self.len == self.min_len {
+            return false;
+        }
+
+        self.len = std::cmp::max(self.len / 2, self.min_len);
+        true
+    }
+}
+
+/// Returns the size of the given file.
+///
+/// For block devices (e.g. `/dev/sda`) this is the capacity of the device, as
+/// their metadata reports zero as the size.
+fn file_size(file: &std::fs::File) -> std::io::Result<u64> {
+    let metadata = file.metadata()?;
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        if metadata.file_type().is_block_device() {
+            return ospect::fs::linux::block_device_size(file);
+        }
+    }
+
+    Ok(metadata.len())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::stream_file_contents::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        let chunk_len = match proto.chunk_length() {
+            0 => None,
+            len if len > MAX_CHUNK_LEN as u64 => {
+                return Err(ParseArgsError::invalid_field("chunk_length", ChunkLenError {
+                    len,
+                }));
+            }
+            len => Some(len as usize),
+        };
+
+        Ok(Args {
+            path,
+            offset: proto.offset(),
+            chunk_len,
+            verify: proto.verify(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::stream_file_contents::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_offset(self.offset);
+        proto.set_length(self.len as u64);
+        proto.set_blob_sha256(self.blob_sha256.to_vec());
+        proto.set_running_sha256(self.running_sha256.to_vec());
+        proto.set_file_size(self.file_size);
+
+        proto
+    }
+}
+
+impl crate::response::Item for VerificationItem {
+
+    type Proto = rrg_proto::stream_file_contents::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+
+        let verification = proto.mut_verification();
+        verification.set_final_file_size(self.final_file_size);
+        verification.set_final_sha256(self.final_sha256.to_vec());
+        verification.set_changed(self.changed);
+
+        proto
+    }
+}
+
+/// An error indicating that the requested chunk length is too big.
+#[derive(Debug)]
+struct ChunkLenError {
+    /// Chunk length that was requested.
+    len: u64,
+}
+
+impl std::fmt::Display for ChunkLenError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "provided chunk length ({}) exceeds the maximum ({})",
+            self.len, MAX_CHUNK_LEN,
+        }
+    }
+}
+
+impl std::error::Error for ChunkLenError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_non_existent() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            offset: 0,
+            chunk_len: None,
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_empty() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: None,
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 0);
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 0);
+    }
+
+    #[test]
+    fn handle_multiple_chunks() {
+        use sha2::Digest as _;
+
+        let contents = (0..10_000u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let mut tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::io::Write::write_all(&mut tempfile, &contents)
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: Some(1024),
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 10);
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 10);
+
+        let mut expected_offset = 0;
+        for item in session.replies::<Item>() {
+            assert_eq!(item.offset, expected_offset);
+            assert_eq!(item.file_size, 10_000);
+
+            let end = expected_offset as usize + item.len;
+            let running_sha256: [u8; 32] = sha2::Sha256::digest(&contents[..end]).into();
+            assert_eq!(item.running_sha256, running_sha256);
+
+            expected_offset += item.len as u64;
+        }
+        assert_eq!(expected_offset, 10_000);
+
+        let blobs = session.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+            .flat_map(|blob| blob.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(blobs, contents);
+    }
+
+    #[test]
+    fn handle_resume_offset() {
+        let mut tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::io::Write::write_all(&mut tempfile, b"foobarbazquux")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 6,
+            chunk_len: Some(4),
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        let offsets = session.replies::<Item>()
+            .map(|item| (item.offset, item.len))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![(6, 4), (10, 3)]);
+
+        let blobs = session.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+            .map(|blob| blob.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(blobs, vec![b"bazq".to_vec(), b"uux".to_vec()]);
+    }
+
+    #[test]
+    fn handle_adaptive_chunk_len() {
+        let contents = (0..1000u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), &contents)
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: None,
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::with_args(crate::args::Args {
+            stream_chunk_min_len: 100,
+            stream_chunk_max_len: 400,
+            ..Default::default()
+        });
+        assert!(handle(&mut session, args).is_ok());
+
+        let lens = session.replies::<Item>()
+            .map(|item| item.len)
+            .collect::<Vec<_>>();
+        assert_eq!(lens, vec![100, 200, 400, 300]);
+    }
+
+    #[test]
+    fn handle_send_failures() {
+        use sha2::Digest as _;
+
+        let contents = (0..1000u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), &contents)
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: None,
+            verify: false,
+        };
+
+        let mut session = FlakySession {
+            inner: crate::session::FakeSession::with_args(crate::args::Args {
+                stream_chunk_min_len: 50,
+                stream_chunk_max_len: 400,
+                ..Default::default()
+            }),
+            // Chunks of 50, 100 and 200 bytes go through, then sending chunks
+            // of 400 and 200 bytes fails, then 100, 200 and 400 go through.
+            failures: [false, false, false, true, true, false, false].into(),
+        };
+        assert!(handle(&mut session, args).is_ok());
+
+        let items = session.inner.replies::<Item>()
+            .map(|item| (item.offset, item.len))
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![
+            (0, 50),
+            (50, 100),
+            (150, 200),
+            (350, 100),
+            (450, 200),
+            (650, 350),
+        ]);
+
+        let running_sha256: [u8; 32] = sha2::Sha256::digest(&contents).into();
+        assert_eq!(session.inner.reply::<Item>(5).running_sha256, running_sha256);
+
+        let blobs = session.inner.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+            .flat_map(|blob| blob.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(blobs, contents);
+    }
+
+    #[test]
+    fn handle_send_failure_at_min_chunk_len() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"foobar")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: Some(4),
+            verify: false,
+        };
+
+        let mut session = FlakySession {
+            inner: crate::session::FakeSession::new(),
+            failures: [true].into(),
+        };
+        assert!(handle(&mut session, args).is_err());
+        assert_eq!(session.inner.reply_count(), 0);
+    }
+
+    #[test]
+    fn chunk_len_policy_backs_off_and_recovers() {
+        let mut policy = ChunkLenPolicy::new(1024, 8 * 1024);
+        assert_eq!(policy.len(), 1024);
+
+        policy.grow();
+        policy.grow();
+        policy.grow();
+        assert_eq!(policy.len(), 8 * 1024);
+
+        policy.grow();
+        assert_eq!(policy.len(), 8 * 1024);
+
+        assert!(policy.back_off());
+        assert!(policy.back_off());
+        assert_eq!(policy.len(), 2 * 1024);
+
+        assert!(policy.back_off());
+        assert_eq!(policy.len(), 1024);
+        assert!(!policy.back_off());
+
+        policy.grow();
+        assert_eq!(policy.len(), 2 * 1024);
+    }
+
+    #[test]
+    fn chunk_len_policy_bounds() {
+        let policy = ChunkLenPolicy::new(0, 0);
+        assert_eq!(policy.len(), 1);
+
+        let mut policy = ChunkLenPolicy::new(4 * MAX_CHUNK_LEN, 8 * MAX_CHUNK_LEN);
+        assert_eq!(policy.len(), MAX_CHUNK_LEN);
+
+        policy.grow();
+        assert_eq!(policy.len(), MAX_CHUNK_LEN);
+    }
+
+    /// A session that fails to send parcels according to a predefined plan.
+    struct FlakySession {
+        /// Session to delegate to.
+        inner: crate::session::FakeSession,
+        /// Outcomes of consecutive sends (`true` means that the send fails).
+        failures: std::collections::VecDeque<bool>,
+    }
+
+    impl crate::session::Session for FlakySession {
+
+        fn args(&self) -> &crate::args::Args {
+            crate::session::Session::args(&self.inner)
+        }
+
+        fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            crate::session::Session::reply(&mut self.inner, item)
+        }
+
+        fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            if self.failures.pop_front().unwrap_or(false) {
+                let error = std::io::Error::from(std::io::ErrorKind::TimedOut);
+                return Err(crate::session::Error::action(error));
+            }
+
+            crate::session::Session::send(&mut self.inner, sink, item)
+        }
+    }
+
+    #[test]
+    fn handle_verify_unchanged() {
+        use sha2::Digest as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"foobarbaz")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: Some(4),
+            verify: true,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 4);
+        assert_eq!(session.replies_of::<Item>().count(), 3);
+
+        let item = session.replies_of::<VerificationItem>()
+            .next()
+            .unwrap();
+        assert_eq!(item.final_file_size, 9);
+        assert_eq!(item.final_sha256, <[u8; 32]>::from(sha2::Sha256::digest(b"foobarbaz")));
+        assert!(!item.changed);
+    }
+
+    #[test]
+    fn handle_verify_modified() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"foobarbaz")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: Some(4),
+            verify: true,
+        };
+
+        // The file is overwritten (without changing its size) after the first
+        // chunk is read, so the streamed chunks mix old and new contents.
+        let mut session = TamperingSession {
+            inner: crate::session::FakeSession::new(),
+            path: tempfile.path().to_path_buf(),
+            contents: Some(b"quuxnorfy".to_vec()),
+        };
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.inner.replies_of::<VerificationItem>()
+            .next()
+            .unwrap();
+        assert_eq!(item.final_file_size, 9);
+        assert!(item.changed);
+    }
+
+    #[test]
+    fn handle_verify_truncated() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::fs::write(tempfile.path(), b"foobarbaz")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 0,
+            chunk_len: Some(4),
+            verify: true,
+        };
+
+        let mut session = TamperingSession {
+            inner: crate::session::FakeSession::new(),
+            path: tempfile.path().to_path_buf(),
+            contents: Some(b"foob".to_vec()),
+        };
+        assert!(handle(&mut session, args).is_ok());
+
+        let item = session.inner.replies_of::<VerificationItem>()
+            .next()
+            .unwrap();
+        assert_eq!(item.final_file_size, 4);
+        assert!(item.changed);
+    }
+
+    /// A session that overwrites a file when the first parcel is sent.
+    struct TamperingSession {
+        /// Session to delegate to.
+        inner: crate::session::FakeSession,
+        /// Path to the file to overwrite.
+        path: PathBuf,
+        /// Contents to overwrite the file with (if not overwritten yet).
+        contents: Option<Vec<u8>>,
+    }
+
+    impl crate::session::Session for TamperingSession {
+
+        fn args(&self) -> &crate::args::Args {
+            crate::session::Session::args(&self.inner)
+        }
+
+        fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            crate::session::Session::reply(&mut self.inner, item)
+        }
+
+        fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
+        where
+            I: crate::response::Item + 'static,
+        {
+            if let Some(contents) = self.contents.take() {
+                // Overwriting in place keeps the inode (and thus the handle
+                // used for streaming) the same.
+                let mut file = std::fs::File::options().write(true).open(&self.path)
+                    .unwrap();
+                std::io::Write::write_all(&mut file, &contents)
+                    .unwrap();
+                file.set_len(contents.len() as u64)
+                    .unwrap();
+            }
+
+            crate::session::Session::send(&mut self.inner, sink, item)
+        }
+    }
+
+    #[test]
+    fn handle_offset_beyond_end() {
+        let mut tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+        std::io::Write::write_all(&mut tempfile, b"foo")
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            offset: 42,
+            chunk_len: None,
+            verify: false,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+}
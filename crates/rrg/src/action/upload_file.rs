@@ -0,0 +1,946 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Uploading of files directly to URLs provided by the server.
+//!
+//! For big collections it is more efficient to bypass Fleetspeak and upload
+//! files straight to some storage (e.g. through a presigned URL of a bucket).
+//! Because this sends data off the host outside of the usual channel, this
+//! action is disabled unless explicitly allowed.
+//!
+//! The transfer itself is done by `curl` (at the path configured for the agent
+//! and verified at startup), which takes care of TLS (always with certificate
+//! verification). Contents are streamed to it through a pipe, so
+//! the reported digest is computed over exactly the bytes that were sent (and
+//! so are digests of individual chunks, if requested). The URL and headers
+//! (which usually carry credentials) are passed in a private configuration
+//! file rather than on the command line, so that they are not visible to other
+//! users of the system.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Maximum time the whole upload can take if not specified.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum time to wait for the connection to the server to be established.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size of the buffer used for streaming the uploaded file.
+const BUF_LEN: usize = 64 * 1024;
+
+/// Whether the `curl` executable passed the checks at startup.
+static CURL_AVAILABLE: std::sync::atomic::AtomicBool = {
+    std::sync::atomic::AtomicBool::new(true)
+};
+
+/// Verifies the `curl` executable configured for the agent.
+///
+/// This should be called when the agent starts. If the executable is not
+/// usable, the error is logged and the action refuses to run.
+pub fn init(args: &crate::args::Args) {
+    if let Err(error) = check_curl(&args.curl_path) {
+        log::error!("file uploads are not available, invalid curl '{}': {error}", args.curl_path.display());
+        CURL_AVAILABLE.store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Checks whether the given path points to an executable file.
+fn check_curl(path: &Path) -> std::io::Result<()> {
+    if !path.is_absolute() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "path is not absolute",
+        ));
+    }
+
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "not executable",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments of the `upload_file` action.
+pub struct Args {
+    /// Path to the file to upload.
+    path: PathBuf,
+    /// URL to upload the file to.
+    url: String,
+    /// HTTP method to upload the file with.
+    method: Method,
+    /// Additional HTTP headers to send as name-value pairs.
+    headers: Vec<(String, String)>,
+    /// Maximum time the whole upload can take.
+    timeout: Duration,
+    /// Length of chunks of the contents to report digests of (if any).
+    chunk_len: Option<u64>,
+}
+
+/// HTTP method to upload a file with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Method {
+    Put,
+    Post,
+}
+
+/// Result of the `upload_file` action.
+struct Item {
+    /// Path to the file that was uploaded.
+    path: PathBuf,
+    /// Number of bytes uploaded.
+    size: u64,
+    /// SHA-256 digest of the uploaded contents.
+    sha256: [u8; 32],
+    /// HTTP status code with which the server responded.
+    status_code: u32,
+}
+
+/// Result of the `upload_file` action for a chunk of the contents.
+struct ChunkItem {
+    /// Byte offset of the chunk within the uploaded contents.
+    offset: u64,
+    /// Number of bytes of the chunk.
+    len: u64,
+    /// SHA-256 digest of the bytes of the chunk.
+    sha256: [u8; 32],
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::UploadFile;
+
+/// Handles invocations of the `upload_file` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if !CURL_AVAILABLE.load(std::sync::atomic::Ordering::Relaxed) {
+        let error = std::io::Error::new(std::io::ErrorKind::NotFound, "curl is not available");
+        return Err(crate::session::Error::action(error));
+    }
+
+    let mut file = crate::fs::open_file(&args.path)
+        .map_err(crate::session::Error::action)?;
+    let len = file.metadata()
+        .map_err(crate::session::Error::action)?
+        .len();
+
+    let temp_dir = crate::temp_dir(session.args())
+        .map_err(crate::session::Error::action)?;
+    let config = Config::create(temp_dir, &args, len)
+        .map_err(crate::session::Error::action)?;
+
+    let mut command = std::process::Command::new(&session.args().curl_path);
+    command
+        .arg("--config").arg(&config.path)
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--proto").arg("=http,https")
+        .arg("--connect-timeout").arg(CONNECT_TIMEOUT.as_secs().to_string())
+        .arg("--max-time").arg(args.timeout.as_secs().max(1).to_string())
+        .arg("--upload-file").arg("-")
+        .arg("--output").arg(if cfg!(target_family = "windows") { "NUL" } else { "/dev/null" })
+        .arg("--write-out").arg("%{http_code}")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    if args.method == Method::Post {
+        command.arg("--request").arg("POST");
+    }
+
+    let mut child = command.spawn()
+        .map_err(crate::session::Error::action)?;
+
+    let mut stdin = child.stdin.take()
+        .expect("no stdin of the child process");
+
+    let (sha256, size) = match stream(session, &mut file, len, args.chunk_len, &mut stdin) {
+        Ok(streamed) => streamed,
+        Err(error) => {
+            // The upload cannot be completed, so there is no point in letting
+            // `curl` wait for the rest of the contents.
+            if let Err(error) = child.kill() {
+                log::error!("failed to kill the upload process: {error}");
+            }
+            let _ = child.wait();
+
+            return Err(error);
+        }
+    };
+    drop(stdin);
+
+    let output = child.wait_with_output()
+        .map_err(crate::session::Error::action)?;
+
+    // `curl` reports `000` if it did not get any response from the server.
+    let status_code = String::from_utf8_lossy(&output.stdout).trim().parse::<u32>()
+        .ok()
+        .filter(|status_code| *status_code != 0);
+
+    if !output.status.success() {
+        return Err(crate::session::Error::action(UploadError {
+            status_code,
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }));
+    }
+
+    let status_code = match status_code {
+        Some(status_code @ 200..=299) => status_code,
+        _ => return Err(crate::session::Error::action(UploadError {
+            status_code,
+            message: String::from("unexpected response status"),
+        })),
+    };
+
+    // We told `curl` the length up front, so it should never succeed if we
+    // sent a different number of bytes, but we verify just to be sure.
+    if size != len {
+        return Err(crate::session::Error::action(UploadError {
+            status_code: Some(status_code),
+            message: format!("file changed during upload ({size} of {len} bytes sent)"),
+        }));
+    }
+
+    log::info! {
+        "uploaded '{}' ({size} bytes) with status {status_code}",
+        args.path.display(),
+    };
+
+    session.reply(Item {
+        path: args.path,
+        size,
+        sha256,
+        status_code,
+    })?;
+
+    Ok(())
+}
+
+/// Streams at most `len` bytes of the `file` to `sink` and returns their digest.
+///
+/// If the `sink` is closed by the other side (e.g. because `curl` failed), the
+/// streaming stops early and the digest of bytes sent so far is returned.
+///
+/// If `chunk_len` is specified, digests of consecutive chunks of this length
+/// are computed along the way and sent as replies as soon as the chunks are
+/// streamed.
+fn stream<S, W>(
+    session: &mut S,
+    file: &mut std::fs::File,
+    len: u64,
+    chunk_len: Option<u64>,
+    sink: &mut W,
+) -> crate::session::Result<([u8; 32], u64)>
+where
+    S: crate::session::Session,
+    W: std::io::Write,
+{
+    use std::io::Read as _;
+
+    use sha2::Digest as _;
+
+    let mut file = file.take(len);
+
+    let mut sha256 = sha2::Sha256::new();
+    let mut size = 0;
+
+    let mut chunk_sha256 = sha2::Sha256::new();
+    let mut chunk_offset = 0;
+    let mut chunk_size = 0;
+
+    let mut buf = vec![0; BUF_LEN];
+    loop {
+        session.check_deadline()?;
+
+        let len_read = match file.read(&mut buf[..]) {
+            Ok(len_read) => len_read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(crate::session::Error::action(error)),
+        };
+
+        if len_read == 0 {
+            break;
+        }
+        session.record_bytes_read(len_read as u64);
+        session.throttle(len_read as u64)?;
+
+        match sink.write_all(&buf[..len_read]) {
+            Ok(()) => (),
+            Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(error) => return Err(crate::session::Error::action(error)),
+        }
+
+        sha256.update(&buf[..len_read]);
+        size += len_read as u64;
+
+        if let Some(chunk_len) = chunk_len {
+            // A single read can span multiple chunks (if they are shorter than
+            // the buffer), so we feed the chunk hasher piece by piece.
+            let mut data = &buf[..len_read];
+            while !data.is_empty() {
+                let head_len = std::cmp::min(chunk_len - chunk_size, data.len() as u64);
+                let (head, tail) = data.split_at(head_len as usize);
+                chunk_sha256.update(head);
+                chunk_size += head_len;
+                data = tail;
+
+                if chunk_size == chunk_len {
+                    session.reply(ChunkItem {
+                        offset: chunk_offset,
+                        len: chunk_size,
+                        sha256: chunk_sha256.finalize_reset().into(),
+                    })?;
+                    chunk_offset += chunk_size;
+                    chunk_size = 0;
+                }
+            }
+        }
+    }
+
+    // The last chunk is usually shorter than the others (and is reported only
+    // if it is not empty).
+    if chunk_size > 0 {
+        session.reply(ChunkItem {
+            offset: chunk_offset,
+            len: chunk_size,
+            sha256: chunk_sha256.finalize().into(),
+        })?;
+    }
+
+    Ok((sha256.finalize().into(), size))
+}
+
+/// Checks whether the given path is a configuration file of an ongoing upload.
+///
+/// Configuration files are named after the process that created them, so the
+/// ones left behind by previous runs of the agent are not considered ongoing.
+pub(crate) fn is_in_flight_config(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    let pid = match name.strip_prefix("rrg-upload-").and_then(|name| name.split_once('-')) {
+        Some((pid, rest)) if rest.ends_with(".curlrc") => pid,
+        _ => return false,
+    };
+
+    pid.parse::<u32>() == Ok(std::process::id())
+}
+
+/// Private configuration file of `curl` with the URL and headers of an upload.
+///
+/// The file is removed once the configuration goes out of scope.
+struct Config {
+    /// Path to the configuration file.
+    path: PathBuf,
+}
+
+impl Config {
+
+    /// Creates a configuration file for the upload in the given directory.
+    fn create(temp_dir: &Path, args: &Args, len: u64) -> std::io::Result<Config> {
+        use std::io::Write as _;
+
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = temp_dir.join(format! {
+            "rrg-upload-{}-{}.curlrc",
+            std::process::id(),
+            time.as_nanos(),
+        });
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&path)?;
+        let config = Config {
+            path,
+        };
+
+        // By default `curl` uses chunked encoding for contents read from the
+        // standard input (which many storage services do not support), so we
+        // specify the length explicitly and disable the encoding. We also drop
+        // the `Expect` header that just delays uploads to servers that do not
+        // support it.
+        let mut contents = format!("url = {}\n", quote(&args.url));
+        contents.push_str(&format!("header = {}\n", quote(&format!("Content-Length: {len}"))));
+        contents.push_str(&format!("header = {}\n", quote("Transfer-Encoding:")));
+        contents.push_str(&format!("header = {}\n", quote("Expect:")));
+        for (name, value) in &args.headers {
+            contents.push_str(&format!("header = {}\n", quote(&format!("{name}: {value}"))));
+        }
+
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(config)
+    }
+}
+
+impl Drop for Config {
+
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_file(&self.path) {
+            log::error! {
+                "failed to remove upload configuration '{}': {error}",
+                self.path.display(),
+            };
+        }
+    }
+}
+
+/// Quotes the given string as a value of a `curl` configuration file.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for char in string.chars() {
+        if char == '"' || char == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(char);
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// An error type for failed uploads.
+#[derive(Debug)]
+struct UploadError {
+    /// HTTP status code of the response (if the server responded).
+    status_code: Option<u32>,
+    /// Description of the failure.
+    message: String,
+}
+
+impl std::fmt::Display for UploadError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.status_code {
+            Some(status_code) => {
+                write!(fmt, "upload failed with status {status_code}: {}", self.message)
+            }
+            None => write!(fmt, "upload failed: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::upload_file::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+        use rrg_proto::upload_file::Method as ProtoMethod;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        let url = proto.take_url();
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        if !matches!(scheme.as_deref(), Some("http" | "https")) || url.contains(['\r', '\n']) {
+            let error = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "only http and https URLs are supported",
+            );
+            return Err(ParseArgsError::invalid_field("url", error));
+        }
+
+        let method = match proto.method.enum_value() {
+            Ok(ProtoMethod::PUT) => Method::Put,
+            Ok(ProtoMethod::POST) => Method::Post,
+            Err(value) => {
+                let error = std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unknown method: {value}"),
+                );
+                return Err(ParseArgsError::invalid_field("method", error));
+            }
+        };
+
+        let headers = proto.take_headers().into_iter()
+            .map(|mut header| {
+                let name = header.take_name();
+                let value = header.take_value();
+
+                if name.is_empty() || name.contains([':', '\r', '\n']) || value.contains(['\r', '\n']) {
+                    let error = std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("malformed header '{name}'"),
+                    );
+                    return Err(ParseArgsError::invalid_field("headers", error));
+                }
+
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let timeout = if proto.has_timeout() {
+            rrg_proto::try_from_duration(proto.take_timeout())
+                .map_err(|error| ParseArgsError::invalid_field("timeout", error))?
+        } else {
+            DEFAULT_TIMEOUT
+        };
+
+        let chunk_len = match proto.chunk_length() {
+            0 => None,
+            chunk_len => Some(chunk_len),
+        };
+
+        Ok(Args {
+            path,
+            url,
+            method,
+            headers,
+            timeout,
+            chunk_len,
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::upload_file::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_size(self.size);
+        proto.set_sha256(self.sha256.to_vec());
+        proto.set_status_code(self.status_code);
+
+        proto
+    }
+}
+
+impl crate::response::Item for ChunkItem {
+
+    type Proto = rrg_proto::upload_file::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto_chunk = rrg_proto::upload_file::Chunk::default();
+        proto_chunk.set_offset(self.offset);
+        proto_chunk.set_length(self.len);
+        proto_chunk.set_sha256(self.sha256.to_vec());
+
+        let mut proto = Self::Proto::default();
+        proto.set_chunk(proto_chunk);
+
+        proto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Request received by the mock server.
+    struct Request {
+        /// Request line and headers.
+        head: String,
+        /// Body of the request.
+        body: Vec<u8>,
+    }
+
+    /// Starts a mock HTTP server responding to a single request with `status`.
+    ///
+    /// Returns the URL of the server and a handle yielding the received request.
+    fn serve_once(status: &'static str) -> (String, std::thread::JoinHandle<Request>) {
+        use std::io::{Read as _, Write as _};
+
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept()
+                .unwrap();
+
+            let mut data = Vec::new();
+            let mut buf = [0; 4096];
+            let head_len = loop {
+                if let Some(pos) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+
+                let len = stream.read(&mut buf).unwrap();
+                assert!(len > 0, "connection closed before the end of headers");
+                data.extend_from_slice(&buf[..len]);
+            };
+
+            let head = String::from_utf8(data[..head_len].to_vec())
+                .unwrap();
+            let content_len = head.lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().unwrap())
+                })
+                .expect("no content length");
+
+            let mut body = data[head_len..].to_vec();
+            while body.len() < content_len {
+                let len = stream.read(&mut buf).unwrap();
+                assert!(len > 0, "connection closed before the end of body");
+                body.extend_from_slice(&buf[..len]);
+            }
+
+            write!(stream, "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            Request {
+                head,
+                body,
+            }
+        });
+
+        (url, handle)
+    }
+
+    /// Checks whether `curl` is available (not every test environment has it).
+    fn has_curl() -> bool {
+        std::process::Command::new(crate::args::Args::default().curl_path).arg("--version")
+            .stdout(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn session(tempdir: &Path) -> crate::session::FakeSession {
+        crate::session::FakeSession::with_args(crate::args::Args {
+            temp_dir: tempdir.to_path_buf(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn handle_put() {
+        use sha2::Digest as _;
+
+        if !has_curl() {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        // We use contents spanning multiple buffers and with null bytes.
+        let contents = (0..3 * BUF_LEN + 1337)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, &contents)
+            .unwrap();
+
+        let (url, server) = serve_once("200 OK");
+
+        let args = Args {
+            path: path.clone(),
+            url,
+            method: Method::Put,
+            headers: vec![(String::from("X-Foo"), String::from("bar \"baz\""))],
+            timeout: Duration::from_secs(30),
+            chunk_len: None,
+        };
+
+        let mut session = session(tempdir.path());
+        assert!(handle(&mut session, args).is_ok());
+
+        let request = server.join()
+            .unwrap();
+        assert!(request.head.starts_with("PUT /upload "));
+        assert!(request.head.contains("X-Foo: bar \"baz\"\r\n"));
+        assert!(!request.head.to_ascii_lowercase().contains("transfer-encoding"));
+        assert_eq!(request.body, contents);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, path);
+        assert_eq!(item.size, contents.len() as u64);
+        assert_eq!(item.sha256, <[u8; 32]>::from(sha2::Sha256::digest(&contents)));
+        assert_eq!(item.status_code, 200);
+
+        // The configuration with the URL must not be left behind.
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn handle_chunks() {
+        use sha2::Digest as _;
+
+        if !has_curl() {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        // Chunks are not aligned with the buffer, so some reads span multiple
+        // chunks and some chunks span multiple reads.
+        let contents = (0..3 * BUF_LEN + 1337)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, &contents)
+            .unwrap();
+
+        let (url, server) = serve_once("200 OK");
+
+        let args = Args {
+            path,
+            url,
+            method: Method::Put,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+            chunk_len: Some(50_000),
+        };
+
+        let mut session = session(tempdir.path());
+        assert!(handle(&mut session, args).is_ok());
+
+        let request = server.join()
+            .unwrap();
+        assert_eq!(request.body, contents);
+
+        let chunk_count = contents.len().div_ceil(50_000);
+        assert_eq!(session.reply_count(), chunk_count + 1);
+
+        let mut offset = 0;
+        for chunk in session.replies_of::<ChunkItem>() {
+            let chunk_contents = &contents[offset..std::cmp::min(offset + 50_000, contents.len())];
+
+            assert_eq!(chunk.offset, offset as u64);
+            assert_eq!(chunk.len, chunk_contents.len() as u64);
+            assert_eq!(chunk.sha256, <[u8; 32]>::from(sha2::Sha256::digest(chunk_contents)));
+
+            offset += chunk_contents.len();
+        }
+        assert_eq!(offset, contents.len());
+
+        let item = session.replies_of::<Item>().next()
+            .unwrap();
+        assert_eq!(item.size, contents.len() as u64);
+        assert_eq!(item.sha256, <[u8; 32]>::from(sha2::Sha256::digest(&contents)));
+    }
+
+    #[test]
+    fn stream_chunks_small() {
+        use sha2::Digest as _;
+
+        let mut tempfile = tempfile::tempfile()
+            .unwrap();
+        std::io::Write::write_all(&mut tempfile, b"foobarbaz")
+            .unwrap();
+        std::io::Seek::rewind(&mut tempfile)
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let mut sink = Vec::new();
+
+        let (sha256, size) = stream(&mut session, &mut tempfile, 9, Some(3), &mut sink)
+            .unwrap();
+        assert_eq!(sink, b"foobarbaz");
+        assert_eq!(size, 9);
+        assert_eq!(sha256, <[u8; 32]>::from(sha2::Sha256::digest(b"foobarbaz")));
+
+        let chunks = session.replies::<ChunkItem>()
+            .map(|chunk| (chunk.offset, chunk.len, chunk.sha256))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, vec![
+            (0, 3, <[u8; 32]>::from(sha2::Sha256::digest(b"foo"))),
+            (3, 3, <[u8; 32]>::from(sha2::Sha256::digest(b"bar"))),
+            (6, 3, <[u8; 32]>::from(sha2::Sha256::digest(b"baz"))),
+        ]);
+    }
+
+    #[test]
+    fn handle_post() {
+        if !has_curl() {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foobar")
+            .unwrap();
+
+        let (url, server) = serve_once("201 Created");
+
+        let args = Args {
+            path,
+            url,
+            method: Method::Post,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+            chunk_len: None,
+        };
+
+        let mut session = session(tempdir.path());
+        assert!(handle(&mut session, args).is_ok());
+
+        let request = server.join()
+            .unwrap();
+        assert!(request.head.starts_with("POST /upload "));
+        assert_eq!(request.body, b"foobar");
+
+        assert_eq!(session.reply::<Item>(0).status_code, 201);
+    }
+
+    #[test]
+    fn handle_server_error() {
+        if !has_curl() {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foobar")
+            .unwrap();
+
+        let (url, server) = serve_once("403 Forbidden");
+
+        let args = Args {
+            path,
+            url,
+            method: Method::Put,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+            chunk_len: None,
+        };
+
+        let mut session = session(tempdir.path());
+        let error = handle(&mut session, args)
+            .unwrap_err();
+        assert!(error.to_string().contains("403"));
+        assert_eq!(session.reply_count(), 0);
+
+        server.join()
+            .unwrap();
+    }
+
+    #[test]
+    fn handle_connection_refused() {
+        if !has_curl() {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foobar")
+            .unwrap();
+
+        // We bind to a random port and close it right away, so that nothing
+        // listens there.
+        let addr = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let args = Args {
+            path,
+            url: format!("http://{addr}/upload"),
+            method: Method::Put,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+            chunk_len: None,
+        };
+
+        let mut session = session(tempdir.path());
+        assert!(handle(&mut session, args).is_err());
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn args_unsupported_scheme() {
+        use crate::request::Args as _;
+
+        let mut proto = rrg_proto::upload_file::Args::default();
+        proto.set_path(PathBuf::from("/foo").into());
+        proto.set_url(String::from("file:///etc/shadow"));
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[test]
+    fn args_malformed_header() {
+        use crate::request::Args as _;
+
+        let mut header = rrg_proto::upload_file::Header::default();
+        header.set_name(String::from("X-Foo"));
+        header.set_value(String::from("bar\r\nX-Injected: baz"));
+
+        let mut proto = rrg_proto::upload_file::Args::default();
+        proto.set_path(PathBuf::from("/foo").into());
+        proto.set_url(String::from("https://example.com/upload"));
+        proto.mut_headers().push(header);
+
+        assert!(Args::from_proto(proto).is_err());
+    }
+
+    #[test]
+    fn quote_escapes() {
+        assert_eq!(quote("foo"), "\"foo\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[test]
+    fn check_curl_relative() {
+        assert!(check_curl(Path::new("curl")).is_err());
+    }
+
+    #[test]
+    fn check_curl_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        assert!(check_curl(tempdir.path()).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn check_curl_not_executable() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("curl");
+        std::fs::write(&path, b"")
+            .unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))
+            .unwrap();
+        assert!(check_curl(&path).is_err());
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        assert!(check_curl(&path).is_ok());
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Watching of directories for changes of their entries.
+//!
+//! This is meant for live triage: the action watches a directory for a short,
+//! bounded time and streams every change (creation, modification, deletion and
+//! so on) of its entries as a separate reply. Changes are observed through
+//! inotify, so this action is available only on Linux.
+//!
+//! The kernel queue of events is bounded. If it overflows, the overflow is
+//! reported as an event, so that the server knows that some changes are
+//! missing.
+
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+/// Time for which directories are watched if not specified in the request.
+#[cfg(target_os = "linux")]
+const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+
+/// Maximum time for which directories can be watched.
+#[cfg(target_os = "linux")]
+const MAX_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum time to wait for events before checking for cancellation.
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Arguments of the `watch_directory` action.
+#[cfg(target_os = "linux")]
+pub struct Args {
+    /// Path to the directory to watch.
+    path: PathBuf,
+    /// Time for which the directory should be watched.
+    duration: Duration,
+}
+
+/// Result of the `watch_directory` action.
+#[cfg(target_os = "linux")]
+struct Item {
+    /// Type of the change.
+    kind: EventKind,
+    /// Path to the entry that changed (or to the watched directory).
+    path: PathBuf,
+    /// Whether the entry that changed is a directory.
+    is_dir: bool,
+    /// Cookie associating the two halves of a rename.
+    cookie: u32,
+    /// Time at which the change was observed.
+    time: std::time::SystemTime,
+}
+
+/// Type of a change of a directory entry.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EventKind {
+    Create,
+    Modify,
+    Attrib,
+    Delete,
+    MovedFrom,
+    MovedTo,
+    Overflow,
+    WatchGone,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::WatchDirectory;
+
+/// Handles invocations of the `watch_directory` action.
+#[cfg(not(target_os = "linux"))]
+pub fn handle<S>(_: &mut S, _: ()) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::{Error, ErrorKind};
+    Err(crate::session::Error::action(Error::from(ErrorKind::Unsupported)))
+}
+
+/// Handles invocations of the `watch_directory` action.
+#[cfg(target_os = "linux")]
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    if args.path.is_relative() {
+        use std::io::{Error, ErrorKind};
+
+        let error = Error::new(ErrorKind::InvalidInput, "relative path");
+        return Err(crate::session::Error::action(error));
+    }
+
+    let watch = ospect::fs::linux::DirWatch::new(&args.path)
+        .map_err(crate::session::Error::action)?;
+
+    let watch_end = std::time::Instant::now() + args.duration;
+    loop {
+        // Deadline of the request is how the server cancels the watch, so we
+        // check it regularly even if there are no events at all.
+        session.check_deadline()?;
+        session.heartbeat();
+
+        let remaining = watch_end.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+
+        let events = watch.read(std::cmp::min(remaining, POLL_INTERVAL))
+            .map_err(crate::session::Error::action)?;
+
+        let time = std::time::SystemTime::now();
+        for event in events {
+            let Some(kind) = EventKind::of(&event) else {
+                log::debug!("unexpected inotify event: {event:?}");
+                continue;
+            };
+
+            if kind == EventKind::Overflow {
+                log::warn!("inotify queue overflow for '{}'", args.path.display());
+            }
+
+            let path = match &event.name {
+                Some(name) => args.path.join(name),
+                None => args.path.clone(),
+            };
+
+            session.reply(Item {
+                kind,
+                path,
+                is_dir: event.is_dir(),
+                cookie: event.cookie,
+                time,
+            })?;
+
+            // No more events are reported once the directory is gone, so there
+            // is no point in waiting for them.
+            if kind == EventKind::WatchGone {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl EventKind {
+
+    /// Determines the type of change the given event is about.
+    ///
+    /// An overflow is checked first, as such events are not about any entry.
+    fn of(event: &ospect::fs::linux::DirEvent) -> Option<EventKind> {
+        if event.is_overflow() {
+            Some(EventKind::Overflow)
+        } else if event.is_create() {
+            Some(EventKind::Create)
+        } else if event.is_modify() {
+            Some(EventKind::Modify)
+        } else if event.is_attrib() {
+            Some(EventKind::Attrib)
+        } else if event.is_delete() {
+            Some(EventKind::Delete)
+        } else if event.is_moved_from() {
+            Some(EventKind::MovedFrom)
+        } else if event.is_moved_to() {
+            Some(EventKind::MovedTo)
+        } else if event.is_watch_gone() {
+            Some(EventKind::WatchGone)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::watch_directory::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        let duration = if proto.has_duration() {
+            rrg_proto::try_from_duration(proto.take_duration())
+                .map_err(|error| ParseArgsError::invalid_field("duration", error))?
+        } else {
+            DEFAULT_DURATION
+        };
+
+        Ok(Args {
+            path,
+            duration: std::cmp::min(duration, MAX_DURATION),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::watch_directory::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        use rrg_proto::watch_directory::EventType;
+
+        let mut proto = Self::Proto::default();
+        proto.set_type(match self.kind {
+            EventKind::Create => EventType::CREATE,
+            EventKind::Modify => EventType::MODIFY,
+            EventKind::Attrib => EventType::ATTRIB,
+            EventKind::Delete => EventType::DELETE,
+            EventKind::MovedFrom => EventType::MOVED_FROM,
+            EventKind::MovedTo => EventType::MOVED_TO,
+            EventKind::Overflow => EventType::OVERFLOW,
+            EventKind::WatchGone => EventType::WATCH_GONE,
+        });
+        proto.set_path(self.path.into());
+        proto.set_is_dir(self.is_dir);
+        proto.set_cookie(self.cookie);
+        proto.set_time(rrg_proto::into_timestamp(self.time));
+
+        proto
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn handle_create() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir_path = tempdir.path().to_path_buf();
+
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            std::fs::write(tempdir_path.join("foo"), b"")
+                .unwrap();
+        });
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            duration: Duration::from_secs(2),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        writer.join()
+            .unwrap();
+
+        let item = session.replies::<Item>()
+            .find(|item| item.kind == EventKind::Create)
+            .unwrap();
+        assert_eq!(item.path, tempdir.path().join("foo"));
+        assert!(!item.is_dir);
+    }
+
+    #[test]
+    fn handle_duration() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().to_path_buf(),
+            duration: Duration::from_millis(100),
+        };
+
+        let start = std::time::Instant::now();
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_watch_gone() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let path = tempdir.path().join("foo");
+        std::fs::create_dir(&path)
+            .unwrap();
+
+        let remover = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(100));
+                std::fs::remove_dir(path)
+                    .unwrap();
+            }
+        });
+
+        let args = Args {
+            path: path.clone(),
+            // The watch should stop as soon as the directory is gone, so the
+            // duration is long enough for the test to time out otherwise.
+            duration: MAX_DURATION,
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_ok());
+
+        remover.join()
+            .unwrap();
+
+        let item = session.replies::<Item>().last()
+            .unwrap();
+        assert_eq!(item.kind, EventKind::WatchGone);
+        assert_eq!(item.path, path);
+    }
+
+    #[test]
+    fn handle_not_dir() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        let args = Args {
+            path: tempfile.path().to_path_buf(),
+            duration: Duration::from_millis(100),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+
+    #[test]
+    fn handle_relative() {
+        let args = Args {
+            path: PathBuf::from("foo"),
+            duration: Duration::from_millis(100),
+        };
+
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle(&mut session, args).is_err());
+    }
+}
@@ -0,0 +1,541 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Writing files to the filesystem.
+//!
+//! Because writing arbitrary files is dangerous, this action is disabled unless
+//! explicitly allowed and writes are permitted only under directories that the
+//! agent was configured with (through the `--write-file-root` flag).
+//!
+//! Requests can be issued in dry-run mode, in which all the checks are done but
+//! nothing is written. This allows to verify that a flow will work as expected
+//! before letting it perform any changes.
+
+use std::path::{Path, PathBuf};
+
+/// Default permission bits of the written files.
+#[cfg(target_family = "unix")]
+const DEFAULT_MODE: u32 = 0o600;
+
+/// Arguments of the `write_file` action.
+pub struct Args {
+    /// Path to the file to write.
+    path: PathBuf,
+    /// Contents to write to the file.
+    contents: Vec<u8>,
+    /// Permission bits of the file.
+    #[cfg_attr(not(target_family = "unix"), allow(dead_code))]
+    mode: u32,
+    /// Whether to replace the file if it already exists.
+    overwrite: bool,
+    /// Whether to only validate the arguments without writing anything.
+    dry_run: bool,
+}
+
+/// Result of the `write_file` action.
+struct Item {
+    /// Canonical path to the written file.
+    path: PathBuf,
+    /// Number of bytes written to the file.
+    len: usize,
+    /// Whether nothing was actually written.
+    dry_run: bool,
+}
+
+/// Action implemented by this module.
+pub const ACTION: crate::request::Action = crate::request::Action::WriteFile;
+
+/// Handles invocations of the `write_file` action.
+pub fn handle<S>(session: &mut S, args: Args) -> crate::session::Result<()>
+where
+    S: crate::session::Session,
+{
+    use std::io::Write as _;
+
+    let path = resolve(&args.path, &session.args().write_file_roots)
+        .map_err(crate::session::Error::action)?;
+
+    check_target(&path, args.overwrite)
+        .map_err(crate::session::Error::action)?;
+
+    if args.dry_run {
+        session.reply(Item {
+            path,
+            len: args.contents.len(),
+            dry_run: true,
+        })?;
+
+        return Ok(());
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if args.overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        // We do not want to follow symlinks as they could point outside of the
+        // allowed roots.
+        options.mode(args.mode);
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+
+    let mut file = options.open(&path)
+        .map_err(crate::session::Error::action)?;
+
+    // If the file already existed, the mode passed to `open` had no effect.
+    #[cfg(target_family = "unix")]
+    if args.overwrite {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let permissions = std::fs::Permissions::from_mode(args.mode);
+        file.set_permissions(permissions)
+            .map_err(crate::session::Error::action)?;
+    }
+
+    file.write_all(&args.contents)
+        .map_err(crate::session::Error::action)?;
+    file.sync_all()
+        .map_err(crate::session::Error::action)?;
+
+    session.reply(Item {
+        path,
+        len: args.contents.len(),
+        dry_run: false,
+    })?;
+
+    Ok(())
+}
+
+/// Resolves the path of the file to write, verifying it is under allowed roots.
+///
+/// The parent of the path is canonicalized, so that it can be reliably compared
+/// against the (canonicalized) roots even if it contains `..` components or
+/// symlinks.
+fn resolve(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, ResolveError> {
+    if path.is_relative() {
+        return Err(ResolveError::Relative);
+    }
+
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return Err(ResolveError::NoFileName),
+    };
+
+    let parent = path.parent()
+        .ok_or(ResolveError::NoFileName)?
+        .canonicalize()
+        .map_err(ResolveError::Parent)?;
+
+    let path = parent.join(file_name);
+
+    let is_allowed = roots.iter()
+        .filter_map(|root| match root.canonicalize() {
+            Ok(root) => Some(root),
+            Err(error) => {
+                log::warn!("failed to canonicalize write root '{}': {error}", root.display());
+                None
+            }
+        })
+        .any(|root| path.starts_with(root));
+
+    if !is_allowed {
+        return Err(ResolveError::NotAllowed(path));
+    }
+
+    Ok(path)
+}
+
+/// Verifies that the file at the (resolved) path can be written.
+///
+/// Note that this check is inherently racy and the actual write does not rely
+/// on it: the file is opened in a way that fails if the conditions change. It
+/// is there so that dry runs can report the same failures as real writes.
+fn check_target(path: &Path, overwrite: bool) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    if !overwrite {
+        return Err(Error::new(ErrorKind::AlreadyExists, "file already exists"));
+    }
+    if metadata.is_symlink() {
+        return Err(Error::new(ErrorKind::InvalidInput, "file is a symlink"));
+    }
+    if !metadata.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a regular file"));
+    }
+
+    Ok(())
+}
+
+impl crate::request::Args for Args {
+
+    type Proto = rrg_proto::write_file::Args;
+
+    fn from_proto(mut proto: Self::Proto) -> Result<Args, crate::request::ParseArgsError> {
+        use crate::request::ParseArgsError;
+
+        let path = PathBuf::try_from(proto.take_path())
+            .map_err(|error| ParseArgsError::invalid_field("path", error))?;
+
+        #[cfg(target_family = "unix")]
+        let mode = match proto.mode() {
+            0 => DEFAULT_MODE,
+            mode => mode,
+        };
+
+        #[cfg(not(target_family = "unix"))]
+        let mode = proto.mode();
+
+        Ok(Args {
+            path,
+            contents: proto.take_contents(),
+            mode,
+            overwrite: proto.overwrite(),
+            dry_run: proto.dry_run(),
+        })
+    }
+}
+
+impl crate::response::Item for Item {
+
+    type Proto = rrg_proto::write_file::Result;
+
+    fn into_proto(self) -> Self::Proto {
+        let mut proto = Self::Proto::default();
+        proto.set_path(self.path.into());
+        proto.set_length(self.len as u64);
+        proto.set_dry_run(self.dry_run);
+
+        proto
+    }
+}
+
+/// An error indicating that the path to write to cannot be used.
+#[derive(Debug)]
+enum ResolveError {
+    /// The path is not absolute.
+    Relative,
+    /// The path does not point to any file (e.g. it is a root).
+    NoFileName,
+    /// The parent directory of the path could not be resolved.
+    Parent(std::io::Error),
+    /// The path is not under any of the allowed roots.
+    NotAllowed(PathBuf),
+}
+
+impl std::fmt::Display for ResolveError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ResolveError::*;
+
+        match self {
+            Relative => write!(fmt, "relative path"),
+            NoFileName => write!(fmt, "path without a file name"),
+            Parent(error) => write!(fmt, "failed to resolve parent: {error}"),
+            NotAllowed(path) => {
+                write!(fmt, "'{}' is outside of allowed roots", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResolveError::Parent(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn session_with_roots(roots: Vec<PathBuf>) -> crate::session::FakeSession {
+        let args = crate::args::Args {
+            write_file_roots: roots,
+            ..Default::default()
+        };
+
+        crate::session::FakeSession::with_args(args)
+    }
+
+    #[test]
+    fn handle_ok() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o640,
+            overwrite: false,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.clone()]);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("foo"));
+        assert_eq!(item.len, 3);
+
+        assert_eq!(std::fs::read(tempdir.join("foo")).unwrap(), b"bar");
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let metadata = std::fs::metadata(tempdir.join("foo")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+    }
+
+    #[test]
+    fn handle_existing_without_overwrite() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"bar")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"baz".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(std::fs::read(tempdir.path().join("foo")).unwrap(), b"bar");
+    }
+
+    #[test]
+    fn handle_existing_with_overwrite() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"bar")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"quux".to_vec(),
+            mode: 0o600,
+            overwrite: true,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(std::fs::read(tempdir.path().join("foo")).unwrap(), b"quux");
+    }
+
+    #[test]
+    fn handle_outside_roots() {
+        let root = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![root.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert!(!tempdir.path().join("foo").exists());
+    }
+
+    #[test]
+    fn handle_outside_roots_parent_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::create_dir(tempdir.path().join("root"))
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("root").join("..").join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.path().join("root")]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert!(!tempdir.path().join("foo").exists());
+    }
+
+    #[test]
+    fn handle_no_roots() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert!(!tempdir.path().join("foo").exists());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn handle_symlink_outside_roots() {
+        let root = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"bar")
+            .unwrap();
+        std::os::unix::fs::symlink(tempdir.path().join("foo"), root.path().join("foo"))
+            .unwrap();
+
+        let args = Args {
+            path: root.path().join("foo"),
+            contents: b"quux".to_vec(),
+            mode: 0o600,
+            overwrite: true,
+            dry_run: false,
+        };
+
+        let mut session = session_with_roots(vec![root.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(std::fs::read(tempdir.path().join("foo")).unwrap(), b"bar");
+    }
+
+    #[test]
+    fn handle_dry_run() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: true,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.clone()]);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert_eq!(session.reply_count(), 1);
+
+        let item = session.reply::<Item>(0);
+        assert_eq!(item.path, tempdir.join("foo"));
+        assert_eq!(item.len, 3);
+        assert!(item.dry_run);
+
+        assert!(!tempdir.join("foo").exists());
+    }
+
+    #[test]
+    fn handle_dry_run_existing_with_overwrite() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"bar")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"quux".to_vec(),
+            mode: 0o600,
+            overwrite: true,
+            dry_run: true,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_ok());
+
+        assert!(session.reply::<Item>(0).dry_run);
+        assert_eq!(std::fs::read(tempdir.path().join("foo")).unwrap(), b"bar");
+    }
+
+    #[test]
+    fn handle_dry_run_existing_without_overwrite() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"bar")
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"baz".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: true,
+        };
+
+        let mut session = session_with_roots(vec![tempdir.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+
+    #[test]
+    fn handle_dry_run_outside_roots() {
+        let root = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let args = Args {
+            path: tempdir.path().join("foo"),
+            contents: b"bar".to_vec(),
+            mode: 0o600,
+            overwrite: false,
+            dry_run: true,
+        };
+
+        let mut session = session_with_roots(vec![root.path().to_path_buf()]);
+        assert!(handle(&mut session, args).is_err());
+
+        assert_eq!(session.reply_count(), 0);
+    }
+}
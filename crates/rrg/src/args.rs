@@ -49,6 +49,449 @@ pub struct Args {
            arg_name="PATH",
            description="whether to log to a file")]
     pub log_to_file: Option<std::path::PathBuf>,
+
+    /// Actions that the agent is allowed to execute.
+    ///
+    /// If empty, all actions that the agent was compiled with are allowed with
+    /// the exception of actions that modify the system (e.g. `write_file`). To
+    /// enable them, they have to be explicitly put on the list.
+    #[argh(option,
+           long="allow-action",
+           arg_name="ACTION",
+           description="action that is allowed to be executed (repeatable)",
+           from_str_fn(parse_action))]
+    pub allowed_actions: Vec<crate::request::Action>,
+
+    /// Actions that the agent is not allowed to execute.
+    #[argh(option,
+           long="deny-action",
+           arg_name="ACTION",
+           description="action that is not allowed to be executed (repeatable)",
+           from_str_fn(parse_action))]
+    pub denied_actions: Vec<crate::request::Action>,
+
+    /// Maximum time an action is allowed to run for.
+    ///
+    /// This applies regardless of the limits specified by the server (and the
+    /// stricter of the two wins). Actions are cancelled cooperatively: once the
+    /// timeout passes, the next check of session limits made by the action
+    /// fails and the action is abandoned with a timeout status.
+    #[argh(option,
+           long="action-timeout",
+           arg_name="DURATION",
+           description="maximum time an action is allowed to run for",
+           from_str_fn(parse_duration))]
+    pub action_timeout: Option<Duration>,
+
+    /// Timeouts of specific actions overriding the default action timeout.
+    #[argh(option,
+           long="action-timeout-override",
+           arg_name="ACTION=DURATION",
+           description="timeout of a specific action (repeatable)",
+           from_str_fn(parse_action_timeout))]
+    pub action_timeout_overrides: Vec<(crate::request::Action, Duration)>,
+
+    /// Number of threads used for collecting filesystem timelines.
+    ///
+    /// If set to 1, the timeline is collected sequentially by the thread that
+    /// executes the action.
+    #[argh(option,
+           long="timeline-concurrency",
+           arg_name="THREADS",
+           default="1",
+           description="number of threads used for collecting timelines")]
+    pub timeline_concurrency: usize,
+
+    /// Number of actions that can be executed concurrently.
+    ///
+    /// If set to 1, requests are handled one by one in the order in which they
+    /// were received. Otherwise, replies of actions executed at the same time
+    /// can interleave (but replies of a single action are always in order).
+    #[argh(option,
+           long="action-concurrency",
+           arg_name="THREADS",
+           default="1",
+           description="number of actions executed concurrently")]
+    pub action_concurrency: usize,
+
+    /// Maximum number of received requests waiting to be executed.
+    ///
+    /// What happens to requests received once the queue is full depends on the
+    /// request queue overflow policy. It has no effect if actions are not
+    /// executed concurrently.
+    #[argh(option,
+           long="request-queue-size",
+           arg_name="COUNT",
+           default="16",
+           description="maximum number of requests waiting to be executed")]
+    pub request_queue_size: usize,
+
+    /// What to do with requests received while the request queue is full.
+    ///
+    /// The agent can either stop collecting new requests until one of the
+    /// queued ones is picked up for execution or immediately reject them with
+    /// a "queue full" status, so that the server can retry them later. Note
+    /// that while the agent is not collecting requests, it does not respond to
+    /// any messages (including control ones).
+    #[argh(option,
+           long="request-queue-overflow",
+           arg_name="POLICY",
+           default="crate::session::OverflowPolicy::Reject",
+           description="handling of requests over the queue size (queue or reject)",
+           from_str_fn(parse_overflow_policy))]
+    pub request_queue_overflow: crate::session::OverflowPolicy,
+
+    /// Maximum numbers of concurrently running instances of specific actions.
+    ///
+    /// Limits of different actions are independent and actions without a limit
+    /// are limited only by the number of actions executed concurrently. This
+    /// protects the system against the server launching many expensive actions
+    /// (e.g. filesystem timelines) at once.
+    #[argh(option,
+           long="action-concurrency-limit",
+           arg_name="ACTION=COUNT",
+           description="maximum number of running instances of an action (repeatable)",
+           from_str_fn(parse_action_concurrency_limit))]
+    pub action_concurrency_limits: Vec<(crate::request::Action, usize)>,
+
+    /// What to do with requests exceeding the concurrency limit of an action.
+    ///
+    /// Requests can either wait (occupying one of the action workers) until one
+    /// of the running instances finishes or fail immediately as busy.
+    #[argh(option,
+           long="action-concurrency-overflow",
+           arg_name="POLICY",
+           default="crate::session::OverflowPolicy::Queue",
+           description="handling of requests over action limits (queue or reject)",
+           from_str_fn(parse_overflow_policy))]
+    pub action_concurrency_overflow: crate::session::OverflowPolicy,
+
+    /// Maximum number of entries read from a single directory.
+    ///
+    /// Actions walking the filesystem (e.g. collecting timelines) stop reading
+    /// directories with more entries than this and report them as truncated.
+    #[argh(option,
+           long="max-dir-entries",
+           arg_name="COUNT",
+           default="1024 * 1024",
+           description="maximum number of entries read from a directory")]
+    pub max_dir_entries: u64,
+
+    /// Maximum number of symlinks followed when resolving a single path.
+    ///
+    /// This applies to all the actions that follow symlinks (e.g. resolving
+    /// paths within image roots or collecting symlink chains) so that deeply
+    /// nested indirection cannot make the agent do excessive work. The default
+    /// is the same as the limit of the Linux kernel.
+    #[argh(option,
+           long="max-symlink-depth",
+           arg_name="COUNT",
+           default="40",
+           description="maximum number of symlinks followed when resolving a path")]
+    pub max_symlink_depth: usize,
+
+    /// Maximum time an operation on a single filesystem entry can take.
+    ///
+    /// Actions walking the filesystem abandon operations (e.g. stat-ing files
+    /// or reading them) on entries that take longer than this and report them
+    /// as timed out, so that a single hung entry (e.g. on an unresponsive
+    /// network mount) does not stall the whole walk. If unset, operations are
+    /// never abandoned.
+    #[argh(option,
+           long="fs-entry-timeout",
+           arg_name="DURATION",
+           description="maximum time an operation on a filesystem entry can take",
+           from_str_fn(parse_duration))]
+    pub fs_entry_timeout: Option<Duration>,
+
+    /// Maximum number of bytes read or sent per second by all actions.
+    ///
+    /// The limit is shared by all the actions (including ones running
+    /// concurrently). If not specified, the throughput is not limited.
+    #[argh(option,
+           long="max-io-rate",
+           arg_name="BYTES",
+           description="maximum number of bytes transferred per second")]
+    pub max_io_rate: Option<std::num::NonZeroU64>,
+
+    /// Maximum number of replies a single action is allowed to send.
+    ///
+    /// Actions attempting to send more replies are stopped and fail with an
+    /// appropriate error. If not specified, the number of replies is not limited.
+    #[argh(option,
+           long="max-replies",
+           arg_name="COUNT",
+           description="maximum number of replies sent by a single action")]
+    pub max_replies: Option<u64>,
+
+    /// Algorithm used for compressing messages sent to the server.
+    ///
+    /// Messages that are tiny or do not compress well are sent uncompressed
+    /// regardless of this setting.
+    #[argh(option,
+           long="compression",
+           arg_name="ALGORITHM",
+           default="crate::transport::Compression::None",
+           description="compression of messages sent to the server (none, gzip or zstd, if enabled)",
+           from_str_fn(parse_compression))]
+    pub compression: crate::transport::Compression,
+
+    /// Maximum size of messages spooled on disk when they cannot be sent.
+    ///
+    /// Messages are spooled in the temporary directory of the agent and sent
+    /// once the connection with the server recovers (or the agent restarts).
+    /// If the spool is full, the oldest messages are dropped. If not specified,
+    /// messages are not spooled at all.
+    #[argh(option,
+           long="spool-max-size",
+           arg_name="BYTES",
+           description="maximum size of messages spooled when they cannot be sent")]
+    pub spool_max_size: Option<u64>,
+
+    /// Path to the file with the secret to encrypt spooled messages with.
+    ///
+    /// The key is derived from the whole contents of the file. Spooled messages
+    /// can contain sensitive data (e.g. contents of files), so encrypting them
+    /// keeps it from being stored on disk as plaintext. If the key changes, the
+    /// messages encrypted with the previous one cannot be replayed and the
+    /// spool is not used until they are removed. If the file cannot be read (or
+    /// the agent is built without encryption support), the agent refuses to
+    /// listen for messages.
+    #[argh(option,
+           long="spool-secret-file",
+           arg_name="PATH",
+           description="path to the file with the secret to encrypt spooled messages with")]
+    pub spool_secret_file: Option<std::path::PathBuf>,
+
+    /// Initial delay before collecting a message again after a failure.
+    ///
+    /// Every consecutive failure doubles the delay (up to the maximum one), so
+    /// that the agent does not spin when the connection is broken. The delay is
+    /// reset once a message is collected successfully.
+    #[argh(option,
+           long="collect-backoff-initial",
+           arg_name="DURATION",
+           default="::std::time::Duration::from_secs(1)",
+           description="initial delay before retrying a failed message collection",
+           from_str_fn(parse_duration))]
+    pub collect_backoff_initial: Duration,
+
+    /// Maximum delay before collecting a message again after a failure.
+    #[argh(option,
+           long="collect-backoff-max",
+           arg_name="DURATION",
+           default="::std::time::Duration::from_secs(5 * 60)",
+           description="maximum delay before retrying a failed message collection",
+           from_str_fn(parse_duration))]
+    pub collect_backoff_max: Duration,
+
+    /// Directories under which the `write_file` action is allowed to write.
+    ///
+    /// If empty, the action refuses to write anywhere.
+    #[argh(option,
+           long="write-file-root",
+           arg_name="PATH",
+           description="directory under which files can be written (repeatable)")]
+    pub write_file_roots: Vec<std::path::PathBuf>,
+
+    /// Directory into which the `quarantine_file` action moves files.
+    ///
+    /// The directory is created when a file is quarantined for the first time
+    /// and is accessible only by the agent. Files can be restored from it only
+    /// by the `restore_quarantined_file` action. If not specified, neither of
+    /// the actions does anything.
+    #[argh(option,
+           long="quarantine-dir",
+           arg_name="PATH",
+           description="directory into which quarantined files are moved")]
+    pub quarantine_dir: Option<std::path::PathBuf>,
+
+    /// Maximum number of replies coalesced into a single Fleetspeak message.
+    ///
+    /// If set to 1 (the default), each reply is sent as a separate message.
+    #[argh(option,
+           long="reply-batch-count",
+           arg_name="COUNT",
+           default="1",
+           description="maximum number of replies sent in a single message")]
+    pub reply_batch_count: usize,
+
+    /// Maximum size of replies coalesced into a single Fleetspeak message.
+    ///
+    /// A batch is sent as soon as it grows beyond this size, so this is a soft
+    /// limit (and a single big reply is always sent on its own).
+    #[argh(option,
+           long="reply-batch-bytes",
+           arg_name="BYTES",
+           default="1024 * 1024",
+           description="maximum size of replies sent in a single message")]
+    pub reply_batch_bytes: usize,
+
+    /// Format in which results are serialized when sent to the server.
+    ///
+    /// Results are packed into Protocol Buffers messages by default. With the
+    /// JSON format they are sent as documents following the canonical proto3
+    /// JSON mapping instead (which some integrations can consume more easily).
+    /// Requests can override this setting.
+    #[argh(option,
+           long="result-serialization",
+           arg_name="FORMAT",
+           default="crate::Serialization::Protobuf",
+           description="serialization of results sent to the server (protobuf or json)",
+           from_str_fn(parse_serialization))]
+    pub result_serialization: crate::Serialization,
+
+    /// Minimum length of chunks sent by the `stream_file_contents` action.
+    ///
+    /// Unless the request specifies a fixed chunk length, chunks start at this
+    /// length and grow as long as sending them succeeds.
+    #[argh(option,
+           long="stream-chunk-min-len",
+           arg_name="BYTES",
+           default="64 * 1024",
+           description="minimum length of chunks of streamed files")]
+    pub stream_chunk_min_len: usize,
+
+    /// Maximum length of chunks sent by the `stream_file_contents` action.
+    ///
+    /// This value is capped to the maximum length of chunks supported by the
+    /// action (2 MiB).
+    #[argh(option,
+           long="stream-chunk-max-len",
+           arg_name="BYTES",
+           default="2 * 1024 * 1024",
+           description="maximum length of chunks of streamed files")]
+    pub stream_chunk_max_len: usize,
+
+    /// Directory for temporary files of the agent.
+    ///
+    /// The directory is created at startup if it does not exist. An existing
+    /// directory has to be owned by the agent and not writable by group or
+    /// others, otherwise features relying on it (e.g. spooling) are disabled.
+    #[argh(option,
+           long="temp-dir",
+           arg_name="PATH",
+           default="default_temp_dir()",
+           description="directory for temporary files of the agent")]
+    pub temp_dir: std::path::PathBuf,
+
+    /// Public key to verify signatures of commands with.
+    ///
+    /// The `execute_signed_command` action runs only commands signed with the
+    /// corresponding private key. If not specified, the action refuses to run
+    /// any command.
+    #[argh(option,
+           long="command-verification-key",
+           arg_name="HEX",
+           description="hex-encoded Ed25519 key to verify commands with",
+           from_str_fn(parse_verification_key))]
+    pub command_verification_key: Option<crate::signed::VerifyingKey>,
+
+    /// Path to the `curl` executable used for uploading files.
+    ///
+    /// The path has to be absolute, so that a different executable cannot be
+    /// picked up from the search path of the agent. The executable is verified
+    /// at startup and, if it is not usable, files cannot be uploaded.
+    #[argh(option,
+           long="curl-path",
+           arg_name="PATH",
+           default="default_curl_path()",
+           description="absolute path to the curl executable for uploading files",
+           from_str_fn(parse_curl_path))]
+    pub curl_path: std::path::PathBuf,
+
+    /// Pattern of names of temporary files created by the agent.
+    ///
+    /// Only files in the temporary directory with names matching the pattern
+    /// are removed when the agent is asked to delete its temporary files. The
+    /// pattern supports the same wildcards as globs (`*`, `?` and `[...]`).
+    #[argh(option,
+           long="temp-file-pattern",
+           arg_name="PATTERN",
+           default="String::from(\"rrg-*\")",
+           description="pattern of names of temporary files of the agent")]
+    pub temp_file_pattern: String,
+
+    /// Whether to preserve access time of files read by actions.
+    ///
+    /// Reading a file normally updates its access time which disturbs the
+    /// evidence on the system. With this option enabled files are opened in a
+    /// way that leaves the access time intact (where the system allows it).
+    /// This is opt-in, as it changes how files are opened.
+    #[argh(option,
+           long="preserve-atime",
+           arg_name="BOOL",
+           default="false",
+           description="whether to preserve access time of read files")]
+    pub preserve_atime: bool,
+
+    /// Public key to verify signatures of requests with.
+    ///
+    /// If specified, only requests signed by the server with the corresponding
+    /// private key are executed. This protects against a compromised channel
+    /// between the server and the agent issuing arbitrary actions.
+    #[argh(option,
+           long="request-verification-key",
+           arg_name="HEX",
+           description="hex-encoded Ed25519 key to verify requests with",
+           from_str_fn(parse_verification_key))]
+    pub request_verification_key: Option<crate::signed::VerifyingKey>,
+
+    /// Maximum age of signed requests and commands.
+    ///
+    /// Signed requests (and commands of the `execute_signed_command` action)
+    /// issued earlier than that are refused, so that requests captured by an
+    /// attacker cannot be replayed at an arbitrary point in the future. Note that requests might spend some time queued in Fleetspeak
+    /// (e.g. when the agent is offline), so this should not be too strict.
+    #[argh(option,
+           long="request-max-age",
+           arg_name="DURATION",
+           default="::std::time::Duration::from_secs(60 * 60)",
+           description="maximum age of signed requests and commands",
+           from_str_fn(parse_duration))]
+    pub request_max_age: Duration,
+
+    /// Public key to verify signatures of agent updates with.
+    ///
+    /// The `apply_agent_update` action installs only executables signed with
+    /// the corresponding private key. If not specified, the action refuses to
+    /// install any update.
+    #[argh(option,
+           long="update-verification-key",
+           arg_name="HEX",
+           description="hex-encoded Ed25519 key to verify agent updates with",
+           from_str_fn(parse_verification_key))]
+    pub update_verification_key: Option<crate::signed::VerifyingKey>,
+}
+
+impl Args {
+
+    /// Returns the maximum time the given action is allowed to run for.
+    ///
+    /// Overrides for the specific action take precedence over the default
+    /// action timeout (the last one wins if there are many).
+    pub fn action_timeout(&self, action: crate::request::Action) -> Option<Duration> {
+        let action_timeout_override = self.action_timeout_overrides.iter()
+            .rev()
+            .find(|(override_action, _)| *override_action == action);
+
+        match action_timeout_override {
+            Some((_, timeout)) => Some(*timeout),
+            None => self.action_timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for Args {
+
+    /// Returns arguments as if the agent was invoked without any flags.
+    fn default() -> Args {
+        use argh::FromArgs as _;
+
+        Args::from_args(&["rrg"], &[])
+            .expect("invalid default arguments")
+    }
 }
 
 /// Parses command-line arguments.
@@ -66,3 +509,109 @@ pub fn from_env_args() -> Args {
 fn parse_duration(value: &str) -> Result<Duration, String> {
     humantime::parse_duration(value).map_err(|error| error.to_string())
 }
+
+/// Parses an action name (e.g. `get_file_metadata`) to an `Action` object.
+fn parse_action(value: &str) -> Result<crate::request::Action, String> {
+    value.parse().map_err(|error: crate::request::ParseActionError| error.to_string())
+}
+
+/// Parses a compression algorithm name (e.g. `gzip`) to a `Compression` object.
+fn parse_compression(value: &str) -> Result<crate::transport::Compression, String> {
+    match value {
+        "none" => Ok(crate::transport::Compression::None),
+        #[cfg(feature = "compression-gzip")]
+        "gzip" => Ok(crate::transport::Compression::Gzip),
+        #[cfg(feature = "compression-zstd")]
+        "zstd" => Ok(crate::transport::Compression::Zstd),
+        _ => Err(format!("unknown compression algorithm: '{value}'")),
+    }
+}
+
+/// Parses a serialization format name (e.g. `json`) to a `Serialization` object.
+fn parse_serialization(value: &str) -> Result<crate::Serialization, String> {
+    match value {
+        "protobuf" => Ok(crate::Serialization::Protobuf),
+        "json" => Ok(crate::Serialization::Json),
+        _ => Err(format!("unknown serialization format: '{value}'")),
+    }
+}
+
+/// Parses an action timeout (e.g. `list_processes=30s`) to an action-duration pair.
+fn parse_action_timeout(value: &str) -> Result<(crate::request::Action, Duration), String> {
+    let (action, timeout) = value.split_once('=')
+        .ok_or_else(|| format!("invalid action timeout: '{value}'"))?;
+
+    Ok((parse_action(action)?, parse_duration(timeout)?))
+}
+
+/// Parses an action concurrency limit (e.g. `get_filesystem_timeline=1`) to an action-count pair.
+fn parse_action_concurrency_limit(value: &str) -> Result<(crate::request::Action, usize), String> {
+    let (action, limit) = value.split_once('=')
+        .ok_or_else(|| format!("invalid action concurrency limit: '{value}'"))?;
+
+    let limit = match limit.parse::<usize>() {
+        Ok(0) => return Err(format!("zero action concurrency limit: '{value}'")),
+        Ok(limit) => limit,
+        Err(error) => return Err(format!("invalid action concurrency limit: '{value}' ({error})")),
+    };
+
+    Ok((parse_action(action)?, limit))
+}
+
+/// Parses a hex-encoded Ed25519 key to a `VerifyingKey` object.
+fn parse_verification_key(value: &str) -> Result<crate::signed::VerifyingKey, String> {
+    crate::signed::parse_key(value).map_err(|error| error.to_string())
+}
+
+/// Parses a path to the `curl` executable, ensuring that it is absolute.
+fn parse_curl_path(value: &str) -> Result<std::path::PathBuf, String> {
+    let path = std::path::PathBuf::from(value);
+    if !path.is_absolute() {
+        return Err(format!("curl path is not absolute: '{value}'"));
+    }
+
+    Ok(path)
+}
+
+/// Parses an overflow policy name (e.g. `reject`) to an `OverflowPolicy` object.
+fn parse_overflow_policy(value: &str) -> Result<crate::session::OverflowPolicy, String> {
+    match value {
+        "queue" => Ok(crate::session::OverflowPolicy::Queue),
+        "reject" => Ok(crate::session::OverflowPolicy::Reject),
+        _ => Err(format!("unknown overflow policy: '{value}'")),
+    }
+}
+
+/// Returns the default directory for temporary files of the agent.
+#[cfg(target_family = "unix")]
+fn default_temp_dir() -> std::path::PathBuf {
+    std::path::PathBuf::from("/var/tmp/rrg")
+}
+
+/// Returns the default directory for temporary files of the agent.
+#[cfg(target_family = "windows")]
+fn default_temp_dir() -> std::path::PathBuf {
+    let system_root = std::env::var_os("SystemRoot")
+        .unwrap_or_else(|| std::ffi::OsString::from("C:\\Windows"));
+
+    let mut path = std::path::PathBuf::from(system_root);
+    path.extend(["Temp", "rrg"]);
+    path
+}
+
+/// Returns the default path to the `curl` executable.
+#[cfg(target_family = "unix")]
+fn default_curl_path() -> std::path::PathBuf {
+    std::path::PathBuf::from("/usr/bin/curl")
+}
+
+/// Returns the default path to the `curl` executable.
+#[cfg(target_family = "windows")]
+fn default_curl_path() -> std::path::PathBuf {
+    let system_root = std::env::var_os("SystemRoot")
+        .unwrap_or_else(|| std::ffi::OsString::from("C:\\Windows"));
+
+    let mut path = std::path::PathBuf::from(system_root);
+    path.extend(["System32", "curl.exe"]);
+    path
+}
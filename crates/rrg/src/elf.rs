@@ -0,0 +1,536 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Parsing of ELF [1] binaries (the executable format of Linux).
+//!
+//! Only the parts needed to fingerprint binaries are parsed: the file header,
+//! program headers (to find the interpreter, the dynamic section and loadable
+//! segments), the dynamic section (to find needed libraries) and types of
+//! sections (to tell whether the binary is stripped). Every offset and size is
+//! validated against the bounds of the file, so malformed binaries yield errors
+//! rather than bogus results.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Executable_and_Linkable_Format
+
+/// Magic number every ELF file starts with.
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+/// Maximum size of a single table (or string) read from the file.
+const MAX_TABLE_LEN: u64 = 16 * 1024 * 1024; // 16 MiB.
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+
+const SHT_SYMTAB: u32 = 2;
+
+/// Parsed ELF binary.
+#[derive(Debug)]
+pub struct Elf {
+    /// Whether the binary is a 64-bit one.
+    pub is_64: bool,
+    /// Whether values of the binary are big-endian.
+    pub is_big_endian: bool,
+    /// Type of the binary (e.g. executable or shared object).
+    pub file_type: u16,
+    /// Machine the binary is built for.
+    pub machine: u16,
+    /// Path to the program interpreter (dynamic linker) of the binary.
+    pub interpreter: Option<String>,
+    /// Names of the shared libraries the binary needs.
+    pub needed: Vec<String>,
+    /// Whether the binary has no symbol table.
+    pub stripped: bool,
+    /// File ranges (offsets and sizes) of the loadable segments.
+    pub load_segments: Vec<(u64, u64)>,
+}
+
+impl Elf {
+
+    /// Returns the conventional name of the machine of the binary.
+    ///
+    /// Returns `None` for machines that are not known.
+    pub fn machine_name(&self) -> Option<&'static str> {
+        let name = match self.machine {
+            2 => "sparc",
+            3 => "x86",
+            8 => "mips",
+            20 => "ppc",
+            21 => "ppc64",
+            22 => "s390",
+            40 => "arm",
+            43 => "sparcv9",
+            62 => "x86_64",
+            183 => "aarch64",
+            243 => "riscv",
+            258 => "loongarch",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+}
+
+/// Layout of structures of a binary of particular class and byte order.
+#[derive(Clone, Copy)]
+struct Layout {
+    /// Whether the binary is a 64-bit one.
+    is_64: bool,
+    /// Whether values of the binary are big-endian.
+    is_big_endian: bool,
+}
+
+impl Layout {
+
+    /// Decodes an unsigned value of the given size at the given offset.
+    ///
+    /// The caller is responsible for ensuring that the buffer is long enough.
+    fn uint(self, buf: &[u8], offset: usize, size: usize) -> u64 {
+        let bytes = &buf[offset..offset + size];
+        let mut value = 0;
+        for i in 0..size {
+            let byte = if self.is_big_endian { bytes[i] } else { bytes[size - 1 - i] };
+            value = value << 8 | u64::from(byte);
+        }
+
+        value
+    }
+
+    fn u16(self, buf: &[u8], offset: usize) -> u16 {
+        self.uint(buf, offset, 2) as u16
+    }
+
+    fn u32(self, buf: &[u8], offset: usize) -> u32 {
+        self.uint(buf, offset, 4) as u32
+    }
+
+    /// Decodes an address-sized value (32 or 64 bits depending on the class).
+    fn addr(self, buf: &[u8], offset: usize) -> u64 {
+        self.uint(buf, offset, if self.is_64 { 8 } else { 4 })
+    }
+}
+
+/// Program header of a binary.
+struct ProgramHeader {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    file_len: u64,
+}
+
+/// Parses the given file as an ELF binary.
+///
+/// Returns `None` if the file is not an ELF binary at all and an error if it
+/// looks like one but is malformed.
+pub fn parse<F>(file: &mut F, file_len: u64) -> std::io::Result<Option<Elf>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    let mut ident = [0; 16];
+    if read_at(file, 0, &mut ident).is_err() || &ident[..4] != ELF_MAGIC {
+        return Ok(None);
+    }
+
+    let is_64 = match ident[4] {
+        1 => false,
+        2 => true,
+        _ => return Err(malformed("invalid class")),
+    };
+    let is_big_endian = match ident[5] {
+        1 => false,
+        2 => true,
+        _ => return Err(malformed("invalid byte order")),
+    };
+    let layout = Layout { is_64, is_big_endian };
+
+    let mut header = [0; 64];
+    let header = &mut header[..if is_64 { 64 } else { 52 }];
+    read_at(file, 0, header)
+        .map_err(|_| malformed("truncated header"))?;
+
+    let file_type = layout.u16(header, 16);
+    let machine = layout.u16(header, 18);
+    let (ph_offset, sh_offset, rest) = if is_64 {
+        (layout.addr(header, 32), layout.addr(header, 40), 54)
+    } else {
+        (layout.addr(header, 28), layout.addr(header, 32), 42)
+    };
+    let ph_entry_len = u64::from(layout.u16(header, rest));
+    let ph_count = u64::from(layout.u16(header, rest + 2));
+    let sh_entry_len = u64::from(layout.u16(header, rest + 4));
+    let sh_count = u64::from(layout.u16(header, rest + 6));
+
+    let program_headers = parse_program_headers(file, file_len, layout, ph_offset, ph_entry_len, ph_count)?;
+
+    let mut elf = Elf {
+        is_64,
+        is_big_endian,
+        file_type,
+        machine,
+        interpreter: None,
+        needed: Vec::new(),
+        stripped: true,
+        load_segments: Vec::new(),
+    };
+
+    for header in &program_headers {
+        match header.kind {
+            PT_LOAD => {
+                elf.load_segments.push((header.offset, header.file_len));
+            }
+            PT_INTERP => {
+                let interpreter = read_table(file, file_len, header.offset, header.file_len)?;
+                elf.interpreter = Some(c_str(&interpreter, 0)?);
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(dynamic) = program_headers.iter().find(|header| header.kind == PT_DYNAMIC) {
+        elf.needed = parse_needed(file, file_len, layout, dynamic, &program_headers)?;
+    }
+
+    elf.stripped = !has_symtab(file, file_len, layout, sh_offset, sh_entry_len, sh_count)?;
+
+    Ok(Some(elf))
+}
+
+/// Parses the table of program headers of a binary.
+fn parse_program_headers<F>(
+    file: &mut F,
+    file_len: u64,
+    layout: Layout,
+    offset: u64,
+    entry_len: u64,
+    count: u64,
+) -> std::io::Result<Vec<ProgramHeader>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if entry_len < if layout.is_64 { 56 } else { 32 } {
+        return Err(malformed("invalid program header size"));
+    }
+
+    let table = read_table(file, file_len, offset, entry_len * count)?;
+
+    let headers = table.chunks_exact(entry_len as usize).map(|entry| {
+        if layout.is_64 {
+            ProgramHeader {
+                kind: layout.u32(entry, 0),
+                offset: layout.addr(entry, 8),
+                vaddr: layout.addr(entry, 16),
+                file_len: layout.addr(entry, 32),
+            }
+        } else {
+            ProgramHeader {
+                kind: layout.u32(entry, 0),
+                offset: layout.addr(entry, 4),
+                vaddr: layout.addr(entry, 8),
+                file_len: layout.addr(entry, 16),
+            }
+        }
+    }).collect::<Vec<_>>();
+
+    for header in &headers {
+        if header.offset.checked_add(header.file_len).is_none_or(|end| end > file_len) {
+            return Err(malformed("segment out of bounds"));
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Parses names of needed libraries from the dynamic segment of a binary.
+fn parse_needed<F>(
+    file: &mut F,
+    file_len: u64,
+    layout: Layout,
+    dynamic: &ProgramHeader,
+    program_headers: &[ProgramHeader],
+) -> std::io::Result<Vec<String>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    let entries = read_table(file, file_len, dynamic.offset, dynamic.file_len)?;
+    let entry_len = if layout.is_64 { 16 } else { 8 };
+
+    let mut needed_offsets = Vec::new();
+    let mut strtab_vaddr = None;
+    let mut strtab_len = None;
+
+    for entry in entries.chunks_exact(entry_len) {
+        let tag = layout.addr(entry, 0);
+        let value = layout.addr(entry, entry_len / 2);
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed_offsets.push(value),
+            DT_STRTAB => strtab_vaddr = Some(value),
+            DT_STRSZ => strtab_len = Some(value),
+            _ => (),
+        }
+    }
+
+    if needed_offsets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (Some(strtab_vaddr), Some(strtab_len)) = (strtab_vaddr, strtab_len) else {
+        return Err(malformed("missing dynamic string table"));
+    };
+
+    // The string table is specified by its virtual address, so we need to find
+    // the loadable segment it is mapped from.
+    let strtab_offset = program_headers.iter()
+        .filter(|header| header.kind == PT_LOAD)
+        .find(|header| {
+            header.vaddr <= strtab_vaddr &&
+            strtab_vaddr - header.vaddr + strtab_len <= header.file_len
+        })
+        .map(|header| header.offset + (strtab_vaddr - header.vaddr))
+        .ok_or_else(|| malformed("unmapped dynamic string table"))?;
+
+    let strtab = read_table(file, file_len, strtab_offset, strtab_len)?;
+
+    needed_offsets.into_iter()
+        .map(|offset| c_str(&strtab, offset))
+        .collect()
+}
+
+/// Checks whether the binary has a symbol table section.
+fn has_symtab<F>(
+    file: &mut F,
+    file_len: u64,
+    layout: Layout,
+    offset: u64,
+    entry_len: u64,
+    mut count: u64,
+) -> std::io::Result<bool>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    if offset == 0 {
+        return Ok(false);
+    }
+    if entry_len < if layout.is_64 { 64 } else { 40 } {
+        return Err(malformed("invalid section header size"));
+    }
+
+    // Binaries with too many sections to fit the header keep the count in the
+    // size field of the first (otherwise unused) section header.
+    if count == 0 {
+        let first = read_table(file, file_len, offset, entry_len)?;
+        count = if layout.is_64 {
+            layout.addr(&first, 32)
+        } else {
+            layout.addr(&first, 20)
+        };
+    }
+
+    let table = read_table(file, file_len, offset, entry_len.saturating_mul(count))?;
+
+    Ok(table.chunks_exact(entry_len as usize).any(|entry| {
+        layout.u32(entry, 4) == SHT_SYMTAB
+    }))
+}
+
+/// Reads `len` bytes at the given offset of the file, checking the bounds.
+fn read_table<F>(file: &mut F, file_len: u64, offset: u64, len: u64) -> std::io::Result<Vec<u8>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    if len > MAX_TABLE_LEN || offset.checked_add(len).is_none_or(|end| end > file_len) {
+        return Err(malformed("table out of bounds"));
+    }
+
+    let mut buf = vec![0; len as usize];
+    read_at(file, offset, &mut buf)
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => malformed("truncated table"),
+            _ => error,
+        })?;
+
+    Ok(buf)
+}
+
+/// Extracts a null-terminated string at the given offset of the buffer.
+fn c_str(buf: &[u8], offset: u64) -> std::io::Result<String> {
+    let buf = usize::try_from(offset).ok()
+        .and_then(|offset| buf.get(offset..))
+        .ok_or_else(|| malformed("string out of bounds"))?;
+    let len = buf.iter().position(|byte| *byte == 0)
+        .ok_or_else(|| malformed("unterminated string"))?;
+
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Reads exactly `buf.len()` bytes at the given offset of the file.
+fn read_at<F>(file: &mut F, offset: u64, buf: &mut [u8]) -> std::io::Result<()>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+/// Creates an error for malformed binaries.
+fn malformed(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Interpreter path of the test fixture.
+#[cfg(test)]
+pub const FIXTURE_INTERPRETER: &str = "/lib64/ld-linux-x86-64.so.2";
+
+/// Builds a minimal little-endian 64-bit x86-64 executable for tests.
+///
+/// The binary has an interpreter, needs `libc` and `libm` and, unless it is
+/// stripped, has a symbol table section. Returns the binary along with the size
+/// of its only loadable segment (which starts at the beginning of the file).
+#[cfg(test)]
+pub fn fixture(stripped: bool) -> (Vec<u8>, u64) {
+    const VADDR: u64 = 0x400000;
+    const PH_LEN: u64 = 56;
+
+    let interp = format!("{FIXTURE_INTERPRETER}\0");
+    let strtab = b"\0libc.so.6\0libm.so.6\0";
+
+    let interp_offset = 64 + 3 * PH_LEN;
+    let strtab_offset = interp_offset + interp.len() as u64;
+    let dynamic_offset = strtab_offset + strtab.len() as u64;
+    let dynamic = [
+        (DT_NEEDED, 1),
+        (DT_NEEDED, 11),
+        (DT_STRTAB, VADDR + strtab_offset),
+        (DT_STRSZ, strtab.len() as u64),
+        (DT_NULL, 0),
+    ];
+    let load_len = dynamic_offset + 16 * dynamic.len() as u64;
+
+    let (sh_offset, sh_count) = if stripped { (0, 0) } else { (load_len, 2) };
+
+    let mut binary = Vec::new();
+    binary.extend_from_slice(ELF_MAGIC);
+    binary.extend_from_slice(&[2, 1, 1, 0]); // 64-bit, little-endian, version 1.
+    binary.extend_from_slice(&[0; 8]);
+    binary.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC.
+    binary.extend_from_slice(&62u16.to_le_bytes()); // EM_X86_64.
+    binary.extend_from_slice(&1u32.to_le_bytes());
+    binary.extend_from_slice(&VADDR.to_le_bytes()); // Entry point.
+    binary.extend_from_slice(&64u64.to_le_bytes()); // Program headers offset.
+    binary.extend_from_slice(&sh_offset.to_le_bytes());
+    binary.extend_from_slice(&0u32.to_le_bytes()); // Flags.
+    binary.extend_from_slice(&64u16.to_le_bytes());
+    binary.extend_from_slice(&(PH_LEN as u16).to_le_bytes());
+    binary.extend_from_slice(&3u16.to_le_bytes());
+    binary.extend_from_slice(&64u16.to_le_bytes());
+    binary.extend_from_slice(&(sh_count as u16).to_le_bytes());
+    binary.extend_from_slice(&0u16.to_le_bytes()); // Section names index.
+
+    let segments = [
+        (PT_INTERP, interp_offset, interp.len() as u64),
+        (PT_LOAD, 0, load_len),
+        (PT_DYNAMIC, dynamic_offset, 16 * dynamic.len() as u64),
+    ];
+    for (kind, offset, len) in segments {
+        binary.extend_from_slice(&kind.to_le_bytes());
+        binary.extend_from_slice(&4u32.to_le_bytes()); // Flags.
+        binary.extend_from_slice(&offset.to_le_bytes());
+        binary.extend_from_slice(&(VADDR + offset).to_le_bytes());
+        binary.extend_from_slice(&(VADDR + offset).to_le_bytes());
+        binary.extend_from_slice(&len.to_le_bytes());
+        binary.extend_from_slice(&len.to_le_bytes());
+        binary.extend_from_slice(&0x1000u64.to_le_bytes()); // Alignment.
+    }
+
+    binary.extend_from_slice(interp.as_bytes());
+    binary.extend_from_slice(strtab);
+    for (tag, value) in dynamic {
+        binary.extend_from_slice(&tag.to_le_bytes());
+        binary.extend_from_slice(&value.to_le_bytes());
+    }
+
+    if !stripped {
+        // Null section followed by an (empty) symbol table.
+        binary.extend_from_slice(&[0; 64]);
+        binary.extend_from_slice(&0u32.to_le_bytes());
+        binary.extend_from_slice(&SHT_SYMTAB.to_le_bytes());
+        binary.extend_from_slice(&[0; 56]);
+    }
+
+    (binary, load_len)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Parses the given bytes as an ELF binary.
+    fn parse_bytes(bytes: &[u8]) -> std::io::Result<Option<Elf>> {
+        parse(&mut std::io::Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    #[test]
+    fn parse_fixture() {
+        let (binary, load_len) = fixture(false);
+
+        let elf = parse_bytes(&binary).unwrap().unwrap();
+        assert!(elf.is_64);
+        assert!(!elf.is_big_endian);
+        assert_eq!(elf.file_type, 2);
+        assert_eq!(elf.machine_name(), Some("x86_64"));
+        assert_eq!(elf.interpreter.as_deref(), Some(FIXTURE_INTERPRETER));
+        assert_eq!(elf.needed, vec!["libc.so.6", "libm.so.6"]);
+        assert!(!elf.stripped);
+        assert_eq!(elf.load_segments, vec![(0, load_len)]);
+    }
+
+    #[test]
+    fn parse_fixture_stripped() {
+        let (binary, _) = fixture(true);
+
+        let elf = parse_bytes(&binary).unwrap().unwrap();
+        assert!(elf.stripped);
+        assert_eq!(elf.needed, vec!["libc.so.6", "libm.so.6"]);
+    }
+
+    #[test]
+    fn parse_not_elf() {
+        assert!(parse_bytes(b"").unwrap().is_none());
+        assert!(parse_bytes(b"\xcf\xfa\xed\xfe\x0c\x00\x00\x01").unwrap().is_none());
+        assert!(parse_bytes(b"lorem ipsum dolor sit amet").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let (binary, _) = fixture(false);
+
+        for len in [20, 100, 300] {
+            let error = parse_bytes(&binary[..len]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn parse_needed_out_of_bounds() {
+        let (mut binary, _) = fixture(true);
+
+        // Offset of the first needed library name within the string table.
+        let dynamic_offset = binary.len() - 16 * 5;
+        binary[dynamic_offset + 8..dynamic_offset + 16]
+            .copy_from_slice(&1024u64.to_le_bytes());
+
+        let error = parse_bytes(&binary).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
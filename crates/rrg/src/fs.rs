@@ -9,6 +9,9 @@
 //! standard `std::fs` module. All functions are portable and should work on all
 //! supported platforms (perhaps with limited capabilities).
 
+#[cfg(target_os = "linux")]
+pub mod linux;
+
 use std::fs::Metadata;
 use std::path::{Path, PathBuf};
 
@@ -23,6 +26,54 @@ pub struct Entry {
     pub path: PathBuf,
     /// Metadata associated with the item.
     pub metadata: Metadata,
+    /// Type of the item as reported by the directory listing (if listed).
+    ///
+    /// Usually this agrees with the type from the metadata but the two can
+    /// diverge, e.g. because of filesystem corruption or tampering.
+    pub listed_type: Option<std::fs::FileType>,
+}
+
+impl Entry {
+
+    /// Checks whether the listed type of the entry disagrees with its metadata.
+    ///
+    /// Entries that were not obtained from a directory listing never have a
+    /// mismatch.
+    pub fn has_type_mismatch(&self) -> bool {
+        match self.listed_type {
+            Some(listed_type) => is_type_mismatch(listed_type, &self.metadata),
+            None => false,
+        }
+    }
+}
+
+/// Checks whether the `listed` type of a file disagrees with its `metadata`.
+///
+/// When a filesystem does not report types of directory entries in listings,
+/// the standard library determines them using `lstat`. Thus, for such entries
+/// the listed type comes from the metadata and there is no mismatch.
+pub fn is_type_mismatch(listed: std::fs::FileType, metadata: &Metadata) -> bool {
+    let stat = metadata.file_type();
+
+    if listed.is_dir() != stat.is_dir() ||
+       listed.is_file() != stat.is_file() ||
+       listed.is_symlink() != stat.is_symlink() {
+        return true;
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        if listed.is_block_device() != stat.is_block_device() ||
+           listed.is_char_device() != stat.is_char_device() ||
+           listed.is_fifo() != stat.is_fifo() ||
+           listed.is_socket() != stat.is_socket() {
+            return true;
+        }
+    }
+
+    false
 }
 
 /// Returns a deep iterator over entries within a directory.
@@ -56,10 +107,7 @@ pub struct Entry {
 pub fn walk_dir<P: AsRef<Path>>(root: P) -> std::io::Result<WalkDir> {
     let root = root.as_ref();
 
-    let iter = ListDir {
-        iter: std::fs::read_dir(root)?,
-        cur_depth: 1,
-    };
+    let iter = ListDir::new(root, 1, u64::MAX, None)?;
 
     #[cfg(target_family = "unix")]
     let dev = {
@@ -69,6 +117,10 @@ pub fn walk_dir<P: AsRef<Path>>(root: P) -> std::io::Result<WalkDir> {
 
     Ok(WalkDir {
         max_depth: u32::MAX,
+        max_dir_entries: u64::MAX,
+        excludes: Vec::new(),
+        fs_type_filter: None,
+        watchdog: None,
         iter,
         pending_iters: vec![],
         #[cfg(target_family = "unix")]
@@ -84,13 +136,19 @@ pub fn walk_dir<P: AsRef<Path>>(root: P) -> std::io::Result<WalkDir> {
 /// directories.
 ///
 /// To limit depth of the recursion once can use the [`with_max_depth`] method
-/// on the instance.
+/// on the instance. To cross device boundaries into filesystems of specific
+/// types one can use the [`with_fs_type_filter`] method.
 ///
 /// The iterator can be constructed with the [`walk_dir`] function.
 ///
 /// [`with_max_depth`]: WalkDir::with_max_depth
+/// [`with_fs_type_filter`]: WalkDir::with_fs_type_filter
 pub struct WalkDir {
     max_depth: u32,
+    max_dir_entries: u64,
+    excludes: Vec<Glob>,
+    fs_type_filter: Option<FsTypeFilter>,
+    watchdog: Option<std::sync::Arc<Watchdog>>,
     iter: ListDir,
     pending_iters: Vec<std::io::Result<ListDir>>,
     #[cfg(target_family = "unix")] dev: u64,
@@ -123,6 +181,121 @@ impl WalkDir {
         self
     }
 
+    /// Limits the number of entries read from a single directory.
+    ///
+    /// Once a directory has more than `max_dir_entries` entries, the rest of
+    /// them is not read. Instead, a [`TruncatedDirError`] is yielded after the
+    /// entries that were read. This protects against directories with millions
+    /// of entries that would otherwise stall the walk.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let paths = rrg::fs::walk_dir("/usr/bin").unwrap().with_max_dir_entries(8)
+    ///     .filter_map(Result::ok)
+    ///     .map(|entry| entry.path)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert!(paths.len() <= 8);
+    /// ```
+    pub fn with_max_dir_entries(mut self, max_dir_entries: u64) -> WalkDir {
+        self.max_dir_entries = max_dir_entries;
+        // The root directory is opened eagerly, so we need to update its limit
+        // as well.
+        self.iter.max_entries = max_dir_entries;
+        self
+    }
+
+    /// Skips entries matching any of the given patterns.
+    ///
+    /// Matching directories are neither yielded nor descended into (they are
+    /// not even opened), so their whole subtrees are pruned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let excludes = vec![rrg::fs::Glob::new("/usr/lib").unwrap()];
+    ///
+    /// let paths = rrg::fs::walk_dir("/usr").unwrap().with_excludes(excludes)
+    ///     .filter_map(Result::ok)
+    ///     .map(|entry| entry.path)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert!(paths.contains(&"/usr/bin".into()));
+    /// assert!(!paths.contains(&"/usr/lib".into()));
+    /// ```
+    pub fn with_excludes(mut self, excludes: Vec<Glob>) -> WalkDir {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Descends only into directories on filesystems allowed by the filter.
+    ///
+    /// This replaces the default behaviour of not crossing device boundaries:
+    /// directories on other devices are descended into as long as the type of
+    /// their filesystem is allowed. Directories on filesystems that are not
+    /// allowed are still yielded, they are just not entered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let filter = rrg::fs::FsTypeFilter::new(vec![], vec!["proc".into()])
+    ///     .unwrap();
+    ///
+    /// let paths = rrg::fs::walk_dir("/").unwrap().with_fs_type_filter(filter)
+    ///     .filter_map(Result::ok)
+    ///     .map(|entry| entry.path)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert!(paths.contains(&"/proc".into()));
+    /// assert!(!paths.contains(&"/proc/self".into()));
+    /// ```
+    pub fn with_fs_type_filter(mut self, fs_type_filter: FsTypeFilter) -> WalkDir {
+        self.fs_type_filter = Some(fs_type_filter);
+        self
+    }
+
+    /// Abandons operations on individual entries taking longer than `timeout`.
+    ///
+    /// Stat-ing entries and opening subdirectories are run under a [`Watchdog`]
+    /// so that a single entry that hangs (e.g. on an unresponsive network
+    /// mount) does not stall the whole walk. Instead, an error of the
+    /// [`TimedOut`] kind is yielded for such entry and the walk continues.
+    ///
+    /// [`TimedOut`]: std::io::ErrorKind::TimedOut
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let timeout = std::time::Duration::from_secs(10);
+    ///
+    /// let paths = rrg::fs::walk_dir("/mnt").unwrap().with_entry_timeout(timeout)
+    ///     .filter_map(Result::ok)
+    ///     .map(|entry| entry.path)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert!(paths.contains(&"/mnt/foo".into()));
+    /// ```
+    pub fn with_entry_timeout(mut self, timeout: std::time::Duration) -> WalkDir {
+        let watchdog = std::sync::Arc::new(Watchdog::new(timeout));
+        // The root directory is opened eagerly, so we need to update it as well
+        // for its entries to be stat-ed under the watchdog.
+        self.iter.watchdog = Some(watchdog.clone());
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    fn is_excluded(&self, entry: &Entry) -> bool {
+        self.excludes.iter().any(|glob| glob.is_prefix_match(&entry.path))
+    }
+
+    fn can_descend(&self, entry: &Entry) -> bool {
+        match &self.fs_type_filter {
+            Some(fs_type_filter) => fs_type_filter.allows(&entry.metadata),
+            None => self.is_same_dev(entry),
+        }
+    }
+
     #[cfg(target_family = "unix")]
     fn is_same_dev(&self, entry: &Entry) -> bool {
         self.dev == std::os::unix::fs::MetadataExt::dev(&entry.metadata)
@@ -146,15 +319,21 @@ impl std::iter::Iterator for WalkDir {
                     Err(error) => return Some(Err(error)),
                 };
 
-                if entry.metadata.is_dir() && self.is_same_dev(&entry) && self.iter.cur_depth < self.max_depth {
+                if self.is_excluded(&entry) {
+                    continue;
+                }
+
+                if entry.metadata.is_dir() && self.can_descend(&entry) && self.iter.cur_depth < self.max_depth {
                     self.pending_iters.push({
-                        std::fs::read_dir(&entry.path).map(|iter| ListDir {
-                            iter,
-                            // This cannot ever overflow because the condition
-                            // above guarantees that `self.iter.cur_depth` is
-                            // less than `u32::MAX`.
-                            cur_depth: self.iter.cur_depth + 1,
-                        })
+                        // This cannot ever overflow because the condition above
+                        // guarantees that `self.iter.cur_depth` is less than
+                        // `u32::MAX`.
+                        ListDir::new(
+                            &entry.path,
+                            self.iter.cur_depth + 1,
+                            self.max_dir_entries,
+                            self.watchdog.clone(),
+                        )
                     });
                 }
 
@@ -182,8 +361,47 @@ impl std::iter::Iterator for WalkDir {
 /// Unlike the [`ReadDir`] iterator entries, [`ListDir`] entries are guaranteed
 /// to have valid metadata objects attached.
 pub struct ListDir {
+    path: PathBuf,
     cur_depth: u32,
     iter: std::fs::ReadDir,
+    /// Maximum number of entries to read before reporting truncation.
+    max_entries: u64,
+    /// Number of entries read so far.
+    len: u64,
+    /// Whether the truncation has been reported already.
+    truncated: bool,
+    /// Watchdog to stat entries under (if they should be abandoned on timeout).
+    watchdog: Option<std::sync::Arc<Watchdog>>,
+}
+
+impl ListDir {
+
+    /// Opens the directory at `path` for listing at most `max_entries` entries.
+    fn new(
+        path: &Path,
+        cur_depth: u32,
+        max_entries: u64,
+        watchdog: Option<std::sync::Arc<Watchdog>>,
+    ) -> std::io::Result<ListDir> {
+        let iter = match &watchdog {
+            Some(watchdog) => {
+                let owned_path = path.to_path_buf();
+                watchdog.run(move || std::fs::read_dir(owned_path))
+                    .map_err(|error| TimedOutEntryError::wrap(error, path))?
+            }
+            None => std::fs::read_dir(path)?,
+        };
+
+        Ok(ListDir {
+            path: path.to_path_buf(),
+            cur_depth,
+            iter,
+            max_entries,
+            len: 0,
+            truncated: false,
+            watchdog,
+        })
+    }
 }
 
 impl std::iter::Iterator for ListDir {
@@ -191,325 +409,2754 @@ impl std::iter::Iterator for ListDir {
     type Item = std::io::Result<Entry>;
 
     fn next(&mut self) -> Option<std::io::Result<Entry>> {
+        if self.truncated {
+            return None;
+        }
+
+        // We read one entry past the limit to find out whether there is more
+        // of them, so that directories with exactly `max_entries` entries are
+        // not reported as truncated.
+        if self.len == self.max_entries {
+            // The entry itself (and its potential error) is not interesting,
+            // we only care whether it exists.
+            let _ = self.iter.next()?;
+            self.truncated = true;
+
+            return Some(Err(std::io::Error::other(TruncatedDirError {
+                path: self.path.clone(),
+                max_entries: self.max_entries,
+            })));
+        }
+        self.len += 1;
+
         let entry = match self.iter.next() {
             Some(Ok(entry)) => entry,
             Some(Err(error)) => return Some(Err(error)),
             None => return None,
         };
 
-        let metadata = match entry.metadata() {
+        let path = entry.path();
+        let listed_type = entry.file_type().ok();
+
+        let metadata = match &self.watchdog {
+            Some(watchdog) => watchdog.run(move || entry.metadata())
+                .map_err(|error| TimedOutEntryError::wrap(error, &path)),
+            None => entry.metadata(),
+        };
+        let metadata = match metadata {
             Ok(metadata) => metadata,
             Err(error) => return Some(Err(error)),
         };
 
         Some(Ok(Entry {
-            path: entry.path(),
+            path,
             metadata,
+            listed_type,
         }))
     }
 }
 
-#[cfg(test)]
-mod tests {
-
-    use std::fs::File;
-
-    use super::*;
+/// An error indicating that a directory has more entries than the limit.
+///
+/// It is yielded by directory walks (in place of the remaining entries) for
+/// directories with entries over the limit set through [`WalkDir::with_max_dir_entries`]
+/// (or given to [`par_walk_dir`]).
+#[derive(Debug)]
+pub struct TruncatedDirError {
+    /// Path to the directory that was truncated.
+    path: PathBuf,
+    /// Maximum number of entries of the directory that were read.
+    max_entries: u64,
+}
 
-    #[test]
-    fn test_walk_dir_non_existing() {
-        let tempdir = tempfile::tempdir().unwrap();
+impl TruncatedDirError {
 
-        let iter = walk_dir(tempdir.path().join("foo"));
-        assert!(iter.is_err());
+    /// Returns the truncation error wrapped in the given I/O error (if any).
+    pub fn from_io_error(error: &std::io::Error) -> Option<&TruncatedDirError> {
+        error.get_ref()?.downcast_ref()
     }
 
-    #[test]
-    fn walk_dir_not_dir() {
-        let tempdir = tempfile::tempdir().unwrap();
-        File::create(tempdir.path().join("foo")).unwrap();
-
-        let iter = walk_dir(tempdir.path().join("foo"));
-        assert!(iter.is_err());
+    /// Returns the path to the directory that was truncated.
+    pub fn path(&self) -> &Path {
+        &self.path
     }
+}
 
-    #[test]
-    fn test_walk_dir_empty() {
-        let tempdir = tempfile::tempdir().unwrap();
+impl std::fmt::Display for TruncatedDirError {
 
-        let iter = walk_dir(&tempdir).unwrap();
-        assert_eq!(iter.count(), 0);
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "directory '{}' has more than {} entries",
+            self.path.display(), self.max_entries,
+        }
     }
+}
 
-    #[test]
-    fn test_walk_dir_with_flat_files() {
-        let tempdir = tempfile::tempdir().unwrap();
-        File::create(tempdir.path().join("abc")).unwrap();
-        File::create(tempdir.path().join("def")).unwrap();
-        File::create(tempdir.path().join("ghi")).unwrap();
-
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+impl std::error::Error for TruncatedDirError {
+}
 
-        assert_eq!(results.len(), 3);
+/// An error indicating that an operation on an entry did not finish in time.
+///
+/// It is yielded by directory walks in place of entries that could not be
+/// stat-ed (or directories that could not be opened) within the timeout set
+/// through [`WalkDir::with_entry_timeout`] (or given to [`par_walk_dir`]). The
+/// error wrapping it is of the [`TimedOut`] kind.
+///
+/// [`TimedOut`]: std::io::ErrorKind::TimedOut
+#[derive(Debug)]
+pub struct TimedOutEntryError {
+    /// Path to the entry that timed out.
+    path: PathBuf,
+    /// Error returned by the watchdog.
+    error: std::io::Error,
+}
 
-        assert_eq!(results[0].path, tempdir.path().join("abc"));
-        assert!(results[0].metadata.is_file());
+impl TimedOutEntryError {
 
-        assert_eq!(results[1].path, tempdir.path().join("def"));
-        assert!(results[1].metadata.is_file());
+    /// Returns the timeout error wrapped in the given I/O error (if any).
+    pub fn from_io_error(error: &std::io::Error) -> Option<&TimedOutEntryError> {
+        error.get_ref()?.downcast_ref()
+    }
 
-        assert_eq!(results[2].path, tempdir.path().join("ghi"));
-        assert!(results[2].metadata.is_file());
+    /// Returns the path to the entry that timed out.
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    #[test]
-    fn test_walk_dir_with_flat_dirs() {
-        let tempdir = tempfile::tempdir().unwrap();
-        std::fs::create_dir(tempdir.path().join("abc")).unwrap();
-        std::fs::create_dir(tempdir.path().join("def")).unwrap();
+    /// Wraps the given error with the path of the entry if it is a timeout.
+    fn wrap<P: AsRef<Path>>(error: std::io::Error, path: P) -> std::io::Error {
+        if error.kind() != std::io::ErrorKind::TimedOut {
+            return error;
+        }
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        std::io::Error::new(std::io::ErrorKind::TimedOut, TimedOutEntryError {
+            path: path.as_ref().to_path_buf(),
+            error,
+        })
+    }
+}
 
-        assert_eq!(results.len(), 2);
+impl std::fmt::Display for TimedOutEntryError {
 
-        assert_eq!(results[0].path, tempdir.path().join("abc"));
-        assert!(results[0].metadata.is_dir());
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "'{}': {}", self.path.display(), self.error)
+    }
+}
 
-        assert_eq!(results[1].path, tempdir.path().join("def"));
-        assert!(results[1].metadata.is_dir());
+impl std::error::Error for TimedOutEntryError {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
     }
+}
 
-    #[test]
-    fn test_walk_dir_with_nested_dirs() {
-        let tempdir = tempfile::tempdir().unwrap();
-        std::fs::create_dir(tempdir.path().join("abc")).unwrap();
-        std::fs::create_dir(tempdir.path().join("abc").join("def")).unwrap();
+/// Returns a deep iterator over entries within a directory using many threads.
+///
+/// This behaves like [`walk_dir`] (including not crossing device boundaries and
+/// not following symlinks) but the directories are listed and their entries are
+/// stat-ed concurrently by a pool of `concurrency` worker threads.
+///
+/// The output is deterministic regardless of the scheduling of the workers:
+/// entries of a single directory are always yielded together (sorted by their
+/// paths) and directories are yielded in depth-first order, a subdirectory
+/// right after the listing of its parent. Listings that workers finish ahead
+/// of their turn are buffered until it comes.
+///
+/// To keep memory usage bounded, workers stop picking new directories once
+/// there are enough listed directories waiting to be consumed (except for the
+/// one the iterator waits for). Dropping the iterator stops the workers.
+///
+/// Recursion is limited to `max_depth` levels, at most `max_dir_entries` are
+/// read from each directory, entries matching `excludes` are pruned, device
+/// boundaries are crossed according to `fs_type_filter` and operations on
+/// entries are abandoned after `entry_timeout` (see [`WalkDir::with_max_depth`],
+/// [`WalkDir::with_max_dir_entries`], [`WalkDir::with_excludes`],
+/// [`WalkDir::with_fs_type_filter`] and [`WalkDir::with_entry_timeout`] for the
+/// semantics).
+///
+/// # Errors
+///
+/// Like with [`walk_dir`], the only errors reported immediately are problems
+/// with reading the root folder. Other errors are yielded by the iterator.
+///
+/// # Panics
+///
+/// Panics if `max_depth` or `concurrency` is zero.
+pub fn par_walk_dir<P>(
+    root: P,
+    max_depth: u32,
+    max_dir_entries: u64,
+    excludes: Vec<Glob>,
+    fs_type_filter: Option<FsTypeFilter>,
+    entry_timeout: Option<std::time::Duration>,
+    concurrency: usize,
+) -> std::io::Result<ParWalkDir>
+where
+    P: AsRef<Path>,
+{
+    use std::sync::{Arc, Condvar, Mutex};
+
+    assert!(max_depth > 0);
+    assert!(concurrency > 0);
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+    let root = root.as_ref();
 
-        assert_eq!(results.len(), 2);
+    // We want to fail early if the root cannot be read. Only then we pass it to
+    // the workers.
+    std::fs::read_dir(root)?;
 
-        assert_eq!(results[0].path, tempdir.path().join("abc"));
-        assert!(results[0].metadata.is_dir());
+    #[cfg(target_family = "unix")]
+    let dev = {
+        let metadata = std::fs::metadata(root)?;
+        std::os::unix::fs::MetadataExt::dev(&metadata)
+    };
 
-        assert_eq!(results[1].path, tempdir.path().join("abc").join("def"));
-        assert!(results[1].metadata.is_dir());
-    }
+    let shared = Arc::new(ParWalkShared {
+        state: Mutex::new(ParWalkState {
+            pending_dirs: std::collections::BTreeMap::from([
+                (Vec::new(), (root.to_path_buf(), 1)),
+            ]),
+            active_workers: 0,
+            unconsumed_dirs: 0,
+            awaited_key: Some(Vec::new()),
+            stopped: false,
+        }),
+        cvar: Condvar::new(),
+        max_unconsumed_dirs: 2 * concurrency,
+        max_depth,
+        max_dir_entries,
+        excludes,
+        fs_type_filter,
+        entry_timeout,
+        #[cfg(target_family = "unix")]
+        dev,
+    });
+
+    let (sender, receiver) = std::sync::mpsc::sync_channel(2 * concurrency);
+
+    let workers = (0..concurrency).map(|_| {
+        let shared = Arc::clone(&shared);
+        let sender = sender.clone();
+
+        std::thread::spawn(move || shared.work(sender))
+    }).collect();
+
+    Ok(ParWalkDir {
+        receiver: Some(receiver),
+        entries: Vec::new().into_iter(),
+        errors: Vec::new().into_iter(),
+        awaited_keys: vec![Vec::new()],
+        listings: std::collections::HashMap::new(),
+        shared,
+        workers,
+    })
+}
 
-    // Both Windows and macOS have limits on the path length. Both of these
-    // limits are very low (260 and 1016 respectively), so making the created
-    // hierarchy to fit these would render the test quite useless. Hence, we
-    // simply ignore it on these platforms.
-    #[cfg_attr(target_os = "macos", ignore)]
-    #[cfg_attr(target_os = "windows", ignore)]
-    #[test]
-    fn test_walk_dir_with_deeply_nested_dirs() {
-        let tempdir = tempfile::tempdir().unwrap();
+/// Iterator over entries in all subdirectories listed by many threads.
+///
+/// The iterator can be constructed with the [`par_walk_dir`] function.
+pub struct ParWalkDir {
+    /// Receiving end of the channel with directory listings sent by workers.
+    receiver: Option<std::sync::mpsc::Receiver<ParWalkListing>>,
+    /// Entries of the current directory listing that are yet to be yielded.
+    entries: std::vec::IntoIter<Entry>,
+    /// Errors of the current directory listing that are yet to be yielded.
+    errors: std::vec::IntoIter<std::io::Error>,
+    /// Keys of directories to yield next (the last one being the first).
+    awaited_keys: Vec<Vec<u32>>,
+    /// Listings received ahead of their turn.
+    listings: std::collections::HashMap<Vec<u32>, ParWalkListing>,
+    /// State shared with the workers.
+    shared: std::sync::Arc<ParWalkShared>,
+    /// Handles to the worker threads.
+    workers: Vec<std::thread::JoinHandle<()>>,
+}
 
-        let mut dir = tempdir.path().to_path_buf();
-        for _ in 0..512 {
-            dir.push("foo");
-            std::fs::create_dir(&dir).unwrap();
-        }
+/// Listing of a single directory sent by a parallel walk worker.
+///
+/// Directories are identified by keys: the root has an empty key and the key
+/// of a subdirectory is the key of its parent extended with the position of the
+/// subdirectory among the ones of the parent to descend into. The depth-first
+/// order of directories is thus the lexicographic order of their keys.
+struct ParWalkListing {
+    /// Key of the listed directory.
+    key: Vec<u32>,
+    /// Entries of the directory (or an error if it could not be listed).
+    result: std::io::Result<Vec<Entry>>,
+    /// Errors (e.g. about truncation) to yield after the entries.
+    errors: Vec<std::io::Error>,
+    /// Number of subdirectories of the directory that are going to be listed.
+    subdir_count: u32,
+}
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+/// State of the parallel directory walk shared between workers.
+struct ParWalkShared {
+    state: std::sync::Mutex<ParWalkState>,
+    cvar: std::sync::Condvar,
+    max_unconsumed_dirs: usize,
+    max_depth: u32,
+    max_dir_entries: u64,
+    excludes: Vec<Glob>,
+    fs_type_filter: Option<FsTypeFilter>,
+    entry_timeout: Option<std::time::Duration>,
+    #[cfg(target_family = "unix")] dev: u64,
+}
 
-        assert_eq!(results.len(), 512);
+/// Mutable state of the parallel directory walk.
+struct ParWalkState {
+    /// Directories (with their depth) that have not been listed yet by key.
+    pending_dirs: std::collections::BTreeMap<Vec<u32>, (PathBuf, u32)>,
+    /// Number of workers that are currently listing a directory.
+    active_workers: usize,
+    /// Number of directories being listed or not consumed by the iterator yet.
+    unconsumed_dirs: usize,
+    /// Key of the directory the iterator waits for.
+    awaited_key: Option<Vec<u32>>,
+    /// Whether the walk has been stopped (or has finished).
+    stopped: bool,
+}
 
-        for entry in &results {
-            assert!(entry.path.starts_with(&tempdir));
-            assert!(entry.path.ends_with("foo"));
-            assert!(entry.metadata.is_dir());
-        }
-    }
+impl ParWalkShared {
 
-    #[test]
-    fn test_walk_dir_with_files_inside_dir() {
-        let tempdir = tempfile::tempdir().unwrap();
-        std::fs::create_dir(tempdir.path().join("foo")).unwrap();
-        File::create(tempdir.path().join("foo").join("abc")).unwrap();
-        File::create(tempdir.path().join("foo").join("def")).unwrap();
+    /// Lists directories pending in the shared state until there are none left.
+    fn work(&self, sender: std::sync::mpsc::SyncSender<ParWalkListing>) {
+        // Every worker needs its own watchdog, as operations it runs are not
+        // executed concurrently.
+        let watchdog = self.entry_timeout.map(|entry_timeout| {
+            std::sync::Arc::new(Watchdog::new(entry_timeout))
+        });
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        loop {
+            let (key, (dir, depth)) = {
+                let mut state = self.state.lock()
+                    .expect("poisoned parallel walk state");
+
+                loop {
+                    if state.stopped {
+                        return;
+                    }
+
+                    // Picking the first directory in the depth-first order
+                    // keeps the workers close to what the iterator waits for,
+                    // so that only few listings need to be buffered.
+                    let can_pick = match state.pending_dirs.first_key_value() {
+                        Some((key, _)) => {
+                            state.unconsumed_dirs < self.max_unconsumed_dirs ||
+                            state.awaited_key.as_ref() == Some(key)
+                        }
+                        None => false,
+                    };
+                    if can_pick {
+                        state.active_workers += 1;
+                        state.unconsumed_dirs += 1;
+                        break state.pending_dirs.pop_first()
+                            .expect("no pending directory");
+                    }
+
+                    // There are no pending directories and no worker is going
+                    // to add any, so the whole walk is finished.
+                    if state.pending_dirs.is_empty() && state.active_workers == 0 {
+                        state.stopped = true;
+                        self.cvar.notify_all();
+                        return;
+                    }
+
+                    state = self.cvar.wait(state)
+                        .expect("poisoned parallel walk state");
+                }
+            };
 
-        assert_eq!(results.len(), 3);
+            // Truncation of the directory is not a failure of the listing, the
+            // entries read until then are sent first and the truncation error
+            // right after them. The same goes for entries that timed out.
+            let mut truncation = None;
+            let mut timeouts = Vec::new();
+
+            let mut result = ListDir::new(&dir, depth, self.max_dir_entries, watchdog.clone()).and_then(|iter| {
+                iter
+                    .filter(|entry| match entry {
+                        Ok(entry) => !self.is_excluded(entry),
+                        Err(_) => true,
+                    })
+                    .filter_map(|entry| match entry {
+                        Err(error) if TruncatedDirError::from_io_error(&error).is_some() => {
+                            truncation = Some(error);
+                            None
+                        }
+                        Err(error) if error.kind() == std::io::ErrorKind::TimedOut => {
+                            timeouts.push(error);
+                            None
+                        }
+                        entry => Some(entry),
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()
+            });
+
+            if let Ok(entries) = &mut result {
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+            }
 
-        assert_eq!(results[0].path, tempdir.path().join("foo"));
-        assert!(results[0].metadata.is_dir());
+            let mut subdir_count = 0;
+            {
+                let mut state = self.state.lock()
+                    .expect("poisoned parallel walk state");
+
+                match &result {
+                    Ok(entries) if depth < self.max_depth => {
+                        for entry in entries {
+                            if entry.metadata.is_dir() && self.can_descend(entry) {
+                                let mut subdir_key = key.clone();
+                                subdir_key.push(subdir_count);
+                                subdir_count += 1;
+
+                                // This cannot overflow because the guard above
+                                // guarantees that `depth` is less than `u32::MAX`.
+                                state.pending_dirs.insert(subdir_key, (entry.path.clone(), depth + 1));
+                            }
+                        }
+                    }
+                    _ => (),
+                }
 
-        assert_eq!(results[1].path, tempdir.path().join("foo").join("abc"));
-        assert!(results[1].metadata.is_file());
+                state.active_workers -= 1;
+                self.cvar.notify_all();
+            }
+
+            let listing = ParWalkListing {
+                key,
+                result,
+                errors: timeouts.into_iter().chain(truncation).collect(),
+                subdir_count,
+            };
+
+            // Sending fails only if the iterator has been dropped, in which
+            // case there is no point in continuing.
+            if sender.send(listing).is_err() {
+                self.stop();
+                return;
+            }
+        }
+    }
+
+    fn is_excluded(&self, entry: &Entry) -> bool {
+        self.excludes.iter().any(|glob| glob.is_prefix_match(&entry.path))
+    }
+
+    fn can_descend(&self, entry: &Entry) -> bool {
+        match &self.fs_type_filter {
+            Some(fs_type_filter) => fs_type_filter.allows(&entry.metadata),
+            None => self.is_same_dev(entry),
+        }
+    }
+
+    /// Marks a listed directory as consumed by the iterator and records the one
+    /// with `awaited_key` as the one the iterator waits for next.
+    fn consume(&self, awaited_key: Option<&Vec<u32>>) {
+        let mut state = self.state.lock()
+            .expect("poisoned parallel walk state");
+
+        state.unconsumed_dirs -= 1;
+        state.awaited_key = awaited_key.cloned();
+        self.cvar.notify_all();
+    }
+
+    /// Stops all the workers.
+    fn stop(&self) {
+        let mut state = self.state.lock()
+            .expect("poisoned parallel walk state");
+
+        state.stopped = true;
+        self.cvar.notify_all();
+    }
+
+    #[cfg(target_family = "unix")]
+    fn is_same_dev(&self, entry: &Entry) -> bool {
+        self.dev == std::os::unix::fs::MetadataExt::dev(&entry.metadata)
+    }
+
+    #[cfg(target_family = "windows")]
+    fn is_same_dev(&self, _entry: &Entry) -> bool {
+        true
+    }
+}
+
+impl std::iter::Iterator for ParWalkDir {
+
+    type Item = std::io::Result<Entry>;
+
+    fn next(&mut self) -> Option<std::io::Result<Entry>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if let Some(error) = self.errors.next() {
+                return Some(Err(error));
+            }
+
+            // Once there are no more directories to wait for, the walk is done.
+            let key = self.awaited_keys.pop()?;
+
+            let listing = loop {
+                if let Some(listing) = self.listings.remove(&key) {
+                    break listing;
+                }
+
+                // The channel is closed once all workers are finished (and drop
+                // their senders), which should not happen before all the awaited
+                // directories are sent (unless the walk was stopped).
+                match self.receiver.as_ref()?.recv() {
+                    Ok(listing) => {
+                        self.listings.insert(listing.key.clone(), listing);
+                    }
+                    Err(std::sync::mpsc::RecvError) => return None,
+                }
+            };
+
+            // Subdirectories are pushed in reverse, so that the first one is
+            // popped first.
+            for i in (0..listing.subdir_count).rev() {
+                let mut subdir_key = key.clone();
+                subdir_key.push(i);
+                self.awaited_keys.push(subdir_key);
+            }
+            self.shared.consume(self.awaited_keys.last());
+
+            self.errors = listing.errors.into_iter();
+            match listing.result {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl Drop for ParWalkDir {
+
+    fn drop(&mut self) {
+        self.shared.stop();
+
+        // Workers might be blocked on sending to a full channel, dropping the
+        // receiver unblocks them.
+        drop(self.receiver.take());
+
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                log::error!("parallel directory walk worker panicked");
+            }
+        }
+    }
+}
+
+/// Maximum number of abandoned watchdog workers that can exist at once.
+///
+/// Abandoned workers are stuck on operations that may never complete, so we
+/// cap their number to avoid leaking threads without a bound.
+const MAX_ABANDONED_WATCHDOG_WORKERS: usize = 64;
+
+/// Number of currently existing abandoned watchdog workers.
+static ABANDONED_WATCHDOG_WORKERS: std::sync::atomic::AtomicUsize = {
+    std::sync::atomic::AtomicUsize::new(0)
+};
+
+/// Runner of filesystem operations that abandons those running for too long.
+///
+/// Operations are executed on a worker thread while the caller waits for their
+/// result for at most the configured timeout. If an operation does not finish
+/// in time (e.g. because it is stuck on an unresponsive network mount or on a
+/// FIFO without a writer), the caller gets an error of the [`TimedOut`] kind
+/// and the worker is abandoned: it exits on its own once the operation ends (if
+/// ever) and a fresh worker is spawned for subsequent operations.
+///
+/// [`TimedOut`]: std::io::ErrorKind::TimedOut
+pub struct Watchdog {
+    /// Maximum time to wait for a single operation.
+    timeout: std::time::Duration,
+    /// Worker to run the next operation on (if there is a healthy one).
+    worker: std::sync::Mutex<Option<WatchdogWorker>>,
+}
+
+/// Job executed by a watchdog worker thread.
+type WatchdogJob = Box<dyn FnOnce() + Send>;
+
+/// Handle to a watchdog worker thread.
+struct WatchdogWorker {
+    /// Sending end of the channel with jobs for the worker to execute.
+    sender: std::sync::mpsc::Sender<WatchdogJob>,
+    /// Whether the worker has been abandoned.
+    abandoned: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Watchdog {
+
+    /// Creates a watchdog abandoning operations running longer than `timeout`.
+    pub fn new(timeout: std::time::Duration) -> Watchdog {
+        Watchdog {
+            timeout,
+            worker: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Runs the given operation, abandoning it if it does not finish in time.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error of the [`TimedOut`] kind if the
+    /// operation did not finish within the timeout (or if there are too many
+    /// operations abandoned already) and the error of the operation otherwise.
+    ///
+    /// [`TimedOut`]: std::io::ErrorKind::TimedOut
+    pub fn run<T, F>(&self, operation: F) -> std::io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    {
+        use std::sync::atomic::Ordering;
+
+        let mut worker = self.worker.lock()
+            .expect("poisoned watchdog worker");
+
+        let cur_worker = match worker.take() {
+            Some(cur_worker) => cur_worker,
+            None => WatchdogWorker::spawn()?,
+        };
+
+        let (result_sender, result_receiver) = std::sync::mpsc::sync_channel(1);
+        let job = Box::new(move || {
+            // The caller might have given up on the result already, in which
+            // case there is nobody to send it to.
+            let _ = result_sender.send(operation());
+        });
+
+        if cur_worker.sender.send(job).is_err() {
+            return Err(std::io::Error::other("watchdog worker is gone"));
+        }
+
+        match result_receiver.recv_timeout(self.timeout) {
+            Ok(result) => {
+                *worker = Some(cur_worker);
+                result
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                // The worker is dropped here (together with the sending end of
+                // its job channel), so it exits once the operation ends.
+                cur_worker.abandoned.store(true, Ordering::SeqCst);
+                ABANDONED_WATCHDOG_WORKERS.fetch_add(1, Ordering::SeqCst);
+
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("operation did not finish within {:?}", self.timeout),
+                ))
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                Err(std::io::Error::other("watchdog operation panicked"))
+            }
+        }
+    }
+}
+
+impl WatchdogWorker {
+
+    /// Spawns a new worker thread for executing watchdog jobs.
+    fn spawn() -> std::io::Result<WatchdogWorker> {
+        use std::sync::atomic::Ordering;
+
+        if ABANDONED_WATCHDOG_WORKERS.load(Ordering::SeqCst) >= MAX_ABANDONED_WATCHDOG_WORKERS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "too many filesystem operations abandoned after timeout",
+            ));
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel::<WatchdogJob>();
+        let abandoned = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let worker_abandoned = abandoned.clone();
+        std::thread::Builder::new()
+            .name(String::from("rrg-fs-watchdog"))
+            .spawn(move || {
+                // The loop ends once the watchdog drops the sending end, either
+                // because it got abandoned or because the watchdog is gone.
+                for job in receiver {
+                    job();
+                }
+
+                if worker_abandoned.load(Ordering::SeqCst) {
+                    ABANDONED_WATCHDOG_WORKERS.fetch_sub(1, Ordering::SeqCst);
+                }
+            })?;
+
+        Ok(WatchdogWorker {
+            sender,
+            abandoned,
+        })
+    }
+}
+
+/// Reader that runs every read of the underlying reader under a [`Watchdog`].
+///
+/// Once a read times out, the underlying reader is abandoned together with the
+/// stuck worker and all subsequent reads fail.
+pub struct WatchdogReader<R> {
+    /// Underlying reader with a buffer for the data it reads.
+    inner: Option<(R, Vec<u8>)>,
+    /// Watchdog to run reads under.
+    watchdog: std::sync::Arc<Watchdog>,
+}
+
+impl<R: std::io::Read + Send + 'static> WatchdogReader<R> {
+
+    /// Wraps the given reader to run its reads under the `watchdog`.
+    pub fn new(inner: R, watchdog: std::sync::Arc<Watchdog>) -> WatchdogReader<R> {
+        WatchdogReader {
+            inner: Some((inner, Vec::new())),
+            watchdog,
+        }
+    }
+}
+
+impl<R: std::io::Read + Send + 'static> std::io::Read for WatchdogReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (mut inner, mut inner_buf) = self.inner.take()
+            .ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "reader abandoned after a timeout",
+            ))?;
+
+        // Data has to be read to a buffer owned by the worker as the caller's
+        // buffer might no longer exist by the time an abandoned read finishes.
+        inner_buf.resize(buf.len(), 0);
+
+        let (inner, inner_buf, result) = self.watchdog.run(move || {
+            let result = inner.read(&mut inner_buf[..]);
+            Ok((inner, inner_buf, result))
+        })?;
+
+        if let Ok(len) = result {
+            buf[..len].copy_from_slice(&inner_buf[..len]);
+        }
+        self.inner = Some((inner, inner_buf));
+
+        result
+    }
+}
+
+/// Index of mounted filesystems keyed by identifiers of their devices.
+///
+/// This is useful for finding out on which filesystem a particular file lives
+/// (by matching the device identifier of its metadata). Because listing mounts
+/// is not free, the index should be created once and reused for all the files
+/// in question.
+#[cfg(target_family = "unix")]
+pub struct Mounts {
+    /// Mounted filesystems keyed by the device identifier of their mount point.
+    by_dev: std::collections::HashMap<u64, ospect::fs::Mount>,
+}
+
+#[cfg(target_family = "unix")]
+impl Mounts {
+
+    /// Creates an index of all filesystems mounted in the system.
+    ///
+    /// Mounts that fail to be read or whose mount point cannot be accessed are
+    /// skipped. If there are multiple filesystems mounted at the same path, the
+    /// most recent (i.e. the visible) one takes precedence.
+    pub fn new() -> std::io::Result<Mounts> {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let mut by_dev = std::collections::HashMap::new();
+
+        for mount in ospect::fs::mounts()? {
+            let mount = match mount {
+                Ok(mount) => mount,
+                Err(error) => {
+                    log::warn!("failed to read mount entry: {error}");
+                    continue;
+                }
+            };
+
+            let metadata = match std::fs::metadata(&mount.path) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    log::debug! {
+                        "failed to stat mount point '{}': {error}",
+                        mount.path.display(),
+                    };
+                    continue;
+                }
+            };
+
+            by_dev.insert(metadata.dev(), mount);
+        }
+
+        Ok(Mounts {
+            by_dev,
+        })
+    }
+
+    /// Returns the filesystem on which the file with given metadata lives.
+    ///
+    /// If the device of the file does not correspond to any of the known mounts,
+    /// `None` is returned.
+    pub fn get(&self, metadata: &Metadata) -> Option<&ospect::fs::Mount> {
+        use std::os::unix::fs::MetadataExt as _;
+
+        self.by_dev.get(&metadata.dev())
+    }
+}
+
+/// Filter of filesystems that directory walks are allowed to descend into.
+///
+/// A filesystem is allowed if its type is one of the included types (or there
+/// are no included types at all) and is not one of the excluded types. Types
+/// of filesystems are known only on Unix, elsewhere all of them are allowed.
+pub struct FsTypeFilter {
+    /// Types of the mounted filesystems keyed by identifiers of their devices.
+    #[cfg(target_family = "unix")]
+    fs_types: std::collections::HashMap<u64, String>,
+    /// Types of filesystems to allow (all if empty).
+    #[cfg_attr(target_family = "windows", allow(dead_code))]
+    include: Vec<String>,
+    /// Types of filesystems to disallow.
+    #[cfg_attr(target_family = "windows", allow(dead_code))]
+    exclude: Vec<String>,
+}
+
+impl FsTypeFilter {
+
+    /// Creates a filter classifying filesystems using the system mount table.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mounts cannot be listed.
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> std::io::Result<FsTypeFilter> {
+        #[cfg(target_family = "unix")]
+        let fs_types = Mounts::new()?.by_dev.into_iter()
+            .map(|(dev, mount)| (dev, mount.fs_type))
+            .collect();
+
+        Ok(FsTypeFilter {
+            #[cfg(target_family = "unix")]
+            fs_types,
+            include,
+            exclude,
+        })
+    }
+
+    /// Checks whether a directory with the given metadata can be descended into.
+    ///
+    /// Directories on devices missing from the mount table have an unknown
+    /// filesystem type, so they are allowed only if there are no included types.
+    #[cfg(target_family = "unix")]
+    pub fn allows(&self, metadata: &Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let fs_type = self.fs_types.get(&metadata.dev());
+
+        let is_included = match fs_type {
+            _ if self.include.is_empty() => true,
+            Some(fs_type) => self.include.contains(fs_type),
+            None => false,
+        };
+        let is_excluded = match fs_type {
+            Some(fs_type) => self.exclude.contains(fs_type),
+            None => false,
+        };
+
+        is_included && !is_excluded
+    }
+
+    /// Checks whether a directory with the given metadata can be descended into.
+    #[cfg(target_family = "windows")]
+    pub fn allows(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+}
+
+/// A chain of symbolic links leading from a path to its final target.
+pub struct SymlinkChain {
+    /// Consecutive symlinks that were followed (starting with the initial path).
+    pub links: Vec<PathBuf>,
+    /// Final target of the chain.
+    ///
+    /// If the target exists, this path is fully canonicalized. Otherwise, only
+    /// the parent directory of the target is canonicalized (if it exists).
+    pub target: PathBuf,
+    /// Whether the final target of the chain exists.
+    pub exists: bool,
+    /// Whether the chain was cut short because of the length limit.
+    ///
+    /// In such case, the target is the first link that was not followed.
+    pub truncated: bool,
+}
+
+/// Follows the chain of symbolic links starting at the given path.
+///
+/// Unlike [`std::fs::canonicalize`], this function does not fail on broken
+/// symlinks. Instead, the returned chain ends at the nonexistent target and it
+/// is marked as such.
+///
+/// # Errors
+///
+/// This function will return an error if the initial path does not exist, if
+/// the chain is longer than `max_len` (e.g. because of a cycle) or if some of
+/// the links in the chain cannot be read. In the second case the error wraps a
+/// [`SymlinkLoopError`].
+pub fn symlink_chain<P: AsRef<Path>>(path: P, max_len: usize) -> std::io::Result<SymlinkChain> {
+    let path = path.as_ref();
+
+    let chain = symlink_chain_with_max_len(path, max_len)?;
+    if chain.truncated {
+        return Err(SymlinkLoopError::io_error(path, max_len));
+    }
+
+    Ok(chain)
+}
+
+/// An error indicating that resolving a path involves too many symlinks.
+///
+/// It is returned by functions following symlinks when the number of links
+/// exceeds the given limit (usually the `--max-symlink-depth` flag of the
+/// agent), e.g. because of a cycle or deliberately deep indirection. The error
+/// wrapping it is of the [`InvalidInput`] kind.
+///
+/// [`InvalidInput`]: std::io::ErrorKind::InvalidInput
+#[derive(Debug)]
+pub struct SymlinkLoopError {
+    /// Path that could not be resolved.
+    path: PathBuf,
+    /// Maximum number of symlinks that were followed.
+    max_len: usize,
+}
+
+impl SymlinkLoopError {
+
+    /// Creates an I/O error for the given path wrapping the loop error.
+    pub fn io_error<P: AsRef<Path>>(path: P, max_len: usize) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, SymlinkLoopError {
+            path: path.as_ref().to_path_buf(),
+            max_len,
+        })
+    }
+
+    /// Returns the loop error wrapped in the given I/O error (if any).
+    pub fn from_io_error(error: &std::io::Error) -> Option<&SymlinkLoopError> {
+        error.get_ref()?.downcast_ref()
+    }
+
+    /// Returns the path that could not be resolved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl std::fmt::Display for SymlinkLoopError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "too many levels of symbolic links in '{}' (more than {})",
+            self.path.display(), self.max_len,
+        }
+    }
+}
+
+impl std::error::Error for SymlinkLoopError {
+}
+
+/// Follows the chain of symbolic links up to `max_len` links.
+///
+/// This is the same as [`symlink_chain`] except that longer chains are not
+/// an error but are returned truncated (and marked as such).
+///
+/// # Errors
+///
+/// This function will return an error if the initial path does not exist or
+/// if some of the links in the chain cannot be read.
+pub fn symlink_chain_with_max_len<P: AsRef<Path>>(
+    path: P,
+    max_len: usize,
+) -> std::io::Result<SymlinkChain> {
+    let mut links = Vec::new();
+    let mut current = path.as_ref().to_path_buf();
+
+    // We want to fail if the initial path does not exist but not if some of the
+    // links in the chain are broken.
+    std::fs::symlink_metadata(&current)?;
+
+    loop {
+        let metadata = match std::fs::symlink_metadata(&current) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let target = match (current.parent(), current.file_name()) {
+                    (Some(parent), Some(file_name)) => match parent.canonicalize() {
+                        Ok(parent) => parent.join(file_name),
+                        Err(_) => current,
+                    },
+                    _ => current,
+                };
+
+                return Ok(SymlinkChain {
+                    links,
+                    target,
+                    exists: false,
+                    truncated: false,
+                });
+            }
+            Err(error) => return Err(error),
+        };
+
+        if !metadata.file_type().is_symlink() {
+            return Ok(SymlinkChain {
+                links,
+                target: current.canonicalize()?,
+                exists: true,
+                truncated: false,
+            });
+        }
+
+        if links.len() >= max_len {
+            return Ok(SymlinkChain {
+                links,
+                target: current,
+                exists: true,
+                truncated: true,
+            });
+        }
+
+        let target = std::fs::read_link(&current)?;
+        let next = match current.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+
+        links.push(std::mem::replace(&mut current, next));
+    }
+}
+
+/// Returns paths to existing filesystem items matching the given pattern.
+///
+/// Individual components of the pattern can use the following wildcards:
+///
+///   * `*` matches any (possibly empty) sequence of characters,
+///   * `?` matches any single character,
+///   * `[...]` matches any character from the given set (ranges like `a-z` are
+///     supported and `[!...]` negates the set).
+///
+/// Wildcards never match the path separator, so each component of the pattern
+/// corresponds to exactly one component of the matched path. Returned paths
+/// are sorted.
+///
+/// # Errors
+///
+/// This function will return an error if the pattern is not absolute. Errors
+/// encountered when listing intermediate directories are ignored.
+pub fn glob<P: AsRef<Path>>(pattern: P) -> std::io::Result<Vec<PathBuf>> {
+    let pattern = pattern.as_ref();
+    if !pattern.is_absolute() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("non-absolute glob pattern '{}'", pattern.display()),
+        ));
+    }
+
+    let mut paths = vec![PathBuf::new()];
+
+    for component in pattern.components() {
+        let component = component.as_os_str();
+
+        let regex = match glob_component_regex(component) {
+            Some(regex) => regex,
+            None => {
+                for path in &mut paths {
+                    path.push(component);
+                }
+                continue;
+            }
+        };
+
+        let mut matches = Vec::new();
+        for path in &paths {
+            let entries = match std::fs::read_dir(path) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(Result::ok) {
+                if regex.is_match(&entry.file_name().to_string_lossy()) {
+                    matches.push(entry.path());
+                }
+            }
+        }
+
+        matches.sort();
+        paths = matches;
+    }
+
+    paths.retain(|path| std::fs::symlink_metadata(path).is_ok());
+    Ok(paths)
+}
+
+/// A compiled glob pattern that can be matched against paths.
+///
+/// Patterns support the same wildcards as the [`glob`] function. Unlike it,
+/// matching does not touch the filesystem at all.
+#[derive(Clone)]
+pub struct Glob {
+    /// Matchers of consecutive components of the pattern.
+    components: Vec<GlobComponent>,
+}
+
+/// A matcher of a single glob pattern component.
+#[derive(Clone)]
+enum GlobComponent {
+    /// Component without wildcards that has to match literally.
+    Literal(std::ffi::OsString),
+    /// Component with wildcards.
+    Regex(regex::Regex),
+}
+
+impl Glob {
+
+    /// Compiles the given glob pattern.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pattern is not absolute.
+    pub fn new<P: AsRef<Path>>(pattern: P) -> std::io::Result<Glob> {
+        let pattern = pattern.as_ref();
+        if !pattern.is_absolute() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("non-absolute glob pattern '{}'", pattern.display()),
+            ));
+        }
+
+        let components = pattern.components()
+            .map(|component| {
+                let component = component.as_os_str();
+                match glob_component_regex(component) {
+                    Some(regex) => GlobComponent::Regex(regex),
+                    None => GlobComponent::Literal(component.to_os_string()),
+                }
+            })
+            .collect();
+
+        Ok(Glob {
+            components,
+        })
+    }
+
+    /// Checks whether the given path or any of its ancestors matches the glob.
+    ///
+    /// In other words, this verifies whether the path belongs to a subtree
+    /// rooted at a path matching the pattern.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let glob = rrg::fs::Glob::new("/home/*/.cache").unwrap();
+    ///
+    /// assert!(glob.is_prefix_match("/home/foo/.cache"));
+    /// assert!(glob.is_prefix_match("/home/foo/.cache/bar"));
+    /// assert!(!glob.is_prefix_match("/home/foo"));
+    /// assert!(!glob.is_prefix_match("/home/foo/.config"));
+    /// ```
+    pub fn is_prefix_match<P: AsRef<Path>>(&self, path: P) -> bool {
+        let mut path_components = path.as_ref().components();
+
+        self.components.iter().all(|component| {
+            let path_component = match path_components.next() {
+                Some(path_component) => path_component.as_os_str(),
+                None => return false,
+            };
+
+            match component {
+                GlobComponent::Literal(literal) => literal == path_component,
+                GlobComponent::Regex(regex) => {
+                    regex.is_match(&path_component.to_string_lossy())
+                }
+            }
+        })
+    }
+}
+
+/// Returns patterns of paths that should not be walked unless asked for.
+///
+/// On Linux this excludes the `/proc` pseudo-filesystem as its contents keep
+/// changing and walking it can yield an enormous number of entries. Patterns
+/// matching the given `root` (e.g. if one explicitly walks `/proc`) are not
+/// included.
+pub fn default_walk_excludes<P: AsRef<Path>>(root: P) -> Vec<Glob> {
+    #[cfg(target_os = "linux")]
+    let patterns: &[&str] = &["/proc"];
+
+    #[cfg(not(target_os = "linux"))]
+    let patterns: &[&str] = &[];
+
+    patterns.iter()
+        .map(|pattern| Glob::new(pattern).expect("invalid default exclude"))
+        .filter(|glob| !glob.is_prefix_match(root.as_ref()))
+        .collect()
+}
+
+/// Reinterprets the absolute `path` as rooted at the alternate `root`.
+///
+/// This can be used to run requests written against the live filesystem (e.g.
+/// for `/etc/passwd`) against a snapshot of it mounted somewhere else (e.g. at
+/// `/mnt/snap`, yielding `/mnt/snap/etc/passwd`). The conversion is lexical
+/// and does not touch the filesystem, see [`check_within_root`] for verifying
+/// that resolving the path does not escape the root through symlinks.
+///
+/// # Errors
+///
+/// This function will return an error if the path is not absolute or if it has
+/// `..` components (as these could be used to escape the root).
+///
+/// # Examples
+///
+/// ```
+/// let path = rrg::fs::reroot("/mnt/snap", "/etc/passwd")
+///     .unwrap();
+///
+/// assert_eq!(path, std::path::Path::new("/mnt/snap/etc/passwd"));
+/// ```
+pub fn reroot<R, P>(root: R, path: P) -> std::io::Result<PathBuf>
+where
+    R: AsRef<Path>,
+    P: AsRef<Path>,
+{
+    use std::path::Component;
+
+    let path = path.as_ref();
+    if !path.is_absolute() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("non-absolute path '{}'", path.display()),
+        ));
+    }
+
+    let mut rerooted = root.as_ref().to_path_buf();
+
+    for component in path.components() {
+        match component {
+            // On Windows, drive letters of the original path are meaningless in
+            // the context of the alternate root, so we just drop them.
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => (),
+            Component::ParentDir => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("path '{}' escapes the alternate root", path.display()),
+                ));
+            }
+            Component::Normal(component) => rerooted.push(component),
+        }
+    }
+
+    Ok(rerooted)
+}
+
+/// Verifies that resolving the given path does not escape the given root.
+///
+/// Only the parent of the path is resolved, as the last component (if it is a
+/// symlink) is never followed by the actions operating on alternate roots.
+///
+/// # Errors
+///
+/// This function will return an error if the resolved path is outside of the
+/// root or if resolution fails (e.g. because the parent does not exist).
+pub fn check_within_root<R, P>(root: R, path: P) -> std::io::Result<()>
+where
+    R: AsRef<Path>,
+    P: AsRef<Path>,
+{
+    let root = root.as_ref().canonicalize()?;
+
+    // The root itself is always fine, even if it is a symlink (e.g. to the
+    // directory where the snapshot is mounted).
+    let path = path.as_ref();
+    if path.canonicalize().is_ok_and(|path| path == root) {
+        return Ok(());
+    }
+
+    let resolved = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => parent.canonicalize()?.join(file_name),
+        _ => path.canonicalize()?,
+    };
+
+    if !resolved.starts_with(&root) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("path '{}' escapes the alternate root", path.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Creates a directory private to the agent (unless it exists already).
+///
+/// On Unix, a missing directory is created with `0700` permissions. Existing
+/// directories are verified to be owned by the agent and not writable by group
+/// or others, so that nobody else can plant or swap files in them. On Windows, the
+/// directory inherits access control lists of its parent and only its type is
+/// verified.
+///
+/// # Errors
+///
+/// This function will return an error if the directory cannot be created or if
+/// the existing one (or a symlink at its path) does not pass the verification.
+pub fn create_private_dir<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
+    let path = path.as_ref();
+
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::DirBuilderExt as _;
+        builder.mode(0o700);
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match builder.create(path) {
+        Ok(()) => (),
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => (),
+        Err(error) => return Err(error),
+    }
+
+    // We do not follow symlinks: they could be swapped for a different target
+    // at any moment.
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is not a directory", path.display()),
+        ));
+    }
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+
+        // SAFETY: This function is always safe to call.
+        let euid = unsafe { libc::geteuid() };
+        if metadata.uid() != euid {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("'{}' is owned by user {} (expected {euid})", path.display(), metadata.uid()),
+            ));
+        }
+
+        if metadata.mode() & 0o022 != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("'{}' is writable by group or others", path.display()),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether files opened for reading should have their access time preserved.
+static PRESERVE_ATIME: std::sync::atomic::AtomicBool = {
+    std::sync::atomic::AtomicBool::new(false)
+};
+
+/// Sets whether files opened for reading should have their access time preserved.
+///
+/// This affects [`open_file`] and [`open_regular_file`] calls made afterwards
+/// by all threads of the agent. The access time is not preserved by default.
+pub fn set_preserve_atime(preserve: bool) {
+    PRESERVE_ATIME.store(preserve, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Opens a file for reading.
+///
+/// Unlike [`std::fs::File::open`], this function does not update the access
+/// time of the file if preserving it is enabled (see [`set_preserve_atime`])
+/// and the system allows it. This is currently supported only on Linux where
+/// it is possible for files owned by the agent or if the agent has the
+/// `CAP_FOWNER` capability. Files for which the access time cannot be preserved
+/// are still opened (a warning is logged).
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be opened.
+pub fn open_file<P: AsRef<Path>>(path: P) -> std::io::Result<std::fs::File> {
+    #[cfg(target_family = "unix")]
+    let file = open_read_with_flags(path.as_ref(), 0)?;
+    #[cfg(not(target_family = "unix"))]
+    let file = open_read_with_flags(path.as_ref())?;
+
+    Ok(file)
+}
+
+/// Opens a file for reading with the given custom `open` flags.
+///
+/// The access time of the file is preserved if possible and enabled.
+#[cfg(target_family = "unix")]
+fn open_read_with_flags(path: &Path, flags: libc::c_int) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt as _;
+
+    #[cfg(target_os = "linux")]
+    if PRESERVE_ATIME.load(std::sync::atomic::Ordering::Relaxed) {
+        let result = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(flags | libc::O_NOATIME)
+            .open(path);
+
+        match result {
+            Ok(file) => return Ok(file),
+            // `O_NOATIME` is allowed only to owners of the file (or processes
+            // with the `CAP_FOWNER` capability), otherwise we fall back to the
+            // normal opening.
+            Err(error) if error.raw_os_error() == Some(libc::EPERM) => {
+                log::warn!("cannot preserve access time of '{}': {error}", path.display());
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if PRESERVE_ATIME.load(std::sync::atomic::Ordering::Relaxed) {
+        log::debug!("cannot preserve access time of '{}'", path.display());
+    }
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(path)
+}
+
+/// Opens a file for reading.
+///
+/// Preserving access time is not supported on this platform.
+#[cfg(not(target_family = "unix"))]
+fn open_read_with_flags(path: &Path) -> std::io::Result<std::fs::File> {
+    if PRESERVE_ATIME.load(std::sync::atomic::Ordering::Relaxed) {
+        log::debug!("cannot preserve access time of '{}'", path.display());
+    }
+
+    std::fs::File::open(path)
+}
+
+/// Opens a regular file for reading without following symlinks.
+///
+/// Opening special files (like FIFOs or devices) can block or have side effects
+/// (e.g. rewinding a tape), so actions that merely want to look at contents of
+/// a file they have seen during a walk should use this function: even if the
+/// file was swapped for a special file (or a symlink to one) in the meantime,
+/// it is never opened in a blocking way and is rejected.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be opened or if it is
+/// not a regular file.
+pub fn open_regular_file<P: AsRef<Path>>(path: P) -> std::io::Result<std::fs::File> {
+    let path = path.as_ref();
+
+    // Opening a FIFO without `O_NONBLOCK` blocks until there is a writer. The
+    // flag is irrelevant for reads from regular files.
+    #[cfg(target_family = "unix")]
+    let file = open_read_with_flags(path, libc::O_NOFOLLOW | libc::O_NONBLOCK)?;
+    #[cfg(not(target_family = "unix"))]
+    let file = open_read_with_flags(path)?;
+
+    if !file.metadata()?.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is not a regular file", path.display()),
+        ));
+    }
+
+    Ok(file)
+}
+
+/// Converts a single glob pattern component to a regex matching it.
+///
+/// Returns `None` if the component has no wildcards and should be matched as
+/// a literal.
+fn glob_component_regex(component: &std::ffi::OsStr) -> Option<regex::Regex> {
+    let component = component.to_string_lossy();
+    if !component.contains(['*', '?', '[']) {
+        return None;
+    }
+
+    let mut regex = String::from("^");
+
+    let mut chars = component.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for char in chars.by_ref() {
+                    if char == ']' && !class.is_empty() {
+                        closed = true;
+                        break;
+                    }
+                    class.push(char);
+                }
+
+                if !closed {
+                    // Unterminated set is treated as a literal sequence.
+                    regex.push_str(&regex::escape(&format!("[{class}")));
+                    continue;
+                }
+
+                regex.push('[');
+                let class = match class.strip_prefix('!') {
+                    Some(class) => {
+                        regex.push('^');
+                        class
+                    }
+                    None => &class,
+                };
+                for char in class.chars() {
+                    match char {
+                        '-' => regex.push('-'),
+                        _ => regex.push_str(&regex::escape(&char.to_string())),
+                    }
+                }
+                regex.push(']');
+            }
+            _ => regex.push_str(&regex::escape(&char.to_string())),
+        }
+    }
+
+    regex.push('$');
+
+    // The regex is built from escaped literals and well-formed constructs so
+    // it is always valid.
+    Some(regex::Regex::new(&regex).expect("invalid glob regex"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn test_walk_dir_non_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let iter = walk_dir(tempdir.path().join("foo"));
+        assert!(iter.is_err());
+    }
+
+    #[test]
+    fn walk_dir_not_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        File::create(tempdir.path().join("foo")).unwrap();
+
+        let iter = walk_dir(tempdir.path().join("foo"));
+        assert!(iter.is_err());
+    }
+
+    #[test]
+    fn test_walk_dir_empty() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let iter = walk_dir(&tempdir).unwrap();
+        assert_eq!(iter.count(), 0);
+    }
+
+    #[test]
+    fn test_walk_dir_with_flat_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        File::create(tempdir.path().join("abc")).unwrap();
+        File::create(tempdir.path().join("def")).unwrap();
+        File::create(tempdir.path().join("ghi")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0].path, tempdir.path().join("abc"));
+        assert!(results[0].metadata.is_file());
+
+        assert_eq!(results[1].path, tempdir.path().join("def"));
+        assert!(results[1].metadata.is_file());
+
+        assert_eq!(results[2].path, tempdir.path().join("ghi"));
+        assert!(results[2].metadata.is_file());
+    }
+
+    #[test]
+    fn test_walk_dir_with_flat_dirs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("abc")).unwrap();
+        std::fs::create_dir(tempdir.path().join("def")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(results[0].path, tempdir.path().join("abc"));
+        assert!(results[0].metadata.is_dir());
+
+        assert_eq!(results[1].path, tempdir.path().join("def"));
+        assert!(results[1].metadata.is_dir());
+    }
+
+    #[test]
+    fn test_walk_dir_with_nested_dirs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("abc")).unwrap();
+        std::fs::create_dir(tempdir.path().join("abc").join("def")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(results[0].path, tempdir.path().join("abc"));
+        assert!(results[0].metadata.is_dir());
+
+        assert_eq!(results[1].path, tempdir.path().join("abc").join("def"));
+        assert!(results[1].metadata.is_dir());
+    }
+
+    // Both Windows and macOS have limits on the path length. Both of these
+    // limits are very low (260 and 1016 respectively), so making the created
+    // hierarchy to fit these would render the test quite useless. Hence, we
+    // simply ignore it on these platforms.
+    #[cfg_attr(target_os = "macos", ignore)]
+    #[cfg_attr(target_os = "windows", ignore)]
+    #[test]
+    fn test_walk_dir_with_deeply_nested_dirs() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let mut dir = tempdir.path().to_path_buf();
+        for _ in 0..512 {
+            dir.push("foo");
+            std::fs::create_dir(&dir).unwrap();
+        }
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 512);
+
+        for entry in &results {
+            assert!(entry.path.starts_with(&tempdir));
+            assert!(entry.path.ends_with("foo"));
+            assert!(entry.metadata.is_dir());
+        }
+    }
+
+    #[test]
+    fn test_walk_dir_with_files_inside_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("foo")).unwrap();
+        File::create(tempdir.path().join("foo").join("abc")).unwrap();
+        File::create(tempdir.path().join("foo").join("def")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0].path, tempdir.path().join("foo"));
+        assert!(results[0].metadata.is_dir());
+
+        assert_eq!(results[1].path, tempdir.path().join("foo").join("abc"));
+        assert!(results[1].metadata.is_file());
 
         assert_eq!(results[2].path, tempdir.path().join("foo").join("def"));
         assert!(results[2].metadata.is_file());
     }
 
-    // Symlinking is supported only on Unix-like systems.
+    // Symlinking is supported only on Unix-like systems.
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_walk_dir_with_dir_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("abc");
+        let file = dir.join("def");
+        let symlink = tempdir.path().join("ghi");
+
+        std::fs::create_dir(&dir).unwrap();
+        File::create(&file).unwrap();
+        std::os::unix::fs::symlink(&dir, &symlink).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0].path, dir);
+        assert!(results[0].metadata.file_type().is_dir());
+
+        assert_eq!(results[1].path, file);
+        assert!(results[1].metadata.file_type().is_file());
+
+        assert_eq!(results[2].path, symlink);
+        assert!(results[2].metadata.file_type().is_symlink());
+    }
+
+    // Symlinking is supported only on Unix-like systems.
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_walk_dir_with_circular_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("foo");
+        let symlink = tempdir.path().join("foo").join("bar");
+
+        std::fs::create_dir(&dir).unwrap();
+        std::os::unix::fs::symlink(&dir, &symlink).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(results[0].path, dir);
+        assert!(results[0].metadata.file_type().is_dir());
+
+        assert_eq!(results[1].path, symlink);
+        assert!(results[1].metadata.file_type().is_symlink());
+    }
+
+    // macOS mangles Unicode-specific characters in filenames.
+    #[cfg_attr(target_os = "macos", ignore)]
+    #[test]
+    fn test_walk_dir_with_unicode_names() {
+        let tempdir = tempfile::tempdir().unwrap();
+        File::create(tempdir.path().join("zażółć gęślą jaźń")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, tempdir.path().join("zażółć gęślą jaźń"));
+    }
+
+    #[test]
+    fn test_walk_dir_metadata_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"123456789").unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].metadata.len(), 9);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn walk_dir_listed_types() {
+        let tempdir = tempfile::tempdir().unwrap();
+        File::create(tempdir.path().join("file")).unwrap();
+        std::fs::create_dir(tempdir.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink("file", tempdir.path().join("link")).unwrap();
+
+        let mut results = walk_dir(&tempdir).unwrap()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        results.sort_by_key(|entry| entry.path.clone());
+
+        assert_eq!(results.len(), 3);
+
+        assert!(results[0].listed_type.unwrap().is_dir());
+        assert!(!results[0].has_type_mismatch());
+
+        assert!(results[1].listed_type.unwrap().is_file());
+        assert!(!results[1].has_type_mismatch());
+
+        assert!(results[2].listed_type.unwrap().is_symlink());
+        assert!(!results[2].has_type_mismatch());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn is_type_mismatch_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        File::create(tempdir.path().join("file")).unwrap();
+        std::os::unix::fs::symlink("file", tempdir.path().join("link")).unwrap();
+
+        let entry = std::fs::read_dir(&tempdir).unwrap()
+            .map(Result::unwrap)
+            .find(|entry| entry.file_name() == "link")
+            .unwrap();
+        let listed_type = entry.file_type().unwrap();
+
+        // The listing reports the symlink itself, so it agrees with `lstat`
+        // but not with `stat` (which follows the symlink to a regular file).
+        let lstat = std::fs::symlink_metadata(entry.path()).unwrap();
+        assert!(!is_type_mismatch(listed_type, &lstat));
+
+        let stat = std::fs::metadata(entry.path()).unwrap();
+        assert!(is_type_mismatch(listed_type, &stat));
+
+        let entry = Entry {
+            path: entry.path(),
+            metadata: stat,
+            listed_type: Some(listed_type),
+        };
+        assert!(entry.has_type_mismatch());
+    }
+
+    #[test]
+    fn entry_without_listed_type() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let entry = Entry {
+            path: tempdir.path().to_path_buf(),
+            metadata: std::fs::symlink_metadata(&tempdir).unwrap(),
+            listed_type: None,
+        };
+        assert!(!entry.has_type_mismatch());
+    }
+
+    #[test]
+    #[should_panic]
+    fn walk_dir_with_max_depth_0() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let _ = walk_dir(tempdir.path()).unwrap()
+            .with_max_depth(0);
+    }
+
+    #[test]
+    fn walk_dir_with_max_depth_1() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        std::fs::create_dir(tempdir.join("abc"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("abc").join("def"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("ghi"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("ghi").join("jkl"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("mno"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("mno").join("pqr"))
+            .unwrap();
+
+        let results = walk_dir(&tempdir).unwrap().with_max_depth(1)
+            .filter_map(Result::ok);
+
+        let paths = results.map(|entry| entry.path)
+            .collect::<Vec<_>>();
+
+        assert!(paths.contains(&tempdir.join("abc")));
+        assert!(paths.contains(&tempdir.join("ghi")));
+        assert!(paths.contains(&tempdir.join("mno")));
+
+        assert!(!paths.contains(&tempdir.join("abc").join("def")));
+        assert!(!paths.contains(&tempdir.join("ghi").join("jkl")));
+        assert!(!paths.contains(&tempdir.join("mno").join("pqr")));
+    }
+
+    #[test]
+    fn walk_dir_with_max_depth_2() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        std::fs::create_dir(tempdir.join("a"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("a").join("b"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("a").join("b").join("c"))
+            .unwrap();
+        std::fs::create_dir(tempdir.join("a").join("b").join("c").join("d"))
+            .unwrap();
+
+        let results = walk_dir(tempdir).unwrap().with_max_depth(2)
+            .filter_map(Result::ok);
+
+        let paths = results.map(|entry| entry.path)
+            .collect::<Vec<_>>();
+
+        assert!(paths.contains(&tempdir.join("a")));
+        assert!(paths.contains(&tempdir.join("a").join("b")));
+
+        assert!(!paths.contains(&tempdir.join("a").join("b").join("c")));
+        assert!(!paths.contains(&tempdir.join("a").join("b").join("c").join("d")));
+    }
+
+    #[test]
+    fn glob_not_absolute() {
+        assert!(glob("foo/*").is_err());
+    }
+
+    #[test]
+    fn glob_literal() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        File::create(tempdir.join("foo")).unwrap();
+
+        assert_eq!(glob(tempdir.join("foo")).unwrap(), vec![tempdir.join("foo")]);
+        assert!(glob(tempdir.join("bar")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn glob_star() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        File::create(tempdir.join("foo.txt")).unwrap();
+        File::create(tempdir.join("bar.txt")).unwrap();
+        File::create(tempdir.join("baz.log")).unwrap();
+
+        assert_eq!(glob(tempdir.join("*.txt")).unwrap(), vec![
+            tempdir.join("bar.txt"),
+            tempdir.join("foo.txt"),
+        ]);
+    }
+
+    #[test]
+    fn glob_question_mark_and_set() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        File::create(tempdir.join("a1")).unwrap();
+        File::create(tempdir.join("a2")).unwrap();
+        File::create(tempdir.join("b3")).unwrap();
+        File::create(tempdir.join("a12")).unwrap();
+
+        assert_eq!(glob(tempdir.join("a?")).unwrap(), vec![
+            tempdir.join("a1"),
+            tempdir.join("a2"),
+        ]);
+        assert_eq!(glob(tempdir.join("[!a]?")).unwrap(), vec![
+            tempdir.join("b3"),
+        ]);
+        assert_eq!(glob(tempdir.join("[a-b][2-3]")).unwrap(), vec![
+            tempdir.join("a2"),
+            tempdir.join("b3"),
+        ]);
+    }
+
+    #[test]
+    fn glob_nested() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        std::fs::create_dir(tempdir.join("foo")).unwrap();
+        std::fs::create_dir(tempdir.join("bar")).unwrap();
+        File::create(tempdir.join("foo").join("quux")).unwrap();
+        File::create(tempdir.join("bar").join("quux")).unwrap();
+        File::create(tempdir.join("bar").join("norf")).unwrap();
+
+        assert_eq!(glob(tempdir.join("*").join("quux")).unwrap(), vec![
+            tempdir.join("bar").join("quux"),
+            tempdir.join("foo").join("quux"),
+        ]);
+    }
+
+    #[test]
+    fn symlink_chain_not_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        assert!(symlink_chain(tempdir.path().join("foo"), 40).is_err());
+    }
+
+    #[test]
+    fn symlink_chain_not_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path().canonicalize().unwrap();
+
+        File::create(tempdir.join("foo")).unwrap();
+
+        let chain = symlink_chain(tempdir.join("foo"), 40).unwrap();
+        assert!(chain.links.is_empty());
+        assert_eq!(chain.target, tempdir.join("foo"));
+        assert!(chain.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn symlink_chain_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path().canonicalize().unwrap();
+
+        File::create(tempdir.join("foo")).unwrap();
+        std::os::unix::fs::symlink("foo", tempdir.join("bar")).unwrap();
+        std::os::unix::fs::symlink(tempdir.join("bar"), tempdir.join("baz")).unwrap();
+
+        let chain = symlink_chain(tempdir.join("baz"), 40).unwrap();
+        assert_eq!(chain.links, vec![tempdir.join("baz"), tempdir.join("bar")]);
+        assert_eq!(chain.target, tempdir.join("foo"));
+        assert!(chain.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn symlink_chain_broken_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path().canonicalize().unwrap();
+
+        std::os::unix::fs::symlink("foo", tempdir.join("bar")).unwrap();
+
+        let chain = symlink_chain(tempdir.join("bar"), 40).unwrap();
+        assert_eq!(chain.links, vec![tempdir.join("bar")]);
+        assert_eq!(chain.target, tempdir.join("foo"));
+        assert!(!chain.exists);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn symlink_chain_cycle() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        std::os::unix::fs::symlink("foo", tempdir.join("bar")).unwrap();
+        std::os::unix::fs::symlink("bar", tempdir.join("foo")).unwrap();
+
+        assert!(symlink_chain(tempdir.join("foo"), 40).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn symlink_chain_too_long() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempdir = tempdir.path();
+
+        std::fs::File::create(tempdir.join("file")).unwrap();
+        std::os::unix::fs::symlink("file", tempdir.join("link0")).unwrap();
+        for i in 1..8 {
+            std::os::unix::fs::symlink(format!("link{}", i - 1), tempdir.join(format!("link{i}"))).unwrap();
+        }
+
+        assert!(symlink_chain(tempdir.join("link7"), 8).is_ok());
+
+        let error = symlink_chain(tempdir.join("link7"), 7).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let error = SymlinkLoopError::from_io_error(&error).unwrap();
+        assert_eq!(error.path(), tempdir.join("link7"));
+        assert!(error.to_string().contains("too many levels"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mounts_get_tempfile() {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let tempfile = tempfile::NamedTempFile::new().unwrap();
+        let metadata = tempfile.path().metadata().unwrap();
+
+        let mounts = Mounts::new().unwrap();
+        let mount = mounts.get(&metadata).unwrap();
+
+        let path = tempfile.path().canonicalize().unwrap();
+        assert!(path.starts_with(&mount.path));
+        assert_eq!(mount.path.metadata().unwrap().dev(), metadata.dev());
+        assert!(!mount.fs_type.is_empty());
+    }
+
+    #[test]
+    fn par_walk_dir_non_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        assert!(par_walk_dir(tempdir.path().join("foo"), u32::MAX, u64::MAX, vec![], None, None, 4).is_err());
+    }
+
+    #[test]
+    fn par_walk_dir_same_as_walk_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        for i in 0..8 {
+            let dir = tempdir.path().join(format!("dir{i}"));
+            std::fs::create_dir(&dir).unwrap();
+
+            for j in 0..8 {
+                let subdir = dir.join(format!("subdir{j}"));
+                std::fs::create_dir(&subdir).unwrap();
+
+                File::create(subdir.join("foo")).unwrap();
+                File::create(subdir.join("bar")).unwrap();
+            }
+
+            File::create(dir.join("file")).unwrap();
+        }
+
+        let mut paths = walk_dir(tempdir.path()).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut par_paths = par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, vec![], None, None, 4).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        par_paths.sort();
+
+        assert_eq!(paths.len(), 8 * (1 + 8 * 3 + 1));
+        assert_eq!(par_paths, paths);
+    }
+
+    #[test]
+    fn par_walk_dir_deterministic() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        for i in 0..16 {
+            let dir = tempdir.path().join(format!("dir{i}"));
+            std::fs::create_dir(&dir).unwrap();
+
+            for j in 0..4 {
+                let subdir = dir.join(format!("subdir{j}"));
+                std::fs::create_dir(&subdir).unwrap();
+
+                File::create(subdir.join("foo")).unwrap();
+                File::create(subdir.join("bar")).unwrap();
+            }
+
+            File::create(dir.join("file")).unwrap();
+        }
+
+        let par_paths = || {
+            par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, vec![], None, None, 8).unwrap()
+                .map(|entry| entry.unwrap().path)
+                .collect::<Vec<_>>()
+        };
+
+        let paths = par_paths();
+        assert_eq!(paths.len(), 16 * (1 + 4 * 3 + 1));
+
+        // Every directory is followed by its contents.
+        assert_eq!(paths[0], tempdir.path().join("dir0"));
+        assert_eq!(paths[1], tempdir.path().join("dir1"));
+        assert_eq!(paths[16], tempdir.path().join("dir0").join("file"));
+
+        for _ in 0..16 {
+            assert_eq!(par_paths(), paths);
+        }
+    }
+
+    #[test]
+    fn par_walk_dir_drop_early() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        for i in 0..64 {
+            let dir = tempdir.path().join(format!("dir{i}"));
+            std::fs::create_dir(&dir).unwrap();
+            File::create(dir.join("file")).unwrap();
+        }
+
+        let mut iter = par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, vec![], None, None, 4).unwrap();
+        assert!(iter.next().is_some());
+
+        // This should not hang even though workers have more to send.
+        drop(iter);
+    }
+
+    #[test]
+    fn par_walk_dir_with_max_depth() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("a").join("b").join("c")).unwrap();
+
+        let mut paths = par_walk_dir(tempdir.path(), 2, u64::MAX, vec![], None, None, 4).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            tempdir.path().join("a"),
+            tempdir.path().join("a").join("b"),
+        ]);
+    }
+
+    #[test]
+    fn glob_is_prefix_match_literal() {
+        let glob = Glob::new("/foo/bar").unwrap();
+
+        assert!(glob.is_prefix_match("/foo/bar"));
+        assert!(glob.is_prefix_match("/foo/bar/baz"));
+        assert!(!glob.is_prefix_match("/foo"));
+        assert!(!glob.is_prefix_match("/foo/barbaz"));
+    }
+
+    #[test]
+    fn glob_is_prefix_match_wildcard() {
+        let glob = Glob::new("/foo/*.log").unwrap();
+
+        assert!(glob.is_prefix_match("/foo/bar.log"));
+        assert!(glob.is_prefix_match("/foo/bar.log/baz"));
+        assert!(!glob.is_prefix_match("/foo/bar.txt"));
+    }
+
+    #[test]
+    fn glob_new_relative() {
+        assert!(Glob::new("foo/*").is_err());
+    }
+
+    #[test]
+    fn walk_dir_with_excludes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("foo").join("bar")).unwrap();
+        std::fs::create_dir_all(tempdir.path().join("baz").join("quux")).unwrap();
+
+        let excludes = vec![Glob::new(tempdir.path().join("f*")).unwrap()];
+
+        let mut paths = walk_dir(tempdir.path()).unwrap()
+            .with_excludes(excludes)
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            tempdir.path().join("baz"),
+            tempdir.path().join("baz").join("quux"),
+        ]);
+    }
+
+    #[test]
+    fn par_walk_dir_with_excludes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("foo").join("bar")).unwrap();
+        std::fs::create_dir_all(tempdir.path().join("baz").join("quux")).unwrap();
+
+        let excludes = vec![Glob::new(tempdir.path().join("f*")).unwrap()];
+
+        let mut paths = par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, excludes, None, None, 4).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            tempdir.path().join("baz"),
+            tempdir.path().join("baz").join("quux"),
+        ]);
+    }
+
+    // There is no way to mount a filesystem in tests, so instead we simulate a
+    // mount table in which the temporary directory lives on an excluded one.
+    #[cfg(target_family = "unix")]
+    fn tempdir_fs_type_filter(tempdir: &Path, exclude: &str) -> FsTypeFilter {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let dev = tempdir.metadata().unwrap().dev();
+
+        FsTypeFilter {
+            fs_types: std::collections::HashMap::from([(dev, String::from("rrgfs"))]),
+            include: vec![],
+            exclude: vec![String::from(exclude)],
+        }
+    }
+
     #[cfg(target_family = "unix")]
     #[test]
-    fn test_walk_dir_with_dir_symlinks() {
+    fn walk_dir_with_fs_type_filter() {
         let tempdir = tempfile::tempdir().unwrap();
-        let dir = tempdir.path().join("abc");
-        let file = dir.join("def");
-        let symlink = tempdir.path().join("ghi");
+        std::fs::create_dir_all(tempdir.path().join("foo").join("bar")).unwrap();
 
-        std::fs::create_dir(&dir).unwrap();
-        File::create(&file).unwrap();
-        std::os::unix::fs::symlink(&dir, &symlink).unwrap();
+        let mut paths = walk_dir(tempdir.path()).unwrap()
+            .with_fs_type_filter(tempdir_fs_type_filter(tempdir.path(), "rrgfs"))
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
+        assert_eq!(paths, vec![
+            tempdir.path().join("foo"),
+        ]);
+
+        let mut paths = walk_dir(tempdir.path()).unwrap()
+            .with_fs_type_filter(tempdir_fs_type_filter(tempdir.path(), "nfs"))
+            .map(|entry| entry.unwrap().path)
             .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        paths.sort();
 
-        assert_eq!(results.len(), 3);
+        assert_eq!(paths, vec![
+            tempdir.path().join("foo"),
+            tempdir.path().join("foo").join("bar"),
+        ]);
+    }
 
-        assert_eq!(results[0].path, dir);
-        assert!(results[0].metadata.file_type().is_dir());
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn par_walk_dir_with_fs_type_filter() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("foo").join("bar")).unwrap();
 
-        assert_eq!(results[1].path, file);
-        assert!(results[1].metadata.file_type().is_file());
+        let fs_type_filter = tempdir_fs_type_filter(tempdir.path(), "rrgfs");
 
-        assert_eq!(results[2].path, symlink);
-        assert!(results[2].metadata.file_type().is_symlink());
+        let mut paths = par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, vec![], Some(fs_type_filter), None, 4).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            tempdir.path().join("foo"),
+        ]);
     }
 
-    // Symlinking is supported only on Unix-like systems.
     #[cfg(target_family = "unix")]
     #[test]
-    fn test_walk_dir_with_circular_symlinks() {
+    fn fs_type_filter_include() {
         let tempdir = tempfile::tempdir().unwrap();
-        let dir = tempdir.path().join("foo");
-        let symlink = tempdir.path().join("foo").join("bar");
+        let metadata = tempdir.path().metadata().unwrap();
 
-        std::fs::create_dir(&dir).unwrap();
-        std::os::unix::fs::symlink(&dir, &symlink).unwrap();
+        let mut filter = tempdir_fs_type_filter(tempdir.path(), "nfs");
+        assert!(filter.allows(&metadata));
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        filter.include = vec![String::from("ext4")];
+        assert!(!filter.allows(&metadata));
 
-        assert_eq!(results.len(), 2);
+        filter.include = vec![String::from("ext4"), String::from("rrgfs")];
+        assert!(filter.allows(&metadata));
 
-        assert_eq!(results[0].path, dir);
-        assert!(results[0].metadata.file_type().is_dir());
+        filter.fs_types.clear();
+        assert!(!filter.allows(&metadata));
+    }
 
-        assert_eq!(results[1].path, symlink);
-        assert!(results[1].metadata.file_type().is_symlink());
+    #[test]
+    fn walk_dir_with_max_dir_entries() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("big")).unwrap();
+        for i in 0..10 {
+            std::fs::write(tempdir.path().join("big").join(i.to_string()), b"").unwrap();
+        }
+        std::fs::create_dir(tempdir.path().join("small")).unwrap();
+        for i in 0..3 {
+            std::fs::write(tempdir.path().join("small").join(i.to_string()), b"").unwrap();
+        }
+
+        let mut paths = Vec::new();
+        let mut truncated_paths = Vec::new();
+
+        for entry in walk_dir(tempdir.path()).unwrap().with_max_dir_entries(3) {
+            match entry {
+                Ok(entry) => paths.push(entry.path),
+                Err(error) => {
+                    let error = TruncatedDirError::from_io_error(&error).unwrap();
+                    truncated_paths.push(error.path().to_path_buf());
+                }
+            }
+        }
+
+        assert_eq!(truncated_paths, vec![tempdir.path().join("big")]);
+
+        let big_paths = paths.iter()
+            .filter(|path| path.parent() == Some(&tempdir.path().join("big")))
+            .count();
+        assert_eq!(big_paths, 3);
+
+        // Directories with exactly the limit of entries are not truncated.
+        let small_paths = paths.iter()
+            .filter(|path| path.parent() == Some(&tempdir.path().join("small")))
+            .count();
+        assert_eq!(small_paths, 3);
     }
 
-    // macOS mangles Unicode-specific characters in filenames.
-    #[cfg_attr(target_os = "macos", ignore)]
     #[test]
-    fn test_walk_dir_with_unicode_names() {
+    fn par_walk_dir_with_max_dir_entries() {
         let tempdir = tempfile::tempdir().unwrap();
-        File::create(tempdir.path().join("zażółć gęślą jaźń")).unwrap();
+        std::fs::create_dir(tempdir.path().join("big")).unwrap();
+        for i in 0..10 {
+            std::fs::write(tempdir.path().join("big").join(i.to_string()), b"").unwrap();
+        }
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        let mut paths = Vec::new();
+        let mut truncated_paths = Vec::new();
 
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].path, tempdir.path().join("zażółć gęślą jaźń"));
+        for entry in par_walk_dir(tempdir.path(), u32::MAX, 3, vec![], None, None, 4).unwrap() {
+            match entry {
+                Ok(entry) => paths.push(entry.path),
+                Err(error) => {
+                    let error = TruncatedDirError::from_io_error(&error).unwrap();
+                    truncated_paths.push(error.path().to_path_buf());
+                }
+            }
+        }
+
+        assert_eq!(truncated_paths, vec![tempdir.path().join("big")]);
+        assert_eq!(paths.len(), 1 + 3);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn reroot_unix() {
+        assert_eq!(reroot("/mnt/snap", "/").unwrap(), Path::new("/mnt/snap"));
+        assert_eq!(reroot("/mnt/snap", "/etc/./passwd").unwrap(), Path::new("/mnt/snap/etc/passwd"));
     }
 
     #[test]
-    fn test_walk_dir_metadata_size() {
+    fn reroot_relative() {
+        assert!(reroot(std::env::temp_dir(), "foo/bar").is_err());
+    }
+
+    #[test]
+    fn reroot_parent_dir() {
+        let root = std::env::temp_dir().join("snap");
+
+        let error = reroot(&root, std::env::temp_dir().join("..").join("foo"))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn check_within_root_ok() {
         let tempdir = tempfile::tempdir().unwrap();
-        std::fs::write(tempdir.path().join("foo"), b"123456789").unwrap();
+        std::fs::create_dir(tempdir.path().join("foo")).unwrap();
 
-        let mut results = walk_dir(&tempdir).unwrap()
-            .filter_map(Result::ok)
-            .collect::<Vec<_>>();
-        results.sort_by_key(|entry| entry.path.clone());
+        assert!(check_within_root(tempdir.path(), tempdir.path()).is_ok());
+        assert!(check_within_root(tempdir.path(), tempdir.path().join("foo")).is_ok());
+        assert!(check_within_root(tempdir.path(), tempdir.path().join("foo").join("bar")).is_ok());
+    }
 
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].metadata.len(), 9);
+    #[test]
+    fn check_within_root_non_existent() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let path = tempdir.path().join("foo").join("bar");
+        assert!(check_within_root(tempdir.path(), path).is_err());
     }
 
+    #[cfg(target_family = "unix")]
     #[test]
-    #[should_panic]
-    fn walk_dir_with_max_depth_0() {
+    fn check_within_root_symlink_escape() {
         let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("root")).unwrap();
+        std::fs::create_dir(tempdir.path().join("outside")).unwrap();
 
-        let _ = walk_dir(tempdir.path()).unwrap()
-            .with_max_depth(0);
+        let root = tempdir.path().join("root");
+        std::os::unix::fs::symlink(tempdir.path().join("outside"), root.join("link")).unwrap();
+
+        // The symlink itself lives within the root...
+        assert!(check_within_root(&root, root.join("link")).is_ok());
+
+        // ...but paths going through it do not.
+        let error = check_within_root(&root, root.join("link").join("foo"))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
     }
 
     #[test]
-    fn walk_dir_with_max_depth_1() {
+    fn create_private_dir_new() {
         let tempdir = tempfile::tempdir().unwrap();
-        let tempdir = tempdir.path();
+        let path = tempdir.path().join("foo").join("bar");
 
-        std::fs::create_dir(tempdir.join("abc"))
-            .unwrap();
-        std::fs::create_dir(tempdir.join("abc").join("def"))
+        create_private_dir(&path).unwrap();
+        assert!(path.is_dir());
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[test]
+    fn create_private_dir_existing() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        create_private_dir(tempdir.path()).unwrap();
+        create_private_dir(tempdir.path()).unwrap();
+    }
+
+    #[test]
+    fn create_private_dir_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("foo"), b"").unwrap();
+
+        assert!(create_private_dir(tempdir.path().join("foo")).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn create_private_dir_world_writable() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("foo");
+
+        std::fs::create_dir(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let error = create_private_dir(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn create_private_dir_group_writable() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("foo");
+
+        std::fs::create_dir(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o770)).unwrap();
+
+        let error = create_private_dir(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn create_private_dir_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("foo")).unwrap();
+        std::os::unix::fs::symlink(tempdir.path().join("foo"), tempdir.path().join("bar")).unwrap();
+
+        assert!(create_private_dir(tempdir.path().join("bar")).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn default_walk_excludes_proc() {
+        assert_eq!(default_walk_excludes("/").len(), 1);
+        assert!(default_walk_excludes("/proc").is_empty());
+        assert!(default_walk_excludes("/proc/self").is_empty());
+    }
+
+    #[test]
+    fn open_regular_file_ok() {
+        use std::io::Read as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"bar").unwrap();
+
+        let mut contents = String::new();
+        open_regular_file(&path).unwrap()
+            .read_to_string(&mut contents).unwrap();
+
+        assert_eq!(contents, "bar");
+    }
+
+    #[test]
+    fn open_regular_file_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let error = open_regular_file(tempdir.path()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn open_regular_file_symlink() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("foo");
+        let symlink = tempdir.path().join("bar");
+        std::fs::write(&target, b"").unwrap();
+        std::os::unix::fs::symlink(&target, &symlink).unwrap();
+
+        assert!(open_regular_file(&symlink).is_err());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn open_regular_file_fifo() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("fifo");
+
+        let path_c = std::ffi::CString::new(path.as_os_str().as_bytes())
             .unwrap();
-        std::fs::create_dir(tempdir.join("ghi"))
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
+
+        // There is no writer, so a blocking open would hang the test.
+        let error = open_regular_file(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_regular_file_char_device() {
+        assert!(open_regular_file("/dev/null").is_err());
+    }
+
+    #[test]
+    fn watchdog_run_ok() {
+        let watchdog = Watchdog::new(std::time::Duration::from_secs(60));
+
+        assert_eq!(watchdog.run(|| Ok(42)).unwrap(), 42);
+        assert_eq!(watchdog.run(|| Ok(1337)).unwrap(), 1337);
+    }
+
+    #[test]
+    fn watchdog_run_error() {
+        let watchdog = Watchdog::new(std::time::Duration::from_secs(60));
+
+        let error = watchdog.run(|| -> std::io::Result<()> {
+            Err(std::io::ErrorKind::NotFound.into())
+        }).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn watchdog_run_fifo_timeout() {
+        use std::os::unix::ffi::OsStrExt as _;
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("fifo");
+
+        let path_c = std::ffi::CString::new(path.as_os_str().as_bytes())
             .unwrap();
-        std::fs::create_dir(tempdir.join("ghi").join("jkl"))
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
+
+        let watchdog = Watchdog::new(std::time::Duration::from_millis(100));
+
+        // There is no writer, so the open blocks.
+        let fifo_path = path.clone();
+        let error = watchdog.run(move || std::fs::File::open(fifo_path))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        // Subsequent operations are run on a fresh worker.
+        assert_eq!(watchdog.run(|| Ok(42)).unwrap(), 42);
+
+        // We unblock the abandoned worker so that it does not outlive the test.
+        let _ = std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn watchdog_reader_fifo_timeout() {
+        use std::io::{Read as _, Write as _};
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("fifo");
+
+        let path_c = std::ffi::CString::new(path.as_os_str().as_bytes())
             .unwrap();
-        std::fs::create_dir(tempdir.join("mno"))
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
+
+        // On Linux opening a FIFO for both reading and writing does not block
+        // but reading from it does until there is some data.
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
             .unwrap();
-        std::fs::create_dir(tempdir.join("mno").join("pqr"))
+        let mut writer = file.try_clone()
             .unwrap();
 
-        let results = walk_dir(&tempdir).unwrap().with_max_depth(1)
-            .filter_map(Result::ok);
+        let watchdog = Watchdog::new(std::time::Duration::from_millis(100));
+        let mut reader = WatchdogReader::new(file, std::sync::Arc::new(watchdog));
 
-        let paths = results.map(|entry| entry.path)
-            .collect::<Vec<_>>();
+        let mut buf = [0; 8];
 
-        assert!(paths.contains(&tempdir.join("abc")));
-        assert!(paths.contains(&tempdir.join("ghi")));
-        assert!(paths.contains(&tempdir.join("mno")));
+        let error = reader.read(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
 
-        assert!(!paths.contains(&tempdir.join("abc").join("def")));
-        assert!(!paths.contains(&tempdir.join("ghi").join("jkl")));
-        assert!(!paths.contains(&tempdir.join("mno").join("pqr")));
+        // The reader is abandoned, so further reads fail without blocking.
+        let error = reader.read(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        // We unblock the abandoned worker so that it does not outlive the test.
+        writer.write_all(b"foo").unwrap();
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn walk_dir_with_max_depth_2() {
+    fn watchdog_reader_ok() {
+        use std::io::Read as _;
+
         let tempdir = tempfile::tempdir().unwrap();
-        let tempdir = tempdir.path();
+        std::fs::write(tempdir.path().join("foo"), b"foobar")
+            .unwrap();
 
-        std::fs::create_dir(tempdir.join("a"))
+        let file = std::fs::File::open(tempdir.path().join("foo"))
             .unwrap();
-        std::fs::create_dir(tempdir.join("a").join("b"))
+
+        let watchdog = Watchdog::new(std::time::Duration::from_secs(60));
+        let mut reader = WatchdogReader::new(file, std::sync::Arc::new(watchdog));
+
+        let mut buf = [0; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"foob");
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ar");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn walk_dir_entry_timeout_with_fifo() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("foo"))
             .unwrap();
-        std::fs::create_dir(tempdir.join("a").join("b").join("c"))
+        std::fs::write(tempdir.path().join("foo").join("bar"), b"")
             .unwrap();
-        std::fs::create_dir(tempdir.join("a").join("b").join("c").join("d"))
+
+        let path_c = std::ffi::CString::new(tempdir.path().join("fifo").as_os_str().as_bytes())
             .unwrap();
+        // SAFETY: The path is a valid null-terminated string.
+        assert_eq!(unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) }, 0);
 
-        let results = walk_dir(tempdir).unwrap().with_max_depth(2)
-            .filter_map(Result::ok);
+        let timeout = std::time::Duration::from_secs(60);
 
-        let paths = results.map(|entry| entry.path)
+        let mut paths = walk_dir(tempdir.path()).unwrap().with_entry_timeout(timeout)
+            .map(|entry| entry.unwrap().path)
             .collect::<Vec<_>>();
+        paths.sort();
 
-        assert!(paths.contains(&tempdir.join("a")));
-        assert!(paths.contains(&tempdir.join("a").join("b")));
+        assert_eq!(paths, vec! {
+            tempdir.path().join("fifo"),
+            tempdir.path().join("foo"),
+            tempdir.path().join("foo").join("bar"),
+        });
 
-        assert!(!paths.contains(&tempdir.join("a").join("b").join("c")));
-        assert!(!paths.contains(&tempdir.join("a").join("b").join("c").join("d")));
+        let mut par_paths = par_walk_dir(tempdir.path(), u32::MAX, u64::MAX, vec![], None, Some(timeout), 4).unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect::<Vec<_>>();
+        par_paths.sort();
+
+        assert_eq!(par_paths, paths);
+    }
+
+    #[test]
+    fn timed_out_entry_error_wrap() {
+        let error = TimedOutEntryError::wrap(std::io::ErrorKind::TimedOut.into(), "/foo");
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        let error = TimedOutEntryError::from_io_error(&error).unwrap();
+        assert_eq!(error.path(), Path::new("/foo"));
+
+        // Errors other than timeouts are passed through as they are.
+        let error = TimedOutEntryError::wrap(std::io::ErrorKind::NotFound.into(), "/foo");
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(TimedOutEntryError::from_io_error(&error).is_none());
     }
 }
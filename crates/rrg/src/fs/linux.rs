@@ -0,0 +1,823 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Linux-specific filesystem utilities.
+
+use std::path::Path;
+
+/// Name of the extended attribute storing the access ACL of a file.
+const ACL_ACCESS_EXT_ATTR: &str = "system.posix_acl_access";
+
+/// Name of the extended attribute storing the default ACL of a directory.
+const ACL_DEFAULT_EXT_ATTR: &str = "system.posix_acl_default";
+
+/// Version of the extended attribute format of ACLs supported by the kernel.
+const ACL_EXT_ATTR_VERSION: u32 = 2;
+
+/// Entry of a POSIX access control list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AclEntry {
+    /// Kind of the entry.
+    pub tag: AclTag,
+    /// User or group identifier the entry applies to.
+    ///
+    /// This is set only for [`AclTag::User`] and [`AclTag::Group`] entries.
+    pub qualifier: Option<u32>,
+    /// Permissions granted by the entry (as `rwx` bits, e.g. `0o6` for `rw-`).
+    pub perms: u8,
+}
+
+/// Kind of a POSIX access control list entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AclTag {
+    /// Permissions of the file owner.
+    UserObj,
+    /// Permissions of a user given by the qualifier.
+    User,
+    /// Permissions of the file owning group.
+    GroupObj,
+    /// Permissions of a group given by the qualifier.
+    Group,
+    /// Upper bound of the permissions granted to users and groups.
+    Mask,
+    /// Permissions of everyone else.
+    Other,
+}
+
+/// Collects the access ACL of the file at the given path.
+///
+/// Symlinks are not followed. Files that have no extended ACL (or live on a
+/// filesystem that does not support ACLs) yield an empty list.
+///
+/// # Errors
+///
+/// This function will return an error if the ACL cannot be read or if it is
+/// malformed.
+pub fn access_acl<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<AclEntry>> {
+    acl(path.as_ref(), ACL_ACCESS_EXT_ATTR)
+}
+
+/// Collects the default ACL of the directory at the given path.
+///
+/// Default ACLs are inherited by files created within the directory. Symlinks
+/// are not followed. Directories without a default ACL (and non-directories)
+/// yield an empty list.
+///
+/// # Errors
+///
+/// This function will return an error if the ACL cannot be read or if it is
+/// malformed.
+pub fn default_acl<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<AclEntry>> {
+    acl(path.as_ref(), ACL_DEFAULT_EXT_ATTR)
+}
+
+/// Collects the ACL stored in the given extended attribute of the file.
+fn acl(path: &Path, ext_attr_name: &str) -> std::io::Result<Vec<AclEntry>> {
+    match ospect::fs::linux::ext_attr_value(path, ext_attr_name) {
+        Ok(value) => parse_acl(&value),
+        Err(error) => match error.raw_os_error() {
+            Some(libc::ENODATA | libc::EOPNOTSUPP) => Ok(Vec::new()),
+            _ => Err(error),
+        },
+    }
+}
+
+/// Parses an ACL in the extended attribute format used by the kernel.
+///
+/// The format consists of a 4-byte version header followed by 8-byte entries
+/// with a 2-byte tag, 2-byte permissions and 4-byte qualifier (all stored in
+/// the little-endian order) [1].
+///
+/// [1]: https://github.com/torvalds/linux/blob/master/include/uapi/linux/posix_acl_xattr.h
+pub fn parse_acl(bytes: &[u8]) -> std::io::Result<Vec<AclEntry>> {
+    use std::io::{Error, ErrorKind};
+
+    let (version, entries) = match bytes {
+        [a, b, c, d, entries @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]), entries),
+        _ => return Err(Error::new(ErrorKind::InvalidData, "truncated ACL header")),
+    };
+
+    if version != ACL_EXT_ATTR_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format! {
+            "unsupported ACL version: {version}"
+        }));
+    }
+
+    if entries.len() % 8 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "truncated ACL entry"));
+    }
+
+    entries.chunks_exact(8)
+        .map(|entry| {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+            let perms = u16::from_le_bytes([entry[2], entry[3]]);
+            let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+
+            let tag = match tag {
+                0x01 => AclTag::UserObj,
+                0x02 => AclTag::User,
+                0x04 => AclTag::GroupObj,
+                0x08 => AclTag::Group,
+                0x10 => AclTag::Mask,
+                0x20 => AclTag::Other,
+                _ => return Err(Error::new(ErrorKind::InvalidData, format! {
+                    "unknown ACL entry tag: {tag:#x}"
+                })),
+            };
+
+            let qualifier = match tag {
+                AclTag::User | AclTag::Group => Some(id),
+                _ => None,
+            };
+
+            Ok(AclEntry {
+                tag,
+                qualifier,
+                perms: (perms & 0o7) as u8,
+            })
+        })
+        .collect()
+}
+
+/// Name of the extended attribute storing the capabilities of a file.
+pub const CAPABILITY_EXT_ATTR: &str = "security.capability";
+
+/// Capabilities granted to executables of a file on `execve`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileCapabilities {
+    /// Capabilities permitted regardless of the inheritable set of the thread.
+    pub permitted: u64,
+    /// Capabilities permitted if they are in the inheritable set of the thread.
+    pub inheritable: u64,
+    /// Whether the permitted capabilities are also made effective.
+    pub effective: bool,
+    /// Identifier of the root user of the user namespace the capabilities are
+    /// valid in (for namespaced capabilities only).
+    pub root_id: Option<u32>,
+}
+
+/// Parses file capabilities in the extended attribute format of the kernel.
+///
+/// All three revisions of the format are supported: version 1 (with 32-bit
+/// sets), version 2 (with 64-bit sets) and version 3 (with 64-bit sets and the
+/// root identifier of the user namespace) [1].
+///
+/// [1]: https://github.com/torvalds/linux/blob/master/include/uapi/linux/capability.h
+pub fn parse_file_capabilities(bytes: &[u8]) -> std::io::Result<FileCapabilities> {
+    use std::io::{Error, ErrorKind};
+
+    const REVISION_MASK: u32 = 0xFF000000;
+    const REVISION_1: u32 = 0x01000000;
+    const REVISION_2: u32 = 0x02000000;
+    const REVISION_3: u32 = 0x03000000;
+    const FLAGS_EFFECTIVE: u32 = 0x000001;
+
+    let words = bytes.chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect::<Vec<_>>();
+
+    let magic = match words.first() {
+        Some(magic) => *magic,
+        None => return Err(Error::new(ErrorKind::InvalidData, "truncated capabilities")),
+    };
+
+    let len = match magic & REVISION_MASK {
+        REVISION_1 => 3,
+        REVISION_2 => 5,
+        REVISION_3 => 6,
+        revision => return Err(Error::new(ErrorKind::InvalidData, format! {
+            "unsupported capabilities revision: {revision:#x}"
+        })),
+    };
+
+    if bytes.len() != len * 4 {
+        return Err(Error::new(ErrorKind::InvalidData, format! {
+            "invalid capabilities length: {}", bytes.len()
+        }));
+    }
+
+    // Capability sets are stored as pairs of permitted and inheritable 32-bit
+    // words, starting with the lower halves.
+    let (permitted, inheritable) = match len {
+        3 => (u64::from(words[1]), u64::from(words[2])),
+        _ => {
+            let permitted = u64::from(words[3]) << 32 | u64::from(words[1]);
+            let inheritable = u64::from(words[4]) << 32 | u64::from(words[2]);
+            (permitted, inheritable)
+        }
+    };
+
+    Ok(FileCapabilities {
+        permitted,
+        inheritable,
+        effective: magic & FLAGS_EFFECTIVE != 0,
+        root_id: words.get(5).copied(),
+    })
+}
+
+/// Name of the extended attribute storing the SELinux context of a file.
+pub const SELINUX_EXT_ATTR: &str = "security.selinux";
+
+/// Name of the extended attribute storing the SMACK label of a file.
+pub const SMACK_EXT_ATTR: &str = "security.SMACK64";
+
+/// Parses a security label (e.g. SELinux context) stored in an extended attribute.
+///
+/// Labels are usually stored with a trailing null byte (although it is not
+/// required) which is stripped.
+pub fn parse_security_label(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(b"\0").unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+// https://elixir.bootlin.com/linux/v6.8/source/include/uapi/linux/fs.h#L292
+const FS_COMPR_FL: u32 = 0x00000004;
+const FS_IMMUTABLE_FL: u32 = 0x00000010;
+const FS_APPEND_FL: u32 = 0x00000020;
+const FS_NODUMP_FL: u32 = 0x00000040;
+const FS_ENCRYPT_FL: u32 = 0x00000800;
+
+/// Flags (also known as attributes) of a Linux inode.
+///
+/// These are the flags that can be inspected with `lsattr` and set through
+/// `chattr`. The raw value is kept intact, the methods decode the flags that are
+/// interesting from the forensic point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InodeFlags(pub u32);
+
+impl InodeFlags {
+
+    /// Returns whether the file cannot be modified, deleted or renamed.
+    pub fn is_immutable(&self) -> bool {
+        self.0 & FS_IMMUTABLE_FL != 0
+    }
+
+    /// Returns whether the file can only be opened in append mode.
+    pub fn is_append_only(&self) -> bool {
+        self.0 & FS_APPEND_FL != 0
+    }
+
+    /// Returns whether the file is compressed by the filesystem.
+    pub fn is_compressed(&self) -> bool {
+        self.0 & FS_COMPR_FL != 0
+    }
+
+    /// Returns whether the file is encrypted by the filesystem.
+    pub fn is_encrypted(&self) -> bool {
+        self.0 & FS_ENCRYPT_FL != 0
+    }
+
+    /// Returns whether the file is excluded from backups made by `dump`.
+    pub fn is_nodump(&self) -> bool {
+        self.0 & FS_NODUMP_FL != 0
+    }
+}
+
+/// Resolves the `path` within `root` as if `root` was the filesystem root.
+///
+/// This is similar to what `chroot` does: absolute symlinks encountered along
+/// the resolution are reinterpreted as rooted at `root` and `..` components are
+/// resolved against the directories within `root`. Symlinks and `..` components
+/// that would lead outside of the root are rejected. All the symlinks along the
+/// path are followed (including the last component), so that the resolved path
+/// can be safely used with functions that follow symlinks.
+///
+/// The `path` has to start with `root` (e.g. it can be a result of a lexical
+/// [`reroot`] of an absolute path). At most `max_symlinks` symlinks are
+/// followed during the whole resolution.
+///
+/// [`reroot`]: crate::fs::reroot
+///
+/// # Errors
+///
+/// This function will return an error if the path escapes the root, if there
+/// are too many symlinks along the way (in which case the error wraps a
+/// [`SymlinkLoopError`]) or if reading any of them fails.
+///
+/// [`SymlinkLoopError`]: crate::fs::SymlinkLoopError
+///
+/// # Examples
+///
+/// ```no_run
+/// let path = rrg::fs::linux::resolve_in_root("/mnt/image", "/mnt/image/lib/libc.so.6", 40)
+///     .unwrap();
+///
+/// // If `/mnt/image/lib` is a symlink to `/usr/lib`, the path is resolved to
+/// // `/mnt/image/usr/lib/libc.so.6` instead of `/usr/lib/libc.so.6`.
+/// println!("{}", path.display());
+/// ```
+pub fn resolve_in_root<R, P>(
+    root: R,
+    path: P,
+    max_symlinks: usize,
+) -> std::io::Result<std::path::PathBuf>
+where
+    R: AsRef<Path>,
+    P: AsRef<Path>,
+{
+    use std::path::Component;
+
+    let root = root.as_ref();
+    let path = path.as_ref();
+
+    let escape_error = || std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        format!("path '{}' escapes the image root", path.display()),
+    );
+
+    let relative = path.strip_prefix(root)
+        .map_err(|_| escape_error())?;
+
+    // Components still to be resolved, in reverse order (so that the next one
+    // can be simply popped).
+    let mut pending = relative.components()
+        .map(|component| component.as_os_str().to_os_string())
+        .collect::<Vec<_>>();
+    pending.reverse();
+
+    let mut resolved = root.to_path_buf();
+    let mut hops = 0;
+
+    while let Some(component) = pending.pop() {
+        match Path::new(&component).components().next() {
+            Some(Component::RootDir) => resolved = root.to_path_buf(),
+            Some(Component::ParentDir) => {
+                if resolved == root {
+                    return Err(escape_error());
+                }
+                resolved.pop();
+            }
+            Some(Component::Normal(name)) => {
+                let candidate = resolved.join(name);
+
+                let is_symlink = match candidate.symlink_metadata() {
+                    Ok(metadata) => metadata.is_symlink(),
+                    // Non-existent components are resolved lexically, the error
+                    // is going to be reported once the path is used anyway.
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => false,
+                    Err(error) => return Err(error),
+                };
+
+                if !is_symlink {
+                    resolved = candidate;
+                    continue;
+                }
+
+                hops += 1;
+                if hops > max_symlinks {
+                    return Err(crate::fs::SymlinkLoopError::io_error(path, max_symlinks));
+                }
+
+                let target = std::fs::read_link(&candidate)?;
+                pending.extend(target.components()
+                    .map(|component| component.as_os_str().to_os_string())
+                    .rev());
+            }
+            Some(Component::CurDir | Component::Prefix(_)) | None => (),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Suffix that the kernel appends to links of descriptors of deleted files.
+const DELETED_FD_LINK_SUFFIX: &[u8] = b" (deleted)";
+
+/// File descriptor open by a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcessFd {
+    /// Identifier of the process that has the file descriptor open.
+    pub pid: u32,
+    /// Number of the file descriptor.
+    pub fd: u32,
+}
+
+/// File that a descriptor open by a process refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessFdTarget {
+    /// Path with which the file was opened.
+    ///
+    /// If the file was deleted (or renamed) since, a file at this path might
+    /// not exist or be a different one.
+    pub path: std::path::PathBuf,
+    /// Whether the file was deleted (has no links left).
+    pub deleted: bool,
+}
+
+impl ProcessFd {
+
+    /// Returns the path through which the open file can be accessed.
+    ///
+    /// This is a "magic" link in `/proc`: functions that follow symlinks (e.g.
+    /// [`std::fs::metadata`] or [`std::fs::File::open`]) act on the open file
+    /// itself, even if it has been deleted.
+    pub fn path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(format!("/proc/{}/fd/{}", self.pid, self.fd))
+    }
+
+    /// Returns information about the file the descriptor refers to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the descriptor cannot be read or
+    /// if it does not refer to a file (e.g. it is a socket or a pipe).
+    pub fn target(&self) -> std::io::Result<ProcessFdTarget> {
+        use std::os::unix::ffi::OsStrExt as _;
+        use std::os::unix::fs::MetadataExt as _;
+
+        let link = std::fs::read_link(self.path())?;
+
+        // Descriptors that do not refer to files have targets like `pipe:[42]`
+        // or `socket:[1337]`.
+        if !link.is_absolute() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("descriptor {} of process {} is not a file", self.fd, self.pid),
+            ));
+        }
+
+        // We want to be sure that the suffix was added by the kernel and is not
+        // part of the actual name of the file, so we check the link count too.
+        let deleted = std::fs::metadata(self.path())?.nlink() == 0;
+
+        let path = match link.as_os_str().as_bytes().strip_suffix(DELETED_FD_LINK_SUFFIX) {
+            Some(path) if deleted => std::path::PathBuf::from(std::ffi::OsStr::from_bytes(path)),
+            _ => link,
+        };
+
+        Ok(ProcessFdTarget {
+            path,
+            deleted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Value of the qualifier of ACL entries that do not have one.
+    const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+    /// Encodes the given entries in the extended attribute format of ACLs.
+    fn encode_acl(entries: &[(u16, u16, u32)]) -> Vec<u8> {
+        let mut bytes = ACL_EXT_ATTR_VERSION.to_le_bytes().to_vec();
+        for (tag, perms, id) in entries {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&perms.to_le_bytes());
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn parse_acl_entries() {
+        let bytes = encode_acl(&[
+            (0x01, 0o6, ACL_UNDEFINED_ID),
+            (0x02, 0o4, 1337),
+            (0x04, 0o4, ACL_UNDEFINED_ID),
+            (0x08, 0o5, 42),
+            (0x10, 0o5, ACL_UNDEFINED_ID),
+            (0x20, 0o0, ACL_UNDEFINED_ID),
+        ]);
+
+        assert_eq!(parse_acl(&bytes).unwrap(), vec![
+            AclEntry { tag: AclTag::UserObj, qualifier: None, perms: 0o6 },
+            AclEntry { tag: AclTag::User, qualifier: Some(1337), perms: 0o4 },
+            AclEntry { tag: AclTag::GroupObj, qualifier: None, perms: 0o4 },
+            AclEntry { tag: AclTag::Group, qualifier: Some(42), perms: 0o5 },
+            AclEntry { tag: AclTag::Mask, qualifier: None, perms: 0o5 },
+            AclEntry { tag: AclTag::Other, qualifier: None, perms: 0o0 },
+        ]);
+    }
+
+    #[test]
+    fn parse_acl_invalid_version() {
+        let mut bytes = encode_acl(&[(0x01, 0o6, ACL_UNDEFINED_ID)]);
+        bytes[0] = 1;
+
+        assert!(parse_acl(&bytes).is_err());
+    }
+
+    #[test]
+    fn parse_acl_truncated() {
+        let bytes = encode_acl(&[(0x01, 0o6, ACL_UNDEFINED_ID)]);
+
+        assert!(parse_acl(&bytes[..2]).is_err());
+        assert!(parse_acl(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn parse_file_capabilities_revision_2() {
+        // Value set by `setcap cap_net_raw+ep <path>`.
+        let bytes = [
+            0x01, 0x00, 0x00, 0x02,
+            0x00, 0x20, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let caps = parse_file_capabilities(&bytes)
+            .unwrap();
+
+        assert_eq!(caps, FileCapabilities {
+            permitted: 1 << 13,
+            inheritable: 0,
+            effective: true,
+            root_id: None,
+        });
+        assert_eq! {
+            ospect::proc::linux::capability_names(caps.permitted),
+            vec!["cap_net_raw"]
+        };
+    }
+
+    #[test]
+    fn parse_file_capabilities_revision_3() {
+        // Value set by `setcap cap_sys_admin,cap_bpf=i <path>` within a user
+        // namespace with root mapped to 100000.
+        let bytes = [
+            0x00, 0x00, 0x00, 0x03,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x20, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x80, 0x00, 0x00, 0x00,
+            0xa0, 0x86, 0x01, 0x00,
+        ];
+
+        let caps = parse_file_capabilities(&bytes)
+            .unwrap();
+
+        assert_eq!(caps, FileCapabilities {
+            permitted: 0,
+            inheritable: 1 << 21 | 1 << 39,
+            effective: false,
+            root_id: Some(100000),
+        });
+    }
+
+    #[test]
+    fn parse_file_capabilities_invalid() {
+        assert!(parse_file_capabilities(&[]).is_err());
+        assert!(parse_file_capabilities(&[0x00, 0x00, 0x00, 0x02]).is_err());
+        assert!(parse_file_capabilities(&[0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn parse_security_label_null_terminated() {
+        let label = parse_security_label(b"system_u:object_r:etc_t:s0\0");
+        assert_eq!(label, "system_u:object_r:etc_t:s0");
+    }
+
+    #[test]
+    fn parse_security_label_not_terminated() {
+        assert_eq!(parse_security_label(b"_"), "_");
+    }
+
+    #[test]
+    fn access_acl_none() {
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        assert!(access_acl(tempfile.path()).unwrap().is_empty());
+        assert!(default_acl(tempfile.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn access_acl_named_user() {
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let tempfile = tempfile::NamedTempFile::new()
+            .unwrap();
+
+        // Equivalent of `setfacl -m u:1337:r <path>`.
+        let bytes = encode_acl(&[
+            (0x01, 0o6, ACL_UNDEFINED_ID),
+            (0x02, 0o4, 1337),
+            (0x04, 0o4, ACL_UNDEFINED_ID),
+            (0x10, 0o4, ACL_UNDEFINED_ID),
+            (0x20, 0o0, ACL_UNDEFINED_ID),
+        ]);
+
+        let path = std::ffi::CString::new(tempfile.path().as_os_str().as_bytes())
+            .unwrap();
+        let name = std::ffi::CString::new(ACL_ACCESS_EXT_ATTR)
+            .unwrap();
+
+        // SAFETY: Path and name are valid null-terminated strings and we pass
+        // a valid buffer along with its length.
+        let code = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                bytes.as_ptr().cast(),
+                bytes.len(),
+                0,
+            )
+        };
+        if code != 0 {
+            let error = std::io::Error::last_os_error();
+            // Not all filesystems support ACLs, in which case there is nothing
+            // that we can test.
+            if error.raw_os_error() == Some(libc::EOPNOTSUPP) {
+                return;
+            }
+            panic!("failed to set ACL: {error}");
+        }
+
+        let acl = access_acl(tempfile.path())
+            .unwrap();
+
+        assert! {
+            acl.contains(&AclEntry { tag: AclTag::User, qualifier: Some(1337), perms: 0o4 })
+        };
+        assert! {
+            acl.contains(&AclEntry { tag: AclTag::Other, qualifier: None, perms: 0o0 })
+        };
+    }
+
+    #[test]
+    fn inode_flags_none() {
+        let flags = InodeFlags(0);
+        assert!(!flags.is_immutable());
+        assert!(!flags.is_append_only());
+        assert!(!flags.is_compressed());
+        assert!(!flags.is_encrypted());
+        assert!(!flags.is_nodump());
+    }
+
+    #[test]
+    fn inode_flags_immutable_nodump() {
+        // Immutable, no-dump and extents (which we do not decode) flags.
+        let flags = InodeFlags(0x00080050);
+        assert!(flags.is_immutable());
+        assert!(!flags.is_append_only());
+        assert!(!flags.is_compressed());
+        assert!(!flags.is_encrypted());
+        assert!(flags.is_nodump());
+    }
+
+    #[test]
+    fn resolve_in_root_plain() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("etc"))
+            .unwrap();
+
+        let path = resolve_in_root(root, root.join("etc").join("passwd"), 40)
+            .unwrap();
+        assert_eq!(path, root.join("etc").join("passwd"));
+    }
+
+    #[test]
+    fn resolve_in_root_absolute_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("usr").join("lib"))
+            .unwrap();
+        std::os::unix::fs::symlink("/usr/lib", root.join("lib"))
+            .unwrap();
+
+        let path = resolve_in_root(root, root.join("lib").join("libc.so.6"), 40)
+            .unwrap();
+        assert_eq!(path, root.join("usr").join("lib").join("libc.so.6"));
+    }
+
+    #[test]
+    fn resolve_in_root_relative_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("usr").join("lib"))
+            .unwrap();
+        std::fs::create_dir_all(root.join("usr").join("lib64"))
+            .unwrap();
+        std::os::unix::fs::symlink("../lib", root.join("usr").join("lib64").join("lib"))
+            .unwrap();
+
+        let path = resolve_in_root(root, root.join("usr").join("lib64").join("lib").join("foo"), 40)
+            .unwrap();
+        assert_eq!(path, root.join("usr").join("lib").join("foo"));
+    }
+
+    #[test]
+    fn resolve_in_root_last_symlink() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::fs::create_dir_all(root.join("usr").join("bin"))
+            .unwrap();
+        std::os::unix::fs::symlink("/usr/bin", root.join("bin"))
+            .unwrap();
+
+        let path = resolve_in_root(root, root.join("bin"), 40)
+            .unwrap();
+        assert_eq!(path, root.join("usr").join("bin"));
+    }
+
+    #[test]
+    fn resolve_in_root_symlink_escape() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::os::unix::fs::symlink("../..", root.join("escape"))
+            .unwrap();
+
+        let error = resolve_in_root(root, root.join("escape").join("etc").join("passwd"), 40)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn resolve_in_root_symlink_loop() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let root = tempdir.path();
+
+        std::os::unix::fs::symlink("/loop", root.join("loop"))
+            .unwrap();
+
+        let error = resolve_in_root(root, root.join("loop").join("foo"), 40)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn resolve_in_root_outside() {
+        let error = resolve_in_root("/mnt/image", "/etc/passwd", 40)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn process_fd_target_deleted() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        let file = std::fs::File::create(tempdir.join("foo"))
+            .unwrap();
+        std::fs::remove_file(tempdir.join("foo"))
+            .unwrap();
+
+        let process_fd = ProcessFd {
+            pid: std::process::id(),
+            fd: std::os::fd::AsRawFd::as_raw_fd(&file) as u32,
+        };
+
+        assert_eq!(process_fd.target().unwrap(), ProcessFdTarget {
+            path: tempdir.join("foo"),
+            deleted: true,
+        });
+    }
+
+    #[test]
+    fn process_fd_target_not_deleted() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+        let tempdir = tempdir.path().canonicalize()
+            .unwrap();
+
+        // The suffix is part of the name here, so it should not be stripped.
+        let file = std::fs::File::create(tempdir.join("foo (deleted)"))
+            .unwrap();
+
+        let process_fd = ProcessFd {
+            pid: std::process::id(),
+            fd: std::os::fd::AsRawFd::as_raw_fd(&file) as u32,
+        };
+
+        assert_eq!(process_fd.target().unwrap(), ProcessFdTarget {
+            path: tempdir.join("foo (deleted)"),
+            deleted: false,
+        });
+    }
+
+    #[test]
+    fn process_fd_target_pipe() {
+        let (reader, _writer) = std::io::pipe()
+            .unwrap();
+
+        let process_fd = ProcessFd {
+            pid: std::process::id(),
+            fd: std::os::fd::AsRawFd::as_raw_fd(&reader) as u32,
+        };
+
+        let error = process_fd.target()
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -138,6 +138,11 @@ impl Compression {
     pub fn best() -> Compression {
         Compression(flate2::Compression::best())
     }
+
+    /// Returns the level of the compression (between 0 and 9).
+    pub fn level(&self) -> u32 {
+        self.0.level()
+    }
 }
 
 impl Default for Compression {
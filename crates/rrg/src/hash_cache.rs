@@ -0,0 +1,630 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Cache of digests of file contents.
+//!
+//! Actions that repeatedly hash files in the same tree can use this cache to
+//! avoid re-hashing files that did not change since the last time. A file is
+//! considered unchanged if its device, inode, size and modification time (and
+//! on Unix also its status change time which, unlike the modification time,
+//! cannot be set by users) are the same as the ones recorded with the digest.
+//!
+//! The cache keeps a bounded number of entries, evicting the least recently
+//! used ones. It is persisted as a single file in the temporary directory of
+//! the agent.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default maximum number of entries in the cache.
+pub const DEFAULT_CAPACITY: usize = 16 * 1024;
+
+/// Name of the file (in the agent temporary directory) storing the cache.
+const FILE_NAME: &str = "hash_cache";
+
+/// Magic bytes at the beginning of the file storing the cache.
+const MAGIC: &[u8; 8] = b"RRGHASH1";
+
+/// Digests of contents of a file.
+///
+/// Not all digests have to be known: the cache stores only the ones that were
+/// needed (and thus computed) so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Digests {
+    /// MD5 digest of the file contents (if known).
+    pub md5: Option<[u8; 16]>,
+    /// SHA-1 digest of the file contents (if known).
+    pub sha1: Option<[u8; 20]>,
+    /// SHA-256 digest of the file contents (if known).
+    pub sha256: Option<[u8; 32]>,
+    /// SHA-1 digest of the file contents as a Git blob object (if known).
+    pub git_blob_sha1: Option<[u8; 20]>,
+}
+
+impl Digests {
+
+    /// Merges the other digests into these ones.
+    ///
+    /// Digests known in `other` take precedence over the ones known here.
+    pub fn merge(&mut self, other: Digests) {
+        self.md5 = other.md5.or(self.md5);
+        self.sha1 = other.sha1.or(self.sha1);
+        self.sha256 = other.sha256.or(self.sha256);
+        self.git_blob_sha1 = other.git_blob_sha1.or(self.git_blob_sha1);
+    }
+}
+
+/// Least-recently-used cache of digests of file contents.
+pub struct HashCache {
+    /// Path to the file the cache is persisted in (if any).
+    path: Option<PathBuf>,
+    /// Maximum number of entries in the cache.
+    capacity: usize,
+    /// Cached entries keyed by the path of the file.
+    entries: HashMap<PathBuf, Entry>,
+    /// Paths of cached files ordered by time of their last use.
+    order: BTreeMap<u64, PathBuf>,
+    /// Counter used for ordering entries by time of their last use.
+    tick: u64,
+}
+
+/// Single entry of the cache.
+struct Entry {
+    /// Metadata of the file at the moment the digests were computed.
+    fingerprint: Fingerprint,
+    /// Digests of the file contents.
+    digests: Digests,
+    /// Time of the last use of the entry.
+    tick: u64,
+}
+
+/// Subset of file metadata that has to be unchanged for the digests to be valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fingerprint {
+    /// Identifier of the device the file lives on.
+    dev: u64,
+    /// Inode number of the file.
+    ino: u64,
+    /// Size of the file.
+    size: u64,
+    /// Time of the last modification of the file (since the Unix epoch).
+    mtime: Duration,
+    /// Time of the last status change of the file (since the Unix epoch).
+    ctime: Duration,
+}
+
+impl Fingerprint {
+
+    /// Creates a fingerprint of the given file metadata.
+    ///
+    /// Returns `None` if the metadata does not have all the required fields.
+    fn new(metadata: &std::fs::Metadata) -> Option<Fingerprint> {
+        let mtime = metadata.modified().ok()?
+            .duration_since(std::time::UNIX_EPOCH).ok()?;
+
+        #[cfg(target_family = "unix")]
+        let (dev, ino, ctime) = {
+            use std::os::unix::fs::MetadataExt as _;
+
+            let ctime_secs = u64::try_from(metadata.ctime()).ok()?;
+            let ctime_nanos = u32::try_from(metadata.ctime_nsec()).ok()?;
+
+            (metadata.dev(), metadata.ino(), Duration::new(ctime_secs, ctime_nanos))
+        };
+
+        #[cfg(not(target_family = "unix"))]
+        let (dev, ino, ctime) = (0, 0, Duration::ZERO);
+
+        Some(Fingerprint {
+            dev,
+            ino,
+            size: metadata.len(),
+            mtime,
+            ctime,
+        })
+    }
+}
+
+impl HashCache {
+
+    /// Creates a new, empty, cache that is not persisted.
+    pub fn new(capacity: usize) -> HashCache {
+        HashCache {
+            path: None,
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Loads the cache persisted in the given agent temporary directory.
+    ///
+    /// If the directory does not exist, the cache is not persisted. If there
+    /// is no cache file (or it is corrupted), an empty cache is returned.
+    pub fn load<P: AsRef<Path>>(temp_dir: P, capacity: usize) -> HashCache {
+        let mut cache = HashCache::new(capacity);
+
+        let temp_dir = temp_dir.as_ref();
+        if !temp_dir.is_dir() {
+            return cache;
+        }
+
+        let path = temp_dir.join(FILE_NAME);
+        match std::fs::read(&path) {
+            Ok(bytes) => if let Err(error) = cache.decode(&bytes) {
+                log::warn!("discarding corrupted hash cache '{}': {error}", path.display());
+                cache = HashCache::new(capacity);
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => (),
+            Err(error) => {
+                log::warn!("failed to read hash cache '{}': {error}", path.display());
+            }
+        }
+
+        cache.path = Some(path);
+        cache
+    }
+
+    /// Saves the cache to the file it was loaded from (if any).
+    ///
+    /// The file is replaced atomically, so a concurrent load never observes a
+    /// partially written cache.
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let path_tmp = path.with_extension("tmp");
+        std::fs::write(&path_tmp, self.encode())?;
+        std::fs::rename(&path_tmp, path)
+    }
+
+    /// Returns cached digests of the file with the given path and metadata.
+    ///
+    /// Digests are returned only if the file did not change since they were
+    /// computed (according to its metadata). Stale entries are removed.
+    pub fn get(&mut self, path: &Path, metadata: &std::fs::Metadata) -> Option<&Digests> {
+        let fingerprint = Fingerprint::new(metadata)?;
+
+        let entry = self.entries.get(path)?;
+        if entry.fingerprint != fingerprint {
+            self.remove(path);
+            return None;
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(path)?;
+        if let Some(path) = self.order.remove(&entry.tick) {
+            self.order.insert(tick, path);
+        }
+        entry.tick = tick;
+
+        Some(&entry.digests)
+    }
+
+    /// Stores digests of the file with the given path and metadata.
+    ///
+    /// The metadata should be collected before the file contents are read, so
+    /// that a modification during hashing invalidates the entry.
+    pub fn insert(&mut self, path: &Path, metadata: &std::fs::Metadata, digests: Digests) {
+        let Some(fingerprint) = Fingerprint::new(metadata) else {
+            return;
+        };
+
+        self.insert_entry(path.to_path_buf(), fingerprint, digests);
+    }
+
+    /// Stores the given entry, evicting the least recently used one if needed.
+    fn insert_entry(&mut self, path: PathBuf, fingerprint: Fingerprint, digests: Digests) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&path);
+
+        while self.entries.len() >= self.capacity {
+            let Some((_, path)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&path);
+        }
+
+        let tick = self.next_tick();
+        self.order.insert(tick, path.clone());
+        self.entries.insert(path, Entry {
+            fingerprint,
+            digests,
+            tick,
+        });
+    }
+
+    /// Removes the entry for the given path (if any).
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.tick);
+        }
+    }
+
+    /// Returns the next value of the usage counter.
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Encodes the cache entries (from the least recently used).
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+
+        for path in self.order.values() {
+            let entry = &self.entries[path];
+            let path = path_to_bytes(path);
+
+            buf.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&path);
+
+            let fingerprint = &entry.fingerprint;
+            buf.extend_from_slice(&fingerprint.dev.to_le_bytes());
+            buf.extend_from_slice(&fingerprint.ino.to_le_bytes());
+            buf.extend_from_slice(&fingerprint.size.to_le_bytes());
+            for time in [fingerprint.mtime, fingerprint.ctime] {
+                buf.extend_from_slice(&time.as_secs().to_le_bytes());
+                buf.extend_from_slice(&time.subsec_nanos().to_le_bytes());
+            }
+
+            let digests = &entry.digests;
+            let flags = u8::from(digests.md5.is_some())
+                | u8::from(digests.sha1.is_some()) << 1
+                | u8::from(digests.sha256.is_some()) << 2
+                | u8::from(digests.git_blob_sha1.is_some()) << 3;
+            buf.push(flags);
+
+            if let Some(md5) = &digests.md5 {
+                buf.extend_from_slice(md5);
+            }
+            if let Some(sha1) = &digests.sha1 {
+                buf.extend_from_slice(sha1);
+            }
+            if let Some(sha256) = &digests.sha256 {
+                buf.extend_from_slice(sha256);
+            }
+            if let Some(git_blob_sha1) = &digests.git_blob_sha1 {
+                buf.extend_from_slice(git_blob_sha1);
+            }
+        }
+
+        buf
+    }
+
+    /// Decodes entries encoded with [`HashCache::encode`] into the cache.
+    fn decode(&mut self, bytes: &[u8]) -> Result<(), DecodeError> {
+        let mut bytes = bytes.strip_prefix(MAGIC)
+            .ok_or(DecodeError::InvalidMagic)?;
+
+        while !bytes.is_empty() {
+            let path_len = u32::from_le_bytes(take(&mut bytes)?) as usize;
+            if bytes.len() < path_len {
+                return Err(DecodeError::UnexpectedEnd);
+            }
+            let (path, rest) = bytes.split_at(path_len);
+            bytes = rest;
+
+            let path = path_from_bytes(path)
+                .ok_or(DecodeError::InvalidPath)?;
+
+            let dev = u64::from_le_bytes(take(&mut bytes)?);
+            let ino = u64::from_le_bytes(take(&mut bytes)?);
+            let size = u64::from_le_bytes(take(&mut bytes)?);
+
+            let mut times = [Duration::ZERO; 2];
+            for time in &mut times {
+                let secs = u64::from_le_bytes(take(&mut bytes)?);
+                let nanos = u32::from_le_bytes(take(&mut bytes)?);
+                if nanos >= 1_000_000_000 {
+                    return Err(DecodeError::InvalidTime);
+                }
+                *time = Duration::new(secs, nanos);
+            }
+            let [mtime, ctime] = times;
+
+            let [flags] = take(&mut bytes)?;
+
+            let mut digests = Digests::default();
+            if flags & 0b0001 != 0 {
+                digests.md5 = Some(take(&mut bytes)?);
+            }
+            if flags & 0b0010 != 0 {
+                digests.sha1 = Some(take(&mut bytes)?);
+            }
+            if flags & 0b0100 != 0 {
+                digests.sha256 = Some(take(&mut bytes)?);
+            }
+            if flags & 0b1000 != 0 {
+                digests.git_blob_sha1 = Some(take(&mut bytes)?);
+            }
+
+            let fingerprint = Fingerprint {
+                dev,
+                ino,
+                size,
+                mtime,
+                ctime,
+            };
+            self.insert_entry(path, fingerprint, digests);
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits off a fixed-size array from the beginning of the given bytes.
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], DecodeError> {
+    if bytes.len() < N {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let (head, rest) = bytes.split_at(N);
+    *bytes = rest;
+
+    Ok(head.try_into().expect("invalid split"))
+}
+
+/// Converts the path to bytes for storing in the cache file.
+#[cfg(target_family = "unix")]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+/// Converts the path to bytes for storing in the cache file.
+#[cfg(target_family = "windows")]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt as _;
+
+    path.as_os_str().encode_wide()
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Converts bytes stored in the cache file back to a path.
+#[cfg(target_family = "unix")]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+/// Converts bytes stored in the cache file back to a path.
+#[cfg(target_family = "windows")]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt as _;
+
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let wide = bytes.chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+
+    Some(PathBuf::from(std::ffi::OsString::from_wide(&wide)))
+}
+
+/// An error indicating that the cache file is corrupted.
+#[derive(Debug)]
+enum DecodeError {
+    /// The file does not start with the expected magic bytes.
+    InvalidMagic,
+    /// The file ends in the middle of an entry.
+    UnexpectedEnd,
+    /// The file contains an invalid path.
+    InvalidPath,
+    /// The file contains an invalid timestamp.
+    InvalidTime,
+}
+
+impl std::fmt::Display for DecodeError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidMagic => write!(fmt, "invalid magic bytes"),
+            DecodeError::UnexpectedEnd => write!(fmt, "unexpected end of file"),
+            DecodeError::InvalidPath => write!(fmt, "invalid path"),
+            DecodeError::InvalidTime => write!(fmt, "invalid timestamp"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn digests() -> Digests {
+        Digests {
+            md5: Some([0x11; 16]),
+            sha1: None,
+            sha256: Some([0x22; 32]),
+            git_blob_sha1: Some([0x33; 20]),
+        }
+    }
+
+    #[test]
+    fn get_unchanged_hit() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foo")
+            .unwrap();
+        let metadata = path.metadata()
+            .unwrap();
+
+        let mut cache = HashCache::new(DEFAULT_CAPACITY);
+        cache.insert(&path, &metadata, digests());
+
+        let metadata = path.metadata()
+            .unwrap();
+        assert_eq!(cache.get(&path, &metadata), Some(&digests()));
+    }
+
+    #[test]
+    fn get_touched_miss() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foo")
+            .unwrap();
+        let metadata = path.metadata()
+            .unwrap();
+
+        let mut cache = HashCache::new(DEFAULT_CAPACITY);
+        cache.insert(&path, &metadata, digests());
+
+        let file = std::fs::File::options().write(true).open(&path)
+            .unwrap();
+        file.set_modified(metadata.modified().unwrap() + Duration::from_secs(1))
+            .unwrap();
+
+        let metadata = path.metadata()
+            .unwrap();
+        assert_eq!(cache.get(&path, &metadata), None);
+
+        // Stale entries are removed from the cache.
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn get_resized_miss() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foo")
+            .unwrap();
+        let metadata = path.metadata()
+            .unwrap();
+
+        let mut cache = HashCache::new(DEFAULT_CAPACITY);
+        cache.insert(&path, &metadata, digests());
+
+        let file = std::fs::File::options().write(true).open(&path)
+            .unwrap();
+        file.set_len(1337)
+            .unwrap();
+        // We restore the modification time to verify that the size alone is
+        // enough to invalidate the entry.
+        file.set_modified(metadata.modified().unwrap())
+            .unwrap();
+
+        let metadata = path.metadata()
+            .unwrap();
+        assert_eq!(cache.get(&path, &metadata), None);
+    }
+
+    #[test]
+    fn insert_evicts_least_recently_used() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let paths = ["foo", "bar", "baz"].map(|name| tempdir.path().join(name));
+        for path in &paths {
+            std::fs::write(path, b"")
+                .unwrap();
+        }
+        let [foo, bar, baz] = &paths;
+        let metadata = foo.metadata()
+            .unwrap();
+
+        let mut cache = HashCache::new(2);
+        cache.insert(foo, &foo.metadata().unwrap(), digests());
+        cache.insert(bar, &bar.metadata().unwrap(), digests());
+
+        // Using `foo` makes `bar` the least recently used entry.
+        assert!(cache.get(foo, &metadata).is_some());
+
+        cache.insert(baz, &baz.metadata().unwrap(), digests());
+        assert_eq!(cache.entries.len(), 2);
+
+        assert!(cache.get(foo, &foo.metadata().unwrap()).is_some());
+        assert!(cache.get(bar, &bar.metadata().unwrap()).is_none());
+        assert!(cache.get(baz, &baz.metadata().unwrap()).is_some());
+    }
+
+    #[test]
+    fn save_load() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let path = tempdir.path().join("foo");
+        std::fs::write(&path, b"foo")
+            .unwrap();
+        let metadata = path.metadata()
+            .unwrap();
+
+        let mut cache = HashCache::load(tempdir.path(), DEFAULT_CAPACITY);
+        assert!(cache.entries.is_empty());
+
+        cache.insert(&path, &metadata, digests());
+        cache.save()
+            .unwrap();
+
+        let mut cache = HashCache::load(tempdir.path(), DEFAULT_CAPACITY);
+        assert_eq!(cache.get(&path, &metadata), Some(&digests()));
+    }
+
+    #[test]
+    fn load_corrupted() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join(FILE_NAME), b"RRGHASH1\x05\x00")
+            .unwrap();
+
+        let cache = HashCache::load(tempdir.path(), DEFAULT_CAPACITY);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn load_non_existent_temp_dir() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let mut cache = HashCache::load(tempdir.path().join("foo"), DEFAULT_CAPACITY);
+        cache.insert(tempdir.path(), &tempdir.path().metadata().unwrap(), digests());
+
+        assert!(cache.save().is_ok());
+        assert!(!tempdir.path().join("foo").exists());
+    }
+
+    #[test]
+    fn digests_merge() {
+        let mut digests = Digests {
+            md5: Some([0x11; 16]),
+            sha1: Some([0x22; 20]),
+            sha256: None,
+            git_blob_sha1: Some([0x55; 20]),
+        };
+        digests.merge(Digests {
+            md5: None,
+            sha1: Some([0x33; 20]),
+            sha256: Some([0x44; 32]),
+            git_blob_sha1: None,
+        });
+
+        assert_eq!(digests, Digests {
+            md5: Some([0x11; 16]),
+            sha1: Some([0x33; 20]),
+            sha256: Some([0x44; 32]),
+            git_blob_sha1: Some([0x55; 20]),
+        });
+    }
+}
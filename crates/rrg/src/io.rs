@@ -145,6 +145,171 @@ where
     }
 }
 
+/// Configuration of content-defined chunking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkingConfig {
+    /// Minimum length of a chunk (except for the last one).
+    pub min_len: usize,
+    /// Expected average length of a chunk.
+    ///
+    /// Note that this value is rounded to the nearest power of two.
+    pub avg_len: usize,
+    /// Maximum length of a chunk.
+    pub max_len: usize,
+}
+
+/// An iterator over content-defined chunks of a reader.
+///
+/// Unlike fixed-size chunking, boundaries of the chunks are determined using a
+/// rolling hash (the "gear" hash used by [FastCDC][1]) of the content. Thus, a
+/// small modification of the input changes only the chunks around it and the
+/// rest of them stays the same.
+///
+/// [1]: https://www.usenix.org/conference/atc16/technical-sessions/presentation/xia
+///
+/// # Examples
+///
+/// ```
+/// let config = rrg::io::ChunkingConfig {
+///     min_len: 4,
+///     avg_len: 8,
+///     max_len: 16,
+/// };
+///
+/// let data = (0..1024).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+///
+/// let chunks = rrg::io::ContentChunks::new(&data[..], config)
+///     .collect::<std::io::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert!(chunks.iter().all(|chunk| chunk.len() <= 16));
+/// assert_eq!(chunks.concat(), data);
+/// ```
+pub struct ContentChunks<R> {
+    /// Underlying reader to chunk.
+    reader: R,
+    /// Configuration of the chunking.
+    config: ChunkingConfig,
+    /// Mask that the rolling hash is checked against to find boundaries.
+    mask: u64,
+    /// Data read from the reader but not yet returned as chunks.
+    buf: Vec<u8>,
+    /// Whether the reader has been read to the end.
+    eof: bool,
+}
+
+impl<R: Read> ContentChunks<R> {
+
+    /// Creates a new iterator over content-defined chunks of the given reader.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the configuration is not ordered (that is,
+    /// the condition `0 < min_len <= avg_len <= max_len` does not hold).
+    pub fn new(reader: R, config: ChunkingConfig) -> ContentChunks<R> {
+        assert! {
+            0 < config.min_len &&
+            config.min_len <= config.avg_len &&
+            config.avg_len <= config.max_len
+        };
+
+        // For uniformly distributed hashes, a boundary with `n` bits set in the
+        // mask occurs on average every `2^n` bytes.
+        let bits = (config.avg_len as f64).log2().round() as u32;
+
+        ContentChunks {
+            reader,
+            config,
+            mask: (1 << bits) - 1,
+            buf: Vec::with_capacity(config.max_len),
+            eof: false,
+        }
+    }
+
+    /// Fills the internal buffer up to the maximum chunk length (if possible).
+    fn fill_buf(&mut self) -> Result<()> {
+        while !self.eof && self.buf.len() < self.config.max_len {
+            let len = self.buf.len();
+            self.buf.resize(self.config.max_len, 0);
+
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(0) => {
+                    self.buf.truncate(len);
+                    self.eof = true;
+                }
+                Ok(len_read) => self.buf.truncate(len + len_read),
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {
+                    self.buf.truncate(len);
+                }
+                Err(error) => {
+                    self.buf.truncate(len);
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the length of the next chunk in the internal buffer.
+    fn next_chunk_len(&self) -> usize {
+        let mut hash = 0u64;
+
+        for (i, byte) in self.buf.iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+
+            if i + 1 >= self.config.min_len && hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+
+        self.buf.len()
+    }
+}
+
+impl<R: Read> Iterator for ContentChunks<R> {
+
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>>> {
+        if let Err(error) = self.fill_buf() {
+            return Some(Err(error));
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let len = self.next_chunk_len();
+        Some(Ok(self.buf.drain(..len).collect()))
+    }
+}
+
+/// Table of pseudorandom values used by the gear rolling hash.
+const GEAR: [u64; 256] = {
+    let mut table = [0; 256];
+
+    // We use SplitMix64 seeded with an arbitrary constant to generate the table
+    // deterministically: the values have to be stable across agent versions for
+    // chunk boundaries (and thus deduplication) to be stable as well.
+    let mut state: u64 = 0x5253_4744_4345_4152;
+
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^= value >> 31;
+
+        table[i] = value;
+        i += 1;
+    }
+
+    table
+};
+
 #[cfg(test)]
 mod tests {
 
@@ -240,4 +405,98 @@ mod tests {
 
         assert_eq!(buf, b"foobarbaz");
     }
+
+    /// Returns deterministic pseudorandom bytes of the given length.
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0xdead_beef_u32;
+
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    const TEST_CHUNKING_CONFIG: ChunkingConfig = ChunkingConfig {
+        min_len: 1024,
+        avg_len: 4096,
+        max_len: 16384,
+    };
+
+    #[test]
+    fn content_chunks_empty() {
+        let data: &[u8] = b"";
+
+        let mut chunks = ContentChunks::new(data, TEST_CHUNKING_CONFIG);
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn content_chunks_concat() {
+        let data = random_bytes(1024 * 1024);
+
+        let chunks = ContentChunks::new(&data[..], TEST_CHUNKING_CONFIG)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn content_chunks_len_bounds() {
+        let data = random_bytes(1024 * 1024);
+
+        let chunks = ContentChunks::new(&data[..], TEST_CHUNKING_CONFIG)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(last.len() <= TEST_CHUNKING_CONFIG.max_len);
+
+        for chunk in rest {
+            assert!(chunk.len() >= TEST_CHUNKING_CONFIG.min_len);
+            assert!(chunk.len() <= TEST_CHUNKING_CONFIG.max_len);
+        }
+    }
+
+    #[test]
+    fn content_chunks_uniform_data_max_len() {
+        let data = vec![0x42; 100_000];
+
+        let chunks = ContentChunks::new(&data[..], TEST_CHUNKING_CONFIG)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        // The hash of uniform data never matches the mask, so all the chunks
+        // should be cut at the maximum length.
+        assert_eq!(chunks.len(), 7);
+        assert!(chunks[..6].iter().all(|chunk| chunk.len() == 16384));
+        assert_eq!(chunks[6].len(), 100_000 - 6 * 16384);
+    }
+
+    #[test]
+    fn content_chunks_insertion_preserves_most_chunks() {
+        let data = random_bytes(1024 * 1024);
+
+        let mut data_modified = data.clone();
+        data_modified.splice(100..100, b"foobar".iter().copied());
+
+        let chunks = ContentChunks::new(&data[..], TEST_CHUNKING_CONFIG)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let chunks_modified = ContentChunks::new(&data_modified[..], TEST_CHUNKING_CONFIG)
+            .map(Result::unwrap)
+            .collect::<std::collections::HashSet<_>>();
+
+        let preserved = chunks.iter()
+            .filter(|chunk| chunks_modified.contains(*chunk))
+            .count();
+
+        // Only the chunk containing the insertion point (and possibly its
+        // neighbour) should be affected.
+        assert!(preserved + 2 >= chunks.len());
+        assert!(preserved < chunks.len());
+    }
 }
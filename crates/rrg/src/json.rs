@@ -0,0 +1,598 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Serialization of Protocol Buffers messages to JSON.
+//!
+//! Messages are serialized according to the canonical proto3 JSON mapping [1]
+//! (using reflection, so that any message can be serialized). Among others,
+//! this means that fields that are not set are omitted, 64-bit integers are
+//! written as strings (as JSON numbers cannot represent them precisely), bytes
+//! are base64-encoded and well-known types like `google.protobuf.Timestamp`
+//! have their special representations.
+//!
+//! The only departure from the mapping are nested `google.protobuf.Any`
+//! messages: expanding them requires knowing the type of every message that
+//! can be packed, so they are written with their raw (base64-encoded) value.
+//!
+//! [1]: https://protobuf.dev/programming-guides/proto3/#json
+
+use protobuf::reflect::{ReflectFieldRef, ReflectValueRef};
+
+/// Prefix of type URLs of messages packed into `google.protobuf.Any`.
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Serializes the given message as if it was packed into `google.protobuf.Any`.
+///
+/// The resulting JSON object has the `@type` key with the type URL of the
+/// message in addition to the keys of the message itself.
+pub fn to_json_any(message: &dyn protobuf::MessageDyn) -> String {
+    let descriptor = message.descriptor_dyn();
+
+    let mut json = String::new();
+    json.push_str("{\"@type\":");
+    write_string(&mut json, &format!("{TYPE_URL_PREFIX}{}", descriptor.full_name()));
+
+    // Messages with special representations cannot be merged into the object,
+    // so they are put under the `value` key instead.
+    if has_special_json(descriptor.full_name()) {
+        json.push_str(",\"value\":");
+        write_message(&mut json, message);
+    } else {
+        write_fields(&mut json, message, false);
+    }
+
+    json.push('}');
+    json
+}
+
+/// Serializes the given message as a JSON object.
+#[cfg(test)]
+pub fn to_json(message: &dyn protobuf::MessageDyn) -> String {
+    let mut json = String::new();
+    write_message(&mut json, message);
+    json
+}
+
+/// Checks whether the message of the given type is not a plain JSON object.
+fn has_special_json(full_name: &str) -> bool {
+    matches! {
+        full_name,
+        "google.protobuf.Timestamp" |
+        "google.protobuf.Duration" |
+        "google.protobuf.DoubleValue" |
+        "google.protobuf.FloatValue" |
+        "google.protobuf.Int64Value" |
+        "google.protobuf.UInt64Value" |
+        "google.protobuf.Int32Value" |
+        "google.protobuf.UInt32Value" |
+        "google.protobuf.BoolValue" |
+        "google.protobuf.StringValue" |
+        "google.protobuf.BytesValue"
+    }
+}
+
+/// Writes the JSON representation of the given message to `json`.
+fn write_message(json: &mut String, message: &dyn protobuf::MessageDyn) {
+    use protobuf::well_known_types::{any::Any, duration::Duration, timestamp::Timestamp};
+
+    let descriptor = message.descriptor_dyn();
+
+    match descriptor.full_name() {
+        "google.protobuf.Timestamp" => {
+            let timestamp = <dyn protobuf::MessageDyn>::downcast_ref::<Timestamp>(message)
+                .expect("invalid timestamp message");
+            write_timestamp(json, timestamp);
+        }
+        "google.protobuf.Duration" => {
+            let duration = <dyn protobuf::MessageDyn>::downcast_ref::<Duration>(message)
+                .expect("invalid duration message");
+            write_duration(json, duration);
+        }
+        "google.protobuf.Any" => {
+            let any = <dyn protobuf::MessageDyn>::downcast_ref::<Any>(message)
+                .expect("invalid any message");
+
+            json.push_str("{\"@type\":");
+            write_string(json, &any.type_url);
+            json.push_str(",\"value\":");
+            write_bytes(json, &any.value);
+            json.push('}');
+        }
+        full_name if has_special_json(full_name) => {
+            // The remaining special types are wrappers: they are represented by
+            // their (only) `value` field, even if it is not set.
+            let field = descriptor.field_by_name("value")
+                .expect("wrapper message without value");
+            write_value(json, field.get_singular_field_or_default(message));
+        }
+        _ => {
+            json.push('{');
+            write_fields(json, message, true);
+            json.push('}');
+        }
+    }
+}
+
+/// Writes the fields of the given message that are set as JSON object members.
+///
+/// If `first` is false, the members are assumed to follow existing ones.
+fn write_fields(json: &mut String, message: &dyn protobuf::MessageDyn, mut first: bool) {
+    let mut write_key = |json: &mut String, name: &str| {
+        if !first {
+            json.push(',');
+        }
+        first = false;
+
+        write_string(json, name);
+        json.push(':');
+    };
+
+    for field in message.descriptor_dyn().fields() {
+        match field.get_reflect(message) {
+            ReflectFieldRef::Optional(value) => {
+                let value = match value.value() {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                write_key(json, field.json_name());
+                write_value(json, value);
+            }
+            ReflectFieldRef::Repeated(values) => {
+                if values.is_empty() {
+                    continue;
+                }
+
+                write_key(json, field.json_name());
+                json.push('[');
+                for (index, value) in values.into_iter().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    write_value(json, value);
+                }
+                json.push(']');
+            }
+            ReflectFieldRef::Map(map) => {
+                if map.is_empty() {
+                    continue;
+                }
+
+                write_key(json, field.json_name());
+                json.push('{');
+                for (index, (key, value)) in (&map).into_iter().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    // Keys of JSON objects are always strings.
+                    match key {
+                        ReflectValueRef::String(key) => write_string(json, key),
+                        key => write_string(json, &key.to_string()),
+                    }
+                    json.push(':');
+                    write_value(json, value);
+                }
+                json.push('}');
+            }
+        }
+    }
+}
+
+/// Writes the JSON representation of the given field value to `json`.
+fn write_value(json: &mut String, value: ReflectValueRef) {
+    use std::fmt::Write as _;
+
+    match value {
+        ReflectValueRef::U32(value) => write!(json, "{value}").unwrap(),
+        ReflectValueRef::I32(value) => write!(json, "{value}").unwrap(),
+        // 64-bit integers do not fit into doubles that JSON numbers commonly
+        // are, so they are written as strings.
+        ReflectValueRef::U64(value) => write!(json, "\"{value}\"").unwrap(),
+        ReflectValueRef::I64(value) => write!(json, "\"{value}\"").unwrap(),
+        ReflectValueRef::F32(value) => write_float(json, f64::from(value), value.to_string()),
+        ReflectValueRef::F64(value) => write_float(json, value, value.to_string()),
+        ReflectValueRef::Bool(value) => write!(json, "{value}").unwrap(),
+        ReflectValueRef::String(value) => write_string(json, value),
+        ReflectValueRef::Bytes(value) => write_bytes(json, value),
+        ReflectValueRef::Enum(descriptor, number) => {
+            match descriptor.value_by_number(number) {
+                Some(value) => write_string(json, value.name()),
+                // Values unknown to the definition are allowed by the mapping
+                // to be written as numbers.
+                None => write!(json, "{number}").unwrap(),
+            }
+        }
+        ReflectValueRef::Message(message) => write_message(json, &*message),
+    }
+}
+
+/// Writes the given floating-point number to `json`.
+///
+/// `repr` should be the shortest decimal representation of the number (which
+/// differs for single- and double-precision numbers).
+fn write_float(json: &mut String, value: f64, repr: String) {
+    if value.is_nan() {
+        json.push_str("\"NaN\"");
+    } else if value == f64::INFINITY {
+        json.push_str("\"Infinity\"");
+    } else if value == f64::NEG_INFINITY {
+        json.push_str("\"-Infinity\"");
+    } else {
+        json.push_str(&repr);
+    }
+}
+
+/// Writes the given string as a JSON string literal to `json`.
+fn write_string(json: &mut String, string: &str) {
+    use std::fmt::Write as _;
+
+    json.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            char if char < ' ' => write!(json, "\\u{:04x}", char as u32).unwrap(),
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+}
+
+/// Writes the given bytes as a base64-encoded JSON string literal to `json`.
+fn write_bytes(json: &mut String, bytes: &[u8]) {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    json.push('"');
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).copied().unwrap_or(0);
+        let b2 = chunk.get(2).copied().unwrap_or(0);
+
+        json.push(char::from(ALPHABET[usize::from(b0 >> 2)]));
+        json.push(char::from(ALPHABET[usize::from((b0 & 0x03) << 4 | b1 >> 4)]));
+        if chunk.len() > 1 {
+            json.push(char::from(ALPHABET[usize::from((b1 & 0x0f) << 2 | b2 >> 6)]));
+        } else {
+            json.push('=');
+        }
+        if chunk.len() > 2 {
+            json.push(char::from(ALPHABET[usize::from(b2 & 0x3f)]));
+        } else {
+            json.push('=');
+        }
+    }
+    json.push('"');
+}
+
+/// Writes the given timestamp as an RFC 3339 JSON string literal to `json`.
+fn write_timestamp(json: &mut String, timestamp: &protobuf::well_known_types::timestamp::Timestamp) {
+    let nanos = std::time::Duration::from_nanos(u64::from(timestamp.nanos.unsigned_abs()));
+    let secs = std::time::Duration::from_secs(timestamp.seconds.unsigned_abs());
+
+    let time = if timestamp.seconds < 0 {
+        std::time::UNIX_EPOCH.checked_sub(secs)
+            .and_then(|time| time.checked_add(nanos))
+    } else {
+        std::time::UNIX_EPOCH.checked_add(secs + nanos)
+    };
+
+    match time {
+        Some(time) => {
+            let time = humantime::format_rfc3339_nanos(time).to_string();
+            write_string(json, &time);
+        }
+        // Timestamps that the system cannot represent are invalid anyway (the
+        // valid range starts at year 1), so we fall back to the epoch.
+        None => write_string(json, "1970-01-01T00:00:00Z"),
+    }
+}
+
+/// Writes the given duration as a JSON string literal (e.g. `"1.5s"`) to `json`.
+fn write_duration(json: &mut String, duration: &protobuf::well_known_types::duration::Duration) {
+    // Both fields of a valid duration have the same sign.
+    let sign = if duration.seconds < 0 || duration.nanos < 0 { "-" } else { "" };
+    let secs = duration.seconds.unsigned_abs();
+    let nanos = duration.nanos.unsigned_abs();
+
+    let duration = if nanos == 0 {
+        format!("{sign}{secs}s")
+    } else {
+        format!("{sign}{secs}.{nanos:09}s")
+    };
+    write_string(json, &duration);
+}
+
+/// A parsed JSON value.
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[cfg(test)]
+impl Value {
+
+    /// Returns the value of the given key if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => {
+                members.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the string this value is (if it is a string).
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the given JSON document.
+///
+/// This is a minimal parser intended only for verifying serialized messages in
+/// tests, so it panics on malformed documents.
+#[cfg(test)]
+pub fn parse(json: &str) -> Value {
+    let mut chars = json.chars().peekable();
+    let value = parse_value(&mut chars);
+
+    assert!(chars.all(char::is_whitespace), "trailing characters");
+    value
+}
+
+#[cfg(test)]
+fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Value {
+    fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.next_if(|char| char.is_whitespace()).is_some() {
+        }
+    }
+
+    fn expect(chars: &mut std::iter::Peekable<std::str::Chars>, literal: &str) {
+        for expected in literal.chars() {
+            assert_eq!(chars.next(), Some(expected));
+        }
+    }
+
+    fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+        assert_eq!(chars.next(), Some('"'));
+
+        let mut string = String::new();
+        loop {
+            match chars.next().expect("unterminated string") {
+                '"' => return string,
+                '\\' => match chars.next().expect("unterminated escape") {
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'u' => {
+                        let code = (0..4).map(|_| chars.next().unwrap()).collect::<String>();
+                        let code = u32::from_str_radix(&code, 16).unwrap();
+                        string.push(char::from_u32(code).unwrap());
+                    }
+                    char => string.push(char),
+                },
+                char => {
+                    assert!(char >= ' ', "unescaped control character");
+                    string.push(char);
+                }
+            }
+        }
+    }
+
+    skip_whitespace(chars);
+    let value = match *chars.peek().expect("unexpected end") {
+        'n' => {
+            expect(chars, "null");
+            Value::Null
+        }
+        't' => {
+            expect(chars, "true");
+            Value::Bool(true)
+        }
+        'f' => {
+            expect(chars, "false");
+            Value::Bool(false)
+        }
+        '"' => Value::String(parse_string(chars)),
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    break;
+                }
+                if !values.is_empty() {
+                    expect(chars, ",");
+                }
+                values.push(parse_value(chars));
+            }
+            Value::Array(values)
+        }
+        '{' => {
+            chars.next();
+            let mut members = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    break;
+                }
+                if !members.is_empty() {
+                    expect(chars, ",");
+                    skip_whitespace(chars);
+                }
+                let name = parse_string(chars);
+                skip_whitespace(chars);
+                expect(chars, ":");
+                members.push((name, parse_value(chars)));
+            }
+            Value::Object(members)
+        }
+        _ => {
+            let mut number = String::new();
+            while let Some(char) = chars.next_if(|char| "+-.eE0123456789".contains(*char)) {
+                number.push(char);
+            }
+            Value::Number(number.parse().expect("invalid number"))
+        }
+    };
+    skip_whitespace(chars);
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn to_json_empty() {
+        let proto = protobuf::well_known_types::empty::Empty::new();
+        assert_eq!(to_json(&proto), "{}");
+    }
+
+    #[test]
+    fn to_json_default_fields_omitted() {
+        let proto = rrg_proto::rrg::Status::new();
+        assert_eq!(to_json(&proto), "{}");
+    }
+
+    #[test]
+    fn to_json_scalars() {
+        let mut proto = rrg_proto::rrg::Status::new();
+        proto.set_reply_count(u64::MAX);
+        proto.set_filtered_out_count(42);
+        proto.mut_error().set_type(rrg_proto::rrg::status::error::Type::ACTION_FAILURE);
+        proto.mut_error().set_message(String::from("foo \"bar\"\n"));
+
+        let json = parse(&to_json(&proto));
+        assert_eq!(json.get("replyCount"), Some(&Value::String(u64::MAX.to_string())));
+        assert_eq!(json.get("filteredOutCount"), Some(&Value::Number(42.0)));
+
+        let error = json.get("error").unwrap();
+        assert_eq!(error.get("type").and_then(Value::as_str), Some("ACTION_FAILURE"));
+        assert_eq!(error.get("message").and_then(Value::as_str), Some("foo \"bar\"\n"));
+    }
+
+    #[test]
+    fn to_json_bytes() {
+        let mut proto = rrg_proto::rrg::SignedRequest::new();
+        proto.set_payload(b"f".to_vec());
+        proto.set_signature(b"foobar".to_vec());
+        assert_eq!(to_json(&proto), r#"{"payload":"Zg==","signature":"Zm9vYmFy"}"#);
+
+        let mut proto = rrg_proto::rrg::SignedRequest::new();
+        proto.set_payload(b"fo".to_vec());
+        proto.set_signature(b"\xff\xfe\xfd\x00".to_vec());
+        assert_eq!(to_json(&proto), r#"{"payload":"Zm8=","signature":"//79AA=="}"#);
+    }
+
+    #[test]
+    fn to_json_control_characters() {
+        let mut proto = rrg_proto::rrg::Log::new();
+        proto.set_message(String::from("\u{1}\t\u{1f}"));
+        assert_eq!(to_json(&proto), r#"{"message":"\u0001\t\u001f"}"#);
+    }
+
+    #[test]
+    fn to_json_timestamp() {
+        let mut proto = rrg_proto::rrg::Log::new();
+        proto.set_level(rrg_proto::rrg::log::Level::WARN);
+        proto.mut_timestamp().seconds = 1_700_000_000;
+        proto.mut_timestamp().nanos = 500_000_000;
+
+        let json = parse(&to_json(&proto));
+        assert_eq!(json.get("level").and_then(Value::as_str), Some("WARN"));
+        assert_eq! {
+            json.get("timestamp").and_then(Value::as_str),
+            Some("2023-11-14T22:13:20.500000000Z"),
+        };
+    }
+
+    #[test]
+    fn to_json_duration() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.mut_cpu_time_limit().seconds = 1;
+        proto.mut_cpu_time_limit().nanos = 500_000_000;
+        proto.mut_real_time_limit().seconds = 60;
+
+        let json = parse(&to_json(&proto));
+        assert_eq!(json.get("cpuTimeLimit").and_then(Value::as_str), Some("1.500000000s"));
+        assert_eq!(json.get("realTimeLimit").and_then(Value::as_str), Some("60s"));
+    }
+
+    #[test]
+    fn to_json_repeated_and_nested_any() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_FILE_CONTENTS);
+        proto.mut_args().type_url = String::from("type.googleapis.com/foo.Bar");
+        proto.mut_args().value = b"foo".to_vec();
+        proto.filters.push(rrg_proto::rrg::Filter::new());
+        proto.filters.push(rrg_proto::rrg::Filter::new());
+
+        let json = parse(&to_json(&proto));
+        assert_eq!(json.get("action").and_then(Value::as_str), Some("GET_FILE_CONTENTS"));
+        assert_eq!(json.get("filters"), Some(&Value::Array(vec![
+            Value::Object(vec![]),
+            Value::Object(vec![]),
+        ])));
+
+        let args = json.get("args").unwrap();
+        assert_eq!(args.get("@type").and_then(Value::as_str), Some("type.googleapis.com/foo.Bar"));
+        assert_eq!(args.get("value").and_then(Value::as_str), Some("Zm9v"));
+    }
+
+    #[test]
+    fn to_json_any_message() {
+        let mut proto = rrg_proto::rrg::Log::new();
+        proto.set_message(String::from("foo"));
+
+        let json = parse(&to_json_any(&proto));
+        assert_eq!(json.get("@type").and_then(Value::as_str), Some("type.googleapis.com/rrg.Log"));
+        assert_eq!(json.get("message").and_then(Value::as_str), Some("foo"));
+    }
+
+    #[test]
+    fn to_json_any_empty() {
+        let proto = protobuf::well_known_types::empty::Empty::new();
+        assert_eq! {
+            to_json_any(&proto),
+            r#"{"@type":"type.googleapis.com/google.protobuf.Empty"}"#,
+        };
+    }
+
+    #[test]
+    fn to_json_any_special() {
+        let mut proto = protobuf::well_known_types::wrappers::UInt64Value::new();
+        proto.value = 0;
+
+        assert_eq! {
+            to_json_any(&proto),
+            r#"{"@type":"type.googleapis.com/google.protobuf.UInt64Value","value":"0"}"#,
+        };
+
+        let mut proto = protobuf::well_known_types::duration::Duration::new();
+        proto.seconds = -1;
+        proto.nanos = -250_000_000;
+
+        assert_eq! {
+            to_json_any(&proto),
+            r#"{"@type":"type.googleapis.com/google.protobuf.Duration","value":"-1.250000000s"}"#,
+        };
+    }
+}
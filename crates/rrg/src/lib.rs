@@ -11,11 +11,36 @@ pub mod io;
 pub mod log;
 pub mod args;
 pub mod session;
+pub mod transport;
 
+#[cfg(any(
+    feature = "action-get_file_contents",
+    feature = "action-get_file_metadata",
+    feature = "action-get_filesystem_timeline",
+    feature = "action-stream_file_contents",
+))]
 mod blob;
+#[cfg(feature = "action-get_file_hash")]
+mod elf;
 mod filter;
+mod json;
+#[cfg(feature = "action-get_file_hash")]
+mod macho;
+#[cfg(feature = "action-get_agent_metrics")]
+mod metrics;
+#[cfg(feature = "action-get_file_metadata")]
+mod hash_cache;
+#[cfg(all(target_family = "unix", any(
+    feature = "action-get_file_metadata",
+    feature = "action-list_processes",
+)))]
+mod owner;
 mod request;
 mod response;
+mod seal;
+mod signed;
+#[cfg(feature = "action-get_file_hash")]
+mod ssdeep;
 
 pub mod startup; // TODO(@panhania): Hide this module.
 
@@ -26,14 +51,52 @@ pub mod chunked;
 pub mod gzchunked;
 
 pub use request::{ParseRequestError, Request, RequestId};
-pub use response::{LogBuilder, ResponseBuilder, ResponseId, Sink};
+pub use response::{LogBuilder, ResponseBuilder, ResponseId, Serialization, Sink};
+
+/// Whether the temporary directory of the agent passed the checks at startup.
+static TEMP_DIR_AVAILABLE: std::sync::atomic::AtomicBool = {
+    std::sync::atomic::AtomicBool::new(true)
+};
+
+/// Returns the temporary directory of the agent.
+///
+/// # Errors
+///
+/// This function will return an error if the directory could not be created or
+/// failed the ownership and permission checks at startup. Features relying on
+/// the directory are then disabled but the rest of the agent keeps working.
+pub(crate) fn temp_dir(args: &crate::args::Args) -> std::io::Result<&std::path::Path> {
+    if TEMP_DIR_AVAILABLE.load(std::sync::atomic::Ordering::Relaxed) {
+        Ok(&args.temp_dir)
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("temporary directory '{}' is not available", args.temp_dir.display()),
+        ))
+    }
+}
 
 /// Initializes the RRG subsystems.
 ///
 /// This function should be called only once (at the very beginning of the
 /// process lifetime).
 pub fn init(args: &crate::args::Args) {
-    log::init(args)
+    log::init(args);
+    #[cfg(feature = "action-get_agent_metrics")]
+    metrics::init();
+
+    fs::set_preserve_atime(args.preserve_atime);
+
+    #[cfg(all(target_family = "windows", feature = "action-apply_agent_update"))]
+    action::apply_agent_update::remove_old_executable();
+
+    #[cfg(feature = "action-upload_file")]
+    action::upload_file::init(args);
+
+    if let Err(error) = fs::create_private_dir(&args.temp_dir) {
+        ::log::error!("invalid temporary directory '{}': {error}", args.temp_dir.display());
+        TEMP_DIR_AVAILABLE.store(false, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 /// Enters the agent's main loop and waits for messages.
@@ -44,22 +107,145 @@ pub fn init(args: &crate::args::Args) {
 /// signals if expected to be long-running) and goes back to idling when action
 /// execution is finished.
 ///
-/// This function never terminates and panics only if something went very wrong
-/// (e.g. the Fleetspeak connection has been broken). All non-critical errors
-/// are going to be handled carefully, notifying the server about the failure if
-/// appropriate.
+/// This function terminates only once a restart of the agent is requested (e.g.
+/// because a new version of it was installed) and all the requests in progress
+/// are handled, in which case the process should exit and let Fleetspeak start
+/// it again. If the Fleetspeak connection breaks, the process exits right away
+/// for the same reason. All non-critical errors are going to be handled
+/// carefully, notifying the server about the failure if appropriate.
+///
+/// # Panics
+///
+/// This function panics if spooling with encryption was requested but the file
+/// with the secret cannot be read (or encryption is not enabled in the build).
 pub fn listen(args: &crate::args::Args) {
-    loop {
-        let request = Request::receive(args.heartbeat_rate);
-        session::FleetspeakSession::dispatch(request);
+    let fleetspeak = || transport::BackoffTransport::new(
+        transport::FleetspeakTransport,
+        args.collect_backoff_initial,
+        args.collect_backoff_max,
+    );
+
+    let spooling = args.spool_max_size.and_then(|max_size| {
+        // If encryption was requested but the secret is not available, this
+        // is a misconfiguration: spooling plaintext would leak the messages to
+        // the disk and not spooling at all would silently lose them.
+        let key = match &args.spool_secret_file {
+            #[cfg(feature = "spool-encryption")]
+            Some(path) => match std::fs::read(path) {
+                Ok(secret) => Some(seal::Key::derive(&secret, b"spool")),
+                Err(error) => {
+                    panic!("failed to read spool secret '{}': {error}", path.display());
+                }
+            },
+            #[cfg(not(feature = "spool-encryption"))]
+            Some(path) => {
+                panic!("spool secret '{}' specified but encryption is not enabled", path.display());
+            }
+            None => None,
+        };
+
+        let spool_dir = match temp_dir(args) {
+            Ok(temp_dir) => temp_dir.join("spool"),
+            Err(error) => {
+                ::log::error!("failed to open spool: {error}");
+                return None;
+            }
+        };
+        match transport::SpoolingTransport::new(fleetspeak(), &spool_dir, max_size, key) {
+            Ok(spooling) => Some(spooling),
+            Err(error) if transport::is_broken(&error) => transport::exit_broken(error),
+            Err(error) => {
+                ::log::error!("failed to open spool '{}': {error}", spool_dir.display());
+                None
+            }
+        }
+    });
+
+    let transport: std::sync::Arc<dyn transport::Transport> = match spooling {
+        Some(spooling) => std::sync::Arc::new(transport::CompressingTransport::new(
+            spooling,
+            args.compression,
+        )),
+        None => std::sync::Arc::new(transport::CompressingTransport::new(
+            fleetspeak(),
+            args.compression,
+        )),
+    };
+
+    let verifier = args.request_verification_key.map(|key| {
+        request::RequestVerifier::new(key, args.request_max_age)
+    });
+
+    let rate_limiter = session::RateLimiter::new(args.max_io_rate);
+    let registry = action::Registry::new(args);
+
+    // Waiting for a request blocks until one arrives, so requests are received
+    // on a separate thread. This way the loop below can notice that a restart
+    // was requested even if no more requests are coming.
+    let (request_sender, request_receiver) = std::sync::mpsc::sync_channel(0);
+    std::thread::spawn({
+        let transport = std::sync::Arc::clone(&transport);
+        let heartbeat_rate = args.heartbeat_rate;
+
+        move || loop {
+            let request = Request::receive(&*transport, heartbeat_rate, verifier.as_ref());
+
+            // The receiving end is dropped only when the agent is restarting.
+            // The request is lost then, the same as it would be if the agent
+            // crashed.
+            if request_sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    // The restart flag is checked before taking the next request, so all the
+    // requests received so far are handled before the loop is left.
+    let requests = std::iter::from_fn(|| loop {
+        use std::sync::mpsc::RecvTimeoutError;
+
+        if is_restart_requested() {
+            return None;
+        }
+
+        match request_receiver.recv_timeout(RESTART_CHECK_INTERVAL) {
+            Ok(request) => return Some(request),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    });
+    session::FleetspeakSession::serve(args, &transport, &rate_limiter, &registry, requests);
+
+    if let Err(error) = transport.flush() {
+        ::log::error!("failed to flush messages before restarting: {error}");
     }
 }
 
+/// Interval at which the agent checks whether a restart was requested while
+/// waiting for requests.
+const RESTART_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Whether the agent should stop listening for messages and restart.
+static RESTART_REQUESTED: std::sync::atomic::AtomicBool = {
+    std::sync::atomic::AtomicBool::new(false)
+};
+
+/// Requests the agent to restart once it is done with the current requests.
+#[cfg(feature = "action-apply_agent_update")]
+fn request_restart() {
+    RESTART_REQUESTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Checks whether a restart of the agent was requested.
+fn is_restart_requested() -> bool {
+    RESTART_REQUESTED.load(std::sync::atomic::Ordering::SeqCst)
+}
+
 /// Sends a system message with startup information to the GRR server.
 ///
 /// This function should be called only once at the beginning of RRG's process
 /// lifetime. It communicates to the GRR server that the agent has been started
 /// and sends some basic information like agent metadata.
 pub fn startup() {
-    startup::startup()
+    startup::startup(&transport::FleetspeakTransport)
 }
@@ -4,7 +4,6 @@
 // in the LICENSE file or at https://opensource.org/licenses/MIT.
 
 use log::Log;
-use lazy_static::lazy_static;
 
 /// Initializes the logging submodule.
 ///
@@ -41,6 +40,22 @@ pub fn init(args: &crate::args::Args) {
     log::set_max_level(log::LevelFilter::Trace);
 }
 
+/// Initializes the logging submodule for tests.
+///
+/// Only the logger that sends messages to the server is enabled, so logs of a
+/// request can be captured by the transport used in a test. It is safe to call
+/// this function multiple times.
+#[cfg(test)]
+pub fn init_test() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+
+    INIT.call_once(|| {
+        log::set_boxed_logger(Box::new(Logger::default()))
+            .expect("failed to initialize logger");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
 /// [`Log`] implementation that aggregates all supported loggers.
 struct Logger {
     /// Logger instance that writes messages to standard output.
@@ -176,51 +191,46 @@ impl<W: std::io::Write + Send + Sync> Log for WriterLogger<W> {
     }
 }
 
-lazy_static! {
-    /// A global instance of a logger that sends messages to the GRR server.
+thread_local! {
+    /// A per-thread instance of a logger that sends messages to the GRR server.
     ///
     /// This instance is `None` normally and is set to `Some` only when we are
     /// processing a request. To set the logger instance one should use the
-    /// [`ResponseLog::context`] method.
-    static ref RESPONSE_LOGGER: std::sync::RwLock<Option<ResponseLogger>> = {
-        std::sync::RwLock::new(None)
+    /// [`ResponseLogger::context`] method.
+    ///
+    /// The logger is specific to a thread because multiple requests can be
+    /// processed at the same time (each by a different thread).
+    static RESPONSE_LOGGER: std::cell::RefCell<Option<ResponseLogger>> = const {
+        std::cell::RefCell::new(None)
     };
 }
 
-
-/// [`Log`] implementation that uses global instance of [`ResponseLogger`].
+/// [`Log`] implementation that uses the thread instance of [`ResponseLogger`].
 struct GlobalResponseLogger;
 
 impl Log for GlobalResponseLogger {
 
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        let logger = RESPONSE_LOGGER.read()
-            .expect("failed to acquire response logger lock");
-
-        match logger.as_ref() {
+        RESPONSE_LOGGER.with_borrow(|logger| match logger.as_ref() {
             Some(logger) => logger.enabled(metadata),
             None => false,
-        }
+        })
     }
 
     fn log(&self, record: &log::Record) {
-        let logger = RESPONSE_LOGGER.read()
-            .expect("failed to acquire response logger lock");
-
-        match logger.as_ref() {
-            Some(logger) => logger.log(record),
-            None => (),
-        }
+        RESPONSE_LOGGER.with_borrow(|logger| {
+            if let Some(logger) = logger {
+                logger.log(record);
+            }
+        })
     }
 
     fn flush(&self) {
-        let logger = RESPONSE_LOGGER.read()
-            .expect("failed to acquire response logger lock");
-
-        match logger.as_ref() {
-            Some(logger) => logger.flush(),
-            None => (),
-        }
+        RESPONSE_LOGGER.with_borrow(|logger| {
+            if let Some(logger) = logger {
+                logger.flush();
+            }
+        })
     }
 }
 
@@ -228,6 +238,8 @@ impl Log for GlobalResponseLogger {
 pub struct ResponseLogger {
     /// Builder used to construct [`crate::response::Log`] objects.
     log_builder: crate::LogBuilder,
+    /// Transport through which the logs are sent.
+    transport: std::sync::Arc<dyn crate::transport::Transport>,
     /// Minimum level at which messages are sent to the server.
     log_level: log::LevelFilter,
 }
@@ -235,34 +247,35 @@ pub struct ResponseLogger {
 impl ResponseLogger {
 
     /// Constructs a new logger instance for the given [`crate::Request`].
-    pub fn new(request: &crate::Request) -> ResponseLogger {
+    ///
+    /// Logs are sent through the given `transport`.
+    pub fn new(
+        request: &crate::Request,
+        transport: std::sync::Arc<dyn crate::transport::Transport>,
+    ) -> ResponseLogger {
         ResponseLogger {
             log_builder: crate::LogBuilder::new(request.id()),
+            transport,
             log_level: request.log_level(),
         }
     }
 
     /// Runs the specified function in a context with this logger enabled.
     ///
+    /// The logger is enabled only on the calling thread, messages logged by
+    /// other threads (e.g. spawned by the function) are not sent.
+    ///
     /// # Panics
     ///
-    /// This function might panic or deadlock if called on a thread already
-    /// running within a response logger context.
+    /// This function might panic if called on a thread already running within
+    /// a response logger context.
     pub fn context<F, T>(self, func: F) -> T
     where
         F: FnOnce() -> T,
     {
-        let mut logger = RESPONSE_LOGGER.write()
-            .expect("failed to acquire response logger lock");
-        *logger = Some(self);
-        drop(logger);
-
+        RESPONSE_LOGGER.set(Some(self));
         let result = func();
-
-        let mut logger = RESPONSE_LOGGER.write()
-            .expect("failed to acquire response logger lock");
-        *logger = None;
-        drop(logger);
+        RESPONSE_LOGGER.set(None);
 
         result
     }
@@ -280,7 +293,7 @@ impl Log for ResponseLogger {
         }
 
         let log = self.log_builder.log(record);
-        log.send_unaccounted();
+        log.send_unaccounted(&*self.transport);
     }
 
     fn flush(&self) {
@@ -0,0 +1,531 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Parsing of Mach-O [1] binaries (the executable format of macOS).
+//!
+//! Only the parts needed to fingerprint binaries are parsed: headers of thin
+//! binaries, architecture tables of universal ("fat") binaries and a handful of
+//! load commands. Everything is read straight from the file and every offset
+//! and size is validated against the bounds of the file, so malformed binaries
+//! yield errors rather than bogus results.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Mach-O
+
+/// Magic number of 32-bit thin binaries (in the byte order of the binary).
+const MH_MAGIC: u32 = 0xfeedface;
+
+/// Magic number of 64-bit thin binaries (in the byte order of the binary).
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+
+/// Magic number of universal binaries with 32-bit offsets (big-endian).
+const FAT_MAGIC: u32 = 0xcafebabe;
+
+/// Magic number of universal binaries with 64-bit offsets (big-endian).
+const FAT_MAGIC_64: u32 = 0xcafebabf;
+
+/// Maximum number of slices of a universal binary.
+///
+/// Java class files use the same magic number as universal binaries but have
+/// the class file version (at least 45) where the slice count is, so a small
+/// bound is enough to tell the two apart.
+const MAX_FAT_ARCH_COUNT: u32 = 32;
+
+/// Maximum size of load commands of a single slice.
+const MAX_CMDS_LEN: u32 = 16 * 1024 * 1024; // 16 MiB.
+
+/// Flag of CPU types with 64-bit ABI.
+const CPU_ARCH_ABI64: u32 = 0x01000000;
+
+/// Flag of CPU types with 64-bit ABI on 32-bit pointers.
+const CPU_ARCH_ABI64_32: u32 = 0x02000000;
+
+/// Mask of capability bits of CPU subtypes.
+const CPU_SUBTYPE_MASK: u32 = 0xff000000;
+
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+const CPU_TYPE_POWERPC: u32 = 18;
+
+const LC_LOAD_DYLIB: u32 = 0xc;
+const LC_LOAD_WEAK_DYLIB: u32 = 0x80000018;
+const LC_REEXPORT_DYLIB: u32 = 0x8000001f;
+const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+const LC_RPATH: u32 = 0x8000001c;
+const LC_UUID: u32 = 0x1b;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const LC_ENCRYPTION_INFO: u32 = 0x21;
+const LC_ENCRYPTION_INFO_64: u32 = 0x2c;
+
+/// Parsed Mach-O binary.
+#[derive(Debug)]
+pub struct MachO {
+    /// Whether the binary is a universal one.
+    pub universal: bool,
+    /// Slices of the binary (a thin binary has exactly one).
+    pub slices: Vec<Slice>,
+}
+
+/// Single-architecture part of a Mach-O binary.
+#[derive(Debug)]
+pub struct Slice {
+    /// CPU type the slice is built for.
+    pub cpu_type: u32,
+    /// CPU subtype the slice is built for.
+    pub cpu_subtype: u32,
+    /// Byte offset of the slice within the file.
+    pub offset: u64,
+    /// Size of the slice in bytes.
+    pub len: u64,
+    /// Type of the slice (e.g. executable or dynamic library).
+    pub file_type: u32,
+    /// UUID of the slice (if it has one).
+    pub uuid: Option<[u8; 16]>,
+    /// Whether the slice has an embedded code signature.
+    pub code_signature: bool,
+    /// Whether the slice has an encrypted segment.
+    pub encrypted: bool,
+    /// Paths to the dynamic libraries the slice links against.
+    pub dylibs: Vec<String>,
+    /// Runtime search paths of dynamic libraries of the slice.
+    pub rpaths: Vec<String>,
+}
+
+impl Slice {
+
+    /// Returns the conventional name of the architecture of the slice.
+    ///
+    /// Returns `None` for architectures that are not known.
+    pub fn arch(&self) -> Option<&'static str> {
+        let cpu_subtype = self.cpu_subtype & !CPU_SUBTYPE_MASK;
+
+        let arch = match self.cpu_type {
+            CPU_TYPE_X86 => "i386",
+            cpu_type if cpu_type == CPU_TYPE_X86 | CPU_ARCH_ABI64 => match cpu_subtype {
+                8 => "x86_64h",
+                _ => "x86_64",
+            },
+            CPU_TYPE_ARM => match cpu_subtype {
+                6 => "armv6",
+                9 => "armv7",
+                11 => "armv7s",
+                12 => "armv7k",
+                _ => "arm",
+            },
+            cpu_type if cpu_type == CPU_TYPE_ARM | CPU_ARCH_ABI64 => match cpu_subtype {
+                2 => "arm64e",
+                _ => "arm64",
+            },
+            cpu_type if cpu_type == CPU_TYPE_ARM | CPU_ARCH_ABI64_32 => "arm64_32",
+            CPU_TYPE_POWERPC => "ppc",
+            cpu_type if cpu_type == CPU_TYPE_POWERPC | CPU_ARCH_ABI64 => "ppc64",
+            _ => return None,
+        };
+
+        Some(arch)
+    }
+}
+
+/// Parses the given file as a Mach-O binary.
+///
+/// Returns `None` if the file is not a Mach-O binary at all and an error if it
+/// looks like one but is malformed.
+pub fn parse<F>(file: &mut F, file_len: u64) -> std::io::Result<Option<MachO>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    let mut header = [0; 8];
+    if read_at(file, 0, &mut header).is_err() {
+        // Files too short to even have a magic number are not binaries.
+        return Ok(None);
+    }
+
+    match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        magic @ (FAT_MAGIC | FAT_MAGIC_64) => {
+            let count = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            if count == 0 || count > MAX_FAT_ARCH_COUNT {
+                return Ok(None);
+            }
+
+            let slices = parse_fat(file, file_len, magic == FAT_MAGIC_64, count)?;
+            Ok(Some(MachO {
+                universal: true,
+                slices,
+            }))
+        }
+        _ => match parse_thin(file, 0, file_len, None)? {
+            Some(slice) => Ok(Some(MachO {
+                universal: false,
+                slices: vec![slice],
+            })),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Parses all the slices described by the architecture table of a universal
+/// binary.
+fn parse_fat<F>(file: &mut F, file_len: u64, is_64: bool, count: u32) -> std::io::Result<Vec<Slice>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    let entry_len = if is_64 { 32 } else { 20 };
+
+    let mut slices = Vec::with_capacity(count as usize);
+    for i in 0..u64::from(count) {
+        let mut entry = [0; 32];
+        let entry = &mut entry[..entry_len];
+        read_at(file, 8 + i * entry_len as u64, entry)?;
+
+        let order = ByteOrder::Big;
+        let cpu_type = order.u32(entry, 0);
+        let cpu_subtype = order.u32(entry, 4);
+        let (offset, len) = if is_64 {
+            (order.u64(entry, 8), order.u64(entry, 16))
+        } else {
+            (u64::from(order.u32(entry, 8)), u64::from(order.u32(entry, 12)))
+        };
+
+        if offset.checked_add(len).is_none_or(|end| end > file_len) {
+            return Err(malformed("slice out of bounds"));
+        }
+
+        let slice = parse_thin(file, offset, len, Some((cpu_type, cpu_subtype)))?
+            .ok_or_else(|| malformed("slice is not a thin binary"))?;
+        slices.push(slice);
+    }
+
+    Ok(slices)
+}
+
+/// Parses a thin binary spanning `len` bytes from `offset` of the file.
+///
+/// For slices of universal binaries the CPU type from the architecture table
+/// is expected to match the one in the header of the slice.
+fn parse_thin<F>(
+    file: &mut F,
+    offset: u64,
+    len: u64,
+    fat_cpu: Option<(u32, u32)>,
+) -> std::io::Result<Option<Slice>>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    let mut header = [0; 32];
+    if len < 28 || read_at(file, offset, &mut header[..28]).is_err() {
+        return match fat_cpu {
+            Some(_) => Err(malformed("truncated slice header")),
+            None => Ok(None),
+        };
+    }
+
+    let (order, is_64) = match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+        MH_MAGIC => (ByteOrder::Little, false),
+        MH_MAGIC_64 => (ByteOrder::Little, true),
+        magic if magic.swap_bytes() == MH_MAGIC => (ByteOrder::Big, false),
+        magic if magic.swap_bytes() == MH_MAGIC_64 => (ByteOrder::Big, true),
+        _ => return Ok(None),
+    };
+
+    let cpu_type = order.u32(&header, 4);
+    let cpu_subtype = order.u32(&header, 8);
+    let file_type = order.u32(&header, 12);
+    let cmd_count = order.u32(&header, 16);
+    let cmds_len = order.u32(&header, 20);
+
+    if let Some((fat_cpu_type, _)) = fat_cpu {
+        if fat_cpu_type != cpu_type {
+            return Err(malformed("slice CPU type mismatch"));
+        }
+    }
+
+    let header_len = if is_64 { 32 } else { 28 };
+    if cmds_len > MAX_CMDS_LEN || header_len + u64::from(cmds_len) > len {
+        return Err(malformed("load commands out of bounds"));
+    }
+
+    let mut cmds = vec![0; cmds_len as usize];
+    read_at(file, offset + header_len, &mut cmds)?;
+
+    let mut slice = Slice {
+        cpu_type,
+        cpu_subtype,
+        offset,
+        len,
+        file_type,
+        uuid: None,
+        code_signature: false,
+        encrypted: false,
+        dylibs: Vec::new(),
+        rpaths: Vec::new(),
+    };
+
+    let mut cmds = &cmds[..];
+    for _ in 0..cmd_count {
+        if cmds.len() < 8 {
+            return Err(malformed("truncated load command"));
+        }
+
+        let cmd = order.u32(cmds, 0);
+        let cmd_len = order.u32(cmds, 4) as usize;
+        if cmd_len < 8 || cmd_len > cmds.len() {
+            return Err(malformed("load command out of bounds"));
+        }
+
+        let (cmd_buf, rest) = cmds.split_at(cmd_len);
+        cmds = rest;
+
+        match cmd {
+            LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LAZY_LOAD_DYLIB => {
+                slice.dylibs.push(lc_str(order, cmd_buf)?);
+            }
+            LC_RPATH => {
+                slice.rpaths.push(lc_str(order, cmd_buf)?);
+            }
+            LC_UUID => {
+                let uuid = cmd_buf.get(8..24)
+                    .ok_or_else(|| malformed("truncated UUID command"))?;
+                slice.uuid = Some(uuid.try_into().unwrap());
+            }
+            LC_CODE_SIGNATURE => {
+                slice.code_signature = true;
+            }
+            LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64 => {
+                if cmd_buf.len() < 20 {
+                    return Err(malformed("truncated encryption command"));
+                }
+                // The identifier of the encryption system is zero if the
+                // segment is not encrypted (yet).
+                slice.encrypted |= order.u32(cmd_buf, 16) != 0;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(Some(slice))
+}
+
+/// Extracts the string a load command refers to.
+///
+/// Strings of load commands are specified by an offset (relative to the start
+/// of the command) right after the command header and are null-terminated.
+fn lc_str(order: ByteOrder, cmd_buf: &[u8]) -> std::io::Result<String> {
+    if cmd_buf.len() < 12 {
+        return Err(malformed("truncated load command string"));
+    }
+
+    let str_offset = order.u32(cmd_buf, 8) as usize;
+    let str_buf = cmd_buf.get(str_offset..)
+        .ok_or_else(|| malformed("load command string out of bounds"))?;
+    let str_len = str_buf.iter().position(|byte| *byte == 0)
+        .unwrap_or(str_buf.len());
+
+    Ok(String::from_utf8_lossy(&str_buf[..str_len]).into_owned())
+}
+
+/// Reads exactly `buf.len()` bytes at the given offset of the file.
+fn read_at<F>(file: &mut F, offset: u64, buf: &mut [u8]) -> std::io::Result<()>
+where
+    F: std::io::Read + std::io::Seek,
+{
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+/// Creates an error for malformed binaries.
+fn malformed(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Byte order of values of a binary.
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+
+    /// Decodes a 32-bit value at the given offset of the buffer.
+    ///
+    /// The caller is responsible for ensuring that the buffer is long enough.
+    fn u32(self, buf: &[u8], offset: usize) -> u32 {
+        let bytes = buf[offset..offset + 4].try_into().unwrap();
+        match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    /// Decodes a 64-bit value at the given offset of the buffer.
+    ///
+    /// The caller is responsible for ensuring that the buffer is long enough.
+    fn u64(self, buf: &[u8], offset: usize) -> u64 {
+        let bytes = buf[offset..offset + 8].try_into().unwrap();
+        match self {
+            ByteOrder::Little => u64::from_le_bytes(bytes),
+            ByteOrder::Big => u64::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// Builds a minimal little-endian 64-bit thin binary for tests.
+///
+/// The binary has a UUID, links against `libSystem` and, if requested, has a
+/// code signature command (without the signature data itself).
+#[cfg(test)]
+pub fn fixture_thin(cpu_type: u32, cpu_subtype: u32, code_signature: bool) -> Vec<u8> {
+    let mut cmds = Vec::new();
+
+    cmds.extend_from_slice(&LC_UUID.to_le_bytes());
+    cmds.extend_from_slice(&24u32.to_le_bytes());
+    cmds.extend_from_slice(&[0x42; 16]);
+
+    let dylib = b"/usr/lib/libSystem.B.dylib\0\0\0\0\0\0";
+    cmds.extend_from_slice(&LC_LOAD_DYLIB.to_le_bytes());
+    cmds.extend_from_slice(&(24 + dylib.len() as u32).to_le_bytes());
+    cmds.extend_from_slice(&24u32.to_le_bytes()); // Name offset.
+    cmds.extend_from_slice(&[0; 12]); // Timestamp and versions.
+    cmds.extend_from_slice(dylib);
+
+    let mut cmd_count = 2u32;
+    if code_signature {
+        cmds.extend_from_slice(&LC_CODE_SIGNATURE.to_le_bytes());
+        cmds.extend_from_slice(&16u32.to_le_bytes());
+        cmds.extend_from_slice(&[0; 8]); // Data offset and size.
+        cmd_count += 1;
+    }
+
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&MH_MAGIC_64.to_le_bytes());
+    binary.extend_from_slice(&cpu_type.to_le_bytes());
+    binary.extend_from_slice(&cpu_subtype.to_le_bytes());
+    binary.extend_from_slice(&2u32.to_le_bytes()); // MH_EXECUTE.
+    binary.extend_from_slice(&cmd_count.to_le_bytes());
+    binary.extend_from_slice(&(cmds.len() as u32).to_le_bytes());
+    binary.extend_from_slice(&0u32.to_le_bytes()); // Flags.
+    binary.extend_from_slice(&0u32.to_le_bytes()); // Reserved.
+    binary.extend_from_slice(&cmds);
+    binary.extend_from_slice(b"__TEXT");
+
+    binary
+}
+
+/// Builds a universal binary consisting of the given thin binaries for tests.
+///
+/// Slices are aligned to 4 KiB like the ones produced by `lipo`.
+#[cfg(test)]
+pub fn fixture_fat(slices: &[Vec<u8>]) -> Vec<u8> {
+    const ALIGN: usize = 4096;
+
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&FAT_MAGIC.to_be_bytes());
+    binary.extend_from_slice(&(slices.len() as u32).to_be_bytes());
+
+    let mut offset = ALIGN;
+    for slice in slices {
+        // CPU type and subtype are at the same place in the thin header.
+        binary.extend_from_slice(&u32::from_le_bytes(slice[4..8].try_into().unwrap()).to_be_bytes());
+        binary.extend_from_slice(&u32::from_le_bytes(slice[8..12].try_into().unwrap()).to_be_bytes());
+        binary.extend_from_slice(&(offset as u32).to_be_bytes());
+        binary.extend_from_slice(&(slice.len() as u32).to_be_bytes());
+        binary.extend_from_slice(&12u32.to_be_bytes()); // Alignment (2^12).
+
+        offset += slice.len().div_ceil(ALIGN) * ALIGN;
+    }
+
+    for slice in slices {
+        binary.resize(binary.len().div_ceil(ALIGN) * ALIGN, 0);
+        binary.extend_from_slice(slice);
+    }
+
+    binary
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Parses the given bytes as a Mach-O binary.
+    fn parse_bytes(bytes: &[u8]) -> std::io::Result<Option<MachO>> {
+        parse(&mut std::io::Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    #[test]
+    fn parse_thin_arm64() {
+        let binary = fixture_thin(CPU_TYPE_ARM | CPU_ARCH_ABI64, 0, true);
+
+        let macho = parse_bytes(&binary).unwrap().unwrap();
+        assert!(!macho.universal);
+        assert_eq!(macho.slices.len(), 1);
+
+        let slice = &macho.slices[0];
+        assert_eq!(slice.arch(), Some("arm64"));
+        assert_eq!(slice.offset, 0);
+        assert_eq!(slice.len, binary.len() as u64);
+        assert_eq!(slice.file_type, 2);
+        assert_eq!(slice.uuid, Some([0x42; 16]));
+        assert!(slice.code_signature);
+        assert!(!slice.encrypted);
+        assert_eq!(slice.dylibs, vec!["/usr/lib/libSystem.B.dylib"]);
+        assert!(slice.rpaths.is_empty());
+    }
+
+    #[test]
+    fn parse_fat_slices() {
+        let binary = fixture_fat(&[
+            fixture_thin(CPU_TYPE_X86 | CPU_ARCH_ABI64, 3, false),
+            fixture_thin(CPU_TYPE_ARM | CPU_ARCH_ABI64, 2, true),
+        ]);
+
+        let macho = parse_bytes(&binary).unwrap().unwrap();
+        assert!(macho.universal);
+
+        let archs = macho.slices.iter()
+            .map(Slice::arch)
+            .collect::<Vec<_>>();
+        assert_eq!(archs, vec![Some("x86_64"), Some("arm64e")]);
+
+        assert!(!macho.slices[0].code_signature);
+        assert!(macho.slices[1].code_signature);
+        assert_eq!(macho.slices[0].offset, 4096);
+        assert_eq!(macho.slices[1].offset, 8192);
+    }
+
+    #[test]
+    fn parse_not_macho() {
+        assert!(parse_bytes(b"").unwrap().is_none());
+        assert!(parse_bytes(b"\x7fELF\x02\x01\x01\0").unwrap().is_none());
+        assert!(parse_bytes(b"lorem ipsum dolor sit amet").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_java_class() {
+        // Magic number followed by the class file version 52.0 (Java 8).
+        let class = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x1d\x0a\x00";
+        assert!(parse_bytes(class).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_fat_slice_out_of_bounds() {
+        let mut binary = fixture_fat(&[
+            fixture_thin(CPU_TYPE_ARM | CPU_ARCH_ABI64, 0, true),
+        ]);
+        binary.truncate(4096 + 16);
+
+        let error = parse_bytes(&binary).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_thin_load_commands_out_of_bounds() {
+        let mut binary = fixture_thin(CPU_TYPE_ARM | CPU_ARCH_ABI64, 0, true);
+        // Size of all the load commands.
+        binary[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let error = parse_bytes(&binary).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -17,4 +17,8 @@ fn main() {
 
     info!("listening for messages");
     rrg::listen(&args);
+
+    // Fleetspeak starts the agent again (from its, possibly updated, executable)
+    // once the process exits.
+    info!("restarting");
 }
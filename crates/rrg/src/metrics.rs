@@ -0,0 +1,159 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Counters describing the activity of the agent.
+//!
+//! Counters are global to the agent process and are updated by the session
+//! layer as requests are handled. They never decrease: the only way to reset
+//! them is to restart the agent.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of actions executed so far (regardless of whether they succeeded).
+static ACTIONS_EXECUTED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of actions that failed so far, by the kind of error.
+static ACTIONS_FAILED: std::sync::Mutex<Vec<(crate::session::ErrorKind, u64)>> = {
+    std::sync::Mutex::new(Vec::new())
+};
+
+/// Number of bytes sent to the server by actions so far.
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+/// Moment at which the metrics started to be collected.
+static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
+/// Snapshot of all the counters at a particular moment.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    /// Number of actions executed (including the failed ones).
+    pub actions_executed: u64,
+    /// Number of actions that failed, by the kind of error.
+    pub actions_failed: Vec<(crate::session::ErrorKind, u64)>,
+    /// Number of bytes sent to the server by actions.
+    pub bytes_sent: u64,
+    /// Time for which the agent has been running.
+    pub uptime: std::time::Duration,
+}
+
+#[cfg(test)]
+impl Metrics {
+
+    /// Returns the number of actions that failed with the given kind of error.
+    pub fn actions_failed(&self, kind: crate::session::ErrorKind) -> u64 {
+        self.actions_failed.iter()
+            .find(|(failed_kind, _)| *failed_kind == kind)
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    }
+}
+
+/// Starts measuring the uptime of the agent.
+///
+/// This function should be called once at the agent startup. Otherwise, the
+/// uptime is measured since the first time the metrics are accessed.
+pub fn init() {
+    START.get_or_init(std::time::Instant::now);
+}
+
+/// Records the result of an executed action.
+pub fn record_action(result: &crate::session::Result<()>) {
+    ACTIONS_EXECUTED.fetch_add(1, Ordering::Relaxed);
+
+    if let Err(error) = result {
+        record_failure(error.kind());
+    }
+}
+
+/// Records that a request could not even be turned into an action execution.
+pub fn record_invalid_request(error: &crate::session::Error) {
+    ACTIONS_EXECUTED.fetch_add(1, Ordering::Relaxed);
+    record_failure(error.kind());
+}
+
+/// Records that the given number of bytes was sent to the server.
+pub fn record_bytes_sent(len: u64) {
+    BYTES_SENT.fetch_add(len, Ordering::Relaxed);
+}
+
+/// Returns the current values of all the counters.
+pub fn snapshot() -> Metrics {
+    let actions_failed = ACTIONS_FAILED.lock()
+        .expect("poisoned failed actions counter")
+        .clone();
+
+    Metrics {
+        actions_executed: ACTIONS_EXECUTED.load(Ordering::Relaxed),
+        actions_failed,
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        uptime: START.get_or_init(std::time::Instant::now).elapsed(),
+    }
+}
+
+/// Increments the counter of failures with the given kind of error.
+fn record_failure(kind: crate::session::ErrorKind) {
+    let mut actions_failed = ACTIONS_FAILED.lock()
+        .expect("poisoned failed actions counter");
+
+    match actions_failed.iter_mut().find(|(failed_kind, _)| *failed_kind == kind) {
+        Some((_, count)) => *count += 1,
+        None => actions_failed.push((kind, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[cfg(feature = "action-get_client_actions")]
+    #[test]
+    fn dispatch_success_and_failure() {
+        use crate::session::ErrorKind::UnsupportedAction;
+
+        let args = crate::args::Args::default();
+        let registry = crate::action::Registry::new(&args);
+        let rate_limiter = crate::session::RateLimiter::default();
+        let transport: std::sync::Arc<dyn crate::transport::Transport> = {
+            std::sync::Arc::new(crate::transport::LoopbackTransport::new())
+        };
+
+        let before = snapshot();
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_args(protobuf::well_known_types::any::Any::pack(&{
+            protobuf::well_known_types::empty::Empty::new()
+        }).unwrap());
+        let request = crate::Request::try_from(request);
+        crate::session::FleetspeakSession::dispatch(&args, &transport, &rate_limiter, &registry, request);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_action(rrg_proto::rrg::Action::LIST_NAMED_PIPES);
+        let request = crate::Request::try_from(request);
+        crate::session::FleetspeakSession::dispatch(&args, &transport, &rate_limiter, &registry, request);
+
+        let after = snapshot();
+
+        // Tests run concurrently and share the counters, so we can only verify
+        // that they grew at least by what we did.
+        assert!(after.actions_executed >= before.actions_executed + 2);
+        assert!(after.actions_failed(UnsupportedAction) > before.actions_failed(UnsupportedAction));
+        assert!(after.bytes_sent > before.bytes_sent);
+        assert!(after.uptime >= before.uptime);
+    }
+
+    #[test]
+    fn record_failure_by_kind() {
+        use crate::session::ErrorKind::FilterFailure;
+
+        let before = snapshot();
+        record_failure(FilterFailure);
+        record_failure(FilterFailure);
+        let after = snapshot();
+
+        assert!(after.actions_failed(FilterFailure) >= before.actions_failed(FilterFailure) + 2);
+    }
+}
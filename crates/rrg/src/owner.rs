@@ -0,0 +1,97 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Resolution of identifiers of users and groups owning files and processes.
+//!
+//! Names are looked up in the system user and group databases (respecting the
+//! system configuration, so e.g. LDAP users are resolved as well). Walks over
+//! the filesystem or the process list encounter the same few owners over and
+//! over again, so every identifier is looked up only once per [`OwnerNames`].
+
+use std::collections::HashMap;
+
+/// Cache of names of users and groups.
+#[derive(Default)]
+pub struct OwnerNames {
+    /// Names of users keyed by their identifiers.
+    users: HashMap<u32, String>,
+    /// Names of groups keyed by their identifiers.
+    groups: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+
+    /// Creates a new, empty cache.
+    pub fn new() -> OwnerNames {
+        OwnerNames::default()
+    }
+
+    /// Returns the name of the user with the given identifier.
+    ///
+    /// Users that do not exist (e.g. owners of orphaned files) or that cannot be
+    /// looked up have an empty name.
+    pub fn user(&mut self, uid: u32) -> &str {
+        self.users.entry(uid)
+            .or_insert_with(|| resolve("user", uid, ospect::os::user_name))
+    }
+
+    /// Returns the name of the group with the given identifier.
+    ///
+    /// Groups that do not exist or that cannot be looked up have an empty name.
+    pub fn group(&mut self, gid: u32) -> &str {
+        self.groups.entry(gid)
+            .or_insert_with(|| resolve("group", gid, ospect::os::group_name))
+    }
+}
+
+/// Looks up the name for the given identifier using the given function.
+fn resolve<F>(kind: &str, id: u32, lookup: F) -> String
+where
+    F: FnOnce(u32) -> std::io::Result<Option<std::ffi::OsString>>,
+{
+    match lookup(id) {
+        Ok(Some(name)) => name.to_string_lossy().into_owned(),
+        Ok(None) => String::new(),
+        Err(error) => {
+            log::warn!("failed to resolve {kind} {id}: {error}");
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn user_root() {
+        let mut names = OwnerNames::new();
+        assert_eq!(names.user(0), "root");
+    }
+
+    #[test]
+    fn user_unknown() {
+        let mut names = OwnerNames::new();
+        assert_eq!(names.user(u32::MAX - 1337), "");
+    }
+
+    #[test]
+    fn group_unknown() {
+        let mut names = OwnerNames::new();
+        assert_eq!(names.group(u32::MAX - 1337), "");
+    }
+
+    #[test]
+    fn cached() {
+        let mut names = OwnerNames::new();
+        assert_eq!(names.user(0), "root");
+        assert_eq!(names.user(0), "root");
+        assert_eq!(names.user(u32::MAX - 1337), "");
+
+        assert_eq!(names.users.len(), 2);
+        assert!(names.groups.is_empty());
+    }
+}
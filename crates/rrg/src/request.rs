@@ -49,6 +49,84 @@ pub enum Action {
     ListWinregKeys,
     /// Query WMI using WQL (Windows-only).
     QueryWmi,
+    /// Get shell history of local users.
+    GetShellHistory,
+    /// Collect an artifact consisting of multiple sources.
+    CollectArtifact,
+    /// List actions that the agent can execute.
+    GetClientActions,
+    /// Resolve symlinks of the specified path.
+    ResolvePath,
+    /// Write a file to the filesystem.
+    WriteFile,
+    /// Compute sizes of a directory subtree.
+    GetDirectorySize,
+    /// Find certificates and private keys.
+    FindCryptoMaterial,
+    /// Stream contents of the whole file in sequential chunks.
+    StreamFileContents,
+    /// Compute Shannon entropy of a file.
+    GetFileEntropy,
+    /// List mount namespaces of processes on the system.
+    ListMountNamespaces,
+    /// Get DNS resolver configuration of the system.
+    GetDnsConfig,
+    /// List entries of the hosts file of the system.
+    ListHostsEntries,
+    /// List open file descriptors of processes.
+    ListOpenFds,
+    /// Execute a command signed with the configured key.
+    ExecuteSignedCommand,
+    /// Get counters describing the activity of the agent.
+    GetAgentMetrics,
+    /// List autorun entries in the Windows registry.
+    ListAutoruns,
+    /// List launchd jobs configured on macOS.
+    ListLaunchdJobs,
+    /// List files recently used by users of desktop environments.
+    ListRecentFiles,
+    /// List block devices and their partitions on Linux.
+    ListBlockDevices,
+    /// Delete temporary files created by the agent.
+    DeleteTempFiles,
+    /// Resolve hostnames to IP addresses.
+    ResolveHostnames,
+    /// Get environment variables of the agent or other processes.
+    GetEnvironment,
+    /// Get entries of the systemd journal.
+    GetJournalEntries,
+    /// Collect authentication-related configuration files.
+    GetAuthConfig,
+    /// Collect authorized SSH keys of local users.
+    GetAuthorizedKeys,
+    /// Walk a directory tree and hash all the files in it.
+    HashFileTree,
+    /// Get records of the Windows event log.
+    GetEventLogRecords,
+    /// Move a file into the quarantine directory of the agent.
+    QuarantineFile,
+    /// Restore a quarantined file to its original location.
+    RestoreQuarantinedFile,
+    /// List memory mappings of processes.
+    ListProcessMappings,
+    /// Query state of system services over D-Bus.
+    QueryDbusState,
+    /// Upload a file to a URL provided by the server.
+    UploadFile,
+    /// List executables reachable through the `PATH` environment variable.
+    ListPathExecutables,
+    /// Get the block allocation map (extents) of a file.
+    GetFileExtents,
+    /// Get events from the Linux audit log.
+    GetAuditEvents,
+    /// Watch a directory for changes for a bounded duration.
+    WatchDirectory,
+    /// List certificates of the system trust store.
+    ListTrustedCertificates,
+    /// Get the timezone and clock settings of the system.
+    GetTimeConfig,
+    /// Replace the agent executable with a new signed version.
+    ApplyAgentUpdate,
 }
 
 impl std::fmt::Display for Action {
@@ -72,6 +150,45 @@ impl std::fmt::Display for Action {
             Action::ListWinregValues => write!(fmt, "list_winreg_values"),
             Action::ListWinregKeys => write!(fmt, "list_winreg_keys"),
             Action::QueryWmi => write!(fmt, "query_wmi"),
+            Action::GetShellHistory => write!(fmt, "get_shell_history"),
+            Action::CollectArtifact => write!(fmt, "collect_artifact"),
+            Action::GetClientActions => write!(fmt, "get_client_actions"),
+            Action::ResolvePath => write!(fmt, "resolve_path"),
+            Action::WriteFile => write!(fmt, "write_file"),
+            Action::GetDirectorySize => write!(fmt, "get_directory_size"),
+            Action::FindCryptoMaterial => write!(fmt, "find_crypto_material"),
+            Action::StreamFileContents => write!(fmt, "stream_file_contents"),
+            Action::GetFileEntropy => write!(fmt, "get_file_entropy"),
+            Action::ListMountNamespaces => write!(fmt, "list_mount_namespaces"),
+            Action::GetDnsConfig => write!(fmt, "get_dns_config"),
+            Action::ListHostsEntries => write!(fmt, "list_hosts_entries"),
+            Action::ListOpenFds => write!(fmt, "list_open_fds"),
+            Action::ExecuteSignedCommand => write!(fmt, "execute_signed_command"),
+            Action::GetAgentMetrics => write!(fmt, "get_agent_metrics"),
+            Action::ListAutoruns => write!(fmt, "list_autoruns"),
+            Action::ListLaunchdJobs => write!(fmt, "list_launchd_jobs"),
+            Action::ListRecentFiles => write!(fmt, "list_recent_files"),
+            Action::ListBlockDevices => write!(fmt, "list_block_devices"),
+            Action::DeleteTempFiles => write!(fmt, "delete_temp_files"),
+            Action::ResolveHostnames => write!(fmt, "resolve_hostnames"),
+            Action::GetEnvironment => write!(fmt, "get_environment"),
+            Action::GetJournalEntries => write!(fmt, "get_journal_entries"),
+            Action::GetAuthConfig => write!(fmt, "get_auth_config"),
+            Action::GetAuthorizedKeys => write!(fmt, "get_authorized_keys"),
+            Action::HashFileTree => write!(fmt, "hash_file_tree"),
+            Action::GetEventLogRecords => write!(fmt, "get_event_log_records"),
+            Action::QuarantineFile => write!(fmt, "quarantine_file"),
+            Action::RestoreQuarantinedFile => write!(fmt, "restore_quarantined_file"),
+            Action::ListProcessMappings => write!(fmt, "list_process_mappings"),
+            Action::QueryDbusState => write!(fmt, "query_dbus_state"),
+            Action::UploadFile => write!(fmt, "upload_file"),
+            Action::ListPathExecutables => write!(fmt, "list_path_executables"),
+            Action::GetFileExtents => write!(fmt, "get_file_extents"),
+            Action::GetAuditEvents => write!(fmt, "get_audit_events"),
+            Action::WatchDirectory => write!(fmt, "watch_directory"),
+            Action::ListTrustedCertificates => write!(fmt, "list_trusted_certificates"),
+            Action::GetTimeConfig => write!(fmt, "get_time_config"),
+            Action::ApplyAgentUpdate => write!(fmt, "apply_agent_update"),
         }
     }
 }
@@ -121,6 +238,45 @@ impl TryFrom<rrg_proto::rrg::Action> for Action {
             LIST_WINREG_VALUES => Ok(Action::ListWinregValues),
             LIST_WINREG_KEYS => Ok(Action::ListWinregKeys),
             QUERY_WMI => Ok(Action::QueryWmi),
+            GET_SHELL_HISTORY => Ok(Action::GetShellHistory),
+            COLLECT_ARTIFACT => Ok(Action::CollectArtifact),
+            GET_CLIENT_ACTIONS => Ok(Action::GetClientActions),
+            RESOLVE_PATH => Ok(Action::ResolvePath),
+            WRITE_FILE => Ok(Action::WriteFile),
+            GET_DIRECTORY_SIZE => Ok(Action::GetDirectorySize),
+            FIND_CRYPTO_MATERIAL => Ok(Action::FindCryptoMaterial),
+            STREAM_FILE_CONTENTS => Ok(Action::StreamFileContents),
+            GET_FILE_ENTROPY => Ok(Action::GetFileEntropy),
+            LIST_MOUNT_NAMESPACES => Ok(Action::ListMountNamespaces),
+            GET_DNS_CONFIG => Ok(Action::GetDnsConfig),
+            LIST_HOSTS_ENTRIES => Ok(Action::ListHostsEntries),
+            LIST_OPEN_FDS => Ok(Action::ListOpenFds),
+            EXECUTE_SIGNED_COMMAND => Ok(Action::ExecuteSignedCommand),
+            GET_AGENT_METRICS => Ok(Action::GetAgentMetrics),
+            LIST_AUTORUNS => Ok(Action::ListAutoruns),
+            LIST_LAUNCHD_JOBS => Ok(Action::ListLaunchdJobs),
+            LIST_RECENT_FILES => Ok(Action::ListRecentFiles),
+            LIST_BLOCK_DEVICES => Ok(Action::ListBlockDevices),
+            DELETE_TEMP_FILES => Ok(Action::DeleteTempFiles),
+            RESOLVE_HOSTNAMES => Ok(Action::ResolveHostnames),
+            GET_ENVIRONMENT => Ok(Action::GetEnvironment),
+            GET_JOURNAL_ENTRIES => Ok(Action::GetJournalEntries),
+            GET_AUTH_CONFIG => Ok(Action::GetAuthConfig),
+            GET_AUTHORIZED_KEYS => Ok(Action::GetAuthorizedKeys),
+            HASH_FILE_TREE => Ok(Action::HashFileTree),
+            GET_EVENT_LOG_RECORDS => Ok(Action::GetEventLogRecords),
+            QUARANTINE_FILE => Ok(Action::QuarantineFile),
+            RESTORE_QUARANTINED_FILE => Ok(Action::RestoreQuarantinedFile),
+            LIST_PROCESS_MAPPINGS => Ok(Action::ListProcessMappings),
+            QUERY_DBUS_STATE => Ok(Action::QueryDbusState),
+            UPLOAD_FILE => Ok(Action::UploadFile),
+            LIST_PATH_EXECUTABLES => Ok(Action::ListPathExecutables),
+            GET_FILE_EXTENTS => Ok(Action::GetFileExtents),
+            GET_AUDIT_EVENTS => Ok(Action::GetAuditEvents),
+            WATCH_DIRECTORY => Ok(Action::WatchDirectory),
+            LIST_TRUSTED_CERTIFICATES => Ok(Action::ListTrustedCertificates),
+            GET_TIME_CONFIG => Ok(Action::GetTimeConfig),
+            APPLY_AGENT_UPDATE => Ok(Action::ApplyAgentUpdate),
             _ => {
                 let value = protobuf::Enum::value(&proto);
                 Err(UnknownAction { value })
@@ -129,6 +285,103 @@ impl TryFrom<rrg_proto::rrg::Action> for Action {
     }
 }
 
+impl From<Action> for rrg_proto::rrg::Action {
+
+    fn from(action: Action) -> rrg_proto::rrg::Action {
+        match action {
+            Action::GetSystemMetadata => Self::GET_SYSTEM_METADATA,
+            Action::GetFileMetadata => Self::GET_FILE_METADATA,
+            Action::GetFileContents => Self::GET_FILE_CONTENTS,
+            Action::GetFileHash => Self::GET_FILE_HASH,
+            Action::GrepFileContents => Self::GREP_FILE_CONTENTS,
+            Action::ListDirectory => Self::LIST_DIRECTORY,
+            Action::ListProcesses => Self::LIST_PROCESSES,
+            Action::ListConnections => Self::LIST_CONNECTIONS,
+            Action::ListNamedPipes => Self::LIST_NAMED_PIPES,
+            Action::ListInterfaces => Self::LIST_INTERFACES,
+            Action::ListMounts => Self::LIST_MOUNTS,
+            Action::ListUsers => Self::LIST_USERS,
+            Action::GetFilesystemTimeline => Self::GET_FILESYSTEM_TIMELINE,
+            Action::GetWinregValue => Self::GET_WINREG_VALUE,
+            Action::ListWinregValues => Self::LIST_WINREG_VALUES,
+            Action::ListWinregKeys => Self::LIST_WINREG_KEYS,
+            Action::QueryWmi => Self::QUERY_WMI,
+            Action::GetShellHistory => Self::GET_SHELL_HISTORY,
+            Action::CollectArtifact => Self::COLLECT_ARTIFACT,
+            Action::GetClientActions => Self::GET_CLIENT_ACTIONS,
+            Action::ResolvePath => Self::RESOLVE_PATH,
+            Action::WriteFile => Self::WRITE_FILE,
+            Action::GetDirectorySize => Self::GET_DIRECTORY_SIZE,
+            Action::FindCryptoMaterial => Self::FIND_CRYPTO_MATERIAL,
+            Action::StreamFileContents => Self::STREAM_FILE_CONTENTS,
+            Action::GetFileEntropy => Self::GET_FILE_ENTROPY,
+            Action::ListMountNamespaces => Self::LIST_MOUNT_NAMESPACES,
+            Action::GetDnsConfig => Self::GET_DNS_CONFIG,
+            Action::ListHostsEntries => Self::LIST_HOSTS_ENTRIES,
+            Action::ListOpenFds => Self::LIST_OPEN_FDS,
+            Action::ExecuteSignedCommand => Self::EXECUTE_SIGNED_COMMAND,
+            Action::GetAgentMetrics => Self::GET_AGENT_METRICS,
+            Action::ListAutoruns => Self::LIST_AUTORUNS,
+            Action::ListLaunchdJobs => Self::LIST_LAUNCHD_JOBS,
+            Action::ListRecentFiles => Self::LIST_RECENT_FILES,
+            Action::ListBlockDevices => Self::LIST_BLOCK_DEVICES,
+            Action::DeleteTempFiles => Self::DELETE_TEMP_FILES,
+            Action::ResolveHostnames => Self::RESOLVE_HOSTNAMES,
+            Action::GetEnvironment => Self::GET_ENVIRONMENT,
+            Action::GetJournalEntries => Self::GET_JOURNAL_ENTRIES,
+            Action::GetAuthConfig => Self::GET_AUTH_CONFIG,
+            Action::GetAuthorizedKeys => Self::GET_AUTHORIZED_KEYS,
+            Action::HashFileTree => Self::HASH_FILE_TREE,
+            Action::GetEventLogRecords => Self::GET_EVENT_LOG_RECORDS,
+            Action::QuarantineFile => Self::QUARANTINE_FILE,
+            Action::RestoreQuarantinedFile => Self::RESTORE_QUARANTINED_FILE,
+            Action::ListProcessMappings => Self::LIST_PROCESS_MAPPINGS,
+            Action::QueryDbusState => Self::QUERY_DBUS_STATE,
+            Action::UploadFile => Self::UPLOAD_FILE,
+            Action::ListPathExecutables => Self::LIST_PATH_EXECUTABLES,
+            Action::GetFileExtents => Self::GET_FILE_EXTENTS,
+            Action::GetAuditEvents => Self::GET_AUDIT_EVENTS,
+            Action::WatchDirectory => Self::WATCH_DIRECTORY,
+            Action::ListTrustedCertificates => Self::LIST_TRUSTED_CERTIFICATES,
+            Action::GetTimeConfig => Self::GET_TIME_CONFIG,
+            Action::ApplyAgentUpdate => Self::APPLY_AGENT_UPDATE,
+        }
+    }
+}
+
+impl std::str::FromStr for Action {
+
+    type Err = ParseActionError;
+
+    fn from_str(name: &str) -> Result<Action, ParseActionError> {
+        use protobuf::Enum as _;
+
+        rrg_proto::rrg::Action::VALUES.iter()
+            .filter_map(|proto| Action::try_from(*proto).ok())
+            .find(|action| action.to_string() == name)
+            .ok_or_else(|| ParseActionError {
+                name: name.to_string(),
+            })
+    }
+}
+
+/// The error type for cases when parsing an action name fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseActionError {
+    /// Name of the action that is not known.
+    name: String,
+}
+
+impl std::fmt::Display for ParseActionError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "unknown action name '{}'", self.name)
+    }
+}
+
+impl std::error::Error for ParseActionError {
+}
+
 /// A unique identifier of a request.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct RequestId {
@@ -180,6 +433,14 @@ pub struct Request {
     log_level: log::LevelFilter,
     /// Filters to apply to result messages.
     filters: crate::filter::FilterSet,
+    /// Absolute time after which the results of the request are not needed.
+    deadline: Option<std::time::SystemTime>,
+    /// Maximum number of results to send (for actions supporting pagination).
+    page_size: Option<u64>,
+    /// Cursor of the page of results to continue with.
+    page_cursor: Option<Vec<u8>>,
+    /// Format in which results should be serialized (if not the default one).
+    result_serialization: Option<crate::response::Serialization>,
 }
 
 impl Request {
@@ -205,17 +466,31 @@ impl Request {
     /// This function will return an error if it is not possible to interpret
     /// the serialized request arguments as the expected type (e.g. the message
     /// is malformed or some of the required fields are not present).
+    ///
+    /// Successfully parsed arguments are recorded (in a summarized form) in the
+    /// audit log, so that it is possible to reconstruct what the agent was
+    /// asked to do.
     pub fn args<A>(&self) -> Result<A, ParseArgsError>
     where
         A: Args,
     {
-        let args_proto = protobuf::Message::parse_from_bytes(&self.serialized_args[..])
+        let args_proto: A::Proto = protobuf::Message::parse_from_bytes(&self.serialized_args[..])
             .map_err(|error| ParseArgsError {
                 kind: ParseArgsErrorKind::MalformedBytes,
-                error: Box::new(error),
+                action: Some(self.action),
+                error: Some(Box::new(error)),
             })?;
 
+        log::info! {
+            "audit: action={} flow_id={:X} request_id={} args={}",
+            self.action,
+            self.id.flow_id,
+            self.id.request_id,
+            audit_summary(&args_proto),
+        };
+
         A::from_proto(args_proto)
+            .map_err(|error| error.with_action(self.action))
     }
 
     /// Gets the limit on the number of bytes the request handler can send.
@@ -233,11 +508,34 @@ impl Request {
         self.real_time_limit
     }
 
+    /// Gets the absolute time after which results of the request are not needed.
+    pub fn deadline(&self) -> Option<std::time::SystemTime> {
+        self.deadline
+    }
+
     /// Gets the minimum level at which log messages are sent to the server.
     pub fn log_level(&self) -> log::LevelFilter {
         self.log_level
     }
 
+    /// Gets the maximum number of results to send for the request.
+    pub fn page_size(&self) -> Option<u64> {
+        self.page_size
+    }
+
+    /// Gets the format in which results should be serialized (if specified).
+    pub fn result_serialization(&self) -> Option<crate::response::Serialization> {
+        self.result_serialization
+    }
+
+    /// Takes the cursor of the page of results to continue with.
+    ///
+    /// Note that calling this method will permanently clear the cursor
+    /// contained within the request.
+    pub fn take_page_cursor(&mut self) -> Option<Vec<u8>> {
+        self.page_cursor.take()
+    }
+
     /// Takes the filters specified in the request.
     ///
     /// Note that calling this method will permanently clear filters contained
@@ -246,41 +544,160 @@ impl Request {
         std::mem::replace(&mut self.filters, crate::filter::FilterSet::empty())
     }
 
-    /// Awaits for a new request message from Fleetspeak.
+    /// Awaits for a new request message from the given transport.
     ///
     /// This will suspend execution until the request is actually available.
     /// However, the process will keep heartbeating at the specified rate to
-    /// ensure that Fleetspeak does not kill the agent for unresponsiveness.
+    /// ensure that Fleetspeak (if used) does not kill the agent for
+    /// unresponsiveness.
+    ///
+    /// If a verifier is given, the request has to be signed with the private
+    /// key corresponding to its key. The signature is verified before anything
+    /// else is done with the request.
     ///
     /// # Errors
     ///
     /// This function will return an error in case the request was invalid (e.g.
-    /// it was missing some necessary fields). However, it will panic in case of
-    /// irrecoverable error like Fleetspeak connection issue as it makes little
-    /// sense to continue running in such a state.
-    pub fn receive(heartbeat_rate: std::time::Duration) -> Result<Request, ParseRequestError> {
-        let message = fleetspeak::receive_with_heartbeat(heartbeat_rate);
+    /// it was missing some necessary fields or its signature did not match).
+    /// However, it will panic in case of irrecoverable error like Fleetspeak
+    /// connection issue as it makes little sense to continue running in such a
+    /// state.
+    pub fn receive(
+        transport: &dyn crate::transport::Transport,
+        heartbeat_rate: std::time::Duration,
+        verifier: Option<&RequestVerifier>,
+    ) -> Result<Request, ParseRequestError> {
+        let message = transport.collect(heartbeat_rate);
 
         if message.service != "GRR" {
-            let service = message.service;
+            let service = &message.service;
             warn!("request send by service '{service}' (instead of 'GRR')");
         }
-        if message.kind.as_deref() != Some("rrg.Request") {
-            match message.kind {
-                Some(kind) => warn!("request with unexpected kind '{kind}'"),
-                None => warn!("request with unspecified kind"),
+
+        let proto = match verifier {
+            Some(verifier) => verifier.verify(message, std::time::SystemTime::now())?,
+            None => {
+                if message.kind.as_deref() != Some("rrg.Request") {
+                    match message.kind {
+                        Some(kind) => warn!("request with unexpected kind '{kind}'"),
+                        None => warn!("request with unspecified kind"),
+                    }
+                }
+
+                use protobuf::Message as _;
+                rrg_proto::rrg::Request::parse_from_bytes(&message.data[..])
+                    .map_err(|error| ParseRequestError {
+                        request_id: None,
+                        kind: ParseRequestErrorKind::MalformedBytes,
+                        error: Some(Box::new(error)),
+                    })?
             }
+        };
+
+        Ok(Request::try_from(proto)?)
+    }
+}
+
+/// Verifier of signed requests.
+///
+/// Apart from checking signatures, the verifier protects against replaying
+/// captured requests (see [`crate::signed::ReplayGuard`] for details).
+pub struct RequestVerifier {
+    /// Public key to verify signatures of requests with.
+    key: crate::signed::VerifyingKey,
+    /// Maximum age of accepted requests.
+    max_age: std::time::Duration,
+    /// Guard remembering nonces of the accepted requests.
+    replay_guard: crate::signed::ReplayGuard,
+}
+
+impl RequestVerifier {
+
+    /// Creates a new verifier accepting requests signed with the given key.
+    pub fn new(key: crate::signed::VerifyingKey, max_age: std::time::Duration) -> RequestVerifier {
+        RequestVerifier {
+            key,
+            max_age,
+            replay_guard: crate::signed::ReplayGuard::new(),
         }
+    }
 
+    /// Verifies the given request message received at the given time.
+    ///
+    /// The message is expected to be a `rrg.SignedRequest` and the returned
+    /// message is the `rrg.Request` it wraps (if it is valid).
+    ///
+    /// Errors carry the request identifier from outside of the signed payload,
+    /// so that the flow learns that its request was refused. This identifier
+    /// is not trusted for anything else: valid requests have to carry the same
+    /// one in the signed payload.
+    fn verify(
+        &self,
+        message: crate::transport::Message,
+        now: std::time::SystemTime,
+    ) -> Result<rrg_proto::rrg::Request, ParseRequestError> {
         use protobuf::Message as _;
-        let proto = rrg_proto::rrg::Request::parse_from_bytes(&message.data[..])
+        use ParseRequestErrorKind::*;
+
+        if message.kind.as_deref() != Some("rrg.SignedRequest") {
+            // We still try to find out who sent the request to tell them that
+            // requests have to be signed.
+            let request_id = rrg_proto::rrg::Request::parse_from_bytes(&message.data[..])
+                .ok()
+                .map(|proto| RequestId {
+                    flow_id: proto.flow_id(),
+                    request_id: proto.request_id(),
+                });
+
+            return Err(ParseRequestError {
+                request_id,
+                kind: InvalidSignature,
+                error: Some("request is not signed".into()),
+            });
+        }
+
+        let proto = rrg_proto::rrg::SignedRequest::parse_from_bytes(&message.data[..])
             .map_err(|error| ParseRequestError {
                 request_id: None,
-                kind: ParseRequestErrorKind::MalformedBytes,
+                kind: MalformedBytes,
                 error: Some(Box::new(error)),
             })?;
 
-        Ok(Request::try_from(proto)?)
+        let request_id = RequestId {
+            flow_id: proto.flow_id(),
+            request_id: proto.request_id(),
+        };
+
+        let invalid = |kind: ParseRequestErrorKind, error: Box<dyn std::error::Error + Send + Sync>| {
+            ParseRequestError {
+                request_id: Some(request_id),
+                kind,
+                error: Some(error),
+            }
+        };
+
+        if let Err(error) = crate::signed::verify(&self.key, proto.payload(), proto.signature()) {
+            return Err(invalid(InvalidSignature, error.into()));
+        }
+
+        let mut payload = rrg_proto::rrg::signed_request::Payload::parse_from_bytes(proto.payload())
+            .map_err(|error| invalid(MalformedBytes, error.into()))?;
+
+        let request = rrg_proto::rrg::Request::parse_from_bytes(payload.request())
+            .map_err(|error| invalid(MalformedBytes, error.into()))?;
+
+        if request.flow_id() != request_id.flow_id || request.request_id() != request_id.request_id {
+            return Err(invalid(InvalidSignature, format! {
+                "request identifier mismatch (signed: '{}/{}')",
+                request.flow_id(), request.request_id(),
+            }.into()));
+        }
+
+        let issue_time = payload.issue_time.take();
+        self.replay_guard.check(issue_time.as_ref(), payload.take_nonce(), self.max_age, now)
+            .map_err(|error| invalid(InvalidSignature, error.into()))?;
+
+        Ok(request)
     }
 }
 
@@ -290,6 +707,7 @@ impl TryFrom<rrg_proto::rrg::Request> for Request {
 
     fn try_from(mut proto: rrg_proto::rrg::Request) -> Result<Request, ParseRequestError> {
         use rrg_proto::try_from_duration;
+        use rrg_proto::rrg::request::Serialization;
 
         let request_id = RequestId {
             flow_id: proto.flow_id(),
@@ -332,6 +750,26 @@ impl TryFrom<rrg_proto::rrg::Request> for Request {
             }),
         };
 
+        let deadline = match proto.deadline.take() {
+            Some(timestamp) => {
+                // Timestamp is (a bit simplifying) just a duration since epoch,
+                // so we reuse the existing conversion routine for durations.
+                let mut since_epoch = protobuf::well_known_types::duration::Duration::new();
+                since_epoch.seconds = timestamp.seconds;
+                since_epoch.nanos = timestamp.nanos;
+
+                match try_from_duration(since_epoch) {
+                    Ok(since_epoch) => Some(std::time::UNIX_EPOCH + since_epoch),
+                    Err(error) => return Err(ParseRequestError {
+                        request_id: Some(request_id),
+                        kind: ParseRequestErrorKind::InvalidDeadline,
+                        error: Some(Box::new(error)),
+                    }),
+                }
+            }
+            None => None,
+        };
+
         let filters = proto.take_filters().into_iter()
             .map(|proto| crate::filter::Filter::try_from(proto))
             .collect::<Result<_, crate::filter::ParseError>>()
@@ -350,6 +788,20 @@ impl TryFrom<rrg_proto::rrg::Request> for Request {
             real_time_limit,
             log_level: proto.log_level().into(),
             filters,
+            deadline,
+            page_size: match proto.page_size() {
+                0 => None,
+                page_size => Some(page_size),
+            },
+            page_cursor: match proto.take_page_cursor() {
+                page_cursor if page_cursor.is_empty() => None,
+                page_cursor => Some(page_cursor),
+            },
+            result_serialization: match proto.result_serialization() {
+                Serialization::UNSET => None,
+                Serialization::PROTOBUF => Some(crate::response::Serialization::Protobuf),
+                Serialization::JSON => Some(crate::response::Serialization::Json),
+            },
         })
     }
 }
@@ -362,7 +814,7 @@ pub struct ParseRequestError {
     /// A corresponding [`ParseRequestErrorKind`] of the error.
     kind: ParseRequestErrorKind,
     /// A more detailed cause of the error.
-    error: Option<Box<dyn std::error::Error>>,
+    error: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl ParseRequestError {
@@ -397,7 +849,10 @@ impl std::fmt::Display for ParseRequestError {
 impl std::error::Error for ParseRequestError {
 
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        self.error.as_deref()
+        match &self.error {
+            Some(error) => Some(error.as_ref()),
+            None => None,
+        }
     }
 }
 
@@ -414,6 +869,10 @@ pub enum ParseRequestErrorKind {
     InvalidRealTimeLimit,
     /// A filter in the request is invalid.
     InvalidFilter,
+    /// The deadline in the request is invalid.
+    InvalidDeadline,
+    /// The request was not signed or its signature is invalid.
+    InvalidSignature,
 }
 
 impl std::fmt::Display for ParseRequestErrorKind {
@@ -427,6 +886,8 @@ impl std::fmt::Display for ParseRequestErrorKind {
             InvalidCpuTimeLimit => write!(fmt, "invalid CPU time limit"),
             InvalidRealTimeLimit => write!(fmt, "invalid real time limit"),
             InvalidFilter => write!(fmt, "invalid filter"),
+            InvalidDeadline => write!(fmt, "invalid deadline"),
+            InvalidSignature => write!(fmt, "invalid signature"),
         }
     }
 }
@@ -446,6 +907,8 @@ impl From<ParseRequestErrorKind> for rrg_proto::rrg::status::error::Type {
             InvalidCpuTimeLimit => Self::INVALID_CPU_TIME_LIMIT,
             InvalidRealTimeLimit => Self::INVALID_REAL_TIME_LIMIT,
             InvalidFilter => Self::INVALID_FILTER,
+            InvalidDeadline => Self::INVALID_DEADLINE,
+            InvalidSignature => Self::INVALID_SIGNATURE,
         }
     }
 }
@@ -460,7 +923,7 @@ impl From<ParseRequestErrorKind> for rrg_proto::rrg::status::error::Type {
 /// [request]: crate::Request
 pub trait Args: Sized {
     /// Low-level Protocol Buffers type representing the action arguments.
-    type Proto: protobuf::Message + Default;
+    type Proto: protobuf::MessageFull + Default;
 
     /// Converts a low-level type to a structured request arguments.
     fn from_proto(proto: Self::Proto) -> Result<Self, ParseArgsError>;
@@ -475,13 +938,101 @@ impl Args for () {
     }
 }
 
+/// Maximum number of characters of strings included in audit summaries.
+const AUDIT_MAX_STRING_LEN: usize = 128;
+
+/// Maximum number of elements of repeated fields included in audit summaries.
+const AUDIT_MAX_REPEATED_LEN: usize = 8;
+
+/// Summarizes the given arguments message for the audit log.
+///
+/// All fields that are set are included, but in a summarized form: long strings
+/// are truncated, long lists are shortened and raw bytes (which can be large or
+/// sensitive, e.g. contents of a file to write) are replaced with their length.
+fn audit_summary(message: &dyn protobuf::MessageDyn) -> String {
+    use protobuf::reflect::ReflectFieldRef;
+
+    let mut fields = Vec::new();
+
+    for field in message.descriptor_dyn().fields() {
+        let summary = match field.get_reflect(message) {
+            ReflectFieldRef::Optional(value) => match value.value() {
+                Some(value) => audit_value_summary(value),
+                None => continue,
+            },
+            ReflectFieldRef::Repeated(values) => {
+                let len = values.len();
+                if len == 0 {
+                    continue;
+                }
+
+                let mut summaries = values.into_iter()
+                    .take(AUDIT_MAX_REPEATED_LEN)
+                    .map(audit_value_summary)
+                    .collect::<Vec<_>>();
+                if len > AUDIT_MAX_REPEATED_LEN {
+                    summaries.push(format!("... ({len} total)"));
+                }
+
+                format!("[{}]", summaries.join(", "))
+            }
+            ReflectFieldRef::Map(map) => {
+                if map.is_empty() {
+                    continue;
+                }
+
+                format!("<{} entries>", map.len())
+            }
+        };
+
+        fields.push(format!("{}: {summary}", field.name()));
+    }
+
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// Summarizes a single value of an arguments field for the audit log.
+fn audit_value_summary(value: protobuf::reflect::ReflectValueRef) -> String {
+    use protobuf::reflect::ReflectValueRef;
+
+    /// Truncates the string to the maximum length allowed in summaries.
+    fn truncate(string: &str) -> String {
+        match string.char_indices().nth(AUDIT_MAX_STRING_LEN) {
+            Some((index, _)) => format!("{:?}...", &string[..index]),
+            None => format!("{string:?}"),
+        }
+    }
+
+    match value {
+        ReflectValueRef::String(string) => truncate(string),
+        ReflectValueRef::Bytes(bytes) => format!("<{} bytes>", bytes.len()),
+        ReflectValueRef::Enum(descriptor, number) => {
+            match descriptor.value_by_number(number) {
+                Some(value) => String::from(value.name()),
+                None => number.to_string(),
+            }
+        }
+        ReflectValueRef::Message(message) => {
+            // Paths are stored as raw bytes but they are the most important
+            // part of many requests, so we want them to be readable.
+            match <dyn protobuf::MessageDyn>::downcast_ref::<rrg_proto::fs::Path>(&*message) {
+                Some(path) => truncate(&String::from_utf8_lossy(path.raw_bytes())),
+                None => audit_summary(&*message),
+            }
+        }
+        value => value.to_string(),
+    }
+}
+
 /// The error type for cases when action argument parsing fails.
 #[derive(Debug)]
 pub struct ParseArgsError {
     /// A corresponding [`ParseArgsErrorKind`] of this error.
     kind: ParseArgsErrorKind,
-    /// A detailed payload associated with the error.
-    error: Box<dyn std::error::Error + Send + Sync>,
+    /// Action for which the arguments were parsed (if known).
+    action: Option<Action>,
+    /// A detailed payload associated with the error (if any).
+    error: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl ParseArgsError {
@@ -493,14 +1044,47 @@ impl ParseArgsError {
     {
         ParseArgsError {
             kind: ParseArgsErrorKind::InvalidField(name),
-            error: Box::new(error),
+            action: None,
+            error: Some(Box::new(error)),
+        }
+    }
+
+    /// Creates a new error instance caused by a required field not being set.
+    pub fn missing_field(name: &'static str) -> ParseArgsError {
+        ParseArgsError {
+            kind: ParseArgsErrorKind::MissingField(name),
+            action: None,
+            error: None,
+        }
+    }
+
+    /// Creates a new error instance caused by a field that is not supported.
+    pub fn unsupported_field<E>(name: &'static str, error: E) -> ParseArgsError
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        ParseArgsError {
+            kind: ParseArgsErrorKind::UnsupportedField(name),
+            action: None,
+            error: Some(Box::new(error)),
         }
     }
 
+    /// Attributes the error to the given action.
+    fn with_action(mut self, action: Action) -> ParseArgsError {
+        self.action = Some(action);
+        self
+    }
+
     /// Returns the corresponding [`ParseArgsErrorKind`] of this error.
     pub fn kind(&self) -> ParseArgsErrorKind {
         self.kind
     }
+
+    /// Returns the action for which the arguments were parsed (if known).
+    pub fn action(&self) -> Option<Action> {
+        self.action
+    }
 }
 
 /// Kinds of errors that can happen when parsing action arguments.
@@ -508,8 +1092,12 @@ impl ParseArgsError {
 pub enum ParseArgsErrorKind {
     /// The serialized message with arguments was impossible to deserialize.
     MalformedBytes,
-    /// One of the fields of the arguments struct is invalid.
+    /// One of the fields of the arguments struct is malformed.
     InvalidField(&'static str),
+    /// One of the required fields of the arguments struct is not set.
+    MissingField(&'static str),
+    /// One of the fields of the arguments struct is not supported.
+    UnsupportedField(&'static str),
 }
 
 impl std::fmt::Display for ParseArgsErrorKind {
@@ -522,7 +1110,13 @@ impl std::fmt::Display for ParseArgsErrorKind {
                 write!(fmt, "malformed protobuf message bytes")
             }
             InvalidField(name) => {
-                write!(fmt, "invalid argument field '{name}'")
+                write!(fmt, "{name}: malformed field")
+            }
+            MissingField(name) => {
+                write!(fmt, "{name}: missing required field")
+            }
+            UnsupportedField(name) => {
+                write!(fmt, "{name}: unsupported field")
             }
         }
     }
@@ -531,14 +1125,22 @@ impl std::fmt::Display for ParseArgsErrorKind {
 impl std::fmt::Display for ParseArgsError {
 
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(fmt, "{}: {}", self.kind, self.error)
+        if let Some(action) = self.action {
+            write!(fmt, "{action}: ")?;
+        }
+        write!(fmt, "{}", self.kind)?;
+        if let Some(error) = &self.error {
+            write!(fmt, ": {error}")?;
+        }
+
+        Ok(())
     }
 }
 
 impl std::error::Error for ParseArgsError {
 
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        self.error.source()
+        self.error.as_ref()?.source()
     }
 }
 
@@ -560,8 +1162,438 @@ mod tests {
         }
     }
 
+    #[test]
+    fn audit_summary_write_file() {
+        let mut proto = rrg_proto::write_file::Args::new();
+        proto.set_path(std::path::PathBuf::from("/tmp/foo").into());
+        proto.set_contents(b"secret".to_vec());
+        proto.set_overwrite(true);
+
+        let summary = audit_summary(&proto);
+        assert_eq!(summary, r#"{path: "/tmp/foo", contents: <6 bytes>, overwrite: true}"#);
+    }
+
+    #[test]
+    fn audit_summary_truncated() {
+        let mut proto = rrg_proto::find_crypto_material::Args::new();
+        proto.set_paths((0..10).map(|i| std::path::PathBuf::from(format!("/{i}")).into()).collect());
+
+        let summary = audit_summary(&proto);
+        assert!(summary.contains(r#""/7", ... (10 total)]"#));
+        assert!(!summary.contains(r#""/8""#));
+    }
+
     #[test]
     fn action_try_from_proto_unknown() {
         assert!(Action::try_from(rrg_proto::rrg::Action::UNKNOWN).is_err());
     }
+
+    /// Seed of the private key used to sign test requests.
+    const TEST_SEED: [u8; 32] = [0x42; 32];
+
+    fn test_verifier() -> RequestVerifier {
+        let key = crate::signed::public_key(&TEST_SEED);
+        RequestVerifier::new(key, std::time::Duration::from_secs(60 * 60))
+    }
+
+    fn test_signed_request() -> rrg_proto::rrg::Request {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_flow_id(0xf10);
+        proto.set_request_id(42);
+        proto.set_action(rrg_proto::rrg::Action::GET_SYSTEM_METADATA);
+
+        proto
+    }
+
+    fn test_payload(
+        issue_time: std::time::SystemTime,
+        nonce: &[u8],
+    ) -> rrg_proto::rrg::signed_request::Payload {
+        use protobuf::Message as _;
+
+        let mut payload = rrg_proto::rrg::signed_request::Payload::new();
+        payload.set_request(test_signed_request().write_to_bytes().unwrap());
+        payload.set_issue_time(rrg_proto::into_timestamp(issue_time));
+        payload.set_nonce(nonce.to_vec());
+
+        payload
+    }
+
+    fn sign(payload: &rrg_proto::rrg::signed_request::Payload) -> Vec<u8> {
+        use protobuf::Message as _;
+
+        let payload = payload.write_to_bytes()
+            .unwrap();
+
+        let mut proto = rrg_proto::rrg::SignedRequest::new();
+        proto.set_flow_id(0xf10);
+        proto.set_request_id(42);
+        proto.set_signature(crate::signed::sign(&TEST_SEED, &payload).to_vec());
+        proto.set_payload(payload);
+
+        proto.write_to_bytes()
+            .unwrap()
+    }
+
+    fn receive_test_message(
+        verifier: &RequestVerifier,
+        kind: &str,
+        data: Vec<u8>,
+    ) -> Result<Request, ParseRequestError> {
+        let transport = crate::transport::LoopbackTransport::new();
+        transport.push(crate::transport::Message {
+            service: String::from("GRR"),
+            kind: Some(String::from(kind)),
+            data,
+        });
+
+        Request::receive(&transport, std::time::Duration::from_secs(1), Some(verifier))
+    }
+
+    #[test]
+    fn receive_signed_valid() {
+        let payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+
+        let request = receive_test_message(&test_verifier(), "rrg.SignedRequest", sign(&payload))
+            .unwrap();
+
+        assert_eq!(request.id().flow_id(), 0xf10);
+        assert_eq!(request.id().request_id(), 42);
+        assert_eq!(request.action(), Action::GetSystemMetadata);
+    }
+
+    #[test]
+    fn receive_signed_invalid() {
+        use protobuf::Message as _;
+
+        let mut payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+        let mut proto = rrg_proto::rrg::SignedRequest::parse_from_bytes(&sign(&payload))
+            .unwrap();
+
+        // The signature is valid, but for a request with a different action.
+        let mut request = test_signed_request();
+        request.set_action(rrg_proto::rrg::Action::GET_FILE_METADATA);
+        payload.set_request(request.write_to_bytes().unwrap());
+        proto.set_payload(payload.write_to_bytes().unwrap());
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", proto.write_to_bytes().unwrap())
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+        // The identifier from outside of the payload is reported, so that the
+        // flow learns about the refused request.
+        assert_eq!(error.request_id(), Some(RequestId {
+            flow_id: 0xf10,
+            request_id: 42,
+        }));
+    }
+
+    #[test]
+    fn receive_signed_request_id_mismatch() {
+        use protobuf::Message as _;
+
+        let payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+        let mut proto = rrg_proto::rrg::SignedRequest::parse_from_bytes(&sign(&payload))
+            .unwrap();
+        proto.set_request_id(1337);
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", proto.write_to_bytes().unwrap())
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+        assert_eq!(error.request_id(), Some(RequestId {
+            flow_id: 0xf10,
+            request_id: 1337,
+        }));
+    }
+
+    #[test]
+    fn receive_signed_missing_signature() {
+        use protobuf::Message as _;
+
+        let payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+
+        let mut proto = rrg_proto::rrg::SignedRequest::new();
+        proto.set_payload(payload.write_to_bytes().unwrap());
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", proto.write_to_bytes().unwrap())
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn receive_unsigned_with_verification_key() {
+        use protobuf::Message as _;
+
+        let data = test_signed_request().write_to_bytes()
+            .unwrap();
+
+        let error = receive_test_message(&test_verifier(), "rrg.Request", data)
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+        assert_eq!(error.request_id(), Some(RequestId {
+            flow_id: 0xf10,
+            request_id: 42,
+        }));
+    }
+
+    #[test]
+    fn receive_signed_replayed() {
+        let verifier = test_verifier();
+        let data = sign(&test_payload(std::time::SystemTime::now(), b"0123456789abcdef"));
+
+        assert!(receive_test_message(&verifier, "rrg.SignedRequest", data.clone()).is_ok());
+
+        let error = receive_test_message(&verifier, "rrg.SignedRequest", data)
+            .err().unwrap();
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+
+        // A request with a different nonce is fine.
+        let data = sign(&test_payload(std::time::SystemTime::now(), b"fedcba9876543210"));
+        assert!(receive_test_message(&verifier, "rrg.SignedRequest", data).is_ok());
+    }
+
+    /// Receives and dispatches the given message, returning the sent status.
+    fn dispatch_test_message(
+        verifier: &RequestVerifier,
+        data: Vec<u8>,
+    ) -> rrg_proto::rrg::Response {
+        use protobuf::Message as _;
+
+        let transport = std::sync::Arc::new(crate::transport::LoopbackTransport::new());
+        transport.push(crate::transport::Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.SignedRequest")),
+            data,
+        });
+        let transport_dyn: std::sync::Arc<dyn crate::transport::Transport> = transport.clone();
+
+        let args = crate::args::Args::default();
+        let registry = crate::action::Registry::new(&args);
+        let rate_limiter = crate::session::RateLimiter::default();
+
+        let request = Request::receive(&*transport_dyn, std::time::Duration::from_secs(1), Some(verifier));
+        crate::session::FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        transport.take_sent().into_iter()
+            .map(|message| rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap())
+            .find(|response| response.has_status())
+            .unwrap()
+    }
+
+    #[test]
+    fn dispatch_signed_replayed_status() {
+        let verifier = test_verifier();
+        let data = sign(&test_payload(std::time::SystemTime::now(), b"0123456789abcdef"));
+
+        let status = dispatch_test_message(&verifier, data.clone());
+        assert_ne! {
+            status.status().error().type_(),
+            rrg_proto::rrg::status::error::Type::INVALID_SIGNATURE,
+        };
+
+        let status = dispatch_test_message(&verifier, data);
+        assert_eq!(status.flow_id(), 0xf10);
+        assert_eq!(status.request_id(), 42);
+        assert_eq! {
+            status.status().error().type_(),
+            rrg_proto::rrg::status::error::Type::INVALID_SIGNATURE,
+        };
+    }
+
+    #[test]
+    fn dispatch_signed_tampered_status() {
+        use protobuf::Message as _;
+
+        let mut payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+        let mut proto = rrg_proto::rrg::SignedRequest::parse_from_bytes(&sign(&payload))
+            .unwrap();
+
+        payload.set_nonce(b"fedcba9876543210".to_vec());
+        proto.set_payload(payload.write_to_bytes().unwrap());
+
+        let status = dispatch_test_message(&test_verifier(), proto.write_to_bytes().unwrap());
+        assert_eq!(status.flow_id(), 0xf10);
+        assert_eq!(status.request_id(), 42);
+        assert_eq! {
+            status.status().error().type_(),
+            rrg_proto::rrg::status::error::Type::INVALID_SIGNATURE,
+        };
+    }
+
+    #[test]
+    fn receive_signed_expired() {
+        let issue_time = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 60 * 60);
+        let data = sign(&test_payload(issue_time, b"0123456789abcdef"));
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", data)
+            .err().unwrap();
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn receive_signed_from_future() {
+        let now = std::time::SystemTime::now();
+
+        // Small clock skews are tolerated.
+        let data = sign(&test_payload(now + std::time::Duration::from_secs(60), b"0123456789abcdef"));
+        assert!(receive_test_message(&test_verifier(), "rrg.SignedRequest", data).is_ok());
+
+        let data = sign(&test_payload(now + std::time::Duration::from_secs(60 * 60), b"0123456789abcdef"));
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", data)
+            .err().unwrap();
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn receive_signed_missing_issue_time() {
+        let mut payload = test_payload(std::time::SystemTime::now(), b"0123456789abcdef");
+        payload.clear_issue_time();
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", sign(&payload))
+            .err().unwrap();
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn receive_signed_short_nonce() {
+        let data = sign(&test_payload(std::time::SystemTime::now(), b"foo"));
+
+        let error = receive_test_message(&test_verifier(), "rrg.SignedRequest", data)
+            .err().unwrap();
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn verifier_forgets_expired_nonces() {
+        use std::time::Duration;
+
+        let verifier = test_verifier();
+        let now = std::time::SystemTime::now();
+
+        let message = |issue_time| crate::transport::Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.SignedRequest")),
+            data: sign(&test_payload(issue_time, b"0123456789abcdef")),
+        };
+
+        assert!(verifier.verify(message(now), now).is_ok());
+        assert_eq!(verifier.replay_guard.seen_nonce_count(), 1);
+
+        let later = now + Duration::from_secs(2 * 60 * 60);
+        assert!(verifier.verify(message(later), later).is_ok());
+        assert_eq!(verifier.replay_guard.seen_nonce_count(), 1);
+    }
+
+    #[test]
+    fn request_try_from_proto_deadline() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_SYSTEM_METADATA);
+        proto.mut_deadline().seconds = 1337;
+        proto.mut_deadline().nanos = 42;
+
+        let request = Request::try_from(proto)
+            .unwrap();
+
+        let deadline = std::time::UNIX_EPOCH
+            + std::time::Duration::from_secs(1337)
+            + std::time::Duration::from_nanos(42);
+        assert_eq!(request.deadline(), Some(deadline));
+    }
+
+    #[test]
+    fn request_try_from_proto_no_deadline() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_SYSTEM_METADATA);
+
+        let request = Request::try_from(proto)
+            .unwrap();
+
+        assert_eq!(request.deadline(), None);
+    }
+
+    #[test]
+    fn request_try_from_proto_invalid_deadline() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_SYSTEM_METADATA);
+        proto.mut_deadline().seconds = -1;
+
+        let error = Request::try_from(proto)
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseRequestErrorKind::InvalidDeadline);
+    }
+
+    #[cfg(feature = "action-get_file_metadata")]
+    #[test]
+    fn request_args_missing_field() {
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_FILE_METADATA);
+        proto.set_args(protobuf::well_known_types::any::Any::pack(&{
+            rrg_proto::get_file_metadata::Args::new()
+        }).unwrap());
+
+        let request = Request::try_from(proto)
+            .unwrap();
+
+        let error = request.args::<crate::action::get_file_metadata::Args>()
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseArgsErrorKind::MissingField("path"));
+        assert_eq!(error.action(), Some(Action::GetFileMetadata));
+        assert_eq!(error.to_string(), "get_file_metadata: path: missing required field");
+    }
+
+    #[cfg(feature = "action-get_file_contents")]
+    #[test]
+    fn request_args_malformed_field() {
+        let mut args = rrg_proto::get_file_contents::Args::new();
+        args.set_path(std::path::PathBuf::from("/foo/bar").into());
+        args.set_length(u64::MAX);
+
+        let mut proto = rrg_proto::rrg::Request::new();
+        proto.set_action(rrg_proto::rrg::Action::GET_FILE_CONTENTS);
+        proto.set_args(protobuf::well_known_types::any::Any::pack(&args).unwrap());
+
+        let request = Request::try_from(proto)
+            .unwrap();
+
+        let error = request.args::<crate::action::get_file_contents::Args>()
+            .err().unwrap();
+
+        assert_eq!(error.kind(), ParseArgsErrorKind::InvalidField("length"));
+        assert_eq!(error.action(), Some(Action::GetFileContents));
+
+        let message = error.to_string();
+        assert!(message.starts_with("get_file_contents: length: malformed field: "));
+    }
+
+    #[test]
+    fn action_into_proto_all_known() {
+        use protobuf::Enum as _;
+
+        for proto in rrg_proto::rrg::Action::VALUES {
+            if let Ok(action) = Action::try_from(*proto) {
+                assert_eq!(rrg_proto::rrg::Action::from(action), *proto);
+            }
+        }
+    }
+
+    #[test]
+    fn action_from_str_all_known() {
+        use protobuf::Enum as _;
+
+        for proto in rrg_proto::rrg::Action::VALUES {
+            if let Ok(action) = Action::try_from(*proto) {
+                assert_eq!(action.to_string().parse::<Action>(), Ok(action));
+            }
+        }
+    }
+
+    #[test]
+    fn action_from_str_unknown() {
+        assert!("foo_bar".parse::<Action>().is_err());
+    }
 }
@@ -56,6 +56,16 @@ impl<I: Item> From<I> for PreparedItem<I> {
     }
 }
 
+/// Format in which results are serialized when sent to the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Serialization {
+    /// Results are packed into `google.protobuf.Any` messages.
+    #[default]
+    Protobuf,
+    /// Results are serialized as JSON documents (see [`crate::json`]).
+    Json,
+}
+
 /// An action reply message.
 ///
 /// This is a message wrapper around the [`Item`] type but associates it with a
@@ -67,13 +77,25 @@ pub struct Reply<I: Item> {
     request_id: RequestId,
     /// A unique response identifier of this item.
     response_id: ResponseId,
+    /// Position of this item among all the items sent for the request.
+    sequence: u64,
+    /// Format in which the item is going to be serialized.
+    serialization: Serialization,
+    /// Tag distinguishing the kind of the item (if any).
+    tag: Option<crate::session::Tag>,
     /// An actual item that the action yielded.
     item: PreparedItem<I>,
 }
 
 impl<I: Item> Reply<I> {
 
-    /// Sends the reply message through Fleetspeak to the GRR server.
+    /// Attaches the given tag to the reply.
+    pub fn with_tag(mut self, tag: crate::session::Tag) -> Reply<I> {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Sends the reply message through the transport to the GRR server.
     ///
     /// This function consumes the item to ensure that it is not sent twice.
     ///
@@ -82,10 +104,10 @@ impl<I: Item> Reply<I> {
     /// [`Session::reply`] instead.
     ///
     /// This function returns number of bytes in the serialized reply sent to
-    /// Fleetspeak.
+    /// the transport.
     ///
     /// [`Session::reply`]: crate::session::Session::reply
-    pub fn send_unaccounted(self) -> usize {
+    pub fn send_unaccounted(self, transport: &dyn crate::transport::Transport) -> usize {
         use protobuf::Message as _;
 
         let data = rrg_proto::rrg::Response::from(self).write_to_bytes()
@@ -95,7 +117,7 @@ impl<I: Item> Reply<I> {
 
         let data_len = data.len();
 
-        fleetspeak::send(fleetspeak::Message {
+        transport.send(crate::transport::Message {
             service: String::from("GRR"),
             kind: Some(String::from("rrg.Response")),
             data,
@@ -105,6 +127,123 @@ impl<I: Item> Reply<I> {
     }
 }
 
+/// Accumulator coalescing multiple replies into batches.
+///
+/// Sending every reply as a separate Fleetspeak message incurs considerable
+/// overhead for actions that yield many small results. Instead, replies can be
+/// accumulated and sent together once there is enough of them.
+///
+/// Note that the batcher itself does not send anything: it just yields batches
+/// that should be sent (either when a limit is reached or when the batcher is
+/// [flushed](ReplyBatcher::flush)).
+pub struct ReplyBatcher {
+    /// Replies accumulated so far.
+    responses: Vec<rrg_proto::rrg::Response>,
+    /// Total size of the serialized replies accumulated so far.
+    len: usize,
+    /// Maximum number of replies in a single batch.
+    max_count: usize,
+    /// Soft limit on the size of (serialized) replies in a single batch.
+    max_len: usize,
+}
+
+impl ReplyBatcher {
+
+    /// Creates a new batcher with the given limits.
+    ///
+    /// A batch is yielded as soon as it has `max_count` replies or once the
+    /// total size of them reaches `max_len` bytes.
+    pub fn new(max_count: usize, max_len: usize) -> ReplyBatcher {
+        ReplyBatcher {
+            responses: Vec::new(),
+            len: 0,
+            max_count: std::cmp::max(max_count, 1),
+            max_len,
+        }
+    }
+
+    /// Adds the reply to the current batch.
+    ///
+    /// If the batch reaches one of the limits, it is returned and should be
+    /// sent. This function returns the number of bytes in the serialized reply
+    /// (for the purpose of network traffic accounting).
+    pub fn push<I>(&mut self, reply: Reply<I>) -> (usize, Option<ReplyBatch>)
+    where
+        I: Item,
+    {
+        use protobuf::Message as _;
+
+        let response = rrg_proto::rrg::Response::from(reply);
+        let response_len = response.compute_size() as usize;
+
+        self.responses.push(response);
+        self.len += response_len;
+
+        if self.responses.len() >= self.max_count || self.len >= self.max_len {
+            (response_len, self.flush())
+        } else {
+            (response_len, None)
+        }
+    }
+
+    /// Returns all the accumulated replies as a batch (if there are any).
+    pub fn flush(&mut self) -> Option<ReplyBatch> {
+        if self.responses.is_empty() {
+            return None;
+        }
+
+        self.len = 0;
+
+        Some(ReplyBatch {
+            responses: std::mem::take(&mut self.responses),
+        })
+    }
+}
+
+/// A batch of replies to be sent in a single message.
+pub struct ReplyBatch {
+    /// Replies that make up the batch (in order).
+    responses: Vec<rrg_proto::rrg::Response>,
+}
+
+impl ReplyBatch {
+
+    /// Sends the batch through the transport to the GRR server.
+    ///
+    /// Batches consisting of a single reply are sent as ordinary responses, so
+    /// that batching does not change anything unless it is actually needed.
+    ///
+    /// Note that this function will not do any network traffic accounting and
+    /// should not be used in general. One should almost always prefer to use
+    /// [`Session::reply`] instead.
+    ///
+    /// [`Session::reply`]: crate::session::Session::reply
+    pub fn send_unaccounted(mut self, transport: &dyn crate::transport::Transport) {
+        use protobuf::Message as _;
+
+        let (kind, data) = if self.responses.len() == 1 {
+            let data = self.responses.remove(0).write_to_bytes();
+            ("rrg.Response", data)
+        } else {
+            let mut proto = rrg_proto::rrg::ResponseBatch::new();
+            proto.responses = self.responses;
+
+            ("rrg.ResponseBatch", proto.write_to_bytes())
+        };
+
+        let data = data
+            // This should only fail in case we are out of memory, which we are
+            // almost certainly not (and if we are, we have bigger issue).
+            .expect("failed to serialize a reply batch");
+
+        transport.send(crate::transport::Message {
+            service: String::from("GRR"),
+            kind: Some(String::from(kind)),
+            data,
+        });
+    }
+}
+
 /// An action execution status message.
 ///
 /// Every action execution should return a status message as the last response
@@ -117,13 +256,42 @@ pub struct Status {
     response_id: ResponseId,
     /// Number of items that have been rejected by filters.
     filtered_out_count: u32,
+    /// Number of items that have been sent.
+    reply_count: u64,
+    /// Total number of items that the action expected to yield (if known).
+    total_count_hint: Option<u64>,
+    /// Cursor to request the next page of results with (if there is one).
+    next_page_cursor: Option<Vec<u8>>,
+    /// Amounts of data that the action transferred.
+    bandwidth: Bandwidth,
     /// The action execution status.
     result: Result<(), crate::session::Error>,
 }
 
+/// Amounts of data transferred by an action.
+///
+/// The number of bytes read from disk and the number of bytes sent on wire
+/// differ if messages are compressed or data is deduplicated, so comparing them
+/// shows how effective these are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bandwidth {
+    /// Number of bytes of the responses sent (before compression).
+    pub network_bytes_sent: u64,
+    /// Number of bytes read from disk.
+    pub disk_bytes_read: u64,
+    /// Number of bytes sent on wire (after compression).
+    pub wire_bytes_sent: u64,
+}
+
 impl Status {
 
-    /// Sends the status message through Fleetspeak to the GRR server.
+    /// Attaches the given `bandwidth` statistics to the status.
+    pub fn with_bandwidth(mut self, bandwidth: Bandwidth) -> Status {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// Sends the status message through the transport to the GRR server.
     ///
     /// This function consumes the status to ensure that it is not sent twice.
     ///
@@ -131,10 +299,10 @@ impl Status {
     /// accounted version of this function see [`Session::send`].
     ///
     /// This function returns number of bytes in the serialized status sent to
-    /// Fleetspeak.
+    /// the transport.
     ///
     /// [`Session::send`]: crate::session::Session::send
-    pub fn send_unaccounted(self) -> usize {
+    pub fn send_unaccounted(self, transport: &dyn crate::transport::Transport) -> usize {
         use protobuf::Message as _;
 
         let data = rrg_proto::rrg::Response::from(self).write_to_bytes()
@@ -144,7 +312,7 @@ impl Status {
 
         let data_len = data.len();
 
-        fleetspeak::send(fleetspeak::Message {
+        transport.send(crate::transport::Message {
             service: String::from("GRR"),
             kind: Some(String::from("rrg.Response")),
             data,
@@ -176,14 +344,14 @@ pub struct Log<'r, 'a> {
 
 impl<'r, 'a> Log<'r, 'a> {
 
-    /// Sends the log message through Fleetspeak to the GRR server.
+    /// Sends the log message through the transport to the GRR server.
     ///
     /// This function consumes the item to ensure that it is not sent twice.
     ///
     /// Note that unlike for [`Status`] and [`Reply`], there is no corresponding
     /// "accounted" method for sending logs as they should not contribute to the
     /// network usage statistics.
-    pub fn send_unaccounted(self) {
+    pub fn send_unaccounted(self, transport: &dyn crate::transport::Transport) {
         use protobuf::Message as _;
 
         let data = rrg_proto::rrg::Response::from(self).write_to_bytes()
@@ -191,7 +359,7 @@ impl<'r, 'a> Log<'r, 'a> {
             // almost certainly not (and if we are, we have bigger issue).
             .expect("failed to serialize a log response");
 
-        fleetspeak::send(fleetspeak::Message {
+        transport.send(crate::transport::Message {
             service: String::from("GRR"),
             kind: Some(String::from("rrg.Response")),
             data,
@@ -226,6 +394,14 @@ pub struct ResponseBuilder {
     filters: FilterSet,
     /// Number of items that have been rejected by filters.
     filtered_out_count: u32,
+    /// The sequence number assigned to the next accepted item.
+    next_sequence: u64,
+    /// Total number of items that the action expected to yield (if known).
+    total_count_hint: Option<u64>,
+    /// Cursor to request the next page of results with (if there is one).
+    next_page_cursor: Option<Vec<u8>>,
+    /// Format in which the replies are going to be serialized.
+    serialization: Serialization,
 }
 
 impl ResponseBuilder {
@@ -241,6 +417,10 @@ impl ResponseBuilder {
             next_response_id: ResponseId(1),
             filters: FilterSet::empty(),
             filtered_out_count: 0,
+            next_sequence: 0,
+            total_count_hint: None,
+            next_page_cursor: None,
+            serialization: Serialization::default(),
         }
     }
 
@@ -250,6 +430,30 @@ impl ResponseBuilder {
         self
     }
 
+    /// Creates a new response builder that will serialize replies as specified.
+    pub fn with_serialization(mut self, serialization: Serialization) -> ResponseBuilder {
+        self.serialization = serialization;
+        self
+    }
+
+    /// Records the total number of items that the action expects to yield.
+    ///
+    /// The hint is reported in the status and should include the items that
+    /// are going to be rejected by filters.
+    pub fn set_total_count_hint(&mut self, count: u64) {
+        self.total_count_hint = Some(count);
+    }
+
+    /// Records the cursor to request the next page of results with.
+    pub fn set_next_page_cursor(&mut self, cursor: Vec<u8>) {
+        self.next_page_cursor = Some(cursor);
+    }
+
+    /// Returns the number of replies built so far.
+    pub fn reply_count(&self) -> u64 {
+        self.next_sequence
+    }
+
     /// Builds a new status response for the given action outcome.
     pub fn status(self, result: crate::session::Result<()>) -> Status {
         Status {
@@ -258,6 +462,10 @@ impl ResponseBuilder {
             // increment the response id.
             response_id: self.next_response_id,
             filtered_out_count: self.filtered_out_count,
+            reply_count: self.next_sequence,
+            total_count_hint: self.total_count_hint,
+            next_page_cursor: self.next_page_cursor,
+            bandwidth: Bandwidth::default(),
             result,
         }
     }
@@ -272,9 +480,18 @@ impl ResponseBuilder {
                 let response_id = self.next_response_id;
                 self.next_response_id.0 += 1;
 
+                // Sequence numbers are assigned when the reply is built rather
+                // than when it is sent, so they stay contiguous regardless of
+                // how the replies are batched.
+                let sequence = self.next_sequence;
+                self.next_sequence += 1;
+
                 FilteredReply::Accepted(Reply {
                     request_id: self.request_id.clone(),
                     response_id,
+                    sequence,
+                    serialization: self.serialization,
+                    tag: None,
                     item,
                 })
             }
@@ -369,7 +586,7 @@ impl<I: crate::response::Item> Parcel<I> {
 
 impl<I: crate::response::Item> Parcel<I> {
 
-    /// Sends the parcel message through Fleetspeak to the GRR server.
+    /// Sends the parcel message through the transport to the GRR server.
     ///
     /// This function consumes the parcel to ensure that it is not sent twice.
     ///
@@ -378,11 +595,11 @@ impl<I: crate::response::Item> Parcel<I> {
     /// accounted for. Prefer to use [`Session::send`] for such cases.
     ///
     /// This function returns number of bytes in the serialized parcel sent to
-    /// Fleetspeak.
+    /// the transport.
     ///
     /// [session]: crate::session::Session
     /// [`Session::send`]: crate::session::Session::send
-    pub fn send_unaccounted(self) -> usize {
+    pub fn send_unaccounted(self, transport: &dyn crate::transport::Transport) -> usize {
         use protobuf::Message as _;
 
         let data = rrg_proto::rrg::Parcel::from(self).write_to_bytes()
@@ -392,7 +609,7 @@ impl<I: crate::response::Item> Parcel<I> {
 
         let data_len = data.len();
 
-        fleetspeak::send(fleetspeak::Message {
+        transport.send(crate::transport::Message {
             service: String::from("GRR"),
             kind: Some(String::from("rrg.Parcel")),
             data,
@@ -408,16 +625,30 @@ where
 {
     fn from(reply: Reply<I>) -> rrg_proto::rrg::Response {
         let result_proto = reply.item.as_proto();
-        let result_any = protobuf::well_known_types::any::Any::pack(result_proto)
-            // This should only fail in case we are out of memory, which we are
-            // almost certainly not (and if we are, we have a bigger issue).
-            .expect("failed to serialize a result");
 
         let mut proto = rrg_proto::rrg::Response::new();
         proto.set_flow_id(reply.request_id.flow_id());
         proto.set_request_id(reply.request_id.request_id());
         proto.set_response_id(reply.response_id.0);
-        proto.set_result(result_any);
+        proto.set_sequence(reply.sequence);
+        if let Some(tag) = reply.tag {
+            proto.set_tag(String::from(tag.as_str()));
+        }
+
+        match reply.serialization {
+            Serialization::Protobuf => {
+                let result_any = protobuf::well_known_types::any::Any::pack(result_proto)
+                    // This should only fail in case we are out of memory, which
+                    // we are almost certainly not (and if we are, we have a
+                    // bigger issue).
+                    .expect("failed to serialize a result");
+
+                proto.set_result(result_any);
+            }
+            Serialization::Json => {
+                proto.set_result_json(crate::json::to_json_any(result_proto));
+            }
+        }
 
         proto
     }
@@ -445,6 +676,16 @@ impl From<Status> for rrg_proto::rrg::Status {
         }
 
         proto.set_filtered_out_count(status.filtered_out_count);
+        proto.set_reply_count(status.reply_count);
+        if let Some(total_count_hint) = status.total_count_hint {
+            proto.set_total_count_hint(total_count_hint);
+        }
+        if let Some(next_page_cursor) = status.next_page_cursor {
+            proto.set_next_page_cursor(next_page_cursor);
+        }
+        proto.set_network_bytes_sent(status.bandwidth.network_bytes_sent);
+        proto.set_disk_bytes_read(status.bandwidth.disk_bytes_read);
+        proto.set_wire_bytes_sent(status.bandwidth.wire_bytes_sent);
 
         proto
     }
@@ -492,3 +733,89 @@ where
         proto
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn replies(count: usize) -> Vec<Reply<()>> {
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_flow_id(0x42);
+        request.set_request_id(0x1337);
+        request.set_action(rrg_proto::rrg::Action::GET_SYSTEM_METADATA);
+
+        let request = crate::Request::try_from(request)
+            .unwrap();
+
+        let mut builder = ResponseBuilder::new(request.id());
+
+        (0..count).map(|_| match builder.reply(PreparedItem::from(())) {
+            FilteredReply::Accepted(reply) => reply,
+            _ => panic!("reply not accepted"),
+        }).collect()
+    }
+
+    fn response_ids(batch: &ReplyBatch) -> Vec<u64> {
+        batch.responses.iter()
+            .map(|response| response.response_id())
+            .collect()
+    }
+
+    #[test]
+    fn reply_batcher_no_batching() {
+        let mut batcher = ReplyBatcher::new(1, usize::MAX);
+
+        for reply in replies(3) {
+            let (_, batch) = batcher.push(reply);
+            assert_eq!(batch.unwrap().responses.len(), 1);
+        }
+
+        assert!(batcher.flush().is_none());
+    }
+
+    #[test]
+    fn reply_batcher_max_count() {
+        let mut batcher = ReplyBatcher::new(4, usize::MAX);
+
+        let mut batches = Vec::new();
+        for reply in replies(10) {
+            let (len, batch) = batcher.push(reply);
+            assert!(len > 0);
+
+            batches.extend(batch);
+        }
+        batches.extend(batcher.flush());
+
+        // 10 small replies should be sent in just 3 messages.
+        assert_eq!(batches.len(), 3);
+        assert_eq!(response_ids(&batches[0]), vec![1, 2, 3, 4]);
+        assert_eq!(response_ids(&batches[1]), vec![5, 6, 7, 8]);
+        assert_eq!(response_ids(&batches[2]), vec![9, 10]);
+    }
+
+    #[test]
+    fn reply_batcher_max_len() {
+        let mut replies = replies(3).into_iter();
+
+        let (len, batch) = ReplyBatcher::new(usize::MAX, usize::MAX)
+            .push(replies.next().unwrap());
+        assert!(batch.is_none());
+
+        // The limit allows for two replies (all of them have the same size).
+        let mut batcher = ReplyBatcher::new(usize::MAX, 2 * len);
+
+        let (_, batch) = batcher.push(replies.next().unwrap());
+        assert!(batch.is_none());
+
+        let (_, batch) = batcher.push(replies.next().unwrap());
+        assert_eq!(response_ids(&batch.unwrap()), vec![2, 3]);
+    }
+
+    #[test]
+    fn reply_batcher_flush_empty() {
+        let mut batcher = ReplyBatcher::new(4, usize::MAX);
+
+        assert!(batcher.flush().is_none());
+    }
+}
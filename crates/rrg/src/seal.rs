@@ -0,0 +1,236 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Authenticated encryption of data stored at rest.
+//!
+//! Data is sealed with ChaCha20-Poly1305 (as specified in [RFC 8439]) using
+//! random nonces obtained from the operating system. Keys are derived from a
+//! single secret with HKDF-SHA256 (as specified in [RFC 5869]).
+//!
+//! Encryption is available only with the `spool-encryption` feature. Without
+//! it, [`Key`] has no values, so the code handling keys is still compiled but
+//! never runs.
+//!
+//! [RFC 8439]: https://www.rfc-editor.org/rfc/rfc8439
+//! [RFC 5869]: https://www.rfc-editor.org/rfc/rfc5869
+
+#[cfg(feature = "spool-encryption")]
+use chacha20poly1305::aead::{Aead as _, KeyInit as _};
+
+/// Length of nonces prepended to the sealed data (in bytes).
+#[cfg(feature = "spool-encryption")]
+const NONCE_LEN: usize = 12;
+
+/// Length of authentication tags appended to the sealed data (in bytes).
+#[cfg(feature = "spool-encryption")]
+const TAG_LEN: usize = 16;
+
+/// HMAC-SHA256 instance used for key fingerprints.
+#[cfg(feature = "spool-encryption")]
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+/// Key used to seal and open data.
+#[cfg(feature = "spool-encryption")]
+#[derive(Clone)]
+pub struct Key {
+    /// Key of the ChaCha20-Poly1305 cipher.
+    cipher_key: [u8; 32],
+    /// Key used to compute the fingerprint of the key.
+    fingerprint_key: [u8; 32],
+}
+
+#[cfg(feature = "spool-encryption")]
+impl Key {
+
+    /// Derives a key from the given secret.
+    ///
+    /// `context` binds the key to a particular use, so that the same secret
+    /// yields unrelated keys for different purposes.
+    pub fn derive(secret: &[u8], context: &[u8]) -> Key {
+        let mut okm = [0; 64];
+        hkdf::Hkdf::<sha2::Sha256>::new(Some(b"rrg"), secret)
+            .expand(context, &mut okm)
+            .expect("invalid HKDF output length");
+
+        let mut cipher_key = [0; 32];
+        cipher_key.copy_from_slice(&okm[..32]);
+        let mut fingerprint_key = [0; 32];
+        fingerprint_key.copy_from_slice(&okm[32..]);
+
+        Key {
+            cipher_key,
+            fingerprint_key,
+        }
+    }
+
+    /// Returns a fingerprint that identifies the key without revealing it.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        use hmac::Mac as _;
+
+        let mut mac = <HmacSha256 as hmac::Mac>::new_from_slice(&self.fingerprint_key)
+            .expect("invalid HMAC key length");
+        mac.update(b"fingerprint");
+
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Encrypts and authenticates the given data.
+    ///
+    /// The result consists of a nonce, the ciphertext and an authentication
+    /// tag. Nonces are random, so sealing the same data twice gives different
+    /// results.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it is not possible to obtain
+    /// randomness for the nonce from the operating system.
+    pub fn seal(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut nonce)
+            .map_err(std::io::Error::from)?;
+
+        let ciphertext = self.cipher()
+            .encrypt(&nonce.into(), data)
+            .expect("failed to encrypt data");
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// Verifies and decrypts data sealed with [`Key::seal`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is too short to be
+    /// sealed or if it was sealed with a different key or tampered with.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, OpenError> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(OpenError::Truncated);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = chacha20poly1305::Nonce::from_slice(nonce);
+
+        self.cipher()
+            .decrypt(nonce, ciphertext)
+            .map_err(|_| OpenError::Mismatch)
+    }
+
+    /// Returns the cipher instance for this key.
+    fn cipher(&self) -> chacha20poly1305::ChaCha20Poly1305 {
+        chacha20poly1305::ChaCha20Poly1305::new(&self.cipher_key.into())
+    }
+}
+
+#[cfg(feature = "spool-encryption")]
+impl std::fmt::Debug for Key {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We never want to print the actual keys, e.g. in logs.
+        fmt.debug_struct("Key").finish_non_exhaustive()
+    }
+}
+
+/// Key used to seal and open data.
+///
+/// Encryption is not enabled, so there are no keys.
+#[cfg(not(feature = "spool-encryption"))]
+#[derive(Clone, Debug)]
+pub enum Key {}
+
+#[cfg(not(feature = "spool-encryption"))]
+impl Key {
+
+    /// Returns a fingerprint that identifies the key without revealing it.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        match *self {}
+    }
+
+    /// Encrypts and authenticates the given data.
+    pub fn seal(&self, _data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match *self {}
+    }
+
+    /// Verifies and decrypts data sealed with [`Key::seal`].
+    pub fn open(&self, _sealed: &[u8]) -> Result<Vec<u8>, OpenError> {
+        match *self {}
+    }
+}
+
+/// Error that can happen when opening sealed data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenError {
+    /// The data is too short to be sealed.
+    Truncated,
+    /// The authentication tag does not match the data.
+    Mismatch,
+}
+
+impl std::fmt::Display for OpenError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenError::Truncated => write!(fmt, "sealed data is truncated"),
+            OpenError::Mismatch => write!(fmt, "sealed data authentication failed (wrong key?)"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {
+}
+
+#[cfg(all(test, feature = "spool-encryption"))]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn seal_open() {
+        let key = Key::derive(b"secret", b"test");
+
+        let sealed = key.seal(b"foobar").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..NONCE_LEN + 6], b"foobar");
+        assert_eq!(key.open(&sealed), Ok(b"foobar".to_vec()));
+    }
+
+    #[test]
+    fn seal_unique_nonces() {
+        let key = Key::derive(b"secret", b"test");
+        assert_ne!(key.seal(b"foobar").unwrap(), key.seal(b"foobar").unwrap());
+    }
+
+    #[test]
+    fn open_wrong_key() {
+        let sealed = Key::derive(b"secret", b"test").seal(b"foobar").unwrap();
+
+        assert_eq!(Key::derive(b"other", b"test").open(&sealed), Err(OpenError::Mismatch));
+        assert_eq!(Key::derive(b"secret", b"other").open(&sealed), Err(OpenError::Mismatch));
+    }
+
+    #[test]
+    fn open_tampered() {
+        let key = Key::derive(b"secret", b"test");
+
+        let mut sealed = key.seal(b"foobar").unwrap();
+        sealed[NONCE_LEN] ^= 1;
+        assert_eq!(key.open(&sealed), Err(OpenError::Mismatch));
+    }
+
+    #[test]
+    fn open_truncated() {
+        let key = Key::derive(b"secret", b"test");
+        assert_eq!(key.open(&[0; NONCE_LEN + TAG_LEN - 1]), Err(OpenError::Truncated));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let key = Key::derive(b"secret", b"test");
+        assert_eq!(key.fingerprint(), Key::derive(b"secret", b"test").fingerprint());
+        assert_ne!(key.fingerprint(), Key::derive(b"other", b"test").fingerprint());
+    }
+}
@@ -14,31 +14,155 @@
 //! bytes, action runtime, etc.) and stop the execution if they exceed limits
 //! for a particular request.
 
+mod concurrency;
 mod error;
 
 #[cfg(test)]
 mod fake;
 mod fleetspeak;
+mod paging;
+#[cfg(any(
+    feature = "action-get_file_contents",
+    feature = "action-get_file_metadata",
+    feature = "action-get_filesystem_timeline",
+    feature = "action-stream_file_contents",
+))]
+mod store;
+mod tag;
+mod throttle;
 
 #[cfg(test)]
 pub use crate::session::fake::FakeSession;
 pub use crate::session::fleetspeak::FleetspeakSession;
 
-pub use self::error::{Error};
+pub use self::concurrency::{ConcurrencyLimiter, ConcurrencyPermit, OverflowPolicy};
+pub use self::error::{Error, ErrorCode, ErrorKind};
+pub use self::paging::{reply_paged, InvalidPageCursorError, Paged};
+#[cfg(any(
+    feature = "action-get_file_contents",
+    feature = "action-get_file_metadata",
+    feature = "action-get_filesystem_timeline",
+    feature = "action-stream_file_contents",
+))]
+pub use self::store::{BlobStore, FileRef};
+pub use self::tag::{Tag, TaggedSession};
+pub use self::throttle::RateLimiter;
 
 /// A specialized `Result` type for sessions.
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Abstraction for various kinds of sessions.
 pub trait Session {
+
+    /// Returns the command-line arguments the agent was invoked with.
+    fn args(&self) -> &crate::args::Args;
+
     /// Sends a reply to the flow that call the action.
     fn reply<I>(&mut self, item: I) -> Result<()>
     where I: crate::response::Item + 'static;
 
+    /// Sends a reply tagged with the given label to the flow that call the action.
+    ///
+    /// Tags let the server tell apart replies of different kinds (e.g. coming
+    /// from different sub-collectors) even if they are batched together. By
+    /// default, the tag is dropped and the item is sent as an ordinary reply.
+    fn reply_tagged<I>(&mut self, tag: &Tag, item: I) -> Result<()>
+    where I: crate::response::Item + 'static,
+    {
+        let _ = tag;
+        self.reply(item)
+    }
+
     /// Sends an item to a particular sink.
     fn send<I>(&mut self, sink: crate::Sink, item: I) -> Result<()>
     where I: crate::response::Item + 'static;
 
+    /// Returns the absolute time after which the results are no longer needed.
+    fn deadline(&self) -> Option<std::time::SystemTime> {
+        None
+    }
+
+    /// Checks whether the deadline specified by the server has passed.
+    fn deadline_exceeded(&self) -> bool {
+        match self.deadline() {
+            Some(deadline) => std::time::SystemTime::now() > deadline,
+            None => false,
+        }
+    }
+
+    /// Verifies that the deadline specified by the server has not passed.
+    ///
+    /// Long-running actions should call this method at convenient points (e.g.
+    /// between processing consecutive chunks of data) to avoid doing work that
+    /// is not going to be needed anyway. Sessions can enforce other time limits
+    /// (like the action timeout) here as well.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the deadline has passed.
+    fn check_deadline(&self) -> Result<()> {
+        match self.deadline() {
+            Some(deadline) if self.deadline_exceeded() => {
+                Err(Error::deadline_exceeded(deadline))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Blocks until the agent is allowed to read or send `len` more bytes.
+    ///
+    /// Actions transferring a lot of data should call this method before every
+    /// big read or send, so that the throughput of the agent stays within the
+    /// configured limit. While blocked, the session still enforces its limits.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session limits (e.g. the
+    /// deadline) are exceeded while waiting.
+    fn throttle(&self, len: u64) -> Result<()> {
+        let _ = len;
+        Ok(())
+    }
+
+    /// Informs the session about the total number of items the action yields.
+    ///
+    /// Actions should call this method only if the total is cheap to know
+    /// (e.g. when all the items are collected before any of them is sent). The
+    /// count should include items that might be rejected by filters, the hint
+    /// is then reported to the server in the status of the request.
+    fn set_total_count_hint(&mut self, count: u64) {
+        let _ = count;
+    }
+
+    /// Informs the session that the action read `len` bytes from disk.
+    ///
+    /// Actions reading file contents should call this method after every read.
+    /// The total is reported to the server in the status of the request, along
+    /// with the number of bytes that were actually sent.
+    fn record_bytes_read(&mut self, len: u64) {
+        let _ = len;
+    }
+
+    /// Returns the maximum number of results to send (if paging was requested).
+    ///
+    /// Actions should not use this directly but rather send their results
+    /// through [`reply_paged`] which takes care of the pagination protocol.
+    fn page_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the cursor of the page of results to continue with (if any).
+    fn page_cursor(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Informs the session about the cursor to request the next page with.
+    ///
+    /// The cursor is reported to the server in the status of the request.
+    fn set_next_page_cursor(&mut self, cursor: Vec<u8>) {
+        let _ = cursor;
+    }
+
     /// Sends a heartbeat signal to the Fleetspeak process.
     fn heartbeat(&mut self) {
         // TODO: Create a real implementation.
@@ -0,0 +1,197 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Utilities for limiting the number of concurrently running actions.
+
+use std::collections::HashMap;
+
+use crate::request::Action;
+
+/// Policy for requests that would exceed the concurrency limit of an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until one of the running instances of the action finishes.
+    Queue,
+    /// Fail immediately with a "busy" error.
+    Reject,
+}
+
+/// Limiter of the number of instances of particular actions running at once.
+///
+/// A single limiter is meant to be shared by all the request workers. Limits
+/// of different actions are independent of each other and actions without a
+/// limit are not limited at all.
+pub struct ConcurrencyLimiter {
+    /// Maximum number of running instances of particular actions.
+    limits: HashMap<Action, usize>,
+    /// What to do with requests that would exceed the limit.
+    policy: OverflowPolicy,
+    /// Number of currently running instances of particular actions.
+    running: std::sync::Mutex<HashMap<Action, usize>>,
+    /// Condition variable notified whenever an instance of an action finishes.
+    finished: std::sync::Condvar,
+}
+
+/// Permit to run a single instance of an action.
+///
+/// The action is considered finished once the permit is dropped.
+pub struct ConcurrencyPermit<'a> {
+    /// Limiter that issued the permit.
+    limiter: &'a ConcurrencyLimiter,
+    /// Action that the permit was issued for (if it is limited).
+    action: Option<Action>,
+}
+
+impl ConcurrencyLimiter {
+
+    /// Creates a new limiter with the given `limits` of particular actions.
+    ///
+    /// If there are many limits for the same action, the last one wins.
+    pub fn new(limits: &[(Action, usize)], policy: OverflowPolicy) -> ConcurrencyLimiter {
+        ConcurrencyLimiter {
+            limits: limits.iter().copied().collect(),
+            policy,
+            running: std::sync::Mutex::new(HashMap::new()),
+            finished: std::sync::Condvar::new(),
+        }
+    }
+
+    /// Acquires a permit to run an instance of the given `action`.
+    ///
+    /// If the limit of the action is reached, depending on the policy this
+    /// either blocks until one of the running instances finishes or fails with
+    /// a "busy" error.
+    pub fn acquire(&self, action: Action) -> crate::session::Result<ConcurrencyPermit<'_>> {
+        let limit = match self.limits.get(&action) {
+            Some(limit) => *limit,
+            None => return Ok(ConcurrencyPermit {
+                limiter: self,
+                action: None,
+            }),
+        };
+
+        let mut running = self.running.lock()
+            .expect("poisoned concurrency limiter");
+
+        while running.get(&action).copied().unwrap_or(0) >= limit {
+            match self.policy {
+                OverflowPolicy::Queue => {
+                    running = self.finished.wait(running)
+                        .expect("poisoned concurrency limiter");
+                }
+                OverflowPolicy::Reject => {
+                    return Err(crate::session::Error::busy(action, limit));
+                }
+            }
+        }
+
+        *running.entry(action).or_insert(0) += 1;
+
+        Ok(ConcurrencyPermit {
+            limiter: self,
+            action: Some(action),
+        })
+    }
+}
+
+impl Default for ConcurrencyLimiter {
+
+    /// Returns a limiter that does not limit any action.
+    fn default() -> ConcurrencyLimiter {
+        ConcurrencyLimiter::new(&[], OverflowPolicy::Queue)
+    }
+}
+
+impl<'a> Drop for ConcurrencyPermit<'a> {
+
+    fn drop(&mut self) {
+        let action = match self.action {
+            Some(action) => action,
+            None => return,
+        };
+
+        let mut running = self.limiter.running.lock()
+            .expect("poisoned concurrency limiter");
+
+        if let Some(count) = running.get_mut(&action) {
+            *count -= 1;
+        }
+        drop(running);
+
+        self.limiter.finished.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn acquire_unlimited() {
+        let limiter = ConcurrencyLimiter::default();
+
+        let permits = (0..128)
+            .map(|_| limiter.acquire(Action::GetFileContents).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(permits.len(), 128);
+    }
+
+    #[test]
+    fn acquire_reject() {
+        let limiter = ConcurrencyLimiter::new(&[
+            (Action::GetFileContents, 2),
+        ], OverflowPolicy::Reject);
+
+        let permit_1 = limiter.acquire(Action::GetFileContents).unwrap();
+        let permit_2 = limiter.acquire(Action::GetFileContents).unwrap();
+
+        let error = limiter.acquire(Action::GetFileContents).err().unwrap();
+        assert_eq!(error.kind(), crate::session::ErrorKind::Busy);
+
+        drop(permit_1);
+        assert!(limiter.acquire(Action::GetFileContents).is_ok());
+
+        drop(permit_2);
+    }
+
+    #[test]
+    fn acquire_independent_actions() {
+        let limiter = ConcurrencyLimiter::new(&[
+            (Action::GetFileContents, 1),
+            (Action::GetFileMetadata, 1),
+        ], OverflowPolicy::Reject);
+
+        let _permit_contents = limiter.acquire(Action::GetFileContents).unwrap();
+        let _permit_metadata = limiter.acquire(Action::GetFileMetadata).unwrap();
+
+        assert!(limiter.acquire(Action::GetFileContents).is_err());
+        assert!(limiter.acquire(Action::GetFileMetadata).is_err());
+        assert!(limiter.acquire(Action::GetSystemMetadata).is_ok());
+    }
+
+    #[test]
+    fn acquire_queue() {
+        let limiter = ConcurrencyLimiter::new(&[
+            (Action::GetFileContents, 1),
+        ], OverflowPolicy::Queue);
+
+        let permit = limiter.acquire(Action::GetFileContents).unwrap();
+
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let _permit = limiter.acquire(Action::GetFileContents).unwrap();
+                std::time::Instant::now()
+            });
+
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            let release_time = std::time::Instant::now();
+            drop(permit);
+
+            assert!(waiter.join().unwrap() >= release_time);
+        });
+    }
+}
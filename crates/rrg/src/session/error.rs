@@ -29,6 +29,37 @@ pub enum ErrorKind {
     NetworkBytesLimitExceeded,
     /// Action execution crossed the allowed real (wall) time limit.
     RealTimeLimitExceeded,
+    /// Action execution crossed the deadline specified by the server.
+    DeadlineExceeded,
+    /// Action execution crossed the allowed number of replies.
+    ReplyLimitExceeded,
+    /// Too many instances of the action were already running.
+    Busy,
+    /// The page cursor given for the action was invalid.
+    InvalidPageCursor,
+    /// Too many requests were already waiting to be executed.
+    QueueFull,
+}
+
+/// Categories of system errors that caused a session error.
+///
+/// Codes are derived from [`std::io::Error`] instances found in the chain of
+/// errors (if any) and can be used to distinguish between common causes of
+/// failures without inspecting error messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The agent lacked privileges to perform the operation.
+    PermissionDenied,
+    /// The requested entity (e.g. a file) was not found.
+    NotFound,
+    /// The entity (e.g. a file) to be created already exists.
+    AlreadyExists,
+    /// The operation timed out.
+    TimedOut,
+    /// The operation was interrupted before it could complete.
+    Interrupted,
+    /// The operation is not supported by the system.
+    Unsupported,
 }
 
 impl Error {
@@ -54,6 +85,77 @@ impl Error {
             error: Box::new(UnsupportedActionError { action }),
         }
     }
+
+    /// Creates an error indicating that the request deadline has passed.
+    pub fn deadline_exceeded(deadline: std::time::SystemTime) -> Error {
+        Error {
+            kind: ErrorKind::DeadlineExceeded,
+            error: Box::new(DeadlineExceededError { deadline }),
+        }
+    }
+
+    /// Creates an error indicating that `limit` instances of `action` run.
+    pub fn busy(action: crate::request::Action, limit: usize) -> Error {
+        Error {
+            kind: ErrorKind::Busy,
+            error: Box::new(BusyError { action, limit }),
+        }
+    }
+
+    /// Creates an error indicating that `size` requests are already queued.
+    pub fn queue_full(size: usize) -> Error {
+        Error {
+            kind: ErrorKind::QueueFull,
+            error: Box::new(QueueFullError { size }),
+        }
+    }
+
+    /// Creates an error indicating that the page cursor is invalid.
+    pub fn invalid_page_cursor<E>(error: E) -> Error
+    where
+        E: std::error::Error + 'static,
+    {
+        Error {
+            kind: ErrorKind::InvalidPageCursor,
+            error: Box::new(error),
+        }
+    }
+
+    /// Returns the corresponding [`ErrorKind`] of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the [`ErrorCode`] of the underlying cause of this error.
+    ///
+    /// The code is determined by the first I/O error found in the chain of
+    /// errors. If there is no such error or its kind does not correspond to
+    /// any of the codes, `None` is returned.
+    pub fn code(&self) -> Option<ErrorCode> {
+        let error: &(dyn std::error::Error + 'static) = self.error.as_ref();
+
+        std::iter::successors(Some(error), |error| error.source())
+            .find_map(|error| error.downcast_ref::<std::io::Error>())
+            .and_then(|error| ErrorCode::from_io_error_kind(error.kind()))
+    }
+}
+
+impl ErrorCode {
+
+    /// Maps the kind of an I/O error to the corresponding error code.
+    fn from_io_error_kind(kind: std::io::ErrorKind) -> Option<ErrorCode> {
+        use std::io::ErrorKind::*;
+
+        match kind {
+            PermissionDenied => Some(ErrorCode::PermissionDenied),
+            NotFound => Some(ErrorCode::NotFound),
+            AlreadyExists => Some(ErrorCode::AlreadyExists),
+            TimedOut => Some(ErrorCode::TimedOut),
+            Interrupted => Some(ErrorCode::Interrupted),
+            Unsupported => Some(ErrorCode::Unsupported),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -88,13 +190,50 @@ impl std::fmt::Display for Error {
             RealTimeLimitExceeded => {
                 write!(fmt, "real time limit exceeded: {}", self.error)
             }
+            DeadlineExceeded => {
+                write!(fmt, "deadline exceeded: {}", self.error)
+            }
+            ReplyLimitExceeded => {
+                write!(fmt, "reply limit exceeded: {}", self.error)
+            }
+            Busy => {
+                write!(fmt, "agent busy: {}", self.error)
+            }
+            InvalidPageCursor => {
+                write!(fmt, "invalid page cursor: {}", self.error)
+            }
+            QueueFull => {
+                write!(fmt, "agent busy: {}", self.error)
+            }
+        }?;
+
+        // With the alternate flag (`{:#}`) we also print all the underlying
+        // causes of the error, so that nested failures are not lost.
+        if fmt.alternate() {
+            let mut message = self.error.to_string();
+
+            let mut source = self.error.source();
+            while let Some(error) = source {
+                let source_message = error.to_string();
+
+                // Errors often include messages of their sources in their own
+                // messages, in which case we do not want to repeat them.
+                if !message.contains(&source_message) {
+                    write!(fmt, ": {source_message}")?;
+                }
+
+                message = source_message;
+                source = error.source();
+            }
         }
+
+        Ok(())
     }
 }
 
 impl std::error::Error for Error {
 
-    fn cause(&self) -> Option<&dyn std::error::Error> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         Some(self.error.as_ref())
     }
 }
@@ -134,7 +273,10 @@ impl From<Error> for rrg_proto::rrg::status::Error {
     fn from(error: Error) -> rrg_proto::rrg::status::Error {
         let mut proto = rrg_proto::rrg::status::Error::new();
         proto.set_type(error.kind.into());
-        proto.set_message(error.to_string());
+        proto.set_message(format!("{error:#}"));
+        if let Some(code) = error.code() {
+            proto.set_code(code.into());
+        }
 
         proto
     }
@@ -153,6 +295,27 @@ impl From<ErrorKind> for rrg_proto::rrg::status::error::Type {
             FilterFailure => Self::FILTER_FAILURE,
             NetworkBytesLimitExceeded => Self::NETWORK_BYTES_SENT_LIMIT_EXCEEDED,
             RealTimeLimitExceeded => Self::REAL_TIME_LIMIT_EXCEEDED,
+            DeadlineExceeded => Self::DEADLINE_EXCEEDED,
+            ReplyLimitExceeded => Self::REPLY_LIMIT_EXCEEDED,
+            Busy => Self::BUSY,
+            InvalidPageCursor => Self::INVALID_PAGE_CURSOR,
+            QueueFull => Self::QUEUE_FULL,
+        }
+    }
+}
+
+impl From<ErrorCode> for rrg_proto::rrg::status::error::Code {
+
+    fn from(code: ErrorCode) -> rrg_proto::rrg::status::error::Code {
+        use ErrorCode::*;
+
+        match code {
+            PermissionDenied => Self::PERMISSION_DENIED,
+            NotFound => Self::NOT_FOUND,
+            AlreadyExists => Self::ALREADY_EXISTS,
+            TimedOut => Self::TIMED_OUT,
+            Interrupted => Self::INTERRUPTED,
+            Unsupported => Self::UNSUPPORTED,
         }
     }
 }
@@ -240,3 +403,227 @@ impl From<RealTimeLimitExceededError> for Error {
         }
     }
 }
+
+/// An error type raised when the reply limit has been exceeded.
+#[derive(Debug)]
+pub struct ReplyLimitExceededError {
+    /// Number of replies we were allowed to send.
+    pub reply_limit: u64,
+}
+
+impl std::fmt::Display for ReplyLimitExceededError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "attempted to send more than {} replies", self.reply_limit)
+    }
+}
+
+impl std::error::Error for ReplyLimitExceededError {
+}
+
+impl From<ReplyLimitExceededError> for Error {
+
+    fn from(error: ReplyLimitExceededError) -> Error {
+        Error {
+            kind: ErrorKind::ReplyLimitExceeded,
+            error: Box::new(error),
+        }
+    }
+}
+
+/// An error type raised when the request deadline has passed.
+#[derive(Debug)]
+struct DeadlineExceededError {
+    /// Deadline specified by the server.
+    deadline: std::time::SystemTime,
+}
+
+impl std::fmt::Display for DeadlineExceededError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "deadline {} has passed",
+            humantime::format_rfc3339(self.deadline),
+        }
+    }
+}
+
+impl std::error::Error for DeadlineExceededError {
+}
+
+/// An error type raised when too many instances of an action are running.
+#[derive(Debug)]
+struct BusyError {
+    /// Action that was requested.
+    action: crate::request::Action,
+    /// Number of instances of the action allowed to run at once.
+    limit: usize,
+}
+
+impl std::fmt::Display for BusyError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write! {
+            fmt,
+            "{} instances of action '{}' already running",
+            self.limit,
+            self.action,
+        }
+    }
+}
+
+impl std::error::Error for BusyError {
+}
+
+/// An error type raised when too many requests are waiting to be executed.
+#[derive(Debug)]
+struct QueueFullError {
+    /// Number of requests allowed to wait for execution at once.
+    size: usize,
+}
+
+impl std::fmt::Display for QueueFullError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{} requests already waiting to be executed", self.size)
+    }
+}
+
+impl std::error::Error for QueueFullError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn code_permission_denied() {
+        let error = Error::action(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+
+        assert_eq!(error.code(), Some(ErrorCode::PermissionDenied));
+
+        let proto = rrg_proto::rrg::status::Error::from(error);
+        assert_eq!(proto.type_(), rrg_proto::rrg::status::error::Type::ACTION_FAILURE);
+        assert_eq!(proto.code(), rrg_proto::rrg::status::error::Code::PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn code_not_found() {
+        let error = Error::action(std::io::Error::from(std::io::ErrorKind::NotFound));
+
+        assert_eq!(error.code(), Some(ErrorCode::NotFound));
+    }
+
+    #[test]
+    fn code_unmapped_io_error() {
+        let error = Error::action(std::io::Error::from(std::io::ErrorKind::InvalidData));
+
+        assert_eq!(error.code(), None);
+
+        let proto = rrg_proto::rrg::status::Error::from(error);
+        assert_eq!(proto.code(), rrg_proto::rrg::status::error::Code::UNSPECIFIED);
+    }
+
+    #[test]
+    fn code_non_io_error() {
+        let error = Error::unsupported_action(crate::request::Action::GetSystemMetadata);
+
+        assert_eq!(error.code(), None);
+    }
+
+    #[test]
+    fn code_nested_io_error() {
+        let error = Error::action(WrapperError({
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied)
+        }));
+
+        assert_eq!(error.code(), Some(ErrorCode::PermissionDenied));
+    }
+
+    #[test]
+    fn source_chain_reaches_io_error() {
+        use std::error::Error as _;
+
+        let error = Error::action(WrapperError({
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, "foo")
+        }));
+
+        let wrapper_error = error.source()
+            .unwrap();
+        assert!(wrapper_error.is::<WrapperError>());
+
+        let io_error = wrapper_error.source()
+            .unwrap()
+            .downcast_ref::<std::io::Error>()
+            .unwrap();
+        assert_eq!(io_error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(io_error.to_string(), "foo");
+    }
+
+    #[test]
+    fn display_alternate_includes_sources() {
+        let error = Error::action(OpaqueError({
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, "foo")
+        }));
+
+        assert_eq!(error.to_string(), "action execution failed: bar");
+        assert_eq!(format!("{error:#}"), "action execution failed: bar: foo");
+    }
+
+    #[test]
+    fn display_alternate_skips_repeated_sources() {
+        let error = Error::action(WrapperError({
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, "foo")
+        }));
+
+        assert_eq!(format!("{error:#}"), "action execution failed: wrapped: foo");
+    }
+
+    #[test]
+    fn proto_message_includes_sources() {
+        let error = Error::action(OpaqueError({
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, "foo")
+        }));
+
+        let proto = rrg_proto::rrg::status::Error::from(error);
+        assert_eq!(proto.message(), "action execution failed: bar: foo");
+    }
+
+    /// Error that includes the message of its source in its own.
+    #[derive(Debug)]
+    struct WrapperError(std::io::Error);
+
+    impl std::fmt::Display for WrapperError {
+
+        fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(fmt, "wrapped: {}", self.0)
+        }
+    }
+
+    impl std::error::Error for WrapperError {
+
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    /// Error that does not include the message of its source in its own.
+    #[derive(Debug)]
+    struct OpaqueError(std::io::Error);
+
+    impl std::fmt::Display for OpaqueError {
+
+        fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(fmt, "bar")
+        }
+    }
+
+    impl std::error::Error for OpaqueError {
+
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+}
@@ -12,17 +12,36 @@ use crate::Sink;
 /// Instead, one can use a `Fake` session. It simply accumulates responses
 /// that the action sends and lets the creator inspect them later.
 pub struct FakeSession {
+    args: crate::args::Args,
     replies: Vec<Box<dyn Any>>,
+    reply_tags: Vec<Option<crate::session::Tag>>,
     parcels: std::collections::HashMap<Sink, Vec<Box<dyn Any>>>,
+    total_count_hint: Option<u64>,
+    page_size: Option<u64>,
+    page_cursor: Option<Vec<u8>>,
+    next_page_cursor: Option<Vec<u8>>,
 }
 
 impl FakeSession {
 
     /// Constructs a new fake session.
+    ///
+    /// The session uses default values of the agent command-line arguments.
     pub fn new() -> FakeSession {
+        FakeSession::with_args(crate::args::Args::default())
+    }
+
+    /// Constructs a new fake session with the given command-line arguments.
+    pub fn with_args(args: crate::args::Args) -> FakeSession {
         FakeSession {
+            args,
             replies: Vec::new(),
+            reply_tags: Vec::new(),
             parcels: std::collections::HashMap::new(),
+            total_count_hint: None,
+            page_size: None,
+            page_cursor: None,
+            next_page_cursor: None,
         }
     }
 
@@ -31,6 +50,22 @@ impl FakeSession {
         self.replies.len()
     }
 
+    /// Yields the total count hint that the action reported (if any).
+    pub fn total_count_hint(&self) -> Option<u64> {
+        self.total_count_hint
+    }
+
+    /// Sets the page of results that the action is requested to send.
+    pub fn set_page(&mut self, size: Option<u64>, cursor: Option<Vec<u8>>) {
+        self.page_size = size;
+        self.page_cursor = cursor;
+    }
+
+    /// Yields the cursor of the next page that the action reported (if any).
+    pub fn next_page_cursor(&self) -> Option<&[u8]> {
+        self.next_page_cursor.as_deref()
+    }
+
     /// Retrieves a reply corresponding to the given id.
     ///
     /// The identifier corresponding to the first response is 0, the second one
@@ -48,6 +83,14 @@ impl FakeSession {
         }
     }
 
+    /// Retrieves the tag of the reply corresponding to the given id (if any).
+    pub fn reply_tag(&self, id: usize) -> Option<&crate::session::Tag> {
+        match self.reply_tags.get(id) {
+            Some(tag) => tag.as_ref(),
+            None => panic!("no reply #{}", id),
+        }
+    }
+
     /// Constructs an iterator over session replies.
     ///
     /// The iterator will panic (but not immediately) if some reply has an
@@ -61,6 +104,17 @@ impl FakeSession {
         })
     }
 
+    /// Constructs an iterator over session replies of the specified type.
+    ///
+    /// Unlike [`FakeSession::replies`], replies of other types are skipped,
+    /// which is useful for actions that send replies of different types.
+    pub fn replies_of<R>(&self) -> impl Iterator<Item = &R>
+    where
+        R: crate::response::Item + 'static
+    {
+        self.replies.iter().filter_map(|reply| reply.downcast_ref())
+    }
+
     /// Yields the number of parcels sent so far to the specified sink.
     pub fn parcel_count(&self, sink: Sink) -> usize {
         match self.parcels.get(&sink) {
@@ -111,11 +165,42 @@ impl FakeSession {
 
 impl crate::session::Session for FakeSession {
 
+    fn args(&self) -> &crate::args::Args {
+        &self.args
+    }
+
+    fn set_total_count_hint(&mut self, count: u64) {
+        self.total_count_hint = Some(count);
+    }
+
+    fn page_size(&self) -> Option<u64> {
+        self.page_size
+    }
+
+    fn page_cursor(&self) -> Option<&[u8]> {
+        self.page_cursor.as_deref()
+    }
+
+    fn set_next_page_cursor(&mut self, cursor: Vec<u8>) {
+        self.next_page_cursor = Some(cursor);
+    }
+
     fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
     where
         I: crate::response::Item + 'static,
     {
         self.replies.push(Box::new(item));
+        self.reply_tags.push(None);
+
+        Ok(())
+    }
+
+    fn reply_tagged<I>(&mut self, tag: &crate::session::Tag, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        self.replies.push(Box::new(item));
+        self.reply_tags.push(Some(tag.clone()));
 
         Ok(())
     }
@@ -5,31 +5,208 @@ use log::{error, info};
 /// This is a normal session type that that is associated with some flow on the
 /// server. It keeps track of the responses it sends and collects statistics
 /// about network and runtime utilization to kill the action if it is needed.
-pub struct FleetspeakSession {
+///
+/// All the responses go through a [transport], which normally is Fleetspeak
+/// but can be replaced (e.g. with a loopback transport in tests).
+///
+/// [transport]: crate::transport::Transport
+pub struct FleetspeakSession<'a> {
+    /// Command-line arguments the agent was invoked with.
+    args: &'a crate::args::Args,
+    /// Transport through which responses are sent to the server.
+    ///
+    /// The transport counts bytes that were sent on wire within the session.
+    transport: crate::transport::CountingTransport<std::sync::Arc<dyn crate::transport::Transport>>,
+    /// Rate limiter shared with all the other sessions.
+    rate_limiter: &'a crate::session::RateLimiter,
     /// A builder for responses sent through Fleetspeak to the GRR server.
     response_builder: crate::ResponseBuilder,
+    /// An accumulator of replies that have not been sent yet.
+    reply_batcher: crate::response::ReplyBatcher,
     /// Number of bytes sent since the session was created.
     network_bytes_sent: u64,
     /// Number of bytes we are allowed to send within the session.
     network_bytes_limit: Option<u64>,
+    /// Number of bytes read from disk since the session was created.
+    disk_bytes_read: u64,
     /// Time at which the session was created.
     real_time_start: std::time::Instant,
     /// Time which we are allowed to spend within the session.
     real_time_limit: Option<std::time::Duration>,
+    /// Absolute time after which the results are no longer needed.
+    deadline: Option<std::time::SystemTime>,
+    /// Maximum number of results to send (if paging was requested).
+    page_size: Option<u64>,
+    /// Cursor of the page of results to continue with.
+    page_cursor: Option<Vec<u8>>,
 }
 
-impl FleetspeakSession {
+impl<'a> FleetspeakSession<'a> {
+
+    /// Dispatches all the given `requests` to appropriate action handlers.
+    ///
+    /// Unless the agent is configured to execute actions concurrently, this is
+    /// equivalent to calling [`FleetspeakSession::dispatch`] for each request.
+    /// Otherwise, requests are handled by a pool of worker threads and pulled
+    /// from the iterator into a bounded queue as they arrive. Requests that do
+    /// not fit into the queue are either rejected right away (with a status
+    /// telling the server that the agent is busy) or, depending on the overflow
+    /// policy, no more requests are pulled until one of the workers is free.
+    ///
+    /// Workers also respect the concurrency limits of particular actions: once
+    /// the limit of an action is reached, its further requests either wait for
+    /// one of the running instances to finish or are rejected as busy.
+    ///
+    /// The function returns once the iterator is exhausted and all the requests
+    /// pulled from it are handled (i.e. all the workers finished).
+    pub fn serve<I>(
+        args: &'a crate::args::Args,
+        transport: &std::sync::Arc<dyn crate::transport::Transport>,
+        rate_limiter: &'a crate::session::RateLimiter,
+        registry: &crate::action::Registry<FleetspeakSession<'a>>,
+        requests: I,
+    )
+    where
+        I: IntoIterator<Item = Result<crate::Request, crate::ParseRequestError>>,
+    {
+        if args.action_concurrency <= 1 {
+            for request in requests {
+                FleetspeakSession::dispatch(args, transport, rate_limiter, registry, request);
+            }
+            return;
+        }
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(args.request_queue_size);
+        let receiver = std::sync::Mutex::new(receiver);
+
+        let concurrency_limiter = crate::session::ConcurrencyLimiter::new(
+            &args.action_concurrency_limits,
+            args.action_concurrency_overflow,
+        );
+
+        std::thread::scope(|scope| {
+            for _ in 0..args.action_concurrency {
+                scope.spawn(|| loop {
+                    // The lock is released as soon as the request is received,
+                    // so that other workers can pick up the next ones while this
+                    // one is being handled.
+                    let request = receiver.lock()
+                        .expect("poisoned request queue")
+                        .recv();
+
+                    match request {
+                        Ok(request) => FleetspeakSession::dispatch_limited(
+                            args,
+                            transport,
+                            rate_limiter,
+                            &concurrency_limiter,
+                            registry,
+                            request,
+                        ),
+                        // The sender is dropped once there are no more requests.
+                        Err(std::sync::mpsc::RecvError) => break,
+                    }
+                });
+            }
+
+            for request in requests {
+                use std::sync::mpsc::TrySendError;
+
+                let request = match args.request_queue_overflow {
+                    crate::session::OverflowPolicy::Queue => request,
+                    crate::session::OverflowPolicy::Reject => match sender.try_send(request) {
+                        Ok(()) => continue,
+                        Err(TrySendError::Full(request)) => {
+                            FleetspeakSession::reject(transport, request, {
+                                crate::session::Error::queue_full(args.request_queue_size)
+                            });
+                            continue;
+                        }
+                        Err(TrySendError::Disconnected(request)) => request,
+                    },
+                };
+
+                sender.send(request)
+                    .expect("no request workers");
+            }
+            drop(sender);
+        });
+    }
 
     /// Dispatches the given `request` to an appropriate action handler.
     ///
     /// This is the main entry point of the session. It processes the request
-    /// and sends the execution status back to the server.
+    /// (using handlers from the given action `registry`) and sends the execution
+    /// status back to the server through the given `transport`. Data transfers
+    /// of the action are throttled by the given `rate_limiter`.
     ///
     /// Note that the function accepts a `Result`. This is because we want to
     /// send the error (in case on occurred) back to the server. But this we can
     /// do only within a sesssion, so we have to create a session from a perhaps
     /// invalid request.
-    pub fn dispatch(request: Result<crate::Request, crate::ParseRequestError>) {
+    pub fn dispatch(
+        args: &'a crate::args::Args,
+        transport: &std::sync::Arc<dyn crate::transport::Transport>,
+        rate_limiter: &'a crate::session::RateLimiter,
+        registry: &crate::action::Registry<FleetspeakSession<'a>>,
+        request: Result<crate::Request, crate::ParseRequestError>,
+    ) {
+        // Requests dispatched one by one can never exceed any of the limits.
+        let concurrency_limiter = crate::session::ConcurrencyLimiter::default();
+
+        FleetspeakSession::dispatch_limited(
+            args,
+            transport,
+            rate_limiter,
+            &concurrency_limiter,
+            registry,
+            request,
+        );
+    }
+
+    /// Rejects the given `request` with the given `error` without executing it.
+    ///
+    /// The status with the error is sent straight away through the given
+    /// `transport`, so this is cheap enough to be done by the thread collecting
+    /// requests.
+    fn reject(
+        transport: &std::sync::Arc<dyn crate::transport::Transport>,
+        request: Result<crate::Request, crate::ParseRequestError>,
+        error: crate::session::Error,
+    ) {
+        let request_id = match &request {
+            Ok(request) => request.id(),
+            Err(parse_error) => match parse_error.request_id() {
+                Some(request_id) => request_id,
+                None => {
+                    error!("invalid request: {parse_error}");
+                    return;
+                }
+            }
+        };
+
+        error!("rejected request '{request_id}': {error}");
+
+        #[cfg(feature = "action-get_agent_metrics")]
+        crate::metrics::record_invalid_request(&error);
+
+        crate::ResponseBuilder::new(request_id)
+            .status(Err(error))
+            .send_unaccounted(&**transport);
+    }
+
+    /// Dispatches the given `request` respecting action concurrency limits.
+    ///
+    /// This is the same as [`FleetspeakSession::dispatch`] but the action is
+    /// executed only once the given `concurrency_limiter` permits it.
+    fn dispatch_limited(
+        args: &'a crate::args::Args,
+        transport: &std::sync::Arc<dyn crate::transport::Transport>,
+        rate_limiter: &'a crate::session::RateLimiter,
+        concurrency_limiter: &crate::session::ConcurrencyLimiter,
+        registry: &crate::action::Registry<FleetspeakSession<'a>>,
+        request: Result<crate::Request, crate::ParseRequestError>,
+    ) {
         let request_id = match &request {
             Ok(request) => request.id(),
             Err(error) => match error.request_id() {
@@ -48,30 +225,135 @@ impl FleetspeakSession {
         let status = match request {
             Ok(mut request) => {
                 let filters = request.take_filters();
+
+                // The action timeout configured for the agent applies on top
+                // of the limit specified by the server, the stricter one wins.
+                let action_timeout = args.action_timeout(request.action());
+                let real_time_limit = match (request.real_time_limit(), action_timeout) {
+                    (Some(limit), Some(timeout)) => Some(std::cmp::min(limit, timeout)),
+                    (limit, timeout) => limit.or(timeout),
+                };
+
                 let mut session = FleetspeakSession {
-                    response_builder: response_builder.with_filters(filters),
+                    args,
+                    transport: crate::transport::CountingTransport::new({
+                        std::sync::Arc::clone(transport)
+                    }),
+                    rate_limiter,
+                    response_builder: response_builder
+                        .with_filters(filters)
+                        .with_serialization({
+                            request.result_serialization()
+                                .unwrap_or(args.result_serialization)
+                        }),
+                    reply_batcher: crate::response::ReplyBatcher::new(
+                        args.reply_batch_count,
+                        args.reply_batch_bytes,
+                    ),
                     network_bytes_sent: 0,
                     network_bytes_limit: request.network_bytes_limit(),
+                    disk_bytes_read: 0,
                     real_time_start: std::time::Instant::now(),
-                    real_time_limit: request.real_time_limit(),
+                    real_time_limit,
+                    deadline: request.deadline(),
+                    page_size: request.page_size(),
+                    page_cursor: request.take_page_cursor(),
                 };
 
-                let result = crate::log::ResponseLogger::new(&request)
-                    .context(|| crate::action::dispatch(&mut session, request));
+                // The permit is held until the action finishes (it is dropped
+                // at the end of the match arm).
+                let result = match concurrency_limiter.acquire(request.action()) {
+                    Ok(_permit) => {
+                        crate::log::ResponseLogger::new(&request, std::sync::Arc::clone(transport))
+                            .context(|| registry.dispatch(&mut session, request))
+                    }
+                    Err(error) => {
+                        error!("rejected request '{request_id}': {error}");
+                        Err(error)
+                    }
+                };
+
+                // Regardless of how the action finished, all the replies have
+                // to be sent before the status (as otherwise the server would
+                // consider them lost).
+                session.flush_replies();
 
-                session.response_builder.status(result)
+                #[cfg(feature = "action-get_agent_metrics")]
+                crate::metrics::record_action(&result);
+
+                let bandwidth = crate::response::Bandwidth {
+                    network_bytes_sent: session.network_bytes_sent,
+                    disk_bytes_read: session.disk_bytes_read,
+                    wire_bytes_sent: session.transport.bytes_sent(),
+                };
+                session.response_builder.status(result).with_bandwidth(bandwidth)
             },
             Err(error) => {
                 error!("invalid request '{request_id}': {error}");
-                response_builder.status(Err(error.into()))
+
+                let error = crate::session::Error::from(error);
+                #[cfg(feature = "action-get_agent_metrics")]
+                crate::metrics::record_invalid_request(&error);
+                response_builder.status(Err(error))
             }
         };
 
-        status.send_unaccounted();
+        status.send_unaccounted(&**transport);
     }
 }
 
-impl FleetspeakSession {
+impl<'a> FleetspeakSession<'a> {
+
+    /// Sends a reply (with an optional tag) to the flow that called the action.
+    fn reply_with_tag<I>(
+        &mut self,
+        tag: Option<&crate::session::Tag>,
+        item: I,
+    ) -> crate::session::Result<()>
+    where
+        I: crate::response::Item,
+    {
+        use crate::session::Session as _;
+
+        self.check_reply_limit()?;
+
+        let item = crate::response::PreparedItem::from(item);
+
+        use crate::response::FilteredReply::*;
+        let mut reply = match self.response_builder.reply(item) {
+            Accepted(reply) => reply,
+            Rejected => return Ok(()),
+            Error(error) => return Err(error.into()),
+        };
+        if let Some(tag) = tag {
+            reply = reply.with_tag(tag.clone());
+        }
+
+        // Replies are accounted for when they are batched rather than when the
+        // batch is sent, so that limits are enforced in the same way regardless
+        // of the batching configuration.
+        let (reply_len, batch) = self.reply_batcher.push(reply);
+        if let Some(batch) = batch {
+            batch.send_unaccounted(&self.transport);
+        }
+
+        self.network_bytes_sent += reply_len as u64;
+        #[cfg(feature = "action-get_agent_metrics")]
+        crate::metrics::record_bytes_sent(reply_len as u64);
+        self.check_network_bytes_limit()?;
+
+        // TODO(@panhania): Enforce CPU time limits.
+        self.check_deadline()?;
+
+        Ok(())
+    }
+
+    /// Sends all the replies that have been accumulated so far.
+    fn flush_replies(&mut self) {
+        if let Some(batch) = self.reply_batcher.flush() {
+            batch.send_unaccounted(&self.transport);
+        }
+    }
 
     /// Checks whether the network bytes limit was crossed.
     ///
@@ -91,6 +373,23 @@ impl FleetspeakSession {
         Ok(())
     }
 
+    /// Checks whether another reply would cross the reply limit.
+    ///
+    /// This function will return an error if it would.
+    fn check_reply_limit(&self) -> crate::session::Result<()> {
+        use crate::session::error::ReplyLimitExceededError;
+
+        if let Some(reply_limit) = self.args.max_replies {
+            if self.response_builder.reply_count() >= reply_limit {
+                return Err(ReplyLimitExceededError {
+                    reply_limit,
+                }.into());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Checks whether the real (wall) time limit was crossed.
     ///
     /// This function will return an error if it was.
@@ -111,28 +410,70 @@ impl FleetspeakSession {
     }
 }
 
-impl crate::session::Session for FleetspeakSession {
+impl<'a> crate::session::Session for FleetspeakSession<'a> {
+
+    fn args(&self) -> &crate::args::Args {
+        self.args
+    }
+
+    fn deadline(&self) -> Option<std::time::SystemTime> {
+        self.deadline
+    }
+
+    fn check_deadline(&self) -> crate::session::Result<()> {
+        // Handlers cannot be forcibly aborted, so the real time limit (which
+        // includes the action timeout) is enforced cooperatively: actions are
+        // expected to check the deadline regularly and this is where we cancel
+        // them once the limit is crossed.
+        self.check_real_time_limit()?;
+
+        match self.deadline {
+            Some(deadline) if self.deadline_exceeded() => {
+                Err(crate::session::Error::deadline_exceeded(deadline))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn throttle(&self, len: u64) -> crate::session::Result<()> {
+        self.rate_limiter.acquire(len, || {
+            // TODO(@panhania): Enforce CPU time limits.
+            self.check_deadline()
+        })
+    }
+
+    fn set_total_count_hint(&mut self, count: u64) {
+        self.response_builder.set_total_count_hint(count);
+    }
+
+    fn record_bytes_read(&mut self, len: u64) {
+        self.disk_bytes_read += len;
+    }
+
+    fn page_size(&self) -> Option<u64> {
+        self.page_size
+    }
+
+    fn page_cursor(&self) -> Option<&[u8]> {
+        self.page_cursor.as_deref()
+    }
+
+    fn set_next_page_cursor(&mut self, cursor: Vec<u8>) {
+        self.response_builder.set_next_page_cursor(cursor);
+    }
 
     fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
     where
         I: crate::response::Item,
     {
-        let item = crate::response::PreparedItem::from(item);
-
-        use crate::response::FilteredReply::*;
-        let reply = match self.response_builder.reply(item) {
-            Accepted(reply) => reply,
-            Rejected => return Ok(()),
-            Error(error) => return Err(error.into()),
-        };
-
-        self.network_bytes_sent += reply.send_unaccounted() as u64;
-        self.check_network_bytes_limit()?;
-
-        // TODO(@panhania): Enforce CPU time limits.
-        self.check_real_time_limit()?;
+        self.reply_with_tag(None, item)
+    }
 
-        Ok(())
+    fn reply_tagged<I>(&mut self, tag: &crate::session::Tag, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item,
+    {
+        self.reply_with_tag(Some(tag), item)
     }
 
     fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
@@ -141,11 +482,18 @@ impl crate::session::Session for FleetspeakSession {
     {
         let parcel = crate::response::Parcel::new(sink, item);
 
-        self.network_bytes_sent += parcel.send_unaccounted() as u64;
+        // Parcels are often related to replies (e.g. blobs that replies refer
+        // to), so we want to keep the order in which they were issued.
+        self.flush_replies();
+
+        let parcel_len = parcel.send_unaccounted(&self.transport) as u64;
+        self.network_bytes_sent += parcel_len;
+        #[cfg(feature = "action-get_agent_metrics")]
+        crate::metrics::record_bytes_sent(parcel_len);
         self.check_network_bytes_limit()?;
 
         // TODO(@panhania): Enforce CPU time limits.
-        self.check_real_time_limit()?;
+        self.check_deadline()?;
 
         Ok(())
     }
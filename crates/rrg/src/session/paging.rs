@@ -0,0 +1,184 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Pagination of results of actions yielding large sets of items.
+//!
+//! The server can ask for at most a given number of results (the page size).
+//! Actions supporting pagination then stop once the page is full and report an
+//! opaque cursor in the request status. The server requests the next page by
+//! sending the same request again, this time with the cursor set.
+//!
+//! The cursor is a serialized state of the enumeration of the action (e.g. the
+//! identifier of the last process sent) rather than a snapshot of the results.
+//! Thus, if the system changes between pages, later pages reflect the changed
+//! state: items that appeared in the meantime may be missed or items can stop
+//! existing before their page is requested.
+
+/// An item of a paged enumeration.
+pub struct Paged<I> {
+    /// Item to send as a reply.
+    pub item: I,
+    /// Cursor to continue the enumeration with right after this item.
+    pub cursor: Vec<u8>,
+}
+
+/// Sends a page of items of an enumeration as replies.
+///
+/// The `resume` function is called with the cursor specified in the request (if
+/// any) and should return items of the enumeration that follow the position it
+/// describes. Once a page full of items is sent and there are more items to
+/// send, the session reports the cursor of the last item sent.
+///
+/// # Errors
+///
+/// This function will return an error if the cursor cannot be resumed from or
+/// if sending any of the replies fails.
+pub fn reply_paged<S, I, T, F, E>(session: &mut S, resume: F) -> super::Result<()>
+where
+    S: super::Session,
+    I: crate::response::Item + 'static,
+    T: IntoIterator<Item = Paged<I>>,
+    F: FnOnce(Option<&[u8]>) -> std::result::Result<T, E>,
+    E: std::error::Error + 'static,
+{
+    let page_size = session.page_size();
+    let page_cursor = session.page_cursor().map(<[u8]>::to_vec);
+
+    let items = resume(page_cursor.as_deref())
+        .map_err(super::Error::invalid_page_cursor)?;
+
+    let mut last_cursor = None;
+
+    for (count, Paged { item, cursor }) in (0..).zip(items) {
+        if page_size == Some(count) {
+            // We get here only if there is at least one item past the page, so
+            // the server does not have to ask for an empty one in the end.
+            if let Some(last_cursor) = last_cursor {
+                session.set_next_page_cursor(last_cursor);
+            }
+            return Ok(());
+        }
+
+        session.reply(item)?;
+
+        last_cursor = Some(cursor);
+    }
+
+    Ok(())
+}
+
+/// An error type for cursors that do not describe a valid position.
+#[derive(Debug)]
+pub struct InvalidPageCursorError;
+
+impl std::fmt::Display for InvalidPageCursorError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "malformed page cursor")
+    }
+}
+
+impl std::error::Error for InvalidPageCursorError {
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Synthetic item of a paged enumeration.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Number(u64);
+
+    impl crate::response::Item for Number {
+
+        type Proto = protobuf::well_known_types::wrappers::UInt64Value;
+
+        fn into_proto(self) -> Self::Proto {
+            let mut proto = Self::Proto::new();
+            proto.value = self.0;
+
+            proto
+        }
+    }
+
+    /// Handler of a synthetic action enumerating numbers up to `count`.
+    fn handle_numbers<S>(session: &mut S, count: u64) -> crate::session::Result<()>
+    where
+        S: crate::session::Session,
+    {
+        reply_paged(session, |cursor| {
+            let start = match cursor {
+                Some(cursor) => {
+                    let cursor = <[u8; 8]>::try_from(cursor)
+                        .map_err(|_| InvalidPageCursorError)?;
+                    u64::from_be_bytes(cursor) + 1
+                }
+                None => 0,
+            };
+
+            Ok::<_, InvalidPageCursorError>((start..count).map(|number| Paged {
+                item: Number(number),
+                cursor: number.to_be_bytes().to_vec(),
+            }))
+        })
+    }
+
+    #[test]
+    fn reply_paged_no_page_size() {
+        let mut session = crate::session::FakeSession::new();
+        assert!(handle_numbers(&mut session, 100).is_ok());
+
+        assert_eq!(session.reply_count(), 100);
+        assert_eq!(session.next_page_cursor(), None);
+    }
+
+    #[test]
+    fn reply_paged_all_pages() {
+        let mut numbers = Vec::new();
+        let mut cursor = None;
+        let mut page_count = 0;
+
+        loop {
+            let mut session = crate::session::FakeSession::new();
+            session.set_page(Some(64), cursor.clone());
+            assert!(handle_numbers(&mut session, 1000).is_ok());
+
+            assert!(session.reply_count() <= 64);
+            numbers.extend(session.replies::<Number>().map(|number| number.0));
+            page_count += 1;
+
+            match session.next_page_cursor() {
+                Some(next_cursor) => cursor = Some(next_cursor.to_vec()),
+                None => break,
+            }
+        }
+
+        // Every number is covered exactly once and in order.
+        assert_eq!(numbers, (0..1000).collect::<Vec<_>>());
+        assert_eq!(page_count, 16);
+    }
+
+    #[test]
+    fn reply_paged_exact_page() {
+        let mut session = crate::session::FakeSession::new();
+        session.set_page(Some(10), None);
+        assert!(handle_numbers(&mut session, 10).is_ok());
+
+        assert_eq!(session.reply_count(), 10);
+        assert_eq!(session.next_page_cursor(), None);
+    }
+
+    #[test]
+    fn reply_paged_invalid_cursor() {
+        let mut session = crate::session::FakeSession::new();
+        session.set_page(Some(10), Some(b"foo".to_vec()));
+
+        let error = handle_numbers(&mut session, 10)
+            .unwrap_err();
+        assert_eq!(error.kind(), crate::session::ErrorKind::InvalidPageCursor);
+        assert_eq!(session.reply_count(), 0);
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Utilities for storing file contents as content-addressed blobs.
+//!
+//! Blobs sent to the blob sink are addressed by their SHA-256 digests, so it is
+//! enough for actions to reply with references (digests and sizes) instead of
+//! the data itself. The [`BlobStore`] takes care of reading, hashing and
+//! sending the data and avoids sending the same blob more than once.
+
+/// Maximum length of individual blobs that file contents are split into.
+const MAX_BLOB_LEN: usize = 2 * 1024 * 1024; // 2 MiB.
+
+/// Reference to file contents stored in the blob sink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileRef {
+    /// SHA-256 digest of the whole contents.
+    pub sha256: [u8; 32],
+    /// Length of the whole contents in bytes.
+    pub len: u64,
+    /// SHA-256 digests of consecutive blobs the contents were split into.
+    pub blob_sha256s: Vec<[u8; 32]>,
+}
+
+/// Store of blobs sent to the blob sink.
+///
+/// The store remembers digests of all the blobs it sent, so blobs with the same
+/// contents (e.g. from duplicated files) are sent only once.
+#[derive(Default)]
+pub struct BlobStore {
+    /// SHA-256 digests of blobs sent so far.
+    sent_sha256s: std::collections::HashSet<[u8; 32]>,
+}
+
+impl BlobStore {
+
+    /// Creates a new store that has not sent any blobs yet.
+    pub fn new() -> BlobStore {
+        BlobStore::default()
+    }
+
+    /// Sends the given blob to the blob sink (unless it was sent before).
+    ///
+    /// The SHA-256 digest of the blob is returned regardless of whether it was
+    /// actually sent.
+    pub fn store<S>(&mut self, session: &mut S, blob: crate::blob::Blob) -> crate::session::Result<[u8; 32]>
+    where
+        S: crate::session::Session,
+    {
+        use sha2::Digest as _;
+
+        let sha256 = sha2::Sha256::digest(blob.as_bytes()).into();
+        if self.sent_sha256s.contains(&sha256) {
+            return Ok(sha256);
+        }
+
+        session.send(crate::Sink::Blob, blob)?;
+        self.sent_sha256s.insert(sha256);
+
+        Ok(sha256)
+    }
+
+    /// Stores all contents of the given file and returns a reference to them.
+    ///
+    /// The file is read in blob-sized parts, so at no point it is buffered as
+    /// a whole. Reads are throttled and the deadline is checked between them.
+    pub fn store_file<S, R>(&mut self, session: &mut S, mut file: R) -> crate::session::Result<FileRef>
+    where
+        S: crate::session::Session,
+        R: std::io::Read,
+    {
+        use sha2::Digest as _;
+        use std::io::Read as _;
+
+        let mut hasher = sha2::Sha256::new();
+        let mut len = 0;
+        let mut blob_sha256s = Vec::new();
+
+        loop {
+            session.check_deadline()?;
+            session.throttle(MAX_BLOB_LEN as u64)?;
+
+            let mut buf = Vec::with_capacity(MAX_BLOB_LEN);
+
+            // A single `read` call might return fewer bytes than requested even
+            // if the end of the file has not been reached yet, so we read until
+            // the part is full to keep blob boundaries (and thus deduplication)
+            // independent of how the data arrives.
+            let len_read = (&mut file).take(MAX_BLOB_LEN as u64).read_to_end(&mut buf)
+                .map_err(crate::session::Error::action)?;
+
+            if len_read == 0 {
+                break;
+            }
+
+            hasher.update(&buf);
+            len += len_read as u64;
+
+            blob_sha256s.push(self.store(session, crate::blob::Blob::from(buf))?);
+        }
+
+        Ok(FileRef {
+            sha256: hasher.finalize().into(),
+            len,
+            blob_sha256s,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn store_file_empty() {
+        let mut session = crate::session::FakeSession::new();
+        let mut store = BlobStore::new();
+
+        let file_ref = store.store_file(&mut session, &b""[..])
+            .unwrap();
+
+        assert_eq!(file_ref.len, 0);
+        assert!(file_ref.blob_sha256s.is_empty());
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 0);
+    }
+
+    #[test]
+    fn store_file_identical_files() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        std::fs::write(tempdir.path().join("foo"), b"0123456789")
+            .unwrap();
+        std::fs::write(tempdir.path().join("bar"), b"0123456789")
+            .unwrap();
+
+        let mut session = crate::session::FakeSession::new();
+        let mut store = BlobStore::new();
+
+        let file = std::fs::File::open(tempdir.path().join("foo"))
+            .unwrap();
+        let foo_ref = store.store_file(&mut session, file)
+            .unwrap();
+
+        let file = std::fs::File::open(tempdir.path().join("bar"))
+            .unwrap();
+        let bar_ref = store.store_file(&mut session, file)
+            .unwrap();
+
+        assert_eq!(foo_ref, bar_ref);
+        assert_eq!(foo_ref.len, 10);
+
+        assert_eq!(session.parcel_count(crate::Sink::Blob), 1);
+        let blob = session.parcel::<crate::blob::Blob>(crate::Sink::Blob, 0);
+        assert_eq!(blob.as_bytes(), b"0123456789");
+    }
+
+    #[test]
+    fn store_file_multiple_blobs() {
+        use sha2::Digest as _;
+
+        let mut data = vec![0; 2 * MAX_BLOB_LEN];
+        data.extend_from_slice(b"foobar");
+
+        let mut session = crate::session::FakeSession::new();
+        let mut store = BlobStore::new();
+
+        let file_ref = store.store_file(&mut session, &data[..])
+            .unwrap();
+
+        assert_eq!(file_ref.len, data.len() as u64);
+        assert_eq!(file_ref.sha256, <[u8; 32]>::from(sha2::Sha256::digest(&data)));
+        assert_eq!(file_ref.blob_sha256s.len(), 3);
+        assert_eq!(file_ref.blob_sha256s[0], file_ref.blob_sha256s[1]);
+
+        // The first two parts are both zeros, so they are sent only once.
+        let blobs = session.parcels::<crate::blob::Blob>(crate::Sink::Blob)
+            .map(crate::blob::Blob::as_bytes)
+            .collect::<Vec<_>>();
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[0], &data[..MAX_BLOB_LEN]);
+        assert_eq!(blobs[1], b"foobar");
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Tagging of replies of different kinds sent for a single request.
+//!
+//! Actions composed of multiple sub-collectors (e.g. artifact collection) send
+//! results of different types, possibly batched together. Tags tell the server
+//! which sub-collector (and which kind of result) every reply comes from.
+//!
+//! Tags are paths of segments separated with `/` (e.g. `files/hashes`). Every
+//! sub-collector can be given its own [`TaggedSession`] which prefixes the tags
+//! of its replies with its segment, so tags of different sub-collectors cannot
+//! collide even if the sub-collectors use the same tags internally.
+
+/// Label distinguishing replies of different kinds sent for a single request.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Tag(String);
+
+impl Tag {
+
+    /// Creates a new tag consisting of a single segment.
+    ///
+    /// The segment can be an arbitrary string: the separator (and the escape
+    /// character) are escaped, so that distinct segments never yield the same
+    /// tag.
+    pub fn new(segment: &str) -> Tag {
+        let mut tag = String::with_capacity(segment.len());
+        push_escaped(&mut tag, segment);
+
+        Tag(tag)
+    }
+
+    /// Returns a tag with the given segment appended to this one.
+    pub fn child(&self, segment: &str) -> Tag {
+        let mut tag = self.0.clone();
+        tag.push('/');
+        push_escaped(&mut tag, segment);
+
+        Tag(tag)
+    }
+
+    /// Returns a tag with the segments of the given tag appended to this one.
+    pub fn join(&self, tag: &Tag) -> Tag {
+        Tag(format!("{}/{}", self.0, tag.0))
+    }
+
+    /// Returns the tag as a string (in the form the server receives it).
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Tag {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+/// Appends the given segment with special characters escaped to `tag`.
+fn push_escaped(tag: &mut String, segment: &str) {
+    for char in segment.chars() {
+        match char {
+            '%' => tag.push_str("%25"),
+            '/' => tag.push_str("%2F"),
+            char => tag.push(char),
+        }
+    }
+}
+
+/// Session wrapper tagging all replies of a sub-collector.
+///
+/// Untagged replies get the tag of the wrapper, tagged replies get their tags
+/// prefixed with it. All the other calls are passed to the underlying session.
+pub struct TaggedSession<'s, S> {
+    /// Underlying session to send the replies through.
+    session: &'s mut S,
+    /// Tag to prefix the tags of the replies with.
+    tag: Tag,
+}
+
+impl<'s, S> TaggedSession<'s, S> {
+
+    /// Wraps the given session so that its replies are tagged with `tag`.
+    pub fn new(session: &'s mut S, tag: Tag) -> TaggedSession<'s, S> {
+        TaggedSession {
+            session,
+            tag,
+        }
+    }
+}
+
+impl<'s, S> crate::session::Session for TaggedSession<'s, S>
+where
+    S: crate::session::Session,
+{
+    fn args(&self) -> &crate::args::Args {
+        self.session.args()
+    }
+
+    fn reply<I>(&mut self, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        self.session.reply_tagged(&self.tag, item)
+    }
+
+    fn reply_tagged<I>(&mut self, tag: &Tag, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        self.session.reply_tagged(&self.tag.join(tag), item)
+    }
+
+    fn send<I>(&mut self, sink: crate::Sink, item: I) -> crate::session::Result<()>
+    where
+        I: crate::response::Item + 'static,
+    {
+        self.session.send(sink, item)
+    }
+
+    fn deadline(&self) -> Option<std::time::SystemTime> {
+        self.session.deadline()
+    }
+
+    fn check_deadline(&self) -> crate::session::Result<()> {
+        self.session.check_deadline()
+    }
+
+    fn throttle(&self, len: u64) -> crate::session::Result<()> {
+        self.session.throttle(len)
+    }
+
+    fn record_bytes_read(&mut self, len: u64) {
+        self.session.record_bytes_read(len)
+    }
+
+    fn heartbeat(&mut self) {
+        self.session.heartbeat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn tag_child() {
+        let tag = Tag::new("foo").child("bar").child("baz");
+        assert_eq!(tag.as_str(), "foo/bar/baz");
+    }
+
+    #[test]
+    fn tag_join() {
+        let tag = Tag::new("foo").join(&Tag::new("bar").child("baz"));
+        assert_eq!(tag, Tag::new("foo").child("bar").child("baz"));
+    }
+
+    #[test]
+    fn tag_escaped_no_collision() {
+        let tag_a = Tag::new("foo/bar").child("baz");
+        let tag_b = Tag::new("foo").child("bar/baz");
+        let tag_c = Tag::new("foo").child("bar").child("baz");
+
+        assert_eq!(tag_a.as_str(), "foo%2Fbar/baz");
+        assert_ne!(tag_a, tag_b);
+        assert_ne!(tag_a, tag_c);
+        assert_ne!(tag_b, tag_c);
+
+        assert_eq!(Tag::new("100%").as_str(), "100%25");
+        assert_ne!(Tag::new("%2F"), Tag::new("/"));
+    }
+
+    #[test]
+    fn tagged_session_nested() {
+        use crate::session::Session as _;
+
+        let mut session = crate::session::FakeSession::new();
+
+        let mut files_session = TaggedSession::new(&mut session, Tag::new("files"));
+        files_session.reply(()).unwrap();
+        files_session.reply_tagged(&Tag::new("hashes"), ()).unwrap();
+
+        let mut nested_session = TaggedSession::new(&mut files_session, Tag::new("nested"));
+        nested_session.reply_tagged(&Tag::new("hashes"), ()).unwrap();
+
+        let mut registry_session = TaggedSession::new(&mut session, Tag::new("registry"));
+        registry_session.reply_tagged(&Tag::new("hashes"), ()).unwrap();
+
+        crate::session::Session::reply(&mut session, ()).unwrap();
+
+        let tags = (0..session.reply_count())
+            .map(|id| session.reply_tag(id).map(Tag::as_str))
+            .collect::<Vec<_>>();
+
+        assert_eq!(tags, vec![
+            Some("files"),
+            Some("files/hashes"),
+            Some("files/nested/hashes"),
+            Some("registry/hashes"),
+            None,
+        ]);
+    }
+}
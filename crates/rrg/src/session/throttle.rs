@@ -0,0 +1,208 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Utilities for limiting the rate at which the agent transfers data.
+
+/// Maximum time the limiter sleeps for before re-checking cancellation.
+const MAX_WAIT_STEP: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Token bucket limiting the number of bytes transferred per second.
+///
+/// A single limiter is meant to be shared by all the sessions, so that the
+/// limit applies to the total throughput of the agent rather than to throughput
+/// of individual actions.
+///
+/// The bucket holds at most as many tokens as the rate allows to transfer in
+/// one second, so idle periods do not allow for arbitrarily big bursts later.
+pub struct RateLimiter {
+    /// Number of bytes allowed to be transferred per second (if limited).
+    rate: Option<std::num::NonZeroU64>,
+    /// State of the bucket.
+    bucket: std::sync::Mutex<Bucket>,
+}
+
+/// State of the token bucket of a [`RateLimiter`].
+struct Bucket {
+    /// Number of bytes that can be transferred right now.
+    ///
+    /// This value can be negative if a transfer bigger than the capacity of the
+    /// bucket was allowed and the bucket is "in debt".
+    tokens: f64,
+    /// Time at which the bucket was last refilled.
+    refill_time: std::time::Instant,
+}
+
+impl RateLimiter {
+
+    /// Creates a new limiter allowing to transfer `rate` bytes per second.
+    ///
+    /// If `rate` is `None`, the limiter does not limit anything.
+    pub fn new(rate: Option<std::num::NonZeroU64>) -> RateLimiter {
+        let tokens = match rate {
+            Some(rate) => rate.get() as f64,
+            None => 0.0,
+        };
+
+        RateLimiter {
+            rate,
+            bucket: std::sync::Mutex::new(Bucket {
+                tokens,
+                refill_time: std::time::Instant::now(),
+            }),
+        }
+    }
+
+    /// Blocks until transferring `len` bytes is allowed.
+    ///
+    /// While waiting, the `check` function is called periodically and if it
+    /// fails, waiting is aborted and the error is returned. This allows callers
+    /// to stop waiting if the action is cancelled (e.g. exceeds its deadline).
+    pub fn acquire<E, F>(&self, len: u64, mut check: F) -> Result<(), E>
+    where
+        F: FnMut() -> Result<(), E>,
+    {
+        let rate = match self.rate {
+            Some(rate) => rate.get() as f64,
+            None => return Ok(()),
+        };
+
+        // Transfers bigger than the capacity of the bucket would never fit, so
+        // for them we wait only for a full bucket and let it go into debt.
+        let tokens_needed = f64::min(len as f64, rate);
+
+        loop {
+            check()?;
+
+            let wait_time = {
+                let mut bucket = self.bucket.lock()
+                    .expect("poisoned rate limiter bucket");
+                bucket.refill(rate);
+
+                if bucket.tokens >= tokens_needed {
+                    bucket.tokens -= len as f64;
+                    return Ok(());
+                }
+
+                std::time::Duration::from_secs_f64((tokens_needed - bucket.tokens) / rate)
+            };
+
+            // The lock is released at this point, so other threads can still
+            // acquire tokens (or get cancelled) while we are sleeping.
+            std::thread::sleep(std::cmp::min(wait_time, MAX_WAIT_STEP));
+        }
+    }
+}
+
+impl Default for RateLimiter {
+
+    fn default() -> RateLimiter {
+        RateLimiter::new(None)
+    }
+}
+
+impl Bucket {
+
+    /// Adds tokens accumulated since the last refill at the given `rate`.
+    fn refill(&mut self, rate: f64) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.refill_time);
+
+        self.tokens = f64::min(self.tokens + elapsed.as_secs_f64() * rate, rate);
+        self.refill_time = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn rate(rate: u64) -> Option<std::num::NonZeroU64> {
+        std::num::NonZeroU64::new(rate)
+    }
+
+    #[test]
+    fn acquire_unlimited() {
+        let limiter = RateLimiter::new(None);
+
+        let start = std::time::Instant::now();
+        for _ in 0..1024 {
+            limiter.acquire(u64::MAX, || Ok::<(), ()>(()))
+                .unwrap();
+        }
+
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn acquire_small_unaffected() {
+        let limiter = RateLimiter::new(rate(20_000));
+
+        let start = std::time::Instant::now();
+        limiter.acquire(1_000, || Ok::<(), ()>(()))
+            .unwrap();
+
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn acquire_large_throttled() {
+        let limiter = RateLimiter::new(rate(20_000));
+
+        // The first 20 000 bytes fit into the initially full bucket, the other
+        // 20 000 bytes need to wait for the bucket to refill for a second.
+        let start = std::time::Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(10_000, || Ok::<(), ()>(()))
+                .unwrap();
+        }
+
+        assert!(start.elapsed() >= std::time::Duration::from_millis(900));
+    }
+
+    #[test]
+    fn acquire_bigger_than_capacity() {
+        let limiter = RateLimiter::new(rate(20_000));
+
+        let start = std::time::Instant::now();
+        limiter.acquire(100_000, || Ok::<(), ()>(()))
+            .unwrap();
+
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn acquire_cancelled() {
+        let limiter = RateLimiter::new(rate(1));
+        limiter.acquire(1, || Ok::<(), ()>(()))
+            .unwrap();
+
+        let mut checks = 0;
+        let result = limiter.acquire(1, || {
+            checks += 1;
+            if checks > 1 { Err("cancelled") } else { Ok(()) }
+        });
+
+        assert_eq!(result, Err("cancelled"));
+        assert_eq!(checks, 2);
+    }
+
+    #[test]
+    fn acquire_shared_between_threads() {
+        let limiter = RateLimiter::new(rate(20_000));
+
+        let start = std::time::Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    limiter.acquire(10_000, || Ok::<(), ()>(()))
+                        .unwrap();
+                });
+            }
+        });
+
+        assert!(start.elapsed() >= std::time::Duration::from_millis(900));
+    }
+}
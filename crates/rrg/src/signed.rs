@@ -0,0 +1,405 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Verification of content signed by the server.
+//!
+//! Signatures are Ed25519 signatures (as specified in [RFC 8032]) verified
+//! with the [`ed25519_dalek`] crate. Verification is always strict, i.e. it
+//! rejects non-canonical and small-order keys and signatures, so that a signed
+//! message has exactly one valid signature.
+//!
+//! [RFC 8032]: https://www.rfc-editor.org/rfc/rfc8032
+
+/// Public key used to verify signatures.
+pub use ed25519_dalek::VerifyingKey;
+
+/// Verifies that `signature` is a valid signature of `message`.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is malformed or it was
+/// not made with the private key corresponding to `key`.
+pub fn verify(
+    key: &VerifyingKey,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), ed25519_dalek::SignatureError> {
+    let signature = ed25519_dalek::Signature::from_slice(signature)?;
+    key.verify_strict(message, &signature)
+}
+
+/// Verifies a signature of `message` with the configured `verification_key`.
+///
+/// This is the common check for everything that the agent accepts only when it
+/// is signed (e.g. executables to install or commands to run): content with no
+/// key configured or without a signature is refused in the same way as content
+/// with an invalid signature.
+///
+/// # Errors
+///
+/// This function will return an error if there is no verification key, the
+/// signature is missing or it is not valid.
+#[cfg(any(
+    feature = "action-apply_agent_update",
+    feature = "action-execute_signed_command",
+))]
+pub fn verify_signed(
+    verification_key: Option<&VerifyingKey>,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), SignedError> {
+    let verification_key = verification_key
+        .ok_or(SignedError::NoVerificationKey)?;
+
+    if signature.is_empty() {
+        return Err(SignedError::Unsigned);
+    }
+
+    verify(verification_key, message, signature)
+        .map_err(SignedError::Invalid)
+}
+
+/// Maximum difference between the issue time of signed content and the time it
+/// is received at that is tolerated as clock skew.
+const MAX_ISSUE_CLOCK_SKEW: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Minimum length of the nonce of signed content.
+const MIN_NONCE_LEN: usize = 16;
+
+/// Guard against replaying captured signed content.
+///
+/// Signed content (e.g. a request or a command) carries the time at which it
+/// was issued and a random nonce. Content issued outside of the accepted window
+/// is refused and so is content with a nonce seen within the window already.
+///
+/// Note that seen nonces are kept only in memory, so content can be replayed
+/// once after the agent restarts (as long as it is still within the window).
+pub struct ReplayGuard {
+    /// Nonces of the accepted content (with their issue times).
+    seen_nonces: std::sync::Mutex<std::collections::BTreeMap<Vec<u8>, std::time::SystemTime>>,
+}
+
+impl ReplayGuard {
+
+    /// Creates a new guard that has not seen any nonces yet.
+    pub const fn new() -> ReplayGuard {
+        ReplayGuard {
+            seen_nonces: std::sync::Mutex::new(std::collections::BTreeMap::new()),
+        }
+    }
+
+    /// Checks content with the given issue time and nonce received at `now`.
+    ///
+    /// Content issued more than `max_age` ago is refused. If the content is
+    /// accepted, its nonce is remembered and refused from now on.
+    ///
+    /// This has to be called only once the signature of the content (covering
+    /// the issue time and the nonce) is verified.
+    pub fn check(
+        &self,
+        issue_time: Option<&protobuf::well_known_types::timestamp::Timestamp>,
+        nonce: Vec<u8>,
+        max_age: std::time::Duration,
+        now: std::time::SystemTime,
+    ) -> Result<(), ReplayError> {
+        let issue_time = issue_time
+            .ok_or(ReplayError::MissingIssueTime)?;
+
+        let secs = u64::try_from(issue_time.seconds)
+            .map_err(|_| ReplayError::InvalidIssueTime)?;
+        let nanos = u32::try_from(issue_time.nanos)
+            .map_err(|_| ReplayError::InvalidIssueTime)?;
+        let issue_time = std::time::UNIX_EPOCH
+            .checked_add(std::time::Duration::new(secs, nanos))
+            .ok_or(ReplayError::InvalidIssueTime)?;
+
+        match now.duration_since(issue_time) {
+            Ok(age) if age > max_age => {
+                return Err(ReplayError::Expired { age, max_age });
+            }
+            Err(error) if error.duration() > MAX_ISSUE_CLOCK_SKEW => {
+                return Err(ReplayError::Future(error.duration()));
+            }
+            _ => (),
+        }
+
+        if nonce.len() < MIN_NONCE_LEN {
+            return Err(ReplayError::ShortNonce(nonce.len()));
+        }
+
+        let mut seen_nonces = self.seen_nonces.lock()
+            .expect("poisoned nonces");
+
+        // Nonces of content issued before the window are not needed anymore as
+        // such content is going to be refused anyway.
+        let cutoff = now.checked_sub(max_age);
+        seen_nonces.retain(|_, issue_time| cutoff.is_none_or(|cutoff| *issue_time >= cutoff));
+
+        if seen_nonces.insert(nonce, issue_time).is_some() {
+            return Err(ReplayError::Replayed);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of nonces that the guard currently remembers.
+    #[cfg(test)]
+    pub fn seen_nonce_count(&self) -> usize {
+        self.seen_nonces.lock()
+            .expect("poisoned nonces")
+            .len()
+    }
+}
+
+/// The error type for cases when signed content might have been replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The content does not specify its issue time.
+    MissingIssueTime,
+    /// The issue time of the content is before the epoch or out of range.
+    InvalidIssueTime,
+    /// The content was issued too long ago.
+    Expired {
+        /// Time that passed since the content was issued.
+        age: std::time::Duration,
+        /// Maximum age of accepted content.
+        max_age: std::time::Duration,
+    },
+    /// The content was issued too far in the future (by the given duration).
+    Future(std::time::Duration),
+    /// The nonce of the content is too short (of the given length).
+    ShortNonce(usize),
+    /// The nonce of the content has been seen already.
+    Replayed,
+}
+
+impl std::fmt::Display for ReplayError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ReplayError::*;
+
+        match self {
+            MissingIssueTime => write!(fmt, "missing issue time"),
+            InvalidIssueTime => write!(fmt, "invalid issue time"),
+            Expired { age, max_age } => {
+                write!(fmt, "issued {age:?} ago (more than {max_age:?})")
+            }
+            Future(duration) => write!(fmt, "issued {duration:?} in the future"),
+            ShortNonce(len) => write!(fmt, "nonce too short ({len} bytes)"),
+            Replayed => write!(fmt, "replayed nonce"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+}
+
+/// Parses a verification key from a hex-encoded string.
+pub fn parse_key(string: &str) -> Result<VerifyingKey, ParseKeyError> {
+    const KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+
+    if string.len() != 2 * KEY_LEN {
+        return Err(ParseKeyError::WrongLen(string.len()));
+    }
+
+    // Checking the digits upfront guarantees that the string can be split at
+    // any position and that `from_str_radix` does not accept a sign.
+    if !string.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(ParseKeyError::NonHexDigit);
+    }
+
+    let mut bytes = [0; KEY_LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&string[2 * i..2 * i + 2], 16)
+            .map_err(|_| ParseKeyError::NonHexDigit)?;
+    }
+
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|_| ParseKeyError::NotOnCurve)
+}
+
+/// The error type for cases when signed content cannot be accepted.
+#[derive(Debug)]
+#[cfg(any(
+    feature = "action-apply_agent_update",
+    feature = "action-execute_signed_command",
+))]
+pub enum SignedError {
+    /// The agent was not configured with a key to verify the content with.
+    NoVerificationKey,
+    /// The content is not signed.
+    Unsigned,
+    /// The signature of the content is not valid.
+    Invalid(ed25519_dalek::SignatureError),
+}
+
+#[cfg(any(
+    feature = "action-apply_agent_update",
+    feature = "action-execute_signed_command",
+))]
+impl std::fmt::Display for SignedError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use SignedError::*;
+
+        match self {
+            NoVerificationKey => write!(fmt, "no verification key configured"),
+            Unsigned => write!(fmt, "missing signature"),
+            Invalid(error) => write!(fmt, "invalid signature: {error}"),
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "action-apply_agent_update",
+    feature = "action-execute_signed_command",
+))]
+impl std::error::Error for SignedError {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SignedError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The error type for cases when parsing a verification key fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseKeyError {
+    /// The hex-encoded key has invalid length.
+    WrongLen(usize),
+    /// The hex-encoded key contains a non-hex digit.
+    NonHexDigit,
+    /// The key does not encode a valid curve point.
+    NotOnCurve,
+}
+
+impl std::fmt::Display for ParseKeyError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ParseKeyError::*;
+
+        match self {
+            WrongLen(len) => write!(fmt, "invalid hex-encoded key length: {len}"),
+            NonHexDigit => write!(fmt, "invalid hex digit"),
+            NotOnCurve => write!(fmt, "key not on the curve"),
+        }
+    }
+}
+
+impl std::error::Error for ParseKeyError {
+}
+
+/// Returns the public key corresponding to the private key `seed`.
+#[cfg(test)]
+pub fn public_key(seed: &[u8; 32]) -> VerifyingKey {
+    ed25519_dalek::SigningKey::from_bytes(seed).verifying_key()
+}
+
+/// Signs `message` with the private key `seed`.
+#[cfg(test)]
+pub fn sign(seed: &[u8; 32], message: &[u8]) -> [u8; ed25519_dalek::SIGNATURE_LENGTH] {
+    use ed25519_dalek::Signer as _;
+
+    ed25519_dalek::SigningKey::from_bytes(seed).sign(message).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const TEST_SEED: [u8; 32] = [
+        0x4c, 0xcd, 0x08, 0x9b, 0x28, 0xff, 0x96, 0xda,
+        0x9d, 0xb6, 0xc3, 0x46, 0xec, 0x11, 0x4e, 0x0f,
+        0x5b, 0x8a, 0x31, 0x9f, 0x35, 0xab, 0xa6, 0x24,
+        0xda, 0x8c, 0xf6, 0xed, 0x4f, 0xb8, 0xa6, 0xfb,
+    ];
+
+    #[test]
+    fn verify_ok() {
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        assert!(verify(&public_key(&TEST_SEED), b"foobar", &signature).is_ok());
+    }
+
+    #[test]
+    fn verify_wrong_message() {
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        assert!(verify(&public_key(&TEST_SEED), b"foobaz", &signature).is_err());
+    }
+
+    #[test]
+    fn verify_invalid_signature_len() {
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        assert!(verify(&public_key(&TEST_SEED), b"foobar", &signature[..63]).is_err());
+    }
+
+    #[test]
+    fn parse_key_ok() {
+        let key = parse_key("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c")
+            .unwrap();
+
+        assert_eq!(key, public_key(&TEST_SEED));
+    }
+
+    #[test]
+    fn parse_key_invalid() {
+        assert_eq!(parse_key("3d40"), Err(ParseKeyError::WrongLen(4)));
+        assert_eq!(parse_key(&"x".repeat(64)), Err(ParseKeyError::NonHexDigit));
+    }
+
+    #[cfg(any(
+        feature = "action-apply_agent_update",
+        feature = "action-execute_signed_command",
+    ))]
+    #[test]
+    fn verify_signed_ok() {
+        let key = public_key(&TEST_SEED);
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        assert!(verify_signed(Some(&key), b"foobar", &signature).is_ok());
+    }
+
+    #[cfg(any(
+        feature = "action-apply_agent_update",
+        feature = "action-execute_signed_command",
+    ))]
+    #[test]
+    fn verify_signed_no_verification_key() {
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        let error = verify_signed(None, b"foobar", &signature);
+        assert!(matches!(error, Err(SignedError::NoVerificationKey)));
+    }
+
+    #[cfg(any(
+        feature = "action-apply_agent_update",
+        feature = "action-execute_signed_command",
+    ))]
+    #[test]
+    fn verify_signed_unsigned() {
+        let key = public_key(&TEST_SEED);
+
+        let error = verify_signed(Some(&key), b"foobar", &[]);
+        assert!(matches!(error, Err(SignedError::Unsigned)));
+    }
+
+    #[cfg(any(
+        feature = "action-apply_agent_update",
+        feature = "action-execute_signed_command",
+    ))]
+    #[test]
+    fn verify_signed_invalid() {
+        let key = public_key(&TEST_SEED);
+        let signature = sign(&TEST_SEED, b"foobar");
+
+        let error = verify_signed(Some(&key), b"foobaz", &signature);
+        assert!(matches!(error, Err(SignedError::Invalid(_))));
+    }
+}
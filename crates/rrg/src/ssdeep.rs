@@ -0,0 +1,404 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Streaming implementation of the ssdeep [1] fuzzy hash.
+//!
+//! Unlike cryptographic hashes, fuzzy hashes of similar inputs are similar, so
+//! they can be used to correlate files that are not identical (e.g. different
+//! builds of the same malware). The implementation produces the same digests
+//! as the reference one but never needs to read the input twice: contexts for
+//! all the block sizes that can still be picked are computed at once.
+//!
+//! [1]: https://ssdeep-project.github.io/ssdeep
+
+/// Size of the window of the rolling hash.
+const ROLLING_WINDOW: usize = 7;
+
+/// Smallest block size of a digest.
+const MIN_BLOCK_SIZE: u64 = 3;
+
+/// Prime of the FNV-1 hash used for hashing blocks.
+const HASH_PRIME: u32 = 0x01000193;
+
+/// Initial value of the FNV-1 hash used for hashing blocks.
+const HASH_INIT: u32 = 0x28021967;
+
+/// Number of block sizes for which contexts can be computed.
+const NUM_BLOCK_HASHES: usize = 31;
+
+/// Maximum number of characters of a single part of a digest.
+const SPAMSUM_LEN: usize = 64;
+
+/// Alphabet of characters of digests.
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Maximum length of the input that can be hashed.
+pub const MAX_LEN: u64 = block_size(NUM_BLOCK_HASHES - 1) * SPAMSUM_LEN as u64;
+
+/// Streaming computation of an ssdeep digest.
+pub struct Hasher {
+    /// State of the rolling hash deciding where blocks end.
+    roll: Roll,
+    /// Contexts of block sizes (indexed by the exponent of the block size).
+    blocks: Vec<Block>,
+    /// Index of the smallest block size that can still be picked.
+    start: usize,
+    /// Hash of the block following the last possible block size.
+    ///
+    /// This is tracked only once contexts for all the block sizes exist.
+    last_h: Option<u32>,
+    /// Number of bytes hashed so far.
+    len: u64,
+}
+
+/// State of the rolling hash of [`Hasher`].
+#[derive(Default)]
+struct Roll {
+    /// Last bytes of the input.
+    window: [u8; ROLLING_WINDOW],
+    /// Position in the window at which the next byte is stored.
+    pos: usize,
+    /// Sum of the bytes in the window.
+    h1: u32,
+    /// Sum of the bytes in the window weighted by their position.
+    h2: u32,
+    /// Shift-xor hash of the recent bytes.
+    h3: u32,
+}
+
+/// Context of a single block size of [`Hasher`].
+struct Block {
+    /// Hash of the current block.
+    h: u32,
+    /// Hash of the current block for the truncated digest.
+    half_h: u32,
+    /// Characters of the digest of complete blocks.
+    digest: Vec<u8>,
+    /// Character replacing the last one once the digest is full.
+    tail: Option<u8>,
+    /// Last character of the truncated digest (once it is long enough).
+    half_tail: Option<u8>,
+}
+
+impl Hasher {
+
+    /// Creates a new hasher for an empty input.
+    pub fn new() -> Hasher {
+        Hasher {
+            roll: Roll::default(),
+            blocks: vec![Block::new(HASH_INIT, HASH_INIT)],
+            start: 0,
+            last_h: None,
+            len: 0,
+        }
+    }
+
+    /// Feeds the hasher with the next part of the input.
+    pub fn update(&mut self, buf: &[u8]) {
+        self.len = self.len.saturating_add(buf.len() as u64);
+
+        for byte in buf {
+            self.step(*byte);
+        }
+    }
+
+    /// Returns the digest of the input fed so far.
+    ///
+    /// `None` is returned if the input is longer than [`MAX_LEN`].
+    pub fn digest(&self) -> Option<String> {
+        let mut index = self.start;
+        let h = self.roll.sum();
+
+        // We pick the smallest block size that yields a digest of at most the
+        // maximum length. If it ends up too short, smaller ones are preferred.
+        while block_size(index) * (SPAMSUM_LEN as u64) < self.len {
+            index += 1;
+            if index >= NUM_BLOCK_HASHES {
+                return None;
+            }
+        }
+        index = std::cmp::min(index, self.blocks.len() - 1);
+        while index > self.start && self.blocks[index].digest.len() < SPAMSUM_LEN / 2 {
+            index -= 1;
+        }
+
+        let block = &self.blocks[index];
+
+        let mut digest = format!("{}:", block_size(index)).into_bytes();
+        digest.extend_from_slice(&block.digest);
+        if h != 0 {
+            digest.push(b64(block.h));
+        } else if let Some(tail) = block.tail {
+            digest.push(tail);
+        }
+        digest.push(b':');
+
+        if let Some(block) = self.blocks.get(index + 1) {
+            let len = std::cmp::min(block.digest.len(), SPAMSUM_LEN / 2 - 1);
+            digest.extend_from_slice(&block.digest[..len]);
+            if h != 0 {
+                digest.push(b64(block.half_h));
+            } else if let Some(half_tail) = block.half_tail {
+                digest.push(half_tail);
+            }
+        } else if h != 0 {
+            // The next block size has no context, either because it was never
+            // reached (and it would be the same as the current one) or because
+            // it is beyond the last one (and we tracked its hash separately).
+            match self.last_h {
+                Some(last_h) => digest.push(b64(last_h)),
+                None => digest.push(b64(block.h)),
+            }
+        }
+
+        // All the characters come from the alphabet or are decimal digits.
+        Some(String::from_utf8(digest).expect("non-ASCII digest"))
+    }
+
+    /// Feeds the hasher with a single byte of the input.
+    fn step(&mut self, byte: u8) {
+        self.roll.update(byte);
+        let h = u64::from(self.roll.sum());
+
+        for block in &mut self.blocks[self.start..] {
+            block.h = sum_hash(byte, block.h);
+            block.half_h = sum_hash(byte, block.half_h);
+        }
+        if let Some(last_h) = &mut self.last_h {
+            *last_h = sum_hash(byte, *last_h);
+        }
+
+        // Block sizes grow exponentially, so if a block does not end for one
+        // of them, it does not end for any of the bigger ones either. Note
+        // that the number of contexts can grow while we iterate.
+        let mut index = self.start;
+        while index < self.blocks.len() {
+            if h % block_size(index) != block_size(index) - 1 {
+                break;
+            }
+
+            if self.blocks[index].digest.is_empty() {
+                self.fork();
+            }
+
+            let block = &mut self.blocks[index];
+            block.half_tail = Some(b64(block.half_h));
+
+            if block.digest.len() < SPAMSUM_LEN - 1 {
+                block.digest.push(b64(block.h));
+                block.h = HASH_INIT;
+
+                if block.digest.len() < SPAMSUM_LEN / 2 {
+                    block.half_h = HASH_INIT;
+                    block.half_tail = None;
+                }
+            } else {
+                block.tail = Some(b64(block.h));
+                self.reduce();
+            }
+
+            index += 1;
+        }
+    }
+
+    /// Creates a context for the next block size.
+    fn fork(&mut self) {
+        let last = self.blocks.last()
+            .expect("no block contexts");
+
+        if self.blocks.len() < NUM_BLOCK_HASHES {
+            self.blocks.push(Block::new(last.h, last.half_h));
+        } else if self.last_h.is_none() {
+            self.last_h = Some(last.h);
+        }
+    }
+
+    /// Drops the context of the smallest block size if it cannot be picked.
+    fn reduce(&mut self) {
+        if self.blocks.len() - self.start < 2 {
+            return;
+        }
+        // The initial estimate based on the input length would pick this one
+        // (or a smaller block size).
+        if block_size(self.start) * (SPAMSUM_LEN as u64) >= self.len {
+            return;
+        }
+        // The next block size yields a digest too short to be preferred.
+        if self.blocks[self.start + 1].digest.len() < SPAMSUM_LEN / 2 {
+            return;
+        }
+
+        self.start += 1;
+    }
+}
+
+impl Default for Hasher {
+
+    fn default() -> Hasher {
+        Hasher::new()
+    }
+}
+
+impl Roll {
+
+    /// Feeds the rolling hash with the next byte of the input.
+    fn update(&mut self, byte: u8) {
+        let byte = u32::from(byte);
+
+        self.h2 = self.h2.wrapping_sub(self.h1)
+            .wrapping_add(ROLLING_WINDOW as u32 * byte);
+        self.h1 = self.h1.wrapping_add(byte)
+            .wrapping_sub(u32::from(self.window[self.pos]));
+
+        self.window[self.pos] = byte as u8;
+        self.pos = (self.pos + 1) % ROLLING_WINDOW;
+
+        self.h3 = (self.h3 << 5) ^ byte;
+    }
+
+    /// Returns the current value of the rolling hash.
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+impl Block {
+
+    /// Creates a new context with the given initial hashes.
+    fn new(h: u32, half_h: u32) -> Block {
+        Block {
+            h,
+            half_h,
+            digest: Vec::with_capacity(SPAMSUM_LEN),
+            tail: None,
+            half_tail: None,
+        }
+    }
+}
+
+/// Returns the block size with the given index.
+const fn block_size(index: usize) -> u64 {
+    MIN_BLOCK_SIZE << index
+}
+
+/// Updates the FNV-1 hash of a block with the next byte.
+fn sum_hash(byte: u8, h: u32) -> u32 {
+    h.wrapping_mul(HASH_PRIME) ^ u32::from(byte)
+}
+
+/// Returns the digest character corresponding to the given hash.
+fn b64(h: u32) -> u8 {
+    B64[(h % 64) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Returns pseudo-random text of the given length.
+    fn text(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f491u32;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            b"abcdefghijklmnopqrstuvwxyz \n"[(state >> 16) as usize % 28]
+        }).collect()
+    }
+
+    /// Returns the ssdeep digest of the given input.
+    fn digest(buf: &[u8]) -> String {
+        let mut hasher = Hasher::new();
+        hasher.update(buf);
+        hasher.digest().unwrap()
+    }
+
+    /// Returns whether the strings have a common substring of the given length.
+    fn has_common_substring(a: &str, b: &str, len: usize) -> bool {
+        a.as_bytes().windows(len)
+            .any(|window| b.as_bytes().windows(len).any(|other| window == other))
+    }
+
+    #[test]
+    fn digest_empty() {
+        assert_eq!(digest(b""), "3::");
+    }
+
+    #[test]
+    fn digest_format() {
+        let digest = digest(&text(64 * 1024));
+
+        let mut parts = digest.split(':');
+        let block_size = parts.next().unwrap().parse::<u64>().unwrap();
+        let part_1 = parts.next().unwrap();
+        let part_2 = parts.next().unwrap();
+        assert!(parts.next().is_none());
+
+        assert!(block_size % MIN_BLOCK_SIZE == 0);
+        assert!((block_size / MIN_BLOCK_SIZE).is_power_of_two());
+        assert!(part_1.len() >= SPAMSUM_LEN / 2 && part_1.len() <= SPAMSUM_LEN);
+        assert!(part_2.len() <= SPAMSUM_LEN / 2);
+        assert!(part_1.bytes().chain(part_2.bytes()).all(|byte| B64.contains(&byte)));
+    }
+
+    #[test]
+    fn digest_identical() {
+        assert_eq!(digest(&text(64 * 1024)), digest(&text(64 * 1024)));
+    }
+
+    #[test]
+    fn digest_similar() {
+        let original = text(64 * 1024);
+
+        let mut modified = original.clone();
+        modified[32 * 1024..32 * 1024 + 16].copy_from_slice(b"0123456789abcdef");
+
+        let digest_original = digest(&original);
+        let digest_modified = digest(&modified);
+        assert_ne!(digest_original, digest_modified);
+
+        let (block_size_original, rest_original) = digest_original.split_once(':').unwrap();
+        let (block_size_modified, rest_modified) = digest_modified.split_once(':').unwrap();
+        assert_eq!(block_size_original, block_size_modified);
+
+        // Only the blocks around the modification are affected, so the digests
+        // should share long runs of characters (ssdeep considers digests to be
+        // comparable only if they share at least 7 consecutive characters).
+        let (part_original, _) = rest_original.split_once(':').unwrap();
+        let (part_modified, _) = rest_modified.split_once(':').unwrap();
+        assert!(has_common_substring(part_original, part_modified, 16));
+    }
+
+    #[test]
+    fn digest_different() {
+        let digest_1 = digest(&text(64 * 1024));
+        let digest_2 = digest(&text(64 * 1024).into_iter().rev().collect::<Vec<_>>());
+
+        let (_, rest_1) = digest_1.split_once(':').unwrap();
+        let (_, rest_2) = digest_2.split_once(':').unwrap();
+        assert!(!has_common_substring(rest_1, rest_2, ROLLING_WINDOW));
+    }
+
+    #[test]
+    fn digest_streaming() {
+        let text = text(256 * 1024);
+
+        for chunk_len in [1, 7, 4096, 100_000] {
+            let mut hasher = Hasher::new();
+            for chunk in text.chunks(chunk_len) {
+                hasher.update(chunk);
+            }
+
+            assert_eq!(hasher.digest().unwrap(), digest(&text));
+        }
+    }
+
+    #[test]
+    fn digest_too_long() {
+        let mut hasher = Hasher::new();
+        hasher.len = MAX_LEN + 1;
+
+        assert_eq!(hasher.digest(), None);
+    }
+}
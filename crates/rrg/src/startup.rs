@@ -6,23 +6,31 @@
 // TODO(panhania): Add support for binary paths in the `Metadata` object.
 
 /// Sends a system message with startup information to the GRR server.
-pub fn startup() {
+pub fn startup(transport: &dyn crate::transport::Transport) {
     let startup = Startup::now();
 
     crate::response::Parcel::new(crate::Sink::Startup, startup)
-        .send_unaccounted();
+        .send_unaccounted(transport);
 }
 
 /// Information about the agent startup.
 pub struct Startup {
     /// Metadata about the agent that has been started.
     pub metadata: Metadata,
-    // Path to the agent's executable that is running.
+    /// Path to the agent's executable that is running.
     pub path: Option<std::path::PathBuf>,
+    /// Identifier of the agent process.
+    pub pid: u32,
     /// Value of command-line arguments that the agent was invoked with.
     pub args: Vec<String>,
     /// Time at which the agent was started.
     pub agent_started: std::time::SystemTime,
+    /// Basic knowledge about the system the agent is running on.
+    pub knowledge_base: KnowledgeBase,
+    /// Summary of privileges the agent runs with.
+    pub privileges: Privileges,
+    /// SHA-256 digest of the agent's executable (if it could be read).
+    pub exe_sha256: Option<[u8; 32]>,
     // TOOD(@panhania): Add support for the `os_booted` field.
 }
 
@@ -30,26 +38,48 @@ impl Startup {
 
     /// Creates a startup information as of now.
     pub fn now() -> Startup {
-        // TODO(rust-lang/rust#91345): Simplify with `inspect_err`.
-        let path = {
-            match std::env::current_exe().and_then(std::fs::canonicalize) {
-                Ok(path) => Some(path),
-                Err(error) => {
-                    log::error!("failed to obtain agent's path: {error}");
-                    None
-                }
-            }
-        };
-
         Startup {
             metadata: Metadata::from_cargo(),
-            path,
+            path: exe_path(),
+            pid: std::process::id(),
             args: std::env::args().collect(),
             agent_started: std::time::SystemTime::now(),
+            knowledge_base: KnowledgeBase::collect(),
+            privileges: Privileges::probe(),
+            #[cfg(feature = "startup-exe_sha256")]
+            exe_sha256: exe_sha256(),
+            #[cfg(not(feature = "startup-exe_sha256"))]
+            exe_sha256: None,
         }
     }
 }
 
+/// Returns the path to the agent's executable.
+///
+/// The path is canonicalized if possible. If the executable path cannot be
+/// obtained from the system at all, the first command-line argument is used as
+/// a fallback (which is not reliable but better than nothing).
+fn exe_path() -> Option<std::path::PathBuf> {
+    let error = match std::env::current_exe() {
+        Ok(path) => match std::fs::canonicalize(&path) {
+            Ok(path) => return Some(path),
+            Err(error) => {
+                // The executable might have been removed or replaced since the
+                // agent started, we still want to report where it was.
+                log::error!("failed to canonicalize agent's path: {error}");
+                return Some(path);
+            }
+        },
+        Err(error) => error,
+    };
+
+    log::error!("failed to obtain agent's path: {error}");
+
+    std::env::args_os().next()
+        .filter(|arg| !arg.is_empty())
+        .map(std::path::PathBuf::from)
+}
+
 impl crate::response::Item for Startup {
     type Proto = rrg_proto::startup::Startup;
 
@@ -58,6 +88,248 @@ impl crate::response::Item for Startup {
     }
 }
 
+/// Returns the SHA-256 digest of the agent's executable.
+///
+/// The executable is read only once, the digest is cached for subsequent calls.
+/// If the executable cannot be read, `None` is returned and a warning is logged.
+#[cfg(feature = "startup-exe_sha256")]
+fn exe_sha256() -> Option<[u8; 32]> {
+    static EXE_SHA256: std::sync::OnceLock<Option<[u8; 32]>> = {
+        std::sync::OnceLock::new()
+    };
+
+    *EXE_SHA256.get_or_init(|| {
+        let digest = std::env::current_exe()
+            .and_then(std::fs::File::open)
+            .and_then(|mut file| {
+                use sha2::Digest as _;
+
+                let mut hasher = sha2::Sha256::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                Ok(<[u8; 32]>::from(hasher.finalize()))
+            });
+
+        match digest {
+            Ok(digest) => Some(digest),
+            Err(error) => {
+                log::warn!("failed to compute agent's executable digest: {error}");
+                None
+            }
+        }
+    })
+}
+
+/// Basic knowledge about the system the agent is running on.
+///
+/// This is what the GRR server needs to know about a client to be able to
+/// interrogate it (so that it does not need to run a separate flow after the
+/// client is enrolled). Information that cannot be collected is `None`.
+pub struct KnowledgeBase {
+    /// Kind of the operating system.
+    pub os: ospect::os::Kind,
+    /// Name of the operating system release (e.g. `Debian GNU/Linux`).
+    pub os_release: Option<String>,
+    /// Version of the operating system release (e.g. `12`).
+    pub os_version: Option<String>,
+    /// FQDN of the system.
+    pub fqdn: Option<std::ffi::OsString>,
+    /// Names of local user accounts.
+    pub users: Vec<std::ffi::OsString>,
+    /// Values of system environment variables relevant for the server.
+    pub environ: Vec<(&'static str, std::ffi::OsString)>,
+}
+
+/// Names of environment variables to report as part of the knowledge base.
+#[cfg(target_os = "windows")]
+const KNOWLEDGE_BASE_ENV_VARS: &[&str] = &[
+    "SystemRoot",
+    "SystemDrive",
+    "WinDir",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramData",
+    "AllUsersProfile",
+    "ComSpec",
+];
+
+/// Names of environment variables to report as part of the knowledge base.
+#[cfg(not(target_os = "windows"))]
+const KNOWLEDGE_BASE_ENV_VARS: &[&str] = &[];
+
+impl KnowledgeBase {
+
+    /// Collects knowledge about the currently running system.
+    ///
+    /// Errors are logged and the affected fields are left unset.
+    pub fn collect() -> KnowledgeBase {
+        let fqdn = match ospect::os::fqdn() {
+            Ok(fqdn) => Some(fqdn),
+            Err(error) => {
+                log::error!("failed to collect system FQDN: {error}");
+                None
+            }
+        };
+
+        let users = match ospect::os::users() {
+            Ok(users) => users.map(|user| user.name).collect(),
+            Err(error) => {
+                log::error!("failed to collect system users: {error}");
+                Vec::new()
+            }
+        };
+
+        let environ = KNOWLEDGE_BASE_ENV_VARS.iter()
+            .filter_map(|name| Some((*name, std::env::var_os(name)?)))
+            .collect();
+
+        let release = ospect::os::release();
+        if release.is_unknown() {
+            log::error!("failed to collect OS release");
+        }
+
+        KnowledgeBase {
+            os: ospect::os::kind(),
+            os_release: None,
+            os_version: None,
+            fqdn,
+            users,
+            environ,
+        }.with_release(release)
+    }
+
+    /// Fills the release information using the given normalized release data.
+    fn with_release(self, release: ospect::os::Release) -> KnowledgeBase {
+        KnowledgeBase {
+            os_release: release.name.or(release.id),
+            os_version: release.version_id,
+            ..self
+        }
+    }
+}
+
+/// Summary of privileges the agent runs with.
+///
+/// Some actions (or some of their features) require elevated privileges, e.g.
+/// reading files of other users or inspecting memory of other processes. The
+/// summary lets operators learn up front that these will fail. It is obtained
+/// just by probing the agent process, nothing is ever escalated.
+pub struct Privileges {
+    /// Whether the agent runs as the superuser.
+    ///
+    /// This means running as root on Unix and as an elevated administrator (or
+    /// system) on Windows.
+    pub superuser: bool,
+    /// Names of relevant privileges that the agent holds.
+    pub held: Vec<String>,
+    /// Names of relevant privileges that the agent lacks.
+    pub missing: Vec<String>,
+}
+
+/// Names of privileges relevant for the agent actions.
+///
+/// These are capabilities for reading arbitrary files, inspecting other
+/// processes and entering their mount namespaces respectively.
+#[cfg(target_os = "linux")]
+const RELEVANT_PRIVILEGES: &[&str] = &[
+    "cap_dac_read_search",
+    "cap_sys_ptrace",
+    "cap_sys_admin",
+];
+
+/// Names of privileges relevant for the agent actions.
+///
+/// These are privileges for inspecting other processes, reading arbitrary
+/// files and reading security logs respectively.
+#[cfg(target_os = "windows")]
+const RELEVANT_PRIVILEGES: &[&str] = &[
+    "SeDebugPrivilege",
+    "SeBackupPrivilege",
+    "SeSecurityPrivilege",
+];
+
+/// Names of privileges relevant for the agent actions.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+const RELEVANT_PRIVILEGES: &[&str] = &[];
+
+impl Privileges {
+
+    /// Probes privileges of the agent process.
+    ///
+    /// Errors are logged and privileges that cannot be verified are reported
+    /// as missing.
+    pub fn probe() -> Privileges {
+        let held = match held_privileges() {
+            Ok(held) => held,
+            Err(error) => {
+                log::error!("failed to probe agent privileges: {error}");
+                Vec::new()
+            }
+        };
+
+        let (held, missing) = RELEVANT_PRIVILEGES.iter()
+            .map(|name| String::from(*name))
+            .partition(|name| held.contains(name));
+
+        let privileges = Privileges {
+            superuser: is_superuser(),
+            held,
+            missing,
+        };
+
+        if !privileges.missing.is_empty() {
+            log::warn! {
+                "agent is missing privileges, some actions may fail: {}",
+                privileges.missing.join(", "),
+            };
+        }
+
+        privileges
+    }
+}
+
+/// Returns names of all privileges that the agent process holds.
+#[cfg(target_os = "linux")]
+fn held_privileges() -> std::io::Result<Vec<String>> {
+    let status = ospect::proc::linux::status(std::process::id())?
+        // We are inspecting ourselves, so we cannot have exited.
+        .expect("no agent process status");
+
+    Ok(ospect::proc::linux::capability_names(status.capabilities.effective))
+}
+
+/// Returns names of all privileges that the agent process holds.
+#[cfg(target_os = "windows")]
+fn held_privileges() -> std::io::Result<Vec<String>> {
+    ospect::proc::windows::privileges(std::process::id())
+}
+
+/// Returns names of all privileges that the agent process holds.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn held_privileges() -> std::io::Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+/// Returns whether the agent process runs as the superuser.
+#[cfg(target_family = "unix")]
+fn is_superuser() -> bool {
+    // SAFETY: This function is always safe to call and never fails.
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Returns whether the agent process runs as the superuser.
+#[cfg(target_family = "windows")]
+fn is_superuser() -> bool {
+    use ospect::proc::windows::IntegrityLevel;
+
+    match ospect::proc::windows::integrity_level(std::process::id()) {
+        Ok(integrity_level) => integrity_level >= IntegrityLevel::High,
+        Err(error) => {
+            log::error!("failed to probe agent integrity level: {error}");
+            false
+        }
+    }
+}
+
 /// A type that holds metadata about the RRG agent.
 pub struct Metadata {
     /// Name of the RRG agent.
@@ -118,8 +390,51 @@ impl Into<rrg_proto::startup::Startup> for Startup {
         if let Some(path) = self.path {
             proto.set_path(path.into());
         }
+        proto.set_pid(self.pid);
         proto.set_args(self.args.into());
         proto.set_agent_startup_time(into_timestamp(self.agent_started));
+        proto.set_knowledge_base(self.knowledge_base.into());
+        proto.set_privileges(self.privileges.into());
+        if let Some(exe_sha256) = self.exe_sha256 {
+            proto.set_exe_sha256(exe_sha256.to_vec());
+        }
+
+        proto
+    }
+}
+
+impl From<Privileges> for rrg_proto::startup::Privileges {
+
+    fn from(privileges: Privileges) -> rrg_proto::startup::Privileges {
+        let mut proto = rrg_proto::startup::Privileges::new();
+        proto.set_superuser(privileges.superuser);
+        proto.set_held(privileges.held);
+        proto.set_missing(privileges.missing);
+
+        proto
+    }
+}
+
+impl From<KnowledgeBase> for rrg_proto::startup::KnowledgeBase {
+
+    fn from(knowledge_base: KnowledgeBase) -> rrg_proto::startup::KnowledgeBase {
+        let mut proto = rrg_proto::startup::KnowledgeBase::new();
+        proto.set_os(knowledge_base.os.into());
+        if let Some(os_release) = knowledge_base.os_release {
+            proto.set_os_release(os_release);
+        }
+        if let Some(os_version) = knowledge_base.os_version {
+            proto.set_os_version(os_version);
+        }
+        if let Some(fqdn) = knowledge_base.fqdn {
+            proto.set_fqdn(fqdn.to_string_lossy().into_owned());
+        }
+        proto.set_users(knowledge_base.users.into_iter()
+            .map(|user| user.to_string_lossy().into_owned())
+            .collect());
+        proto.set_environ(knowledge_base.environ.into_iter()
+            .map(|(name, value)| (String::from(name), value.to_string_lossy().into_owned()))
+            .collect());
 
         proto
     }
@@ -149,3 +464,145 @@ impl Into<rrg_proto::startup::Version> for Version {
         proto
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn startup_now_path() {
+        let startup = Startup::now();
+
+        let path = startup.path.unwrap();
+        assert!(!path.as_os_str().is_empty());
+        assert!(path.exists());
+    }
+
+    #[cfg(feature = "startup-exe_sha256")]
+    #[test]
+    fn startup_now_exe_sha256() {
+        use sha2::Digest as _;
+
+        let startup = Startup::now();
+
+        let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let exe_sha256 = <[u8; 32]>::from(sha2::Sha256::digest(&exe));
+
+        assert_eq!(startup.exe_sha256, Some(exe_sha256));
+
+        let proto: rrg_proto::startup::Startup = startup.into();
+        assert_eq!(proto.exe_sha256().len(), 32);
+        assert_eq!(proto.exe_sha256(), &exe_sha256[..]);
+    }
+
+    #[test]
+    fn startup_now_pid() {
+        let startup = Startup::now();
+
+        assert_eq!(startup.pid, std::process::id());
+    }
+
+    #[test]
+    fn startup_now_agent_started() {
+        let startup = Startup::now();
+
+        assert!(startup.agent_started <= std::time::SystemTime::now());
+    }
+
+    #[test]
+    fn startup_into_proto() {
+        let proto: rrg_proto::startup::Startup = Startup::now().into();
+
+        assert!(proto.has_path());
+        assert_eq!(proto.pid(), std::process::id());
+        assert!(proto.has_agent_startup_time());
+    }
+
+    #[test]
+    fn knowledge_base_collect_os() {
+        let knowledge_base = KnowledgeBase::collect();
+        assert_eq! {
+            rrg_proto::os::Type::from(knowledge_base.os),
+            rrg_proto::os::Type::from(ospect::os::kind()),
+        };
+    }
+
+    #[test]
+    fn privileges_probe_relevant() {
+        let privileges = Privileges::probe();
+
+        let mut names = privileges.held.iter()
+            .chain(privileges.missing.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort();
+
+        let mut relevant = RELEVANT_PRIVILEGES.to_vec();
+        relevant.sort();
+
+        assert_eq!(names, relevant);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn privileges_probe_superuser() {
+        let privileges = Privileges::probe();
+
+        // SAFETY: This function is always safe to call and never fails.
+        let euid = unsafe { libc::geteuid() };
+        assert_eq!(privileges.superuser, euid == 0);
+
+        // Unprivileged processes cannot have any capabilities effective at all
+        // (unless they are granted to the executable which is not the case for
+        // the test binary).
+        #[cfg(target_os = "linux")]
+        if euid != 0 {
+            assert!(privileges.held.is_empty());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn knowledge_base_with_os_release() {
+        let contents = b"\
+NAME=\"Ubuntu\"
+VERSION_ID=\"22.04\"
+ID=ubuntu
+";
+
+        let os_release = ospect::os::linux::parse_os_release(&contents[..])
+            .unwrap();
+
+        let knowledge_base = KnowledgeBase {
+            os: ospect::os::Kind::Linux,
+            os_release: None,
+            os_version: None,
+            fqdn: None,
+            users: Vec::new(),
+            environ: Vec::new(),
+        }.with_release(os_release.into());
+
+        assert_eq!(knowledge_base.os_release.as_deref(), Some("Ubuntu"));
+        assert_eq!(knowledge_base.os_version.as_deref(), Some("22.04"));
+    }
+
+    #[test]
+    fn knowledge_base_into_proto_unset() {
+        let knowledge_base = KnowledgeBase {
+            os: ospect::os::Kind::Linux,
+            os_release: None,
+            os_version: None,
+            fqdn: None,
+            users: vec![std::ffi::OsString::from("foo")],
+            environ: Vec::new(),
+        };
+
+        let proto: rrg_proto::startup::KnowledgeBase = knowledge_base.into();
+        assert_eq!(proto.os(), rrg_proto::os::Type::LINUX);
+        assert!(!proto.has_os_release());
+        assert!(!proto.has_os_version());
+        assert!(!proto.has_fqdn());
+        assert_eq!(proto.users, vec![String::from("foo")]);
+    }
+}
@@ -0,0 +1,1722 @@
+// Copyright 2024 Google LLC
+//
+// Use of this source code is governed by an MIT-style license that can be found
+// in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+//! Transports for exchanging messages with the GRR server.
+//!
+//! Normally, the agent talks to the server through Fleetspeak. However, all the
+//! communication goes through the [`Transport`] trait, so that the agent can be
+//! deployed with other transports or be tested end-to-end without Fleetspeak
+//! (using [`LoopbackTransport`]).
+
+/// Message exchanged with the server.
+pub use fleetspeak::Message;
+
+/// Abstraction for various ways of communicating with the server.
+pub trait Transport: Send + Sync {
+
+    /// Sends the given message to the server.
+    fn send(&self, message: Message);
+
+    /// Attempts to send the given message to the server.
+    ///
+    /// Unlike [`Transport::send`], this method reports failures to deliver the
+    /// message instead of panicking, so that the caller can retry later. By
+    /// default, the transport is assumed to never fail.
+    fn try_send(&self, message: &Message) -> std::io::Result<()> {
+        self.send(clone_message(message));
+        Ok(())
+    }
+
+    /// Sends the given message to the server and returns its length on wire.
+    ///
+    /// Transports that transform messages (e.g. by compressing them) report the
+    /// number of bytes of the transformed message. By default, the message is
+    /// assumed to be sent as it is.
+    fn send_counted(&self, message: Message) -> usize {
+        let len = message.data.len();
+        self.send(message);
+        len
+    }
+
+    /// Waits for a message from the server.
+    ///
+    /// The agent is expected to signal that it is alive at the specified rate
+    /// for as long as it waits (if the transport supports it).
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message;
+
+    /// Attempts to wait for a message from the server.
+    ///
+    /// Unlike [`Transport::collect`], this method reports failures to receive
+    /// the message instead of panicking, so that the caller can retry later. By
+    /// default, the transport is assumed to never fail.
+    fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+        Ok(self.collect(heartbeat_rate))
+    }
+
+    /// Attempts to deliver messages that the transport holds back.
+    ///
+    /// This should be called before the agent exits, so that messages waiting
+    /// for delivery (e.g. spooled ones) are not delayed until the next run. By
+    /// default, the transport is assumed to send all messages right away.
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+
+    fn send(&self, message: Message) {
+        (**self).send(message)
+    }
+
+    fn try_send(&self, message: &Message) -> std::io::Result<()> {
+        (**self).try_send(message)
+    }
+
+    fn send_counted(&self, message: Message) -> usize {
+        (**self).send_counted(message)
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        (**self).collect(heartbeat_rate)
+    }
+
+    fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+        (**self).try_collect(heartbeat_rate)
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Error indicating that the connection is broken beyond repair.
+///
+/// Errors of this kind should not be retried: the only way to recover from them
+/// is to restart the agent (see [`exit_broken`]).
+#[derive(Debug)]
+pub struct BrokenError;
+
+impl std::fmt::Display for BrokenError {
+
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "connection is broken")
+    }
+}
+
+impl std::error::Error for BrokenError {
+}
+
+impl BrokenError {
+
+    /// Creates an I/O error signalling that the connection is broken.
+    pub fn io() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::BrokenPipe, BrokenError)
+    }
+}
+
+/// Checks whether the given error signals that the connection is broken.
+pub fn is_broken(error: &std::io::Error) -> bool {
+    error.get_ref().is_some_and(|error| error.is::<BrokenError>())
+}
+
+/// Terminates the agent because of a broken connection.
+///
+/// The agent is expected to be run by a supervisor (normally Fleetspeak) that
+/// restarts it after it exits, establishing a new connection.
+pub fn exit_broken(error: std::io::Error) -> ! {
+    log::error!("unrecoverable transport failure, exiting: {error}");
+    std::process::exit(1)
+}
+
+/// Whether the Fleetspeak connection is broken.
+static FLEETSPEAK_BROKEN: std::sync::atomic::AtomicBool = {
+    std::sync::atomic::AtomicBool::new(false)
+};
+
+/// Transport using the Fleetspeak connection.
+///
+/// This is the transport used by the agent in production.
+pub struct FleetspeakTransport;
+
+impl FleetspeakTransport {
+
+    /// Runs the given Fleetspeak operation, reporting connection failures.
+    ///
+    /// The Fleetspeak library does not report connection failures and just
+    /// panics instead, poisoning the mutex guarding the connection. Hence, once
+    /// a failure happens, the connection is unusable and all the subsequent
+    /// operations are refused without even calling into the library.
+    fn guard<R>(operation: impl FnOnce() -> R) -> std::io::Result<R> {
+        use std::sync::atomic::Ordering;
+
+        if FLEETSPEAK_BROKEN.load(Ordering::SeqCst) {
+            return Err(BrokenError::io());
+        }
+
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(operation))
+            .map_err(|_| {
+                FLEETSPEAK_BROKEN.store(true, Ordering::SeqCst);
+                BrokenError::io()
+            })
+    }
+}
+
+impl Transport for FleetspeakTransport {
+
+    fn send(&self, message: Message) {
+        fleetspeak::send(message)
+    }
+
+    fn try_send(&self, message: &Message) -> std::io::Result<()> {
+        let message = clone_message(message);
+        FleetspeakTransport::guard(|| fleetspeak::send(message))
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        fleetspeak::receive_with_heartbeat(heartbeat_rate)
+    }
+
+    fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+        FleetspeakTransport::guard(|| fleetspeak::receive_with_heartbeat(heartbeat_rate))
+    }
+}
+
+/// Transport keeping all the messages in memory.
+///
+/// Messages for the agent have to be pushed with [`LoopbackTransport::push`]
+/// and messages it sent can be inspected with [`LoopbackTransport::take_sent`].
+/// This is useful for testing the whole request processing cycle.
+pub struct LoopbackTransport {
+    /// Sending end of the queue with messages for the agent.
+    incoming_sender: std::sync::Mutex<std::sync::mpsc::Sender<Message>>,
+    /// Receiving end of the queue with messages for the agent.
+    incoming_receiver: std::sync::Mutex<std::sync::mpsc::Receiver<Message>>,
+    /// Messages sent by the agent so far.
+    outgoing: std::sync::Mutex<Vec<Message>>,
+}
+
+impl LoopbackTransport {
+
+    /// Creates a new transport with no pending messages.
+    pub fn new() -> LoopbackTransport {
+        let (incoming_sender, incoming_receiver) = std::sync::mpsc::channel();
+
+        LoopbackTransport {
+            incoming_sender: std::sync::Mutex::new(incoming_sender),
+            incoming_receiver: std::sync::Mutex::new(incoming_receiver),
+            outgoing: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queues the given message to be collected by the agent.
+    pub fn push(&self, message: Message) {
+        self.incoming_sender.lock()
+            .expect("poisoned loopback sender")
+            .send(message)
+            // The receiving end is owned by the transport itself, so it cannot
+            // be closed while we are using it.
+            .expect("closed loopback channel");
+    }
+
+    /// Returns all the messages sent by the agent since the last call.
+    pub fn take_sent(&self) -> Vec<Message> {
+        let mut outgoing = self.outgoing.lock()
+            .expect("poisoned loopback outgoing messages");
+
+        std::mem::take(&mut *outgoing)
+    }
+}
+
+impl Default for LoopbackTransport {
+
+    fn default() -> LoopbackTransport {
+        LoopbackTransport::new()
+    }
+}
+
+impl Transport for LoopbackTransport {
+
+    fn send(&self, message: Message) {
+        self.outgoing.lock()
+            .expect("poisoned loopback outgoing messages")
+            .push(message);
+    }
+
+    /// Waits for a message pushed with [`LoopbackTransport::push`].
+    ///
+    /// There is nothing to heartbeat to, so the rate is ignored.
+    fn collect(&self, _: std::time::Duration) -> Message {
+        self.incoming_receiver.lock()
+            .expect("poisoned loopback receiver")
+            .recv()
+            // The sending end is owned by the transport itself, so it cannot
+            // be closed while we are using it.
+            .expect("closed loopback channel")
+    }
+}
+
+/// Transport retrying failed collections with an exponential backoff.
+///
+/// Messages are collected through [`Transport::try_collect`] of the inner
+/// transport. Every consecutive failure doubles the delay before the next
+/// attempt (up to the maximum delay), so that a flaky connection does not make
+/// the agent spin. The delay is reset once a message is collected.
+///
+/// Only recoverable errors are retried: if the connection is broken (see
+/// [`is_broken`]), retrying is pointless and the agent exits instead.
+pub struct BackoffTransport<T> {
+    /// Transport through which the messages are collected.
+    inner: T,
+    /// Backoff state shared by all the collecting threads.
+    backoff: std::sync::Mutex<Backoff>,
+}
+
+impl<T: Transport> BackoffTransport<T> {
+
+    /// Creates a new transport with the given bounds of the retry delay.
+    pub fn new(
+        inner: T,
+        initial_delay: std::time::Duration,
+        max_delay: std::time::Duration,
+    ) -> BackoffTransport<T> {
+        BackoffTransport {
+            inner,
+            backoff: std::sync::Mutex::new(Backoff::new(initial_delay, max_delay)),
+        }
+    }
+
+    /// Returns the transport through which the messages are sent.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for BackoffTransport<T> {
+
+    fn send(&self, message: Message) {
+        self.inner.send(message)
+    }
+
+    fn try_send(&self, message: &Message) -> std::io::Result<()> {
+        self.inner.try_send(message)
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        match self.try_collect(heartbeat_rate) {
+            Ok(message) => message,
+            Err(error) => exit_broken(error),
+        }
+    }
+
+    /// Collects a message, retrying for as long as the errors are recoverable.
+    fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+        loop {
+            match self.inner.try_collect(heartbeat_rate) {
+                Ok(message) => {
+                    self.backoff.lock()
+                        .expect("poisoned backoff")
+                        .reset();
+
+                    return Ok(message);
+                }
+                Err(error) if is_broken(&error) => return Err(error),
+                Err(error) => {
+                    let delay = self.backoff.lock()
+                        .expect("poisoned backoff")
+                        .next_delay();
+
+                    log::error!("failed to collect message, retrying in {delay:?}: {error}");
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+    }
+}
+
+/// Exponentially growing delay between consecutive retries.
+#[derive(Debug)]
+struct Backoff {
+    /// Delay before the first retry.
+    initial_delay: std::time::Duration,
+    /// Upper bound of the delay.
+    max_delay: std::time::Duration,
+    /// Delay before the next retry (`None` if there were no failures).
+    next_delay: Option<std::time::Duration>,
+}
+
+impl Backoff {
+
+    /// Creates a new backoff with the given bounds of the delay.
+    fn new(initial_delay: std::time::Duration, max_delay: std::time::Duration) -> Backoff {
+        Backoff {
+            initial_delay,
+            max_delay,
+            next_delay: None,
+        }
+    }
+
+    /// Returns the delay to wait for before the next retry.
+    fn next_delay(&mut self) -> std::time::Duration {
+        let delay = match self.next_delay {
+            Some(delay) => delay,
+            None => self.initial_delay,
+        };
+        let delay = std::cmp::min(delay, self.max_delay);
+
+        self.next_delay = Some(std::cmp::min(delay.saturating_mul(2), self.max_delay));
+        delay
+    }
+
+    /// Resets the delay to the initial one.
+    fn reset(&mut self) {
+        self.next_delay = None;
+    }
+}
+
+/// Transport counting bytes that another transport sent on wire.
+///
+/// The count includes only bytes of messages as they leave the inner transport
+/// (e.g. after compression), so comparing it with the length of the original
+/// messages shows how effective the compression is.
+pub struct CountingTransport<T> {
+    /// Transport through which the messages are sent.
+    inner: T,
+    /// Number of bytes sent on wire so far.
+    bytes_sent: std::sync::atomic::AtomicU64,
+}
+
+impl<T: Transport> CountingTransport<T> {
+
+    /// Creates a new transport counting bytes sent by the given one.
+    pub fn new(inner: T) -> CountingTransport<T> {
+        CountingTransport {
+            inner,
+            bytes_sent: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the transport through which the messages are sent.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the number of bytes sent on wire so far.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+impl<T: Transport> Transport for CountingTransport<T> {
+
+    fn send(&self, message: Message) {
+        self.send_counted(message);
+    }
+
+    fn send_counted(&self, message: Message) -> usize {
+        let len = self.inner.send_counted(message);
+        self.bytes_sent.fetch_add(len as u64, std::sync::atomic::Ordering::Relaxed);
+        len
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        self.inner.collect(heartbeat_rate)
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Algorithm used for compressing messages sent to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Messages are sent as they are.
+    None,
+    /// Messages are compressed using the gzip format.
+    #[cfg(feature = "compression-gzip")]
+    Gzip,
+    /// Messages are compressed using the Zstandard format.
+    #[cfg(feature = "compression-zstd")]
+    Zstd,
+}
+
+impl Compression {
+
+    /// Compresses the given data (unless there is no compression to apply).
+    ///
+    /// The compressed data is returned along with the algorithm to report to
+    /// the server.
+    #[cfg_attr(not(any(
+        feature = "compression-gzip",
+        feature = "compression-zstd",
+    )), allow(unused_variables))]
+    fn compress(self, data: &[u8]) -> Option<(rrg_proto::rrg::compressed_message::Algorithm, Vec<u8>)> {
+        #[cfg(any(
+            feature = "compression-gzip",
+            feature = "compression-zstd",
+        ))]
+        use rrg_proto::rrg::compressed_message::Algorithm;
+
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "compression-gzip")]
+            Compression::Gzip => Some((Algorithm::GZIP, gzip(data))),
+            #[cfg(feature = "compression-zstd")]
+            Compression::Zstd => Some((Algorithm::ZSTD, zstd(data))),
+        }
+    }
+}
+
+/// Minimum length of messages that are worth compressing.
+const MIN_COMPRESSED_LEN: usize = 1024;
+
+/// Transport compressing messages before passing them to another transport.
+///
+/// Compressed messages are wrapped in the `rrg.CompressedMessage` message that
+/// records the algorithm and the kind of the original message, so the server
+/// can decompress them. Tiny messages and messages that do not compress well
+/// (e.g. because they contain already compressed data) are sent unchanged.
+pub struct CompressingTransport<T> {
+    /// Transport through which the (possibly compressed) messages are sent.
+    inner: T,
+    /// Algorithm with which the messages are compressed.
+    compression: Compression,
+}
+
+impl<T: Transport> CompressingTransport<T> {
+
+    /// Creates a new transport compressing messages with the given algorithm.
+    pub fn new(inner: T, compression: Compression) -> CompressingTransport<T> {
+        CompressingTransport {
+            inner,
+            compression,
+        }
+    }
+
+    /// Returns the transport through which the messages are sent.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for CompressingTransport<T> {
+
+    fn send(&self, message: Message) {
+        self.send_counted(message);
+    }
+
+    fn send_counted(&self, message: Message) -> usize {
+        use protobuf::Message as _;
+
+        if message.data.len() < MIN_COMPRESSED_LEN {
+            return self.inner.send_counted(message);
+        }
+
+        let Some((algorithm, data)) = self.compression.compress(&message.data) else {
+            return self.inner.send_counted(message);
+        };
+
+        // Compression has its (CPU and decoding) cost on both sides, so we
+        // need to gain at least 10% of the size to make it worthwhile.
+        if data.len() > message.data.len() / 10 * 9 {
+            return self.inner.send_counted(message);
+        }
+
+        let mut proto = rrg_proto::rrg::CompressedMessage::new();
+        proto.set_algorithm(algorithm);
+        proto.set_kind(message.kind.unwrap_or_default());
+        proto.set_data(data);
+
+        self.inner.send_counted(Message {
+            service: message.service,
+            kind: Some(String::from("rrg.CompressedMessage")),
+            data: proto.write_to_bytes()
+                // This should only fail in case we are out of memory, which we
+                // are almost certainly not (and if we are, we have a bigger
+                // issue).
+                .expect("failed to serialize a compressed message"),
+        })
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        self.inner.collect(heartbeat_rate)
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Magic bytes at the beginning of files storing spooled messages.
+const SPOOL_MAGIC: &[u8; 8] = b"RRGSPOL1";
+
+/// Magic bytes at the beginning of files storing encrypted spooled messages.
+const SPOOL_SEALED_MAGIC: &[u8; 8] = b"RRGSPOS1";
+
+/// Name of the file with the fingerprint of the key encrypting the spool.
+const SPOOL_KEY_FINGERPRINT_FILE_NAME: &str = "key-fingerprint";
+
+/// Transport storing messages on disk when they cannot be delivered.
+///
+/// Messages that the inner transport fails to send are written to the spool
+/// directory and are sent again (in the original order) before any other
+/// message once the inner transport recovers. Messages left in the spool are
+/// also replayed when the transport is created (e.g. after the agent restarts).
+///
+/// Not all failures are recoverable though: a broken Fleetspeak connection
+/// stays broken until the agent is restarted. Thus, if the inner transport
+/// reports that the connection is broken (see [`is_broken`]), the message is
+/// spooled and the agent exits, so that it gets a new connection and replays
+/// the spool on startup.
+///
+/// The spool is bounded in size: if it grows beyond the limit, the oldest
+/// messages are dropped.
+///
+/// If a key is given, spooled messages are encrypted with it, so that their
+/// contents (which might be sensitive) are not stored on disk as plaintext.
+pub struct SpoolingTransport<T> {
+    /// Transport through which the messages are sent.
+    inner: T,
+    /// Messages that could not be delivered yet.
+    spool: std::sync::Mutex<Spool>,
+}
+
+/// On-disk queue of messages that could not be delivered yet.
+struct Spool {
+    /// Directory in which the spooled messages are stored.
+    dir: std::path::PathBuf,
+    /// Maximum total size (in bytes) of the spooled messages.
+    max_size: u64,
+    /// Files with the spooled messages (from the oldest) and their sizes.
+    entries: std::collections::VecDeque<(std::path::PathBuf, u64)>,
+    /// Total size of the spooled messages.
+    size: u64,
+    /// Sequence number of the next spooled message.
+    next_seq: u64,
+    /// Key to encrypt the spooled messages with (if any).
+    key: Option<crate::seal::Key>,
+}
+
+impl<T: Transport> SpoolingTransport<T> {
+
+    /// Creates a new transport spooling messages in the given directory.
+    ///
+    /// The directory is created if it does not exist. Messages that are in the
+    /// directory already (e.g. left there by a previous run of the agent) are
+    /// sent right away.
+    ///
+    /// If `key` is specified, messages are encrypted with it before they are
+    /// written to the directory.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory cannot be created
+    /// or listed. It will also fail if the directory has messages encrypted
+    /// with a different key (or encrypted at all if there is no key), as they
+    /// could not be replayed. Finally, it will fail if the messages cannot be
+    /// replayed because the connection is broken (see [`is_broken`]).
+    pub fn new<P>(
+        inner: T,
+        dir: P,
+        max_size: u64,
+        key: Option<crate::seal::Key>,
+    ) -> std::io::Result<SpoolingTransport<T>>
+    where
+        P: Into<std::path::PathBuf>,
+    {
+        let mut spool = Spool::open(dir.into(), max_size, key)?;
+        match spool.flush(&inner) {
+            Err(error) if is_broken(&error) => return Err(error),
+            Err(error) => log::warn!("failed to replay spooled messages: {error}"),
+            Ok(()) => (),
+        }
+
+        Ok(SpoolingTransport {
+            inner,
+            spool: std::sync::Mutex::new(spool),
+        })
+    }
+
+    /// Returns the transport through which the messages are sent.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for SpoolingTransport<T> {
+
+    fn send(&self, message: Message) {
+        if let Err(error) = self.try_send(&message) {
+            exit_broken(error);
+        }
+    }
+
+    /// Sends the given message or spools it if it cannot be delivered.
+    ///
+    /// Errors are reported only if the connection is broken (in which case the
+    /// message is spooled nevertheless).
+    fn try_send(&self, message: &Message) -> std::io::Result<()> {
+        let mut spool = self.spool.lock()
+            .expect("poisoned spool");
+
+        let mut result = spool.flush(&self.inner);
+
+        // We cannot send the message directly if there are some older messages
+        // still waiting in the spool as it would break the order.
+        if spool.is_empty() {
+            result = self.inner.try_send(message);
+            if result.is_ok() {
+                return Ok(());
+            }
+        }
+
+        spool.push(message);
+
+        match result {
+            Err(error) if is_broken(&error) => Err(error),
+            Err(error) => {
+                log::warn!("failed to send message, spooled: {error}");
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+        match self.try_collect(heartbeat_rate) {
+            Ok(message) => message,
+            Err(error) => exit_broken(error),
+        }
+    }
+
+    fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+        // We might wait for a long time, so we try to deliver whatever is left
+        // in the spool first.
+        let result = self.spool.lock()
+            .expect("poisoned spool")
+            .flush(&self.inner);
+        if let Err(error) = result {
+            if is_broken(&error) {
+                return Err(error);
+            }
+        }
+
+        self.inner.try_collect(heartbeat_rate)
+    }
+
+    /// Sends all the spooled messages.
+    ///
+    /// Messages that still cannot be delivered stay in the spool, so they are
+    /// replayed when the transport is created again.
+    fn flush(&self) -> std::io::Result<()> {
+        self.spool.lock()
+            .expect("poisoned spool")
+            .flush(&self.inner)?;
+
+        self.inner.flush()
+    }
+}
+
+impl Spool {
+
+    /// Opens the spool stored in the given directory.
+    fn open(
+        dir: std::path::PathBuf,
+        max_size: u64,
+        key: Option<crate::seal::Key>,
+    ) -> std::io::Result<Spool> {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+
+            // Files that do not look like spooled messages are not ours, so we
+            // just leave them be.
+            let seq = match entry.file_name().to_str().map(str::parse::<u64>) {
+                Some(Ok(seq)) => seq,
+                _ => continue,
+            };
+            entries.push((seq, entry.path(), entry.metadata()?.len()));
+        }
+        entries.sort();
+
+        check_spool_key(&dir, key.as_ref(), !entries.is_empty())?;
+
+        let mut spool = Spool {
+            dir,
+            max_size,
+            entries: std::collections::VecDeque::new(),
+            size: 0,
+            next_seq: entries.last().map(|(seq, _, _)| seq + 1).unwrap_or(0),
+            key,
+        };
+        for (_, path, len) in entries {
+            spool.entries.push_back((path, len));
+            spool.size += len;
+        }
+        spool.shrink();
+
+        Ok(spool)
+    }
+
+    /// Returns whether there are no spooled messages.
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends the given message at the end of the spool.
+    fn push(&mut self, message: &Message) {
+        let path = self.dir.join(format!("{:020}", self.next_seq));
+        self.next_seq += 1;
+
+        let data = match &self.key {
+            Some(key) => match key.seal(&encode_message(message)) {
+                Ok(sealed) => [&SPOOL_SEALED_MAGIC[..], &sealed].concat(),
+                Err(error) => {
+                    log::error!("failed to seal message to '{}': {error}", path.display());
+                    return;
+                }
+            },
+            None => encode_message(message),
+        };
+        if let Err(error) = std::fs::write(&path, &data) {
+            log::error!("failed to spool message to '{}': {error}", path.display());
+            return;
+        }
+
+        self.entries.push_back((path, data.len() as u64));
+        self.size += data.len() as u64;
+        self.shrink();
+    }
+
+    /// Sends spooled messages through the given transport (from the oldest).
+    ///
+    /// Sending stops at the first failure (which is returned), the remaining
+    /// messages stay in the spool.
+    fn flush<T: Transport>(&mut self, transport: &T) -> std::io::Result<()> {
+        while let Some((path, _)) = self.entries.front() {
+            let message = std::fs::read(path)
+                .and_then(|data| self.decode(&data));
+
+            match message {
+                Ok(message) => transport.try_send(&message)?,
+                Err(error) => {
+                    log::error!("discarding corrupted spooled message '{}': {error}", path.display());
+                }
+            }
+
+            self.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Decodes a spooled message (decrypting it first if needed).
+    fn decode(&self, data: &[u8]) -> std::io::Result<Message> {
+        let Some(sealed) = data.strip_prefix(SPOOL_SEALED_MAGIC) else {
+            return decode_message(data);
+        };
+
+        let key = self.key.as_ref().ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "encrypted spooled message without a key",
+        ))?;
+
+        let data = key.open(sealed)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+
+        decode_message(&data)
+    }
+
+    /// Drops the oldest messages until the spool fits in the size limit.
+    fn shrink(&mut self) {
+        while self.size > self.max_size {
+            if let Some((path, _)) = self.entries.front() {
+                log::warn!("spool is full, dropping message '{}'", path.display());
+            }
+            self.pop();
+        }
+    }
+
+    /// Removes the oldest message from the spool.
+    fn pop(&mut self) {
+        let Some((path, len)) = self.entries.pop_front() else {
+            return;
+        };
+        self.size -= len;
+
+        if let Err(error) = std::fs::remove_file(&path) {
+            log::error!("failed to remove spooled message '{}': {error}", path.display());
+        }
+    }
+}
+
+/// Verifies that messages in the spool directory can be decrypted with `key`.
+///
+/// The fingerprint of the key is stored alongside the messages, so that a
+/// mismatch is detected (and reported) when the spool is opened rather than
+/// messages being discarded one by one as they fail to be decrypted.
+fn check_spool_key(
+    dir: &std::path::Path,
+    key: Option<&crate::seal::Key>,
+    has_entries: bool,
+) -> std::io::Result<()> {
+    let path = dir.join(SPOOL_KEY_FINGERPRINT_FILE_NAME);
+
+    let fingerprint = match std::fs::read(&path) {
+        Ok(fingerprint) => Some(fingerprint),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+
+    // Without any messages there is nothing to decrypt, so the key can change
+    // freely.
+    if has_entries {
+        match (fingerprint, key) {
+            (Some(fingerprint), Some(key)) if fingerprint != key.fingerprint() => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "spool is encrypted with a different key",
+                ));
+            }
+            (Some(_), None) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "spool is encrypted but no key was specified",
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    match key {
+        Some(key) => std::fs::write(&path, key.fingerprint()),
+        None => match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Encodes the given message for storing it in the spool.
+fn encode_message(message: &Message) -> Vec<u8> {
+    let mut buf = SPOOL_MAGIC.to_vec();
+
+    buf.extend_from_slice(&(message.service.len() as u32).to_le_bytes());
+    buf.extend_from_slice(message.service.as_bytes());
+
+    match &message.kind {
+        Some(kind) => {
+            buf.push(1);
+            buf.extend_from_slice(&(kind.len() as u32).to_le_bytes());
+            buf.extend_from_slice(kind.as_bytes());
+        }
+        None => buf.push(0),
+    }
+
+    buf.extend_from_slice(&message.data);
+    buf
+}
+
+/// Decodes a message encoded with [`encode_message`].
+fn decode_message(bytes: &[u8]) -> std::io::Result<Message> {
+    fn invalid() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed spooled message")
+    }
+
+    fn take_string(bytes: &mut &[u8]) -> std::io::Result<String> {
+        let (len, rest) = bytes.split_first_chunk::<4>()
+            .ok_or_else(invalid)?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(invalid());
+        }
+
+        let (string, rest) = rest.split_at(len);
+        *bytes = rest;
+
+        String::from_utf8(string.to_vec())
+            .map_err(|_| invalid())
+    }
+
+    let mut bytes = bytes.strip_prefix(SPOOL_MAGIC)
+        .ok_or_else(invalid)?;
+
+    let service = take_string(&mut bytes)?;
+
+    let (has_kind, rest) = bytes.split_first()
+        .ok_or_else(invalid)?;
+    bytes = rest;
+    let kind = match has_kind {
+        0 => None,
+        1 => Some(take_string(&mut bytes)?),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Message {
+        service,
+        kind,
+        data: bytes.to_vec(),
+    })
+}
+
+/// Creates a copy of the given message.
+fn clone_message(message: &Message) -> Message {
+    Message {
+        service: message.service.clone(),
+        kind: message.kind.clone(),
+        data: message.data.clone(),
+    }
+}
+
+/// Compresses the given bytes using the gzip format.
+#[cfg(feature = "compression-gzip")]
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write as _;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    // Writing to a vector cannot fail.
+    encoder.write_all(data)
+        .expect("failed to compress to memory");
+    encoder.finish()
+        .expect("failed to compress to memory")
+}
+
+/// Compresses the given data using the Zstandard format.
+#[cfg(feature = "compression-zstd")]
+fn zstd(data: &[u8]) -> Vec<u8> {
+    // Compressing in memory can fail only if we run out of it.
+    zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+        .expect("failed to compress to memory")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn loopback_push_collect() {
+        let transport = LoopbackTransport::new();
+        transport.push(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("foo")),
+            data: b"bar".to_vec(),
+        });
+
+        let message = transport.collect(std::time::Duration::from_secs(1));
+        assert_eq!(message.kind.as_deref(), Some("foo"));
+        assert_eq!(message.data, b"bar");
+    }
+
+    #[test]
+    fn loopback_take_sent() {
+        let transport = LoopbackTransport::new();
+        for data in [b"foo", b"bar"] {
+            transport.send(Message {
+                service: String::from("GRR"),
+                kind: None,
+                data: data.to_vec(),
+            });
+        }
+
+        let sent = transport.take_sent()
+            .into_iter()
+            .map(|message| message.data)
+            .collect::<Vec<_>>();
+        assert_eq!(sent, vec![b"foo".to_vec(), b"bar".to_vec()]);
+
+        assert!(transport.take_sent().is_empty());
+    }
+
+    #[cfg(feature = "action-get_client_actions")]
+    #[test]
+    fn loopback_dispatch() {
+        use protobuf::Message as _;
+
+        let args = crate::args::Args::default();
+        let registry = crate::action::Registry::new(&args);
+
+        let mut request = rrg_proto::rrg::Request::new();
+        request.set_flow_id(0xf10);
+        request.set_request_id(42);
+        request.set_action(rrg_proto::rrg::Action::GET_CLIENT_ACTIONS);
+        request.set_args(protobuf::well_known_types::any::Any::pack(&{
+            protobuf::well_known_types::empty::Empty::new()
+        }).unwrap());
+
+        let transport = std::sync::Arc::new(LoopbackTransport::new());
+        transport.push(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Request")),
+            data: request.write_to_bytes().unwrap(),
+        });
+
+        let request = crate::Request::receive(&*transport, std::time::Duration::from_secs(1), None);
+        let transport_dyn: std::sync::Arc<dyn Transport> = transport.clone();
+        let rate_limiter = crate::session::RateLimiter::default();
+        crate::session::FleetspeakSession::dispatch(&args, &transport_dyn, &rate_limiter, &registry, request);
+
+        let responses = transport.take_sent()
+            .into_iter()
+            .map(|message| {
+                assert_eq!(message.kind.as_deref(), Some("rrg.Response"));
+                rrg_proto::rrg::Response::parse_from_bytes(&message.data).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // The action yields a single result which has to be followed by the
+        // status, both tagged with the identifiers of the request.
+        assert_eq!(responses.len(), 2);
+        for response in &responses {
+            assert_eq!(response.flow_id(), 0xf10);
+            assert_eq!(response.request_id(), 42);
+        }
+
+        let result = responses[0].result()
+            .unpack::<rrg_proto::get_client_actions::Result>()
+            .unwrap().unwrap();
+        assert!(result.actions.contains(&rrg_proto::rrg::Action::GET_CLIENT_ACTIONS.into()));
+
+        assert!(responses[1].has_status());
+        assert!(!responses[1].status().has_error());
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn compressing_gzip_compressible() {
+        use protobuf::Message as _;
+        use std::io::Read as _;
+
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::Gzip);
+
+        let data = b"foobarbaz".repeat(1024);
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: data.clone(),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].service, "GRR");
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.CompressedMessage"));
+        assert!(sent[0].data.len() < data.len());
+
+        let proto = rrg_proto::rrg::CompressedMessage::parse_from_bytes(&sent[0].data)
+            .unwrap();
+        assert_eq!(proto.algorithm(), rrg_proto::rrg::compressed_message::Algorithm::GZIP);
+        assert_eq!(proto.kind(), "rrg.Response");
+
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(proto.data())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    #[test]
+    fn compressing_zstd_compressible() {
+        use protobuf::Message as _;
+
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::Zstd);
+
+        let data = b"foobarbaz".repeat(1024);
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: data.clone(),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.CompressedMessage"));
+        assert!(sent[0].data.len() < data.len());
+
+        let proto = rrg_proto::rrg::CompressedMessage::parse_from_bytes(&sent[0].data)
+            .unwrap();
+        assert_eq!(proto.algorithm(), rrg_proto::rrg::compressed_message::Algorithm::ZSTD);
+        assert_eq!(proto.kind(), "rrg.Response");
+        assert_eq!(zstd::decode_all(proto.data()).unwrap(), data);
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    #[test]
+    fn compressing_zstd_incompressible() {
+        use rand::RngCore as _;
+
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::Zstd);
+
+        let mut data = vec![0; 64 * 1024];
+        rand::thread_rng().fill_bytes(&mut data);
+
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Parcel")),
+            data: data.clone(),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.Parcel"));
+        assert_eq!(sent[0].data, data);
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn counting_compressed() {
+        let transport = CountingTransport::new({
+            CompressingTransport::new(LoopbackTransport::new(), Compression::Gzip)
+        });
+
+        let data = b"foobarbaz".repeat(1024);
+        let len = transport.send_counted(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: data.clone(),
+        });
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: b"foo".to_vec(),
+        });
+
+        let sent = transport.inner().inner().take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(len, sent[0].data.len());
+        assert!(len < data.len());
+
+        assert_eq!(transport.bytes_sent(), (sent[0].data.len() + 3) as u64);
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn compressing_gzip_tiny() {
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::Gzip);
+
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: b"foo".repeat(8),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.Response"));
+        assert_eq!(sent[0].data, b"foo".repeat(8));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn compressing_gzip_incompressible() {
+        use rand::RngCore as _;
+
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::Gzip);
+
+        let mut data = vec![0; 64 * 1024];
+        rand::thread_rng().fill_bytes(&mut data);
+
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Parcel")),
+            data: data.clone(),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.Parcel"));
+        assert_eq!(sent[0].data, data);
+    }
+
+    #[test]
+    fn compressing_none() {
+        let transport = CompressingTransport::new(LoopbackTransport::new(), Compression::None);
+
+        let data = b"foobarbaz".repeat(1024);
+        transport.send(Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: data.clone(),
+        });
+
+        let sent = transport.inner().take_sent();
+        assert_eq!(sent[0].kind.as_deref(), Some("rrg.Response"));
+        assert_eq!(sent[0].data, data);
+    }
+
+    /// Transport failing to send a number of messages before recovering.
+    struct FlakyTransport {
+        /// Transport to which messages are passed after recovery.
+        inner: LoopbackTransport,
+        /// Number of attempts that are still going to fail.
+        failures: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FlakyTransport {
+
+        fn new(failures: usize) -> FlakyTransport {
+            FlakyTransport {
+                inner: LoopbackTransport::new(),
+                failures: std::sync::atomic::AtomicUsize::new(failures),
+            }
+        }
+    }
+
+    impl Transport for FlakyTransport {
+
+        fn send(&self, message: Message) {
+            self.try_send(&message)
+                .expect("flaky transport failure")
+        }
+
+        fn try_send(&self, message: &Message) -> std::io::Result<()> {
+            use std::sync::atomic::Ordering;
+
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            self.inner.send(clone_message(message));
+            Ok(())
+        }
+
+        fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+            self.inner.collect(heartbeat_rate)
+        }
+    }
+
+    /// Transport failing to collect a number of messages before recovering.
+    struct FlakyCollectTransport {
+        /// Transport from which messages are collected after recovery.
+        inner: LoopbackTransport,
+        /// Number of attempts that are still going to fail.
+        failures: std::sync::atomic::AtomicUsize,
+        /// Moments at which collection was attempted.
+        attempts: std::sync::Mutex<Vec<std::time::Instant>>,
+    }
+
+    impl FlakyCollectTransport {
+
+        fn new(failures: usize) -> FlakyCollectTransport {
+            FlakyCollectTransport {
+                inner: LoopbackTransport::new(),
+                failures: std::sync::atomic::AtomicUsize::new(failures),
+                attempts: std::sync::Mutex::new(Vec::new()),
+            }
+        }
+
+        fn fail(&self, failures: usize) {
+            self.failures.store(failures, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        /// Returns delays between consecutive attempts since the last call.
+        fn take_delays(&self) -> Vec<std::time::Duration> {
+            let attempts = std::mem::take(&mut *self.attempts.lock().unwrap());
+            attempts.windows(2)
+                .map(|window| window[1] - window[0])
+                .collect()
+        }
+    }
+
+    impl Transport for FlakyCollectTransport {
+
+        fn send(&self, message: Message) {
+            self.inner.send(message)
+        }
+
+        fn collect(&self, heartbeat_rate: std::time::Duration) -> Message {
+            self.try_collect(heartbeat_rate)
+                .expect("flaky transport failure")
+        }
+
+        fn try_collect(&self, heartbeat_rate: std::time::Duration) -> std::io::Result<Message> {
+            use std::sync::atomic::Ordering;
+
+            self.attempts.lock().unwrap()
+                .push(std::time::Instant::now());
+
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            Ok(self.inner.collect(heartbeat_rate))
+        }
+    }
+
+    /// Transport with a connection that is broken for good.
+    ///
+    /// This is how the Fleetspeak transport behaves after the first failure.
+    struct BrokenTransport {
+        /// Number of attempts to send or collect a message so far.
+        attempts: std::sync::atomic::AtomicUsize,
+    }
+
+    impl BrokenTransport {
+
+        fn new() -> BrokenTransport {
+            BrokenTransport {
+                attempts: std::sync::atomic::AtomicUsize::new(0),
+            }
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    impl Transport for BrokenTransport {
+
+        fn send(&self, _: Message) {
+            panic!("broken transport")
+        }
+
+        fn try_send(&self, _: &Message) -> std::io::Result<()> {
+            self.attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(BrokenError::io())
+        }
+
+        fn collect(&self, _: std::time::Duration) -> Message {
+            panic!("broken transport")
+        }
+
+        fn try_collect(&self, _: std::time::Duration) -> std::io::Result<Message> {
+            self.attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(BrokenError::io())
+        }
+    }
+
+    fn message(data: &[u8]) -> Message {
+        Message {
+            service: String::from("GRR"),
+            kind: Some(String::from("rrg.Response")),
+            data: data.to_vec(),
+        }
+    }
+
+    fn sent_data(transport: &LoopbackTransport) -> Vec<Vec<u8>> {
+        transport.take_sent()
+            .into_iter()
+            .map(|message| message.data)
+            .collect()
+    }
+
+    #[test]
+    fn backoff_growing_and_capped() {
+        use std::time::Duration;
+
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_failing_then_recovering() {
+        use std::time::Duration;
+
+        let transport = BackoffTransport::new(
+            FlakyCollectTransport::new(3),
+            Duration::from_millis(10),
+            Duration::from_secs(1),
+        );
+
+        transport.inner().inner.push(message(b"foo"));
+        assert_eq!(transport.collect(Duration::from_secs(1)).data, b"foo");
+
+        // There were three failures, so there were three growing delays.
+        let delays = transport.inner().take_delays();
+        assert_eq!(delays.len(), 3);
+        assert!(delays[0] >= Duration::from_millis(10));
+        assert!(delays[1] >= Duration::from_millis(20));
+        assert!(delays[2] >= Duration::from_millis(40));
+
+        // Once a message was collected, the delay starts from scratch.
+        assert_eq!(transport.backoff.lock().unwrap().next_delay, None);
+
+        transport.inner().fail(1);
+        transport.inner().inner.push(message(b"bar"));
+        assert_eq!(transport.collect(Duration::from_secs(1)).data, b"bar");
+        assert_eq!(transport.inner().take_delays().len(), 1);
+    }
+
+    #[test]
+    fn backoff_broken_not_retried() {
+        use std::time::Duration;
+
+        let transport = BackoffTransport::new(
+            BrokenTransport::new(),
+            Duration::from_millis(10),
+            Duration::from_secs(1),
+        );
+
+        let error = transport.try_collect(Duration::from_secs(1))
+            .err().unwrap();
+        assert!(is_broken(&error));
+        assert_eq!(transport.inner().attempts(), 1);
+    }
+
+    #[test]
+    fn fleetspeak_guard_broken_after_panic() {
+        // Once an operation panics, the connection might be in an inconsistent
+        // state, so no other operation should be attempted.
+        let error = FleetspeakTransport::guard(|| panic!("connection failure"))
+            .unwrap_err();
+        assert!(is_broken(&error));
+
+        let error = FleetspeakTransport::guard(|| unreachable!())
+            .unwrap_err();
+        assert!(is_broken(&error));
+    }
+
+    #[test]
+    fn is_broken_other_errors() {
+        assert!(!is_broken(&std::io::ErrorKind::BrokenPipe.into()));
+        assert!(!is_broken(&std::io::Error::other("foo")));
+    }
+
+    #[test]
+    fn spooling_broken_spools_and_fails() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let transport = SpoolingTransport::new(BrokenTransport::new(), tempdir.path(), 1024, None)
+            .unwrap();
+
+        let error = transport.try_send(&message(b"foo"))
+            .unwrap_err();
+        assert!(is_broken(&error));
+
+        // The message has to be spooled nevertheless, so that it is sent after
+        // the agent restarts.
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 1);
+
+        let error = transport.try_collect(std::time::Duration::from_secs(1))
+            .err().unwrap();
+        assert!(is_broken(&error));
+        drop(transport);
+
+        // The connection is still broken after the restart, so there is no way
+        // to replay the messages.
+        let error = SpoolingTransport::new(BrokenTransport::new(), tempdir.path(), 1024, None)
+            .err().unwrap();
+        assert!(is_broken(&error));
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn spooling_flushes_on_startup() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let transport = SpoolingTransport::new(BrokenTransport::new(), tempdir.path(), 1024, None)
+            .unwrap();
+        assert!(transport.try_send(&message(b"foo")).is_err());
+        assert!(transport.try_send(&message(b"bar")).is_err());
+        drop(transport);
+
+        // Messages should be delivered as soon as a working connection is
+        // available, not only once another message is sent.
+        let transport = SpoolingTransport::new(LoopbackTransport::new(), tempdir.path(), 1024, None)
+            .unwrap();
+        assert_eq!(sent_data(transport.inner()), vec![
+            b"foo".to_vec(),
+            b"bar".to_vec(),
+        ]);
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn spooling_failing_then_recovering() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(2), tempdir.path(), 1024, None)
+            .unwrap();
+
+        transport.send(message(b"foo"));
+        transport.send(message(b"bar"));
+        assert!(transport.inner().inner.take_sent().is_empty());
+
+        transport.send(message(b"baz"));
+        assert_eq!(sent_data(&transport.inner().inner), vec![
+            b"foo".to_vec(),
+            b"bar".to_vec(),
+            b"baz".to_vec(),
+        ]);
+
+        // Delivered messages should be removed from the spool.
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn spooling_flush() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(2), tempdir.path(), 1024, None)
+            .unwrap();
+
+        transport.send(message(b"foo"));
+        transport.send(message(b"bar"));
+        assert!(transport.inner().inner.take_sent().is_empty());
+
+        // Spooled messages should be delivered without sending another one.
+        transport.flush()
+            .unwrap();
+        assert_eq!(sent_data(&transport.inner().inner), vec![
+            b"foo".to_vec(),
+            b"bar".to_vec(),
+        ]);
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn spooling_drops_oldest() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let len = encode_message(&message(b"foo")).len() as u64;
+        let transport = SpoolingTransport::new(FlakyTransport::new(3), tempdir.path(), 2 * len, None)
+            .unwrap();
+
+        // The first message fails to be delivered directly and the subsequent
+        // ones fail as the spool is flushed. Since the spool can hold only two
+        // messages, the oldest one gets dropped.
+        transport.send(message(b"foo"));
+        transport.send(message(b"bar"));
+        transport.send(message(b"baz"));
+        assert!(transport.inner().inner.take_sent().is_empty());
+
+        transport.send(message(b"quux"));
+        assert_eq!(sent_data(&transport.inner().inner), vec![
+            b"bar".to_vec(),
+            b"baz".to_vec(),
+            b"quux".to_vec(),
+        ]);
+    }
+
+    #[test]
+    fn spooling_replays_after_restart() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(usize::MAX), tempdir.path(), 1024, None)
+            .unwrap();
+        transport.send(message(b"foo"));
+        transport.send(message(b"bar"));
+        drop(transport);
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(0), tempdir.path(), 1024, None)
+            .unwrap();
+        transport.send(message(b"baz"));
+        assert_eq!(sent_data(&transport.inner().inner), vec![
+            b"foo".to_vec(),
+            b"bar".to_vec(),
+            b"baz".to_vec(),
+        ]);
+    }
+
+    #[cfg(feature = "spool-encryption")]
+    #[test]
+    fn spooling_encrypted_replays_after_restart() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let key = || Some(crate::seal::Key::derive(b"secret", b"spool"));
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(usize::MAX), tempdir.path(), 1024, key())
+            .unwrap();
+        transport.send(message(b"foo"));
+        transport.send(message(b"bar"));
+        drop(transport);
+
+        // Contents of the messages should not be stored as plaintext.
+        for entry in std::fs::read_dir(tempdir.path()).unwrap() {
+            let data = std::fs::read(entry.unwrap().path())
+                .unwrap();
+            assert!(!data.windows(3).any(|window| window == b"foo" || window == b"bar"));
+        }
+
+        let transport = SpoolingTransport::new(FlakyTransport::new(0), tempdir.path(), 1024, key())
+            .unwrap();
+        transport.send(message(b"baz"));
+        assert_eq!(sent_data(&transport.inner().inner), vec![
+            b"foo".to_vec(),
+            b"bar".to_vec(),
+            b"baz".to_vec(),
+        ]);
+    }
+
+    #[cfg(feature = "spool-encryption")]
+    #[test]
+    fn spooling_encrypted_wrong_key() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let key = crate::seal::Key::derive(b"secret", b"spool");
+        let transport = SpoolingTransport::new(FlakyTransport::new(usize::MAX), tempdir.path(), 1024, Some(key))
+            .unwrap();
+        transport.send(message(b"foo"));
+        drop(transport);
+
+        let key = crate::seal::Key::derive(b"other", b"spool");
+        let error = SpoolingTransport::new(FlakyTransport::new(0), tempdir.path(), 1024, Some(key))
+            .err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let error = SpoolingTransport::new(FlakyTransport::new(0), tempdir.path(), 1024, None)
+            .err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "spool-encryption")]
+    #[test]
+    fn spool_decode_wrong_key() {
+        let tempdir = tempfile::tempdir()
+            .unwrap();
+
+        let key = crate::seal::Key::derive(b"secret", b"spool");
+        let sealed = [&SPOOL_SEALED_MAGIC[..], &key.seal(&encode_message(&message(b"foo"))).unwrap()].concat();
+
+        let key = crate::seal::Key::derive(b"other", b"spool");
+        let spool = Spool::open(tempdir.path().to_path_buf(), 1024, Some(key))
+            .unwrap();
+        assert!(spool.decode(&sealed).is_err());
+
+        let spool = Spool::open(tempdir.path().to_path_buf(), 1024, None)
+            .unwrap();
+        assert!(spool.decode(&sealed).is_err());
+    }
+
+    #[test]
+    fn encode_decode_message() {
+        let message = Message {
+            service: String::from("GRR"),
+            kind: None,
+            data: b"\x00\x01\x02".to_vec(),
+        };
+
+        let decoded = decode_message(&encode_message(&message))
+            .unwrap();
+        assert_eq!(decoded.service, "GRR");
+        assert_eq!(decoded.kind, None);
+        assert_eq!(decoded.data, b"\x00\x01\x02");
+
+        assert!(decode_message(b"RRGSPOL1\xff").is_err());
+    }
+}